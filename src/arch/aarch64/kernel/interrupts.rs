@@ -16,6 +16,7 @@ use crate::arch::aarch64::kernel::core_local::increment_irq_counter;
 use crate::arch::aarch64::kernel::scheduler::State;
 use crate::arch::aarch64::mm::paging::{self, BasePageSize, PageSize, PageTableEntryFlags};
 use crate::arch::aarch64::mm::{virtualmem, PhysAddr};
+use crate::arch::irq_statistics::for_each_irq_counter;
 use crate::core_scheduler;
 use crate::scheduler::{self, CoreId};
 
@@ -42,6 +43,7 @@ pub(crate) static mut GIC: OnceCell<GicV3> = OnceCell::new();
 
 fn timer_handler(_state: &State) -> bool {
 	debug!("Handle timer interrupt");
+	crate::scheduler::fallback_timer::record_interrupt();
 
 	// disable timer
 	unsafe {
@@ -358,7 +360,7 @@ pub(crate) fn add_irq_name(irq_number: u8, name: &'static str) {
 	IRQ_NAMES.lock().insert(SPI_START + irq_number, name);
 }
 
-fn get_irq_name(irq_number: u8) -> Option<&'static str> {
+pub(crate) fn get_irq_name(irq_number: u8) -> Option<&'static str> {
 	IRQ_NAMES.lock().get(&irq_number).copied()
 }
 
@@ -385,19 +387,12 @@ impl IrqStatistics {
 
 pub(crate) fn print_statistics() {
 	info!("Number of interrupts");
-	for (core_id, irg_statistics) in IRQ_COUNTERS.lock().iter() {
-		for (i, counter) in irg_statistics.counters.iter().enumerate() {
-			let counter = counter.load(Ordering::Relaxed);
-			if counter > 0 {
-				match get_irq_name(i.try_into().unwrap()) {
-					Some(name) => {
-						info!("[{core_id}][{name}]: {counter}");
-					}
-					_ => {
-						info!("[{core_id}][{i}]: {counter}");
-					}
-				}
-			}
+	for_each_irq_counter(|core_id, i, name, counter| match name {
+		Some(name) => {
+			info!("[{core_id}][{name}]: {counter}");
 		}
-	}
+		_ => {
+			info!("[{core_id}][{i}]: {counter}");
+		}
+	});
 }
@@ -245,6 +245,12 @@ fn __set_oneshot_timer(wakeup_time: Option<u64>) {
 }
 
 pub fn set_oneshot_timer(wakeup_time: Option<u64>) {
+	let wakeup_time = crate::scheduler::tick::align(wakeup_time);
+	#[cfg(feature = "watchdog")]
+	let wakeup_time = crate::scheduler::watchdog::arm(wakeup_time);
+	// A polled timer keeps the hardware timer disabled. Hence, it cannot fire late.
+	let wakeup_time = wakeup_time.filter(|_| !crate::scheduler::fallback_timer::is_active());
+
 	without_interrupts(|| {
 		__set_oneshot_timer(wakeup_time);
 	});
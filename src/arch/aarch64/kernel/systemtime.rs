@@ -96,7 +96,14 @@ pub fn init() {
 	}
 
 	PL031_ADDRESS.set(VirtAddr::zero()).unwrap();
-	BOOT_TIME.set(0).unwrap();
+
+	let micros = crate::env::boot_epoch().unwrap_or_else(|| {
+		warn!("No wall clock available, the realtime clock starts at the epoch");
+		0
+	});
+	let boot_time = OffsetDateTime::from_unix_timestamp_nanos(i128::from(micros) * 1000).unwrap();
+	info!("Hermit booted on {boot_time}");
+	BOOT_TIME.set(micros).unwrap();
 }
 
 /// Returns the current time in microseconds since UNIX epoch.
@@ -38,6 +38,11 @@ pub fn total_memory_size() -> usize {
 	TOTAL_MEMORY.load(Ordering::SeqCst)
 }
 
+/// Returns the size of the physical memory, which isn't allocated yet.
+pub fn free_memory_size() -> usize {
+	PHYSICAL_FREE_LIST.lock().free_space()
+}
+
 pub fn init_page_tables() {}
 
 pub fn allocate(size: usize) -> Result<PhysAddr, AllocError> {
@@ -0,0 +1,19 @@
+//! Architecture-independent access to the interrupt counters.
+
+use core::sync::atomic::Ordering;
+
+use crate::arch::interrupts::{get_irq_name, IRQ_COUNTERS};
+use crate::scheduler::CoreId;
+
+/// Calls `f` with the core, the number, the name (if registered) and the
+/// counter of every interrupt, which was received at least once.
+pub(crate) fn for_each_irq_counter(mut f: impl FnMut(CoreId, usize, Option<&'static str>, u64)) {
+	for (core_id, irg_statistics) in IRQ_COUNTERS.lock().iter() {
+		for (i, counter) in irg_statistics.counters.iter().enumerate() {
+			let counter = counter.load(Ordering::Relaxed);
+			if counter > 0 {
+				f(*core_id, i, get_irq_name(i.try_into().unwrap()), counter);
+			}
+		}
+	}
+}
@@ -1,5 +1,8 @@
 //! Architecture-specific architecture abstraction.
 
+#[cfg(not(target_arch = "riscv64"))]
+pub(crate) mod irq_statistics;
+
 cfg_if::cfg_if! {
 	if #[cfg(target_arch = "aarch64")] {
 		pub(crate) mod aarch64;
@@ -266,6 +266,10 @@ pub fn supports_2mib_pages() -> bool {
 }
 
 pub fn set_oneshot_timer(wakeup_time: Option<u64>) {
+	let wakeup_time = crate::scheduler::tick::align(wakeup_time);
+	#[cfg(feature = "watchdog")]
+	let wakeup_time = crate::scheduler::watchdog::arm(wakeup_time);
+
 	if let Some(wt) = wakeup_time {
 		debug!("Starting Timer: {:x}", get_timestamp());
 		unsafe {
@@ -45,6 +45,8 @@ static MADT: OnceCell<AcpiTable<'_>> = OnceCell::new();
 static PM1A_CNT_BLK: OnceCell<u16> = OnceCell::new();
 /// The Sleeping State Type code for powering off the computer through ACPI.
 static SLP_TYPA: OnceCell<u8> = OnceCell::new();
+/// The physical address of the registers of the "High Precision Event Timer" (HPET).
+static HPET_ADDRESS: OnceCell<PhysAddr> = OnceCell::new();
 
 /// The "Root System Description Pointer" structure providing pointers to all other ACPI tables.
 #[repr(C, packed)]
@@ -167,6 +169,7 @@ struct AcpiGenericAddress {
 	address: u64,
 }
 
+const GENERIC_ADDRESS_MEMORY_SPACE: u8 = 0;
 const GENERIC_ADDRESS_IO_SPACE: u8 = 1;
 
 /// The "Fixed ACPI Description Table" (FADT), also called "Fixed ACPI Control Pointer" (FACP).
@@ -230,6 +233,17 @@ struct AcpiFadt {
 	hypervisor_vendor_id: u64,
 }
 
+/// The "High Precision Event Timer Description Table" (HPET).
+/// Described in IA-PC HPET Specification 1.0a, 3.2.4 The ACPI 2.0 HPET Description Table (HPET).
+#[repr(C, packed)]
+struct AcpiHpet {
+	event_timer_block_id: u32,
+	base_address: AcpiGenericAddress,
+	hpet_number: u8,
+	minimum_tick: u16,
+	page_protection: u8,
+}
+
 /// Verifies the checksum of an ACPI table.
 /// Tables supporting this feature contain a "checksum" field. The value of this field is chosen, so that a
 /// (wrapping) sum over all table fields equals zero.
@@ -442,10 +456,28 @@ fn parse_ssdt(ssdt: AcpiTable<'_>) {
 	search_s5_in_table(ssdt);
 }
 
+fn parse_hpet(hpet: AcpiTable<'_>) {
+	let hpet_table =
+		unsafe { &*ptr::with_exposed_provenance::<AcpiHpet>(hpet.table_start_address()) };
+
+	// The registers of the HPET are always memory-mapped.
+	if hpet_table.base_address.address_space != GENERIC_ADDRESS_MEMORY_SPACE {
+		warn!("Ignore HPET, whose registers aren't memory-mapped");
+		return;
+	}
+
+	// Only the first HPET is used, if multiple timer blocks are available.
+	let _ = HPET_ADDRESS.set(PhysAddr(hpet_table.base_address.address));
+}
+
 pub fn get_madt() -> Option<&'static AcpiTable<'static>> {
 	MADT.get()
 }
 
+pub fn get_hpet_address() -> Option<PhysAddr> {
+	HPET_ADDRESS.get().copied()
+}
+
 pub fn poweroff() {
 	if let (Some(&pm1a_cnt_blk), Some(&slp_typa)) = (PM1A_CNT_BLK.get(), SLP_TYPA.get()) {
 		let bits = (u16::from(slp_typa) << 10) | SLP_EN;
@@ -522,6 +554,12 @@ pub fn init() {
 				"SSDT at {table_physical_address:p} has invalid checksum"
 			);
 			parse_ssdt(table);
+		} else if table.header.signature() == "HPET" {
+			assert!(
+				verify_checksum(table.header_start_address(), table.header.length as usize).is_ok(),
+				"HPET at {table_physical_address:p} has invalid checksum"
+			);
+			parse_hpet(table);
 		}
 	}
 }
@@ -40,6 +40,8 @@ const APIC_DIV_CONF_DIVIDE_BY_8: u64 = 0b0010;
 const APIC_EOI_ACK: u64 = 0;
 #[cfg(feature = "smp")]
 const APIC_ICR_DELIVERY_MODE_FIXED: u64 = 0x000;
+#[cfg(all(feature = "smp", feature = "watchdog"))]
+const APIC_ICR_DELIVERY_MODE_NMI: u64 = 0x400;
 #[cfg(feature = "smp")]
 const APIC_ICR_DELIVERY_MODE_INIT: u64 = 0x500;
 #[cfg(feature = "smp")]
@@ -627,9 +629,9 @@ fn __set_oneshot_timer(wakeup_time: Option<u64>) {
 	if let Some(wt) = wakeup_time {
 		if processor::supports_tsc_deadline() {
 			// wt is the absolute wakeup time in microseconds based on processor::get_timer_ticks.
-			// We can simply multiply it by the processor frequency to get the absolute Time-Stamp Counter deadline
+			// We can simply convert it by the TSC frequency to get the absolute TSC deadline
 			// (see processor::get_timer_ticks).
-			let tsc_deadline = wt * (u64::from(processor::get_frequency()));
+			let tsc_deadline = processor::micros_to_timestamp(wt);
 
 			// Enable the APIC Timer in TSC-Deadline Mode and let it start by writing to the respective MSR.
 			local_apic_write(
@@ -665,6 +667,12 @@ fn __set_oneshot_timer(wakeup_time: Option<u64>) {
 }
 
 pub fn set_oneshot_timer(wakeup_time: Option<u64>) {
+	let wakeup_time = crate::scheduler::tick::align(wakeup_time);
+	#[cfg(feature = "watchdog")]
+	let wakeup_time = crate::scheduler::watchdog::arm(wakeup_time);
+	// A polled timer keeps the hardware timer disabled. Hence, it cannot fire late.
+	let wakeup_time = wakeup_time.filter(|_| !crate::scheduler::fallback_timer::is_active());
+
 	without_interrupts(|| {
 		__set_oneshot_timer(wakeup_time);
 	});
@@ -855,6 +863,21 @@ pub fn wakeup_core(core_id_to_wakeup: CoreId) {
 	}
 }
 
+/// Sends a non-maskable interrupt to a core. In contrast to other interrupts,
+/// the core is interrupted even if it runs with disabled interrupts.
+#[cfg(all(feature = "smp", feature = "watchdog"))]
+pub fn ipi_nmi(core_id_to_interrupt: CoreId) {
+	without_interrupts(|| {
+		let apic_ids = CPU_LOCAL_APIC_IDS.lock();
+		let local_apic_id = apic_ids[core_id_to_interrupt as usize];
+		let destination = u64::from(local_apic_id) << 32;
+		local_apic_write(
+			IA32_X2APIC_ICR,
+			destination | APIC_ICR_LEVEL_ASSERT | APIC_ICR_DELIVERY_MODE_NMI,
+		);
+	});
+}
+
 /// Translate the x2APIC MSR into an xAPIC memory address.
 #[inline]
 fn translate_x2apic_msr_to_xapic_address(x2apic_msr: u32) -> VirtAddr {
@@ -0,0 +1,106 @@
+//! The High Precision Event Timer (HPET) serves as clocksource, if the TSC
+//! isn't invariant and its rate changes with the power state of the processor.
+
+use core::ptr;
+
+use hermit_sync::OnceCell;
+
+use crate::arch::x86_64::kernel::{acpi, processor};
+use crate::arch::x86_64::mm::paging::{
+	BasePageSize, PageSize, PageTableEntryFlags, PageTableEntryFlagsExt,
+};
+use crate::arch::x86_64::mm::{paging, virtualmem, VirtAddr};
+
+/// Offset of the General Capabilities and ID Register
+const HPET_GENERAL_CAPABILITIES: usize = 0x000;
+/// Offset of the General Configuration Register
+const HPET_GENERAL_CONFIGURATION: usize = 0x010;
+/// Offset of the Main Counter Value Register
+const HPET_MAIN_COUNTER: usize = 0x0F0;
+
+/// The main counter is 64 bits wide
+const HPET_COUNT_SIZE_CAP: u64 = 1 << 13;
+/// Starts the main counter
+const HPET_ENABLE_CNF: u64 = 1 << 0;
+/// Maximal period of the main counter in femtoseconds
+const HPET_MAX_PERIOD: u64 = 100_000_000;
+
+const FEMTOSECONDS_PER_MICROSECOND: u64 = 1_000_000_000;
+
+static HPET: OnceCell<Hpet> = OnceCell::new();
+
+struct Hpet {
+	/// Virtual address of the registers
+	address: VirtAddr,
+	/// Factor, which converts ticks of the main counter to microseconds
+	/// (see [`processor::fixed_point_ratio`])
+	scale: u128,
+	/// Microseconds, which are added to the converted main counter, such that
+	/// the time continues seamlessly from the previous clocksource
+	offset: u64,
+}
+
+impl Hpet {
+	fn read(&self, register: usize) -> u64 {
+		unsafe { ptr::read_volatile((self.address + register).as_ptr::<u64>()) }
+	}
+
+	fn write(&self, register: usize, value: u64) {
+		unsafe {
+			ptr::write_volatile((self.address + register).as_mut_ptr::<u64>(), value);
+		}
+	}
+
+	fn get_timer_ticks(&self) -> u64 {
+		processor::mul_fixed_point(self.read(HPET_MAIN_COUNTER), self.scale)
+			.wrapping_add(self.offset)
+	}
+}
+
+/// Starts the main counter of the HPET, which is described by the ACPI tables,
+/// and uses it as clocksource. The time continues at `uptime` microseconds.
+pub fn init(uptime: u64) -> Result<(), ()> {
+	let phys_addr = acpi::get_hpet_address().ok_or(())?;
+	let address = virtualmem::allocate(BasePageSize::SIZE as usize).unwrap();
+	debug!("Mapping HPET at {phys_addr:p} to virtual address {address:p}");
+
+	let mut flags = PageTableEntryFlags::empty();
+	flags.device().writable().execute_disable();
+	paging::map::<BasePageSize>(address, phys_addr, 1, flags);
+
+	let mut hpet = Hpet {
+		address,
+		scale: 0,
+		offset: 0,
+	};
+
+	// A 32-bit counter overflows within minutes, which the clocksource doesn't handle.
+	let capabilities = hpet.read(HPET_GENERAL_CAPABILITIES);
+	let period = capabilities >> 32;
+	if capabilities & HPET_COUNT_SIZE_CAP == 0 || period == 0 || period > HPET_MAX_PERIOD {
+		warn!("HPET with the capabilities {capabilities:#x} can't serve as clocksource");
+		paging::unmap::<BasePageSize>(address, 1);
+		virtualmem::deallocate(address, BasePageSize::SIZE as usize);
+		return Err(());
+	}
+
+	hpet.scale = processor::fixed_point_ratio(period, FEMTOSECONDS_PER_MICROSECOND);
+	let configuration = hpet.read(HPET_GENERAL_CONFIGURATION);
+	hpet.write(HPET_GENERAL_CONFIGURATION, configuration | HPET_ENABLE_CNF);
+	hpet.offset = uptime.wrapping_sub(hpet.get_timer_ticks());
+
+	info!(
+		"HPET frequency: {} Hz",
+		FEMTOSECONDS_PER_MICROSECOND * 1_000_000 / period
+	);
+	HPET.set(hpet).map_err(|_| ())
+}
+
+/// Returns the microseconds since boot, if the HPET serves as clocksource.
+pub fn get_timer_ticks() -> Option<u64> {
+	HPET.get().map(Hpet::get_timer_ticks)
+}
+
+pub fn is_enabled() -> bool {
+	HPET.get().is_some()
+}
@@ -10,6 +10,7 @@ use x86_64::set_general_handler;
 pub use x86_64::structures::idt::InterruptStackFrame as ExceptionStackFrame;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
+use crate::arch::irq_statistics::for_each_irq_counter;
 use crate::arch::x86_64::kernel::core_local::{core_scheduler, increment_irq_counter};
 use crate::arch::x86_64::kernel::{apic, processor};
 use crate::arch::x86_64::mm::paging::{page_fault_handler, BasePageSize, PageSize};
@@ -157,10 +158,81 @@ extern "x86-interrupt" fn debug_exception(stack_frame: ExceptionStackFrame) {
 
 extern "x86-interrupt" fn nmi_exception(stack_frame: ExceptionStackFrame) {
 	swapgs(&stack_frame);
+	// the watchdog interrupts stalled cores to log their backtrace
+	#[cfg(feature = "watchdog")]
+	if scheduler::watchdog::handle_backtrace_request(|| log_backtrace(&stack_frame)) {
+		swapgs(&stack_frame);
+		return;
+	}
+
 	error!("Non-Maskable Interrupt (NMI) Exception: {:#?}", stack_frame);
 	scheduler::abort();
 }
 
+/// Logs the backtrace of the interrupted task.
+///
+/// The return addresses are found by walking the frame pointers. Hence, the
+/// backtrace is only complete, if the kernel is built with
+/// `-Cforce-frame-pointers=yes`. The walk stops at the first frame, which
+/// isn't located above the previous one on the mapped stack of the task.
+#[cfg(feature = "watchdog")]
+#[inline(never)]
+fn log_backtrace(stack_frame: &ExceptionStackFrame) {
+	use crate::arch::x86_64::kernel::core_local::core_id;
+	use crate::arch::x86_64::mm::{paging, VirtAddr};
+	use crate::config::DEFAULT_STACK_SIZE;
+
+	const MAX_FRAMES: usize = 32;
+
+	let is_mapped = |addr: u64| {
+		x86_64::VirtAddr::try_new(addr).is_ok()
+			&& paging::virtual_to_physical(VirtAddr(addr)).is_some()
+	};
+	// the frame has to be located above `lower` and on the stack of the task
+	let is_frame = |frame: u64, lower: u64| {
+		frame % 8 == 0
+			&& frame >= lower
+			&& frame - lower < DEFAULT_STACK_SIZE as u64
+			&& is_mapped(frame)
+			&& is_mapped(frame + 8)
+	};
+
+	match core_scheduler().try_get_current_task_id() {
+		Some(id) => error!("Watchdog: backtrace of task {id} on core {}", core_id()),
+		None => error!("Watchdog: backtrace of core {}", core_id()),
+	}
+	error!("  0: {:#x}", stack_frame.instruction_pointer.as_u64());
+
+	// The prologue of the interrupt handler stores the frame pointer of the
+	// interrupted code at the frame pointer of the handler.
+	let rbp: u64;
+	let rsp: u64;
+	unsafe {
+		asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+		asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+	}
+	if !(rbp >= rsp && rbp - rsp < IST_SIZE as u64 && is_mapped(rbp)) {
+		return;
+	}
+
+	let mut lower = stack_frame.stack_pointer.as_u64();
+	let mut frame = unsafe { *(rbp as *const u64) };
+	for i in 1..MAX_FRAMES {
+		if !is_frame(frame, lower) {
+			break;
+		}
+
+		let return_address = unsafe { *((frame + 8) as *const u64) };
+		if return_address == 0 {
+			break;
+		}
+		error!("{i:3}: {return_address:#x}");
+
+		lower = frame + 16;
+		frame = unsafe { *(frame as *const u64) };
+	}
+}
+
 extern "x86-interrupt" fn breakpoint_exception(stack_frame: ExceptionStackFrame) {
 	swapgs(&stack_frame);
 	error!("Breakpoint (#BP) Exception: {:#?}", stack_frame);
@@ -296,7 +368,7 @@ pub(crate) fn add_irq_name(irq_number: u8, name: &'static str) {
 	IRQ_NAMES.lock().insert(32 + irq_number, name);
 }
 
-fn get_irq_name(irq_number: u8) -> Option<&'static str> {
+pub(crate) fn get_irq_name(irq_number: u8) -> Option<&'static str> {
 	IRQ_NAMES.lock().get(&irq_number).copied()
 }
 
@@ -323,19 +395,12 @@ impl IrqStatistics {
 
 pub(crate) fn print_statistics() {
 	println!("Number of interrupts");
-	for (core_id, irg_statistics) in IRQ_COUNTERS.lock().iter() {
-		for (i, counter) in irg_statistics.counters.iter().enumerate() {
-			let counter = counter.load(Ordering::Relaxed);
-			if counter > 0 {
-				match get_irq_name(i.try_into().unwrap()) {
-					Some(name) => {
-						println!("[{core_id}][{name}]: {counter}");
-					}
-					_ => {
-						println!("[{core_id}][{i}]: {counter}");
-					}
-				}
-			}
+	for_each_irq_counter(|core_id, i, name, counter| match name {
+		Some(name) => {
+			println!("[{core_id}][{name}]: {counter}");
 		}
-	}
+		_ => {
+			println!("[{core_id}][{i}]: {counter}");
+		}
+	});
 }
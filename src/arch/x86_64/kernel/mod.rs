@@ -18,6 +18,8 @@ pub mod acpi;
 pub mod apic;
 pub mod core_local;
 pub mod gdt;
+#[cfg(feature = "acpi")]
+pub mod hpet;
 pub mod interrupts;
 #[cfg(all(not(feature = "pci"), any(feature = "tcp", feature = "udp")))]
 pub mod mmio;
@@ -186,6 +188,7 @@ pub fn boot_processor_init() {
 		acpi::init();
 	}
 
+	processor::select_clocksource();
 	apic::init();
 	scheduler::install_timer_handler();
 	serial::install_serial_interrupt();
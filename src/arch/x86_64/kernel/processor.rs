@@ -22,7 +22,7 @@ use x86_64::structures::DescriptorTablePointer;
 use x86_64::VirtAddr;
 
 #[cfg(feature = "acpi")]
-use crate::arch::x86_64::kernel::acpi;
+use crate::arch::x86_64::kernel::{acpi, hpet};
 use crate::arch::x86_64::kernel::{boot_info, interrupts, pic, pit};
 use crate::env;
 
@@ -279,28 +279,79 @@ impl fmt::Display for CpuFrequencySources {
 	}
 }
 
+/// Returns the factor in 0.128 fixed point, which multiplies a value by
+/// `numerator / denominator` (see [`mul_fixed_point`]). `numerator` has to be
+/// less than `denominator`.
+///
+/// The factor is rounded up. Because its error is less than 2^-128, the
+/// product of any 64-bit value is still the exact, rounded down ratio.
+pub(crate) const fn fixed_point_ratio(numerator: u64, denominator: u64) -> u128 {
+	assert!(numerator < denominator);
+
+	// Divide `numerator << 128` by `denominator` in two steps of 64 bits.
+	let denominator = denominator as u128;
+	let dividend = (numerator as u128) << 64;
+	let high = dividend / denominator;
+	let dividend = (dividend % denominator) << 64;
+	let low = dividend / denominator;
+	let factor = (high << 64) | low;
+
+	if dividend % denominator == 0 {
+		factor
+	} else {
+		factor + 1
+	}
+}
+
+/// Multiplies `value` by the fixed-point `factor` of [`fixed_point_ratio`].
+pub(crate) const fn mul_fixed_point(value: u64, factor: u128) -> u64 {
+	let high = value as u128 * (factor >> 64);
+	let low = (value as u128 * (factor as u64 as u128)) >> 64;
+	((high + low) >> 64) as u64
+}
+
+/// Returns the factor, which converts TSC cycles of a clock with `khz` to microseconds.
+const fn tsc_scale(khz: u32) -> u128 {
+	fixed_point_ratio(1000, khz as u64)
+}
+
+/// Converts `usecs` microseconds to TSC cycles of a clock with `khz`. The result
+/// is rounded up, such that the cycles scaled by [`tsc_scale`] amount to at least
+/// `usecs` microseconds once the returned number of cycles has elapsed.
+const fn micros_to_cycles(usecs: u64, khz: u32) -> u64 {
+	(usecs as u128 * khz as u128).div_ceil(1000) as u64
+}
+
 struct CpuFrequency {
-	mhz: u16,
+	/// Frequency of the TSC in kHz
+	khz: u32,
+	/// Factor, which converts TSC cycles to microseconds (see [`tsc_scale`])
+	scale: u128,
 	source: CpuFrequencySources,
+	/// The TSC runs at a constant rate in all power states
+	invariant: bool,
 }
 
 impl CpuFrequency {
 	const fn new() -> Self {
 		CpuFrequency {
-			mhz: 0,
+			khz: 0,
+			scale: 0,
 			source: CpuFrequencySources::Invalid,
+			invariant: false,
 		}
 	}
 
 	fn set_detected_cpu_frequency(
 		&mut self,
-		mhz: u16,
+		khz: u32,
 		source: CpuFrequencySources,
 	) -> Result<(), ()> {
-		//The clock frequency must never be set to zero, otherwise a division by zero will
-		//occur during runtime
-		if mhz > 0 {
-			self.mhz = mhz;
+		//The clock frequency must be above 1 MHz, otherwise the factor, which converts
+		//TSC cycles to microseconds, isn't a fraction
+		if khz > 1000 {
+			self.khz = khz;
+			self.scale = tsc_scale(khz);
 			self.source = source;
 			Ok(())
 		} else {
@@ -310,7 +361,7 @@ impl CpuFrequency {
 
 	unsafe fn detect_from_cmdline(&mut self) -> Result<(), ()> {
 		let mhz = env::freq().ok_or(())?;
-		self.set_detected_cpu_frequency(mhz, CpuFrequencySources::CommandLine)
+		self.set_detected_cpu_frequency(u32::from(mhz) * 1000, CpuFrequencySources::CommandLine)
 	}
 
 	unsafe fn detect_from_cpuid(&mut self, cpuid: &CpuId) -> Result<(), ()> {
@@ -319,7 +370,7 @@ impl CpuFrequency {
 		match processor_frequency_info {
 			Some(freq_info) => {
 				let mhz = freq_info.processor_base_frequency();
-				self.set_detected_cpu_frequency(mhz, CpuFrequencySources::CpuId)
+				self.set_detected_cpu_frequency(u32::from(mhz) * 1000, CpuFrequencySources::CpuId)
 			}
 			None => Err(()),
 		}
@@ -328,17 +379,15 @@ impl CpuFrequency {
 	unsafe fn detect_from_cpuid_tsc_info(&mut self, cpuid: &CpuId) -> Result<(), ()> {
 		let tsc_info = cpuid.get_tsc_info().ok_or(())?;
 		let freq = tsc_info.tsc_frequency().ok_or(())?;
-		let mhz = (freq / 1000000u64) as u16;
-		self.set_detected_cpu_frequency(mhz, CpuFrequencySources::CpuIdTscInfo)
+		let khz = u32::try_from(freq / 1000).map_err(|_| ())?;
+		self.set_detected_cpu_frequency(khz, CpuFrequencySources::CpuIdTscInfo)
 	}
 
 	unsafe fn detect_from_cpuid_hypervisor_info(&mut self, cpuid: &CpuId) -> Result<(), ()> {
-		const KHZ_TO_HZ: u64 = 1000;
-		const MHZ_TO_HZ: u64 = 1000000;
 		let hypervisor_info = cpuid.get_hypervisor_info().ok_or(())?;
-		let freq = hypervisor_info.tsc_frequency().ok_or(())? as u64 * KHZ_TO_HZ;
-		let mhz: u16 = (freq / MHZ_TO_HZ).try_into().unwrap();
-		self.set_detected_cpu_frequency(mhz, CpuFrequencySources::HypervisorTscInfo)
+		// the hypervisor reports the frequency in kHz
+		let khz = hypervisor_info.tsc_frequency().ok_or(())?;
+		self.set_detected_cpu_frequency(khz, CpuFrequencySources::HypervisorTscInfo)
 	}
 
 	unsafe fn detect_from_cpuid_brand_string(&mut self, cpuid: &CpuId) -> Result<(), ()> {
@@ -359,8 +408,11 @@ impl CpuFrequency {
 					hundred_char.to_digit(10),
 					ten_char.to_digit(10),
 				) {
-					let mhz = (thousand * 1000 + hundred * 100 + ten * 10) as u16;
-					return self.set_detected_cpu_frequency(mhz, CpuFrequencySources::CpuIdTscInfo);
+					let mhz = thousand * 1000 + hundred * 100 + ten * 10;
+					return self.set_detected_cpu_frequency(
+						mhz * 1000,
+						CpuFrequencySources::CpuIdBrandString,
+					);
 				}
 			}
 		}
@@ -369,12 +421,12 @@ impl CpuFrequency {
 	}
 
 	fn detect_from_hypervisor(&mut self) -> Result<(), ()> {
-		fn detect_from_uhyve() -> Result<u16, ()> {
+		fn detect_from_uhyve() -> Result<u32, ()> {
 			match boot_info().platform_info {
-				PlatformInfo::Uhyve { cpu_freq, .. } => Ok(u16::try_from(
-					cpu_freq.map(NonZeroU32::get).unwrap_or_default() / 1000,
-				)
-				.unwrap()),
+				// uhyve reports the frequency in kHz
+				PlatformInfo::Uhyve { cpu_freq, .. } => {
+					Ok(cpu_freq.map(NonZeroU32::get).unwrap_or_default())
+				}
 				_ => Err(()),
 			}
 		}
@@ -471,17 +523,22 @@ impl CpuFrequency {
 
 		// Calculate the CPU frequency out of this measurement.
 		let cycle_count = end - start;
-		let mhz = (measurement_frequency * cycle_count / (1_000_000 * tick_count)) as u16;
-		self.set_detected_cpu_frequency(mhz, CpuFrequencySources::Measurement)
+		let khz = measurement_frequency * cycle_count / (1000 * tick_count);
+		self.set_detected_cpu_frequency(
+			u32::try_from(khz).map_err(|_| ())?,
+			CpuFrequencySources::Measurement,
+		)
 	}
 
 	unsafe fn detect(&mut self) {
 		let cpuid = CpuId::new();
+		// The exact TSC frequency of the processor (leaf 0x15) or of the hypervisor
+		// (leaf 0x40000010) is preferred over the nominal base frequency (leaf 0x16).
 		unsafe {
-			self.detect_from_cpuid(&cpuid)
-				.or_else(|_e| self.detect_from_cpuid_tsc_info(&cpuid))
+			self.detect_from_cpuid_tsc_info(&cpuid)
 				.or_else(|_e| self.detect_from_cpuid_hypervisor_info(&cpuid))
 				.or_else(|_e| self.detect_from_hypervisor())
+				.or_else(|_e| self.detect_from_cpuid(&cpuid))
 				.or_else(|_e| self.detect_from_cmdline())
 				.or_else(|_e| self.detect_from_cpuid_brand_string(&cpuid))
 				.or_else(|_e| self.measure_frequency())
@@ -489,20 +546,31 @@ impl CpuFrequency {
 					warn!(
 						"Could not determine the processor frequency! Guess a frequency of 2Ghz!"
 					);
-					self.set_detected_cpu_frequency(2000, CpuFrequencySources::Visionary)
+					self.set_detected_cpu_frequency(2_000_000, CpuFrequencySources::Visionary)
 				})
 				.unwrap();
 		}
+
+		self.invariant = cpuid
+			.get_advanced_power_mgmt_info()
+			.is_some_and(|info| info.has_invariant_tsc());
+		info!("TSC frequency: {self}");
 	}
 
 	fn get(&self) -> u16 {
-		self.mhz
+		((self.khz + 500) / 1000).try_into().unwrap_or(u16::MAX)
 	}
 }
 
 impl fmt::Display for CpuFrequency {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "{} MHz (from {})", self.mhz, self.source)
+		write!(
+			f,
+			"{}.{:03} MHz (from {})",
+			self.khz / 1000,
+			self.khz % 1000,
+			self.source
+		)
 	}
 }
 
@@ -905,6 +973,22 @@ pub fn detect_frequency() {
 	Lazy::force(&CPU_FREQUENCY);
 }
 
+/// Uses the HPET as clocksource, if the TSC isn't invariant. The ACPI tables
+/// have to be parsed before.
+pub fn select_clocksource() {
+	if CPU_FREQUENCY.invariant {
+		return;
+	}
+
+	#[cfg(feature = "acpi")]
+	if hpet::init(get_timer_ticks()).is_ok() {
+		info!("The TSC isn't invariant, hence the HPET serves as clocksource");
+		return;
+	}
+
+	warn!("The TSC isn't invariant, hence the time may drift with frequency changes");
+}
+
 pub fn print_information() {
 	infoheader!(" CPU INFORMATION ");
 
@@ -984,6 +1068,12 @@ pub fn supports_avx() -> bool {
 
 #[inline]
 pub fn supports_tsc_deadline() -> bool {
+	// The TSC deadline can't be derived from the time of the HPET.
+	#[cfg(feature = "acpi")]
+	if hpet::is_enabled() {
+		return false;
+	}
+
 	FEATURES.supports_tsc_deadline
 }
 
@@ -1046,9 +1136,19 @@ pub fn shutdown(error_code: i32) -> ! {
 }
 
 pub fn get_timer_ticks() -> u64 {
+	#[cfg(feature = "acpi")]
+	if let Some(ticks) = hpet::get_timer_ticks() {
+		return ticks;
+	}
+
 	// We simulate a timer with a 1 microsecond resolution by taking the CPU timestamp
-	// and dividing it by the CPU frequency in MHz.
-	get_timestamp() / u64::from(get_frequency())
+	// and scaling it by the calibrated TSC frequency.
+	mul_fixed_point(get_timestamp(), CPU_FREQUENCY.scale)
+}
+
+/// Returns the number of TSC cycles of `usecs` microseconds.
+pub fn micros_to_timestamp(usecs: u64) -> u64 {
+	micros_to_cycles(usecs, CPU_FREQUENCY.khz)
 }
 
 pub fn get_frequency() -> u16 {
@@ -1129,8 +1229,55 @@ unsafe fn get_timestamp_rdtscp() -> u64 {
 /// Delay execution by the given number of microseconds using busy-waiting.
 #[inline]
 pub fn udelay(usecs: u64) {
-	let end = get_timestamp() + u64::from(get_frequency()) * usecs;
+	let end = get_timestamp() + micros_to_timestamp(usecs);
 	while get_timestamp() < end {
 		spin_loop();
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn convert_tsc_to_micros() {
+		let scale = tsc_scale(2_400_000);
+		assert_eq!(mul_fixed_point(2_400_000_000, scale), 1_000_000);
+		assert_eq!(micros_to_cycles(1_000_000, 2_400_000), 2_400_000_000);
+
+		// the fractional part of the frequency doesn't get lost
+		let one_hour = 3_600_000_000u64;
+		let cycles = micros_to_cycles(one_hour, 2_893_437);
+		assert_eq!(cycles, 10_416_373_200_000);
+		assert_eq!(mul_fixed_point(cycles, tsc_scale(2_893_437)), one_hour);
+	}
+
+	#[test]
+	fn fixed_point_ratio_is_exact() {
+		// TSC frequencies in kHz and HPET periods in femtoseconds
+		for (numerator, denominator) in [
+			(1000, 2_893_437),
+			(1000, u64::from(u32::MAX)),
+			(10_000_000, 1_000_000_000),
+			(69_841_279, 1_000_000_000),
+		] {
+			let factor = fixed_point_ratio(numerator, denominator);
+			for value in [0, 1, 999_999, 10_416_373_200_000, u64::MAX / 3, u64::MAX] {
+				let expected = u128::from(value) * u128::from(numerator) / u128::from(denominator);
+				assert_eq!(u128::from(mul_fixed_point(value, factor)), expected);
+			}
+		}
+	}
+
+	#[test]
+	fn deadline_does_not_expire_early() {
+		for khz in [1_000_001, 2_893_437, 3_000_000] {
+			let scale = tsc_scale(khz);
+			for usecs in [1, 7, 999, 1_000_003, 3_600_000_000] {
+				let cycles = micros_to_cycles(usecs, khz);
+				assert!(mul_fixed_point(cycles, scale) >= usecs);
+				assert!(mul_fixed_point(cycles - 1, scale) < usecs);
+			}
+		}
+	}
+}
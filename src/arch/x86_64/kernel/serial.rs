@@ -1,6 +1,7 @@
-#[cfg(feature = "shell")]
 use alloc::collections::VecDeque;
+use core::task::Waker;
 
+use hermit_sync::InterruptTicketMutex;
 use x86_64::instructions::port::Port;
 
 use crate::arch::x86_64::kernel::core_local::increment_irq_counter;
@@ -8,6 +9,13 @@ use crate::arch::x86_64::kernel::interrupts::{self, IDT};
 use crate::arch::x86_64::kernel::{apic, COM1};
 
 const SERIAL_IRQ: u8 = 36;
+/// Maximal number of received bytes, which aren't consumed yet.
+/// Further bytes are dropped.
+const INPUT_BUFFER_SIZE: usize = 4096;
+
+/// Tasks, which wait for input of the serial port
+static INPUT_WAKERS: InterruptTicketMutex<VecDeque<Waker>> =
+	InterruptTicketMutex::new(VecDeque::new());
 
 enum SerialInner {
 	Uart(uart_16550::SerialPort),
@@ -16,7 +24,6 @@ enum SerialInner {
 
 pub struct SerialPort {
 	inner: SerialInner,
-	#[cfg(feature = "shell")]
 	buffer: VecDeque<u8>,
 }
 
@@ -26,7 +33,6 @@ impl SerialPort {
 			let serial = Port::new(base);
 			Self {
 				inner: SerialInner::Uhyve(serial),
-				#[cfg(feature = "shell")]
 				buffer: VecDeque::new(),
 			}
 		} else {
@@ -34,7 +40,6 @@ impl SerialPort {
 			serial.init();
 			Self {
 				inner: SerialInner::Uart(serial),
-				#[cfg(feature = "shell")]
 				buffer: VecDeque::new(),
 			}
 		}
@@ -42,26 +47,31 @@ impl SerialPort {
 
 	pub fn buffer_input(&mut self) {
 		if let SerialInner::Uart(s) = &mut self.inner {
-			let c = unsafe { char::from_u32_unchecked(s.receive().into()) };
-			#[cfg(not(feature = "shell"))]
-			if !c.is_ascii_control() {
-				print!("{}", c);
+			let byte = s.receive();
+			if self.buffer.len() < INPUT_BUFFER_SIZE {
+				self.buffer.push_back(byte);
 			}
-			#[cfg(feature = "shell")]
-			self.buffer.push_back(c.try_into().unwrap());
 		}
 	}
 
+	/// Returns the next received byte.
 	#[allow(dead_code)]
-	#[cfg(feature = "shell")]
 	pub fn read(&mut self) -> Option<u8> {
 		self.buffer.pop_front()
 	}
 
-	#[allow(dead_code)]
-	#[cfg(not(feature = "shell"))]
-	pub fn read(&mut self) -> Option<u8> {
-		None
+	/// Moves the received bytes to `buf` and returns their number.
+	pub fn read_buf(&mut self, buf: &mut [u8]) -> usize {
+		let len = buf.len().min(self.buffer.len());
+		for (dst, src) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+			*dst = src;
+		}
+		len
+	}
+
+	/// Returns `true`, if received bytes are available.
+	pub fn has_input(&self) -> bool {
+		!self.buffer.is_empty()
 	}
 
 	pub fn send(&mut self, buf: &[u8]) {
@@ -84,6 +94,11 @@ impl SerialPort {
 
 extern "x86-interrupt" fn serial_interrupt(_stack_frame: crate::interrupts::ExceptionStackFrame) {
 	COM1.lock().as_mut().unwrap().buffer_input();
+	#[cfg(feature = "shell")]
+	crate::shell::wakeup();
+	for waker in core::mem::take(&mut *INPUT_WAKERS.lock()) {
+		waker.wake();
+	}
 	increment_irq_counter(SERIAL_IRQ);
 
 	apic::eoi();
@@ -98,3 +113,11 @@ pub(crate) fn install_serial_interrupt() {
 	}
 	interrupts::add_irq_name(SERIAL_IRQ - 32, "COM1");
 }
+
+/// Registers `waker`, which is woken up, when the serial port receives input.
+pub(crate) fn register_input_waker(waker: &Waker) {
+	let mut wakers = INPUT_WAKERS.lock();
+	if !wakers.iter().any(|w| w.will_wake(waker)) {
+		wakers.push_back(waker.clone());
+	}
+}
@@ -1,11 +1,20 @@
+use alloc::boxed::Box;
+use core::arch::x86_64::__cpuid;
 use core::hint::spin_loop;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
 
 use hermit_entry::boot_info::PlatformInfo;
 use hermit_sync::{without_interrupts, OnceCell};
 use time::OffsetDateTime;
+use x86::cpuid::CpuId;
 use x86::io::*;
+use x86::msr::wrmsr;
 
 use crate::arch::x86_64::kernel::{boot_info, processor};
+use crate::arch::x86_64::mm::paging::virtual_to_physical;
+use crate::arch::x86_64::mm::VirtAddr;
+use crate::env;
 
 const CMOS_COMMAND_PORT: u16 = 0x70;
 const CMOS_DATA_PORT: u16 = 0x71;
@@ -26,15 +35,166 @@ const CMOS_24_HOUR_FORMAT_FLAG: u8 = 1 << 1;
 const CMOS_BINARY_FORMAT_FLAG: u8 = 1 << 2;
 const CMOS_12_HOUR_PM_FLAG: u8 = 0x80;
 
+/// First CPUID leaf, which is reserved for hypervisors
+const HYPERVISOR_CPUID_BASE: u32 = 0x4000_0000;
+/// End of the CPUID leaves, which are reserved for hypervisors
+const HYPERVISOR_CPUID_END: u32 = 0x4001_0000;
+/// Signature of KVM in `ebx`, `ecx` and `edx` of its base leaf
+const KVM_SIGNATURE: &[u8; 12] = b"KVMKVMKVM\0\0\0";
+/// Offset of the CPUID leaf with the features of KVM to the base leaf
+const KVM_CPUID_FEATURES: u32 = 1;
+/// KVM supports the kvm-clock MSRs [`MSR_KVM_WALL_CLOCK_NEW`] and [`MSR_KVM_SYSTEM_TIME_NEW`]
+const KVM_FEATURE_CLOCKSOURCE2: u32 = 1 << 3;
+/// Requests the wall clock at the time, at which kvm-clock was zero
+const MSR_KVM_WALL_CLOCK_NEW: u32 = 0x4b56_4d00;
+/// Enables the kvm-clock of the current vCPU
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4b56_4d01;
+
+/// Wall clock, which is written by KVM.
+/// The alignment ensures that the structure doesn't cross a page boundary.
+#[repr(C, align(16))]
+#[derive(Default)]
+struct PvclockWallClock {
+	version: u32,
+	sec: u32,
+	nsec: u32,
+}
+
+/// kvm-clock of a vCPU, which is updated by KVM.
+/// The alignment ensures that the structure doesn't cross a page boundary.
+#[repr(C, align(32))]
+#[derive(Default, Clone, Copy)]
+#[allow(dead_code)]
+struct PvclockVcpuTimeInfo {
+	version: u32,
+	pad0: u32,
+	tsc_timestamp: u64,
+	system_time: u64,
+	tsc_to_system_mul: u32,
+	tsc_shift: i8,
+	flags: u8,
+	pad: [u8; 2],
+}
+
+impl PvclockVcpuTimeInfo {
+	/// Returns the nanoseconds since kvm-clock was zero at the time stamp `tsc`.
+	fn system_time(&self, tsc: u64) -> u64 {
+		let delta = tsc.wrapping_sub(self.tsc_timestamp);
+		let delta = if self.tsc_shift >= 0 {
+			delta << self.tsc_shift
+		} else {
+			delta >> -self.tsc_shift
+		};
+
+		self.system_time + ((u128::from(delta) * u128::from(self.tsc_to_system_mul)) >> 32) as u64
+	}
+}
+
+/// Reads `*src` again, until the version of the structure is even and didn't change
+/// during the read. KVM increments the version before and after each update.
+///
+/// # Safety
+///
+/// `src` has to point to a readable structure, whose first field is the version.
+unsafe fn read_versioned<T: Copy>(src: *const T) -> T {
+	let version = src.cast::<u32>();
+
+	loop {
+		let before = unsafe { ptr::read_volatile(version) };
+		if before % 2 == 1 {
+			spin_loop();
+			continue;
+		}
+
+		fence(Ordering::Acquire);
+		let value = unsafe { ptr::read_volatile(src) };
+		fence(Ordering::Acquire);
+
+		if unsafe { ptr::read_volatile(version) } == before {
+			return value;
+		}
+	}
+}
+
+fn virt_to_phys<T>(value: &T) -> u64 {
+	let addr = ptr::from_ref(value).addr();
+	virtual_to_physical(VirtAddr::from(addr)).unwrap().as_u64()
+}
+
+/// Returns the base leaf of the CPUID leaves of KVM, if the kernel runs on KVM.
+///
+/// KVM doesn't start at [`HYPERVISOR_CPUID_BASE`], if it also provides the
+/// interface of another hypervisor, e.g. of Hyper-V. Hence, the base leaf is
+/// searched in steps of 0x100 like Linux does.
+fn kvm_cpuid_base() -> Option<u32> {
+	if !CpuId::new()
+		.get_feature_info()
+		.is_some_and(|info| info.has_hypervisor())
+	{
+		return None;
+	}
+
+	(HYPERVISOR_CPUID_BASE..HYPERVISOR_CPUID_END)
+		.step_by(0x100)
+		.find(|&leaf| {
+			let result = unsafe { __cpuid(leaf) };
+			let mut signature = [0; 12];
+			signature[..4].copy_from_slice(&result.ebx.to_le_bytes());
+			signature[4..8].copy_from_slice(&result.ecx.to_le_bytes());
+			signature[8..].copy_from_slice(&result.edx.to_le_bytes());
+			&signature == KVM_SIGNATURE
+		})
+}
+
+/// Returns the current time in microseconds since the epoch from kvm-clock,
+/// if the kernel runs on KVM.
+fn kvm_clock_micros() -> Option<u64> {
+	let base = kvm_cpuid_base()?;
+	let features = unsafe { __cpuid(base + KVM_CPUID_FEATURES) }.eax;
+	if features & KVM_FEATURE_CLOCKSOURCE2 == 0 {
+		return None;
+	}
+
+	let wall_clock = Box::new(PvclockWallClock::default());
+	let time_info = Box::new(PvclockVcpuTimeInfo::default());
+	let wall_clock_addr = virt_to_phys(&*wall_clock);
+	let time_info_addr = virt_to_phys(&*time_info);
+
+	let micros = without_interrupts(|| unsafe {
+		// Bit 0 enables the kvm-clock of the current vCPU.
+		wrmsr(MSR_KVM_SYSTEM_TIME_NEW, time_info_addr | 1);
+		wrmsr(MSR_KVM_WALL_CLOCK_NEW, wall_clock_addr);
+
+		let version = ptr::read_volatile(ptr::addr_of!(wall_clock.version));
+		fence(Ordering::Acquire);
+		let sec = ptr::read_volatile(ptr::addr_of!(wall_clock.sec));
+		let nsec = ptr::read_volatile(ptr::addr_of!(wall_clock.nsec));
+		debug_assert_eq!(version % 2, 0);
+
+		let time_info = read_versioned(ptr::from_ref(&*time_info));
+		let system_time = time_info.system_time(processor::get_timestamp());
+
+		// Stop the updates of kvm-clock before the memory is freed.
+		wrmsr(MSR_KVM_SYSTEM_TIME_NEW, 0);
+
+		(u64::from(sec) * 1_000_000_000 + u64::from(nsec) + system_time) / 1000
+	});
+
+	debug!("Read wall clock from kvm-clock");
+	Some(micros)
+}
+
 struct Rtc {
 	cmos_format: u8,
 }
 
 impl Rtc {
-	fn new() -> Self {
-		Self {
-			cmos_format: Self::read_cmos_register(CMOS_STATUS_REGISTER_B),
-		}
+	/// Returns `None`, if the system doesn't provide a CMOS RTC.
+	fn new() -> Option<Self> {
+		let cmos_format = Self::read_cmos_register(CMOS_STATUS_REGISTER_B);
+
+		// Reads of a missing RTC return all bits set.
+		(cmos_format != 0xff).then_some(Self { cmos_format })
 	}
 
 	const fn is_24_hour_format(&self) -> bool {
@@ -172,20 +332,32 @@ impl Rtc {
 
 static BOOT_TIME: OnceCell<u64> = OnceCell::new();
 
+/// Returns the boot time in microseconds since the epoch.
+fn boot_time_micros() -> u64 {
+	if let PlatformInfo::Uhyve { boot_time, .. } = boot_info().platform_info {
+		return u64::try_from(boot_time.unix_timestamp_nanos() / 1000).unwrap();
+	}
+
+	// Get the current time in microseconds since the epoch (1970-01-01) from kvm-clock
+	// or the x86 RTC. Subtract the timer ticks to get the actual time when Hermit was booted.
+	let current_time = kvm_clock_micros().or_else(|| {
+		without_interrupts(|| Rtc::new().map(|rtc| rtc.get_microseconds_since_epoch()))
+	});
+	if let Some(current_time) = current_time {
+		return current_time - processor::get_timer_ticks();
+	}
+
+	env::boot_epoch().unwrap_or_else(|| {
+		warn!("No wall clock available, the realtime clock starts at the epoch");
+		0
+	})
+}
+
 pub fn init() {
-	let boot_time = match boot_info().platform_info {
-		PlatformInfo::Uhyve { boot_time, .. } => boot_time,
-		_ => {
-			// Get the current time in microseconds since the epoch (1970-01-01) from the x86 RTC.
-			// Subtract the timer ticks to get the actual time when Hermit was booted.
-			let current_time = without_interrupts(|| Rtc::new().get_microseconds_since_epoch());
-			let boot_time = current_time - processor::get_timer_ticks();
-			OffsetDateTime::from_unix_timestamp_nanos(boot_time as i128 * 1000).unwrap()
-		}
-	};
+	let micros = boot_time_micros();
+	let boot_time = OffsetDateTime::from_unix_timestamp_nanos(i128::from(micros) * 1000).unwrap();
 	info!("Hermit booted on {boot_time}");
 
-	let micros = u64::try_from(boot_time.unix_timestamp_nanos() / 1000).unwrap();
 	BOOT_TIME.set(micros).unwrap();
 }
 
@@ -193,3 +365,28 @@ pub fn init() {
 pub fn now_micros() -> u64 {
 	*BOOT_TIME.get().unwrap() + super::processor::get_timer_ticks()
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scale_kvm_clock() {
+		let time_info = PvclockVcpuTimeInfo {
+			tsc_timestamp: 1000,
+			system_time: 5_000_000,
+			// 2 GHz: 0.5 ns per tick
+			tsc_to_system_mul: 1 << 31,
+			tsc_shift: 0,
+			..Default::default()
+		};
+		assert_eq!(time_info.system_time(1000), 5_000_000);
+		assert_eq!(time_info.system_time(3000), 5_001_000);
+
+		let time_info = PvclockVcpuTimeInfo {
+			tsc_shift: -1,
+			..time_info
+		};
+		assert_eq!(time_info.system_time(3000), 5_000_500);
+	}
+}
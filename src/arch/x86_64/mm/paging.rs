@@ -118,11 +118,49 @@ pub fn virtual_to_physical(virtual_address: VirtAddr) -> Option<PhysAddr> {
 	}
 }
 
+#[cfg(not(all(test, not(target_os = "none"))))]
 #[no_mangle]
 pub extern "C" fn virt_to_phys(virtual_address: VirtAddr) -> PhysAddr {
 	virtual_to_physical(virtual_address).unwrap()
 }
 
+/// Host tests run without page tables. Hence, the memory is identity mapped,
+/// unless a test shifts the physical addresses by [`PhysOffset`].
+#[cfg(all(test, not(target_os = "none")))]
+pub extern "C" fn virt_to_phys(virtual_address: VirtAddr) -> PhysAddr {
+	PhysAddr(virtual_address.0 + PHYS_OFFSET.get())
+}
+
+/// Offset between the virtual and the physical addresses of the current test
+#[cfg(all(test, not(target_os = "none")))]
+#[thread_local]
+static PHYS_OFFSET: core::cell::Cell<u64> = core::cell::Cell::new(0);
+
+/// Shifts the physical addresses of the current thread by an offset, until it is
+/// dropped. This makes addresses visible, which are passed to a device without
+/// translation.
+#[cfg(all(test, not(target_os = "none")))]
+pub(crate) struct PhysOffset(u64);
+
+#[cfg(all(test, not(target_os = "none")))]
+impl PhysOffset {
+	pub(crate) fn new(offset: u64) -> Self {
+		PHYS_OFFSET.set(offset);
+		Self(offset)
+	}
+
+	pub(crate) fn get(&self) -> u64 {
+		self.0
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl Drop for PhysOffset {
+	fn drop(&mut self) {
+		PHYS_OFFSET.set(0);
+	}
+}
+
 /// Maps a continuous range of pages.
 ///
 /// # Arguments
@@ -272,6 +310,13 @@ pub(crate) extern "x86-interrupt" fn page_fault_handler(
 	stack_frame: ExceptionStackFrame,
 	error_code: PageFaultErrorCode,
 ) {
+	// released pages of anonymous mappings are mapped again on demand
+	if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+		&& mm::mmap::handle_page_fault(VirtAddr(Cr2::read().unwrap().as_u64()))
+	{
+		return;
+	}
+
 	error!("Page fault (#PF)!");
 	error!("page_fault_linear_address = {:p}", Cr2::read().unwrap());
 	error!("error_code = {error_code:?}");
@@ -4,18 +4,17 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
 use pci_types::InterruptLine;
-use virtio_spec::FeatureBits;
 
 use crate::config::VIRTIO_MAX_QUEUE_SIZE;
 #[cfg(feature = "pci")]
 use crate::drivers::fs::virtio_pci::FsDevCfgRaw;
 use crate::drivers::virtio::error::VirtioFsError;
-#[cfg(not(feature = "pci"))]
-use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, NotifCfg};
-#[cfg(feature = "pci")]
-use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::features::{self, MissingFeature, OptionalFeatures, RequiredFeatures};
+use crate::drivers::virtio::transport::{ComCfg, IsrStatus, NotifCfg};
 use crate::drivers::virtio::virtqueue::error::VirtqError;
 use crate::drivers::virtio::virtqueue::split::SplitVq;
+#[cfg(feature = "virtio-latency")]
+use crate::drivers::virtio::virtqueue::LatencyHistogram;
 use crate::drivers::virtio::virtqueue::{AsSliceU8, BufferType, Virtq, VqIndex, VqSize};
 use crate::fs::fuse::{self, FuseInterface};
 
@@ -54,30 +53,13 @@ impl VirtioFsDriver {
 		self.com_cfg.set_failed();
 	}
 
-	/// Negotiates a subset of features, understood and wanted by both the OS
-	/// and the device.
-	fn negotiate_features(
-		&mut self,
-		driver_features: virtio_spec::fs::F,
-	) -> Result<(), VirtioFsError> {
-		let device_features = virtio_spec::fs::F::from(self.com_cfg.dev_features());
-
-		if device_features.requirements_satisfied() {
-			debug!("Feature set wanted by filesystem driver are in conformance with specification.")
-		} else {
-			return Err(VirtioFsError::FeatureRequirementsNotMet(device_features));
-		}
-
-		if device_features.contains(driver_features) {
-			// If device supports subset of features write feature set to common config
-			self.com_cfg.set_drv_features(driver_features.into());
-			Ok(())
-		} else {
-			Err(VirtioFsError::IncompatibleFeatureSets(
-				driver_features,
-				device_features,
-			))
-		}
+	/// Returns the latency histograms of the queues together with their queue index.
+	#[cfg(feature = "virtio-latency")]
+	pub fn latency_histograms(&self) -> Vec<(VqIndex, LatencyHistogram)> {
+		self.vqueues
+			.iter()
+			.map(|vq| (vq.index(), vq.latency_histogram()))
+			.collect()
 	}
 
 	/// Initializes the device in adherence to specification. Returns Some(VirtioFsError)
@@ -95,8 +77,15 @@ impl VirtioFsDriver {
 		// Indicate device, that driver is able to handle it
 		self.com_cfg.set_drv();
 
-		let features = virtio_spec::fs::F::VERSION_1;
-		self.negotiate_features(features)?;
+		let required = RequiredFeatures(virtio_spec::fs::F::VERSION_1);
+		let optional = OptionalFeatures(virtio_spec::fs::F::empty());
+		let device_features = virtio_spec::fs::F::from(self.com_cfg.dev_features());
+		let features = features::negotiate(device_features, required, optional).map_err(
+			|MissingFeature(missing)| {
+				VirtioFsError::IncompatibleFeatureSets(missing, device_features)
+			},
+		)?;
+		self.com_cfg.set_drv_features(features.into());
 
 		// Indicates the device, that the current feature set is final for the driver
 		// and will not be changed.
@@ -184,9 +173,6 @@ pub mod error {
 		/// The first field contains the feature bits wanted by the driver.
 		/// but which are incompatible with the device feature set, second field.
 		IncompatibleFeatureSets(virtio_spec::fs::F, virtio_spec::fs::F),
-		/// Set of features does not adhere to the requirements of features
-		/// indicated by the specification
-		FeatureRequirementsNotMet(virtio_spec::fs::F),
 		Unknown,
 	}
 }
@@ -107,9 +107,9 @@ impl VirtioFsDriver {
 
 		Ok(VirtioFsDriver {
 			dev_cfg,
-			com_cfg,
-			isr_stat,
-			notif_cfg,
+			com_cfg: com_cfg.into(),
+			isr_stat: isr_stat.into(),
+			notif_cfg: notif_cfg.into(),
 			vqueues: Vec::new(),
 			irq: device.get_irq().unwrap(),
 		})
@@ -1,5 +1,20 @@
 //! A module containing hermit-rs driver, hermit-rs driver trait and driver specific errors.
 
+// Feature combinations, which compile to a driver set that can't work.
+//
+// `rtl8139` together with `fuse` is a valid combination: the RTL8139 driver
+// only replaces virtio-net, while virtio-fs is still available.
+#[cfg(all(feature = "rtl8139", not(target_arch = "x86_64")))]
+compile_error!("The feature `rtl8139` is only supported on x86_64.");
+#[cfg(all(feature = "virtio-legacy", not(target_arch = "x86_64")))]
+compile_error!(
+	"The feature `virtio-legacy` is only supported on x86_64, because the legacy interface uses I/O ports."
+);
+#[cfg(all(feature = "virtio-legacy", feature = "rtl8139"))]
+compile_error!(
+	"The feature `virtio-legacy` requires the virtio network driver, which is replaced by `rtl8139`."
+);
+
 #[cfg(feature = "fuse")]
 pub mod fs;
 #[cfg(not(feature = "pci"))]
@@ -9,6 +9,8 @@ pub mod virtio_net;
 #[cfg(all(feature = "pci", not(feature = "rtl8139")))]
 pub mod virtio_pci;
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use smoltcp::phy::ChecksumCapabilities;
 
 #[cfg(target_arch = "x86_64")]
@@ -25,6 +27,30 @@ use crate::arch::scheduler::State;
 use crate::drivers::pci as hardware;
 use crate::executor::device::{RxToken, TxToken};
 
+/// Type of the packet, from which the network device computed the flow hash
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HashType {
+	Ipv4,
+	Tcpv4,
+	Udpv4,
+	Ipv6,
+	Tcpv6,
+	Udpv6,
+	Ipv6Ex,
+	Tcpv6Ex,
+	Udpv6Ex,
+}
+
+/// Flow hash of a received packet, which is computed by the network device.
+/// Allows to steer packets of the same flow to the same core.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RxHash {
+	pub value: u32,
+	pub hash_type: HashType,
+}
+
 /// A trait for accessing the network interface
 pub(crate) trait NetworkDriver {
 	/// Returns smoltcp's checksum capabilities
@@ -46,14 +72,42 @@ pub(crate) trait NetworkDriver {
 	fn has_packet(&self) -> bool;
 	/// Enable / disable the polling mode of the network interface
 	fn set_polling_mode(&mut self, value: bool);
+	/// Returns `true`, if received packets don't raise interrupts and
+	/// the device has to be polled continuously
+	fn is_busy_polling(&self) -> bool {
+		false
+	}
 	/// Handle interrupt and check if a packet is available
 	fn handle_interrupt(&mut self) -> bool;
+	/// Acknowledges that the addresses of the interface are announced
+	fn ack_announce(&mut self) {}
+}
+
+/// Set, if the device requests to announce the addresses of the interface
+static ANNOUNCE_REQUEST: AtomicBool = AtomicBool::new(false);
+
+/// Requests to announce the addresses of the interface, e.g. after a live migration.
+pub(crate) fn request_announce() {
+	ANNOUNCE_REQUEST.store(true, Ordering::Relaxed);
+}
+
+/// Returns and resets the pending request to announce the addresses of the interface.
+pub(crate) fn take_announce_request() -> bool {
+	ANNOUNCE_REQUEST.swap(false, Ordering::Relaxed)
 }
 
 #[inline]
 fn _irqhandler() -> bool {
 	let result = if let Some(driver) = hardware::get_network_driver() {
-		driver.lock().handle_interrupt()
+		let mut guard = driver.lock();
+		let result = guard.handle_interrupt();
+		if result {
+			// SAFETY: the driver lock serializes the interrupt handlers
+			unsafe {
+				crate::executor::network::signal_rx_ready();
+			}
+		}
+		result
 	} else {
 		debug!("Unable to handle interrupt!");
 		false
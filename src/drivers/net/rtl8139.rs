@@ -3,7 +3,9 @@
 #![allow(dead_code)]
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::mem;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use pci_types::{Bar, InterruptLine, MAX_BARS};
 use x86::io::*;
@@ -18,7 +20,7 @@ use crate::drivers::net::{network_irqhandler, NetworkDriver};
 use crate::drivers::pci::{PciCommand, PciDevice};
 use crate::executor::device::{RxToken, TxToken};
 
-/// size of the receive buffer
+/// size of the receive ring
 const RX_BUF_LEN: usize = 8192;
 /// size of the send buffer
 const TX_BUF_LEN: usize = 4096;
@@ -183,13 +185,25 @@ const TSD_SIZE: u32 = 0x1fff; // Descriptor size, the total size in bytes of dat
 /// interrupts, we would have the TOK and ROK bits of the IMR high and leave the
 /// rest low. That way when a TOK or ROK IRQ happens, it actually will go through
 /// and fire up an IRQ.
-const INT_MASK: u16 = ISR_ROK | ISR_TOK | ISR_RXOVW | ISR_TER | ISR_RER;
+const INT_MASK: u16 = ISR_ROK | ISR_TOK | ISR_RXOVW | ISR_FIFOOVW | ISR_TER | ISR_RER;
 
 /// Beside Receive OK (ROK) interrupt, this mask enable all other interrupts
-const INT_MASK_NO_ROK: u16 = ISR_TOK | ISR_RXOVW | ISR_TER | ISR_RER;
+const INT_MASK_NO_ROK: u16 = ISR_TOK | ISR_RXOVW | ISR_FIFOOVW | ISR_TER | ISR_RER;
 
 const NO_TX_BUFFERS: usize = 4;
 
+/// The chip may write up to 16 bytes behind the end of the receive ring.
+/// See RTL8139 datasheet, Receive Configuration Register
+const RX_BUF_PAD: usize = 16;
+
+/// Size of the receive header in front of each packet (status and length)
+const RX_HEADER_LEN: usize = 4;
+/// Size of the CRC at the end of each received packet
+const RX_CRC_LEN: usize = 4;
+
+/// Configuration of the receiver
+const RCR_CONFIG: u32 = RCR_MXDMA2 | RCR_MXDMA1 | RCR_MXDMA0 | RCR_AB | RCR_AM | RCR_APM | RCR_AAP;
+
 #[derive(Debug)]
 pub enum RTL8139Error {
 	InitFailed,
@@ -197,6 +211,61 @@ pub enum RTL8139Error {
 	Unknown,
 }
 
+/// Error counters of the RTL8139 driver
+pub(crate) struct Rtl8139Stats {
+	/// Received packets with an invalid header or length
+	pub rx_errors: AtomicU64,
+	/// Overflows of the receive ring or the receive FIFO
+	pub rx_overflows: AtomicU64,
+	/// Aborted transmissions and out of window collisions
+	pub tx_errors: AtomicU64,
+	/// Underruns of the transmit FIFO
+	pub tx_underruns: AtomicU64,
+	/// Number of resets, which are triggered to recover from errors
+	pub resets: AtomicU64,
+}
+
+impl Rtl8139Stats {
+	const fn new() -> Self {
+		Self {
+			rx_errors: AtomicU64::new(0),
+			rx_overflows: AtomicU64::new(0),
+			tx_errors: AtomicU64::new(0),
+			tx_underruns: AtomicU64::new(0),
+			resets: AtomicU64::new(0),
+		}
+	}
+}
+
+pub(crate) static RTL8139_STATS: Rtl8139Stats = Rtl8139Stats::new();
+
+/// Returns the length of the packet, which is described by the receive header,
+/// without the CRC. Fails, if the header reports an error or an invalid length.
+fn rx_packet_len(status: u16, length: u16) -> Option<usize> {
+	if status & ISR_ROK != ISR_ROK {
+		return None;
+	}
+
+	let length = usize::from(length);
+	if length <= RX_CRC_LEN || length > RX_BUF_LEN - RX_HEADER_LEN {
+		return None;
+	}
+
+	Some(length - RX_CRC_LEN)
+}
+
+/// Copies `len` bytes at `pos` out of the receive ring.
+/// The packet may wrap around at the end of the ring.
+fn copy_from_ring(ring: &[u8], pos: usize, len: usize) -> Vec<u8> {
+	if pos + len > RX_BUF_LEN {
+		let first = &ring[pos..RX_BUF_LEN];
+		let second = &ring[..len - first.len()];
+		[first, second].concat()
+	} else {
+		ring[pos..][..len].to_vec()
+	}
+}
+
 /// RealTek RTL8139 network driver struct.
 ///
 /// Struct allows to control device queues as also
@@ -211,6 +280,7 @@ pub(crate) struct RTL8139Driver {
 	rxbuffer: Box<[u8]>,
 	rxpos: usize,
 	txbuffer: Box<[u8]>,
+	polling: bool,
 }
 
 impl NetworkDriver for RTL8139Driver {
@@ -274,28 +344,27 @@ impl NetworkDriver for RTL8139Driver {
 			let header = self.rx_peek_u16();
 			self.advance_rxpos(mem::size_of::<u16>());
 
-			if header & ISR_ROK == ISR_ROK {
-				let length = self.rx_peek_u16() - 4; // copy packet (but not the CRC)
+			if let Some(length) = rx_packet_len(header, self.rx_peek_u16()) {
 				let pos = (self.rxpos + mem::size_of::<u16>()) % RX_BUF_LEN;
 
-				// do we reach the end of the receive buffers?
-				// in this case, we conact the two slices to one vec
-				let vec_data = if pos + length as usize > RX_BUF_LEN {
-					let first = &self.rxbuffer[pos..RX_BUF_LEN];
-					let second = &self.rxbuffer[..length as usize - first.len()];
-					[first, second].concat()
-				} else {
-					(self.rxbuffer[pos..][..length.into()]).to_vec()
-				};
+				// copy packet (but not the CRC)
+				let vec_data = copy_from_ring(&self.rxbuffer, pos, length);
 
 				self.consume_current_buffer();
 
 				Some((RxToken::new(vec_data), TxToken::new()))
 			} else {
-				warn!(
-					"RTL8192: invalid header {:#x}, rx_pos {}\n",
-					header, self.rxpos
+				rate_limited_warn!(
+					"rtl8139-rx",
+					"RTL8139: invalid header {:#x}, length {}, rx_pos {}",
+					header,
+					self.rx_peek_u16(),
+					self.rxpos
 				);
+				RTL8139_STATS.rx_errors.fetch_add(1, Ordering::Relaxed);
+
+				// The position of the next packet is unknown
+				self.reset_rx();
 
 				None
 			}
@@ -305,6 +374,7 @@ impl NetworkDriver for RTL8139Driver {
 	}
 
 	fn set_polling_mode(&mut self, value: bool) {
+		self.polling = value;
 		if value {
 			unsafe {
 				outw(self.iobase + IMR, INT_MASK_NO_ROK);
@@ -327,15 +397,19 @@ impl NetworkDriver for RTL8139Driver {
 		}
 
 		if (isr_contents & ISR_RER) == ISR_RER {
-			error!("RTL88139: RX error detected!\n");
+			rate_limited_error!("rtl8139-rx", "RTL8139: RX error detected!");
+			RTL8139_STATS.rx_errors.fetch_add(1, Ordering::Relaxed);
 		}
 
 		if (isr_contents & ISR_TER) == ISR_TER {
-			trace!("RTL88139r: TX error detected!\n");
+			rate_limited_warn!("rtl8139-tx", "RTL8139: TX error detected!");
+			self.tx_handler();
 		}
 
-		if (isr_contents & ISR_RXOVW) == ISR_RXOVW {
-			trace!("RTL88139: RX overflow detected!\n");
+		let overflow = isr_contents & (ISR_RXOVW | ISR_FIFOOVW) != 0;
+		if overflow {
+			rate_limited_warn!("rtl8139-rx", "RTL8139: RX overflow detected!");
+			RTL8139_STATS.rx_overflows.fetch_add(1, Ordering::Relaxed);
 		}
 
 		let ret = (isr_contents & ISR_ROK) == ISR_ROK;
@@ -343,10 +417,15 @@ impl NetworkDriver for RTL8139Driver {
 		unsafe {
 			outw(
 				self.iobase + ISR,
-				isr_contents & (ISR_RXOVW | ISR_TER | ISR_RER | ISR_TOK | ISR_ROK),
+				isr_contents & (ISR_RXOVW | ISR_FIFOOVW | ISR_TER | ISR_RER | ISR_TOK | ISR_ROK),
 			);
 		}
 
+		// The packets in the receive ring are lost, but the receiver works again.
+		if overflow {
+			self.reset_rx();
+		}
+
 		ret
 	}
 }
@@ -387,17 +466,25 @@ impl RTL8139Driver {
 	}
 
 	fn tx_handler(&mut self) {
+		let mut abort = false;
+
 		for i in 0..self.tx_in_use.len() {
 			if self.tx_in_use[i] {
 				let txstatus = unsafe { inl(self.iobase + TSD0 + i as u16 * 4) };
 
 				if (txstatus & (TSD_TABT | TSD_OWC)) > 0 {
-					error!("RTL8139: major error");
+					rate_limited_error!(
+						"rtl8139-tx",
+						"RTL8139: transmission aborted, status {txstatus:#x}"
+					);
+					RTL8139_STATS.tx_errors.fetch_add(1, Ordering::Relaxed);
+					abort = true;
 					continue;
 				}
 
 				if (txstatus & TSD_TUN) == TSD_TUN {
-					error!("RTL8139: transmit underrun");
+					rate_limited_warn!("rtl8139-tx", "RTL8139: transmit underrun");
+					RTL8139_STATS.tx_underruns.fetch_add(1, Ordering::Relaxed);
 				}
 
 				if (txstatus & TSD_TOK) == TSD_TOK {
@@ -405,6 +492,64 @@ impl RTL8139Driver {
 				}
 			}
 		}
+
+		// An aborted buffer is never released by the chip. Otherwise,
+		// the driver runs out of transmit buffers.
+		if abort {
+			self.reset();
+		}
+	}
+
+	/// Restarts the receiver after an overflow or an invalid packet header.
+	///
+	/// See RTL8139 programming guide, Receive Buffer Overflow
+	fn reset_rx(&mut self) {
+		unsafe {
+			// disable the receiver
+			outb(self.iobase + CR, CR_TE);
+		}
+
+		self.rxpos = 0;
+
+		unsafe {
+			outl(self.iobase + RBSTART, phys_addr(self.rxbuffer.as_ptr()));
+			outw(self.iobase + CAPR, (RX_BUF_LEN - 0x10).try_into().unwrap());
+			outb(self.iobase + CR, CR_TE | CR_RE);
+			outl(self.iobase + RCR, RCR_CONFIG);
+		}
+	}
+
+	/// Resets the chip and restores the configuration of the driver.
+	/// All packets, which are in transit, are lost.
+	fn reset(&mut self) {
+		warn!("RTL8139: reset chip");
+		RTL8139_STATS.resets.fetch_add(1, Ordering::Relaxed);
+
+		unsafe {
+			if let Err(err) = reset_chip(self.iobase) {
+				error!("RTL8139: unable to recover from error: {err:?}");
+				return;
+			}
+
+			configure(self.iobase);
+			program_buffers(self.iobase, &self.rxbuffer, &self.txbuffer);
+		}
+
+		self.rxpos = 0;
+		self.tx_in_use = [false; NO_TX_BUFFERS];
+		self.tx_counter = 0;
+
+		unsafe {
+			outw(
+				self.iobase + IMR,
+				if self.polling {
+					INT_MASK_NO_ROK
+				} else {
+					INT_MASK
+				},
+			);
+			outb(self.iobase + CR, CR_TE | CR_RE);
+		}
 	}
 }
 
@@ -462,6 +607,77 @@ pub(crate) fn init_device(
 			return Err(DriverError::InitRTL8139DevFail(RTL8139Error::InitFailed));
 		}
 
+		reset_chip(iobase).map_err(DriverError::InitRTL8139DevFail)?;
+		configure(iobase);
+	}
+
+	// The WRAP bit isn't set. Hence, the chip wraps around at the end of the
+	// ring, but may still write a few bytes behind the end.
+	let rxbuffer = vec![0; RX_BUF_LEN + RX_BUF_PAD].into_boxed_slice();
+	let txbuffer = vec![0; NO_TX_BUFFERS * TX_BUF_LEN].into_boxed_slice();
+
+	debug!(
+		"Allocate TxBuffer at {:p} and RxBuffer at {:p}",
+		txbuffer, rxbuffer
+	);
+
+	unsafe {
+		program_buffers(iobase, &rxbuffer, &txbuffer);
+
+		// Enable all known interrupts by setting the interrupt mask.
+		outw(iobase + IMR, INT_MASK);
+
+		outw(iobase + BMCR, BMCR_ANE);
+		let speed;
+		let tmp = inw(iobase + BMCR);
+		if tmp & BMCR_SPD1000 == BMCR_SPD1000 {
+			speed = 1000;
+		} else if tmp & BMCR_SPD100 == BMCR_SPD100 {
+			speed = 100;
+		} else {
+			speed = 10;
+		}
+
+		// Enable Receive and Transmitter
+		outb(iobase + CR, CR_TE | CR_RE); // Sets the RE and TE bits high
+
+		info!(
+			"RTL8139: CR = {:#x}, ISR = {:#x}, speed = {} mbps",
+			inb(iobase + CR),
+			inw(iobase + ISR),
+			speed
+		);
+	}
+
+	// Install interrupt handler for RTL8139
+	debug!("Install interrupt handler for RTL8139 at {}", irq);
+	irq_install_handler(irq, network_irqhandler);
+	add_irq_name(irq, "rtl8139_net");
+
+	Ok(RTL8139Driver {
+		iobase,
+		mtu: 1514,
+		irq,
+		mac,
+		tx_in_use: [false; NO_TX_BUFFERS],
+		tx_counter: 0,
+		rxbuffer,
+		rxpos: 0,
+		txbuffer,
+		polling: false,
+	})
+}
+
+fn phys_addr(p: *const u8) -> u32 {
+	virt_to_phys(VirtAddr::from_usize(p as _))
+		.as_u64()
+		.try_into()
+		.unwrap()
+}
+
+/// Performs a software reset of the chip.
+unsafe fn reset_chip(iobase: u16) -> Result<(), RTL8139Error> {
+	unsafe {
 		// Software reset
 		outb(iobase + CR, CR_RST);
 
@@ -475,9 +691,16 @@ pub(crate) fn init_device(
 
 		if tmp == 0 {
 			error!("RTL8139 reset failed");
-			return Err(DriverError::InitRTL8139DevFail(RTL8139Error::ResetFailed));
+			return Err(RTL8139Error::ResetFailed);
 		}
+	}
 
+	Ok(())
+}
+
+/// Configures the receiver and the transmitter after a reset.
+unsafe fn configure(iobase: u16) {
+	unsafe {
 		// Enable Receive and Transmitter
 		outb(iobase + CR, CR_TE | CR_RE); // Sets the RE and TE bits high
 
@@ -501,32 +724,17 @@ pub(crate) fn init_device(
 		// AM - Accept Multicast: Accept multicast packets.
 		// APM - Accept Physical Match: Accept packets send to NIC's MAC address.
 		// AAP - Accept All Packets. Accept all packets (run in promiscuous mode).
-		outl(
-			iobase + RCR,
-			RCR_MXDMA2 | RCR_MXDMA1 | RCR_MXDMA0 | RCR_AB | RCR_AM | RCR_APM | RCR_AAP,
-		); // The WRAP bit isn't set!
+		outl(iobase + RCR, RCR_CONFIG); // The WRAP bit isn't set!
 
 		// set the transmit config register to
 		// be the normal interframe gap time
 		// set DMA max burst to 64bytes
 		outl(iobase + TCR, TCR_IFG | TCR_MXDMA0 | TCR_MXDMA1 | TCR_MXDMA2);
 	}
+}
 
-	let rxbuffer = vec![0; RX_BUF_LEN].into_boxed_slice();
-	let txbuffer = vec![0; NO_TX_BUFFERS * TX_BUF_LEN].into_boxed_slice();
-
-	debug!(
-		"Allocate TxBuffer at {:p} and RxBuffer at {:p}",
-		txbuffer, rxbuffer
-	);
-
-	let phys_addr = |p| {
-		virt_to_phys(VirtAddr::from_usize(p as _))
-			.as_u64()
-			.try_into()
-			.unwrap()
-	};
-
+/// Registers the receive ring and the transmit buffers at the chip.
+unsafe fn program_buffers(iobase: u16, rxbuffer: &[u8], txbuffer: &[u8]) {
 	unsafe {
 		// register the receive buffer
 		outl(iobase + RBSTART, phys_addr(rxbuffer.as_ptr()));
@@ -545,46 +753,32 @@ pub(crate) fn init_device(
 			iobase + TSAD3,
 			phys_addr(txbuffer[3 * TX_BUF_LEN..][..TX_BUF_LEN].as_ptr()),
 		);
+	}
+}
 
-		// Enable all known interrupts by setting the interrupt mask.
-		outw(iobase + IMR, INT_MASK);
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec;
 
-		outw(iobase + BMCR, BMCR_ANE);
-		let speed;
-		let tmp = inw(iobase + BMCR);
-		if tmp & BMCR_SPD1000 == BMCR_SPD1000 {
-			speed = 1000;
-		} else if tmp & BMCR_SPD100 == BMCR_SPD100 {
-			speed = 100;
-		} else {
-			speed = 10;
-		}
+	use super::*;
 
-		// Enable Receive and Transmitter
-		outb(iobase + CR, CR_TE | CR_RE); // Sets the RE and TE bits high
+	#[test]
+	fn copy_packet_across_end_of_ring() {
+		let mut ring = vec![0u8; RX_BUF_LEN + RX_BUF_PAD];
+		ring[RX_BUF_LEN - 2..RX_BUF_LEN].copy_from_slice(&[1, 2]);
+		ring[..3].copy_from_slice(&[3, 4, 5]);
 
-		info!(
-			"RTL8139: CR = {:#x}, ISR = {:#x}, speed = {} mbps",
-			inb(iobase + CR),
-			inw(iobase + ISR),
-			speed
-		);
+		assert_eq!(copy_from_ring(&ring, RX_BUF_LEN - 2, 5), [1, 2, 3, 4, 5]);
+		assert_eq!(copy_from_ring(&ring, 0, 3), [3, 4, 5]);
 	}
 
-	// Install interrupt handler for RTL8139
-	debug!("Install interrupt handler for RTL8139 at {}", irq);
-	irq_install_handler(irq, network_irqhandler);
-	add_irq_name(irq, "rtl8139_net");
-
-	Ok(RTL8139Driver {
-		iobase,
-		mtu: 1514,
-		irq,
-		mac,
-		tx_in_use: [false; NO_TX_BUFFERS],
-		tx_counter: 0,
-		rxbuffer,
-		rxpos: 0,
-		txbuffer,
-	})
+	#[test]
+	fn reject_invalid_rx_header() {
+		assert_eq!(rx_packet_len(ISR_ROK, 64 + 4), Some(64));
+		// receive error
+		assert_eq!(rx_packet_len(ISR_RER, 64 + 4), None);
+		// length without payload or larger than the ring
+		assert_eq!(rx_packet_len(ISR_ROK, 4), None);
+		assert_eq!(rx_packet_len(ISR_ROK, RX_BUF_LEN as u16), None);
+	}
 }
@@ -13,8 +13,11 @@ use virtio_spec::mmio::{DeviceRegisterVolatileFieldAccess, DeviceRegisters};
 use volatile::VolatileRef;
 
 use crate::drivers::net::virtio_net::constants::Status;
-use crate::drivers::net::virtio_net::{CtrlQueue, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver};
+use crate::drivers::net::virtio_net::{
+	CtrlQueue, DevState, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver,
+};
 use crate::drivers::virtio::error::{VirtioError, VirtioNetError};
+use crate::drivers::virtio::transport;
 use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, NotifCfg};
 use crate::drivers::virtio::virtqueue::Virtq;
 
@@ -32,6 +35,12 @@ pub struct NetDevCfgRaw {
 	max_virtqueue_pairs: u16,
 	// Indicates the maximum MTU driver should use. Only valid if VIRTIONET_F_MTU is set.
 	mtu: u16,
+	speed: u32,
+	duplex: u8,
+	// The following fields are only valid if VIRTIO_NET_F_RSS or VIRTIO_NET_F_HASH_REPORT is set.
+	rss_max_key_size: u8,
+	rss_max_indirection_table_length: u16,
+	supported_hash_types: u32,
 }
 
 impl NetDevCfgRaw {
@@ -105,6 +114,23 @@ impl NetDevCfgRaw {
 			}
 		}
 	}
+
+	pub fn get_supported_hash_types(&self) -> u32 {
+		// see Virtio specification v1.1 -  2.4.1
+		unsafe {
+			loop {
+				let before = read_volatile(&self.config_generation);
+				fence(Ordering::SeqCst);
+				let hash_types = read_volatile(&self.supported_hash_types);
+				fence(Ordering::SeqCst);
+				let after = read_volatile(&self.config_generation);
+
+				if before == after {
+					return hash_types;
+				}
+			}
+		}
+	}
 }
 
 // Backend-dependent interface for Virtio network driver
@@ -140,9 +166,9 @@ impl VirtioNetDriver {
 
 		Ok(VirtioNetDriver {
 			dev_cfg,
-			com_cfg: ComCfg::new(registers, 1),
-			isr_stat,
-			notif_cfg,
+			com_cfg: ComCfg::new(registers, 1).into(),
+			isr_stat: isr_stat.into(),
+			notif_cfg: notif_cfg.into(),
 			ctrl_vq: CtrlQueue::new(None),
 			recv_vqs: RxQueues::new(Vec::<Rc<dyn Virtq>>::new(), false),
 			send_vqs: TxQueues::new(Vec::<Rc<dyn Virtq>>::new(), Vec::new(), false),
@@ -150,11 +176,14 @@ impl VirtioNetDriver {
 			irq,
 			mtu,
 			checksums: ChecksumCapabilities::default(),
+			state: DevState::Active,
 		})
 	}
 
 	pub fn print_information(&mut self) {
-		self.com_cfg.print_information();
+		// MMIO builds don't contain other transports.
+		let transport::ComCfg::Mmio(com_cfg) = &mut self.com_cfg;
+		com_cfg.print_information();
 		if self.dev_status() == u16::from(Status::VIRTIO_NET_S_LINK_UP) {
 			info!("The link of the network device is up!");
 		}
@@ -13,7 +13,6 @@ use pci_types::InterruptLine;
 use smoltcp::phy::{Checksum, ChecksumCapabilities};
 use smoltcp::wire::{EthernetFrame, Ipv4Packet, Ipv6Packet, ETHERNET_HEADER_LEN};
 use virtio_spec::net::{Hdr, HdrF};
-use virtio_spec::FeatureBits;
 
 use self::constants::{Status, MAX_NUM_VQ};
 use self::error::VirtioNetError;
@@ -24,15 +23,18 @@ use crate::config::VIRTIO_MAX_QUEUE_SIZE;
 use crate::drivers::net::virtio_mmio::NetDevCfgRaw;
 #[cfg(feature = "pci")]
 use crate::drivers::net::virtio_pci::NetDevCfgRaw;
-use crate::drivers::net::NetworkDriver;
-#[cfg(not(feature = "pci"))]
-use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, NotifCfg};
-#[cfg(feature = "pci")]
-use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::net::{HashType, NetworkDriver, RxHash};
+use crate::drivers::virtio::features::{self, MissingFeature, OptionalFeatures, RequiredFeatures};
+use crate::drivers::virtio::transport::{self, ComCfg, IsrFlags, IsrStatus, NotifCfg, VqReset};
 use crate::drivers::virtio::virtqueue::packed::PackedVq;
 use crate::drivers::virtio::virtqueue::split::SplitVq;
-use crate::drivers::virtio::virtqueue::{BuffSpec, BufferToken, Bytes, Virtq, VqIndex, VqSize};
+#[cfg(feature = "virtio-latency")]
+use crate::drivers::virtio::virtqueue::LatencyHistogram;
+use crate::drivers::virtio::virtqueue::{
+	self, BuffSpec, BufferToken, BufferType, Bytes, QueueMode, Virtq, VqIndex, VqSize,
+};
 use crate::executor::device::{RxToken, TxToken};
+use crate::executor::rx_pool;
 
 /// A wrapper struct for the raw configuration structure.
 /// Handling the right access to fields, as some are read-only
@@ -43,34 +45,200 @@ pub(crate) struct NetDevCfg {
 	pub features: virtio_spec::net::F,
 }
 
+impl NetDevCfg {
+	/// Returns the length of the header, which precedes each packet.
+	pub fn hdr_len(&self) -> usize {
+		hdr_len(self.features)
+	}
+}
+
+/// Length of the header without the field `num_buffers`
+const LEGACY_HDR_LEN: usize = 10;
+
+/// Length of the fields `hash_value`, `hash_report` and `padding_reserved`,
+/// which extend the header, if `VIRTIO_NET_F_HASH_REPORT` is negotiated.
+const HASH_FIELDS_LEN: usize = 8;
+
+/// Returns the length of the header for the negotiated `features`.
+///
+/// The field `num_buffers` is only part of the header, if `VIRTIO_NET_F_VERSION_1`
+/// or `VIRTIO_NET_F_MRG_RXBUF` is negotiated. Otherwise, a legacy device uses a
+/// header of 10 bytes. If `VIRTIO_NET_F_HASH_REPORT` is negotiated, the header is
+/// extended by the hash fields.
+///
+/// See Virtio specification v1.2 - 5.1.6 and 5.1.6.1
+fn hdr_len(features: virtio_spec::net::F) -> usize {
+	let len = if features.contains(virtio_spec::net::F::VERSION_1)
+		|| features.contains(virtio_spec::net::F::MRG_RXBUF)
+	{
+		mem::size_of::<Hdr>()
+	} else {
+		LEGACY_HDR_LEN
+	};
+
+	if features.contains(virtio_spec::net::F::HASH_REPORT) {
+		len + HASH_FIELDS_LEN
+	} else {
+		len
+	}
+}
+
+/// Splits a received packet into its header of `hdr_len` bytes and its payload.
+///
+/// Returns the payload, the number of buffers, which form the packet, and the
+/// hash of the packet. Without the field `num_buffers`, the packet consists of
+/// one buffer. Returns `None`, if the packet is shorter than its header.
+fn split_packet(packet: &[u8], hdr_len: usize) -> Option<(&[u8], u16, Option<RxHash>)> {
+	let (header, payload) = (packet.get(..hdr_len)?, &packet[hdr_len..]);
+	trace!("Header: {:?}", header);
+
+	let num_buffers = header
+		.get(LEGACY_HDR_LEN..mem::size_of::<Hdr>())
+		.map_or(1, |field| u16::from_le_bytes(field.try_into().unwrap()));
+	let hash = header
+		.get(mem::size_of::<Hdr>()..)
+		.filter(|fields| !fields.is_empty())
+		.and_then(parse_hash);
+
+	Some((payload, num_buffers, hash))
+}
+
+/// Returns the size of the receive buffers, which are filled with frames of up
+/// to `frame_len` bytes and a header of `hdr_len` bytes.
+///
+/// With `VIRTIO_NET_F_MRG_RXBUF`, a frame spans multiple buffers. Hence, the
+/// buffers are sized for a standard Ethernet frame. Otherwise, a single buffer
+/// has to hold the largest frame, e.g. a jumbo frame.
+///
+/// See Virtio specification v1.1. - 5.1.6.3.1
+fn rx_buffer_len(features: virtio_spec::net::F, frame_len: usize, hdr_len: usize) -> usize {
+	if features.contains(virtio_spec::net::F::MRG_RXBUF) {
+		(1514 + hdr_len).align_up(core::mem::size_of::<crossbeam_utils::CachePadded<u8>>())
+	} else {
+		frame_len.max(1514) + hdr_len
+	}
+}
+
+/// Parses the hash fields, which follow the header of a received packet.
+/// Returns `None`, if the device didn't compute a hash for the packet.
+///
+/// See Virtio specification v1.2 - 5.1.6.4.3.4
+fn parse_hash(fields: &[u8]) -> Option<RxHash> {
+	let value = u32::from_le_bytes(fields.get(..4)?.try_into().unwrap());
+	let report = u16::from_le_bytes(fields.get(4..6)?.try_into().unwrap());
+	let hash_type = match report {
+		1 => HashType::Ipv4,
+		2 => HashType::Tcpv4,
+		3 => HashType::Udpv4,
+		4 => HashType::Ipv6,
+		5 => HashType::Tcpv6,
+		6 => HashType::Udpv6,
+		7 => HashType::Ipv6Ex,
+		8 => HashType::Tcpv6Ex,
+		9 => HashType::Udpv6Ex,
+		// VIRTIO_NET_HASH_REPORT_NONE or unknown type
+		_ => return None,
+	};
+
+	Some(RxHash { value, hash_type })
+}
+
+/// Hash types, which are reported for received packets, if the device supports them
+///
+/// See Virtio specification v1.2. - 5.1.6.4.3.1
+const HASH_TYPES: u32 = VIRTIO_NET_HASH_TYPE_IPV4
+	| VIRTIO_NET_HASH_TYPE_TCPV4
+	| VIRTIO_NET_HASH_TYPE_UDPV4
+	| VIRTIO_NET_HASH_TYPE_IPV6
+	| VIRTIO_NET_HASH_TYPE_TCPV6
+	| VIRTIO_NET_HASH_TYPE_UDPV6;
+const VIRTIO_NET_HASH_TYPE_IPV4: u32 = 1 << 0;
+const VIRTIO_NET_HASH_TYPE_TCPV4: u32 = 1 << 1;
+const VIRTIO_NET_HASH_TYPE_UDPV4: u32 = 1 << 2;
+const VIRTIO_NET_HASH_TYPE_IPV6: u32 = 1 << 3;
+const VIRTIO_NET_HASH_TYPE_TCPV6: u32 = 1 << 4;
+const VIRTIO_NET_HASH_TYPE_UDPV6: u32 = 1 << 5;
+
+/// Default key of the Toeplitz hash. Devices, which offer `VIRTIO_NET_F_HASH_REPORT`,
+/// support keys of at least 40 bytes.
+const HASH_KEY: [u8; 40] = [
+	0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+	0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+	0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// Returns the data of the command `VIRTIO_NET_CTRL_MQ_HASH_CONFIG`, which enables
+/// the hash types of [`HASH_TYPES`], which are part of `supported_hash_types`.
+///
+/// See Virtio specification v1.2. - 5.1.6.5.6.4
+fn hash_config(supported_hash_types: u32) -> Vec<u8> {
+	let mut data = Vec::with_capacity(13 + HASH_KEY.len());
+	data.extend_from_slice(&(HASH_TYPES & supported_hash_types).to_le_bytes());
+	// reserved
+	data.extend_from_slice(&[0; 8]);
+	data.push(u8::try_from(HASH_KEY.len()).unwrap());
+	data.extend_from_slice(&HASH_KEY);
+	data
+}
+
 pub struct CtrlQueue(Option<Rc<dyn Virtq>>);
 
 impl CtrlQueue {
 	pub fn new(vq: Option<Rc<dyn Virtq>>) -> Self {
 		CtrlQueue(vq)
 	}
+
+	/// Sends the command `command` of the class `class` with the command specific
+	/// `data` to the device and waits for its acknowledgment.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5
+	fn send_command(
+		&self,
+		class: CtrlClass,
+		command: u8,
+		data: &[u8],
+	) -> Result<(), VirtioNetError> {
+		let vq = self.0.as_ref().ok_or(VirtioNetError::FeatureNotNegotiated(
+			virtio_spec::net::F::CTRL_VQ,
+		))?;
+
+		let hdr = [u8::from(class), command];
+		let mut ack = [VIRTIO_NET_ERR];
+		vq.clone()
+			.prep_transfer_from_raw(&[&hdr, data], &[&mut ack], BufferType::Direct)
+			.and_then(|tkn| tkn.dispatch_blocking())
+			.map_err(|_| VirtioNetError::CtrlCommandFailed(u8::from(class), command))?;
+
+		if ack[0] == VIRTIO_NET_OK {
+			Ok(())
+		} else {
+			Err(VirtioNetError::CtrlCommandFailed(u8::from(class), command))
+		}
+	}
 }
 
+/// Acknowledgment of a successful command on the control queue
+const VIRTIO_NET_OK: u8 = 0;
+/// Acknowledgment of a failed command on the control queue
+const VIRTIO_NET_ERR: u8 = 1;
+
+/// Number of VLAN IDs, which can be filtered by `VIRTIO_NET_CTRL_VLAN`
+const VLAN_ID_MAX: u16 = 4095;
+
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum CtrlClass {
-	VIRTIO_NET_CTRL_RX = 1 << 0,
-	VIRTIO_NET_CTRL_MAC = 1 << 1,
-	VIRTIO_NET_CTRL_VLAN = 1 << 2,
-	VIRTIO_NET_CTRL_ANNOUNCE = 1 << 3,
-	VIRTIO_NET_CTRL_MQ = 1 << 4,
+	VIRTIO_NET_CTRL_RX = 0,
+	VIRTIO_NET_CTRL_MAC = 1,
+	VIRTIO_NET_CTRL_VLAN = 2,
+	VIRTIO_NET_CTRL_ANNOUNCE = 3,
+	VIRTIO_NET_CTRL_MQ = 4,
 }
 
 impl From<CtrlClass> for u8 {
 	fn from(val: CtrlClass) -> Self {
-		match val {
-			CtrlClass::VIRTIO_NET_CTRL_RX => 1 << 0,
-			CtrlClass::VIRTIO_NET_CTRL_MAC => 1 << 1,
-			CtrlClass::VIRTIO_NET_CTRL_VLAN => 1 << 2,
-			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE => 1 << 3,
-			CtrlClass::VIRTIO_NET_CTRL_MQ => 1 << 4,
-		}
+		val as u8
 	}
 }
 
@@ -78,44 +246,44 @@ impl From<CtrlClass> for u8 {
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum RxCmd {
-	VIRTIO_NET_CTRL_RX_PROMISC = 1 << 0,
-	VIRTIO_NET_CTRL_RX_ALLMULTI = 1 << 1,
-	VIRTIO_NET_CTRL_RX_ALLUNI = 1 << 2,
-	VIRTIO_NET_CTRL_RX_NOMULTI = 1 << 3,
-	VIRTIO_NET_CTRL_RX_NOUNI = 1 << 4,
-	VIRTIO_NET_CTRL_RX_NOBCAST = 1 << 5,
+	VIRTIO_NET_CTRL_RX_PROMISC = 0,
+	VIRTIO_NET_CTRL_RX_ALLMULTI = 1,
+	VIRTIO_NET_CTRL_RX_ALLUNI = 2,
+	VIRTIO_NET_CTRL_RX_NOMULTI = 3,
+	VIRTIO_NET_CTRL_RX_NOUNI = 4,
+	VIRTIO_NET_CTRL_RX_NOBCAST = 5,
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum MacCmd {
-	VIRTIO_NET_CTRL_MAC_TABLE_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1 << 1,
+	VIRTIO_NET_CTRL_MAC_TABLE_SET = 0,
+	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1,
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum VlanCmd {
-	VIRTIO_NET_CTRL_VLAN_ADD = 1 << 0,
-	VIRTIO_NET_CTRL_VLAN_DEL = 1 << 1,
+	VIRTIO_NET_CTRL_VLAN_ADD = 0,
+	VIRTIO_NET_CTRL_VLAN_DEL = 1,
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum AnceCmd {
-	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 1 << 0,
+	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 0,
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum MqCmd {
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN = 1 << 1,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MAX = 0x80,
+	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 0,
+	VIRTIO_NET_CTRL_MQ_RSS_CONFIG = 1,
+	VIRTIO_NET_CTRL_MQ_HASH_CONFIG = 2,
 }
 
 pub struct RxQueues {
@@ -144,18 +312,14 @@ impl RxQueues {
 		Ok(buffer_tkn)
 	}
 
-	/// Adds a given queue to the underlying vector and populates the queue with RecvBuffers.
+	/// Adds a given queue to the underlying vector and populates the queue with RecvBuffers,
+	/// which receive frames of up to `frame_len` bytes.
 	///
 	/// Queues are all populated according to Virtio specification v1.1. - 5.1.6.3.1
-	fn add(&mut self, vq: Rc<dyn Virtq>, dev_cfg: &NetDevCfg) {
+	fn add(&mut self, vq: Rc<dyn Virtq>, dev_cfg: &NetDevCfg, frame_len: usize) {
 		let num_buff: u16 = vq.size().into();
 
-		let rx_size = if dev_cfg.features.contains(virtio_spec::net::F::MRG_RXBUF) {
-			(1514 + mem::size_of::<Hdr>())
-				.align_up(core::mem::size_of::<crossbeam_utils::CachePadded<u8>>())
-		} else {
-			dev_cfg.raw.get_mtu() as usize + mem::size_of::<Hdr>()
-		};
+		let rx_size = rx_buffer_len(dev_cfg.features, frame_len, dev_cfg.hdr_len());
 
 		// See Virtio specification v1.1 - 5.1.6.3.1
 		//
@@ -185,26 +349,32 @@ impl RxQueues {
 		}
 	}
 
-	fn get_next(&mut self) -> Option<Box<BufferToken>> {
+	fn get_next(&mut self, ctrl_vq: &CtrlQueue) -> Option<Box<BufferToken>> {
 		let transfer = self.poll_receiver.try_recv();
 
 		transfer
 			.or_else(|_| {
 				// Check if any not yet provided transfers are in the queue.
-				self.poll();
+				self.poll(ctrl_vq);
 
 				self.poll_receiver.try_recv()
 			})
 			.ok()
 	}
 
-	fn poll(&self) {
-		if self.is_multi {
-			for vq in &self.vqs {
-				vq.poll();
-			}
+	/// Polls the queues together with the control queue `ctrl_vq` and returns `true`,
+	/// if further used buffers are pending.
+	///
+	/// All queues share the budget. Hence, a busy receive queue doesn't starve the
+	/// control queue.
+	fn poll(&self, ctrl_vq: &CtrlQueue) -> bool {
+		if self.is_multi || ctrl_vq.0.is_some() {
+			virtqueue::poll_all(
+				self.vqs.iter().chain(ctrl_vq.0.iter()),
+				VIRTIO_MAX_QUEUE_SIZE,
+			)
 		} else {
-			self.vqs[0].poll();
+			self.vqs[0].poll()
 		}
 	}
 
@@ -274,17 +444,18 @@ impl TxQueues {
 		}
 	}
 
-	fn poll(&self) {
+	/// Polls the queues and returns `true`, if further used buffers are pending.
+	fn poll(&self) -> bool {
 		if self.is_multi {
-			for vq in &self.vqs {
-				vq.poll();
-			}
+			virtqueue::poll_all(&self.vqs, VIRTIO_MAX_QUEUE_SIZE)
 		} else {
-			self.vqs[0].poll();
+			self.vqs[0].poll()
 		}
 	}
 
-	fn add(&mut self, vq: Rc<dyn Virtq>, dev_cfg: &NetDevCfg) {
+	/// Adds a given queue to the underlying vector and prepares buffers, which
+	/// transmit frames of up to `frame_len` bytes.
+	fn add(&mut self, vq: Rc<dyn Virtq>, dev_cfg: &NetDevCfg, frame_len: usize) {
 		// Safe virtqueue
 		self.vqs.push(vq.clone());
 		if self.vqs.len() == 1 {
@@ -299,7 +470,7 @@ impl TxQueues {
 				//      Header and data are added as ONE output descriptor to the transmitvq.
 				//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
 				// As usize is currently safe as the minimal usize is defined as 16bit in rust.
-				let buff_def = Bytes::new(mem::size_of::<Hdr>() + 65550).unwrap();
+				let buff_def = Bytes::new(dev_cfg.hdr_len() + 65550).unwrap();
 				let spec = BuffSpec::Single(buff_def);
 
 				let num_buff: u16 = vq.size().into();
@@ -318,8 +489,7 @@ impl TxQueues {
 				//      Header and data are added as ONE output descriptor to the transmitvq.
 				//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
 				// As usize is currently safe as the minimal usize is defined as 16bit in rust.
-				let buff_def =
-					Bytes::new(mem::size_of::<Hdr>() + dev_cfg.raw.get_mtu() as usize).unwrap();
+				let buff_def = Bytes::new(dev_cfg.hdr_len() + frame_len).unwrap();
 				let spec = BuffSpec::Single(buff_def);
 
 				let num_buff: u16 = vq.size().into();
@@ -395,6 +565,31 @@ impl TxQueues {
 	}
 }
 
+/// State of the device, which is tracked by the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DevState {
+	/// The device transfers packets.
+	Active,
+	/// The device signaled a fatal error by DEVICE_NEEDS_RESET. Before the
+	/// driver transfers further packets, it resets and initializes the device again.
+	NeedsReset,
+	/// The device couldn't be initialized again. The driver drops all further
+	/// packets instead of waiting for the device.
+	Failed,
+}
+
+impl DevState {
+	/// Returns the state after a configuration change, at which the device
+	/// signaled, whether it needs a reset.
+	fn on_cfg_change(self, needs_reset: bool) -> Self {
+		if needs_reset && self == Self::Active {
+			Self::NeedsReset
+		} else {
+			self
+		}
+	}
+}
+
 /// Virtio network driver struct.
 ///
 /// Struct allows to control devices virtqueues as also
@@ -412,8 +607,11 @@ pub(crate) struct VirtioNetDriver {
 	pub(super) num_vqs: u16,
 	#[cfg_attr(target_arch = "riscv64", allow(dead_code))]
 	pub(super) irq: InterruptLine,
+	/// Maximal length of a frame including the Ethernet header, which is
+	/// the MTU in the sense of smoltcp
 	pub(super) mtu: u16,
 	pub(super) checksums: ChecksumCapabilities,
+	pub(super) state: DevState,
 }
 
 impl NetworkDriver for VirtioNetDriver {
@@ -438,7 +636,7 @@ impl NetworkDriver for VirtioNetDriver {
 
 	#[allow(dead_code)]
 	fn has_packet(&self) -> bool {
-		self.recv_vqs.poll();
+		self.recv_vqs.poll(&self.ctrl_vq);
 		!self.recv_vqs.poll_receiver.is_empty()
 	}
 
@@ -448,19 +646,32 @@ impl NetworkDriver for VirtioNetDriver {
 	where
 		F: FnOnce(&mut [u8]) -> R,
 	{
-		if let Some((mut buff_tkn, _vq_index)) =
-			self.send_vqs.get_tkn(len + core::mem::size_of::<Hdr>())
-		{
+		if self.state == DevState::NeedsReset {
+			self.reinit();
+		}
+		if self.state == DevState::Failed {
+			// The device doesn't process any transfer. Hence, the packet is dropped.
+			rate_limited_error!(
+				"virtio-net-failed",
+				"Virtio-net device has failed, drop the transmitted packet"
+			);
+			let mut buffer = vec![0; len];
+			return f(&mut buffer);
+		}
+
+		let hdr_len = self.dev_cfg.hdr_len();
+		if let Some((mut buff_tkn, _vq_index)) = self.send_vqs.get_tkn(len + hdr_len) {
 			let (send_ptrs, _) = buff_tkn.raw_ptrs();
 			// Currently we have single Buffers in the TxQueue of size: MTU + ETHERNET_HEADER_LEN + VIRTIO_NET_HDR
 			// see TxQueue.add()
 			let (buff_ptr, _) = send_ptrs.unwrap()[0];
 
 			// Do not show smoltcp the memory region for Hdr.
-			let header = unsafe { &mut *(buff_ptr as *mut Hdr) };
-			*header = Default::default();
-			let buff_ptr =
-				unsafe { buff_ptr.offset(isize::try_from(core::mem::size_of::<Hdr>()).unwrap()) };
+			// The hash fields of the header are unused for transmitted packets and stay zero.
+			unsafe { core::ptr::write_bytes(buff_ptr, 0, hdr_len) };
+			let header_ptr = buff_ptr;
+			let mut header = Hdr::default();
+			let buff_ptr = unsafe { buff_ptr.offset(isize::try_from(hdr_len).unwrap()) };
 
 			let buf_slice: &'static mut [u8] =
 				unsafe { core::slice::from_raw_parts_mut(buff_ptr, len) };
@@ -500,6 +711,15 @@ impl NetworkDriver for VirtioNetDriver {
 				.into();
 			}
 
+			// A legacy header without `num_buffers` is shorter than Hdr.
+			unsafe {
+				core::ptr::copy_nonoverlapping(
+					core::ptr::from_ref(&header).cast::<u8>(),
+					header_ptr,
+					hdr_len.min(mem::size_of::<Hdr>()),
+				);
+			}
+
 			buff_tkn
 				.provide()
 				.dispatch_await(self.send_vqs.poll_sender.clone(), false);
@@ -511,12 +731,23 @@ impl NetworkDriver for VirtioNetDriver {
 	}
 
 	fn receive_packet(&mut self) -> Option<(RxToken, TxToken)> {
-		match self.recv_vqs.get_next() {
+		if self.state == DevState::NeedsReset {
+			self.reinit();
+		}
+		if self.state == DevState::Failed {
+			return None;
+		}
+
+		match self.recv_vqs.get_next(&self.ctrl_vq) {
 			Some(transfer) => {
 				let transfer = match RxQueues::post_processing(transfer) {
 					Ok(trf) => trf,
 					Err(vnet_err) => {
-						warn!("Post processing failed. Err: {:?}", vnet_err);
+						rate_limited_warn!(
+							"virtio-net-rx",
+							"Post processing failed. Err: {:?}",
+							vnet_err
+						);
 						return None;
 					}
 				};
@@ -526,47 +757,45 @@ impl NetworkDriver for VirtioNetDriver {
 
 				// If the given length isn't 1, we currently fail.
 				if recv_data.len() == 1 {
-					let mut vec_data: Vec<u8> = Vec::with_capacity(self.mtu.into());
-					let num_buffers = {
-						const HEADER_SIZE: usize = mem::size_of::<Hdr>();
+					let mut vec_data = rx_pool::take(self.mtu.into());
+					let hdr_len = self.dev_cfg.hdr_len();
+					let (num_buffers, hash) = {
 						let packet = recv_data.pop().unwrap();
 
 						// drop packets with invalid packet size
-						if packet.len() < HEADER_SIZE {
+						let Some((payload, num_buffers, hash)) = split_packet(packet, hdr_len)
+						else {
 							transfer
 								.reset()
 								.provide()
 								.dispatch_await(self.recv_vqs.poll_sender.clone(), false);
 
 							return None;
-						}
-
-						let header = unsafe {
-							core::mem::transmute::<[u8; HEADER_SIZE], Hdr>(
-								packet[..HEADER_SIZE].try_into().unwrap(),
-							)
 						};
-						trace!("Header: {:?}", header);
-						let num_buffers = header.num_buffers;
 
-						vec_data.extend_from_slice(&packet[mem::size_of::<Hdr>()..]);
+						vec_data.extend_from_slice(payload);
 						transfer
 							.reset()
 							.provide()
 							.dispatch_await(self.recv_vqs.poll_sender.clone(), false);
 
-						num_buffers
+						(num_buffers, hash)
 					};
 
-					for _ in 1..num_buffers.to_ne() {
-						let transfer =
-							match RxQueues::post_processing(self.recv_vqs.get_next().unwrap()) {
-								Ok(trf) => trf,
-								Err(vnet_err) => {
-									warn!("Post processing failed. Err: {:?}", vnet_err);
-									return None;
-								}
-							};
+					for _ in 1..num_buffers {
+						let transfer = match RxQueues::post_processing(
+							self.recv_vqs.get_next(&self.ctrl_vq).unwrap(),
+						) {
+							Ok(trf) => trf,
+							Err(vnet_err) => {
+								rate_limited_warn!(
+									"virtio-net-rx",
+									"Post processing failed. Err: {:?}",
+									vnet_err
+								);
+								return None;
+							}
+						};
 
 						let (_, recv_data_opt) = transfer.as_slices().unwrap();
 						let mut recv_data = recv_data_opt.unwrap();
@@ -578,15 +807,13 @@ impl NetworkDriver for VirtioNetDriver {
 							.dispatch_await(self.recv_vqs.poll_sender.clone(), false);
 					}
 
-					Some((RxToken::new(vec_data), TxToken::new()))
+					Some((RxToken::new(vec_data).with_hash(hash), TxToken::new()))
 				} else {
-					error!("Empty transfer, or with wrong buffer layout. Reusing and returning error to user-space network driver...");
-					transfer
-						.reset()
-						.write_seq(None::<&Hdr>, Some(&Hdr::default()))
-						.unwrap()
-						.provide()
-						.dispatch_await(self.recv_vqs.poll_sender.clone(), false);
+					rate_limited_error!(
+						"virtio-net-rx",
+						"Empty transfer, or with wrong buffer layout. Reset the queue..."
+					);
+					self.reset_rx_queue(transfer.vq_index());
 
 					None
 				}
@@ -603,22 +830,49 @@ impl NetworkDriver for VirtioNetDriver {
 		}
 	}
 
+	fn is_busy_polling(&self) -> bool {
+		self.recv_vqs
+			.vqs
+			.iter()
+			.any(|vq| vq.mode() == QueueMode::Poll)
+	}
+
 	fn handle_interrupt(&mut self) -> bool {
 		#[cfg(not(target_arch = "riscv64"))]
 		increment_irq_counter(32 + self.irq);
 
-		let result = if self.isr_stat.is_interrupt() {
-			true
-		} else if self.isr_stat.is_cfg_change() {
-			info!("Configuration changes are not possible! Aborting");
-			todo!("Implement possibility to change config on the fly...")
-		} else {
-			false
-		};
+		// The status has to be read exactly once, as reading it acknowledges the interrupt.
+		let status = self.isr_stat.read_and_clear();
+
+		if status.is_empty() {
+			// The interrupt line is shared and the interrupt was raised by another device.
+			trace!("Ignore interrupt, which was not raised by the virtio-net device");
+			return false;
+		}
 
-		self.isr_stat.acknowledge();
+		if status.contains(IsrFlags::CONFIG) {
+			self.handle_cfg_change();
+		}
+
+		match self.state {
+			DevState::Active => status.contains(IsrFlags::QUEUE),
+			// The network task resets the device, when it receives the next packet.
+			DevState::NeedsReset => true,
+			DevState::Failed => false,
+		}
+	}
 
-		result
+	/// Acknowledges the announcement, which was requested by the device.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.4
+	fn ack_announce(&mut self) {
+		if let Err(err) = self.ctrl_vq.send_command(
+			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE,
+			AnceCmd::VIRTIO_NET_CTRL_ANNOUNCE_ACK as u8,
+			&[],
+		) {
+			warn!("Unable to acknowledge the announcement: {err:?}");
+		}
 	}
 }
 
@@ -629,6 +883,19 @@ impl VirtioNetDriver {
 		self.dev_cfg.dev_id
 	}
 
+	/// Returns the latency histograms of the receive, transmit and control
+	/// queues together with their queue index.
+	#[cfg(feature = "virtio-latency")]
+	pub fn latency_histograms(&self) -> Vec<(VqIndex, LatencyHistogram)> {
+		self.recv_vqs
+			.vqs
+			.iter()
+			.chain(self.send_vqs.vqs.iter())
+			.chain(self.ctrl_vq.0.iter())
+			.map(|vq| (vq.index(), vq.latency_histogram()))
+			.collect()
+	}
+
 	#[cfg(feature = "pci")]
 	pub fn set_failed(&mut self) {
 		self.com_cfg.set_failed();
@@ -647,7 +914,6 @@ impl VirtioNetDriver {
 
 	/// Returns the links status.
 	/// If feature VIRTIO_NET_F_STATUS has not been negotiated, then we assume the link is up!
-	#[cfg(feature = "pci")]
 	pub fn is_link_up(&self) -> bool {
 		if self.dev_cfg.features.contains(virtio_spec::net::F::STATUS) {
 			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_LINK_UP)
@@ -657,7 +923,70 @@ impl VirtioNetDriver {
 		}
 	}
 
-	#[allow(dead_code)]
+	/// Re-reads the device specific configuration after the device signaled
+	/// a configuration change.
+	///
+	/// A configuration change is also raised, if the device sets DEVICE_NEEDS_RESET.
+	/// In this case, the device is reset outside of the interrupt handler by [`Self::reinit`].
+	///
+	/// See Virtio specification v1.1. - 2.1.2, 4.1.4.5 and 5.1.4
+	fn handle_cfg_change(&mut self) {
+		if self.state == DevState::Active {
+			self.state = self.state.on_cfg_change(self.com_cfg.needs_reset());
+			if self.state == DevState::NeedsReset {
+				error!("Virtio-net device needs a reset!");
+				return;
+			}
+		}
+
+		let announce = self
+			.dev_cfg
+			.features
+			.contains(virtio_spec::net::F::GUEST_ANNOUNCE);
+		if announce && self.is_announce() {
+			info!("Virtio-net device requests to announce the interface");
+			super::request_announce();
+		}
+
+		if self.is_link_up() {
+			rate_limited_info!(
+				"virtio-net-link",
+				"Virtio-net link is up after configuration change."
+			);
+		} else {
+			rate_limited_info!(
+				"virtio-net-link",
+				"Virtio-net link is down after configuration change!"
+			);
+		}
+	}
+
+	/// Resets the device, which signaled DEVICE_NEEDS_RESET, and initializes it
+	/// again. The queues are set up again and the packets in flight are lost.
+	/// If the initialization fails, the device is marked as failed.
+	///
+	/// The old queues aren't freed, because their transfer tokens still refer to them.
+	///
+	/// See Virtio specification v1.1. - 2.1.2
+	fn reinit(&mut self) {
+		warn!("Reset the virtio-net device and initialize it again");
+		// The device mustn't access the old queues any more.
+		self.com_cfg.reset_dev();
+		self.ctrl_vq = CtrlQueue::new(None);
+		self.recv_vqs = RxQueues::new(Vec::new(), false);
+		self.send_vqs = TxQueues::new(Vec::new(), Vec::new(), false);
+
+		self.state = match self.init_dev() {
+			Ok(()) => DevState::Active,
+			Err(err) => {
+				error!("Unable to initialize the virtio-net device again: {err:?}");
+				self.com_cfg.set_failed();
+				DevState::Failed
+			}
+		};
+	}
+
+	/// Returns `true`, if the device requests to announce the interface.
 	pub fn is_announce(&self) -> bool {
 		if self.dev_cfg.features.contains(virtio_spec::net::F::STATUS) {
 			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
@@ -672,7 +1001,6 @@ impl VirtioNetDriver {
 	/// device and overrides the num_vq field in the common config.
 	///
 	/// Returns 1 (i.e. minimum number of pairs) if VIRTIO_NET_F_MQ is not set.
-	#[allow(dead_code)]
 	pub fn get_max_vq_pairs(&self) -> u16 {
 		if self.dev_cfg.features.contains(virtio_spec::net::F::MQ) {
 			self.dev_cfg.raw.get_max_virtqueue_pairs()
@@ -681,6 +1009,43 @@ impl VirtioNetDriver {
 		}
 	}
 
+	/// Adds `vid` to the VLAN filter of the device. Afterwards, the device passes
+	/// frames, which are tagged by `vid`.
+	///
+	/// Only the filter is programmed. smoltcp neither strips nor inserts 802.1Q
+	/// tags. Hence, tagged frames aren't processed yet by the network stack.
+	#[allow(dead_code)]
+	pub fn vlan_add(&self, vid: u16) -> Result<(), VirtioNetError> {
+		self.send_vlan_command(VlanCmd::VIRTIO_NET_CTRL_VLAN_ADD, vid)
+	}
+
+	/// Removes `vid` from the VLAN filter of the device.
+	#[allow(dead_code)]
+	pub fn vlan_del(&self, vid: u16) -> Result<(), VirtioNetError> {
+		self.send_vlan_command(VlanCmd::VIRTIO_NET_CTRL_VLAN_DEL, vid)
+	}
+
+	fn send_vlan_command(&self, command: VlanCmd, vid: u16) -> Result<(), VirtioNetError> {
+		if !self
+			.dev_cfg
+			.features
+			.contains(virtio_spec::net::F::CTRL_VLAN)
+		{
+			return Err(VirtioNetError::FeatureNotNegotiated(
+				virtio_spec::net::F::CTRL_VLAN,
+			));
+		}
+		if vid > VLAN_ID_MAX {
+			return Err(VirtioNetError::InvalidVlanId(vid));
+		}
+
+		self.ctrl_vq.send_command(
+			CtrlClass::VIRTIO_NET_CTRL_VLAN,
+			command as u8,
+			&vid.to_le_bytes(),
+		)
+	}
+
 	pub fn disable_interrupts(&self) {
 		// For send and receive queues?
 		// Only for receive? Because send is off anyway?
@@ -708,12 +1073,17 @@ impl VirtioNetDriver {
 		// Indicate device, that driver is able to handle it
 		self.com_cfg.set_drv();
 
-		let minimal_features = virtio_spec::net::F::VERSION_1 | virtio_spec::net::F::MAC;
+		// Legacy devices don't offer VERSION_1.
+		let required = RequiredFeatures(if self.com_cfg.is_legacy() {
+			virtio_spec::net::F::MAC
+		} else {
+			virtio_spec::net::F::VERSION_1 | virtio_spec::net::F::MAC
+		});
 
 		// If wanted, push new features into feats here:
-		let mut features = minimal_features
+		let optional = OptionalFeatures(
 			// Indirect descriptors can be used
-			| virtio_spec::net::F::INDIRECT_DESC
+			virtio_spec::net::F::INDIRECT_DESC
 			// Packed Vq can be used
 			| virtio_spec::net::F::RING_PACKED
 			// Host should avoid the creation of checksums
@@ -727,7 +1097,18 @@ impl VirtioNetDriver {
 			// the link status can be announced
 			| virtio_spec::net::F::STATUS
 			// Multiqueue support
-			| virtio_spec::net::F::MQ;
+			| virtio_spec::net::F::MQ
+			// the device reports the flow hash of received packets
+			| virtio_spec::net::F::HASH_REPORT
+			// Control queue
+			| virtio_spec::net::F::CTRL_VQ
+			// VLAN filtering
+			| virtio_spec::net::F::CTRL_VLAN
+			// single virtqueues can be reset
+			| virtio_spec::net::F::RING_RESET
+			// the guest announces itself, e.g. after a live migration
+			| virtio_spec::net::F::GUEST_ANNOUNCE,
+		);
 
 		// Currently the driver does NOT support the features below.
 		// In order to provide functionality for these, the driver
@@ -736,58 +1117,28 @@ impl VirtioNetDriver {
 		// | virtio_spec::net::F::GUEST_TSO4
 		// | virtio_spec::net::F::GUEST_TSO6
 
-		// Negotiate features with device. Automatically reduces selected feats in order to meet device capabilities.
-		// Aborts in case incompatible features are selected by the driver or the device does not support min_feat_set.
-		match self.negotiate_features(features) {
-			Ok(_) => info!(
-				"Driver found a subset of features for virtio device {:x}. Features are: {features:?}",
-				self.dev_cfg.dev_id
-			),
-			Err(vnet_err) => {
-				match vnet_err {
-					VirtioNetError::FeatureRequirementsNotMet(features) => {
-						error!("Network drivers feature set {features:?} does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!");
-						return Err(vnet_err);
-					}
-					VirtioNetError::IncompatibleFeatureSets(drv_feats, dev_feats) => {
-						// Create a new matching feature set for device and driver if the minimal set is met!
-						if !dev_feats.contains(minimal_features) {
-							error!("Device features set, does not satisfy minimal features needed. Aborting!");
-							return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
-						} else {
-							let common_features = drv_feats & dev_feats;
-							if common_features.is_empty() {
-								error!("Feature negotiation failed with minimal feature set. Aborting!");
-								return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
-							}
-							features = common_features;
-
-							match self.negotiate_features(features) {
-                                Ok(_) => info!("Driver found a subset of features for virtio device {:x}. Features are: {features:?}", self.dev_cfg.dev_id),
-                                Err(vnet_err) => {
-                                    match vnet_err {
-                                        VirtioNetError::FeatureRequirementsNotMet(features) => {
-                                            error!("Network device offers a feature set {features:?} when used completely does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!");
-                                            return Err(vnet_err);
-                                        },
-                                        _ => {
-                                            error!("Feature Set after reduction still not usable. Set: {features:?}. Aborting!");
-                                            return Err(vnet_err);
-                                        }
-                                    }
-                                }
-                            }
-						}
-					}
-					_ => {
-						error!(
-							"Wanted set of features is NOT supported by device. Set: {features:?}"
-						);
-						return Err(vnet_err);
-					}
-				}
+		// Negotiate the required features and the offered optional features with the device.
+		let device_features = virtio_spec::net::F::from(self.com_cfg.dev_features());
+		let features = match features::negotiate(device_features, required, optional) {
+			Ok(features) => {
+				info!(
+					"Driver found a subset of features for virtio device {:x}. Features are: {features:?}",
+					self.dev_cfg.dev_id
+				);
+				features
 			}
-		}
+			Err(MissingFeature(missing)) => {
+				rate_limited_error!(
+					"virtio-net-negotiation",
+					"Device doesn't offer the required features {missing:?}. Aborting!"
+				);
+				return Err(VirtioNetError::IncompatibleFeatureSets(
+					missing,
+					device_features,
+				));
+			}
+		};
+		self.com_cfg.set_drv_features(features.into());
 
 		// Indicates the device, that the current feature set is final for the driver
 		// and will not be changed.
@@ -805,6 +1156,13 @@ impl VirtioNetDriver {
 			return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
 		}
 
+		// The buffers of the queues have to hold the largest frame, e.g. a jumbo frame.
+		if self.dev_cfg.features.contains(virtio_spec::net::F::MTU) {
+			let header_len = u16::try_from(ETHERNET_HEADER_LEN).unwrap();
+			self.mtu = self.dev_cfg.raw.get_mtu().saturating_add(header_len);
+		}
+		info!("Maximal frame length: {} bytes", self.mtu);
+
 		match self.dev_spec_init() {
 			Ok(_) => info!(
 				"Device specific initialization for Virtio network device {:x} finished",
@@ -815,6 +1173,21 @@ impl VirtioNetDriver {
 		// At this point the device is "live"
 		self.com_cfg.drv_ok();
 
+		// Without a hash configuration, the device doesn't report any hash.
+		if self
+			.dev_cfg
+			.features
+			.contains(virtio_spec::net::F::HASH_REPORT)
+		{
+			if let Err(err) = self.ctrl_vq.send_command(
+				CtrlClass::VIRTIO_NET_CTRL_MQ,
+				MqCmd::VIRTIO_NET_CTRL_MQ_HASH_CONFIG as u8,
+				&hash_config(self.dev_cfg.raw.get_supported_hash_types()),
+			) {
+				warn!("Unable to configure the hash of received packets: {err:?}");
+			}
+		}
+
 		if self.dev_cfg.features.contains(virtio_spec::net::F::CSUM)
 			&& self
 				.dev_cfg
@@ -836,39 +1209,12 @@ impl VirtioNetDriver {
 		}
 		debug!("{:?}", self.checksums);
 
-		if self.dev_cfg.features.contains(virtio_spec::net::F::MTU) {
-			self.mtu = self.dev_cfg.raw.get_mtu();
-		}
+		// Received packets are copied into recycled buffers
+		rx_pool::fill(VIRTIO_MAX_QUEUE_SIZE.into(), self.mtu.into());
 
 		Ok(())
 	}
 
-	/// Negotiates a subset of features, understood and wanted by both the OS
-	/// and the device.
-	fn negotiate_features(
-		&mut self,
-		driver_features: virtio_spec::net::F,
-	) -> Result<(), VirtioNetError> {
-		let device_features = virtio_spec::net::F::from(self.com_cfg.dev_features());
-
-		if device_features.requirements_satisfied() {
-			info!("Feature set wanted by network driver are in conformance with specification.");
-		} else {
-			return Err(VirtioNetError::FeatureRequirementsNotMet(device_features));
-		}
-
-		if device_features.contains(driver_features) {
-			// If device supports subset of features write feature set to common config
-			self.com_cfg.set_drv_features(driver_features.into());
-			Ok(())
-		} else {
-			Err(VirtioNetError::IncompatibleFeatureSets(
-				driver_features,
-				device_features,
-			))
-		}
-	}
-
 	/// Device Specific initialization according to Virtio specifictation v1.1. - 5.1.5
 	fn dev_spec_init(&mut self) -> Result<(), VirtioNetError> {
 		match self.virtqueue_init() {
@@ -876,37 +1222,13 @@ impl VirtioNetDriver {
 			Err(vnet_err) => return Err(vnet_err),
 		}
 
-		// Add a control if feature is negotiated
+		// Add a control if feature is negotiated. The control queue follows the
+		// maximal number of queue pairs, even if fewer pairs are used.
+		// See Virtio specification v1.1. - 5.1.2
 		if self.dev_cfg.features.contains(virtio_spec::net::F::CTRL_VQ) {
-			if self
-				.dev_cfg
-				.features
-				.contains(virtio_spec::net::F::RING_PACKED)
-			{
-				self.ctrl_vq = CtrlQueue(Some(Rc::new(
-					PackedVq::new(
-						&mut self.com_cfg,
-						&self.notif_cfg,
-						VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-						VqIndex::from(self.num_vqs),
-						self.dev_cfg.features.into(),
-					)
-					.unwrap(),
-				)));
-			} else {
-				self.ctrl_vq = CtrlQueue(Some(Rc::new(
-					SplitVq::new(
-						&mut self.com_cfg,
-						&self.notif_cfg,
-						VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-						VqIndex::from(self.num_vqs),
-						self.dev_cfg.features.into(),
-					)
-					.unwrap(),
-				)));
-			}
-
-			self.ctrl_vq.0.as_ref().unwrap().enable_notifs();
+			let vq = self.create_vq(VqIndex::from(2 * self.get_max_vq_pairs()));
+			vq.enable_notifs();
+			self.ctrl_vq = CtrlQueue(Some(vq));
 		}
 
 		Ok(())
@@ -945,69 +1267,101 @@ impl VirtioNetDriver {
 		assert_eq!(self.num_vqs % 2, 0);
 
 		for i in 0..(self.num_vqs / 2) {
-			if self
-				.dev_cfg
-				.features
-				.contains(virtio_spec::net::F::RING_PACKED)
-			{
-				let vq = PackedVq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqIndex::from(2 * i),
-					self.dev_cfg.features.into(),
-				)
-				.unwrap();
-				// Interrupt for receiving packets is wanted
-				vq.enable_notifs();
+			let vq = self.create_vq(VqIndex::from(2 * i));
+			// Interrupt for receiving packets is wanted
+			vq.enable_notifs();
 
-				self.recv_vqs.add(Rc::from(vq), &self.dev_cfg);
+			self.recv_vqs.add(vq, &self.dev_cfg, self.mtu.into());
 
-				let vq = PackedVq::new(
-					&mut self.com_cfg,
-					&self.notif_cfg,
-					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqIndex::from(2 * i + 1),
-					self.dev_cfg.features.into(),
-				)
-				.unwrap();
-				// Interrupt for comunicating that a sended packet left, is not needed
-				vq.disable_notifs();
+			let vq = self.create_vq(VqIndex::from(2 * i + 1));
+			// Interrupt for comunicating that a sended packet left, is not needed
+			vq.disable_notifs();
 
-				self.send_vqs.add(Rc::from(vq), &self.dev_cfg);
-			} else {
-				let vq = SplitVq::new(
+			self.send_vqs.add(vq, &self.dev_cfg, self.mtu.into());
+		}
+
+		if let Some(queues) = hermit_var!("HERMIT_NET_POLL_QUEUES") {
+			for vq in self.recv_vqs.vqs.iter().chain(&self.send_vqs.vqs) {
+				let index = u16::from(vq.index());
+				if is_poll_queue(&queues, index) {
+					info!("Virtqueue {index} of the network device is in poll mode");
+					vq.set_mode(QueueMode::Poll);
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl VirtioNetDriver {
+	/// Creates and enables the virtqueue `index` in the negotiated layout.
+	fn create_vq(&mut self, index: VqIndex) -> Rc<dyn Virtq> {
+		if self
+			.dev_cfg
+			.features
+			.contains(virtio_spec::net::F::RING_PACKED)
+		{
+			Rc::new(
+				PackedVq::new(
 					&mut self.com_cfg,
 					&self.notif_cfg,
 					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqIndex::from(2 * i),
+					index,
 					self.dev_cfg.features.into(),
 				)
-				.unwrap();
-				// Interrupt for receiving packets is wanted
-				vq.enable_notifs();
-
-				self.recv_vqs.add(Rc::from(vq), &self.dev_cfg);
-
-				let vq = SplitVq::new(
+				.unwrap(),
+			)
+		} else {
+			Rc::new(
+				SplitVq::new(
 					&mut self.com_cfg,
 					&self.notif_cfg,
 					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
-					VqIndex::from(2 * i + 1),
+					index,
 					self.dev_cfg.features.into(),
 				)
-				.unwrap();
-				// Interrupt for comunicating that a sended packet left, is not needed
-				vq.disable_notifs();
+				.unwrap(),
+			)
+		}
+	}
 
-				self.send_vqs.add(Rc::from(vq), &self.dev_cfg);
+	/// Resets the receive queue `index`, which returned a malformed buffer.
+	///
+	/// With `VIRTIO_F_RING_RESET`, only the queue is reset and set up again.
+	/// The old queue isn't freed, because its transfer tokens still refer to it.
+	/// Otherwise, the whole device is reset and initialized again.
+	///
+	/// See Virtio specification v1.2. - 2.6.1
+	fn reset_rx_queue(&mut self, index: VqIndex) {
+		match self
+			.com_cfg
+			.reset_vq(index.into(), self.dev_cfg.features.into())
+		{
+			VqReset::Queue => {
+				info!(
+					"Virtqueue {} of the network device is reset",
+					u16::from(index)
+				);
+				self.recv_vqs.vqs.retain(|vq| vq.index() != index);
+				let vq = self.create_vq(index);
+				vq.enable_notifs();
+				self.recv_vqs.add(vq, &self.dev_cfg, self.mtu.into());
 			}
+			VqReset::Device => self.reinit(),
 		}
-
-		Ok(())
 	}
 }
 
+/// Returns `true`, if the virtqueue `index` is part of `queues`, which is
+/// either `all` or a comma-separated list of virtqueue indices.
+fn is_poll_queue(queues: &str, index: u16) -> bool {
+	queues.trim() == "all"
+		|| queues
+			.split(',')
+			.any(|queue| queue.trim().parse::<u16>() == Ok(index))
+}
+
 pub mod constants {
 	// Configuration constants
 	pub const MAX_NUM_VQ: u16 = 2;
@@ -1050,11 +1404,185 @@ pub mod error {
 		#[cfg(feature = "pci")]
 		NoNotifCfg(u16),
 		FailFeatureNeg(u16),
-		/// Set of features does not adhere to the requirements of features
-		/// indicated by the specification
-		FeatureRequirementsNotMet(virtio_spec::net::F),
 		/// The first field contains the feature bits wanted by the driver.
 		/// but which are incompatible with the device feature set, second field.
 		IncompatibleFeatureSets(virtio_spec::net::F, virtio_spec::net::F),
+		/// The operation requires a feature, which isn't negotiated.
+		FeatureNotNegotiated(virtio_spec::net::F),
+		/// VLAN IDs are limited to 12 bits.
+		InvalidVlanId(u16),
+		/// The device rejected the command of the class and the command code.
+		CtrlCommandFailed(u8, u8),
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_hash_fields() {
+		assert_eq!(
+			parse_hash(&[0x78, 0x56, 0x34, 0x12, 2, 0, 0, 0]),
+			Some(RxHash {
+				value: 0x1234_5678,
+				hash_type: HashType::Tcpv4
+			})
+		);
+		assert_eq!(parse_hash(&[0x78, 0x56, 0x34, 0x12, 0, 0, 0, 0]), None);
+		assert_eq!(parse_hash(&[0; 4]), None);
+	}
+
+	#[test]
+	fn configure_supported_hash_types() {
+		let supported = VIRTIO_NET_HASH_TYPE_TCPV4 | VIRTIO_NET_HASH_TYPE_UDPV6 | (1 << 8);
+		let data = hash_config(supported);
+		assert_eq!(
+			data[..4],
+			(VIRTIO_NET_HASH_TYPE_TCPV4 | VIRTIO_NET_HASH_TYPE_UDPV6).to_le_bytes()
+		);
+		assert_eq!(data[4..12], [0; 8]);
+		assert_eq!(usize::from(data[12]), HASH_KEY.len());
+		assert_eq!(data[13..], HASH_KEY);
+	}
+
+	#[test]
+	fn split_packet_with_hash_report() {
+		let features = virtio_spec::net::F::VERSION_1 | virtio_spec::net::F::HASH_REPORT;
+		assert_eq!(hdr_len(features), 20);
+
+		let payload = [0xaa, 0xbb, 0xcc];
+		let mut packet = vec![0; 12];
+		packet[10] = 1;
+		// hash value, VIRTIO_NET_HASH_REPORT_TCPv4 and padding
+		packet.extend_from_slice(&[0x78, 0x56, 0x34, 0x12, 2, 0, 0, 0]);
+		packet.extend_from_slice(&payload);
+
+		let (data, num_buffers, hash) = split_packet(&packet, hdr_len(features)).unwrap();
+		assert_eq!(data, payload);
+		assert_eq!(num_buffers, 1);
+		assert_eq!(
+			hash,
+			Some(RxHash {
+				value: 0x1234_5678,
+				hash_type: HashType::Tcpv4
+			})
+		);
+	}
+
+	#[test]
+	fn rx_buffers_of_jumbo_frames() {
+		let jumbo = 9000 + ETHERNET_HEADER_LEN;
+
+		let single = virtio_spec::net::F::MAC | virtio_spec::net::F::MTU;
+		assert_eq!(rx_buffer_len(single, jumbo, 12), jumbo + 12);
+		assert_eq!(rx_buffer_len(single, 590, 12), 1514 + 12);
+
+		let merged = single | virtio_spec::net::F::MRG_RXBUF;
+		let len = rx_buffer_len(merged, jumbo, 12);
+		assert!((1514 + 12..jumbo).contains(&len));
+	}
+
+	#[test]
+	fn split_packet_of_both_header_versions() {
+		let payload = [0xaa, 0xbb, 0xcc];
+
+		let legacy = virtio_spec::net::F::MAC;
+		assert_eq!(hdr_len(legacy), 10);
+		let mut packet = vec![0; 10];
+		packet.extend_from_slice(&payload);
+		let (data, num_buffers, hash) = split_packet(&packet, hdr_len(legacy)).unwrap();
+		assert_eq!(data, payload);
+		assert_eq!(num_buffers, 1);
+		assert_eq!(hash, None);
+
+		for features in [
+			virtio_spec::net::F::MAC | virtio_spec::net::F::MRG_RXBUF,
+			virtio_spec::net::F::MAC | virtio_spec::net::F::VERSION_1,
+		] {
+			assert_eq!(hdr_len(features), 12);
+			let mut packet = vec![0; 12];
+			packet[10] = 2;
+			packet.extend_from_slice(&payload);
+			let (data, num_buffers, _) = split_packet(&packet, hdr_len(features)).unwrap();
+			assert_eq!(data, payload);
+			assert_eq!(num_buffers, 2);
+		}
+
+		// a legacy packet, which is shorter than a modern header
+		assert!(split_packet(&[0; 11], 12).is_none());
+		assert!(split_packet(&[0; 11], 10).is_some());
+	}
+
+	#[test]
+	fn fail_on_needs_reset() {
+		use virtio_spec::DeviceStatus;
+
+		let status = DeviceStatus::ACKNOWLEDGE
+			| DeviceStatus::DRIVER
+			| DeviceStatus::FEATURES_OK
+			| DeviceStatus::DRIVER_OK;
+		let state = DevState::Active.on_cfg_change(transport::needs_reset(status.bits()));
+		assert_eq!(state, DevState::Active);
+
+		let status = status | DeviceStatus::DEVICE_NEEDS_RESET;
+		let state = state.on_cfg_change(transport::needs_reset(status.bits()));
+		assert_eq!(state, DevState::NeedsReset);
+		// The device stays in the state until it is reset.
+		assert_eq!(state.on_cfg_change(false), DevState::NeedsReset);
+		// A failed device stays failed.
+		assert_eq!(DevState::Failed.on_cfg_change(true), DevState::Failed);
+	}
+
+	/// After a configuration change with DEVICE_NEEDS_RESET, the driver resets
+	/// the device. As the device doesn't offer any feature, its initialization
+	/// fails and the driver neither hands packets over to the device nor waits
+	/// for received packets.
+	#[cfg(feature = "pci")]
+	#[test]
+	fn reset_after_needs_reset() {
+		use virtio_spec::pci::CommonCfgVolatileFieldAccess;
+		use virtio_spec::DeviceStatus;
+		use volatile::VolatileRef;
+
+		use crate::drivers::virtio::transport::pci;
+
+		let (raw, com_cfg, isr_stat, notif_cfg) = pci::in_memory_device();
+		// The driver doesn't own any queue. Hence, each transfer would panic.
+		let mut driver = VirtioNetDriver {
+			dev_cfg: NetDevCfg {
+				raw: Box::leak(Box::new(unsafe { mem::zeroed::<NetDevCfgRaw>() })),
+				dev_id: 0,
+				features: virtio_spec::net::F::empty(),
+			},
+			com_cfg: com_cfg.into(),
+			isr_stat: isr_stat.into(),
+			notif_cfg: notif_cfg.into(),
+			ctrl_vq: CtrlQueue::new(None),
+			recv_vqs: RxQueues::new(Vec::new(), false),
+			send_vqs: TxQueues::new(Vec::new(), Vec::new(), false),
+			num_vqs: 0,
+			irq: 0,
+			mtu: 1514,
+			checksums: ChecksumCapabilities::default(),
+			state: DevState::Active,
+		};
+
+		let mut device = unsafe { VolatileRef::new(raw) };
+		device
+			.as_mut_ptr()
+			.device_status()
+			.write(DeviceStatus::DRIVER_OK | DeviceStatus::DEVICE_NEEDS_RESET);
+		driver.handle_cfg_change();
+		assert_eq!(driver.state, DevState::NeedsReset);
+
+		// The next transfer resets the device and initializes it again.
+		assert!(driver.receive_packet().is_none());
+		assert_eq!(driver.state, DevState::Failed);
+		assert_eq!(device.as_ptr().device_status().read(), DeviceStatus::FAILED);
+
+		assert!(driver.receive_packet().is_none());
+		let len = driver.send_packet(64, |buffer| buffer.len());
+		assert_eq!(len, 64);
 	}
 }
@@ -2,15 +2,21 @@
 //!
 //! The module contains ...
 
+#[cfg(feature = "virtio-legacy")]
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::str::FromStr;
 
 use smoltcp::phy::ChecksumCapabilities;
 
 use crate::arch::pci::PciConfigRegion;
-use crate::drivers::net::virtio_net::{CtrlQueue, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver};
+use crate::drivers::net::virtio_net::{
+	CtrlQueue, DevState, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver,
+};
 use crate::drivers::pci::{PciCommand, PciDevice};
 use crate::drivers::virtio::error::{self, VirtioError};
+#[cfg(feature = "virtio-legacy")]
+use crate::drivers::virtio::transport::legacy;
 use crate::drivers::virtio::transport::pci;
 use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
 
@@ -27,6 +33,12 @@ pub(crate) struct NetDevCfgRaw {
 	max_virtqueue_pairs: u16,
 	// Indicates the maximum MTU driver should use. Only valid if VIRTIONET_F_MTU is set.
 	mtu: u16,
+	speed: u32,
+	duplex: u8,
+	// The following fields are only valid if VIRTIO_NET_F_RSS or VIRTIO_NET_F_HASH_REPORT is set.
+	rss_max_key_size: u8,
+	rss_max_indirection_table_length: u16,
+	supported_hash_types: u32,
 }
 
 impl NetDevCfgRaw {
@@ -45,6 +57,10 @@ impl NetDevCfgRaw {
 	pub fn get_max_virtqueue_pairs(&self) -> u16 {
 		self.max_virtqueue_pairs
 	}
+
+	pub fn get_supported_hash_types(&self) -> u32 {
+		self.supported_hash_types
+	}
 }
 
 // Backend-dependent interface for Virtio network driver
@@ -137,9 +153,9 @@ impl VirtioNetDriver {
 
 		Ok(VirtioNetDriver {
 			dev_cfg,
-			com_cfg,
-			isr_stat,
-			notif_cfg,
+			com_cfg: com_cfg.into(),
+			isr_stat: isr_stat.into(),
+			notif_cfg: notif_cfg.into(),
 
 			ctrl_vq: CtrlQueue::new(None),
 			recv_vqs: RxQueues::new(Vec::new(), false),
@@ -148,9 +164,60 @@ impl VirtioNetDriver {
 			irq: device.get_irq().unwrap(),
 			mtu,
 			checksums: ChecksumCapabilities::default(),
+			state: DevState::Active,
 		})
 	}
 
+	/// Instantiates a new [VirtioNetDriver] for a device, which only provides the legacy
+	/// interface at `iobase`.
+	///
+	/// The device specific configuration is located in the I/O space. Hence, it is copied
+	/// at this point and later changes of the link status are not visible to the driver.
+	#[cfg(feature = "virtio-legacy")]
+	fn new_legacy(iobase: u16, device: &PciDevice<PciConfigRegion>) -> Self {
+		let com_cfg = legacy::ComCfg::new(iobase);
+		let read_u16 = |offset| {
+			u16::from_le_bytes([
+				com_cfg.read_dev_cfg(offset),
+				com_cfg.read_dev_cfg(offset + 1),
+			])
+		};
+
+		let raw = NetDevCfgRaw {
+			mac: core::array::from_fn(|i| com_cfg.read_dev_cfg(i.try_into().unwrap())),
+			status: read_u16(6),
+			max_virtqueue_pairs: read_u16(8),
+			mtu: read_u16(10),
+		};
+
+		let mtu = if let Some(my_mtu) = hermit_var!("HERMIT_MTU") {
+			u16::from_str(&my_mtu).unwrap()
+		} else {
+			// fallback to the default MTU
+			1514
+		};
+
+		VirtioNetDriver {
+			dev_cfg: NetDevCfg {
+				raw: Box::leak(Box::new(raw)),
+				dev_id: device.device_id(),
+				features: virtio_spec::net::F::empty(),
+			},
+			isr_stat: com_cfg.isr_status().into(),
+			notif_cfg: com_cfg.notif_cfg().into(),
+			com_cfg: com_cfg.into(),
+
+			ctrl_vq: CtrlQueue::new(None),
+			recv_vqs: RxQueues::new(Vec::new(), false),
+			send_vqs: TxQueues::new(Vec::new(), Vec::new(), false),
+			num_vqs: 0,
+			irq: device.get_irq().unwrap(),
+			mtu,
+			checksums: ChecksumCapabilities::default(),
+			state: DevState::Active,
+		}
+	}
+
 	/// Initializes virtio network device by mapping configuration layout to
 	/// respective structs (configuration structs are:
 	/// [ComCfg](structs.comcfg.html), [NotifCfg](structs.notifcfg.html)
@@ -173,6 +240,18 @@ impl VirtioNetDriver {
 					return Err(VirtioError::NetDriver(vnet_err));
 				}
 			},
+			#[cfg(feature = "virtio-legacy")]
+			Err(pci_error) => match legacy::map_io_bar(device) {
+				Some(iobase) => {
+					info!("Falling back to the legacy interface at I/O base {iobase:#x}");
+					VirtioNetDriver::new_legacy(iobase, device)
+				}
+				None => {
+					error!("Mapping capabilities failed. Aborting!");
+					return Err(VirtioError::FromPci(pci_error));
+				}
+			},
+			#[cfg(not(feature = "virtio-legacy"))]
 			Err(pci_error) => {
 				error!("Mapping capabilities failed. Aborting!");
 				return Err(VirtioError::FromPci(pci_error));
@@ -0,0 +1,129 @@
+//! Negotiation of feature bits.
+//!
+//! A driver declares once the features, which it requires, and the features, which
+//! it uses, if the device offers them. The negotiated features consist of the
+//! required features and the offered optional features, whose dependencies are
+//! negotiated as well.
+//!
+//! See Virtio specification v1.1. - 2.2
+//!                          v1.1. - 3.1.1
+
+use virtio_spec::{FeatureBits, F};
+
+/// Features, without which the driver can't operate the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequiredFeatures<T>(pub T);
+
+/// Features, which the driver uses, if the device offers them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionalFeatures<T>(pub T);
+
+/// Required features, which the device doesn't offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingFeature<T>(pub T);
+
+/// Returns the features of `offered`, which a driver negotiates.
+///
+/// Fails with the required features, which aren't offered. A required feature,
+/// which depends on a feature, which isn't required, fails with its dependencies.
+pub fn negotiate<T>(
+	offered: T,
+	required: RequiredFeatures<T>,
+	optional: OptionalFeatures<T>,
+) -> Result<T, MissingFeature<T>>
+where
+	T: FeatureBits + Copy + From<F> + AsRef<F> + AsMut<F>,
+	F: From<T> + AsRef<T> + AsMut<T>,
+{
+	let RequiredFeatures(required) = required;
+	let OptionalFeatures(optional) = optional;
+
+	let missing = required.difference(offered);
+	if !missing.is_empty() {
+		return Err(MissingFeature(missing));
+	}
+
+	let mut negotiated = required.union(optional.intersection(offered));
+	loop {
+		let unsatisfied = unsatisfied(negotiated);
+		if unsatisfied.is_empty() {
+			return Ok(negotiated);
+		}
+
+		let unsatisfied_required = unsatisfied.intersection(required);
+		if !unsatisfied_required.is_empty() {
+			let dependencies = unsatisfied_required
+				.iter()
+				.fold(T::empty(), |dependencies, feature| {
+					dependencies.union(feature.requirements())
+				});
+			return Err(MissingFeature(dependencies));
+		}
+
+		// Dropping a feature may break the dependencies of further features.
+		negotiated = negotiated.difference(unsatisfied);
+	}
+}
+
+/// Returns the features of `features`, whose dependencies are missing.
+fn unsatisfied<T>(features: T) -> T
+where
+	T: FeatureBits + Copy + From<F> + AsRef<F> + AsMut<F>,
+	F: From<T> + AsRef<T> + AsMut<T>,
+{
+	features
+		.iter()
+		.filter(|feature| {
+			let requirements = feature.requirements();
+			!requirements.is_empty() && !features.intersects(requirements)
+		})
+		.fold(T::empty(), |unsatisfied, feature| {
+			unsatisfied.union(feature)
+		})
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use virtio_spec::net;
+
+	use super::*;
+
+	#[test]
+	fn negotiate_offered_features() {
+		let required = RequiredFeatures(net::F::VERSION_1 | net::F::MAC);
+		let optional = OptionalFeatures(net::F::MTU | net::F::MQ | net::F::CTRL_VQ);
+
+		let offered = net::F::VERSION_1 | net::F::MAC | net::F::MTU | net::F::STATUS;
+		assert_eq!(
+			negotiate(offered, required, optional),
+			Ok(net::F::VERSION_1 | net::F::MAC | net::F::MTU)
+		);
+
+		// MQ depends on CTRL_VQ, which isn't offered.
+		let offered = net::F::VERSION_1 | net::F::MAC | net::F::MQ;
+		assert_eq!(
+			negotiate(offered, required, optional),
+			Ok(net::F::VERSION_1 | net::F::MAC)
+		);
+		let offered = offered | net::F::CTRL_VQ;
+		assert_eq!(negotiate(offered, required, optional), Ok(offered));
+	}
+
+	#[test]
+	fn missing_required_feature() {
+		let required = RequiredFeatures(net::F::VERSION_1 | net::F::MAC);
+		let optional = OptionalFeatures(net::F::MTU);
+
+		assert_eq!(
+			negotiate(net::F::VERSION_1 | net::F::MTU, required, optional),
+			Err(MissingFeature(net::F::MAC))
+		);
+
+		let required = RequiredFeatures(net::F::VERSION_1 | net::F::MQ);
+		let offered = net::F::VERSION_1 | net::F::MQ;
+		assert_eq!(
+			negotiate(offered, required, OptionalFeatures(net::F::CTRL_VQ)),
+			Err(MissingFeature(net::F::CTRL_VQ))
+		);
+	}
+}
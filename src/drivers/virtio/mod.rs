@@ -2,6 +2,7 @@
 //!
 //! The module contains virtios transport mechanisms, virtqueues and virtio specific errors
 pub mod env;
+pub mod features;
 pub mod transport;
 pub mod virtqueue;
 
@@ -54,8 +55,10 @@ pub mod error {
 					#[cfg(feature = "pci")]
                     VirtioNetError::NoNotifCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed notification config!"),
                     VirtioNetError::FailFeatureNeg(id) => write!(f, "Virtio network driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
-                    VirtioNetError::FeatureRequirementsNotMet(features) => write!(f, "Virtio network driver tried to set feature bit without setting dependency feature. Feat set: {features:?}"),
                     VirtioNetError::IncompatibleFeatureSets(driver_features, device_features) => write!(f, "Feature set: {driver_features:?} , is incompatible with the device features: {device_features:?}"),
+                    VirtioNetError::FeatureNotNegotiated(features) => write!(f, "Virtio network driver requires the feature set {features:?}, which isn't negotiated!"),
+                    VirtioNetError::InvalidVlanId(vid) => write!(f, "VLAN ID {vid} is out of range!"),
+                    VirtioNetError::CtrlCommandFailed(class, command) => write!(f, "Virtio network device rejected the control command {command} of class {class}!"),
                 },
 				#[cfg(feature = "fuse")]
 				VirtioError::FsDriver(fs_error) => match fs_error {
@@ -68,7 +71,6 @@ pub mod error {
 					#[cfg(feature = "pci")]
                     VirtioFsError::NoNotifCfg(id) =>  write!(f, "Virtio filesystem driver failed, for device {id:x}, due to a missing or malformed notification config!"),
 					VirtioFsError::FailFeatureNeg(id) => write!(f, "Virtio filesystem driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
-                    VirtioFsError::FeatureRequirementsNotMet(features) => write!(f, "Virtio filesystem driver tried to set feature bit without setting dependency feature. Feat set: {features:?}"),
 					VirtioFsError::IncompatibleFeatureSets(driver_features, device_features) => write!(f, "Feature set: {driver_features:?} , is incompatible with the device features: {device_features:?}", ),
 					VirtioFsError::Unknown => write!(f, "Virtio filesystem failed, driver failed due unknown reason!"),
 				},
@@ -0,0 +1,306 @@
+//! Legacy interface of virtio PCI devices.
+//!
+//! Devices, which predate the virtio specification v1.0, don't provide the
+//! virtio capabilities of the modern PCI transport. Instead, all registers are
+//! located at fixed offsets in the I/O space of the first BAR. The types of
+//! this module adapt the legacy register block to the interfaces of the
+//! transport, as far as the legacy interface allows it:
+//!
+//! * Only the lower 32 bits of the feature bits are available.
+//! * The size of a virtqueue is defined by the device.
+//! * The rings of a split virtqueue have to be located in a single contiguous
+//!   area, whose page frame number is written to the device.
+//! * Packed virtqueues and notification data are not supported.
+//!
+//! The registers are accessed via I/O ports. Hence, the interface is only
+//! available on x86_64.
+//!
+//! See Virtio specification v1.1. - 4.1.4.8
+
+use pci_types::Bar;
+use virtio_spec::DeviceStatus;
+use x86::io::{inb, inl, inw, outb, outl, outw};
+
+use crate::arch::memory_barrier;
+use crate::arch::mm::PhysAddr;
+use crate::arch::pci::PciConfigRegion;
+use crate::drivers::pci::PciDevice;
+use crate::drivers::virtio::transport::IsrFlags;
+
+/// Features offered by the device (32 bit, read-only)
+const HOST_FEATURES: u16 = 0x00;
+/// Features accepted by the driver (32 bit)
+const GUEST_FEATURES: u16 = 0x04;
+/// Page frame number of the selected virtqueue (32 bit)
+const QUEUE_PFN: u16 = 0x08;
+/// Size of the selected virtqueue (16 bit, read-only)
+const QUEUE_SIZE: u16 = 0x0c;
+/// Index of the selected virtqueue (16 bit)
+const QUEUE_SELECT: u16 = 0x0e;
+/// Index of the notified virtqueue (16 bit)
+const QUEUE_NOTIFY: u16 = 0x10;
+/// Device status (8 bit)
+const DEVICE_STATUS: u16 = 0x12;
+/// ISR status (8 bit, read clears)
+const ISR_STATUS: u16 = 0x13;
+/// Start of the device specific configuration, if MSI-X is disabled
+const DEVICE_CFG: u16 = 0x14;
+
+/// Shift of the page frame number in [`QUEUE_PFN`]
+const QUEUE_ADDR_SHIFT: u32 = 12;
+
+/// Alignment of the used ring of a legacy split virtqueue
+pub const QUEUE_ALIGN: usize = 1 << QUEUE_ADDR_SHIFT;
+
+/// Returns the I/O base of the legacy interface, if `device` is a transitional
+/// virtio device, whose first BAR is located in the I/O space.
+pub(crate) fn map_io_bar(device: &PciDevice<PciConfigRegion>) -> Option<u16> {
+	// Transitional devices use the device ids 0x1000 through 0x103f.
+	// See Virtio specification v1.1. - 4.1.2.1
+	if !(0x1000..=0x103f).contains(&device.device_id()) {
+		return None;
+	}
+
+	match device.get_bar(0) {
+		Some(Bar::Io { port }) => u16::try_from(port).ok(),
+		_ => {
+			error!(
+				"Legacy interface of device {:x} is not located in the I/O space!",
+				device.device_id()
+			);
+			None
+		}
+	}
+}
+
+/// Common configuration of a legacy device.
+pub struct ComCfg {
+	/// Base of the legacy register block in the I/O space
+	iobase: u16,
+}
+
+impl ComCfg {
+	pub fn new(iobase: u16) -> Self {
+		ComCfg { iobase }
+	}
+
+	fn read_status(&self) -> DeviceStatus {
+		DeviceStatus::from_bits_retain(unsafe { inb(self.iobase + DEVICE_STATUS) })
+	}
+
+	fn write_status(&mut self, status: DeviceStatus) {
+		memory_barrier();
+		unsafe {
+			outb(self.iobase + DEVICE_STATUS, status.bits());
+		}
+	}
+
+	/// Select a queue via an index. If queue does NOT exist returns `None`, else
+	/// returns `Some(VqCfgHandler)`.
+	pub fn select_vq(&mut self, index: u16) -> Option<VqCfgHandler<'_>> {
+		let size = unsafe {
+			outw(self.iobase + QUEUE_SELECT, index);
+			inw(self.iobase + QUEUE_SIZE)
+		};
+
+		if size == 0 {
+			None
+		} else {
+			Some(VqCfgHandler {
+				vq_index: index,
+				com_cfg: self,
+			})
+		}
+	}
+
+	/// Returns the device status field.
+	pub fn dev_status(&self) -> u8 {
+		self.read_status().bits()
+	}
+
+	/// Resets the device status field to zero.
+	pub fn reset_dev(&mut self) {
+		self.write_status(DeviceStatus::empty());
+	}
+
+	/// Sets the device status field to FAILED.
+	pub fn set_failed(&mut self) {
+		self.write_status(DeviceStatus::FAILED);
+	}
+
+	/// Sets the ACKNOWLEDGE bit in the device status field.
+	pub fn ack_dev(&mut self) {
+		let status = self.read_status();
+		self.write_status(status | DeviceStatus::ACKNOWLEDGE);
+	}
+
+	/// Sets the DRIVER bit in the device status field.
+	pub fn set_drv(&mut self) {
+		let status = self.read_status();
+		self.write_status(status | DeviceStatus::DRIVER);
+	}
+
+	/// Legacy devices don't know the FEATURES_OK bit. The features are
+	/// final as soon as they are written. Hence, this is a no-op.
+	pub fn features_ok(&mut self) {}
+
+	/// Legacy devices can't reject the written features.
+	pub fn check_features(&self) -> bool {
+		true
+	}
+
+	/// Sets the DRIVER_OK bit in the device status field.
+	pub fn drv_ok(&mut self) {
+		let status = self.read_status();
+		self.write_status(status | DeviceStatus::DRIVER_OK);
+	}
+
+	/// Returns the lower 32 bits of the device features. The upper
+	/// bits are not accessible via the legacy interface.
+	pub fn dev_features(&mut self) -> virtio_spec::F {
+		memory_barrier();
+		let device_features = unsafe { inl(self.iobase + HOST_FEATURES) };
+
+		virtio_spec::F::from_bits_retain(u128::from(device_features).into())
+	}
+
+	/// Writes the lower 32 bits of `features` to the device.
+	pub fn set_drv_features(&mut self, features: virtio_spec::F) {
+		let features = features.bits().to_ne() as u32;
+
+		memory_barrier();
+		unsafe {
+			outl(self.iobase + GUEST_FEATURES, features);
+		}
+	}
+
+	/// Reads a byte of the device specific configuration at `offset`.
+	pub fn read_dev_cfg(&self, offset: u16) -> u8 {
+		unsafe { inb(self.iobase + DEVICE_CFG + offset) }
+	}
+
+	/// Returns the notification configuration of the device.
+	pub fn notif_cfg(&self) -> NotifCfg {
+		NotifCfg {
+			port: self.iobase + QUEUE_NOTIFY,
+		}
+	}
+
+	/// Returns the ISR status of the device.
+	pub fn isr_status(&self) -> IsrStatus {
+		IsrStatus {
+			port: self.iobase + ISR_STATUS,
+		}
+	}
+}
+
+pub struct VqCfgHandler<'a> {
+	vq_index: u16,
+	com_cfg: &'a mut ComCfg,
+}
+
+impl<'a> VqCfgHandler<'a> {
+	fn select_queue(&mut self) {
+		unsafe {
+			outw(self.com_cfg.iobase + QUEUE_SELECT, self.vq_index);
+		}
+	}
+
+	/// The size of a legacy virtqueue is defined by the device. Hence,
+	/// `size` is ignored and the size of the device is returned.
+	pub fn set_vq_size(&mut self, _size: u16) -> u16 {
+		self.select_queue();
+		unsafe { inw(self.com_cfg.iobase + QUEUE_SIZE) }
+	}
+
+	/// Writes the page frame number of the contiguous area, which starts
+	/// with the descriptor table. This also activates the queue.
+	pub fn set_ring_addr(&mut self, addr: PhysAddr) {
+		assert!(
+			addr.as_u64() % QUEUE_ALIGN as u64 == 0,
+			"The rings of a legacy virtqueue have to be page-aligned"
+		);
+		let pfn = u32::try_from(addr.as_u64() >> QUEUE_ADDR_SHIFT)
+			.expect("The rings of a legacy virtqueue have to be located below 16 TiB");
+
+		self.select_queue();
+		memory_barrier();
+		unsafe {
+			outl(self.com_cfg.iobase + QUEUE_PFN, pfn);
+		}
+	}
+
+	/// The device derives the address of the available ring from
+	/// the ring address. Hence, this is a no-op.
+	pub fn set_drv_ctrl_addr(&mut self, _addr: PhysAddr) {}
+
+	/// The device derives the address of the used ring from
+	/// the ring address. Hence, this is a no-op.
+	pub fn set_dev_ctrl_addr(&mut self, _addr: PhysAddr) {}
+
+	/// All queues are notified via the same register.
+	pub fn notif_off(&mut self) -> u16 {
+		0
+	}
+
+	/// A legacy queue is active as soon as its ring address is written.
+	/// Hence, this is a no-op.
+	pub fn enable_queue(&mut self) {}
+}
+
+/// Notification configuration of a legacy device.
+pub struct NotifCfg {
+	/// Port of the queue notify register
+	port: u16,
+}
+
+impl NotifCfg {
+	/// Returns the port of the queue notify register.
+	pub fn base(&self) -> usize {
+		self.port.into()
+	}
+
+	/// All queues are notified via the same register.
+	pub fn multiplier(&self) -> u32 {
+		0
+	}
+
+	pub fn notif_ctrl(&self) -> NotifCtrl {
+		NotifCtrl { port: self.port }
+	}
+}
+
+/// Control structure, which notifies a legacy device by writing the
+/// index of the virtqueue to the queue notify register.
+pub struct NotifCtrl {
+	port: u16,
+}
+
+impl NotifCtrl {
+	/// Notification data is not supported by legacy devices.
+	pub fn enable_notif_data(&mut self) {
+		warn!("Notification data is not supported by legacy virtio devices");
+	}
+
+	pub fn notify_dev(&self, notif_data: &[u8]) {
+		let vq_index = u16::from_le_bytes(notif_data[0..2].try_into().unwrap());
+
+		memory_barrier();
+		unsafe {
+			outw(self.port, vq_index);
+		}
+	}
+}
+
+/// ISR status of a legacy device.
+pub struct IsrStatus {
+	/// Port of the ISR status register
+	port: u16,
+}
+
+impl IsrStatus {
+	/// Reads the ISR status and thereby acknowledges the interrupt.
+	pub fn read_and_clear(&mut self) -> IsrFlags {
+		IsrFlags::from_bits_truncate(unsafe { inb(self.port) })
+	}
+}
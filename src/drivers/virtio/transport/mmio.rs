@@ -8,7 +8,6 @@ use core::sync::atomic::{fence, Ordering};
 
 use virtio_spec::mmio::{
 	DeviceRegisterVolatileFieldAccess, DeviceRegisterVolatileWideFieldAccess, DeviceRegisters,
-	InterruptStatus,
 };
 use virtio_spec::DeviceStatus;
 use volatile::VolatileRef;
@@ -22,6 +21,7 @@ use crate::drivers::net::network_irqhandler;
 #[cfg(any(feature = "tcp", feature = "udp"))]
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::transport::IsrFlags;
 
 /// Virtio device ID's
 /// See Virtio specification v1.1. - 5
@@ -120,6 +120,18 @@ impl<'a> VqCfgHandler<'a> {
 	}
 }
 
+impl super::QueueReset for VqCfgHandler<'_> {
+	fn write_queue_reset(&mut self, value: u16) {
+		self.select_queue();
+		self.raw.as_mut_ptr().queue_reset().write(value.into());
+	}
+
+	fn read_queue_reset(&mut self) -> u16 {
+		self.select_queue();
+		self.raw.as_mut_ptr().queue_reset().read().to_ne() as u16
+	}
+}
+
 /// Wraps a [MmioRegisterLayout] in order to preserve
 /// the original structure.
 ///
@@ -399,26 +411,20 @@ impl IsrStatus {
 		Self { raw }
 	}
 
-	pub fn is_interrupt(&self) -> bool {
-		self.raw
-			.as_ptr()
-			.interrupt_status()
-			.read()
-			.contains(InterruptStatus::USED_BUFFER_NOTIFICATION)
-	}
-
-	pub fn is_cfg_change(&self) -> bool {
-		self.raw
-			.as_ptr()
-			.interrupt_status()
-			.read()
-			.contains(InterruptStatus::CONFIGURATION_CHANGE_NOTIFICATION)
-	}
-
-	pub fn acknowledge(&mut self) {
+	/// Reads the interrupt status and acknowledges all reported events.
+	///
+	/// An empty set indicates, that the interrupt was not raised by this
+	/// device (e.g. on a shared interrupt line).
+	///
+	/// See Virtio specification v1.1. - 4.2.2
+	pub fn read_and_clear(&mut self) -> IsrFlags {
 		let ptr = self.raw.as_mut_ptr();
 		let status = ptr.interrupt_status().read();
-		ptr.interrupt_ack().write(status);
+		if !status.is_empty() {
+			ptr.interrupt_ack().write(status);
+		}
+
+		IsrFlags::from_bits_truncate(status.bits())
 	}
 }
 
@@ -1,10 +1,459 @@
 //! A module containing virtios transport mechanisms.
 //!
-//! The module contains only PCI specific transport mechanism.
-//! Other mechanisms (MMIO and Channel I/O) are currently not
-//! supported.
+//! The module contains the PCI and MMIO specific transport mechanisms. With the
+//! `virtio-legacy` feature, it also contains the legacy interface of pre-1.0 PCI devices.
+//! Drivers and virtqueues use the transport independent wrappers of this
+//! module, which dispatch to the transport of the respective device.
+//! Channel I/O is currently not supported.
 
+#[cfg(feature = "virtio-legacy")]
+pub mod legacy;
 #[cfg(not(feature = "pci"))]
 pub mod mmio;
 #[cfg(feature = "pci")]
 pub mod pci;
+
+use virtio_spec::DeviceStatus;
+
+use crate::arch::mm::PhysAddr;
+
+bitflags! {
+	/// Decoded interrupt status of a virtio device.
+	///
+	/// The bit layout is shared by the PCI ISR status capability and the
+	/// MMIO `InterruptStatus` register.
+	///
+	/// See Virtio specification v1.1. - 4.1.4.5
+	///                          v1.1. - 4.2.2
+	#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+	pub struct IsrFlags: u8 {
+		/// The device has used a buffer in at least one of its virtqueues.
+		const QUEUE = 1 << 0;
+		/// The configuration of the device has changed.
+		const CONFIG = 1 << 1;
+	}
+}
+
+/// Returns `true` if the device status `status` has the DEVICE_NEEDS_RESET bit set.
+///
+/// See Virtio specification v1.1. - 2.1.1
+pub(crate) fn needs_reset(status: u8) -> bool {
+	DeviceStatus::from_bits_retain(status).contains(DeviceStatus::DEVICE_NEEDS_RESET)
+}
+
+/// Access to the `queue_reset` field of a selected virtqueue.
+///
+/// See Virtio specification v1.2. - 4.1.4.3
+///                          v1.2. - 4.2.2
+trait QueueReset {
+	fn write_queue_reset(&mut self, value: u16);
+
+	fn read_queue_reset(&mut self) -> u16;
+}
+
+/// Number of reads of `queue_reset`, after which a queue reset is considered failed
+const QUEUE_RESET_POLLS: u32 = 1_000_000;
+
+/// Resets a single virtqueue by writing 1 to `queue_reset` and waiting until the
+/// device reads 0. Returns `false` if the device doesn't complete the reset.
+///
+/// See Virtio specification v1.2. - 2.6.1
+fn reset_queue(queue: &mut impl QueueReset) -> bool {
+	queue.write_queue_reset(1);
+
+	for _ in 0..QUEUE_RESET_POLLS {
+		if queue.read_queue_reset() == 0 {
+			return true;
+		}
+		core::hint::spin_loop();
+	}
+
+	false
+}
+
+/// Kind of reset, which was performed by [`ComCfg::reset_vq`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VqReset {
+	/// Only the virtqueue was reset and may be set up and enabled again.
+	Queue,
+	/// The whole device was reset and has to be initialized again.
+	Device,
+}
+
+/// Forwards a method call to the transport specific implementation.
+macro_rules! dispatch {
+	($self:expr, $inner:ident => $e:expr) => {
+		match $self {
+			#[cfg(feature = "pci")]
+			Self::Pci($inner) => $e,
+			#[cfg(feature = "virtio-legacy")]
+			Self::Legacy($inner) => $e,
+			#[cfg(not(feature = "pci"))]
+			Self::Mmio($inner) => $e,
+		}
+	};
+}
+
+/// Common configuration structure of a virtio device.
+///
+/// The transport is selected at compile time: with the `pci` feature, devices
+/// are accessed via PCI, otherwise via MMIO. Only PCI builds with the
+/// `virtio-legacy` feature decide at runtime, whether a device is accessed by
+/// the modern or the legacy interface.
+pub enum ComCfg {
+	#[cfg(feature = "pci")]
+	Pci(pci::ComCfg),
+	#[cfg(feature = "virtio-legacy")]
+	Legacy(legacy::ComCfg),
+	#[cfg(not(feature = "pci"))]
+	Mmio(mmio::ComCfg),
+}
+
+#[cfg(feature = "pci")]
+impl From<pci::ComCfg> for ComCfg {
+	fn from(com_cfg: pci::ComCfg) -> Self {
+		Self::Pci(com_cfg)
+	}
+}
+
+#[cfg(feature = "virtio-legacy")]
+impl From<legacy::ComCfg> for ComCfg {
+	fn from(com_cfg: legacy::ComCfg) -> Self {
+		Self::Legacy(com_cfg)
+	}
+}
+
+#[cfg(not(feature = "pci"))]
+impl From<mmio::ComCfg> for ComCfg {
+	fn from(com_cfg: mmio::ComCfg) -> Self {
+		Self::Mmio(com_cfg)
+	}
+}
+
+impl ComCfg {
+	/// Selects the virtqueue `index` and returns a handler to its configuration.
+	/// Returns `None` if the queue does not exist.
+	pub fn select_vq(&mut self, index: u16) -> Option<VqCfgHandler<'_>> {
+		dispatch!(self, com_cfg => com_cfg.select_vq(index).map(Into::into))
+	}
+
+	/// Returns the device status field.
+	pub fn dev_status(&self) -> u8 {
+		dispatch!(self, com_cfg => com_cfg.dev_status())
+	}
+
+	/// Returns `true` if the device signals by DEVICE_NEEDS_RESET that it
+	/// encountered an error, from which it only recovers by a reset.
+	///
+	/// Legacy devices don't provide this status bit.
+	pub fn needs_reset(&self) -> bool {
+		!self.is_legacy() && needs_reset(self.dev_status())
+	}
+
+	/// Returns `true` if the device is accessed via the legacy interface.
+	pub fn is_legacy(&self) -> bool {
+		match self {
+			#[cfg(feature = "virtio-legacy")]
+			Self::Legacy(_) => true,
+			_ => false,
+		}
+	}
+
+	/// Resets the device status field to zero.
+	pub fn reset_dev(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.reset_dev())
+	}
+
+	/// Resets the virtqueue `index`.
+	///
+	/// If `VIRTIO_F_RING_RESET` is part of the negotiated `features`, only the queue
+	/// is reset. Otherwise, or if the device doesn't complete the queue reset, the
+	/// whole device is reset.
+	pub fn reset_vq(&mut self, index: u16, features: virtio_spec::F) -> VqReset {
+		if features.contains(virtio_spec::F::RING_RESET) {
+			if let Some(mut vq_handler) = self.select_vq(index) {
+				if vq_handler.reset_queue() {
+					return VqReset::Queue;
+				}
+				warn!("Virtqueue {index} doesn't complete its reset, reset the device");
+			}
+		}
+
+		self.reset_dev();
+		VqReset::Device
+	}
+
+	/// Sets the device status field to FAILED.
+	pub fn set_failed(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.set_failed())
+	}
+
+	/// Sets the ACKNOWLEDGE bit in the device status field.
+	pub fn ack_dev(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.ack_dev())
+	}
+
+	/// Sets the DRIVER bit in the device status field.
+	pub fn set_drv(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.set_drv())
+	}
+
+	/// Sets the FEATURES_OK bit in the device status field.
+	pub fn features_ok(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.features_ok())
+	}
+
+	/// Returns `true` if the device accepted the negotiated features.
+	pub fn check_features(&self) -> bool {
+		dispatch!(self, com_cfg => com_cfg.check_features())
+	}
+
+	/// Sets the DRIVER_OK bit in the device status field.
+	pub fn drv_ok(&mut self) {
+		dispatch!(self, com_cfg => com_cfg.drv_ok())
+	}
+
+	/// Returns the features offered by the device.
+	pub fn dev_features(&mut self) -> virtio_spec::F {
+		dispatch!(self, com_cfg => com_cfg.dev_features())
+	}
+
+	/// Writes the features accepted by the driver.
+	pub fn set_drv_features(&mut self, features: virtio_spec::F) {
+		dispatch!(self, com_cfg => com_cfg.set_drv_features(features))
+	}
+}
+
+/// Handler to the configuration of a single virtqueue.
+pub enum VqCfgHandler<'a> {
+	#[cfg(feature = "pci")]
+	Pci(pci::VqCfgHandler<'a>),
+	#[cfg(feature = "virtio-legacy")]
+	Legacy(legacy::VqCfgHandler<'a>),
+	#[cfg(not(feature = "pci"))]
+	Mmio(mmio::VqCfgHandler<'a>),
+}
+
+#[cfg(feature = "pci")]
+impl<'a> From<pci::VqCfgHandler<'a>> for VqCfgHandler<'a> {
+	fn from(handler: pci::VqCfgHandler<'a>) -> Self {
+		Self::Pci(handler)
+	}
+}
+
+#[cfg(feature = "virtio-legacy")]
+impl<'a> From<legacy::VqCfgHandler<'a>> for VqCfgHandler<'a> {
+	fn from(handler: legacy::VqCfgHandler<'a>) -> Self {
+		Self::Legacy(handler)
+	}
+}
+
+#[cfg(not(feature = "pci"))]
+impl<'a> From<mmio::VqCfgHandler<'a>> for VqCfgHandler<'a> {
+	fn from(handler: mmio::VqCfgHandler<'a>) -> Self {
+		Self::Mmio(handler)
+	}
+}
+
+impl<'a> VqCfgHandler<'a> {
+	/// Sets the size of the queue and returns the size, which is accepted by the device.
+	pub fn set_vq_size(&mut self, size: u16) -> u16 {
+		dispatch!(self, handler => handler.set_vq_size(size))
+	}
+
+	pub fn set_ring_addr(&mut self, addr: PhysAddr) {
+		dispatch!(self, handler => handler.set_ring_addr(addr))
+	}
+
+	pub fn set_drv_ctrl_addr(&mut self, addr: PhysAddr) {
+		dispatch!(self, handler => handler.set_drv_ctrl_addr(addr))
+	}
+
+	pub fn set_dev_ctrl_addr(&mut self, addr: PhysAddr) {
+		dispatch!(self, handler => handler.set_dev_ctrl_addr(addr))
+	}
+
+	pub fn notif_off(&mut self) -> u16 {
+		dispatch!(self, handler => handler.notif_off())
+	}
+
+	pub fn enable_queue(&mut self) {
+		dispatch!(self, handler => handler.enable_queue())
+	}
+
+	/// Resets the queue via `queue_reset`. Returns `false` if the device doesn't
+	/// complete the reset or the transport doesn't provide queue resets.
+	///
+	/// Requires the negotiation of `VIRTIO_F_RING_RESET`.
+	pub fn reset_queue(&mut self) -> bool {
+		match self {
+			#[cfg(feature = "pci")]
+			Self::Pci(handler) => reset_queue(handler),
+			// Legacy devices don't provide queue resets.
+			#[cfg(feature = "virtio-legacy")]
+			Self::Legacy(_) => false,
+			#[cfg(not(feature = "pci"))]
+			Self::Mmio(handler) => reset_queue(handler),
+		}
+	}
+}
+
+/// Notification configuration of a virtio device.
+pub enum NotifCfg {
+	#[cfg(feature = "pci")]
+	Pci(pci::NotifCfg),
+	#[cfg(feature = "virtio-legacy")]
+	Legacy(legacy::NotifCfg),
+	#[cfg(not(feature = "pci"))]
+	Mmio(mmio::NotifCfg),
+}
+
+#[cfg(feature = "pci")]
+impl From<pci::NotifCfg> for NotifCfg {
+	fn from(notif_cfg: pci::NotifCfg) -> Self {
+		Self::Pci(notif_cfg)
+	}
+}
+
+#[cfg(feature = "virtio-legacy")]
+impl From<legacy::NotifCfg> for NotifCfg {
+	fn from(notif_cfg: legacy::NotifCfg) -> Self {
+		Self::Legacy(notif_cfg)
+	}
+}
+
+#[cfg(not(feature = "pci"))]
+impl From<mmio::NotifCfg> for NotifCfg {
+	fn from(notif_cfg: mmio::NotifCfg) -> Self {
+		Self::Mmio(notif_cfg)
+	}
+}
+
+impl NotifCfg {
+	pub fn base(&self) -> usize {
+		dispatch!(self, notif_cfg => notif_cfg.base())
+	}
+
+	pub fn multiplier(&self) -> u32 {
+		dispatch!(self, notif_cfg => notif_cfg.multiplier())
+	}
+
+	/// Creates the notification control of a virtqueue, whose notification
+	/// address is `notif_addr`. The control uses the same transport as `self`.
+	pub fn notif_ctrl(&self, notif_addr: *mut usize) -> NotifCtrl {
+		match self {
+			#[cfg(feature = "pci")]
+			Self::Pci(_) => NotifCtrl::Pci(pci::NotifCtrl::new(notif_addr)),
+			#[cfg(feature = "virtio-legacy")]
+			Self::Legacy(notif_cfg) => NotifCtrl::Legacy(notif_cfg.notif_ctrl()),
+			#[cfg(not(feature = "pci"))]
+			Self::Mmio(_) => NotifCtrl::Mmio(mmio::NotifCtrl::new(notif_addr)),
+		}
+	}
+}
+
+/// Control structure to notify the device about available buffers of a virtqueue.
+pub enum NotifCtrl {
+	#[cfg(feature = "pci")]
+	Pci(pci::NotifCtrl),
+	#[cfg(feature = "virtio-legacy")]
+	Legacy(legacy::NotifCtrl),
+	#[cfg(not(feature = "pci"))]
+	Mmio(mmio::NotifCtrl),
+}
+
+impl NotifCtrl {
+	pub fn enable_notif_data(&mut self) {
+		dispatch!(self, notif_ctrl => notif_ctrl.enable_notif_data())
+	}
+
+	pub fn notify_dev(&self, notif_data: &[u8]) {
+		dispatch!(self, notif_ctrl => notif_ctrl.notify_dev(notif_data))
+	}
+}
+
+/// Interrupt status of a virtio device.
+pub enum IsrStatus {
+	#[cfg(feature = "pci")]
+	Pci(pci::IsrStatus),
+	#[cfg(feature = "virtio-legacy")]
+	Legacy(legacy::IsrStatus),
+	#[cfg(not(feature = "pci"))]
+	Mmio(mmio::IsrStatus),
+}
+
+#[cfg(feature = "pci")]
+impl From<pci::IsrStatus> for IsrStatus {
+	fn from(isr_stat: pci::IsrStatus) -> Self {
+		Self::Pci(isr_stat)
+	}
+}
+
+#[cfg(feature = "virtio-legacy")]
+impl From<legacy::IsrStatus> for IsrStatus {
+	fn from(isr_stat: legacy::IsrStatus) -> Self {
+		Self::Legacy(isr_stat)
+	}
+}
+
+#[cfg(not(feature = "pci"))]
+impl From<mmio::IsrStatus> for IsrStatus {
+	fn from(isr_stat: mmio::IsrStatus) -> Self {
+		Self::Mmio(isr_stat)
+	}
+}
+
+impl IsrStatus {
+	/// Reads and acknowledges the pending interrupt causes.
+	pub fn read_and_clear(&mut self) -> IsrFlags {
+		dispatch!(self, isr_stat => isr_stat.read_and_clear())
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	/// Device, which completes a queue reset after `pending` reads
+	struct MockQueue {
+		queue_reset: u16,
+		pending: u32,
+		writes: u32,
+	}
+
+	impl QueueReset for MockQueue {
+		fn write_queue_reset(&mut self, value: u16) {
+			self.queue_reset = value;
+			self.writes += 1;
+		}
+
+		fn read_queue_reset(&mut self) -> u16 {
+			if self.pending == 0 {
+				self.queue_reset = 0;
+			} else {
+				self.pending -= 1;
+			}
+			self.queue_reset
+		}
+	}
+
+	#[test]
+	fn queue_reset_handshake() {
+		let mut queue = MockQueue {
+			queue_reset: 0,
+			pending: 3,
+			writes: 0,
+		};
+		assert!(reset_queue(&mut queue));
+		assert_eq!(queue.writes, 1);
+		assert_eq!(queue.queue_reset, 0);
+
+		let mut queue = MockQueue {
+			queue_reset: 0,
+			pending: u32::MAX,
+			writes: 0,
+		};
+		assert!(!reset_queue(&mut queue));
+		assert_eq!(queue.queue_reset, 1);
+	}
+}
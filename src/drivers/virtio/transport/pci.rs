@@ -28,6 +28,7 @@ use crate::drivers::pci::error::PciError;
 use crate::drivers::pci::{DeviceHeader, Masks, PciDevice};
 use crate::drivers::virtio::env::memory::{MemLen, MemOff, VirtMemAddr};
 use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::transport::IsrFlags;
 
 /// Virtio device ID's
 /// See Virtio specification v1.1. - 5
@@ -494,6 +495,18 @@ impl<'a> VqCfgHandler<'a> {
 	}
 }
 
+impl super::QueueReset for VqCfgHandler<'_> {
+	fn write_queue_reset(&mut self, value: u16) {
+		self.select_queue();
+		self.raw.as_mut_ptr().queue_reset().write(value.into());
+	}
+
+	fn read_queue_reset(&mut self) -> u16 {
+		self.select_queue();
+		self.raw.as_mut_ptr().queue_reset().read().to_ne()
+	}
+}
+
 // Public Interface of ComCfg
 impl ComCfg {
 	/// Select a queue via an index. If queue does NOT exist returns `None`, else
@@ -807,16 +820,16 @@ impl IsrStatus {
 		}
 	}
 
-	pub fn is_interrupt(&self) -> bool {
-		self.isr_stat.flags & 1 << 0 == 1
-	}
-
-	pub fn is_cfg_change(&self) -> bool {
-		self.isr_stat.flags & 1 << 1 == 1 << 1
-	}
-
-	pub fn acknowledge(&mut self) {
-		// nothing to do
+	/// Reads the ISR status and thereby acknowledges the interrupt.
+	///
+	/// Reading the ISR status capability resets it to zero, hence the status
+	/// must only be read once per interrupt. An empty set indicates, that the
+	/// interrupt was not raised by this device (e.g. on a shared interrupt line).
+	///
+	/// See Virtio specification v1.1. - 4.1.4.5.1
+	pub fn read_and_clear(&mut self) -> IsrFlags {
+		let flags = unsafe { ptr::read_volatile(ptr::addr_of!(self.isr_stat.flags)) };
+		IsrFlags::from_bits_truncate(flags)
 	}
 }
 
@@ -1286,7 +1299,15 @@ pub(crate) fn init_device(
 ) -> Result<VirtioDriver, DriverError> {
 	let device_id = device.device_id();
 
-	let virt_drv = match DevId::from(device_id) {
+	let dev_id = match DevId::from(device_id) {
+		// The network driver falls back to the legacy interface of transitional
+		// devices, which don't provide the modern capabilities.
+		#[cfg(feature = "virtio-legacy")]
+		DevId::VIRTIO_TRANS_DEV_ID_NET => DevId::VIRTIO_DEV_ID_NET,
+		dev_id => dev_id,
+	};
+
+	let virt_drv = match dev_id {
 		DevId::VIRTIO_TRANS_DEV_ID_NET
 		| DevId::VIRTIO_TRANS_DEV_ID_BLK
 		| DevId::VIRTIO_TRANS_DEV_ID_MEM_BALL
@@ -1376,3 +1397,31 @@ pub(crate) enum VirtioDriver {
 	#[cfg(feature = "fuse")]
 	FileSystem(VirtioFsDriver),
 }
+
+/// Returns the common configuration, the ISR status and the notification structure
+/// of a device without queues, which resides in ordinary memory. Host tests take
+/// over the part of the device by the returned common configuration.
+#[cfg(all(
+	test,
+	not(target_os = "none"),
+	not(feature = "rtl8139"),
+	any(feature = "tcp", feature = "udp")
+))]
+pub(crate) fn in_memory_device() -> (NonNull<CommonCfg>, ComCfg, IsrStatus, NotifCfg) {
+	use alloc::boxed::Box;
+
+	use zerocopy::FromZeroes;
+
+	let raw = NonNull::from(Box::leak(Box::new(CommonCfg::new_zeroed())));
+	let com_cfg = ComCfg::new(unsafe { VolatileRef::new(raw) }, 1);
+	let isr_stat = IsrStatus::new(Box::leak(Box::new(IsrStatusRaw { flags: 0 })), 1);
+	// The device doesn't have any queue. Hence, it is never notified.
+	let notif_cfg = NotifCfg {
+		base_addr: VirtMemAddr::from(0usize),
+		notify_off_multiplier: 0,
+		rank: 1,
+		length: MemLen::from(0usize),
+	};
+
+	(raw, com_cfg, isr_stat, notif_cfg)
+}
@@ -11,6 +11,7 @@
 #![allow(clippy::type_complexity)]
 
 pub mod packed;
+pub mod select;
 pub mod split;
 
 use alloc::boxed::Box;
@@ -18,17 +19,14 @@ use alloc::rc::Rc;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::ops::{BitAnd, Deref, DerefMut};
-use core::ptr;
+use core::{fmt, ptr};
 
 use align_address::Align;
 use async_channel::TryRecvError;
 use zerocopy::AsBytes;
 
 use self::error::{BufferError, VirtqError};
-#[cfg(not(feature = "pci"))]
-use super::transport::mmio::{ComCfg, NotifCfg};
-#[cfg(feature = "pci")]
-use super::transport::pci::{ComCfg, NotifCfg};
+use super::transport::{ComCfg, NotifCfg};
 use crate::arch::mm::paging::{BasePageSize, PageSize};
 use crate::arch::mm::{paging, VirtAddr};
 
@@ -99,8 +97,164 @@ struct Descriptor {
 	flags: u16,
 }
 
+/// Queue, which receives the [BufferToken] of a finished transfer.
+///
+/// The channel may be bounded. As long as it is full, split virtqueues leave further
+/// used buffers in the ring (backpressure). Tokens for a closed channel are dropped.
 type BufferTokenSender = async_channel::Sender<Box<BufferToken>>;
 
+/// Moves `item` into `queue` without blocking.
+///
+/// If the queue is full or the receiver has been dropped, the item is dropped
+/// with a warning instead of panicking in the interrupt context.
+fn forward<T>(queue: &async_channel::Sender<T>, item: T) {
+	match queue.try_send(item) {
+		Ok(()) => {}
+		Err(async_channel::TrySendError::Full(_)) => {
+			warn!("Await queue is full, drop the finished transfer");
+		}
+		Err(async_channel::TrySendError::Closed(_)) => {
+			warn!("Await queue is closed, drop the finished transfer");
+		}
+	}
+}
+
+/// Callback, which is invoked with the [BufferToken] of a finished transfer.
+///
+/// The callback runs in the context of [Virtq::poll], which may be the interrupt
+/// handler. Hence, it must not block and should return quickly.
+pub type CompletionCallback = fn(&BufferToken);
+
+/// Defines, how the driver learns about used buffers of a virtqueue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueueMode {
+	/// The device notifies the driver by an interrupt and the driver sleeps in between.
+	#[default]
+	Interrupt,
+	/// Notifications are disabled and the driver busy polls the queue.
+	Poll,
+}
+
+/// State of the ring indices of a virtqueue, see [Virtq::debug_snapshot].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingSnapshot {
+	/// Indices of a split virtqueue
+	Split {
+		/// Index of the next free entry of the available ring
+		avail_idx: u16,
+		/// Index of the next free entry of the used ring, as written by the device
+		used_idx: u16,
+		/// Index of the next used element, which is processed by the driver
+		read_idx: u16,
+	},
+	/// Indices and wrap counters of a packed virtqueue
+	Packed {
+		/// Position, at which the next available descriptor is written
+		write_index: usize,
+		/// Position, at which the next used descriptor is expected
+		poll_index: usize,
+		/// Wrap counter of the driver
+		drv_wrap_count: bool,
+		/// Wrap counter of the device
+		dev_wrap_count: bool,
+	},
+}
+
+/// Read-only snapshot of the state of a virtqueue for debugging purposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VqSnapshot {
+	pub index: u16,
+	pub size: u16,
+	pub mode: QueueMode,
+	pub ring: RingSnapshot,
+	/// Number of descriptors, which are currently not used by any transfer
+	pub free_descriptors: usize,
+	/// Number of transfers, which have been dispatched but not yet been used by the device
+	pub in_flight: usize,
+}
+
+impl fmt::Display for VqSnapshot {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"vq {} (size {}, {:?}): ",
+			self.index, self.size, self.mode
+		)?;
+		match self.ring {
+			RingSnapshot::Split {
+				avail_idx,
+				used_idx,
+				read_idx,
+			} => write!(
+				f,
+				"split avail_idx={avail_idx} used_idx={used_idx} read_idx={read_idx}"
+			)?,
+			RingSnapshot::Packed {
+				write_index,
+				poll_index,
+				drv_wrap_count,
+				dev_wrap_count,
+			} => write!(
+				f,
+				"packed write_index={write_index} poll_index={poll_index} drv_wc={} dev_wc={}",
+				u8::from(drv_wrap_count),
+				u8::from(dev_wrap_count)
+			)?,
+		}
+		write!(
+			f,
+			", free={} in_flight={}",
+			self.free_descriptors, self.in_flight
+		)
+	}
+}
+
+/// Number of buckets of a [LatencyHistogram]
+#[cfg(feature = "virtio-latency")]
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// Histogram of the latencies between the dispatch of transfers and their completion,
+/// see [Virtq::latency_histogram].
+///
+/// Bucket `i` counts latencies of `2^i` up to `2^(i+1)` microseconds. The first bucket
+/// also counts latencies below one microsecond. Latencies beyond the last bucket are
+/// counted as overflows.
+#[cfg(feature = "virtio-latency")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyHistogram {
+	pub buckets: [u64; LATENCY_BUCKETS],
+	pub overflows: u64,
+}
+
+#[cfg(feature = "virtio-latency")]
+impl LatencyHistogram {
+	/// Counts a transfer, which completed after `micros` microseconds.
+	pub fn record(&mut self, micros: u64) {
+		let bucket = usize::try_from(micros.max(1).ilog2()).unwrap();
+		match self.buckets.get_mut(bucket) {
+			Some(count) => *count = count.saturating_add(1),
+			None => self.overflows = self.overflows.saturating_add(1),
+		}
+	}
+}
+
+#[cfg(feature = "virtio-latency")]
+impl fmt::Display for LatencyHistogram {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, count) in self.buckets.iter().enumerate() {
+			if *count != 0 {
+				writeln!(f, "< {:>6} us: {count}", 1u64 << (i + 1))?;
+			}
+		}
+		write!(
+			f,
+			">= {:>5} us: {}",
+			1u64 << LATENCY_BUCKETS,
+			self.overflows
+		)
+	}
+}
+
 // Public interface of Virtq
 
 /// The Virtq trait unifies access to the two different Virtqueue types
@@ -121,17 +275,46 @@ pub trait Virtq: VirtqPrivate {
 	fn dispatch(&self, tkn: TransferToken, notif: bool);
 
 	/// Enables interrupts for this virtqueue upon receiving a transfer
+	///
+	/// Has no effect, if the queue is in [QueueMode::Poll].
 	fn enable_notifs(&self);
 
 	/// Disables interrupts for this virtqueue upon receiving a transfer
 	fn disable_notifs(&self);
 
+	/// Switches the queue between interrupt and poll mode.
+	///
+	/// In poll mode, notifications of the device are disabled until the queue is
+	/// switched back to [QueueMode::Interrupt]. The driver has to call [Virtq::poll]
+	/// on its own.
+	fn set_mode(&self, mode: QueueMode);
+
+	/// Returns the current mode of the queue. Queues start in [QueueMode::Interrupt].
+	fn mode(&self) -> QueueMode;
+
+	/// Returns a snapshot of the indices of the queue.
+	///
+	/// The snapshot is cheap and doesn't modify the queue. Hence, it may be
+	/// taken at any time to inspect a misbehaving queue.
+	fn debug_snapshot(&self) -> VqSnapshot;
+
+	/// Returns the histogram of the latencies of the completed transfers.
+	#[cfg(feature = "virtio-latency")]
+	fn latency_histogram(&self) -> LatencyHistogram;
+
 	/// Checks if new used descriptors have been written by the device.
 	/// This activates the queue and polls the descriptor ring of the queue.
 	///
 	/// * `TransferTokens` which hold an `await_queue` will be placed into
 	/// these queues.
-	fn poll(&self);
+	///
+	/// The amount of work done by a single call is bounded. Returns `true`, if
+	/// further used descriptors are pending and the queue should be polled again.
+	fn poll(&self) -> bool;
+
+	/// Processes at most `budget` used descriptors. Returns `true`, if further used
+	/// descriptors are pending.
+	fn poll_bounded(&self, budget: u16) -> bool;
 
 	/// Dispatches a batch of transfer token. The buffers of the respective transfers are provided to the queue in
 	/// sequence. After the last buffer has been written, the queue marks the first buffer as available and triggers
@@ -214,6 +397,7 @@ pub trait Virtq: VirtqPrivate {
 	/// **Reasons for Failure:**
 	/// * Queue does not have enough descriptors left, to split `T` or `K` into the desired amount of memory chunks.
 	/// * Calley mixed `Indirect (Direct::Indirect())` with `Direct(BuffSpec::Single() or BuffSpec::Multiple())` descriptors.
+	/// * Calley requested indirect descriptors, but the device has not negotiated `VIRTIO_F_INDIRECT_DESC`.
 	///
 	/// **Details on Usage:**
 	/// * `(Single, _ )` or `(_ , Single)` -> Results in one descriptor in the queue, hence Consumes one element.
@@ -267,6 +451,16 @@ pub trait Virtq: VirtqPrivate {
 		if send.is_empty() && recv.is_empty() {
 			return Err(VirtqError::BufferNotSpecified);
 		}
+		self.check_buffer_type(buffer_type)?;
+
+		// Only a direct buffer with a single descriptor may be empty. The same
+		// holds for buffers, which are specified by a [BuffSpec].
+		let empty_allowed = |len: usize| buffer_type == BufferType::Direct && len == 1;
+		if (!empty_allowed(send.len()) && send.iter().any(|slice| slice.is_empty()))
+			|| (!empty_allowed(recv.len()) && recv.iter().any(|slice| slice.is_empty()))
+		{
+			return Err(VirtqError::BufferSizeZero);
+		}
 
 		let total_send_len = send.iter().map(|slice| slice.len()).sum();
 		let total_recv_len = recv.iter().map(|slice| slice.len()).sum();
@@ -378,6 +572,9 @@ pub trait Virtq: VirtqPrivate {
 				reusable: false,
 			}),
 			await_queue: None,
+			on_complete: None,
+			#[cfg(feature = "virtio-latency")]
+			dispatched: 0,
 		})
 	}
 
@@ -400,6 +597,7 @@ pub trait Virtq: VirtqPrivate {
 	/// **Reasons for Failure:**
 	/// * Queue does not have enough descriptors left to create the desired amount of descriptors as indicated by the `BuffSpec`.
 	/// * Calley mixed `Indirect (Direct::Indirect())` with `Direct(BuffSpec::Single() or BuffSpec::Multiple())` descriptors.
+	/// * Calley requested indirect descriptors, but the device has not negotiated `VIRTIO_F_INDIRECT_DESC`.
 	/// * Systerm does not have enough memory resources left.
 	///
 	/// **Details on Usage:**
@@ -439,6 +637,13 @@ pub trait Virtq: VirtqPrivate {
 	where
 		Self: Sized + 'static,
 	{
+		for spec in send.iter().chain(recv.iter()) {
+			spec.validate()?;
+			if let BuffSpec::Indirect(_) = spec {
+				self.check_buffer_type(BufferType::Indirect)?;
+			}
+		}
+
 		match (send, recv) {
 			// No buffers specified
 			(None, None) => Err(VirtqError::BufferNotSpecified),
@@ -830,6 +1035,19 @@ trait VirtqPrivate {
 	) -> Result<MemDescr, VirtqError>;
 
 	fn mem_pool(&self) -> Rc<MemPool>;
+
+	/// Returns `true`, if the device has negotiated `VIRTIO_F_INDIRECT_DESC`.
+	fn indirect_supported(&self) -> bool;
+
+	/// Checks that the queue is able to transfer buffers of `buffer_type`.
+	fn check_buffer_type(&self, buffer_type: BufferType) -> Result<(), VirtqError> {
+		match buffer_type {
+			BufferType::Indirect if !self.indirect_supported() => Err(
+				VirtqError::FeatureNotSupported(virtio_spec::F::INDIRECT_DESC),
+			),
+			BufferType::Direct | BufferType::Indirect => Ok(()),
+		}
+	}
 }
 
 /// Allows to check, if a given structure crosses a physical page boundary.
@@ -972,6 +1190,29 @@ pub fn dispatch_batch_await(tkns: Vec<TransferToken>, await_queue: BufferTokenSe
 	}
 }
 
+/// Polls the queues `vqs` round-robin. The `budget` of used descriptors is
+/// divided evenly among the queues, but each queue processes at least one
+/// descriptor. Hence, a busy queue doesn't starve the other queues of a device.
+///
+/// Returns `true`, if further used descriptors are pending in any queue.
+pub fn poll_all<'a, I>(vqs: I, budget: u16) -> bool
+where
+	I: IntoIterator<Item = &'a Rc<dyn Virtq>>,
+	I::IntoIter: Clone,
+{
+	let vqs = vqs.into_iter();
+	let queues = u16::try_from(vqs.clone().count()).unwrap();
+	if queues == 0 {
+		return false;
+	}
+
+	vqs.zip(0..).fold(false, |pending, (vq, i)| {
+		// The first queues get the remainder of the budget.
+		let share = budget / queues + u16::from(i < budget % queues);
+		vq.poll_bounded(share.max(1)) | pending
+	})
+}
+
 /// The trait needs to be implemented for
 /// structures which are to be used to write data into buffers of a [BufferToken] via [BufferToken::write] or
 /// `BufferToken.write_seq()`.
@@ -1025,6 +1266,12 @@ pub struct TransferToken {
 	/// as finished `Transfers`. If None, only the state
 	/// of the Token will be changed.
 	await_queue: Option<BufferTokenSender>,
+	/// Callback, which is invoked before the finished
+	/// BufferToken is placed into the `await_queue`
+	on_complete: Option<CompletionCallback>,
+	/// Time of the dispatch in microseconds
+	#[cfg(feature = "virtio-latency")]
+	dispatched: u64,
 }
 
 /// Public Interface for TransferToken
@@ -1035,12 +1282,25 @@ impl TransferToken {
 		Rc::clone(&self.buff_tkn.as_ref().unwrap().vq)
 	}
 
+	/// Registers a callback, which is invoked as soon as the transfer is finished.
+	///
+	/// The callback runs before the [BufferToken] is placed into the `await_queue`. Without
+	/// an `await_queue`, the [BufferToken] is dropped afterwards. This allows fire-and-forget
+	/// transfers without allocating a channel.
+	///
+	/// The callback runs in interrupt or poll context and must therefore be non-blocking.
+	pub fn on_complete(mut self, on_complete: CompletionCallback) -> Self {
+		self.on_complete = Some(on_complete);
+		self
+	}
+
 	/// Dispatches a TransferToken and awaits it at the specified queue.
 	///
 	/// The `notif` parameter indicates if the driver wants to have a notification for this specific
 	/// transfer. This is only for performance optimization. As it is NOT ensured, that the device sees the
 	/// updated notification flags before finishing transfers!
 	pub fn dispatch_await(mut self, await_queue: BufferTokenSender, notif: bool) {
+		debug_assert!(self.buff_tkn.as_ref().unwrap().buffer_type().is_ok());
 		self.await_queue = Some(await_queue.clone());
 
 		self.get_vq().dispatch(self, notif);
@@ -1052,6 +1312,7 @@ impl TransferToken {
 	/// transfer. This is only for performance optimization. As it is NOT ensured, that the device sees the
 	/// updated notification flags before finishing transfers!
 	pub fn dispatch(self, notif: bool) {
+		debug_assert!(self.buff_tkn.as_ref().unwrap().buffer_type().is_ok());
 		self.get_vq().dispatch(self, notif);
 	}
 
@@ -1080,7 +1341,9 @@ impl TransferToken {
 					break;
 				}
 				Err(TryRecvError::Closed) => return Err(VirtqError::General),
-				Err(TryRecvError::Empty) => vq.poll(),
+				Err(TryRecvError::Empty) => {
+					vq.poll();
+				}
 			}
 		}
 
@@ -1090,6 +1353,48 @@ impl TransferToken {
 	}
 }
 
+impl TransferToken {
+	/// Records the time of the dispatch, in order to measure the latency of the transfer.
+	/// Without the feature `virtio-latency`, the call has no effect.
+	fn stamp(&mut self) {
+		#[cfg(feature = "virtio-latency")]
+		{
+			self.dispatched = crate::arch::processor::get_timer_ticks();
+		}
+	}
+
+	/// Returns the microseconds since the dispatch of the transfer.
+	#[cfg(feature = "virtio-latency")]
+	fn latency(&self) -> u64 {
+		crate::arch::processor::get_timer_ticks().saturating_sub(self.dispatched)
+	}
+
+	/// Returns `true` if the `await_queue` can't take the [BufferToken] at the moment.
+	///
+	/// A closed queue never drains. Hence, it doesn't block.
+	fn is_blocked(&self) -> bool {
+		self.await_queue
+			.as_ref()
+			.is_some_and(|queue| queue.is_full() && !queue.is_closed())
+	}
+
+	/// Finishes the transfer. Invokes the completion callback and moves the
+	/// [BufferToken] into the `await_queue`, if available.
+	fn complete(mut self) {
+		// Unwrapping is okay here, as TransferToken must hold a BufferToken
+		let buff_tkn = self.buff_tkn.take().unwrap();
+
+		if let Some(on_complete) = self.on_complete.take() {
+			on_complete(&buff_tkn);
+		}
+
+		if let Some(queue) = self.await_queue.take() {
+			// Place the TransferToken in a Transfer, which will hold ownership of the token
+			forward(&queue, Box::new(buff_tkn));
+		}
+	}
+}
+
 /// The struct represents buffers which are ready to be written or to be send.
 ///
 /// BufferTokens can be written in two ways:
@@ -1132,6 +1437,14 @@ pub struct BufferToken {
 
 // Private interface of BufferToken
 impl BufferToken {
+	/// Returns the type of the buffers of the token.
+	fn buffer_type(&self) -> Result<BufferType, VirtqError> {
+		BufferType::from_layout(
+			self.send_buff.as_ref().map(Buffer::is_indirect),
+			self.recv_buff.as_ref().map(Buffer::is_indirect),
+		)
+	}
+
 	/// Returns the overall number of descriptors.
 	fn num_descr(&self) -> usize {
 		let mut len = 0usize;
@@ -1351,6 +1664,11 @@ impl BufferToken {
 
 // Public interface of BufferToken
 impl BufferToken {
+	/// Returns the index of the virtqueue, to which the token belongs.
+	pub fn vq_index(&self) -> VqIndex {
+		self.vq.index()
+	}
+
 	/// Restricts the size of a given BufferToken. One must specify either a `new_send_len` or/and `new_recv_len`. If possible
 	/// the function will restrict the respective buffers size to this value. This is especially useful if one has to provide the
 	/// user-space or the device with a buffer and has already a free buffer at hand, which is to large. With this method the user
@@ -1751,6 +2069,9 @@ impl BufferToken {
 		Ok(TransferToken {
 			buff_tkn: Some(self),
 			await_queue: None,
+			on_complete: None,
+			#[cfg(feature = "virtio-latency")]
+			dispatched: 0,
 		})
 	}
 
@@ -1813,15 +2134,43 @@ impl BufferToken {
 		TransferToken {
 			buff_tkn: Some(self),
 			await_queue: None,
+			on_complete: None,
+			#[cfg(feature = "virtio-latency")]
+			dispatched: 0,
 		}
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BufferType {
 	Direct,
 	Indirect,
 }
 
+impl BufferType {
+	/// Derives the type of a token from the layout of its send and recv buffers.
+	/// The arguments indicate whether the respective buffer is indirect, or are `None`
+	/// if the buffer does not exist.
+	///
+	/// Returns an error if no buffer exists or if direct and indirect buffers are mixed.
+	fn from_layout(
+		send_indirect: Option<bool>,
+		recv_indirect: Option<bool>,
+	) -> Result<Self, VirtqError> {
+		let indirect = match (send_indirect, recv_indirect) {
+			(None, None) => return Err(VirtqError::BufferNotSpecified),
+			(Some(send), Some(recv)) if send != recv => return Err(VirtqError::BufferInWithDirect),
+			(Some(indirect), _) | (None, Some(indirect)) => indirect,
+		};
+
+		Ok(if indirect {
+			BufferType::Indirect
+		} else {
+			BufferType::Direct
+		})
+	}
+}
+
 /// Describes the type of a buffer and unifies them.
 enum Buffer {
 	/// A buffer consisting of a single [Memory Descriptor](MemDescr).
@@ -2214,6 +2563,9 @@ enum Dealloc {
 	AsSlice,
 }
 
+/// Memory of zero-length descriptors. See [`MemPool::zero_length`].
+static ZERO_LENGTH: u8 = 0;
+
 /// MemPool allows to easily control, request and provide memory for Virtqueues.
 ///
 /// * The struct is initialized with a limit of free running "tracked" (see `fn pull_untracked`)
@@ -2238,6 +2590,15 @@ impl MemPool {
 		self.pool.borrow_mut().push(id);
 	}
 
+	/// Takes an id from the pool.
+	///
+	/// The id must be handed over to a [MemDescr], which returns it upon drop. Hence,
+	/// if the pool is exhausted in the middle of a descriptor chain, dropping the
+	/// already pulled descriptors restores the pool.
+	fn pop_id(&self) -> Result<MemDescrId, VirtqError> {
+		self.pool.borrow_mut().pop().ok_or(VirtqError::NoDescrAvail)
+	}
+
 	/// Returns a new instance, with a pool of the specified size.
 	fn new(size: u16) -> MemPool {
 		// Not really safe "as usize". But the minimum usize on rust is currently
@@ -2260,7 +2621,6 @@ impl MemPool {
 	/// Creates a MemDescr which refers to already existing memory.
 	///
 	/// **Info on Usage:**
-	/// * `Panics` if given `slice.len() == 0`
 	/// * `Panics` if slice crosses physical page boundary
 	/// * The given slice MUST be a heap allocated slice.
 	/// * Panics if slice crosses page boundaries!
@@ -2269,10 +2629,13 @@ impl MemPool {
 	///
 	/// * The descriptor will consume one element of the pool.
 	/// * The referred to memory area will NOT be deallocated upon drop.
+	/// * An empty slice results in a zero-length descriptor, which doesn't
+	///   refer to the slice (see `fn pull()`).
 	fn pull_from_raw(self: Rc<Self>, slice: &[u8]) -> Result<MemDescr, VirtqError> {
-		// Zero sized descriptors are NOT allowed
-		// This also prohibids a panic due to accessing wrong index below
-		assert!(!slice.is_empty());
+		// An empty slice may not refer to mapped memory
+		if slice.is_empty() {
+			return self.pull(Bytes(0));
+		}
 
 		// Assert descriptor does not cross a page barrier
 		let start_virt = ptr::from_ref(slice.first().unwrap()).addr();
@@ -2282,10 +2645,7 @@ impl MemPool {
 
 		assert_eq!(end_phy, end_phy_calc);
 
-		let desc_id = match self.pool.borrow_mut().pop() {
-			Some(id) => id,
-			None => return Err(VirtqError::NoDescrAvail),
-		};
+		let desc_id = self.pop_id()?;
 
 		Ok(MemDescr {
 			ptr: slice.as_ptr() as *mut _,
@@ -2303,7 +2663,6 @@ impl MemPool {
 	/// be used with `Buffer::Indirect`.
 	///
 	/// **Info on Usage:**
-	/// * `Panics` if given `slice.len() == 0`
 	/// * `Panics` if slice crosses physical page boundary
 	/// * The given slice MUST be a heap allocated slice.
 	///
@@ -2311,10 +2670,13 @@ impl MemPool {
 	///
 	/// * The descriptor will consume one element of the pool.
 	/// * The referred to memory area will NOT be deallocated upon drop.
+	/// * An empty slice results in a zero-length descriptor, which doesn't
+	///   refer to the slice (see `fn pull_untracked()`).
 	fn pull_from_raw_untracked(self: Rc<Self>, slice: &[u8]) -> MemDescr {
-		// Zero sized descriptors are NOT allowed
-		// This also prohibids a panic due to accessing wrong index below
-		assert!(!slice.is_empty());
+		// An empty slice may not refer to mapped memory
+		if slice.is_empty() {
+			return self.pull_untracked(Bytes(0));
+		}
 
 		// Assert descriptor does not cross a page barrier
 		let start_virt = ptr::from_ref(slice.first().unwrap()).addr();
@@ -2348,12 +2710,12 @@ impl MemPool {
 	///   * Second MemPool.pull -> MemDesc with id = 100
 	///   * Third MemPool.pull -> MemDesc with id = 2,
 	fn pull(self: Rc<Self>, bytes: Bytes) -> Result<MemDescr, VirtqError> {
-		let id = match self.pool.borrow_mut().pop() {
-			Some(id) => id,
-			None => return Err(VirtqError::NoDescrAvail),
-		};
+		let id = self.pop_id()?;
 
 		let len = bytes.0;
+		if len == 0 {
+			return Ok(self.zero_length(Some(id)));
+		}
 
 		// Allocate heap memory via a vec, leak and cast
 		let _mem_len = len.align_up(BasePageSize::SIZE as usize);
@@ -2389,6 +2751,9 @@ impl MemPool {
 	///   * Third MemPool.pull -> MemDesc with id = 2,
 	fn pull_untracked(self: Rc<Self>, bytes: Bytes) -> MemDescr {
 		let len = bytes.0;
+		if len == 0 {
+			return self.zero_length(None);
+		}
 
 		// Allocate heap memory via a vec, leak and cast
 		let _mem_len = len.align_up(BasePageSize::SIZE as usize);
@@ -2412,6 +2777,23 @@ impl MemPool {
 			pool: self.clone(),
 		}
 	}
+
+	/// Creates a zero-length descriptor without allocating memory.
+	///
+	/// The device never accesses the memory of a zero-length descriptor. Nevertheless,
+	/// the descriptor has to refer to a mapped address, which is translated to a
+	/// physical address.
+	fn zero_length(self: Rc<Self>, id: Option<MemDescrId>) -> MemDescr {
+		MemDescr {
+			ptr: ptr::addr_of!(ZERO_LENGTH).cast_mut(),
+			len: 0,
+			_init_len: 0,
+			_mem_len: 0,
+			id,
+			dealloc: Dealloc::Not,
+			pool: self.clone(),
+		}
+	}
 }
 
 /// Specifies the type of buffer and amount of memory chunks that buffer does consist of wanted.
@@ -2458,6 +2840,28 @@ pub enum BuffSpec<'a> {
 	Indirect(&'a [Bytes]),
 }
 
+impl BuffSpec<'_> {
+	/// Checks that the buffer consists of at least one descriptor.
+	///
+	/// A buffer may have a length of zero (e.g. a zero-byte datagram), but only
+	/// if it consists of a single descriptor. Buffers with multiple descriptors
+	/// must not contain zero-length descriptors.
+	fn validate(&self) -> Result<(), VirtqError> {
+		match self {
+			BuffSpec::Single(_) => Ok(()),
+			BuffSpec::Multiple(size_lst) | BuffSpec::Indirect(size_lst) => {
+				if size_lst.is_empty() {
+					Err(VirtqError::BufferNotSpecified)
+				} else if size_lst.iter().any(|size| size.0 == 0) {
+					Err(VirtqError::BufferSizeZero)
+				} else {
+					Ok(())
+				}
+			}
+		}
+	}
+}
+
 /// Virtqueue descr flags as defined in the specification.
 ///
 /// See Virtio specification v1.1. - 2.6.5
@@ -2592,6 +2996,9 @@ pub mod error {
 		QueueSizeNotAllowed(u16),
 		FeatureNotSupported(virtio_spec::F),
 		AllocationError,
+		/// A buffer, which consists of multiple descriptors, contains a zero-length
+		/// descriptor. Only buffers with a single descriptor may be empty.
+		BufferSizeZero,
 	}
 
 	impl core::fmt::Debug for VirtqError {
@@ -2609,7 +3016,8 @@ pub mod error {
                 VirtqError::BufferToLarge => write!(f, "Buffer to large for queue! u32::MAX exceeded."),
 				VirtqError::QueueSizeNotAllowed(_) => write!(f, "The requested queue size is not valid."),
 				VirtqError::FeatureNotSupported(_) => write!(f, "An unsupported feature was requested from the queue."),
-				VirtqError::AllocationError => write!(f, "An error was encountered during the allocation of the queue structures.")
+				VirtqError::AllocationError => write!(f, "An error was encountered during the allocation of the queue structures."),
+				VirtqError::BufferSizeZero => write!(f, "Only buffers with a single descriptor may have a length of zero.")
             }
 		}
 	}
@@ -2620,3 +3028,116 @@ pub mod error {
 		}
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(feature = "virtio-latency")]
+	fn latency_histogram_buckets() {
+		let mut histogram = LatencyHistogram::default();
+		histogram.record(0);
+		histogram.record(1);
+		histogram.record(3);
+		histogram.record(1000);
+		histogram.record(u64::MAX);
+
+		assert_eq!(histogram.buckets[0], 2);
+		assert_eq!(histogram.buckets[1], 1);
+		assert_eq!(histogram.buckets[9], 1);
+		assert_eq!(histogram.overflows, 1);
+	}
+
+	#[test]
+	fn forward_to_full_or_closed_queue() {
+		let (sender, receiver) = async_channel::bounded(1);
+		forward(&sender, 1u32);
+		// The queue is full, the second item is dropped.
+		forward(&sender, 2u32);
+		assert_eq!(receiver.try_recv(), Ok(1));
+		assert!(receiver.try_recv().is_err());
+
+		drop(receiver);
+		forward(&sender, 3u32);
+	}
+
+	#[test]
+	fn buffer_type_from_layout() {
+		assert_eq!(
+			BufferType::from_layout(Some(false), None).unwrap(),
+			BufferType::Direct
+		);
+		assert_eq!(
+			BufferType::from_layout(None, Some(true)).unwrap(),
+			BufferType::Indirect
+		);
+		assert_eq!(
+			BufferType::from_layout(Some(true), Some(true)).unwrap(),
+			BufferType::Indirect
+		);
+	}
+
+	#[test]
+	fn buffer_type_rejects_mismatched_layout() {
+		assert!(matches!(
+			BufferType::from_layout(Some(false), Some(true)),
+			Err(VirtqError::BufferInWithDirect)
+		));
+		assert!(matches!(
+			BufferType::from_layout(Some(true), Some(false)),
+			Err(VirtqError::BufferInWithDirect)
+		));
+		assert!(matches!(
+			BufferType::from_layout(None, None),
+			Err(VirtqError::BufferNotSpecified)
+		));
+	}
+
+	#[test]
+	fn buff_spec_allows_single_zero_length_descriptor() {
+		assert!(BuffSpec::Single(Bytes(0)).validate().is_ok());
+		assert!(BuffSpec::Multiple(&[Bytes(10), Bytes(20)])
+			.validate()
+			.is_ok());
+	}
+
+	#[test]
+	fn buff_spec_rejects_zero_length_descriptor_in_list() {
+		assert!(matches!(
+			BuffSpec::Multiple(&[Bytes(10), Bytes(0)]).validate(),
+			Err(VirtqError::BufferSizeZero)
+		));
+		assert!(matches!(
+			BuffSpec::Indirect(&[Bytes(0)]).validate(),
+			Err(VirtqError::BufferSizeZero)
+		));
+		assert!(matches!(
+			BuffSpec::Multiple(&[]).validate(),
+			Err(VirtqError::BufferNotSpecified)
+		));
+	}
+
+	#[test]
+	fn display_snapshot() {
+		use alloc::string::ToString;
+
+		let snapshot = VqSnapshot {
+			index: 1,
+			size: 256,
+			mode: QueueMode::Interrupt,
+			ring: RingSnapshot::Packed {
+				write_index: 3,
+				poll_index: 1,
+				drv_wrap_count: true,
+				dev_wrap_count: false,
+			},
+			free_descriptors: 254,
+			in_flight: 2,
+		};
+		assert_eq!(
+			snapshot.to_string(),
+			"vq 1 (size 256, Interrupt): packed write_index=3 poll_index=1 drv_wc=1 dev_wc=0, free=254 in_flight=2"
+		);
+	}
+}
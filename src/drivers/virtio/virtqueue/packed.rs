@@ -5,24 +5,22 @@
 use alloc::boxed::Box;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::ptr;
 use core::sync::atomic::{fence, Ordering};
 
-use align_address::Align;
-use zerocopy::little_endian;
+use virtio_spec::{le16, le32, le64};
 
-#[cfg(not(feature = "pci"))]
-use super::super::transport::mmio::{ComCfg, NotifCfg, NotifCtrl};
-#[cfg(feature = "pci")]
-use super::super::transport::pci::{ComCfg, NotifCfg, NotifCtrl};
+use super::super::transport::{ComCfg, NotifCfg, NotifCtrl};
 use super::error::VirtqError;
+#[cfg(feature = "virtio-latency")]
+use super::LatencyHistogram;
 use super::{
-	BuffSpec, Buffer, BufferToken, BufferType, Bytes, DescrFlags, MemDescr, MemPool, TransferToken,
-	Virtq, VirtqPrivate, VqIndex, VqSize,
+	BuffSpec, Buffer, BufferToken, BufferType, Bytes, DescrFlags, MemDescr, MemPool, QueueMode,
+	RingSnapshot, TransferToken, Virtq, VirtqPrivate, VqIndex, VqSize, VqSnapshot,
 };
-use crate::arch::mm::paging::{BasePageSize, PageSize};
-use crate::arch::mm::{paging, VirtAddr};
+use crate::arch::mm::PhysAddr;
+use crate::mm::device_alloc::DeviceAlloc;
 
 /// A newtype of bool used for convenience in context with
 /// packed queues wrap counter.
@@ -79,10 +77,69 @@ impl WrapCount {
 	}
 }
 
+/// Returns the physical address of `ptr`, which is handed to the device.
+///
+/// The device accesses the rings and the buffers by DMA. Hence, it doesn't know
+/// about the virtual addresses of the kernel.
+fn phys_addr<T>(ptr: *const T) -> PhysAddr {
+	use crate::arch::mm::{paging, VirtAddr};
+
+	paging::virt_to_phys(VirtAddr::from(ptr as u64))
+}
+
+/// Transfers, which are in flight, indexed by the buffer ID of their descriptor chain
+///
+/// Each transfer is boxed. Hence, its address stays stable, while the device processes it.
+/// A slot has to be released by [`take`](Self::take) before it is used again.
+struct TokenRing<T> {
+	slots: Box<[Option<T>]>,
+}
+
+impl<T> TokenRing<T> {
+	/// Creates a ring for the buffer IDs `1..=size`.
+	fn new(size: usize) -> Self {
+		// Buffer ID's run from 1 to size_of_queue. In order to index directly via an ID,
+		// it is much easier to allocate size + 1 slots and not to use the first one.
+		// `Box` is not Clone, so neither is `None::<Box<_>>`. Hence, we need to produce `None`s with a closure.
+		let slots = core::iter::repeat_with(|| None)
+			.take(size + 1)
+			.collect::<Vec<_>>()
+			.into_boxed_slice();
+
+		Self { slots }
+	}
+
+	/// Stores the transfer of the buffer ID `buff_id`.
+	///
+	/// Panics, if the buffer ID is out of range or its slot is still occupied,
+	/// because this would lose a transfer in flight.
+	fn insert(&mut self, buff_id: u16, tkn: T) {
+		assert_ne!(buff_id, 0, "buffer ID 0 is never used");
+		let slot = &mut self.slots[usize::from(buff_id)];
+		assert!(slot.is_none(), "buffer ID {buff_id} is already in flight");
+		*slot = Some(tkn);
+	}
+
+	/// Returns the transfer of the buffer ID `buff_id`.
+	fn get(&self, buff_id: u16) -> Option<&T> {
+		self.slots.get(usize::from(buff_id))?.as_ref()
+	}
+
+	/// Removes and returns the transfer of the buffer ID `buff_id`.
+	fn take(&mut self, buff_id: u16) -> Option<T> {
+		self.slots.get_mut(usize::from(buff_id))?.take()
+	}
+
+	/// Returns the number of transfers in flight.
+	fn in_flight(&self) -> usize {
+		self.slots.iter().filter(|tkn| tkn.is_some()).count()
+	}
+}
+
 /// Structure which allows to control raw ring and operate easily on it
 struct DescriptorRing {
-	ring: &'static mut [Descriptor],
-	tkn_ref_ring: Box<[Option<Box<TransferToken>>]>,
+	ring: Box<[Descriptor], DeviceAlloc>,
+	tkn_ref_ring: TokenRing<Box<TransferToken>>,
 
 	// Controlling variables for the ring
 	//
@@ -95,53 +152,124 @@ struct DescriptorRing {
 	/// See Virtio specification v1.1. - 2.7.1
 	drv_wc: WrapCount,
 	dev_wc: WrapCount,
+	#[cfg(feature = "virtio-latency")]
+	latency: LatencyHistogram,
 }
 
 impl DescriptorRing {
-	fn new(size: u16) -> Self {
-		let size = usize::from(size);
-
-		// Allocate heap memory via a vec, leak and cast
-		let _mem_len =
-			(size * core::mem::size_of::<Descriptor>()).align_up(BasePageSize::SIZE as usize);
-		let ptr = ptr::with_exposed_provenance_mut(crate::mm::allocate(_mem_len, true).0 as usize);
+	/// Returns the number of descriptors for a ring of the given queue size.
+	///
+	/// A ring without any descriptor can never make progress (see `WriteCtrl::incrmt`),
+	/// hence a size of zero is rejected.
+	fn checked_size(size: u16) -> Result<usize, VirtqError> {
+		if size == 0 {
+			Err(VirtqError::QueueSizeNotAllowed(size))
+		} else {
+			Ok(usize::from(size))
+		}
+	}
 
-		let ring: &'static mut [Descriptor] = unsafe { core::slice::from_raw_parts_mut(ptr, size) };
+	fn new(size: u16) -> Result<Self, VirtqError> {
+		let size = Self::checked_size(size)?;
 
-		// Descriptor ID's run from 1 to size_of_queue. In order to index directly into the
-		// reference ring via an ID it is much easier to simply have an array of size = size_of_queue + 1
-		// and do not care about the first element being unused.
-		// `Box` is not Clone, so neither is `None::<Box<_>>`. Hence, we need to produce `None`s with a closure.
-		let tkn_ref_ring = core::iter::repeat_with(|| None)
-			.take(size + 1)
-			.collect::<Vec<_>>()
-			.into_boxed_slice();
+		// The device accesses the ring by DMA. Hence, it has to be physically contiguous.
+		let mut ring = Box::new_uninit_slice_in(size, DeviceAlloc);
+		for desc in ring.iter_mut() {
+			desc.write(Descriptor::new(0, 0, 0, 0));
+		}
+		let ring = unsafe { ring.assume_init() };
 
-		DescriptorRing {
+		Ok(DescriptorRing {
 			ring,
-			tkn_ref_ring,
+			tkn_ref_ring: TokenRing::new(size),
 			write_index: 0,
 			capacity: size,
 			poll_index: 0,
 			drv_wc: WrapCount::new(),
 			dev_wc: WrapCount::new(),
-		}
+			#[cfg(feature = "virtio-latency")]
+			latency: LatencyHistogram::default(),
+		})
+	}
+
+	/// Checks the invariants of the ring, if the feature `virtio-debug` is enabled.
+	/// Otherwise, the checks are compiled out.
+	///
+	/// * `write_index` and `poll_index` lie within the ring.
+	/// * `capacity` doesn't exceed the size of the ring. As the capacity is unsigned,
+	///   this catches a capacity, which became negative.
+	/// * The descriptors in use are the ones from `poll_index` up to `write_index`.
+	///   Hence, the driver never writes a descriptor, which the device still owns.
+	/// * Each transfer in flight uses at least one descriptor of the ring.
+	/// * The driver wrap count differs from the device wrap count exactly if the
+	///   descriptors in use wrap around the end of the ring.
+	///
+	/// Panics with a description of the violated invariant.
+	#[cfg(feature = "virtio-debug")]
+	fn check_invariants(&self) {
+		let size = self.ring.len();
+		assert!(
+			self.write_index < size,
+			"write index {} is out of the ring of size {size}",
+			self.write_index
+		);
+		assert!(
+			self.poll_index < size,
+			"poll index {} is out of the ring of size {size}",
+			self.poll_index
+		);
+		assert!(
+			self.capacity <= size,
+			"capacity {} exceeds the ring of size {size}",
+			self.capacity
+		);
+
+		let in_use = size - self.capacity;
+		assert_eq!(
+			(self.poll_index + in_use) % size,
+			self.write_index,
+			"{in_use} descriptors in use don't end at the write index"
+		);
+
+		let in_flight = self.tkn_ref_ring.in_flight();
+		assert!(
+			in_flight <= in_use,
+			"{in_flight} transfers in flight, but only {in_use} descriptors in use"
+		);
+
+		let wrapped = self.poll_index + in_use >= size;
+		assert_eq!(
+			self.drv_wc.0 != self.dev_wc.0,
+			wrapped,
+			"driver wrap count {:?} is inconsistent with device wrap count {:?}",
+			self.drv_wc,
+			self.dev_wc
+		);
 	}
 
+	#[cfg(not(feature = "virtio-debug"))]
+	#[inline(always)]
+	fn check_invariants(&self) {}
+
 	/// Polls poll index and sets the state of any finished TransferTokens.
 	/// If [TransferToken::await_queue] is available, the [BufferToken] will be moved to the queue.
-	fn poll(&mut self) {
+	/// A registered [TransferToken::on_complete] callback is invoked beforehand.
+	///
+	/// Returns `true` if the device has already marked the next descriptor as used.
+	fn poll(&mut self) -> bool {
 		let mut ctrl = self.get_read_ctrler();
 
-		if let Some(mut tkn) = ctrl.poll_next() {
-			if let Some(queue) = tkn.await_queue.take() {
-				// Place the TransferToken in a Transfer, which will hold ownership of the token
-				queue.try_send(Box::new(tkn.buff_tkn.unwrap())).unwrap();
-			}
+		if let Some(tkn) = ctrl.poll_next() {
+			#[cfg(feature = "virtio-latency")]
+			self.latency.record(tkn.latency());
+			tkn.complete();
 		}
+		self.check_invariants();
+
+		self.ring[self.poll_index].is_used(self.dev_wc)
 	}
 
-	fn push_batch(&mut self, tkn_lst: Vec<TransferToken>) -> (usize, u8) {
+	fn push_batch(&mut self, tkn_lst: Vec<TransferToken>) -> (usize, WrapCount) {
 		// Catch empty push, in order to allow zero initialized first_ctrl_settings struct
 		// which will be overwritten in the first iteration of the for-loop
 		assert!(!tkn_lst.is_empty());
@@ -149,7 +277,9 @@ impl DescriptorRing {
 		let mut first_ctrl_settings: (usize, u16, WrapCount) = (0, 0, WrapCount::new());
 		let mut first_buffer = None;
 
-		for (i, tkn) in tkn_lst.into_iter().enumerate() {
+		for (i, mut tkn) in tkn_lst.into_iter().enumerate() {
+			tkn.stamp();
+
 			// Check length and if its fits. This should always be true due to the restriction of
 			// the memory pool, but to be sure.
 			assert!(tkn.buff_tkn.as_ref().unwrap().num_consuming_descr() <= self.capacity);
@@ -271,17 +401,23 @@ impl DescriptorRing {
 		//
 		// Providing the first buffer in the list manually
 		// provide reference, in order to let TransferToken now upon finish.
-		self.tkn_ref_ring[usize::from(first_ctrl_settings.1)] = first_buffer;
+		if let Some(first_buffer) = first_buffer {
+			self.tkn_ref_ring
+				.insert(first_ctrl_settings.1, first_buffer);
+		}
 		// The driver performs a suitable memory barrier to ensure the device sees the updated descriptor table and available ring before the next step.
 		// See Virtio specfification v1.1. - 2.7.21
 		fence(Ordering::SeqCst);
-		self.ring[first_ctrl_settings.0].flags |= first_ctrl_settings.2.as_flags_avail().into();
+		let desc = &mut self.ring[first_ctrl_settings.0];
+		desc.flags = (desc.flags.to_ne() | first_ctrl_settings.2.as_flags_avail()).into();
+		self.check_invariants();
 
-		// Converting a boolean as u8 is fine
-		(first_ctrl_settings.0, first_ctrl_settings.2 .0 as u8)
+		(first_ctrl_settings.0, first_ctrl_settings.2)
 	}
 
-	fn push(&mut self, tkn: TransferToken) -> (usize, u8) {
+	fn push(&mut self, mut tkn: TransferToken) -> (usize, WrapCount) {
+		tkn.stamp();
+
 		// Check length and if its fits. This should always be true due to the restriction of
 		// the memory pool, but to be sure.
 		assert!(tkn.buff_tkn.as_ref().unwrap().num_consuming_descr() <= self.capacity);
@@ -389,14 +525,22 @@ impl DescriptorRing {
 		ctrl.make_avail(Box::new(tkn));
 		fence(Ordering::SeqCst);
 
-		// Converting a boolean as u8 is fine
-		(ctrl.start, ctrl.wrap_at_init.0 as u8)
+		let start = ctrl.start;
+		let wrap_at_init = ctrl.wrap_at_init;
+		self.check_invariants();
+
+		(start, wrap_at_init)
 	}
 
-	/// # Unsafe
-	/// Returns the memory address of the first element of the descriptor ring
-	fn raw_addr(&self) -> usize {
-		self.ring.as_ptr() as usize
+	/// Returns the notification data of the queue `index`, which tells the device
+	/// the offset and the wrap count of the next available descriptor.
+	///
+	/// See Virtio specification v1.1. - 4.1.5.2
+	fn notif_data(&self, index: VqIndex) -> [u8; 4] {
+		// The queue size is at most 2^15. Hence, the offset fits into 15 bits.
+		let next_off = u32::try_from(self.write_index).unwrap();
+		let next_wrap = u32::from(self.drv_wc.0);
+		(u32::from(index.0) | next_off << 16 | next_wrap << 31).to_le_bytes()
 	}
 
 	/// Returns an initialized write controller in order
@@ -436,13 +580,23 @@ struct ReadCtrl<'a> {
 impl<'a> ReadCtrl<'a> {
 	/// Polls the ring for a new finished buffer. If buffer is marked as finished, takes care of
 	/// updating the queue and returns the respective TransferToken.
+	///
+	/// As long as the `await_queue` of the finished transfer is full, the buffer is left
+	/// in the ring, until the consumer drains the queue.
 	fn poll_next(&mut self) -> Option<Box<TransferToken>> {
 		// Check if descriptor has been marked used.
-		if self.desc_ring.ring[self.position].flags.get() & WrapCount::flag_mask()
-			== self.desc_ring.dev_wc.as_flags_used()
-		{
-			let buff_id = usize::from(self.desc_ring.ring[self.position].buff_id);
-			let mut tkn = self.desc_ring.tkn_ref_ring[buff_id].take().expect(
+		if self.desc_ring.ring[self.position].is_used(self.desc_ring.dev_wc) {
+			let buff_id = self.desc_ring.ring[self.position].buff_id.to_ne();
+			if self
+				.desc_ring
+				.tkn_ref_ring
+				.get(buff_id)
+				.is_some_and(|tkn| tkn.is_blocked())
+			{
+				return None;
+			}
+
+			let mut tkn = self.desc_ring.tkn_ref_ring.take(buff_id).expect(
 				"The buff_id is incorrect or the reference to the TransferToken was misplaced.",
 			);
 
@@ -472,17 +626,17 @@ impl<'a> ReadCtrl<'a> {
 			// INFO:
 			// Due to the behaviour of the currently used devices and the virtio code from the linux kernel, we assume, that device do NOT set this
 			// flag correctly upon writes. Hence we omit it, in order to receive data.
-			let write_len = self.desc_ring.ring[self.position].len;
+			let write_len = self.desc_ring.ring[self.position].len.to_ne();
 
 			match (send_buff, recv_buff) {
 				(Some(send_buff), Some(recv_buff)) => {
 					// Need to only check for either send or receive buff to contain
 					// a ctrl_desc as, both carry the same if they carry one.
 					if send_buff.is_indirect() {
-						self.update_indirect(Some(send_buff), Some((recv_buff, write_len.into())));
+						self.update_indirect(Some(send_buff), Some((recv_buff, write_len)));
 					} else {
 						self.update_send(send_buff);
-						self.update_recv((recv_buff, write_len.into()));
+						self.update_recv((recv_buff, write_len));
 					}
 				}
 				(Some(send_buff), None) => {
@@ -494,9 +648,9 @@ impl<'a> ReadCtrl<'a> {
 				}
 				(None, Some(recv_buff)) => {
 					if recv_buff.is_indirect() {
-						self.update_indirect(None, Some((recv_buff, write_len.into())));
+						self.update_indirect(None, Some((recv_buff, write_len)));
 					} else {
-						self.update_recv((recv_buff, write_len.into()));
+						self.update_recv((recv_buff, write_len));
 					}
 				}
 				(None, None) => unreachable!("Empty Transfers are not allowed..."),
@@ -545,13 +699,13 @@ impl<'a> ReadCtrl<'a> {
 					// Unwrapping is fine here, as lists must be of same size and same ordering
 					let ring_desc = desc_iter.next().unwrap();
 
-					if write_len >= ring_desc.len.into() {
+					if write_len >= ring_desc.len.to_ne() {
 						// Complete length has been written but reduce len_written for next one
-						write_len -= ring_desc.len.get();
+						write_len -= ring_desc.len.to_ne();
 					} else {
 						ring_desc.len = (write_len).into();
 						desc.len = write_len as usize;
-						write_len -= ring_desc.len.get();
+						write_len -= ring_desc.len.to_ne();
 						assert_eq!(write_len, 0);
 					}
 				}
@@ -597,13 +751,13 @@ impl<'a> ReadCtrl<'a> {
 					// Unwrapping is fine here, as lists must be of same size and same ordering
 					let ring_desc = desc_iter.next().unwrap();
 
-					if write_len >= ring_desc.len.into() {
+					if write_len >= ring_desc.len.to_ne() {
 						// Complete length has been written but reduce len_written for next one
-						write_len -= ring_desc.len.get();
+						write_len -= ring_desc.len.to_ne();
 					} else {
 						ring_desc.len = write_len.into();
 						desc.len = write_len as usize;
-						write_len -= ring_desc.len.get();
+						write_len -= ring_desc.len.to_ne();
 						assert_eq!(write_len, 0);
 					}
 				}
@@ -734,9 +888,7 @@ impl<'a> WriteCtrl<'a> {
 		// descriptor.
 		if self.start == self.position {
 			let desc_ref = &mut self.desc_ring.ring[self.position];
-			desc_ref
-				.address
-				.set(paging::virt_to_phys(VirtAddr::from(mem_desc.ptr as u64)).into());
+			desc_ref.address = u64::from(phys_addr(mem_desc.ptr)).into();
 			desc_ref.len = (mem_desc.len as u32).into();
 			desc_ref.buff_id = (mem_desc.id.as_ref().unwrap().0).into();
 			// Remove possibly set avail and used flags
@@ -748,9 +900,7 @@ impl<'a> WriteCtrl<'a> {
 			self.incrmt();
 		} else {
 			let desc_ref = &mut self.desc_ring.ring[self.position];
-			desc_ref
-				.address
-				.set(paging::virt_to_phys(VirtAddr::from(mem_desc.ptr as u64)).into());
+			desc_ref.address = u64::from(phys_addr(mem_desc.ptr)).into();
 			desc_ref.len = (mem_desc.len as u32).into();
 			desc_ref.buff_id = (self.buff_id).into();
 			// Remove possibly set avail and used flags and then set avail and used
@@ -771,21 +921,26 @@ impl<'a> WriteCtrl<'a> {
 		assert!(self.buff_id != 0);
 
 		// provide reference, in order to let TransferToken know upon finish.
-		self.desc_ring.tkn_ref_ring[usize::from(self.buff_id)] = Some(raw_tkn);
+		self.desc_ring.tkn_ref_ring.insert(self.buff_id, raw_tkn);
 		// The driver performs a suitable memory barrier to ensure the device sees the updated descriptor table and available ring before the next step.
 		// See Virtio specfification v1.1. - 2.7.21
 		fence(Ordering::SeqCst);
-		self.desc_ring.ring[self.start].flags |= self.wrap_at_init.as_flags_avail().into();
+		let desc = &mut self.desc_ring.ring[self.start];
+		desc.flags = (desc.flags.to_ne() | self.wrap_at_init.as_flags_avail()).into();
 	}
 }
 
+/// Descriptor of the packed ring, whose fields are stored in little-endian order
+/// as read by the device.
+///
+/// Structure layout see Virtio specification v1.1. - 2.7.13
 #[derive(Clone, Copy)]
 #[repr(C, align(16))]
 struct Descriptor {
-	address: little_endian::U64,
-	len: little_endian::U32,
-	buff_id: little_endian::U16,
-	flags: little_endian::U16,
+	address: le64,
+	len: le32,
+	buff_id: le16,
+	flags: le16,
 }
 
 impl Descriptor {
@@ -797,6 +952,14 @@ impl Descriptor {
 			flags: flags.into(),
 		}
 	}
+
+	/// Returns `true` if the device has marked the descriptor as used, i.e. if
+	/// its avail and used flags both match `wrap_count`.
+	///
+	/// See Virtio specification v1.1. - 2.7.1
+	fn is_used(&self, wrap_count: WrapCount) -> bool {
+		self.flags.to_ne() & WrapCount::flag_mask() == wrap_count.as_flags_used()
+	}
 }
 
 /// Driver and device event suppression struct used in packed virtqueues.
@@ -839,7 +1002,7 @@ struct DrvNotif {
 	/// Indicates if VIRTIO_F_RING_EVENT_IDX has been negotiated
 	f_notif_idx: bool,
 	/// Actual structure to read from, if device wants notifs
-	raw: &'static mut EventSuppr,
+	raw: Box<EventSuppr, DeviceAlloc>,
 }
 
 /// A newtype in order to implement the correct functionality upon
@@ -850,9 +1013,16 @@ struct DevNotif {
 	/// Indicates if VIRTIO_F_RING_EVENT_IDX has been negotiated
 	f_notif_idx: bool,
 	/// Actual structure to read from, if device wants notifs
-	raw: &'static mut EventSuppr,
+	raw: Box<EventSuppr, DeviceAlloc>,
 }
 
+/// Enable events
+const RING_EVENT_FLAGS_ENABLE: u16 = 0x0;
+/// Disable events
+const RING_EVENT_FLAGS_DISABLE: u16 = 0x1;
+/// Enable events for a specific descriptor
+const RING_EVENT_FLAGS_DESC: u16 = 0x2;
+
 impl EventSuppr {
 	/// Returns a zero initialized EventSuppr structure
 	fn new() -> Self {
@@ -861,27 +1031,28 @@ impl EventSuppr {
 }
 
 impl DrvNotif {
-	/// Enables notifications by setting the LSB.
+	/// Enables notifications by unsetting the LSB.
 	/// See Virito specification v1.1. - 2.7.10
 	fn enable_notif(&mut self) {
-		self.raw.flags = 0;
+		self.raw.flags = RING_EVENT_FLAGS_ENABLE;
 	}
 
-	/// Disables notifications by unsetting the LSB.
+	/// Disables notifications by setting the LSB.
 	/// See Virtio specification v1.1. - 2.7.10
 	fn disable_notif(&mut self) {
-		self.raw.flags = 0;
+		self.raw.flags = RING_EVENT_FLAGS_DISABLE;
 	}
 
-	/// Enables a notification by the device for a specific descriptor.
-	fn enable_specific(&mut self, at_offset: u16, at_wrap: u8) {
+	/// Enables a notification by the device for the descriptor at `at_offset`,
+	/// once it has been used with the wrap count `at_wrap`.
+	/// See Virtio specification v1.1. - 2.7.10
+	fn enable_specific(&mut self, at_offset: u16, at_wrap: WrapCount) {
 		// Check if VIRTIO_F_RING_EVENT_IDX has been negotiated
 		if self.f_notif_idx {
-			self.raw.flags |= 1 << 1;
-			// Reset event fields
-			self.raw.event = 0;
-			self.raw.event = at_offset;
-			self.raw.event |= (at_wrap as u16) << 15;
+			self.raw.event = at_offset | u16::from(at_wrap.0) << 15;
+			// The device must not see the flags before the new event.
+			fence(Ordering::SeqCst);
+			self.raw.flags = RING_EVENT_FLAGS_DESC;
 		}
 	}
 }
@@ -892,26 +1063,35 @@ impl DevNotif {
 		self.f_notif_idx = true;
 	}
 
-	/// Reads notification bit (i.e. LSB) and returns value.
-	/// If notifications are enabled returns true, else false.
-	fn is_notif(&self) -> bool {
-		self.raw.flags & (1 << 0) == 0
-	}
-
-	fn is_notif_specfic(&self, next_off: usize, next_wrap: u8) -> bool {
-		if self.f_notif_idx {
-			if self.raw.flags & 1 << 1 == 2 {
-				// as u16 is okay for usize, as size of queue is restricted to 2^15
-				// it is also okay to just loose the upper 8 bits, as we only check the LSB in second clause.
-				let desc_event_off = self.raw.event & !(1 << 15);
-				let desc_event_wrap = (self.raw.event >> 15) as u8;
+	/// Returns `true` if the device wants a notification about the descriptors
+	/// from `start` up to (but excluding) `end`, which have just been made
+	/// available. Both positions consist of the offset and the wrap count of
+	/// the driver at this offset.
+	///
+	/// See Virtio specification v1.1. - 2.7.10 and 2.7.21
+	fn is_notif(&self, start: (usize, WrapCount), end: (usize, WrapCount), size: usize) -> bool {
+		match self.raw.flags {
+			RING_EVENT_FLAGS_DISABLE => false,
+			RING_EVENT_FLAGS_DESC if self.f_notif_idx => {
+				let event = (
+					usize::from(self.raw.event & !(1 << 15)),
+					WrapCount(self.raw.event >> 15 != 0),
+				);
+
+				// Positions, which differ in the wrap count, lie one ring apart.
+				let period = 2 * size;
+				let linear = |(off, wrap): (usize, WrapCount)| {
+					if wrap.0 {
+						off
+					} else {
+						off + size
+					}
+				};
+				let dist = |pos| (linear(pos) + period - linear(start)) % period;
 
-				desc_event_off == next_off as u16 && desc_event_wrap == next_wrap
-			} else {
-				false
+				dist(event) < dist(end)
 			}
-		} else {
-			false
+			_ => true,
 		}
 	}
 }
@@ -937,6 +1117,10 @@ pub struct PackedVq {
 	/// The virtqueues index. This identifies the virtqueue to the
 	/// device and is unique on a per device basis.
 	index: VqIndex,
+	/// Defines, if the driver waits for interrupts or polls the queue
+	mode: Cell<QueueMode>,
+	/// Defines, if the device accepts indirect descriptors
+	indirect: bool,
 }
 
 // Public interface of PackedVq
@@ -945,15 +1129,62 @@ pub struct PackedVq {
 // queue. This could be eased
 impl Virtq for PackedVq {
 	fn enable_notifs(&self) {
-		self.drv_event.borrow_mut().enable_notif();
+		if self.mode.get() == QueueMode::Interrupt {
+			self.drv_event.borrow_mut().enable_notif();
+		}
 	}
 
 	fn disable_notifs(&self) {
 		self.drv_event.borrow_mut().disable_notif();
 	}
 
-	fn poll(&self) {
-		self.descr_ring.borrow_mut().poll();
+	fn set_mode(&self, mode: QueueMode) {
+		self.mode.set(mode);
+		match mode {
+			QueueMode::Interrupt => self.enable_notifs(),
+			QueueMode::Poll => self.disable_notifs(),
+		}
+	}
+
+	fn mode(&self) -> QueueMode {
+		self.mode.get()
+	}
+
+	fn debug_snapshot(&self) -> VqSnapshot {
+		let descr_ring = self.descr_ring.borrow();
+
+		VqSnapshot {
+			index: self.index.into(),
+			size: self.size.into(),
+			mode: self.mode.get(),
+			ring: RingSnapshot::Packed {
+				write_index: descr_ring.write_index,
+				poll_index: descr_ring.poll_index,
+				drv_wrap_count: descr_ring.drv_wc.0,
+				dev_wrap_count: descr_ring.dev_wc.0,
+			},
+			free_descriptors: descr_ring.capacity,
+			in_flight: descr_ring.tkn_ref_ring.in_flight(),
+		}
+	}
+
+	#[cfg(feature = "virtio-latency")]
+	fn latency_histogram(&self) -> LatencyHistogram {
+		self.descr_ring.borrow().latency
+	}
+
+	fn poll(&self) -> bool {
+		self.descr_ring.borrow_mut().poll()
+	}
+
+	fn poll_bounded(&self, budget: u16) -> bool {
+		let mut descr_ring = self.descr_ring.borrow_mut();
+		for _ in 0..budget {
+			if !descr_ring.poll() {
+				return false;
+			}
+		}
+		true
 	}
 
 	fn dispatch_batch(&self, tkns: Vec<TransferToken>, notif: bool) {
@@ -968,25 +1199,7 @@ impl Virtq for PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() | self.dev_event.is_notif_specfic(next_off, next_wrap) {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_dev((next_off, next_wrap));
 	}
 
 	fn dispatch_batch_await(
@@ -1011,25 +1224,7 @@ impl Virtq for PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_dev((next_off, next_wrap));
 	}
 
 	fn dispatch(&self, tkn: TransferToken, notif: bool) {
@@ -1041,25 +1236,7 @@ impl Virtq for PackedVq {
 				.enable_specific(next_off as u16, next_wrap);
 		}
 
-		if self.dev_event.is_notif() {
-			let index = self.index.0.to_le_bytes();
-			let mut index = index.iter();
-			// Even on 64bit systems this is fine, as we have a queue_size < 2^15!
-			let det_notif_data: u16 = (next_off as u16) >> 1;
-			let flags = (det_notif_data | (u16::from(next_wrap) << 15)).to_le_bytes();
-			let mut flags = flags.iter();
-			let mut notif_data: [u8; 4] = [0, 0, 0, 0];
-
-			for (i, byte) in notif_data.iter_mut().enumerate() {
-				if i < 2 {
-					*byte = *index.next().unwrap();
-				} else {
-					*byte = *flags.next().unwrap();
-				}
-			}
-
-			self.notif_ctrl.notify_dev(&notif_data)
-		}
+		self.notify_dev((next_off, next_wrap));
 	}
 
 	fn index(&self) -> VqIndex {
@@ -1101,38 +1278,7 @@ impl Virtq for PackedVq {
 			vq_handler.set_vq_size(size.0)
 		};
 
-		let descr_ring = RefCell::new(DescriptorRing::new(vq_size));
-		// Allocate heap memory via a vec, leak and cast
-		let _mem_len = core::mem::size_of::<EventSuppr>().align_up(BasePageSize::SIZE as usize);
-
-		let drv_event_ptr =
-			ptr::with_exposed_provenance_mut(crate::mm::allocate(_mem_len, true).0 as usize);
-		let dev_event_ptr =
-			ptr::with_exposed_provenance_mut(crate::mm::allocate(_mem_len, true).0 as usize);
-
-		// Provide memory areas of the queues data structures to the device
-		vq_handler.set_ring_addr(paging::virt_to_phys(VirtAddr::from(
-			descr_ring.borrow().raw_addr() as u64,
-		)));
-		// As usize is safe here, as the *mut EventSuppr raw pointer is a thin pointer of size usize
-		vq_handler.set_drv_ctrl_addr(paging::virt_to_phys(VirtAddr::from(drv_event_ptr as u64)));
-		vq_handler.set_dev_ctrl_addr(paging::virt_to_phys(VirtAddr::from(dev_event_ptr as u64)));
-
-		let drv_event: &'static mut EventSuppr = unsafe { &mut *(drv_event_ptr) };
-
-		let dev_event: &'static mut EventSuppr = unsafe { &mut *(dev_event_ptr) };
-
-		let drv_event = RefCell::new(DrvNotif {
-			f_notif_idx: false,
-			raw: drv_event,
-		});
-
-		let dev_event = DevNotif {
-			f_notif_idx: false,
-			raw: dev_event,
-		};
-
-		let mut notif_ctrl = NotifCtrl::new(ptr::with_exposed_provenance_mut(
+		let mut notif_ctrl = notif_cfg.notif_ctrl(ptr::with_exposed_provenance_mut(
 			notif_cfg.base()
 				+ usize::from(vq_handler.notif_off())
 				+ usize::try_from(notif_cfg.multiplier()).unwrap(),
@@ -1142,26 +1288,24 @@ impl Virtq for PackedVq {
 			notif_ctrl.enable_notif_data();
 		}
 
-		if features.contains(virtio_spec::F::EVENT_IDX) {
-			drv_event.borrow_mut().f_notif_idx = true;
-		}
+		let mut vq = Self::with_notif_ctrl(
+			vq_size,
+			index,
+			notif_ctrl,
+			features.contains(virtio_spec::F::EVENT_IDX),
+		)?;
+		vq.indirect = features.contains(virtio_spec::F::INDIRECT_DESC);
 
-		// Initialize new memory pool.
-		let mem_pool = Rc::new(MemPool::new(vq_size));
+		// Provide memory areas of the queues data structures to the device
+		vq_handler.set_ring_addr(phys_addr(vq.descr_ring.borrow().ring.as_ptr()));
+		vq_handler.set_drv_ctrl_addr(phys_addr(ptr::from_ref(&*vq.drv_event.borrow().raw)));
+		vq_handler.set_dev_ctrl_addr(phys_addr(ptr::from_ref(&*vq.dev_event.raw)));
 
 		vq_handler.enable_queue();
 
 		info!("Created PackedVq: idx={}, size={}", index.0, vq_size);
 
-		Ok(PackedVq {
-			descr_ring,
-			drv_event,
-			dev_event,
-			notif_ctrl,
-			mem_pool,
-			size: VqSize::from(vq_size),
-			index,
-		})
+		Ok(vq)
 	}
 
 	fn prep_transfer_from_raw(
@@ -1186,11 +1330,68 @@ impl Virtq for PackedVq {
 	}
 }
 
+impl PackedVq {
+	/// Allocates the rings of a queue with `size` entries, which notifies its
+	/// device by `notif_ctrl`. The rings still have to be provided to the device.
+	fn with_notif_ctrl(
+		size: u16,
+		index: VqIndex,
+		notif_ctrl: NotifCtrl,
+		f_notif_idx: bool,
+	) -> Result<Self, VirtqError> {
+		let descr_ring = RefCell::new(DescriptorRing::new(size)?);
+
+		let drv_event = RefCell::new(DrvNotif {
+			f_notif_idx,
+			raw: Box::new_in(EventSuppr::new(), DeviceAlloc),
+		});
+
+		let dev_event = DevNotif {
+			f_notif_idx,
+			raw: Box::new_in(EventSuppr::new(), DeviceAlloc),
+		};
+
+		// Initialize new memory pool.
+		let mem_pool = Rc::new(MemPool::new(size));
+
+		Ok(PackedVq {
+			descr_ring,
+			drv_event,
+			dev_event,
+			notif_ctrl,
+			mem_pool,
+			size: VqSize::from(size),
+			index,
+			mode: Cell::new(QueueMode::default()),
+			indirect: false,
+		})
+	}
+
+	/// Notifies the device about the descriptors, which have been made available
+	/// from `start` up to the write index, if the device wants a notification.
+	fn notify_dev(&self, start: (usize, WrapCount)) {
+		// The driver reads the device event suppression after making the descriptors available.
+		// See Virtio specfification v1.1. - 2.7.21
+		fence(Ordering::SeqCst);
+
+		let descr_ring = self.descr_ring.borrow();
+		let end = (descr_ring.write_index, descr_ring.drv_wc);
+		if self.dev_event.is_notif(start, end, descr_ring.ring.len()) {
+			let notif_data = descr_ring.notif_data(self.index);
+			self.notif_ctrl.notify_dev(&notif_data)
+		}
+	}
+}
+
 impl VirtqPrivate for PackedVq {
 	fn mem_pool(&self) -> Rc<MemPool> {
 		self.mem_pool.clone()
 	}
 
+	fn indirect_supported(&self) -> bool {
+		self.indirect
+	}
+
 	fn create_indirect_ctrl(
 		&self,
 		send: Option<&Vec<MemDescr>>,
@@ -1214,73 +1415,324 @@ impl VirtqPrivate for PackedVq {
 			Err(vq_err) => return Err(vq_err),
 		};
 
-		// For indexing into the allocated memory area. This reduces the
-		// function to only iterate over the MemDescr once and not twice
-		// as otherwise needed if the raw descriptor bytes were to be stored
-		// in an array.
-		let mut crtl_desc_iter = 0usize;
-
 		let desc_slice = unsafe {
 			let size = core::mem::size_of::<Descriptor>();
 			core::slice::from_raw_parts_mut(ctrl_desc.ptr as *mut Descriptor, ctrl_desc.len / size)
 		};
+		fill_indirect_table(desc_slice, send, recv);
 
-		match (send, recv) {
-			(None, None) => Err(VirtqError::BufferNotSpecified),
-			// Only recving descriptorsn (those are writabel by device)
-			(None, Some(recv_desc_lst)) => {
-				for desc in recv_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						DescrFlags::VIRTQ_DESC_F_WRITE.into(),
-					);
-
-					crtl_desc_iter += 1;
-				}
-				Ok(ctrl_desc)
-			}
-			// Only sending descriptors
-			(Some(send_desc_lst), None) => {
-				for desc in send_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						0,
-					);
-
-					crtl_desc_iter += 1;
-				}
-				Ok(ctrl_desc)
-			}
-			(Some(send_desc_lst), Some(recv_desc_lst)) => {
-				// Send descriptors ALWAYS before receiving ones.
-				for desc in send_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						0,
-					);
-
-					crtl_desc_iter += 1;
-				}
+		Ok(ctrl_desc)
+	}
+}
 
-				for desc in recv_desc_lst {
-					desc_slice[crtl_desc_iter] = Descriptor::new(
-						paging::virt_to_phys(VirtAddr::from(desc.ptr as u64)).into(),
-						desc.len as u32,
-						0,
-						DescrFlags::VIRTQ_DESC_F_WRITE.into(),
-					);
+/// Writes the descriptors of an indirect table, the send descriptors always
+/// before the receiving ones.
+///
+/// In contrast to split virtqueues, the descriptors of a packed indirect table
+/// are not chained. The device reads them consecutively and `VIRTQ_DESC_F_WRITE`
+/// is the only valid flag (see Virtio specification v1.2 - 2.8.19).
+fn fill_indirect_table(
+	table: &mut [Descriptor],
+	send: Option<&Vec<MemDescr>>,
+	recv: Option<&Vec<MemDescr>>,
+) {
+	let send = send.into_iter().flatten().map(|desc| (desc, 0));
+	let recv = recv
+		.into_iter()
+		.flatten()
+		.map(|desc| (desc, u16::from(DescrFlags::VIRTQ_DESC_F_WRITE)));
+
+	for (entry, (desc, flags)) in table.iter_mut().zip(send.chain(recv)) {
+		*entry = Descriptor::new(phys_addr(desc.ptr).into(), desc.len as u32, 0, flags);
+	}
+}
 
-					crtl_desc_iter += 1;
-				}
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+	use crate::arch::mm::paging;
+	#[cfg(not(feature = "pci"))]
+	use crate::drivers::virtio::transport::mmio;
+	#[cfg(feature = "pci")]
+	use crate::drivers::virtio::transport::pci;
+	use crate::drivers::virtio::virtqueue::{Dealloc, MemDescrId};
+
+	#[test]
+	fn descriptor_ring_rejects_zero_size() {
+		assert!(matches!(
+			DescriptorRing::checked_size(0),
+			Err(VirtqError::QueueSizeNotAllowed(0))
+		));
+		assert!(matches!(
+			DescriptorRing::new(0),
+			Err(VirtqError::QueueSizeNotAllowed(0))
+		));
+	}
 
-				Ok(ctrl_desc)
-			}
+	#[test]
+	#[should_panic(expected = "buffer ID 2 is already in flight")]
+	fn token_ring_rejects_occupied_slot() {
+		let mut tkn_ref_ring = TokenRing::new(4);
+		tkn_ref_ring.insert(2, 1u32);
+		assert_eq!(tkn_ref_ring.in_flight(), 1);
+		assert_eq!(tkn_ref_ring.take(2), Some(1));
+		assert_eq!(tkn_ref_ring.take(2), None);
+		assert_eq!(tkn_ref_ring.take(5), None);
+
+		tkn_ref_ring.insert(2, 2);
+		tkn_ref_ring.insert(2, 3);
+	}
+
+	#[test]
+	fn indirect_table_is_not_chained() {
+		let mut buffers = [[0u8; 16]; 3];
+		let pool = Rc::new(MemPool::new(1));
+		let mut descs: Vec<MemDescr> = buffers
+			.iter_mut()
+			.map(|buffer| MemDescr {
+				ptr: buffer.as_mut_ptr(),
+				len: buffer.len(),
+				_init_len: buffer.len(),
+				_mem_len: buffer.len(),
+				id: None,
+				pool: pool.clone(),
+				dealloc: Dealloc::Not,
+			})
+			.collect();
+		let recv = descs.split_off(1);
+		let send = descs;
+
+		let mut table = [Descriptor::new(0, 0, 0, 0); 3];
+		fill_indirect_table(&mut table, Some(&send), Some(&recv));
+
+		for (entry, desc) in table.iter().zip(send.iter().chain(&recv)) {
+			assert_eq!(entry.address.to_ne(), u64::from(phys_addr(desc.ptr)));
+			assert_eq!(entry.len.to_ne(), 16);
+			assert_eq!(entry.buff_id.to_ne(), 0);
+		}
+		assert_eq!(table[0].flags.to_ne(), 0);
+		for entry in &table[1..] {
+			assert_eq!(entry.flags.to_ne(), DescrFlags::VIRTQ_DESC_F_WRITE);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "virtio-debug")]
+	#[should_panic(expected = "descriptors in use don't end at the write index")]
+	fn check_lost_descriptor() {
+		let mut descr_ring = DescriptorRing::new(4).unwrap();
+		descr_ring.check_invariants();
+
+		descr_ring.capacity -= 1;
+		descr_ring.check_invariants();
+	}
+
+	#[test]
+	fn notification_data_of_next_descriptor() {
+		let mut descr_ring = DescriptorRing::new(8).unwrap();
+		assert_eq!(descr_ring.notif_data(VqIndex::from(3u16)), [3, 0, 0, 0x80]);
+
+		descr_ring.write_index = 5;
+		descr_ring.drv_wc.wrap();
+		assert_eq!(descr_ring.notif_data(VqIndex::from(3u16)), [3, 0, 5, 0]);
+	}
+
+	#[test]
+	fn descriptor_ring_accepts_single_descriptor() {
+		assert!(matches!(DescriptorRing::checked_size(1), Ok(1)));
+	}
+
+	#[test]
+	fn write_physical_descriptor_address() {
+		let offset = paging::PhysOffset::new(0x1000_0000_0000);
+		let mut descr_ring = DescriptorRing::new(4).unwrap();
+
+		let mut buffer = [0u8; 64];
+		let mem_desc = MemDescr {
+			ptr: buffer.as_mut_ptr(),
+			len: buffer.len(),
+			_init_len: buffer.len(),
+			_mem_len: buffer.len(),
+			id: Some(MemDescrId(1)),
+			pool: Rc::new(MemPool::new(1)),
+			dealloc: Dealloc::Not,
+		};
+
+		let mut ctrl = descr_ring.get_write_ctrler();
+		ctrl.write_desc(&mem_desc, DescrFlags::VIRTQ_DESC_F_WRITE.into());
+		let desc = descr_ring.ring[0];
+		assert_eq!(desc.address.to_ne(), buffer.as_ptr() as u64 + offset.get());
+		assert_eq!(desc.len.to_ne(), 64);
+		assert_eq!(desc.buff_id.to_ne(), 1);
+	}
+
+	#[test]
+	fn descriptor_layout_is_little_endian() {
+		let desc = Descriptor::new(0x0102_0304_0506_0708, 0x1112_1314, 0x2122, 0x3132);
+		let bytes = unsafe { core::mem::transmute::<Descriptor, [u8; 16]>(desc) };
+		assert_eq!(
+			bytes,
+			[
+				0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x14, 0x13, 0x12, 0x11, 0x22, 0x21,
+				0x32, 0x31
+			]
+		);
+	}
+
+	#[test]
+	fn descriptor_layout_keeps_high_flag_byte() {
+		// The avail and used flags are stored at bits 7 and 15.
+		let desc = Descriptor::new(0x0102_0304_0506_0708, 0x1112_1314, 0x2122, 0x8080);
+		let bytes = unsafe { core::mem::transmute::<Descriptor, [u8; 16]>(desc) };
+		assert_eq!(
+			bytes,
+			[
+				0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x14, 0x13, 0x12, 0x11, 0x22, 0x21,
+				0x80, 0x80
+			]
+		);
+	}
+
+	/// Creates a queue with `size` entries, which notifies the device by writing to `notif`.
+	fn create_queue(size: u16, notif: &mut usize) -> Rc<PackedVq> {
+		let notif_addr = ptr::from_mut(notif);
+		#[cfg(feature = "pci")]
+		let notif_ctrl = NotifCtrl::Pci(pci::NotifCtrl::new(notif_addr));
+		#[cfg(not(feature = "pci"))]
+		let notif_ctrl = NotifCtrl::Mmio(mmio::NotifCtrl::new(notif_addr));
+		Rc::new(PackedVq::with_notif_ctrl(size, VqIndex(0), notif_ctrl, false).unwrap())
+	}
+
+	#[test]
+	fn chained_recv_descriptors_are_writable() {
+		let mut notif = Box::new(0usize);
+		let vq = create_queue(4, &mut notif);
+
+		let mut recv = [0u8; 8];
+		let (head, tail) = recv.split_at_mut(4);
+		vq.clone()
+			.prep_transfer_from_raw(&[], &[head, tail], BufferType::Direct)
+			.unwrap()
+			.dispatch(false);
+
+		let descr_ring = vq.descr_ring.borrow();
+		let flags = DescrFlags::VIRTQ_DESC_F_NEXT | DescrFlags::VIRTQ_DESC_F_WRITE;
+		assert_eq!(
+			descr_ring.ring[0].flags.to_ne() & !WrapCount::flag_mask(),
+			flags
+		);
+		assert_eq!(
+			descr_ring.ring[1].flags.to_ne() & !WrapCount::flag_mask(),
+			DescrFlags::VIRTQ_DESC_F_WRITE
+		);
+	}
+
+	#[test]
+	fn batch_is_made_available_at_once() {
+		let mut notif = Box::new(usize::MAX);
+		let vq = create_queue(4, &mut notif);
+
+		let send = [1u8; 8];
+		let mut recv = [0u8; 8];
+		let tkns = vec![
+			vq.clone()
+				.prep_transfer_from_raw(&[&send[..]], &[&mut recv[..]], BufferType::Direct)
+				.unwrap(),
+			vq.clone()
+				.prep_transfer_from_raw(&[&send[..]], &[], BufferType::Direct)
+				.unwrap(),
+		];
+		vq.dispatch_batch(tkns, false);
+		assert_ne!(*notif, usize::MAX);
+
+		let descr_ring = vq.descr_ring.borrow();
+		let avail = WrapCount::new().as_flags_avail();
+		// The head of the batch was made available last.
+		assert_eq!(
+			descr_ring.ring[0].flags.to_ne(),
+			avail | DescrFlags::VIRTQ_DESC_F_NEXT
+		);
+		assert_eq!(
+			descr_ring.ring[1].flags.to_ne(),
+			avail | DescrFlags::VIRTQ_DESC_F_WRITE
+		);
+		assert_eq!(descr_ring.ring[2].flags.to_ne(), avail);
+		assert_eq!(descr_ring.write_index, 3);
+		assert_eq!(descr_ring.tkn_ref_ring.in_flight(), 2);
+	}
+
+	#[test]
+	fn driver_event_suppression() {
+		let mut drv_event = DrvNotif {
+			f_notif_idx: true,
+			raw: Box::new_in(EventSuppr::new(), DeviceAlloc),
+		};
+
+		drv_event.disable_notif();
+		assert_eq!(drv_event.raw.flags, RING_EVENT_FLAGS_DISABLE);
+
+		drv_event.enable_specific(5, WrapCount(false));
+		assert_eq!(drv_event.raw.flags, RING_EVENT_FLAGS_DESC);
+		assert_eq!(drv_event.raw.event, 5);
+
+		drv_event.enable_specific(3, WrapCount(true));
+		assert_eq!(drv_event.raw.event, 3 | 1 << 15);
+
+		drv_event.enable_notif();
+		assert_eq!(drv_event.raw.flags, RING_EVENT_FLAGS_ENABLE);
+	}
+
+	#[test]
+	fn device_event_suppression() {
+		let mut dev_event = DevNotif {
+			f_notif_idx: true,
+			raw: Box::new_in(EventSuppr::new(), DeviceAlloc),
+		};
+		let start = (2, WrapCount(true));
+		let end = (6, WrapCount(true));
+		assert!(dev_event.is_notif(start, end, 8));
+
+		dev_event.raw.flags = RING_EVENT_FLAGS_DISABLE;
+		assert!(!dev_event.is_notif(start, end, 8));
+
+		dev_event.raw.flags = RING_EVENT_FLAGS_DESC;
+		dev_event.raw.event = 5 | 1 << 15;
+		assert!(dev_event.is_notif(start, end, 8));
+		dev_event.raw.event = 6 | 1 << 15;
+		assert!(!dev_event.is_notif(start, end, 8));
+		// Same offset, but on the next pass through the ring
+		dev_event.raw.event = 5;
+		assert!(!dev_event.is_notif(start, end, 8));
+
+		// The descriptors wrap around the end of the ring.
+		let start = (6, WrapCount(true));
+		let end = (2, WrapCount(false));
+		dev_event.raw.event = 1;
+		assert!(dev_event.is_notif(start, end, 8));
+		dev_event.raw.event = 1 | 1 << 15;
+		assert!(!dev_event.is_notif(start, end, 8));
+
+		// Without VIRTIO_F_RING_EVENT_IDX, the event is ignored.
+		dev_event.f_notif_idx = false;
+		assert!(dev_event.is_notif(start, end, 8));
+	}
+
+	#[test]
+	fn descriptor_is_used_with_matching_wrap_count() {
+		let flags = u16::from(DescrFlags::VIRTQ_DESC_F_WRITE);
+		let avail =
+			|wrap_count: WrapCount| Descriptor::new(0, 0, 0, flags | wrap_count.as_flags_avail());
+		let used =
+			|wrap_count: WrapCount| Descriptor::new(0, 0, 0, flags | wrap_count.as_flags_used());
+
+		for wrap_count in [WrapCount(true), WrapCount(false)] {
+			let mut other = wrap_count;
+			other.wrap();
+
+			assert!(used(wrap_count).is_used(wrap_count));
+			assert!(!used(other).is_used(wrap_count));
+			assert!(!avail(wrap_count).is_used(wrap_count));
+			assert!(!avail(other).is_used(wrap_count));
 		}
 	}
 }
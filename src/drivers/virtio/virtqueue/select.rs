@@ -0,0 +1,191 @@
+//! Awaiting the first of several transfers, which may belong to different virtqueues.
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use async_channel::RecvError;
+
+use super::error::VirtqError;
+use super::{BufferToken, TransferToken, Virtq, VqIndex};
+
+type Completion<T> = Pin<Box<dyn Future<Output = Result<T, RecvError>>>>;
+
+/// Set of pending completions, each of which is tagged by the index of its queue.
+struct Select<T> {
+	pending: Vec<(VqIndex, Completion<T>)>,
+}
+
+impl<T: 'static> Select<T> {
+	const fn new() -> Self {
+		Self {
+			pending: Vec::new(),
+		}
+	}
+
+	fn push(&mut self, index: VqIndex, receiver: async_channel::Receiver<T>) {
+		self.pending
+			.push((index, Box::pin(async move { receiver.recv().await })));
+	}
+
+	/// Returns the first finished completion and removes it from the set.
+	///
+	/// A completion, which finished before, is returned without waiting.
+	fn poll_first(&mut self, cx: &mut Context<'_>) -> Poll<Result<(VqIndex, T), VirtqError>> {
+		for i in 0..self.pending.len() {
+			let (index, completion) = &mut self.pending[i];
+			if let Poll::Ready(result) = completion.as_mut().poll(cx) {
+				let index = *index;
+				self.pending.swap_remove(i);
+				return Poll::Ready(
+					result
+						.map(|value| (index, value))
+						.map_err(|_| VirtqError::General),
+				);
+			}
+		}
+
+		Poll::Pending
+	}
+}
+
+/// Awaits the first of several in-flight transfers, which may be dispatched
+/// on different virtqueues.
+///
+/// Awaiting the set returns the [BufferToken] of the first finished transfer
+/// together with the index of its queue and removes the transfer from the set.
+/// The set can be awaited again for the remaining transfers:
+///
+/// ```ignore
+/// let mut select = VirtqSelect::new();
+/// select.dispatch(rx_transfer, false);
+/// select.dispatch(ctrl_transfer, false);
+/// let (index, buff_tkn) = (&mut select).await?;
+/// ```
+///
+/// Besides waiting for a wakeup, each poll checks the involved queues for used
+/// buffers. Hence, queues in [QueueMode::Poll](super::QueueMode::Poll) make
+/// progress as well.
+pub struct VirtqSelect {
+	select: Select<Box<BufferToken>>,
+	queues: Vec<Rc<dyn Virtq>>,
+}
+
+impl VirtqSelect {
+	pub const fn new() -> Self {
+		Self {
+			select: Select::new(),
+			queues: Vec::new(),
+		}
+	}
+
+	/// Dispatches `tkn` to its queue and adds the transfer to the set.
+	pub fn dispatch(&mut self, tkn: TransferToken, notif: bool) {
+		let vq = tkn.get_vq();
+		let (sender, receiver) = async_channel::bounded(1);
+
+		self.select.push(vq.index(), receiver);
+		if !self.queues.iter().any(|queue| queue.index() == vq.index()) {
+			self.queues.push(vq);
+		}
+		tkn.dispatch_await(sender, notif);
+	}
+
+	/// Returns `true`, if no transfer is in flight.
+	pub fn is_empty(&self) -> bool {
+		self.select.pending.is_empty()
+	}
+
+	/// Returns the number of in-flight transfers.
+	pub fn len(&self) -> usize {
+		self.select.pending.len()
+	}
+}
+
+impl Default for VirtqSelect {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Future for VirtqSelect {
+	type Output = Result<(VqIndex, Box<BufferToken>), VirtqError>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		if self.select.pending.is_empty() {
+			return Poll::Ready(Err(VirtqError::General));
+		}
+
+		if let Poll::Ready(result) = self.select.poll_first(cx) {
+			return Poll::Ready(result);
+		}
+
+		for queue in self.queues.iter() {
+			queue.poll();
+		}
+		self.select.poll_first(cx)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::sync::Arc;
+	use core::task::Waker;
+
+	use super::*;
+	use crate::executor::WakeCounter;
+
+	#[test]
+	fn return_completion_before_await() {
+		let mut select = Select::new();
+		let (rx_sender, rx_receiver) = async_channel::bounded(1);
+		let (_ctrl_sender, ctrl_receiver) = async_channel::bounded::<u32>(1);
+		select.push(VqIndex::from(0u16), rx_receiver);
+		select.push(VqIndex::from(2u16), ctrl_receiver);
+
+		rx_sender.try_send(7).unwrap();
+		let mut cx = Context::from_waker(Waker::noop());
+		match select.poll_first(&mut cx) {
+			Poll::Ready(Ok((index, value))) => {
+				assert_eq!(index, VqIndex::from(0u16));
+				assert_eq!(value, 7);
+			}
+			_ => panic!("completion isn't returned"),
+		}
+		assert_eq!(select.pending.len(), 1);
+	}
+
+	#[test]
+	fn wake_on_first_completion() {
+		let mut select = Select::new();
+		let (rx_sender, rx_receiver) = async_channel::bounded(1);
+		let (ctrl_sender, ctrl_receiver) = async_channel::bounded(1);
+		select.push(VqIndex::from(0u16), rx_receiver);
+		select.push(VqIndex::from(2u16), ctrl_receiver);
+
+		let counter = Arc::new(WakeCounter::default());
+		let waker = Waker::from(counter.clone());
+		let mut cx = Context::from_waker(&waker);
+		assert!(select.poll_first(&mut cx).is_pending());
+
+		ctrl_sender.try_send(3u32).unwrap();
+		assert!(counter.count() > 0);
+		match select.poll_first(&mut cx) {
+			Poll::Ready(Ok((index, value))) => {
+				assert_eq!(index, VqIndex::from(2u16));
+				assert_eq!(value, 3);
+			}
+			_ => panic!("completion isn't returned"),
+		}
+
+		drop(rx_sender);
+		assert!(matches!(
+			select.poll_first(&mut cx),
+			Poll::Ready(Err(VirtqError::General))
+		));
+		assert!(select.pending.is_empty());
+	}
+}
@@ -5,23 +5,25 @@
 use alloc::boxed::Box;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
-use core::alloc::{Allocator, Layout};
-use core::cell::{RefCell, UnsafeCell};
+use core::alloc::{AllocError, Allocator, Layout};
+use core::cell::{Cell, RefCell, UnsafeCell};
 use core::mem::{size_of, MaybeUninit};
 use core::ptr::{self, NonNull};
 
+use align_address::Align;
 use virtio_spec::{le16, le32, le64};
 use volatile::access::ReadOnly;
 use volatile::{map_field, VolatilePtr, VolatileRef};
 
-#[cfg(not(feature = "pci"))]
-use super::super::transport::mmio::{ComCfg, NotifCfg, NotifCtrl};
-#[cfg(feature = "pci")]
-use super::super::transport::pci::{ComCfg, NotifCfg, NotifCtrl};
+#[cfg(feature = "virtio-legacy")]
+use super::super::transport::legacy;
+use super::super::transport::{ComCfg, NotifCfg, NotifCtrl};
 use super::error::VirtqError;
+#[cfg(feature = "virtio-latency")]
+use super::LatencyHistogram;
 use super::{
-	BuffSpec, BufferToken, BufferType, Bytes, DescrFlags, MemDescr, MemPool, TransferToken, Virtq,
-	VirtqPrivate, VqIndex, VqSize,
+	BuffSpec, BufferToken, BufferType, Bytes, DescrFlags, MemDescr, MemPool, QueueMode,
+	RingSnapshot, TransferToken, Virtq, VirtqPrivate, VqIndex, VqSize, VqSnapshot,
 };
 use crate::arch::memory_barrier;
 use crate::arch::mm::{paging, VirtAddr};
@@ -121,9 +123,175 @@ struct UsedElem {
 	len: le32,
 }
 
+/// Allocator of the rings of a split virtqueue
+#[derive(Clone, Copy)]
+enum RingAlloc {
+	/// The ring is a separate allocation of [`DeviceAlloc`].
+	Device,
+	/// The ring is located at the start of the contiguous area of a legacy
+	/// virtqueue, whose layout is given. Freeing the ring frees the whole area.
+	LegacyArea(Layout),
+	/// The ring is located within the area of a legacy virtqueue and is freed with it.
+	LegacyPart,
+}
+
+unsafe impl Allocator for RingAlloc {
+	fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+		match self {
+			Self::Device => DeviceAlloc.allocate(layout),
+			Self::LegacyArea(_) | Self::LegacyPart => Err(AllocError),
+		}
+	}
+
+	unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+		match self {
+			Self::Device => unsafe { DeviceAlloc.deallocate(ptr, layout) },
+			Self::LegacyArea(area_layout) => unsafe { DeviceAlloc.deallocate(ptr, *area_layout) },
+			Self::LegacyPart => {}
+		}
+	}
+}
+
+type Rings = (
+	Box<UnsafeCell<[MaybeUninit<Descriptor>]>, RingAlloc>,
+	Box<UnsafeCell<AvailRing>, RingAlloc>,
+	Box<UnsafeCell<UsedRing>, RingAlloc>,
+);
+
+/// Allocates the descriptor table, the available ring and the used ring separately.
+fn alloc_rings(size: u16) -> Result<Rings, VirtqError> {
+	const ALLOCATOR: RingAlloc = RingAlloc::Device;
+
+	let descr_table_cell = unsafe {
+		core::mem::transmute::<
+			Box<[MaybeUninit<Descriptor>], RingAlloc>,
+			Box<UnsafeCell<[MaybeUninit<Descriptor>]>, RingAlloc>,
+		>(Box::new_uninit_slice_in(size.into(), ALLOCATOR))
+	};
+
+	// The device starts with zeroed indices and flags.
+	let avail_ring_cell = {
+		let ring_and_event_len = usize::from(size) + 1;
+		let allocation = ALLOCATOR
+			.allocate_zeroed(
+				Layout::new::<GenericRing<()>>() // flags
+					.extend(Layout::array::<le16>(ring_and_event_len).unwrap()) // +1 for event
+					.unwrap()
+					.0
+					.pad_to_align(),
+			)
+			.map_err(|_| VirtqError::AllocationError)?;
+		unsafe {
+			Box::from_raw_in(
+				core::ptr::slice_from_raw_parts_mut(allocation.as_mut_ptr(), ring_and_event_len)
+					as *mut UnsafeCell<AvailRing>,
+				ALLOCATOR,
+			)
+		}
+	};
+
+	let used_ring_cell = {
+		let ring_and_event_layout = Layout::array::<UsedElem>(size.into())
+			.unwrap()
+			.extend(Layout::new::<le16>()) // for event
+			.unwrap()
+			.0;
+		let allocation = ALLOCATOR
+			.allocate_zeroed(
+				Layout::new::<GenericRing<()>>()
+					.extend(ring_and_event_layout)
+					.unwrap()
+					.0
+					.pad_to_align(),
+			)
+			.map_err(|_| VirtqError::AllocationError)?;
+		unsafe {
+			Box::from_raw_in(
+				core::ptr::slice_from_raw_parts_mut(
+					allocation.as_mut_ptr(),
+					ring_and_event_layout.size(),
+				) as *mut UnsafeCell<UsedRing>,
+				ALLOCATOR,
+			)
+		}
+	};
+
+	Ok((descr_table_cell, avail_ring_cell, used_ring_cell))
+}
+
+/// Allocates the rings in a single contiguous area, as required by the legacy interface.
+/// The available ring directly follows the descriptor table and the used ring starts
+/// at the next multiple of [`QUEUE_ALIGN`](legacy::QUEUE_ALIGN).
+///
+/// See Virtio specification v1.1. - 2.6.2
+#[cfg(feature = "virtio-legacy")]
+fn alloc_legacy_rings(size: u16) -> Result<Rings, VirtqError> {
+	let descr_table_layout = Layout::array::<Descriptor>(size.into()).unwrap();
+	let avail_ring_and_event_len = usize::from(size) + 1;
+	let avail_ring_layout = Layout::new::<GenericRing<()>>()
+		.extend(Layout::array::<le16>(avail_ring_and_event_len).unwrap())
+		.unwrap()
+		.0;
+	let used_ring_and_event_layout = Layout::array::<UsedElem>(size.into())
+		.unwrap()
+		.extend(Layout::new::<le16>())
+		.unwrap()
+		.0;
+	let used_ring_layout = Layout::new::<GenericRing<()>>()
+		.extend(used_ring_and_event_layout)
+		.unwrap()
+		.0;
+
+	let (descr_and_avail_layout, avail_ring_offset) =
+		descr_table_layout.extend(avail_ring_layout).unwrap();
+	let used_ring_offset = descr_and_avail_layout.size().align_up(legacy::QUEUE_ALIGN);
+	let area_layout = Layout::from_size_align(
+		used_ring_offset + used_ring_layout.size(),
+		legacy::QUEUE_ALIGN,
+	)
+	.unwrap();
+
+	// The device starts with zeroed indices and flags.
+	let area = DeviceAlloc
+		.allocate_zeroed(area_layout)
+		.map_err(|_| VirtqError::AllocationError)?;
+	let base = area.as_mut_ptr();
+
+	unsafe {
+		let descr_table_cell = Box::from_raw_in(
+			ptr::slice_from_raw_parts_mut(base.cast::<MaybeUninit<Descriptor>>(), size.into())
+				as *mut UnsafeCell<[MaybeUninit<Descriptor>]>,
+			RingAlloc::LegacyArea(area_layout),
+		);
+		let avail_ring_cell = Box::from_raw_in(
+			ptr::slice_from_raw_parts_mut(base.add(avail_ring_offset), avail_ring_and_event_len)
+				as *mut UnsafeCell<AvailRing>,
+			RingAlloc::LegacyPart,
+		);
+		let used_ring_cell = Box::from_raw_in(
+			ptr::slice_from_raw_parts_mut(
+				base.add(used_ring_offset),
+				used_ring_and_event_layout.size(),
+			) as *mut UnsafeCell<UsedRing>,
+			RingAlloc::LegacyPart,
+		);
+
+		Ok((descr_table_cell, avail_ring_cell, used_ring_cell))
+	}
+}
+
+#[cfg(not(feature = "virtio-legacy"))]
+fn alloc_legacy_rings(_size: u16) -> Result<Rings, VirtqError> {
+	unreachable!("legacy virtqueues require the feature virtio-legacy")
+}
+
 struct DescrRing {
 	read_idx: u16,
+	/// Maximal number of used elements, which are processed by a single call of [`DescrRing::poll`].
+	poll_budget: u16,
 	token_ring: Box<[Option<Box<TransferToken>>]>,
+	#[cfg(feature = "virtio-latency")]
+	latency: LatencyHistogram,
 
 	/// Descriptor Tables
 	///
@@ -131,9 +299,9 @@ struct DescrRing {
 	///
 	/// These tables may only be accessed via volatile operations.
 	/// See the corresponding method for a safe wrapper.
-	descr_table_cell: Box<UnsafeCell<[MaybeUninit<Descriptor>]>, DeviceAlloc>,
-	avail_ring_cell: Box<UnsafeCell<AvailRing>, DeviceAlloc>,
-	used_ring_cell: Box<UnsafeCell<UsedRing>, DeviceAlloc>,
+	descr_table_cell: Box<UnsafeCell<[MaybeUninit<Descriptor>]>, RingAlloc>,
+	avail_ring_cell: Box<UnsafeCell<AvailRing>, RingAlloc>,
+	used_ring_cell: Box<UnsafeCell<UsedRing>, RingAlloc>,
 }
 
 impl DescrRing {
@@ -147,7 +315,45 @@ impl DescrRing {
 		unsafe { VolatileRef::new_read_only(NonNull::new(self.used_ring_cell.get()).unwrap()) }
 	}
 
-	fn push(&mut self, tkn: TransferToken) -> (u16, u16) {
+	/// Checks the invariants of the ring, if the feature `virtio-debug` is enabled.
+	/// Otherwise, the checks are compiled out.
+	///
+	/// * The available elements from `read_idx` up to the index of the available
+	///   ring don't exceed the size of the ring.
+	/// * Each available element has a transfer in flight. Hence, no transfer of the
+	///   token ring was overwritten or lost.
+	/// * The device didn't use more elements than the driver made available.
+	///
+	/// Panics with a description of the violated invariant.
+	#[cfg(feature = "virtio-debug")]
+	fn check_invariants(&mut self) {
+		let size = self.token_ring.len();
+		let available = usize::from(self.avail_idx().wrapping_sub(self.read_idx));
+		assert!(
+			available <= size,
+			"{available} available elements exceed the ring of size {size}"
+		);
+
+		let in_flight = self.token_ring.iter().filter(|tkn| tkn.is_some()).count();
+		assert_eq!(
+			in_flight, available,
+			"{in_flight} transfers in flight, but {available} elements available"
+		);
+
+		let used = usize::from(self.used_idx().wrapping_sub(self.read_idx));
+		assert!(
+			used <= available,
+			"device used {used} elements, but only {available} are available"
+		);
+	}
+
+	#[cfg(not(feature = "virtio-debug"))]
+	#[inline(always)]
+	fn check_invariants(&mut self) {}
+
+	fn push(&mut self, mut tkn: TransferToken) -> (u16, u16) {
+		tkn.stamp();
+
 		let mut desc_lst = Vec::new();
 		let mut is_indirect = false;
 
@@ -266,37 +472,53 @@ impl DescrRing {
 		let avail_ring = avail_ring_ref.as_mut_ptr();
 		let idx = map_field!(avail_ring.index).read().to_ne();
 		AvailRing::ring_ptr(avail_ring)
-			.index(idx as usize % len)
+			.index(ring_slot(idx, len))
 			.write(MaybeUninit::new((index as u16).into()));
 
 		memory_barrier();
 		map_field!(avail_ring.index).update(|val| (val.to_ne().wrapping_add(1)).into());
+		self.check_invariants();
 
 		(0, 0)
 	}
 
-	fn poll(&mut self) {
-		// We cannot use a simple while loop here because Rust cannot tell that [Self::used_ring_ref],
-		// [Self::read_idx] and [Self::token_ring] access separate fields of `self`. For this reason we
-		// need to move [Self::used_ring_ref] lines into a separate scope.
-		loop {
-			let used_elem;
-			{
+	fn avail_idx(&mut self) -> u16 {
+		let mut avail_ring_ref = self.avail_ring_ref();
+		let avail_ring = avail_ring_ref.as_mut_ptr();
+		map_field!(avail_ring.index).read().to_ne()
+	}
+
+	fn used_idx(&self) -> u16 {
+		let used_ring_ref = self.used_ring_ref();
+		let used_ring = used_ring_ref.as_ptr();
+		map_field!(used_ring.index).read().to_ne()
+	}
+
+	/// Processes at most `budget` used elements.
+	///
+	/// Returns `true` if the device has written further used elements, which
+	/// have not been processed yet. These are left for the next call, in order to
+	/// prevent a flooding device from starving other tasks. The same applies, if
+	/// the `await_queue` of a transfer is full, until its consumer drains it.
+	fn poll(&mut self, budget: u16) -> bool {
+		let count = poll_count(self.read_idx, self.used_idx(), budget);
+
+		for _ in 0..count {
+			let used_elem = {
 				let used_ring_ref = self.used_ring_ref();
 				let used_ring = used_ring_ref.as_ptr();
-				if self.read_idx == map_field!(used_ring.index).read().to_ne() {
-					break;
-				} else {
-					let cur_ring_index = self.read_idx as usize % self.token_ring.len();
-					used_elem = UsedRing::ring_ptr(used_ring).index(cur_ring_index).read();
-				}
+				let cur_ring_index = ring_slot(self.read_idx, self.token_ring.len());
+				UsedRing::ring_ptr(used_ring).index(cur_ring_index).read()
+			};
+
+			let slot = &mut self.token_ring[used_elem.id.to_ne() as usize];
+			if slot.as_ref().is_some_and(|tkn| tkn.is_blocked()) {
+				break;
 			}
 
-			let mut tkn = self.token_ring[used_elem.id.to_ne() as usize]
-				.take()
-				.expect(
-					"The buff_id is incorrect or the reference to the TransferToken was misplaced.",
-				);
+			let mut tkn = slot.take().expect(
+				"The buff_id is incorrect or the reference to the TransferToken was misplaced.",
+			);
 
 			if tkn.buff_tkn.as_ref().unwrap().recv_buff.as_ref().is_some() {
 				tkn.buff_tkn
@@ -305,12 +527,15 @@ impl DescrRing {
 					.restr_size(None, Some(used_elem.len.to_ne() as usize))
 					.unwrap();
 			}
-			if let Some(queue) = tkn.await_queue.take() {
-				queue.try_send(Box::new(tkn.buff_tkn.unwrap())).unwrap()
-			}
+			#[cfg(feature = "virtio-latency")]
+			self.latency.record(tkn.latency());
+			tkn.complete();
 			memory_barrier();
 			self.read_idx = self.read_idx.wrapping_add(1);
 		}
+		self.check_invariants();
+
+		self.read_idx != self.used_idx()
 	}
 
 	fn drv_enable_notif(&mut self) {
@@ -332,27 +557,90 @@ impl DescrRing {
 	}
 }
 
+/// Returns the slot of the free running ring index `idx` in a ring with `len` entries.
+///
+/// The index wraps around at `u16::MAX`. The mapping stays continuous across this
+/// boundary only, if `len` divides 2^16. Hence, the size of a split virtqueue has
+/// to be a power of two (see Virtio specification v1.1. - 2.6).
+fn ring_slot(idx: u16, len: usize) -> usize {
+	debug_assert!(len.is_power_of_two());
+	usize::from(idx) % len
+}
+
+/// Returns the number of used elements between `read_idx` and `used_idx`, which may
+/// be processed within the given `budget`.
+///
+/// Both indices are free running and wrap around at `u16::MAX`.
+fn poll_count(read_idx: u16, used_idx: u16, budget: u16) -> u16 {
+	used_idx.wrapping_sub(read_idx).min(budget)
+}
+
 /// Virtio's split virtqueue structure
 pub struct SplitVq {
 	ring: RefCell<DescrRing>,
 	mem_pool: Rc<MemPool>,
 	size: VqSize,
 	index: VqIndex,
+	mode: Cell<QueueMode>,
+	/// Whether the device accepts indirect descriptors
+	indirect: bool,
 
 	notif_ctrl: NotifCtrl,
 }
 
 impl Virtq for SplitVq {
 	fn enable_notifs(&self) {
-		self.ring.borrow_mut().drv_enable_notif();
+		if self.mode.get() == QueueMode::Interrupt {
+			self.ring.borrow_mut().drv_enable_notif();
+		}
 	}
 
 	fn disable_notifs(&self) {
 		self.ring.borrow_mut().drv_disable_notif();
 	}
 
-	fn poll(&self) {
-		self.ring.borrow_mut().poll()
+	fn set_mode(&self, mode: QueueMode) {
+		self.mode.set(mode);
+		match mode {
+			QueueMode::Interrupt => self.enable_notifs(),
+			QueueMode::Poll => self.disable_notifs(),
+		}
+	}
+
+	fn mode(&self) -> QueueMode {
+		self.mode.get()
+	}
+
+	fn debug_snapshot(&self) -> VqSnapshot {
+		let mut ring = self.ring.borrow_mut();
+
+		VqSnapshot {
+			index: self.index.into(),
+			size: self.size.into(),
+			mode: self.mode.get(),
+			ring: RingSnapshot::Split {
+				avail_idx: ring.avail_idx(),
+				used_idx: ring.used_idx(),
+				read_idx: ring.read_idx,
+			},
+			free_descriptors: self.mem_pool.pool.borrow().len(),
+			in_flight: ring.token_ring.iter().filter(|tkn| tkn.is_some()).count(),
+		}
+	}
+
+	#[cfg(feature = "virtio-latency")]
+	fn latency_histogram(&self) -> LatencyHistogram {
+		self.ring.borrow().latency
+	}
+
+	fn poll(&self) -> bool {
+		let mut ring = self.ring.borrow_mut();
+		let budget = ring.poll_budget;
+		ring.poll(budget)
+	}
+
+	fn poll_bounded(&self, budget: u16) -> bool {
+		self.ring.borrow_mut().poll(budget)
 	}
 
 	fn dispatch_batch(&self, _tkns: Vec<TransferToken>, _notif: bool) {
@@ -409,6 +697,8 @@ impl Virtq for SplitVq {
 		index: VqIndex,
 		features: virtio_spec::F,
 	) -> Result<Self, VirtqError> {
+		let legacy = com_cfg.is_legacy();
+
 		// Get a handler to the queues configuration area.
 		let mut vq_handler = match com_cfg.select_vq(index.into()) {
 			Some(handler) => handler,
@@ -416,86 +706,12 @@ impl Virtq for SplitVq {
 		};
 
 		let size = vq_handler.set_vq_size(size.0);
-		const ALLOCATOR: DeviceAlloc = DeviceAlloc;
-
-		let descr_table_cell = unsafe {
-			core::mem::transmute::<
-				Box<[MaybeUninit<Descriptor>], DeviceAlloc>,
-				Box<UnsafeCell<[MaybeUninit<Descriptor>]>, DeviceAlloc>,
-			>(Box::new_uninit_slice_in(size.into(), ALLOCATOR))
-		};
-
-		let avail_ring_cell = {
-			let ring_and_event_len = usize::from(size) + 1;
-			let allocation = ALLOCATOR
-				.allocate(
-					Layout::new::<GenericRing<()>>() // flags
-						.extend(Layout::array::<le16>(ring_and_event_len).unwrap()) // +1 for event
-						.unwrap()
-						.0
-						.pad_to_align(),
-				)
-				.map_err(|_| VirtqError::AllocationError)?;
-			unsafe {
-				Box::from_raw_in(
-					core::ptr::slice_from_raw_parts_mut(allocation.as_mut_ptr(), ring_and_event_len)
-						as *mut UnsafeCell<AvailRing>,
-					ALLOCATOR,
-				)
-			}
-		};
-
-		let used_ring_cell = {
-			let ring_and_event_layout = Layout::array::<UsedElem>(size.into())
-				.unwrap()
-				.extend(Layout::new::<le16>()) // for event
-				.unwrap()
-				.0;
-			let allocation = ALLOCATOR
-				.allocate(
-					Layout::new::<GenericRing<()>>()
-						.extend(ring_and_event_layout)
-						.unwrap()
-						.0
-						.pad_to_align(),
-				)
-				.map_err(|_| VirtqError::AllocationError)?;
-			unsafe {
-				Box::from_raw_in(
-					core::ptr::slice_from_raw_parts_mut(
-						allocation.as_mut_ptr(),
-						ring_and_event_layout.size(),
-					) as *mut UnsafeCell<UsedRing>,
-					ALLOCATOR,
-				)
-			}
-		};
-
-		// Provide memory areas of the queues data structures to the device
-		vq_handler.set_ring_addr(paging::virt_to_phys(VirtAddr::from(
-			ptr::from_ref(descr_table_cell.as_ref()).expose_provenance(),
-		)));
-		// As usize is safe here, as the *mut EventSuppr raw pointer is a thin pointer of size usize
-		vq_handler.set_drv_ctrl_addr(paging::virt_to_phys(VirtAddr::from(
-			ptr::from_ref(avail_ring_cell.as_ref()).expose_provenance(),
-		)));
-		vq_handler.set_dev_ctrl_addr(paging::virt_to_phys(VirtAddr::from(
-			ptr::from_ref(used_ring_cell.as_ref()).expose_provenance(),
-		)));
-
-		let descr_ring = DescrRing {
-			read_idx: 0,
-			token_ring: core::iter::repeat_with(|| None)
-				.take(size.into())
-				.collect::<Vec<_>>()
-				.into_boxed_slice(),
-
-			descr_table_cell,
-			avail_ring_cell,
-			used_ring_cell,
-		};
+		// Otherwise, the slots of the avail and used ring are not continuous, when the ring indices wrap around.
+		if !size.is_power_of_two() {
+			return Err(VirtqError::QueueSizeNotAllowed(size));
+		}
 
-		let mut notif_ctrl = NotifCtrl::new(ptr::with_exposed_provenance_mut(
+		let mut notif_ctrl = notif_cfg.notif_ctrl(ptr::with_exposed_provenance_mut(
 			notif_cfg.base()
 				+ usize::from(vq_handler.notif_off())
 				+ usize::try_from(notif_cfg.multiplier()).unwrap(),
@@ -505,20 +721,29 @@ impl Virtq for SplitVq {
 			notif_ctrl.enable_notif_data();
 		}
 
-		// Initialize new memory pool.
-		let mem_pool = Rc::new(MemPool::new(size));
+		let mut vq = Self::with_notif_ctrl(size, index, notif_ctrl, legacy)?;
+		vq.indirect = features.contains(virtio_spec::F::INDIRECT_DESC);
+
+		// Provide memory areas of the queues data structures to the device
+		{
+			let ring = vq.ring.borrow();
+			vq_handler.set_ring_addr(paging::virt_to_phys(VirtAddr::from(
+				ptr::from_ref(ring.descr_table_cell.as_ref()).expose_provenance(),
+			)));
+			// As usize is safe here, as the *mut EventSuppr raw pointer is a thin pointer of size usize
+			vq_handler.set_drv_ctrl_addr(paging::virt_to_phys(VirtAddr::from(
+				ptr::from_ref(ring.avail_ring_cell.as_ref()).expose_provenance(),
+			)));
+			vq_handler.set_dev_ctrl_addr(paging::virt_to_phys(VirtAddr::from(
+				ptr::from_ref(ring.used_ring_cell.as_ref()).expose_provenance(),
+			)));
+		}
 
 		vq_handler.enable_queue();
 
 		info!("Created SplitVq: idx={}, size={}", index.0, size);
 
-		Ok(SplitVq {
-			ring: RefCell::new(descr_ring),
-			notif_ctrl,
-			mem_pool,
-			size: VqSize(size),
-			index,
-		})
+		Ok(vq)
 	}
 
 	fn prep_transfer_from_raw(
@@ -543,6 +768,58 @@ impl Virtq for SplitVq {
 	}
 }
 
+impl SplitVq {
+	/// Allocates the rings of a queue with `size` entries, which notifies its
+	/// device by `notif_ctrl`. The rings still have to be provided to the device.
+	fn with_notif_ctrl(
+		size: u16,
+		index: VqIndex,
+		notif_ctrl: NotifCtrl,
+		legacy: bool,
+	) -> Result<Self, VirtqError> {
+		let (descr_table_cell, avail_ring_cell, used_ring_cell) = if legacy {
+			alloc_legacy_rings(size)?
+		} else {
+			alloc_rings(size)?
+		};
+
+		let descr_ring = DescrRing {
+			read_idx: 0,
+			poll_budget: size,
+			token_ring: core::iter::repeat_with(|| None)
+				.take(size.into())
+				.collect::<Vec<_>>()
+				.into_boxed_slice(),
+			#[cfg(feature = "virtio-latency")]
+			latency: LatencyHistogram::default(),
+
+			descr_table_cell,
+			avail_ring_cell,
+			used_ring_cell,
+		};
+
+		// Initialize new memory pool.
+		let mem_pool = Rc::new(MemPool::new(size));
+
+		Ok(SplitVq {
+			ring: RefCell::new(descr_ring),
+			notif_ctrl,
+			mem_pool,
+			size: VqSize(size),
+			index,
+			mode: Cell::new(QueueMode::default()),
+			indirect: false,
+		})
+	}
+
+	/// Sets the maximal number of used elements, which are processed by a single
+	/// call of [`Virtq::poll`]. Defaults to the size of the queue.
+	pub fn set_poll_budget(&self, budget: u16) {
+		assert!(budget != 0);
+		self.ring.borrow_mut().poll_budget = budget;
+	}
+}
+
 impl VirtqPrivate for SplitVq {
 	fn create_indirect_ctrl(
 		&self,
@@ -680,4 +957,565 @@ impl VirtqPrivate for SplitVq {
 	fn mem_pool(&self) -> Rc<MemPool> {
 		self.mem_pool.clone()
 	}
+
+	fn indirect_supported(&self) -> bool {
+		self.indirect
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+	#[cfg(not(feature = "pci"))]
+	use crate::drivers::virtio::transport::mmio;
+	#[cfg(feature = "pci")]
+	use crate::drivers::virtio::transport::pci;
+
+	/// Device, which processes the descriptor chains of a [`SplitVq`] in memory.
+	///
+	/// The device records the data of all device-readable descriptors and fills
+	/// the device-writable descriptors with a given byte.
+	struct FakeDevice {
+		/// Free running index of the next available element
+		next_avail: u16,
+		/// Notification address of the queue
+		notif: Box<usize>,
+		/// Data of the device-readable descriptors
+		received: Vec<u8>,
+	}
+
+	impl FakeDevice {
+		fn new() -> Self {
+			Self {
+				next_avail: 0,
+				notif: Box::new(usize::MAX),
+				received: Vec::new(),
+			}
+		}
+
+		/// Creates a queue with `size` entries, which notifies this device.
+		fn create_queue(&mut self, size: u16) -> Rc<SplitVq> {
+			Rc::new(self.new_queue(size))
+		}
+
+		/// Creates a queue with `size` entries, which accepts indirect descriptors.
+		fn create_indirect_queue(&mut self, size: u16) -> Rc<SplitVq> {
+			let mut vq = self.new_queue(size);
+			vq.indirect = true;
+			Rc::new(vq)
+		}
+
+		fn new_queue(&mut self, size: u16) -> SplitVq {
+			let notif_addr = ptr::from_mut(self.notif.as_mut());
+			#[cfg(feature = "pci")]
+			let notif_ctrl = NotifCtrl::Pci(pci::NotifCtrl::new(notif_addr));
+			#[cfg(not(feature = "pci"))]
+			let notif_ctrl = NotifCtrl::Mmio(mmio::NotifCtrl::new(notif_addr));
+
+			SplitVq::with_notif_ctrl(size, VqIndex(0), notif_ctrl, false).unwrap()
+		}
+
+		/// Returns `true` if the queue has notified the device since the last call.
+		fn take_notification(&mut self) -> bool {
+			core::mem::replace(self.notif.as_mut(), usize::MAX) != usize::MAX
+		}
+
+		/// Processes all available descriptor chains and returns their number.
+		fn run(&mut self, vq: &SplitVq, fill: u8) -> usize {
+			let mut ring = vq.ring.borrow_mut();
+			let len = ring.token_ring.len();
+			let avail_idx = ring.avail_idx();
+			let mut processed = 0;
+
+			while self.next_avail != avail_idx {
+				let head = {
+					let mut avail_ring_ref = ring.avail_ring_ref();
+					let avail_ring = avail_ring_ref.as_mut_ptr();
+					let slot = AvailRing::ring_ptr(avail_ring)
+						.index(ring_slot(self.next_avail, len))
+						.read();
+					unsafe { slot.assume_init() }.to_ne()
+				};
+
+				let mut written = 0;
+				let mut id = head;
+				loop {
+					let descr = ring.descr_table_ref().as_ptr().index(id.into()).read();
+					let descr = unsafe { descr.assume_init() };
+					if descr.flags.to_ne() & DescrFlags::VIRTQ_DESC_F_INDIRECT != 0 {
+						written += self.process_indirect(&descr, fill);
+					} else {
+						written += self.process(&descr, fill);
+					}
+
+					if descr.flags.to_ne() & DescrFlags::VIRTQ_DESC_F_NEXT == 0 {
+						break;
+					}
+					id = descr.next.to_ne();
+				}
+
+				let mut used_ring_ref =
+					unsafe { VolatileRef::new(NonNull::new(ring.used_ring_cell.get()).unwrap()) };
+				let used_ring = used_ring_ref.as_mut_ptr();
+				let used_idx = map_field!(used_ring.index).read().to_ne();
+				UsedRing::ring_ptr(used_ring)
+					.index(ring_slot(used_idx, len))
+					.write(UsedElem {
+						id: u32::from(head).into(),
+						len: written.into(),
+					});
+				map_field!(used_ring.index).write(used_idx.wrapping_add(1).into());
+
+				self.next_avail = self.next_avail.wrapping_add(1);
+				processed += 1;
+			}
+
+			processed
+		}
+
+		/// Processes the chain of an indirect descriptor table.
+		fn process_indirect(&mut self, descr: &Descriptor, fill: u8) -> u32 {
+			let table = unsafe {
+				core::slice::from_raw_parts(
+					ptr::with_exposed_provenance::<Descriptor>(descr.address.to_ne() as usize),
+					descr.len.to_ne() as usize / size_of::<Descriptor>(),
+				)
+			};
+
+			let mut written = 0;
+			let mut id = 0;
+			loop {
+				written += self.process(&table[id], fill);
+				if table[id].flags.to_ne() & DescrFlags::VIRTQ_DESC_F_NEXT == 0 {
+					break;
+				}
+				id = table[id].next.to_ne().into();
+			}
+
+			written
+		}
+
+		/// Reads or writes the buffer of a single descriptor and returns the
+		/// number of written bytes.
+		fn process(&mut self, descr: &Descriptor, fill: u8) -> u32 {
+			let len = descr.len.to_ne();
+			let buffer = unsafe {
+				core::slice::from_raw_parts_mut(
+					ptr::with_exposed_provenance_mut::<u8>(descr.address.to_ne() as usize),
+					len as usize,
+				)
+			};
+
+			if descr.flags.to_ne() & DescrFlags::VIRTQ_DESC_F_WRITE != 0 {
+				buffer.fill(fill);
+				len
+			} else {
+				self.received.extend_from_slice(buffer);
+				0
+			}
+		}
+	}
+
+	#[test]
+	fn chained_buffers_round_trip() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(8);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let send = [1u8, 2, 3, 4];
+		let mut recv = [0u8; 6];
+		vq.clone()
+			.prep_transfer_from_raw(
+				&[&send[..2], &send[2..]],
+				&[&mut recv[..]],
+				BufferType::Direct,
+			)
+			.unwrap()
+			.dispatch_await(sender, false);
+		assert!(device.take_notification());
+		assert_eq!(vq.debug_snapshot().free_descriptors, 5);
+
+		assert_eq!(device.run(&vq, 0xab), 1);
+		assert!(!vq.poll());
+		drop(receiver.try_recv().unwrap());
+
+		assert_eq!(device.received, send);
+		assert_eq!(recv, [0xab; 6]);
+		let snapshot = vq.debug_snapshot();
+		assert_eq!(snapshot.in_flight, 0);
+		assert_eq!(snapshot.free_descriptors, 8);
+	}
+
+	#[test]
+	fn exhausted_chain_returns_descriptor_ids() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(2);
+		let (sender, receiver) = async_channel::unbounded();
+
+		// A chain of three descriptors doesn't fit into a queue of two descriptors.
+		let send = [1u8, 2, 3];
+		let err = vq
+			.clone()
+			.prep_transfer_from_raw(
+				&[&send[..1], &send[1..2], &send[2..]],
+				&[],
+				BufferType::Direct,
+			)
+			.unwrap_err();
+		assert!(matches!(err, VirtqError::NoDescrAvail));
+		assert_eq!(vq.debug_snapshot().free_descriptors, 2);
+
+		// Hence, a chain of two descriptors still fits.
+		vq.clone()
+			.prep_transfer_from_raw(&[&send[..1], &send[1..]], &[], BufferType::Direct)
+			.unwrap()
+			.dispatch_await(sender, false);
+		assert_eq!(device.run(&vq, 0), 1);
+		assert!(!vq.poll());
+		drop(receiver.try_recv().unwrap());
+
+		assert_eq!(device.received, send);
+		assert_eq!(vq.debug_snapshot().free_descriptors, 2);
+	}
+
+	#[test]
+	fn indirect_buffers_round_trip() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_indirect_queue(8);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let send = [1u8, 2, 3, 4];
+		let mut recv = [0u8; 2];
+		vq.clone()
+			.prep_transfer_from_raw(
+				&[&send[..1], &send[1..]],
+				&[&mut recv[..1], &mut []],
+				BufferType::Indirect,
+			)
+			.unwrap_err();
+		vq.clone()
+			.prep_transfer_from_raw(
+				&[&send[..1], &send[1..]],
+				&[&mut recv[..]],
+				BufferType::Indirect,
+			)
+			.unwrap()
+			.dispatch_await(sender, false);
+		// The indirect table occupies a single descriptor of the ring.
+		assert_eq!(vq.debug_snapshot().free_descriptors, 7);
+
+		assert_eq!(device.run(&vq, 0xcd), 1);
+		assert!(!vq.poll());
+		drop(receiver.try_recv().unwrap());
+
+		assert_eq!(device.received, send);
+		assert_eq!(recv, [0xcd; 2]);
+		assert_eq!(vq.debug_snapshot().free_descriptors, 8);
+	}
+
+	/// Without `VIRTIO_F_INDIRECT_DESC`, only direct buffers are dispatched.
+	#[test]
+	fn reject_indirect_buffers_without_feature() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(8);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let send = [1u8, 2, 3, 4];
+		let err = vq
+			.clone()
+			.prep_transfer_from_raw(&[&send[..]], &[], BufferType::Indirect)
+			.unwrap_err();
+		assert!(matches!(
+			err,
+			VirtqError::FeatureNotSupported(feature) if feature == virtio_spec::F::INDIRECT_DESC
+		));
+		let err = vq
+			.clone()
+			.prep_buffer(Some(BuffSpec::Indirect(&[Bytes(4)])), None)
+			.unwrap_err();
+		assert!(matches!(
+			err,
+			VirtqError::FeatureNotSupported(feature) if feature == virtio_spec::F::INDIRECT_DESC
+		));
+		assert_eq!(vq.debug_snapshot().free_descriptors, 8);
+
+		vq.clone()
+			.prep_transfer_from_raw(&[&send[..]], &[], BufferType::Direct)
+			.unwrap()
+			.dispatch_await(sender, false);
+		assert_eq!(device.run(&vq, 0), 1);
+		assert!(!vq.poll());
+		drop(receiver.try_recv().unwrap());
+		assert_eq!(device.received, send);
+	}
+
+	/// A zero-length buffer doesn't allocate memory and may only be dispatched as
+	/// a direct buffer.
+	#[test]
+	fn dispatch_zero_length_buffer() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_indirect_queue(4);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let err = vq
+			.clone()
+			.prep_transfer_from_raw(&[&[]], &[], BufferType::Indirect)
+			.unwrap_err();
+		assert!(matches!(err, VirtqError::BufferSizeZero));
+		let err = vq
+			.clone()
+			.prep_buffer(Some(BuffSpec::Indirect(&[Bytes(0)])), None)
+			.unwrap_err();
+		assert!(matches!(err, VirtqError::BufferSizeZero));
+
+		let tkn = vq
+			.clone()
+			.prep_buffer(Some(BuffSpec::Single(Bytes(0))), None)
+			.unwrap();
+		let Some(super::super::Buffer::Single { desc_lst, .. }) = &tkn.send_buff else {
+			panic!("expected a single descriptor");
+		};
+		assert_eq!(desc_lst[0]._mem_len, 0);
+		assert_eq!(
+			desc_lst[0].ptr.cast_const(),
+			ptr::addr_of!(super::super::ZERO_LENGTH)
+		);
+		tkn.provide().dispatch_await(sender.clone(), false);
+		vq.clone()
+			.prep_transfer_from_raw(&[&[]], &[], BufferType::Direct)
+			.unwrap()
+			.dispatch_await(sender, false);
+
+		assert_eq!(device.run(&vq, 0), 2);
+		assert!(!vq.poll());
+		assert_eq!(receiver.len(), 2);
+		assert!(device.received.is_empty());
+		assert_eq!(vq.debug_snapshot().free_descriptors, 4);
+	}
+
+	#[test]
+	fn used_ring_wraps_around() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(4);
+		let mut recv = [0u8; 8];
+
+		for _ in 0..=u32::from(u16::MAX) + 4 {
+			vq.clone()
+				.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+				.unwrap()
+				.dispatch(false);
+			assert_eq!(device.run(&vq, 0xef), 1);
+			assert!(!vq.poll());
+		}
+
+		let snapshot = vq.debug_snapshot();
+		assert_eq!(snapshot.in_flight, 0);
+		assert_eq!(snapshot.free_descriptors, 4);
+		assert!(matches!(
+			snapshot.ring,
+			RingSnapshot::Split {
+				avail_idx: 4,
+				used_idx: 4,
+				read_idx: 4
+			}
+		));
+	}
+
+	#[test]
+	fn poll_with_dropped_receiver() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(4);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let mut recv = [0u8; 8];
+		vq.clone()
+			.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+			.unwrap()
+			.dispatch_await(sender, false);
+		drop(receiver);
+
+		// The finished transfer is dropped instead of panicking.
+		assert_eq!(device.run(&vq, 0xef), 1);
+		assert!(!vq.poll());
+		let snapshot = vq.debug_snapshot();
+		assert_eq!(snapshot.in_flight, 0);
+		assert_eq!(snapshot.free_descriptors, 4);
+	}
+
+	#[test]
+	fn on_complete_fires_once() {
+		use core::sync::atomic::{AtomicUsize, Ordering};
+
+		static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+		fn on_complete(_tkn: &BufferToken) {
+			COMPLETED.fetch_add(1, Ordering::Relaxed);
+		}
+
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(4);
+		let (sender, receiver) = async_channel::unbounded();
+
+		let mut recv = [0u8; 8];
+		vq.clone()
+			.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+			.unwrap()
+			.on_complete(on_complete)
+			.dispatch_await(sender, false);
+		assert_eq!(COMPLETED.load(Ordering::Relaxed), 0);
+
+		assert_eq!(device.run(&vq, 0xef), 1);
+		assert!(!vq.poll());
+		assert_eq!(COMPLETED.load(Ordering::Relaxed), 1);
+		assert!(!vq.poll());
+		assert_eq!(COMPLETED.load(Ordering::Relaxed), 1);
+		assert!(receiver.try_recv().is_ok());
+
+		// Without an await queue, the token is dropped after the callback.
+		vq.clone()
+			.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+			.unwrap()
+			.on_complete(on_complete)
+			.dispatch(false);
+		assert_eq!(device.run(&vq, 0xef), 1);
+		assert!(!vq.poll());
+		assert_eq!(COMPLETED.load(Ordering::Relaxed), 2);
+		let snapshot = vq.debug_snapshot();
+		assert_eq!(snapshot.in_flight, 0);
+		assert_eq!(snapshot.free_descriptors, 4);
+	}
+
+	#[test]
+	fn poll_stops_at_full_await_queue() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(4);
+		let (sender, receiver) = async_channel::bounded(1);
+
+		let mut recv = [0u8; 8];
+		for _ in 0..2 {
+			vq.clone()
+				.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+				.unwrap()
+				.dispatch_await(sender.clone(), false);
+		}
+		assert_eq!(device.run(&vq, 0xef), 2);
+
+		// The second transfer stays in the ring, until the first one is consumed.
+		assert!(vq.poll());
+		assert_eq!(vq.debug_snapshot().in_flight, 1);
+		drop(receiver.try_recv().unwrap());
+		assert!(!vq.poll());
+		drop(receiver.try_recv().unwrap());
+		assert_eq!(vq.debug_snapshot().in_flight, 0);
+	}
+
+	#[test]
+	fn poll_all_shares_budget() {
+		use crate::drivers::virtio::virtqueue::poll_all;
+
+		let mut devices = [FakeDevice::new(), FakeDevice::new()];
+		let queues = devices.each_mut().map(|device| device.create_queue(4));
+		let mut recv = [0u8; 8];
+		for (device, vq) in devices.iter_mut().zip(&queues) {
+			for _ in 0..4 {
+				vq.clone()
+					.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+					.unwrap()
+					.dispatch(false);
+			}
+			assert_eq!(device.run(vq, 0xef), 4);
+		}
+
+		// Both queues make progress, although each one could use the whole budget.
+		let vqs = queues
+			.iter()
+			.map(|vq| Rc::clone(vq) as Rc<dyn Virtq>)
+			.collect::<Vec<_>>();
+		assert!(poll_all(&vqs, 4));
+		for vq in &queues {
+			assert_eq!(vq.debug_snapshot().in_flight, 2);
+		}
+
+		assert!(!poll_all(&vqs, 4));
+		for vq in &queues {
+			assert_eq!(vq.debug_snapshot().in_flight, 0);
+		}
+
+		// A device without queues has nothing to poll.
+		assert!(!poll_all(&Vec::<Rc<dyn Virtq>>::new(), 4));
+	}
+
+	#[test]
+	#[cfg(feature = "virtio-debug")]
+	#[should_panic(expected = "transfers in flight, but 1 elements available")]
+	fn check_lost_transfer() {
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(4);
+		let mut recv = [0u8; 8];
+		vq.clone()
+			.prep_transfer_from_raw(&[], &[&mut recv[..]], BufferType::Direct)
+			.unwrap()
+			.dispatch(false);
+
+		let mut ring = vq.ring.borrow_mut();
+		ring.check_invariants();
+
+		ring.token_ring.fill_with(|| None);
+		ring.check_invariants();
+	}
+
+	#[test]
+	fn poll_count_respects_budget() {
+		// more used elements than the budget allows
+		assert_eq!(poll_count(0, 10, 4), 4);
+		// the remaining elements are processed by the next call
+		assert_eq!(poll_count(4, 10, 4), 4);
+		assert_eq!(poll_count(8, 10, 4), 2);
+		assert_eq!(poll_count(10, 10, 4), 0);
+	}
+
+	#[test]
+	fn poll_count_wraps_around() {
+		assert_eq!(poll_count(u16::MAX - 1, 2, 8), 4);
+		assert_eq!(poll_count(u16::MAX - 1, 2, 3), 3);
+	}
+
+	#[test]
+	fn ring_slot_continuous_across_wrap() {
+		assert_eq!(ring_slot(u16::MAX, 8), 7);
+		assert_eq!(ring_slot(u16::MAX.wrapping_add(1), 8), 0);
+	}
+
+	/// Pushes more than 2^16 entries through a small ring, while the device lags behind.
+	#[test]
+	fn ring_index_wraps_around() {
+		const LEN: u16 = 8;
+		const TRANSFERS: u32 = 70_000;
+
+		let mut device = FakeDevice::new();
+		let vq = device.create_queue(LEN);
+		let data = (0..TRANSFERS).map(u32::to_le_bytes).collect::<Vec<_>>();
+
+		for (i, value) in data.iter().enumerate() {
+			vq.clone()
+				.prep_transfer_from_raw(&[&value[..]], &[], BufferType::Direct)
+				.unwrap()
+				.dispatch(false);
+
+			// The device only processes a completely filled ring.
+			if vq.debug_snapshot().free_descriptors == 0 {
+				assert_eq!(device.run(&vq, 0), usize::from(LEN));
+				assert!(!vq.poll());
+				assert_eq!(device.received.len(), (i + 1) * 4);
+			}
+		}
+
+		assert_eq!(device.received, data.concat());
+		let idx = (TRANSFERS % (1 << 16)) as u16;
+		assert!(matches!(
+			vq.debug_snapshot().ring,
+			RingSnapshot::Split { avail_idx, used_idx, read_idx }
+				if avail_idx == idx && used_idx == idx && read_idx == idx
+		));
+	}
 }
@@ -122,6 +122,28 @@ pub fn vars() -> Iter<'static, String, String> {
 	CLI.get().unwrap().env_vars.iter()
 }
 
+/// Boot time in microseconds since the epoch, if given by `HERMIT_BOOT_EPOCH` in seconds.
+///
+/// Used as fallback, if no hardware clock is available.
+pub fn boot_epoch() -> Option<u64> {
+	hermit_var!("HERMIT_BOOT_EPOCH")
+		.and_then(|epoch| epoch.parse::<u64>().ok())
+		.map(|secs| secs * 1_000_000)
+}
+
+/// Difference between TAI and UTC in seconds, which applies since 2017-01-01
+const DEFAULT_TAI_OFFSET: i64 = 37;
+
+/// Difference between TAI and UTC in seconds.
+///
+/// The offset can be overridden by `HERMIT_TAI_OFFSET`, e.g. after an
+/// announcement of a new leap second.
+pub fn tai_offset() -> i64 {
+	hermit_var!("HERMIT_TAI_OFFSET")
+		.and_then(|offset| offset.parse::<i64>().ok())
+		.unwrap_or(DEFAULT_TAI_OFFSET)
+}
+
 /// Returns the cmdline argument passed in after "--"
 pub fn args() -> &'static [String] {
 	CLI.get().unwrap().args.as_slice()
@@ -0,0 +1,109 @@
+//! Announcement of the guest after a live migration.
+//!
+//! After a migration, switches still forward the frames of the guest to its old
+//! location. If the device requests it (see Virtio specification v1.1. - 5.1.6.5.4),
+//! the guest announces each of its addresses by a gratuitous ARP (IPv4) or an
+//! unsolicited neighbor advertisement (IPv6).
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::wire::{
+	ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+	EthernetRepr, Icmpv6Packet, Icmpv6Repr, IpAddress, IpProtocol, Ipv4Address, Ipv6Address,
+	Ipv6Packet, Ipv6Repr, NdiscNeighborFlags, NdiscRepr, RawHardwareAddress,
+};
+
+/// Ethernet address of the IPv6 all-nodes multicast group
+const ALL_NODES: EthernetAddress = EthernetAddress([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]);
+
+/// Returns the frame, which announces `addr` of the interface with the hardware address `mac`.
+pub(crate) fn frame(mac: EthernetAddress, addr: IpAddress) -> Vec<u8> {
+	match addr {
+		IpAddress::Ipv4(addr) => gratuitous_arp(mac, addr),
+		IpAddress::Ipv6(addr) => unsolicited_advertisement(mac, addr),
+	}
+}
+
+/// Returns an ARP request, whose sender and target are `addr`.
+fn gratuitous_arp(mac: EthernetAddress, addr: Ipv4Address) -> Vec<u8> {
+	let arp = ArpRepr::EthernetIpv4 {
+		operation: ArpOperation::Request,
+		source_hardware_addr: mac,
+		source_protocol_addr: addr,
+		target_hardware_addr: EthernetAddress([0; 6]),
+		target_protocol_addr: addr,
+	};
+	let ethernet = EthernetRepr {
+		src_addr: mac,
+		dst_addr: EthernetAddress::BROADCAST,
+		ethertype: EthernetProtocol::Arp,
+	};
+
+	let mut buffer = vec![0; ethernet.buffer_len() + arp.buffer_len()];
+	let mut frame = EthernetFrame::new_unchecked(&mut buffer[..]);
+	ethernet.emit(&mut frame);
+	arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+	buffer
+}
+
+/// Returns a neighbor advertisement of `addr` to all nodes, which overrides cached entries.
+fn unsolicited_advertisement(mac: EthernetAddress, addr: Ipv6Address) -> Vec<u8> {
+	let icmp = Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert {
+		flags: NdiscNeighborFlags::OVERRIDE,
+		target_addr: addr,
+		lladdr: Some(RawHardwareAddress::from_bytes(mac.as_bytes())),
+	});
+	let ip = Ipv6Repr {
+		src_addr: addr,
+		dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+		next_header: IpProtocol::Icmpv6,
+		payload_len: icmp.buffer_len(),
+		hop_limit: 255,
+	};
+	let ethernet = EthernetRepr {
+		src_addr: mac,
+		dst_addr: ALL_NODES,
+		ethertype: EthernetProtocol::Ipv6,
+	};
+
+	let mut buffer = vec![0; ethernet.buffer_len() + ip.buffer_len() + icmp.buffer_len()];
+	let mut frame = EthernetFrame::new_unchecked(&mut buffer[..]);
+	ethernet.emit(&mut frame);
+	let mut packet = Ipv6Packet::new_unchecked(frame.payload_mut());
+	ip.emit(&mut packet);
+	icmp.emit(
+		&IpAddress::Ipv6(ip.src_addr),
+		&IpAddress::Ipv6(ip.dst_addr),
+		&mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+		&ChecksumCapabilities::default(),
+	);
+	buffer
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn announce_ipv4_address() {
+		let mac = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+		let addr = Ipv4Address::new(10, 0, 5, 3);
+		let buffer = frame(mac, IpAddress::Ipv4(addr));
+
+		let frame = EthernetFrame::new_checked(&buffer[..]).unwrap();
+		assert_eq!(frame.dst_addr(), EthernetAddress::BROADCAST);
+		let arp = ArpRepr::parse(&ArpPacket::new_checked(frame.payload()).unwrap()).unwrap();
+		assert_eq!(
+			arp,
+			ArpRepr::EthernetIpv4 {
+				operation: ArpOperation::Request,
+				source_hardware_addr: mac,
+				source_protocol_addr: addr,
+				target_hardware_addr: EthernetAddress([0; 6]),
+				target_protocol_addr: addr,
+			}
+		);
+	}
+}
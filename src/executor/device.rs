@@ -1,7 +1,9 @@
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::mem;
 #[cfg(not(feature = "dhcpv4"))]
 use core::str::FromStr;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use smoltcp::iface::{Config, Interface, SocketSet};
 use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium};
@@ -9,37 +11,172 @@ use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Mediu
 use smoltcp::socket::dhcpv4;
 #[cfg(all(feature = "dns", not(feature = "dhcpv4")))]
 use smoltcp::socket::dns;
+#[cfg(feature = "ipv6")]
+use smoltcp::socket::raw;
 use smoltcp::time::Instant;
 #[cfg(not(feature = "dhcpv4"))]
+use smoltcp::wire::IpAddress;
+#[cfg(any(feature = "ipv6", not(feature = "dhcpv4")))]
+use smoltcp::wire::IpCidr;
+#[cfg(not(feature = "dhcpv4"))]
 use smoltcp::wire::Ipv4Address;
 use smoltcp::wire::{EthernetAddress, HardwareAddress};
-#[cfg(not(feature = "dhcpv4"))]
-use smoltcp::wire::{IpAddress, IpCidr};
-
+#[cfg(feature = "ipv6")]
+use smoltcp::wire::{IpProtocol, IpVersion};
+
+#[cfg(feature = "tcp")]
+use super::initcwnd;
+#[cfg(feature = "tcp")]
+use super::keepalive;
+#[cfg(feature = "ipv6")]
+use super::ndisc::{self, Slaac};
+#[cfg(feature = "udp")]
+use super::neighbor::QueuedDatagrams;
+use super::neighbor::{self, Resolutions};
 use super::network::{NetworkInterface, NetworkState};
-use crate::arch;
+use super::rx_pool;
+#[cfg(feature = "tcp")]
+use super::tfo::CookieCache;
 #[cfg(not(feature = "pci"))]
 use crate::arch::kernel::mmio as hardware;
-use crate::drivers::net::NetworkDriver;
+use crate::drivers::net::{NetworkDriver, RxHash};
 #[cfg(feature = "pci")]
 use crate::drivers::pci as hardware;
 
+/// Counters of the packets, which are received and sent by the network interface
+pub(crate) struct NetStats {
+	pub rx_packets: AtomicU64,
+	pub rx_bytes: AtomicU64,
+	pub tx_packets: AtomicU64,
+	pub tx_bytes: AtomicU64,
+	/// Number of interrupts, which signaled received packets to the network task
+	pub irq_signals: AtomicU64,
+	/// Sum of the latencies in microseconds between the interrupts and their processing
+	pub irq_latency_sum: AtomicU64,
+	/// Maximal latency in microseconds between an interrupt and its processing
+	pub irq_latency_max: AtomicU64,
+	/// Number of signals, which were dropped, because the network task was behind
+	pub irq_signal_overflows: AtomicU64,
+	/// Number of buffers for received packets, which had to be allocated
+	pub rx_buffer_allocs: AtomicU64,
+	/// Number of buffers for received packets, which were taken from the pool
+	pub rx_buffer_reuses: AtomicU64,
+}
+
+impl NetStats {
+	const fn new() -> Self {
+		Self {
+			rx_packets: AtomicU64::new(0),
+			rx_bytes: AtomicU64::new(0),
+			tx_packets: AtomicU64::new(0),
+			tx_bytes: AtomicU64::new(0),
+			irq_signals: AtomicU64::new(0),
+			irq_latency_sum: AtomicU64::new(0),
+			irq_latency_max: AtomicU64::new(0),
+			irq_signal_overflows: AtomicU64::new(0),
+			rx_buffer_allocs: AtomicU64::new(0),
+			rx_buffer_reuses: AtomicU64::new(0),
+		}
+	}
+
+	/// Records the `latency` in microseconds between an interrupt and its processing.
+	pub fn record_irq_latency(&self, latency: u64) {
+		self.irq_signals.fetch_add(1, Ordering::Relaxed);
+		self.irq_latency_sum.fetch_add(latency, Ordering::Relaxed);
+		self.irq_latency_max.fetch_max(latency, Ordering::Relaxed);
+	}
+
+	/// Returns the average latency in microseconds between an interrupt and its processing.
+	pub fn irq_latency_avg(&self) -> u64 {
+		let signals = self.irq_signals.load(Ordering::Relaxed);
+		self.irq_latency_sum
+			.load(Ordering::Relaxed)
+			.checked_div(signals)
+			.unwrap_or(0)
+	}
+}
+
+pub(crate) static NET_STATS: NetStats = NetStats::new();
+
+/// Prints the counters of the network interface and the error counters of the driver.
+#[cfg(feature = "shell")]
+pub(crate) fn print_statistics() {
+	println!(
+		"RX: {} packets, {} bytes",
+		NET_STATS.rx_packets.load(Ordering::Relaxed),
+		NET_STATS.rx_bytes.load(Ordering::Relaxed)
+	);
+	println!(
+		"TX: {} packets, {} bytes",
+		NET_STATS.tx_packets.load(Ordering::Relaxed),
+		NET_STATS.tx_bytes.load(Ordering::Relaxed)
+	);
+	println!(
+		"IRQ latency: {} signals, {} us average, {} us maximum, {} overflows",
+		NET_STATS.irq_signals.load(Ordering::Relaxed),
+		NET_STATS.irq_latency_avg(),
+		NET_STATS.irq_latency_max.load(Ordering::Relaxed),
+		NET_STATS.irq_signal_overflows.load(Ordering::Relaxed)
+	);
+	println!(
+		"RX buffers: {} allocated, {} reused",
+		NET_STATS.rx_buffer_allocs.load(Ordering::Relaxed),
+		NET_STATS.rx_buffer_reuses.load(Ordering::Relaxed)
+	);
+
+	#[cfg(feature = "rtl8139")]
+	{
+		use crate::drivers::net::rtl8139::RTL8139_STATS;
+
+		println!(
+			"RX errors: {}, RX overflows: {}",
+			RTL8139_STATS.rx_errors.load(Ordering::Relaxed),
+			RTL8139_STATS.rx_overflows.load(Ordering::Relaxed)
+		);
+		println!(
+			"TX errors: {}, TX underruns: {}",
+			RTL8139_STATS.tx_errors.load(Ordering::Relaxed),
+			RTL8139_STATS.tx_underruns.load(Ordering::Relaxed)
+		);
+		println!("Resets: {}", RTL8139_STATS.resets.load(Ordering::Relaxed));
+	}
+}
+
 /// Data type to determine the mac address
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub(crate) struct HermitNet {
 	mtu: u16,
 	checksums: ChecksumCapabilities,
+	/// Number of packets, which may still be received before the device
+	/// reports an empty receive queue to smoltcp.
+	rx_budget: usize,
 }
 
 impl HermitNet {
 	pub(crate) const fn new(mtu: u16, checksums: ChecksumCapabilities) -> Self {
-		Self { mtu, checksums }
+		Self {
+			mtu,
+			checksums,
+			rx_budget: usize::MAX,
+		}
+	}
+
+	/// Limits the number of packets, which are received until the next call
+	/// of this function.
+	pub(crate) fn set_rx_budget(&mut self, budget: usize) {
+		self.rx_budget = budget;
+	}
+
+	/// Returns `true` if the receive budget is used up and
+	/// further packets may be pending.
+	pub(crate) fn rx_budget_exhausted(&self) -> bool {
+		self.rx_budget == 0
 	}
 }
 
 impl<'a> NetworkInterface<'a> {
-	#[cfg(feature = "dhcpv4")]
+	/// Creates the network interface on top of the network driver of the kernel.
 	pub(crate) fn create() -> NetworkState<'a> {
 		let (mtu, mac, checksums) = if let Some(driver) = hardware::get_network_driver() {
 			let guard = driver.lock();
@@ -52,52 +189,163 @@ impl<'a> NetworkInterface<'a> {
 			return NetworkState::InitializationFailed;
 		};
 
-		let mut device = HermitNet::new(mtu, checksums);
+		info!("MTU: {} bytes", mtu);
+
+		let device = HermitNet::new(mtu, checksums);
+
+		let mut nic = Self::create_with_device(device, mac, crate::executor::network::now());
+		if let Some(timeout) = hermit_var!("HERMIT_NEIGHBOR_TIMEOUT") {
+			match neighbor::parse(timeout) {
+				Ok(timeout) => {
+					info!("Timeout of the neighbor resolution: {timeout}");
+					nic = nic.with_neighbor_timeout(timeout);
+				}
+				Err(_) => warn!("Invalid timeout of the neighbor resolution {timeout} ms"),
+			}
+		}
+		if let Some(max_sockets) = hermit_var!("HERMIT_MAX_SOCKETS") {
+			match max_sockets.parse::<usize>() {
+				Ok(max_sockets) => {
+					info!("Limit the number of sockets to {max_sockets}");
+					nic = nic.with_max_sockets(max_sockets);
+				}
+				Err(_) => warn!("Invalid socket limit {max_sockets}"),
+			}
+		}
+		#[cfg(feature = "tcp")]
+		if let Some(initcwnd) = hermit_var!("HERMIT_TCP_INITCWND") {
+			match initcwnd::parse(initcwnd) {
+				Ok(segments) => {
+					info!("Initial congestion window of TCP connections: {segments} segments");
+					nic = nic.with_tcp_initcwnd(segments);
+				}
+				Err(_) => warn!(
+					"Invalid initial congestion window {initcwnd}, expected {}..={} segments",
+					initcwnd::MIN_SEGMENTS,
+					initcwnd::MAX_SEGMENTS
+				),
+			}
+		}
+
+		#[cfg(feature = "dhcpv4")]
+		if let Some(hostname) = hermit_var!("HERMIT_HOSTNAME") {
+			if is_valid_hostname(&hostname) {
+				info!("Send host name {hostname} to the DHCP server");
+				nic = nic.with_dhcp_hostname(&hostname);
+			} else {
+				warn!("Invalid host name {hostname}, don't send it to the DHCP server");
+			}
+		}
+		#[cfg(feature = "dhcpv4")]
+		if hermit_var!("HERMIT_DHCP_CLIENTID").is_some() {
+			// smoltcp always sends the hardware address as client identifier. A
+			// second option 61 would be concatenated by the server (RFC 3396).
+			warn!("HERMIT_DHCP_CLIENTID isn't supported, the MAC address identifies the client");
+		}
+
+		#[cfg(feature = "tcp")]
+		if let Some(probes) = hermit_var!("HERMIT_TCP_KEEPALIVE_PROBES") {
+			match keepalive::parse(probes) {
+				Ok(probes) => {
+					info!("Drop TCP connections after {probes} unanswered keepalive probes");
+					nic = nic.with_tcp_keepalive_probes(probes);
+				}
+				Err(_) => warn!("Invalid number of keepalive probes {probes}"),
+			}
+		}
+
+		NetworkState::Initialized(Box::new(nic))
+	}
+}
+
+/// Returns `true`, if `name` is a valid host name (RFC 1123), which consists of
+/// labels with letters, digits and hyphens.
+#[cfg(feature = "dhcpv4")]
+fn is_valid_hostname(name: &str) -> bool {
+	(1..=253).contains(&name.len())
+		&& name.split('.').all(|label| {
+			(1..=63).contains(&label.len())
+				&& !label.starts_with('-')
+				&& !label.ends_with('-')
+				&& label
+					.bytes()
+					.all(|c| c.is_ascii_alphanumeric() || c == b'-')
+		})
+}
 
+impl<'a, D: Device> NetworkInterface<'a, D> {
+	/// Creates a network interface on top of an arbitrary smoltcp device.
+	///
+	/// Beside the kernel's own network driver, this allows to run the
+	/// network stack on a loopback or mock device.
+	#[cfg(feature = "dhcpv4")]
+	pub(crate) fn create_with_device(mut device: D, mac: [u8; 6], timestamp: Instant) -> Self {
 		let ethernet_addr = EthernetAddress([mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]);
 		let hardware_addr = HardwareAddress::Ethernet(ethernet_addr);
 
 		info!("MAC address {}", hardware_addr);
-		info!("MTU: {} bytes", mtu);
 
 		let dhcp = dhcpv4::Socket::new();
 
 		// use the current time based on the wall-clock time as seed
 		let mut config = Config::new(hardware_addr);
-		config.random_seed = (arch::kernel::systemtime::now_micros()) / 1000000;
+		config.random_seed = timestamp.secs().try_into().unwrap();
 		if device.capabilities().medium == Medium::Ethernet {
 			config.hardware_addr = hardware_addr;
 		}
 
-		let iface = Interface::new(config, &mut device, crate::executor::network::now());
+		#[allow(unused_mut)]
+		let mut iface = Interface::new(config, &mut device, timestamp);
 		let mut sockets = SocketSet::new(vec![]);
 		let dhcp_handle = sockets.add(dhcp);
+		#[cfg(feature = "ipv6")]
+		let slaac = Self::create_slaac(&mut iface, &mut sockets, ethernet_addr);
 
-		NetworkState::Initialized(Box::new(Self {
+		Self {
 			iface,
 			sockets,
 			device,
 			dhcp_handle,
 			#[cfg(feature = "dns")]
 			dns_handle: None,
-		}))
+			#[cfg(feature = "ipv6")]
+			slaac,
+			max_sockets: usize::MAX,
+			#[cfg(feature = "udp")]
+			udp_zero_checksum: Vec::new(),
+			#[cfg(feature = "udp")]
+			udp_queued: QueuedDatagrams::new(),
+			#[cfg(feature = "tcp")]
+			tcp_bound: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tfo_cookies: CookieCache::new(),
+			#[cfg(feature = "tcp")]
+			tcp_initial_data: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_initcwnd: None,
+			#[cfg(feature = "tcp")]
+			tcp_initial_windows: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_low_water: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_keep_alives: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_keepalive_probes: keepalive::DEFAULT_PROBES,
+			#[cfg(feature = "tcp")]
+			tcp_splices: Vec::new(),
+			neighbors: Resolutions::new(),
+			neighbor_timeout: neighbor::DEFAULT_TIMEOUT,
+			socket_errors: Vec::new(),
+			config_wakers: Vec::new(),
+		}
 	}
 
+	/// Creates a network interface on top of an arbitrary smoltcp device.
+	///
+	/// Beside the kernel's own network driver, this allows to run the
+	/// network stack on a loopback or mock device.
 	#[cfg(not(feature = "dhcpv4"))]
-	pub(crate) fn create() -> NetworkState<'a> {
-		let (mtu, mac, checksums) = if let Some(driver) = hardware::get_network_driver() {
-			let guard = driver.lock();
-			(
-				guard.get_mtu(),
-				guard.get_mac_address(),
-				guard.get_checksums(),
-			)
-		} else {
-			return NetworkState::InitializationFailed;
-		};
-
-		let mut device = HermitNet::new(mtu, checksums);
-
+	pub(crate) fn create_with_device(mut device: D, mac: [u8; 6], timestamp: Instant) -> Self {
 		let myip = Ipv4Address::from_str(hermit_var_or!("HERMIT_IP", "10.0.5.3")).unwrap();
 		let mygw = Ipv4Address::from_str(hermit_var_or!("HERMIT_GATEWAY", "10.0.5.1")).unwrap();
 		let mymask = Ipv4Address::from_str(hermit_var_or!("HERMIT_MASK", "255.255.255.0")).unwrap();
@@ -137,16 +385,15 @@ impl<'a> NetworkInterface<'a> {
 		info!("MAC address {}", hardware_addr);
 		info!("Configure network interface with address {}", ip_addrs[0]);
 		info!("Configure gateway with address {}", mygw);
-		info!("MTU: {} bytes", mtu);
 
 		// use the current time based on the wall-clock time as seed
 		let mut config = Config::new(hardware_addr);
-		config.random_seed = (arch::kernel::systemtime::now_micros()) / 1000000;
+		config.random_seed = timestamp.secs().try_into().unwrap();
 		if device.capabilities().medium == Medium::Ethernet {
 			config.hardware_addr = hardware_addr;
 		}
 
-		let mut iface = Interface::new(config, &mut device, crate::executor::network::now());
+		let mut iface = Interface::new(config, &mut device, timestamp);
 		iface.update_ip_addrs(|ip_addrs| {
 			ip_addrs
 				.push(IpCidr::new(
@@ -172,16 +419,81 @@ impl<'a> NetworkInterface<'a> {
 			sockets.add(dns_socket)
 		};
 
-		NetworkState::Initialized(Box::new(Self {
+		#[cfg(feature = "ipv6")]
+		let slaac = Self::create_slaac(&mut iface, &mut sockets, ethernet_addr);
+
+		Self {
 			iface,
 			sockets,
 			device,
 			#[cfg(feature = "dns")]
 			dns_handle: Some(dns_handle),
-		}))
+			#[cfg(feature = "ipv6")]
+			slaac,
+			max_sockets: usize::MAX,
+			#[cfg(feature = "udp")]
+			udp_zero_checksum: Vec::new(),
+			#[cfg(feature = "udp")]
+			udp_queued: QueuedDatagrams::new(),
+			#[cfg(feature = "tcp")]
+			tcp_bound: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tfo_cookies: CookieCache::new(),
+			#[cfg(feature = "tcp")]
+			tcp_initial_data: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_initcwnd: None,
+			#[cfg(feature = "tcp")]
+			tcp_initial_windows: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_low_water: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_keep_alives: Vec::new(),
+			#[cfg(feature = "tcp")]
+			tcp_keepalive_probes: keepalive::DEFAULT_PROBES,
+			#[cfg(feature = "tcp")]
+			tcp_splices: Vec::new(),
+			neighbors: Resolutions::new(),
+			neighbor_timeout: neighbor::DEFAULT_TIMEOUT,
+			socket_errors: Vec::new(),
+			config_wakers: Vec::new(),
+		}
+	}
+
+	/// Assigns the link-local address to the interface and creates the raw socket,
+	/// which receives the router advertisements.
+	#[cfg(feature = "ipv6")]
+	fn create_slaac(
+		iface: &mut Interface,
+		sockets: &mut SocketSet<'a>,
+		mac: EthernetAddress,
+	) -> Slaac {
+		let link_local = ndisc::link_local_address(mac);
+		info!("Link-local address {}", link_local);
+		iface.update_ip_addrs(|addrs| {
+			if addrs.push(IpCidr::Ipv6(link_local)).is_err() {
+				info!("Unable to add link-local address");
+			}
+		});
+
+		let rx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 4096]);
+		let tx_buffer = raw::PacketBuffer::new(vec![], vec![]);
+		let socket = raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer);
+
+		Slaac::new(sockets.add(socket), mac)
 	}
 }
 
+/// MAC address of the interfaces created by [`test_nic`].
+#[cfg(all(test, not(target_os = "none")))]
+pub(crate) const TEST_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+/// Creates a network interface on top of `device` for host tests.
+#[cfg(all(test, not(target_os = "none"), feature = "dhcpv4"))]
+pub(crate) fn test_nic<'a, D: Device>(device: D) -> NetworkInterface<'a, D> {
+	NetworkInterface::create_with_device(device, TEST_MAC, Instant::from_secs(1))
+}
+
 impl Device for HermitNet {
 	type RxToken<'a> = RxToken;
 	type TxToken<'a> = TxToken;
@@ -195,8 +507,16 @@ impl Device for HermitNet {
 	}
 
 	fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+		if self.rx_budget == 0 {
+			return None;
+		}
+
 		if let Some(driver) = hardware::get_network_driver() {
-			driver.lock().receive_packet()
+			let tokens = driver.lock().receive_packet();
+			if tokens.is_some() {
+				self.rx_budget -= 1;
+			}
+			tokens
 		} else {
 			None
 		}
@@ -213,11 +533,23 @@ pub(crate) type RxHandle = usize;
 #[doc(hidden)]
 pub(crate) struct RxToken {
 	buffer: Vec<u8>,
+	hash: Option<RxHash>,
 }
 
 impl RxToken {
 	pub(crate) fn new(buffer: Vec<u8>) -> Self {
-		Self { buffer }
+		Self { buffer, hash: None }
+	}
+
+	/// Attaches the flow hash, which is reported by the device.
+	pub(crate) fn with_hash(mut self, hash: Option<RxHash>) -> Self {
+		self.hash = hash;
+		self
+	}
+
+	/// Returns the flow hash of the packet, if the device reports it.
+	pub(crate) fn hash(&self) -> Option<RxHash> {
+		self.hash
 	}
 }
 
@@ -226,10 +558,22 @@ impl phy::RxToken for RxToken {
 	where
 		F: FnOnce(&mut [u8]) -> R,
 	{
+		NET_STATS.rx_packets.fetch_add(1, Ordering::Relaxed);
+		NET_STATS
+			.rx_bytes
+			.fetch_add(self.buffer.len() as u64, Ordering::Relaxed);
+		#[cfg(feature = "pcap")]
+		super::pcap::record(&self.buffer);
 		f(&mut self.buffer[..])
 	}
 }
 
+impl Drop for RxToken {
+	fn drop(&mut self) {
+		rx_pool::recycle(mem::take(&mut self.buffer));
+	}
+}
+
 #[doc(hidden)]
 pub(crate) struct TxToken;
 
@@ -244,9 +588,49 @@ impl phy::TxToken for TxToken {
 	where
 		F: FnOnce(&mut [u8]) -> R,
 	{
+		NET_STATS.tx_packets.fetch_add(1, Ordering::Relaxed);
+		NET_STATS.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
 		hardware::get_network_driver()
 			.unwrap()
 			.lock()
-			.send_packet(len, f)
+			.send_packet(len, |buffer| {
+				let result = f(buffer);
+				#[cfg(feature = "pcap")]
+				super::pcap::record(buffer);
+				result
+			})
+	}
+}
+
+#[cfg(all(test, not(target_os = "none"), feature = "dhcpv4"))]
+mod tests {
+	use smoltcp::phy::Loopback;
+
+	use super::*;
+
+	#[test]
+	fn create_with_loopback_device() {
+		let mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+		let nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			mac,
+			Instant::from_secs(1),
+		);
+
+		assert_eq!(
+			nic.iface.hardware_addr(),
+			HardwareAddress::Ethernet(EthernetAddress(mac))
+		);
+	}
+
+	#[test]
+	fn hostname_validation() {
+		assert!(is_valid_hostname("hermit"));
+		assert!(is_valid_hostname("node-1.example.com"));
+		assert!(!is_valid_hostname(""));
+		assert!(!is_valid_hostname("-hermit"));
+		assert!(!is_valid_hostname("her mit"));
+		assert!(!is_valid_hostname("hermit..local"));
+		assert!(!is_valid_hostname(&"a".repeat(64)));
 	}
 }
@@ -0,0 +1,100 @@
+//! Initial congestion window of TCP connections (RFC 6928).
+//!
+//! smoltcp has neither slow start nor an interface to tune its congestion
+//! control. A connection sends as much data as the receive window of the peer
+//! allows. Hence, the initial window is approximated on top of smoltcp: until the
+//! peer acknowledges the first data, at most `segments * MSS` bytes are moved into
+//! the transmit buffer of a socket. Afterwards, smoltcp alone controls the
+//! transmission. The window is configured for all sockets by `HERMIT_TCP_INITCWND`,
+//! which is passed to `NetworkInterface::with_tcp_initcwnd`.
+
+use crate::fd::IoError;
+
+/// Minimal number of segments of the initial window
+pub(crate) const MIN_SEGMENTS: u16 = 1;
+/// Maximal number of segments of the initial window, see RFC 6928
+pub(crate) const MAX_SEGMENTS: u16 = 10;
+
+/// Checks that `segments` is within the bounds of RFC 6928.
+fn validate(segments: u16) -> Result<u16, IoError> {
+	if (MIN_SEGMENTS..=MAX_SEGMENTS).contains(&segments) {
+		Ok(segments)
+	} else {
+		Err(IoError::EINVAL)
+	}
+}
+
+/// Parses the number of segments of the initial window, e.g. of `HERMIT_TCP_INITCWND`.
+pub(crate) fn parse(segments: &str) -> Result<u16, IoError> {
+	segments
+		.parse::<u16>()
+		.map_err(|_| IoError::EINVAL)
+		.and_then(validate)
+}
+
+/// Data of a socket, which is enqueued before the peer acknowledged any data
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InitialWindow {
+	/// Size of the initial window in bytes
+	limit: usize,
+	/// Number of enqueued bytes
+	enqueued: usize,
+}
+
+impl InitialWindow {
+	pub fn new(segments: u16, mss: usize) -> Self {
+		Self {
+			limit: usize::from(segments) * mss,
+			enqueued: 0,
+		}
+	}
+
+	/// Returns `true`, if the peer acknowledged data. `send_queue` is the number
+	/// of bytes in the transmit buffer, which are unacknowledged or not yet sent.
+	pub fn is_acked(&self, send_queue: usize) -> bool {
+		send_queue < self.enqueued
+	}
+
+	/// Returns `true`, if no further data may be enqueued before an acknowledgment.
+	pub fn is_exhausted(&self) -> bool {
+		self.enqueued >= self.limit
+	}
+
+	/// Returns how many of `len` bytes may be enqueued.
+	pub fn admit(&self, len: usize) -> usize {
+		len.min(self.limit.saturating_sub(self.enqueued))
+	}
+
+	/// Accounts `len` enqueued bytes.
+	pub fn consume(&mut self, len: usize) {
+		self.enqueued += len;
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reject_out_of_bounds() {
+		assert_eq!(parse("10"), Ok(10));
+		assert_eq!(parse("1"), Ok(1));
+		assert_eq!(parse("0"), Err(IoError::EINVAL));
+		assert_eq!(parse("11"), Err(IoError::EINVAL));
+		assert_eq!(parse("ten"), Err(IoError::EINVAL));
+	}
+
+	#[test]
+	fn limit_until_acked() {
+		let mut window = InitialWindow::new(2, 1460);
+		assert_eq!(window.admit(4000), 2920);
+		window.consume(2000);
+		assert_eq!(window.admit(4000), 920);
+		window.consume(920);
+		assert!(window.is_exhausted());
+		assert_eq!(window.admit(4000), 0);
+
+		assert!(!window.is_acked(2920));
+		assert!(window.is_acked(1460));
+	}
+}
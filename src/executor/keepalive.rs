@@ -0,0 +1,203 @@
+//! Dropping of idle TCP connections, whose peer doesn't answer keepalive probes.
+//!
+//! smoltcp sends a keepalive probe, if a connection is silent for the keepalive
+//! interval, and repeats the probe after each further interval. However, it never
+//! gives up on an unresponsive peer, e.g. after a NAT mapping expired. The network
+//! interface observes the received segments of connections with keepalive. If the
+//! configured number of probes remains unanswered, the connection is aborted and
+//! fails with `ETIMEDOUT`.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use smoltcp::phy::Device;
+use smoltcp::time::{Duration, Instant};
+#[cfg(feature = "ipv6")]
+use smoltcp::wire::Ipv6Packet;
+use smoltcp::wire::{
+	EthernetFrame, EthernetProtocol, IpEndpoint, IpProtocol, Ipv4Packet, TcpPacket,
+};
+
+use crate::fd::IoError;
+
+/// Number of unanswered probes, after which a connection is dropped
+pub(crate) const DEFAULT_PROBES: u32 = 9;
+
+/// Parses the number of probes, e.g. of `HERMIT_TCP_KEEPALIVE_PROBES`.
+pub(crate) fn parse(probes: &str) -> Result<u32, IoError> {
+	match probes.parse::<u32>() {
+		Ok(probes) if probes > 0 => Ok(probes),
+		_ => Err(IoError::EINVAL),
+	}
+}
+
+/// Returns the remote endpoint and the local port of the received TCP `frame`.
+fn tcp_segment(frame: &[u8]) -> Option<(IpEndpoint, u16)> {
+	let frame = EthernetFrame::new_checked(frame).ok()?;
+	match frame.ethertype() {
+		EthernetProtocol::Ipv4 => {
+			let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+			if packet.next_header() != IpProtocol::Tcp {
+				return None;
+			}
+			let segment = TcpPacket::new_checked(packet.payload()).ok()?;
+			let remote = IpEndpoint::new(packet.src_addr().into(), segment.src_port());
+			Some((remote, segment.dst_port()))
+		}
+		#[cfg(feature = "ipv6")]
+		EthernetProtocol::Ipv6 => {
+			let packet = Ipv6Packet::new_checked(frame.payload()).ok()?;
+			if packet.next_header() != IpProtocol::Tcp {
+				return None;
+			}
+			let segment = TcpPacket::new_checked(packet.payload()).ok()?;
+			let remote = IpEndpoint::new(packet.src_addr().into(), segment.src_port());
+			Some((remote, segment.dst_port()))
+		}
+		_ => None,
+	}
+}
+
+/// Keepalive of a TCP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeepAlive {
+	/// Idle time before the first probe and time between subsequent probes
+	pub interval: Duration,
+	/// Time, at which the last segment of the peer was received. Before the next
+	/// poll of the interface, the time is unknown.
+	last_heard: Option<Instant>,
+}
+
+impl KeepAlive {
+	pub fn new(interval: Duration) -> Self {
+		Self {
+			interval,
+			last_heard: None,
+		}
+	}
+
+	/// Records the poll of the interface at `timestamp`, which received a segment
+	/// of the peer, if `heard` is set.
+	pub fn polled(&mut self, timestamp: Instant, heard: bool) {
+		if heard || self.last_heard.is_none() {
+			self.last_heard = Some(timestamp);
+		}
+	}
+
+	/// Returns the time, at which `probes` probes remain unanswered.
+	pub fn deadline(&self, probes: u32) -> Option<Instant> {
+		self.last_heard
+			.map(|last_heard| last_heard + self.interval * (probes + 1))
+	}
+}
+
+/// Connection with keepalive, whose received segments are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Peer {
+	remote: Option<IpEndpoint>,
+	local_port: u16,
+	/// Set, if a segment of the peer was received
+	pub heard: bool,
+}
+
+impl Peer {
+	pub fn new(remote: Option<IpEndpoint>, local_port: u16) -> Self {
+		Self {
+			remote,
+			local_port,
+			heard: false,
+		}
+	}
+}
+
+/// Records the received segment `frame` at the matching peer.
+fn record(peers: &RefCell<Vec<Peer>>, frame: &[u8]) {
+	let Some((remote, local_port)) = tcp_segment(frame) else {
+		return;
+	};
+
+	for peer in peers.borrow_mut().iter_mut() {
+		if peer.remote == Some(remote) && peer.local_port == local_port {
+			peer.heard = true;
+		}
+	}
+}
+
+/// Device, which records the received segments of connections with keepalive
+pub(crate) struct KeepAliveProbe<'d, D> {
+	device: &'d mut D,
+	peers: &'d RefCell<Vec<Peer>>,
+}
+
+impl<'d, D> KeepAliveProbe<'d, D> {
+	pub fn new(device: &'d mut D, peers: &'d RefCell<Vec<Peer>>) -> Self {
+		Self { device, peers }
+	}
+}
+
+pub(crate) struct KeepAliveProbeRxToken<'d, T> {
+	token: T,
+	peers: &'d RefCell<Vec<Peer>>,
+}
+
+impl<T: smoltcp::phy::RxToken> smoltcp::phy::RxToken for KeepAliveProbeRxToken<'_, T> {
+	fn consume<R, F>(self, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		let peers = self.peers;
+		self.token.consume(|buffer| {
+			if !peers.borrow().is_empty() {
+				record(peers, buffer);
+			}
+			f(buffer)
+		})
+	}
+}
+
+impl<'d, D: Device> Device for KeepAliveProbe<'d, D> {
+	type RxToken<'a>
+		= KeepAliveProbeRxToken<'a, D::RxToken<'a>>
+	where
+		Self: 'a;
+	type TxToken<'a>
+		= D::TxToken<'a>
+	where
+		Self: 'a;
+
+	fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+		let peers = self.peers;
+		self.device
+			.receive(timestamp)
+			.map(|(token, tx)| (KeepAliveProbeRxToken { token, peers }, tx))
+	}
+
+	fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+		self.device.transmit(timestamp)
+	}
+
+	fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+		self.device.capabilities()
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn deadline_after_unanswered_probes() {
+		let mut keep_alive = KeepAlive::new(Duration::from_secs(75));
+		assert_eq!(keep_alive.deadline(2), None);
+
+		keep_alive.polled(Instant::from_secs(10), false);
+		keep_alive.polled(Instant::from_secs(50), false);
+		assert_eq!(keep_alive.deadline(2), Some(Instant::from_secs(235)));
+
+		keep_alive.polled(Instant::from_secs(100), true);
+		assert_eq!(keep_alive.deadline(2), Some(Instant::from_secs(325)));
+
+		assert_eq!(parse("3"), Ok(3));
+		assert_eq!(parse("0"), Err(IoError::EINVAL));
+	}
+}
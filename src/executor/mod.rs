@@ -1,10 +1,31 @@
 #![allow(dead_code)]
 
+#[cfg(any(feature = "tcp", feature = "udp"))]
+pub(crate) mod announce;
 #[cfg(any(feature = "tcp", feature = "udp"))]
 pub(crate) mod device;
+#[cfg(feature = "tcp")]
+pub(crate) mod initcwnd;
+#[cfg(feature = "tcp")]
+pub(crate) mod keepalive;
+#[cfg(all(feature = "ipv6", any(feature = "tcp", feature = "udp")))]
+pub(crate) mod ndisc;
+#[cfg(any(feature = "tcp", feature = "udp"))]
+pub(crate) mod neighbor;
 #[cfg(any(feature = "tcp", feature = "udp"))]
 pub(crate) mod network;
+#[cfg(feature = "pcap")]
+pub(crate) mod pcap;
+#[cfg(any(feature = "tcp", feature = "udp"))]
+pub(crate) mod rx_pool;
+#[cfg(feature = "tcp")]
+pub(crate) mod splice;
+#[cfg(feature = "stats-server")]
+mod stats;
+pub(crate) mod sync;
 pub(crate) mod task;
+#[cfg(feature = "tcp")]
+pub(crate) mod tfo;
 
 use alloc::sync::Arc;
 use alloc::task::Wake;
@@ -73,6 +94,25 @@ impl Wake for TaskNotify {
 	}
 }
 
+/// Waker for host tests, which counts how often it is woken up.
+#[cfg(all(test, not(target_os = "none")))]
+#[derive(Default)]
+pub(crate) struct WakeCounter(core::sync::atomic::AtomicUsize);
+
+#[cfg(all(test, not(target_os = "none")))]
+impl WakeCounter {
+	pub fn count(&self) -> usize {
+		self.0.load(core::sync::atomic::Ordering::Relaxed)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl Wake for WakeCounter {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+	}
+}
+
 pub(crate) fn run() {
 	let mut cx = Context::from_waker(Waker::noop());
 
@@ -86,6 +126,8 @@ pub(crate) fn run() {
 			}
 		})
 	});
+
+	crate::logging::flush_rate_limited();
 }
 
 /// Spawns a future on the executor.
@@ -96,6 +138,17 @@ where
 	without_interrupts(|| async_tasks().push(AsyncTask::new(future)));
 }
 
+/// Returns `true`, if idle cores have to poll the network device,
+/// because it doesn't raise interrupts for received packets.
+#[inline]
+pub(crate) fn is_busy_polling() -> bool {
+	#[cfg(any(feature = "tcp", feature = "udp"))]
+	return network::is_busy_polling();
+
+	#[cfg(not(any(feature = "tcp", feature = "udp")))]
+	false
+}
+
 pub fn init() {
 	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 	crate::executor::network::init();
@@ -119,6 +172,7 @@ where
 	let no_retransmission = if let Some(nic) = nic {
 		let mut guard = nic.lock();
 		guard.set_polling_mode(true);
+		network::set_polling_thread(true);
 		guard.get_checksums().tcp.tx()
 	} else {
 		true
@@ -145,6 +199,7 @@ where
 			#[cfg(any(feature = "tcp", feature = "udp"))]
 			if let Some(nic) = nic {
 				nic.lock().set_polling_mode(false);
+				network::set_polling_thread(false);
 			}
 
 			return t;
@@ -164,6 +219,7 @@ where
 				#[cfg(any(feature = "tcp", feature = "udp"))]
 				if let Some(nic) = nic {
 					nic.lock().set_polling_mode(false);
+					network::set_polling_thread(false);
 				}
 
 				return Err(IoError::ETIME);
@@ -185,6 +241,7 @@ where
 	let no_retransmission = if let Some(nic) = nic {
 		let mut guard = nic.lock();
 		guard.set_polling_mode(true);
+		network::set_polling_thread(true);
 		!guard.get_checksums().tcp.tx()
 	} else {
 		true
@@ -215,6 +272,7 @@ where
 			#[cfg(any(feature = "tcp", feature = "udp"))]
 			if let Some(nic) = nic {
 				nic.lock().set_polling_mode(false);
+				network::set_polling_thread(false);
 			}
 
 			return t;
@@ -234,6 +292,7 @@ where
 				#[cfg(any(feature = "tcp", feature = "udp"))]
 				if let Some(nic) = nic {
 					nic.lock().set_polling_mode(false);
+					network::set_polling_thread(false);
 				}
 
 				return Err(IoError::ETIME);
@@ -257,6 +316,7 @@ where
 				// allow network interrupts
 				if let Some(nic) = nic {
 					nic.lock().set_polling_mode(false);
+					network::set_polling_thread(false);
 				}
 
 				// switch to another task
@@ -265,6 +325,7 @@ where
 				// restore default values
 				if let Some(nic) = nic {
 					nic.lock().set_polling_mode(true);
+					network::set_polling_thread(true);
 				}
 				backoff.reset();
 			} else {
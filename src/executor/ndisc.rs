@@ -0,0 +1,260 @@
+//! Stateless address autoconfiguration (SLAAC) based on router advertisements.
+//!
+//! Router advertisements (RFC 4861) are received by a raw ICMPv6 socket.
+//! Beside the prefix information, which is used to derive a global address,
+//! the recursive DNS server option (RFC 8106) is evaluated.
+
+use alloc::vec::Vec;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{EthernetAddress, Ipv6Address, Ipv6Cidr};
+
+/// Length of the fixed IPv6 header
+const IPV6_HEADER_LEN: usize = 40;
+/// Next header value of ICMPv6
+const PROTOCOL_ICMPV6: u8 = 58;
+/// ICMPv6 type of a router advertisement
+const ROUTER_ADVERT: u8 = 134;
+/// Length of a router advertisement without options
+const ROUTER_ADVERT_LEN: usize = 16;
+/// Option type of the prefix information
+const OPTION_PREFIX_INFO: u8 = 3;
+/// Option type of the recursive DNS servers
+const OPTION_RDNSS: u8 = 25;
+/// Autonomous address-configuration flag of the prefix information
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+/// Length of the prefix, which is required to derive an address
+const SLAAC_PREFIX_LEN: u8 = 64;
+
+/// Prefix, which may be used for stateless address autoconfiguration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PrefixInfo {
+	pub prefix: Ipv6Address,
+	pub valid_lifetime: Duration,
+}
+
+/// Content of a router advertisement, which is relevant for the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RouterAdvert {
+	/// Link-local address of the sending router
+	pub router: Ipv6Address,
+	/// Lifetime of the router as default router. Zero, if the router
+	/// shouldn't be used as default router.
+	pub router_lifetime: Duration,
+	pub prefix: Option<PrefixInfo>,
+	pub dns_servers: Vec<Ipv6Address>,
+	pub dns_lifetime: Duration,
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+	u16::from_be_bytes([data[0], data[1]])
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+	u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Parses an IPv6 packet, which is received by the raw ICMPv6 socket.
+///
+/// Returns `None` if the packet isn't a valid router advertisement.
+pub(crate) fn parse(packet: &[u8]) -> Option<RouterAdvert> {
+	if packet.len() < IPV6_HEADER_LEN + ROUTER_ADVERT_LEN
+		|| packet[0] >> 4 != 6
+		|| packet[6] != PROTOCOL_ICMPV6
+		// router advertisements have to be sent with a hop limit of 255
+		|| packet[7] != 255
+	{
+		return None;
+	}
+
+	let router = Ipv6Address::from_bytes(&packet[8..24]);
+	// the source of a router advertisement has to be a link-local address
+	if router.0[0] != 0xfe || router.0[1] & 0xc0 != 0x80 {
+		return None;
+	}
+
+	let payload_len = usize::from(read_u16(&packet[4..6]));
+	let icmp = packet.get(IPV6_HEADER_LEN..IPV6_HEADER_LEN + payload_len)?;
+	if icmp.len() < ROUTER_ADVERT_LEN || icmp[0] != ROUTER_ADVERT || icmp[1] != 0 {
+		return None;
+	}
+
+	let mut advert = RouterAdvert {
+		router,
+		router_lifetime: Duration::from_secs(read_u16(&icmp[6..8]).into()),
+		prefix: None,
+		dns_servers: Vec::new(),
+		dns_lifetime: Duration::ZERO,
+	};
+
+	let mut options = &icmp[ROUTER_ADVERT_LEN..];
+	while options.len() >= 2 {
+		// the length of an option is specified in units of 8 bytes
+		let len = usize::from(options[1]) * 8;
+		if len == 0 || len > options.len() {
+			return None;
+		}
+
+		let option = &options[..len];
+		match option[0] {
+			OPTION_PREFIX_INFO if len == 32 => {
+				if option[2] == SLAAC_PREFIX_LEN && option[3] & PREFIX_FLAG_AUTONOMOUS != 0 {
+					advert.prefix = Some(PrefixInfo {
+						prefix: Ipv6Address::from_bytes(&option[16..32]),
+						valid_lifetime: Duration::from_secs(read_u32(&option[4..8]).into()),
+					});
+				}
+			}
+			OPTION_RDNSS if len >= 24 => {
+				advert.dns_lifetime = Duration::from_secs(read_u32(&option[4..8]).into());
+				advert.dns_servers = option[8..]
+					.chunks_exact(16)
+					.map(Ipv6Address::from_bytes)
+					.collect();
+			}
+			_ => {}
+		}
+
+		options = &options[len..];
+	}
+
+	Some(advert)
+}
+
+/// Derives the modified EUI-64 interface identifier from the MAC address.
+fn interface_id(mac: EthernetAddress) -> [u8; 8] {
+	let mac = mac.0;
+	[
+		mac[0] ^ 0x02,
+		mac[1],
+		mac[2],
+		0xff,
+		0xfe,
+		mac[3],
+		mac[4],
+		mac[5],
+	]
+}
+
+/// Derives an address with a 64 bit prefix from `prefix` and the MAC address.
+pub(crate) fn slaac_address(prefix: Ipv6Address, mac: EthernetAddress) -> Ipv6Cidr {
+	let mut bytes = prefix.0;
+	bytes[8..].copy_from_slice(&interface_id(mac));
+	Ipv6Cidr::new(Ipv6Address(bytes), SLAAC_PREFIX_LEN)
+}
+
+/// Returns the link-local address of the interface.
+pub(crate) fn link_local_address(mac: EthernetAddress) -> Ipv6Cidr {
+	slaac_address(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), mac)
+}
+
+/// Configuration, which is acquired from router advertisements
+pub(crate) struct Slaac {
+	/// Handle of the raw ICMPv6 socket
+	pub handle: SocketHandle,
+	pub mac: EthernetAddress,
+	/// Global address and the time, when it expires
+	pub address: Option<(Ipv6Cidr, Instant)>,
+	/// Default router and the time, when it expires
+	pub router: Option<(Ipv6Address, Instant)>,
+	/// Recursive DNS servers and the time, when they expire
+	pub dns_servers: Option<(Vec<Ipv6Address>, Instant)>,
+	/// True, if the DNS socket of the interface was created for the
+	/// recursive DNS servers
+	#[cfg(feature = "dns")]
+	pub dns_socket: bool,
+}
+
+impl Slaac {
+	pub fn new(handle: SocketHandle, mac: EthernetAddress) -> Self {
+		Self {
+			handle,
+			mac,
+			address: None,
+			router: None,
+			dns_servers: None,
+			#[cfg(feature = "dns")]
+			dns_socket: false,
+		}
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec;
+
+	use super::*;
+
+	const MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+
+	fn router_advert() -> Vec<u8> {
+		// router lifetime of 1800 s, reachable time and retransmission timer
+		let mut icmp = vec![ROUTER_ADVERT, 0, 0, 0, 64, 0, 0x07, 0x08];
+		icmp.extend_from_slice(&[0; 8]);
+		// prefix information 2001:db8::/64
+		icmp.extend_from_slice(&[OPTION_PREFIX_INFO, 4, 64, 0xc0]);
+		icmp.extend_from_slice(&3600u32.to_be_bytes());
+		icmp.extend_from_slice(&1800u32.to_be_bytes());
+		icmp.extend_from_slice(&[0; 4]);
+		icmp.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		// recursive DNS server 2001:db8::53
+		icmp.extend_from_slice(&[OPTION_RDNSS, 3, 0, 0]);
+		icmp.extend_from_slice(&600u32.to_be_bytes());
+		icmp.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0]);
+		icmp.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x53]);
+
+		let mut packet = vec![0x60, 0, 0, 0];
+		packet.extend_from_slice(&u16::try_from(icmp.len()).unwrap().to_be_bytes());
+		packet.extend_from_slice(&[PROTOCOL_ICMPV6, 255]);
+		packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+		packet.extend_from_slice(&[0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+		packet.extend_from_slice(&icmp);
+		packet
+	}
+
+	#[test]
+	fn parse_router_advert() {
+		let advert = parse(&router_advert()).unwrap();
+
+		assert_eq!(advert.router, Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+		assert_eq!(advert.router_lifetime, Duration::from_secs(0x0708));
+		assert_eq!(
+			advert.prefix,
+			Some(PrefixInfo {
+				prefix: Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+				valid_lifetime: Duration::from_secs(3600),
+			})
+		);
+		assert_eq!(
+			advert.dns_servers,
+			vec![Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x53)]
+		);
+		assert_eq!(advert.dns_lifetime, Duration::from_secs(600));
+	}
+
+	#[test]
+	fn reject_forwarded_router_advert() {
+		let mut packet = router_advert();
+		packet[7] = 64;
+		assert_eq!(parse(&packet), None);
+	}
+
+	#[test]
+	fn derive_addresses() {
+		assert_eq!(
+			link_local_address(MAC),
+			Ipv6Cidr::new(
+				Ipv6Address::new(0xfe80, 0, 0, 0, 0x5054, 0x00ff, 0xfe12, 0x3456),
+				64
+			)
+		);
+		assert_eq!(
+			slaac_address(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), MAC),
+			Ipv6Cidr::new(
+				Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0x5054, 0x00ff, 0xfe12, 0x3456),
+				64
+			)
+		);
+	}
+}
@@ -0,0 +1,423 @@
+//! Bounded resolution of neighbors.
+//!
+//! smoltcp holds back packets, whose next hop isn't resolved by ARP or NDP, and
+//! repeats the solicitation without a deadline. Hence, a socket waits forever for
+//! an unresponsive gateway or peer. The network interface observes the transmitted
+//! solicitations and the received answers. If a next hop isn't resolved within
+//! the timeout, the sockets, which wait for it, fail with `EHOSTUNREACH`.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use smoltcp::iface::Route;
+#[cfg(feature = "udp")]
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::Device;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+	ArpOperation, ArpPacket, ArpRepr, EthernetFrame, EthernetProtocol, IpAddress, IpCidr,
+};
+#[cfg(feature = "ipv6")]
+use smoltcp::wire::{Icmpv6Message, Icmpv6Packet, IpProtocol, Ipv6Address, Ipv6Packet};
+
+use crate::fd::IoError;
+
+/// Time, after which an unresolved neighbor is considered unreachable
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Parses the timeout in milliseconds, e.g. of `HERMIT_NEIGHBOR_TIMEOUT`.
+pub(crate) fn parse(millis: &str) -> Result<Duration, IoError> {
+	match millis.parse::<u64>() {
+		Ok(millis) if millis > 0 => Ok(Duration::from_millis(millis)),
+		_ => Err(IoError::EINVAL),
+	}
+}
+
+/// Returns the next hop of `addr`. This is `addr` itself, if it belongs to an
+/// attached subnet. Otherwise, it is the router of the most specific route.
+pub(crate) fn next_hop(addr: IpAddress, cidrs: &[IpCidr], routes: &[Route]) -> Option<IpAddress> {
+	if cidrs.iter().any(|cidr| cidr.contains_addr(&addr)) {
+		return Some(addr);
+	}
+
+	routes
+		.iter()
+		.filter(|route| route.cidr.contains_addr(&addr))
+		.max_by_key(|route| route.cidr.prefix_len())
+		.map(|route| route.via_router)
+}
+
+/// Returns the address, which is solicited by the transmitted `frame`.
+fn solicited(frame: &[u8]) -> Option<IpAddress> {
+	let frame = EthernetFrame::new_checked(frame).ok()?;
+	match frame.ethertype() {
+		EthernetProtocol::Arp => {
+			let packet = ArpPacket::new_checked(frame.payload()).ok()?;
+			match ArpRepr::parse(&packet).ok()? {
+				ArpRepr::EthernetIpv4 {
+					operation: ArpOperation::Request,
+					target_protocol_addr,
+					..
+				} => Some(IpAddress::Ipv4(target_protocol_addr)),
+				_ => None,
+			}
+		}
+		#[cfg(feature = "ipv6")]
+		EthernetProtocol::Ipv6 => {
+			ndisc_target(frame.payload(), Icmpv6Message::NeighborSolicit).map(IpAddress::Ipv6)
+		}
+		_ => None,
+	}
+}
+
+/// Returns the address, which is resolved by the received `frame`.
+///
+/// Besides replies, requests of a neighbor reveal its link-layer address.
+fn resolved(frame: &[u8]) -> Option<IpAddress> {
+	let frame = EthernetFrame::new_checked(frame).ok()?;
+	match frame.ethertype() {
+		EthernetProtocol::Arp => {
+			let packet = ArpPacket::new_checked(frame.payload()).ok()?;
+			match ArpRepr::parse(&packet).ok()? {
+				ArpRepr::EthernetIpv4 {
+					source_protocol_addr,
+					..
+				} => Some(IpAddress::Ipv4(source_protocol_addr)),
+				#[allow(unreachable_patterns)]
+				_ => None,
+			}
+		}
+		#[cfg(feature = "ipv6")]
+		EthernetProtocol::Ipv6 => {
+			ndisc_target(frame.payload(), Icmpv6Message::NeighborAdvert).map(IpAddress::Ipv6)
+		}
+		_ => None,
+	}
+}
+
+/// Returns the target address of a neighbor discovery message of type `msg_type`.
+#[cfg(feature = "ipv6")]
+fn ndisc_target(packet: &[u8], msg_type: Icmpv6Message) -> Option<Ipv6Address> {
+	let packet = Ipv6Packet::new_checked(packet).ok()?;
+	if packet.next_header() != IpProtocol::Icmpv6 {
+		return None;
+	}
+
+	let message = Icmpv6Packet::new_checked(packet.payload()).ok()?;
+	(message.msg_type() == msg_type).then(|| message.target_addr())
+}
+
+/// Neighbors, whose resolution is pending
+#[derive(Debug, Default)]
+pub(crate) struct Resolutions {
+	/// Solicited addresses and the time of their first solicitation
+	pending: Vec<(IpAddress, Instant)>,
+}
+
+impl Resolutions {
+	pub const fn new() -> Self {
+		Self {
+			pending: Vec::new(),
+		}
+	}
+
+	/// Records a solicitation of `addr`. Repeated solicitations keep the time
+	/// of the first one.
+	pub fn solicit(&mut self, addr: IpAddress, timestamp: Instant) {
+		if !self.pending.iter().any(|(pending, _)| *pending == addr) {
+			self.pending.push((addr, timestamp));
+		}
+	}
+
+	/// Records that `addr` is resolved.
+	pub fn resolve(&mut self, addr: IpAddress) {
+		self.pending.retain(|(pending, _)| *pending != addr);
+	}
+
+	/// Removes and returns the addresses, whose resolution is pending for
+	/// at least `timeout`.
+	pub fn expire(&mut self, timestamp: Instant, timeout: Duration) -> Vec<IpAddress> {
+		let mut expired = Vec::new();
+		self.pending.retain(|(addr, solicited)| {
+			if timestamp - *solicited >= timeout {
+				expired.push(*addr);
+				false
+			} else {
+				true
+			}
+		});
+		expired
+	}
+}
+
+/// Destinations of the datagrams, which are queued by UDP sockets
+///
+/// smoltcp doesn't reveal the destinations of its queued datagrams, but
+/// transmits them in order. Hence, the oldest datagrams of a socket are sent,
+/// if they exceed the bytes, which are still queued.
+#[cfg(feature = "udp")]
+#[derive(Debug, Default)]
+pub(crate) struct QueuedDatagrams {
+	/// Socket, destination and length of each queued datagram
+	datagrams: Vec<(SocketHandle, IpAddress, usize)>,
+}
+
+#[cfg(feature = "udp")]
+impl QueuedDatagrams {
+	pub const fn new() -> Self {
+		Self {
+			datagrams: Vec::new(),
+		}
+	}
+
+	/// Records a datagram of `len` bytes, which `handle` queues for `addr`.
+	pub fn push(&mut self, handle: SocketHandle, addr: IpAddress, len: usize) {
+		self.datagrams.push((handle, addr, len));
+	}
+
+	/// Forgets the sent datagrams of `handle`, which still queues `queued` bytes.
+	pub fn sync(&mut self, handle: SocketHandle, mut queued: usize) {
+		let sent = self.datagrams.iter().rposition(|(socket, _, len)| {
+			if *socket != handle {
+				return false;
+			}
+			if *len > queued {
+				return true;
+			}
+			queued -= len;
+			false
+		});
+
+		if let Some(sent) = sent {
+			let mut index = 0;
+			self.datagrams.retain(|(socket, _, _)| {
+				index += 1;
+				*socket != handle || index > sent + 1
+			});
+		}
+	}
+
+	/// Returns the destinations of the queued datagrams of `handle`.
+	pub fn destinations(&self, handle: SocketHandle) -> impl Iterator<Item = IpAddress> + '_ {
+		self.datagrams
+			.iter()
+			.filter(move |(socket, _, _)| *socket == handle)
+			.map(|(_, addr, _)| *addr)
+	}
+
+	/// Forgets all datagrams of `handle`.
+	pub fn remove(&mut self, handle: SocketHandle) {
+		self.datagrams.retain(|(socket, _, _)| *socket != handle);
+	}
+}
+
+/// Device, which records the solicitations and resolutions of neighbors
+pub(crate) struct NeighborProbe<'d, D> {
+	device: &'d mut D,
+	resolutions: &'d RefCell<Resolutions>,
+}
+
+impl<'d, D> NeighborProbe<'d, D> {
+	pub fn new(device: &'d mut D, resolutions: &'d RefCell<Resolutions>) -> Self {
+		Self {
+			device,
+			resolutions,
+		}
+	}
+}
+
+pub(crate) struct NeighborProbeRxToken<'d, T> {
+	token: T,
+	resolutions: &'d RefCell<Resolutions>,
+}
+
+impl<T: smoltcp::phy::RxToken> smoltcp::phy::RxToken for NeighborProbeRxToken<'_, T> {
+	fn consume<R, F>(self, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		let resolutions = self.resolutions;
+		self.token.consume(|buffer| {
+			if let Some(addr) = resolved(buffer) {
+				resolutions.borrow_mut().resolve(addr);
+			}
+			f(buffer)
+		})
+	}
+}
+
+pub(crate) struct NeighborProbeTxToken<'d, T> {
+	token: T,
+	resolutions: &'d RefCell<Resolutions>,
+	timestamp: Instant,
+}
+
+impl<T: smoltcp::phy::TxToken> smoltcp::phy::TxToken for NeighborProbeTxToken<'_, T> {
+	fn consume<R, F>(self, len: usize, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		let resolutions = self.resolutions;
+		let timestamp = self.timestamp;
+		self.token.consume(len, |buffer| {
+			let result = f(buffer);
+			if let Some(addr) = solicited(buffer) {
+				resolutions.borrow_mut().solicit(addr, timestamp);
+			}
+			result
+		})
+	}
+}
+
+impl<'d, D: Device> Device for NeighborProbe<'d, D> {
+	type RxToken<'a>
+		= NeighborProbeRxToken<'a, D::RxToken<'a>>
+	where
+		Self: 'a;
+	type TxToken<'a>
+		= NeighborProbeTxToken<'a, D::TxToken<'a>>
+	where
+		Self: 'a;
+
+	fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+		let resolutions = self.resolutions;
+		self.device.receive(timestamp).map(|(rx, tx)| {
+			(
+				NeighborProbeRxToken {
+					token: rx,
+					resolutions,
+				},
+				NeighborProbeTxToken {
+					token: tx,
+					resolutions,
+					timestamp,
+				},
+			)
+		})
+	}
+
+	fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+		let resolutions = self.resolutions;
+		self.device
+			.transmit(timestamp)
+			.map(|token| NeighborProbeTxToken {
+				token,
+				resolutions,
+				timestamp,
+			})
+	}
+
+	fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+		self.device.capabilities()
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use smoltcp::wire::{EthernetAddress, EthernetRepr, Ipv4Address};
+
+	use super::*;
+
+	fn arp_frame(operation: ArpOperation, source: Ipv4Address, target: Ipv4Address) -> Vec<u8> {
+		let arp = ArpRepr::EthernetIpv4 {
+			operation,
+			source_hardware_addr: EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+			source_protocol_addr: source,
+			target_hardware_addr: EthernetAddress([0; 6]),
+			target_protocol_addr: target,
+		};
+		let ethernet = EthernetRepr {
+			src_addr: EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+			dst_addr: EthernetAddress::BROADCAST,
+			ethertype: EthernetProtocol::Arp,
+		};
+		let mut buffer = vec![0; ethernet.buffer_len() + arp.buffer_len()];
+		let mut frame = EthernetFrame::new_unchecked(&mut buffer[..]);
+		ethernet.emit(&mut frame);
+		arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+		buffer
+	}
+
+	#[test]
+	fn expire_unanswered_solicitations() {
+		let host = Ipv4Address::new(10, 0, 5, 3);
+		let gateway = Ipv4Address::new(10, 0, 5, 1);
+		let peer = Ipv4Address::new(10, 0, 5, 2);
+
+		let request = arp_frame(ArpOperation::Request, host, gateway);
+		assert_eq!(solicited(&request), Some(IpAddress::Ipv4(gateway)));
+		let reply = arp_frame(ArpOperation::Reply, peer, host);
+		assert_eq!(solicited(&reply), None);
+		assert_eq!(resolved(&reply), Some(IpAddress::Ipv4(peer)));
+
+		let mut resolutions = Resolutions::new();
+		resolutions.solicit(IpAddress::Ipv4(gateway), Instant::from_secs(1));
+		resolutions.solicit(IpAddress::Ipv4(peer), Instant::from_secs(1));
+		resolutions.solicit(IpAddress::Ipv4(gateway), Instant::from_secs(2));
+		resolutions.resolve(IpAddress::Ipv4(peer));
+
+		assert!(resolutions
+			.expire(Instant::from_secs(3), DEFAULT_TIMEOUT)
+			.is_empty());
+		assert_eq!(
+			resolutions.expire(Instant::from_secs(4), DEFAULT_TIMEOUT),
+			[IpAddress::Ipv4(gateway)]
+		);
+		assert!(resolutions.pending.is_empty());
+	}
+
+	#[cfg(feature = "udp")]
+	#[test]
+	fn forget_sent_datagrams() {
+		use smoltcp::socket::udp;
+
+		let mut sockets = smoltcp::iface::SocketSet::new(Vec::new());
+		let mut add_socket = || {
+			let rx_buffer = udp::PacketBuffer::new(Vec::new(), Vec::new());
+			let tx_buffer = udp::PacketBuffer::new(Vec::new(), Vec::new());
+			sockets.add(udp::Socket::new(rx_buffer, tx_buffer))
+		};
+		let first = add_socket();
+		let second = add_socket();
+		let gateway = IpAddress::v4(10, 0, 5, 1);
+		let peer = IpAddress::v4(10, 0, 5, 2);
+
+		let mut queued = QueuedDatagrams::new();
+		queued.push(first, gateway, 4);
+		queued.push(second, gateway, 8);
+		queued.push(first, peer, 2);
+		queued.push(first, gateway, 3);
+
+		// only the last two datagrams of `first` are still queued
+		queued.sync(first, 5);
+		assert_eq!(
+			queued.destinations(first).collect::<Vec<_>>(),
+			[peer, gateway]
+		);
+		assert_eq!(queued.destinations(second).collect::<Vec<_>>(), [gateway]);
+
+		queued.sync(first, 5);
+		assert_eq!(queued.destinations(first).count(), 2);
+		queued.sync(first, 0);
+		assert_eq!(queued.destinations(first).count(), 0);
+
+		queued.remove(second);
+		assert!(queued.datagrams.is_empty());
+	}
+
+	#[test]
+	fn next_hop_of_remote_address() {
+		let cidrs = [IpCidr::new(IpAddress::v4(10, 0, 5, 3), 24)];
+		let routes = [Route::new_ipv4_gateway(Ipv4Address::new(10, 0, 5, 1))];
+
+		assert_eq!(
+			next_hop(IpAddress::v4(10, 0, 5, 2), &cidrs, &routes),
+			Some(IpAddress::v4(10, 0, 5, 2))
+		);
+		assert_eq!(
+			next_hop(IpAddress::v4(192, 168, 1, 1), &cidrs, &routes),
+			Some(IpAddress::v4(10, 0, 5, 1))
+		);
+		assert_eq!(next_hop(IpAddress::v4(192, 168, 1, 1), &cidrs, &[]), None);
+	}
+}
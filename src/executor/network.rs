@@ -1,17 +1,21 @@
 use alloc::boxed::Box;
-#[cfg(feature = "dns")]
+use alloc::format;
 use alloc::vec::Vec;
+use core::cell::RefCell;
 use core::future;
 use core::ops::DerefMut;
-use core::sync::atomic::{AtomicU16, Ordering};
-use core::task::Poll;
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
 
 use hermit_sync::InterruptTicketMutex;
 use smoltcp::iface::{SocketHandle, SocketSet};
+use smoltcp::phy::Device;
 #[cfg(feature = "dhcpv4")]
 use smoltcp::socket::dhcpv4;
 #[cfg(feature = "dns")]
 use smoltcp::socket::dns::{self, GetQueryResultError, QueryHandle};
+#[cfg(feature = "ipv6")]
+use smoltcp::socket::raw;
 #[cfg(feature = "tcp")]
 use smoltcp::socket::tcp;
 #[cfg(feature = "udp")]
@@ -19,16 +23,43 @@ use smoltcp::socket::udp;
 use smoltcp::socket::AnySocket;
 use smoltcp::time::{Duration, Instant};
 #[cfg(feature = "dns")]
-use smoltcp::wire::{DnsQueryType, IpAddress};
+use smoltcp::wire::DnsQueryType;
+#[cfg(any(feature = "dns", feature = "udp"))]
+use smoltcp::wire::IpAddress;
+#[cfg(any(feature = "dhcpv4", feature = "ipv6"))]
+use smoltcp::wire::IpCidr;
+#[cfg(feature = "tcp")]
+use smoltcp::wire::IpEndpoint;
+use smoltcp::wire::IpListenEndpoint;
+#[cfg(feature = "ipv6")]
+use smoltcp::wire::Ipv6Address;
 #[cfg(feature = "dhcpv4")]
-use smoltcp::wire::{IpCidr, Ipv4Address, Ipv4Cidr};
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
 
 use crate::arch;
-use crate::executor::device::HermitNet;
-use crate::executor::spawn;
-#[cfg(feature = "dns")]
+#[cfg(not(feature = "pci"))]
+use crate::arch::kernel::mmio as hardware;
+use crate::drivers::net::{self, NetworkDriver};
+#[cfg(feature = "pci")]
+use crate::drivers::pci as hardware;
+use crate::executor::device::{HermitNet, NET_STATS};
+#[cfg(feature = "tcp")]
+use crate::executor::initcwnd::InitialWindow;
+#[cfg(feature = "tcp")]
+use crate::executor::keepalive::{self, KeepAlive, KeepAliveProbe, Peer};
+#[cfg(feature = "ipv6")]
+use crate::executor::ndisc::{self, Slaac};
+#[cfg(feature = "udp")]
+use crate::executor::neighbor::QueuedDatagrams;
+use crate::executor::neighbor::{self, NeighborProbe, Resolutions};
+#[cfg(feature = "tcp")]
+use crate::executor::splice::Splice;
+#[cfg(feature = "tcp")]
+use crate::executor::tfo::CookieCache;
+use crate::executor::{announce, spawn};
 use crate::fd::IoError;
 use crate::scheduler::PerCoreSchedulerExt;
+use crate::synch::spsc::SpscRing;
 
 pub(crate) enum NetworkState<'a> {
 	Missing,
@@ -47,18 +78,111 @@ impl<'a> NetworkState<'a> {
 
 pub(crate) type Handle = SocketHandle;
 
+/// Maximal number of packets, which are received by a single call of
+/// [`NetworkInterface::poll_common`]. Bounds the time, which the network
+/// task holds the lock of the NIC.
+const RX_BUDGET: usize = 64;
+
+/// Maximal payload of a UDP datagram over IPv4 (65535 bytes minus the IPv4 and UDP header)
+#[cfg(feature = "udp")]
+const UDP_MAX_PAYLOAD_IPV4: usize = 65507;
+/// Maximal payload of a UDP datagram over IPv6 without jumbograms (65535 bytes minus the UDP header)
+#[cfg(feature = "udp")]
+pub(crate) const UDP_MAX_PAYLOAD_IPV6: usize = 65527;
+
 static LOCAL_ENDPOINT: AtomicU16 = AtomicU16::new(0);
+/// Set, if at least one receive queue of the network device is in poll mode.
+/// In this case, idle cores keep polling instead of waiting for interrupts.
+static BUSY_POLLING: AtomicBool = AtomicBool::new(false);
+/// Number of threads, which block in the executor and poll the network device,
+/// while its interrupts are disabled
+static POLLING_THREADS: AtomicUsize = AtomicUsize::new(0);
+/// Timestamps in microseconds of the network interrupts, which signal received
+/// packets to the network task. The interrupt handler produces the signals without
+/// taking the lock of the NIC. The network task only takes the lock, if signals
+/// are pending or the interface is due to be polled.
+static RX_SIGNALS: SpscRing<u64, 16> = SpscRing::new();
+/// Time in microseconds, at which the interface has to be polled next, e.g.
+/// because of a retransmission timeout. Zero enforces a poll at the next run
+/// of the network task.
+static POLL_DEADLINE: AtomicU64 = AtomicU64::new(0);
 pub(crate) static NIC: InterruptTicketMutex<NetworkState<'_>> =
 	InterruptTicketMutex::new(NetworkState::Missing);
+/// Tasks, which wait for the configuration of the interface, before the
+/// interface is initialized. Always locked after [`NIC`].
+static INIT_WAKERS: InterruptTicketMutex<Vec<Waker>> = InterruptTicketMutex::new(Vec::new());
 
-pub(crate) struct NetworkInterface<'a> {
+pub(crate) struct NetworkInterface<'a, D = HermitNet> {
 	pub(super) iface: smoltcp::iface::Interface,
 	pub(super) sockets: SocketSet<'a>,
-	pub(super) device: HermitNet,
+	pub(super) device: D,
 	#[cfg(feature = "dhcpv4")]
 	pub(super) dhcp_handle: SocketHandle,
 	#[cfg(feature = "dns")]
 	pub(super) dns_handle: Option<SocketHandle>,
+	#[cfg(feature = "ipv6")]
+	pub(super) slaac: Slaac,
+	/// Maximal number of TCP and UDP sockets
+	pub(super) max_sockets: usize,
+	/// UDP sockets, whose IPv4 datagrams are transmitted with a zero checksum
+	#[cfg(feature = "udp")]
+	pub(super) udp_zero_checksum: Vec<Handle>,
+	/// Destinations of the datagrams, which UDP sockets have queued
+	#[cfg(feature = "udp")]
+	pub(super) udp_queued: QueuedDatagrams,
+	/// Local endpoints of bound TCP sockets
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_bound: Vec<(Handle, IpListenEndpoint)>,
+	/// Fast open cookies of the servers, to which TCP sockets connected
+	#[cfg(feature = "tcp")]
+	pub(super) tfo_cookies: CookieCache,
+	/// Data of connecting TCP sockets, which is sent as soon as the connection is established
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_initial_data: Vec<(Handle, Vec<u8>)>,
+	/// Initial congestion window of new TCP sockets in segments
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_initcwnd: Option<u16>,
+	/// Initial windows of TCP sockets, whose peer hasn't acknowledged any data yet
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_initial_windows: Vec<(Handle, InitialWindow)>,
+	/// Low-water marks of TCP sockets, which differ from the default
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_low_water: Vec<(Handle, LowWater)>,
+	/// Keepalives of TCP sockets, whose peer has to answer the probes
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_keep_alives: Vec<(Handle, KeepAlive)>,
+	/// Number of unanswered keepalive probes, after which a TCP connection is dropped
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_keepalive_probes: u32,
+	/// Pairs of TCP sockets, which forward their data to each other
+	#[cfg(feature = "tcp")]
+	pub(super) tcp_splices: Vec<Splice>,
+	/// Neighbors, whose resolution is pending
+	pub(super) neighbors: Resolutions,
+	/// Time, after which an unresolved neighbor is considered unreachable
+	pub(super) neighbor_timeout: Duration,
+	/// Errors of sockets, which are reported by the next operation on the socket
+	pub(super) socket_errors: Vec<(Handle, IoError)>,
+	/// Tasks, which wait for the configuration of the interface
+	pub(super) config_wakers: Vec<Waker>,
+}
+
+/// Minimal number of bytes, which make a TCP socket readable or writable
+/// (`SO_RCVLOWAT` and `SO_SNDLOWAT`)
+#[cfg(feature = "tcp")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LowWater {
+	/// Minimal number of received bytes in the receive buffer
+	pub recv: usize,
+	/// Minimal number of free bytes in the transmit buffer
+	pub send: usize,
+}
+
+#[cfg(feature = "tcp")]
+impl Default for LowWater {
+	fn default() -> Self {
+		Self { recv: 1, send: 1 }
+	}
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -96,12 +220,81 @@ pub(crate) fn now() -> Instant {
 	Instant::from_micros_const(arch::kernel::systemtime::now_micros().try_into().unwrap())
 }
 
+/// Returns `true`, if the network device has to be polled continuously.
+#[inline]
+pub(crate) fn is_busy_polling() -> bool {
+	BUSY_POLLING.load(Ordering::Relaxed)
+}
+
+/// Registers that the calling thread starts (`true`) or stops (`false`) to poll
+/// the network device with disabled interrupts.
+pub(crate) fn set_polling_thread(polling: bool) {
+	if polling {
+		POLLING_THREADS.fetch_add(1, Ordering::Relaxed);
+	} else {
+		POLLING_THREADS.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// Signals the network task that the device received packets.
+///
+/// # Safety
+///
+/// The caller has to hold the lock of the network driver, which serializes
+/// the producers of the signals.
+pub(crate) unsafe fn signal_rx_ready() {
+	let _ = unsafe { RX_SIGNALS.push(arch::processor::get_timer_ticks()) };
+}
+
+/// Removes the pending signals of received packets and records the latency
+/// between each interrupt and its processing by the network task.
+///
+/// Consumes the signals. Hence, the caller has to hold the lock of the NIC.
+fn drain_rx_signals() {
+	let ticks = arch::processor::get_timer_ticks();
+	// SAFETY: the lock of the NIC serializes the consumers
+	while let Some(signaled) = unsafe { RX_SIGNALS.pop() } {
+		NET_STATS.record_irq_latency(ticks.saturating_sub(signaled));
+	}
+	NET_STATS
+		.irq_signal_overflows
+		.store(RX_SIGNALS.overflows(), Ordering::Relaxed);
+}
+
+/// Returns `true`, if the network task has to poll the interface.
+fn is_poll_due() -> bool {
+	!RX_SIGNALS.is_empty()
+		|| is_busy_polling()
+		|| POLLING_THREADS.load(Ordering::Relaxed) > 0
+		|| arch::processor::get_timer_ticks() >= POLL_DEADLINE.load(Ordering::Relaxed)
+}
+
+/// Polls the network interface, if received packets are signaled by an interrupt,
+/// a timer of the interface expires or the device has to be polled continuously.
+/// Each poll processes at most [`RX_BUDGET`] packets, which bounds the time spent
+/// in this task, if the device is busy polled.
 async fn network_run() {
-	future::poll_fn(|_cx| {
+	future::poll_fn(|cx| {
+		if !is_poll_due() {
+			return Poll::Pending;
+		}
+
 		if let Some(mut guard) = NIC.try_lock() {
+			// The state is checked after each acquisition of the lock,
+			// because the interface could be torn down, while we yielded.
 			match guard.deref_mut() {
 				NetworkState::Initialized(nic) => {
-					nic.poll_common(now());
+					drain_rx_signals();
+					if nic.poll_common(now()) {
+						// further packets are pending => release the lock and
+						// give other tasks the chance to use the NIC before
+						// we continue with the next chunk
+						cx.waker().wake_by_ref();
+					} else {
+						// The poll may have started socket timers, e.g. the
+						// retransmission timeout of a sent segment.
+						nic.schedule_timer();
+					}
 					Poll::Pending
 				}
 				_ => Poll::Ready(()),
@@ -114,11 +307,23 @@ async fn network_run() {
 	.await
 }
 
+/// Waits until the transmit buffer of the TCP socket `handle` has room for further data.
+#[cfg(feature = "tcp")]
+pub(crate) async fn tcp_send_ready(handle: Handle) -> Result<(), IoError> {
+	future::poll_fn(|cx| match NIC.lock().as_nic_mut() {
+		Ok(nic) => nic.poll_send_ready(handle, cx),
+		Err(_) => Poll::Ready(Err(IoError::EIO)),
+	})
+	.await
+}
+
 #[cfg(feature = "dns")]
 pub(crate) async fn get_query_result(query: QueryHandle) -> Result<Vec<IpAddress>, IoError> {
 	future::poll_fn(|cx| {
 		let mut guard = NIC.lock();
-		let nic = guard.as_nic_mut().unwrap();
+		let Ok(nic) = guard.as_nic_mut() else {
+			return Poll::Ready(Err(IoError::EIO));
+		};
 		let socket = nic.get_mut_dns_socket()?;
 		match socket.get_query_result(query) {
 			Ok(addrs) => {
@@ -153,176 +358,2672 @@ pub(crate) fn init() {
 	*guard = NetworkInterface::create();
 
 	if let NetworkState::Initialized(nic) = guard.deref_mut() {
-		let time = now();
-		nic.poll_common(time);
-		let wakeup_time = nic
-			.poll_delay(time)
-			.map(|d| crate::arch::processor::get_timer_ticks() + d.total_micros());
-		crate::core_scheduler().add_network_timer(wakeup_time);
+		nic.poll_common(now());
+		nic.schedule_timer();
+
+		let busy_polling =
+			hardware::get_network_driver().is_some_and(|driver| driver.lock().is_busy_polling());
+		BUSY_POLLING.store(busy_polling, Ordering::Relaxed);
 
 		spawn(network_run());
+
+		#[cfg(feature = "pcap")]
+		super::pcap::init(nic);
+		#[cfg(feature = "stats-server")]
+		super::stats::init(nic);
 	}
+
+	// The waiting tasks either find a configured interface (e.g. by a static
+	// address), wait for its configuration or fail, if there is no interface.
+	for waker in INIT_WAKERS.lock().drain(..) {
+		waker.wake();
+	}
+}
+
+/// Waits until the interface is configured, e.g. by a DHCP lease, SLAAC or a
+/// static address. Completes immediately, if the interface is already configured,
+/// and fails with `EIO`, if there is no interface.
+pub(crate) async fn wait_configured() -> Result<(), IoError> {
+	future::poll_fn(|cx| {
+		let mut guard = NIC.lock();
+		let nic = match guard.deref_mut() {
+			NetworkState::Initialized(nic) => nic,
+			NetworkState::Missing => {
+				// the interface is still being initialized
+				let mut wakers = INIT_WAKERS.lock();
+				if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+					wakers.push(cx.waker().clone());
+				}
+				return Poll::Pending;
+			}
+			NetworkState::InitializationFailed => return Poll::Ready(Err(IoError::EIO)),
+		};
+
+		if nic.is_configured() {
+			Poll::Ready(Ok(()))
+		} else {
+			if !nic.config_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+				nic.config_wakers.push(cx.waker().clone());
+			}
+			Poll::Pending
+		}
+	})
+	.await
+}
+
+/// Blocks until the interface is configured. Fails with `ETIME`, if the
+/// interface isn't configured within `timeout`.
+pub(crate) fn wait_configured_timeout(timeout: core::time::Duration) -> Result<(), IoError> {
+	crate::executor::block_on(wait_configured(), Some(timeout))
 }
 
 impl<'a> NetworkInterface<'a> {
-	#[cfg(feature = "udp")]
-	pub(crate) fn create_udp_handle(&mut self) -> Result<Handle, ()> {
-		let udp_rx_buffer =
-			udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 65535]);
-		let udp_tx_buffer =
-			udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 65535]);
-		let udp_socket = udp::Socket::new(udp_rx_buffer, udp_tx_buffer);
-		let udp_handle = self.sockets.add(udp_socket);
+	/// Arms the network timer of the current core for the next deadline of the interface.
+	///
+	/// The deadline covers the timers of all sockets, e.g. the retransmission timeout,
+	/// the delayed ACK and TIME_WAIT. Hence, a lost segment is retransmitted in time,
+	/// even if no further packets arrive.
+	pub(crate) fn schedule_timer(&mut self) {
+		let wakeup_time = self
+			.poll_delay(now())
+			.map(|d| arch::processor::get_timer_ticks() + d.total_micros());
+		crate::core_scheduler().add_network_timer(wakeup_time);
+	}
 
-		Ok(udp_handle)
+	/// Polls the interface and processes at most [`RX_BUDGET`] received packets.
+	///
+	/// Returns `true` if the budget is used up and further packets may be pending.
+	pub(crate) fn poll_common(&mut self, timestamp: Instant) -> bool {
+		if net::take_announce_request() {
+			self.announce(timestamp);
+			if let Some(driver) = hardware::get_network_driver() {
+				driver.lock().ack_announce();
+			}
+		}
+
+		self.device.set_rx_budget(RX_BUDGET);
+		self.poll_device(timestamp);
+		let exhausted = self.device.rx_budget_exhausted();
+
+		let deadline = if exhausted {
+			0
+		} else {
+			self.poll_delay(timestamp).map_or(u64::MAX, |delay| {
+				arch::processor::get_timer_ticks() + delay.total_micros()
+			})
+		};
+		POLL_DEADLINE.store(deadline, Ordering::Relaxed);
+
+		exhausted
 	}
+}
 
-	#[cfg(feature = "tcp")]
-	pub(crate) fn create_tcp_handle(&mut self) -> Result<Handle, ()> {
-		let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-		let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-		let mut tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
-		tcp_socket.set_nagle_enabled(true);
-		let tcp_handle = self.sockets.add(tcp_socket);
+impl<'a, D: Device> NetworkInterface<'a, D> {
+	/// Returns `true`, if an IPv4 address is assigned to the interface, either
+	/// statically or by DHCP, or an IPv6 address by SLAAC.
+	pub(crate) fn is_configured(&self) -> bool {
+		#[cfg(feature = "ipv6")]
+		if self.slaac.address.is_some() {
+			return true;
+		}
 
-		Ok(tcp_handle)
+		self.ipv4_addr().is_some()
 	}
 
-	pub(crate) fn poll_common(&mut self, timestamp: Instant) {
-		let _ = self
-			.iface
-			.poll(timestamp, &mut self.device, &mut self.sockets);
+	/// Returns the IPv4 address of the interface, if it is configured.
+	pub(crate) fn ipv4_addr(&self) -> Option<smoltcp::wire::Ipv4Address> {
+		self.iface.ipv4_addr().filter(|addr| !addr.is_unspecified())
+	}
 
-		#[cfg(feature = "dhcpv4")]
-		match self
-			.sockets
-			.get_mut::<dhcpv4::Socket<'_>>(self.dhcp_handle)
-			.poll()
-		{
-			None => {}
-			Some(dhcpv4::Event::Configured(config)) => {
-				info!("DHCP config acquired!");
-				info!("IP address:      {}", config.address);
-				self.iface.update_ip_addrs(|addrs| {
-					if let Some(dest) = addrs.iter_mut().next() {
-						*dest = IpCidr::Ipv4(config.address);
-					} else if addrs.push(IpCidr::Ipv4(config.address)).is_err() {
-						info!("Unable to update IP address");
-					}
-				});
-				if let Some(router) = config.router {
-					info!("Default gateway: {}", router);
-					self.iface
-						.routes_mut()
-						.add_default_ipv4_route(router)
-						.unwrap();
-				} else {
-					info!("Default gateway: None");
-					self.iface.routes_mut().remove_default_ipv4_route();
-				}
+	/// Returns the Ethernet address of the interface.
+	pub(crate) fn ethernet_addr(&self) -> Option<smoltcp::wire::EthernetAddress> {
+		use smoltcp::wire::HardwareAddress;
 
-				#[cfg(feature = "dns")]
-				let mut dns_servers: Vec<IpAddress> = Vec::new();
-				for (i, s) in config.dns_servers.iter().enumerate() {
-					info!("DNS server {}:    {}", i, s);
-					#[cfg(feature = "dns")]
-					dns_servers.push(IpAddress::Ipv4(*s));
-				}
+		let HardwareAddress::Ethernet(mac) = self.iface.hardware_addr() else {
+			return None;
+		};
+		Some(mac)
+	}
 
-				#[cfg(feature = "dns")]
-				if dns_servers.len() > 0 {
-					let dns_socket = dns::Socket::new(dns_servers.as_slice(), vec![]);
-					self.dns_handle = Some(self.sockets.add(dns_socket));
-				}
+	/// Wakes up the tasks, which wait for the configuration of the interface.
+	#[cfg(any(feature = "dhcpv4", feature = "ipv6"))]
+	fn wake_config_waiters(&mut self) {
+		for waker in self.config_wakers.drain(..) {
+			waker.wake();
+		}
+	}
+
+	/// Announces all addresses of the interface, e.g. after a live migration.
+	pub(crate) fn announce(&mut self, timestamp: Instant) {
+		use smoltcp::phy::TxToken;
+		use smoltcp::wire::HardwareAddress;
+
+		let HardwareAddress::Ethernet(mac) = self.iface.hardware_addr() else {
+			return;
+		};
+
+		for cidr in self.iface.ip_addrs() {
+			let frame = announce::frame(mac, cidr.address());
+			if let Some(token) = self.device.transmit(timestamp) {
+				token.consume(frame.len(), |buffer| buffer.copy_from_slice(&frame));
 			}
-			Some(dhcpv4::Event::Deconfigured) => {
-				info!("DHCP lost config!");
-				let cidr = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
-				self.iface.update_ip_addrs(|addrs| {
-					if let Some(dest) = addrs.iter_mut().next() {
-						*dest = IpCidr::Ipv4(cidr);
-					}
-				});
-				self.iface.routes_mut().remove_default_ipv4_route();
+		}
+	}
 
-				#[cfg(feature = "dns")]
-				{
-					if let Some(dns_handle) = self.dns_handle {
-						self.sockets.remove(dns_handle);
-					}
+	/// Limits the number of TCP and UDP sockets, which may exist at the same time.
+	pub(crate) fn with_max_sockets(mut self, max_sockets: usize) -> Self {
+		self.max_sockets = max_sockets;
+		self
+	}
 
-					self.dns_handle = None;
+	/// Sets the time, after which an unresolved neighbor is considered unreachable.
+	pub(crate) fn with_neighbor_timeout(mut self, timeout: Duration) -> Self {
+		self.neighbor_timeout = timeout;
+		self
+	}
+
+	/// Sends `hostname` as host name option (12) in the requests of the DHCP client.
+	#[cfg(feature = "dhcpv4")]
+	pub(crate) fn with_dhcp_hostname(mut self, hostname: &str) -> Self {
+		use smoltcp::wire::DhcpOption;
+
+		const OPT_HOST_NAME: u8 = 12;
+
+		// The interface isn't destroyed, hence the options are allocated only once.
+		let data: &'static [u8] = Box::leak(hostname.as_bytes().into());
+		let options: &'static [DhcpOption<'static>] = Box::leak(Box::new([DhcpOption {
+			kind: OPT_HOST_NAME,
+			data,
+		}]));
+		self.sockets
+			.get_mut::<dhcpv4::Socket<'a>>(self.dhcp_handle)
+			.set_outgoing_options(options);
+		self
+	}
+
+	/// Removes and returns the pending error of the socket `handle`, e.g. because
+	/// its next hop is unreachable.
+	pub(crate) fn take_socket_error(&mut self, handle: Handle) -> Option<IoError> {
+		let index = self
+			.socket_errors
+			.iter()
+			.position(|(socket, _)| *socket == handle)?;
+		Some(self.socket_errors.swap_remove(index).1)
+	}
+
+	/// Fails the sockets, which wait for a neighbor, whose resolution is pending
+	/// for longer than the timeout, with `EHOSTUNREACH`.
+	///
+	/// TCP sockets are aborted, if they connect or have unacknowledged data for
+	/// the unreachable next hop. Because a blocked datagram stalls the whole
+	/// socket, the queued datagrams of UDP sockets, which have a datagram for
+	/// the unreachable next hop queued, are dropped.
+	fn fail_unresolved_neighbors(&mut self, timestamp: Instant) {
+		let unresolved = self.neighbors.expire(timestamp, self.neighbor_timeout);
+		if unresolved.is_empty() {
+			return;
+		}
+
+		for addr in unresolved.iter() {
+			warn!("Neighbor {addr} is unreachable");
+		}
+		let cidrs = self.iface.ip_addrs().to_vec();
+		let mut routes = Vec::new();
+		self.iface
+			.routes_mut()
+			.update(|table| routes.extend(table.iter().cloned()));
+
+		for (handle, socket) in self.sockets.iter_mut() {
+			#[cfg(feature = "tcp")]
+			if let Some(socket) = tcp::Socket::downcast_mut(socket) {
+				let waiting = socket.state() == tcp::State::SynSent || socket.send_queue() > 0;
+				let next_hop = socket
+					.remote_endpoint()
+					.and_then(|remote| neighbor::next_hop(remote.addr, &cidrs, &routes));
+				if waiting && next_hop.is_some_and(|next_hop| unresolved.contains(&next_hop)) {
+					socket.abort();
+					self.socket_errors.push((handle, IoError::EHOSTUNREACH));
 				}
+				continue;
 			}
-		};
+
+			#[cfg(feature = "udp")]
+			if let Some(socket) = udp::Socket::downcast_mut(socket) {
+				self.udp_queued.sync(handle, socket.send_queue());
+				let waiting = self.udp_queued.destinations(handle).any(|addr| {
+					neighbor::next_hop(addr, &cidrs, &routes)
+						.is_some_and(|next_hop| unresolved.contains(&next_hop))
+				});
+				if waiting {
+					let endpoint = socket.endpoint();
+					socket.close();
+					let _ = socket.bind(endpoint);
+					self.udp_queued.remove(handle);
+					self.socket_errors.push((handle, IoError::EHOSTUNREACH));
+				}
+			}
+		}
 	}
 
-	pub(crate) fn poll_delay(&mut self, timestamp: Instant) -> Option<Duration> {
-		self.iface.poll_delay(timestamp, &self.sockets)
+	/// Sets the number of unanswered keepalive probes, after which a TCP connection
+	/// is dropped. `probes` has to be validated by [`keepalive::parse`].
+	#[cfg(feature = "tcp")]
+	pub(crate) fn with_tcp_keepalive_probes(mut self, probes: u32) -> Self {
+		self.tcp_keepalive_probes = probes;
+		self
 	}
 
-	#[allow(dead_code)]
-	pub(crate) fn get_socket<T: AnySocket<'a>>(&self, handle: SocketHandle) -> &T {
-		self.sockets.get(handle)
+	/// Returns the time, at which the TCP connection of `handle` is dropped, because
+	/// its peer doesn't answer the keepalive probes. Returns `None`, if the connection
+	/// isn't synchronized.
+	#[cfg(feature = "tcp")]
+	fn tcp_keepalive_deadline(&self, handle: Handle, keep_alive: &KeepAlive) -> Option<Instant> {
+		let socket = self.sockets.get::<tcp::Socket<'_>>(handle);
+		if socket.may_send() || socket.may_recv() {
+			keep_alive.deadline(self.tcp_keepalive_probes)
+		} else {
+			None
+		}
 	}
 
-	pub(crate) fn get_mut_socket<T: AnySocket<'a>>(&mut self, handle: SocketHandle) -> &mut T {
-		self.sockets.get_mut(handle)
+	/// Aborts the TCP connections, whose peer doesn't answer the keepalive probes,
+	/// and fails them with `ETIMEDOUT`.
+	#[cfg(feature = "tcp")]
+	fn fail_idle_connections(&mut self, timestamp: Instant) {
+		let mut expired = Vec::new();
+		for (handle, keep_alive) in self.tcp_keep_alives.iter() {
+			let deadline = self.tcp_keepalive_deadline(*handle, keep_alive);
+			if deadline.is_some_and(|deadline| deadline <= timestamp) {
+				expired.push(*handle);
+			}
+		}
+
+		for handle in expired {
+			warn!("Peer of TCP socket {handle} doesn't answer the keepalive probes");
+			self.sockets.get_mut::<tcp::Socket<'_>>(handle).abort();
+			self.socket_errors.push((handle, IoError::ETIMEDOUT));
+		}
 	}
 
-	pub(crate) fn get_socket_and_context<T: AnySocket<'a>>(
-		&mut self,
-		handle: SocketHandle,
-	) -> (&mut T, &mut smoltcp::iface::Context) {
-		(self.sockets.get_mut(handle), self.iface.context())
+	/// Enables keepalive probes of the TCP socket `handle` after `interval` of
+	/// idle time. If the peer doesn't answer the configured number of probes,
+	/// the connection is dropped. `None` disables the probes.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn set_tcp_keep_alive(&mut self, handle: Handle, interval: Option<Duration>) {
+		self.sockets
+			.get_mut::<tcp::Socket<'_>>(handle)
+			.set_keep_alive(interval);
+		self.tcp_keep_alives.retain(|(other, _)| *other != handle);
+		if let Some(interval) = interval {
+			self.tcp_keep_alives
+				.push((handle, KeepAlive::new(interval)));
+		}
 	}
 
-	pub(crate) fn destroy_socket(&mut self, handle: Handle) {
-		// This deallocates the socket's buffers
-		self.sockets.remove(handle);
+	/// Forwards the data of the TCP sockets `a` and `b` to each other, until both
+	/// directions are finished or a connection is reset. Both sockets have to
+	/// connect or listen. Fails with `EINVAL`, if a socket is already spliced.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn splice(&mut self, a: Handle, b: Handle) -> Result<(), IoError> {
+		let spliced = self
+			.tcp_splices
+			.iter()
+			.any(|splice| splice.contains(a) || splice.contains(b));
+		if a == b || spliced {
+			return Err(IoError::EINVAL);
+		}
+
+		self.tcp_splices.push(Splice::new(a, b));
+		Ok(())
 	}
 
-	#[cfg(feature = "dns")]
-	pub(crate) fn start_query(
-		&mut self,
-		name: &str,
-		query_type: DnsQueryType,
-	) -> Result<QueryHandle, IoError> {
-		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
-		let socket: &mut dns::Socket<'a> = self.sockets.get_mut(dns_handle);
-		socket
-			.start_query(self.iface.context(), name, query_type)
-			.map_err(|_| IoError::EIO)
+	/// Returns the keepalive interval of the TCP socket `handle`.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_keep_alive(&self, handle: Handle) -> Option<Duration> {
+		self.tcp_keep_alives
+			.iter()
+			.find(|(keep_alive, _)| *keep_alive == handle)
+			.map(|(_, keep_alive)| keep_alive.interval)
 	}
 
-	#[allow(dead_code)]
-	#[cfg(feature = "dns")]
-	pub(crate) fn get_dns_socket(&self) -> Result<&dns::Socket<'a>, IoError> {
-		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
-		Ok(self.sockets.get(dns_handle))
+	/// Limits the data, which new TCP sockets send before the first acknowledgment,
+	/// to `segments` segments. `segments` has to be validated by
+	/// [`initcwnd::parse`](crate::executor::initcwnd::parse).
+	#[cfg(feature = "tcp")]
+	pub(crate) fn with_tcp_initcwnd(mut self, segments: u16) -> Self {
+		self.tcp_initcwnd = Some(segments);
+		self
 	}
 
-	#[cfg(feature = "dns")]
-	pub(crate) fn get_mut_dns_socket(&mut self) -> Result<&mut dns::Socket<'a>, IoError> {
-		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
-		Ok(self.sockets.get_mut(dns_handle))
+	/// Fails with `EMFILE`, if the limit of TCP and UDP sockets is reached.
+	fn check_socket_limit(&self) -> Result<(), IoError> {
+		if self.max_sockets == usize::MAX {
+			return Ok(());
+		}
+
+		let count = self
+			.sockets
+			.iter()
+			.filter(|(_, socket)| {
+				#[cfg(feature = "tcp")]
+				if tcp::Socket::downcast(socket).is_some() {
+					return true;
+				}
+				#[cfg(feature = "udp")]
+				if udp::Socket::downcast(socket).is_some() {
+					return true;
+				}
+				false
+			})
+			.count();
+
+		if count < self.max_sockets {
+			Ok(())
+		} else {
+			Err(IoError::EMFILE)
+		}
 	}
-}
 
-#[inline]
-pub(crate) fn network_delay(timestamp: Instant) -> Option<Duration> {
-	crate::executor::network::NIC
-		.lock()
-		.as_nic_mut()
-		.unwrap()
-		.poll_delay(timestamp)
-}
+	#[cfg(feature = "udp")]
+	pub(crate) fn create_udp_handle(&mut self) -> Result<Handle, IoError> {
+		self.check_socket_limit()?;
 
-#[inline]
-fn network_poll(timestamp: Instant) {
-	crate::executor::network::NIC
-		.lock()
-		.as_nic_mut()
-		.unwrap()
-		.poll_common(timestamp);
+		let udp_rx_buffer =
+			udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 65535]);
+		let udp_tx_buffer =
+			udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 65535]);
+		let udp_socket = udp::Socket::new(udp_rx_buffer, udp_tx_buffer);
+		let udp_handle = self.sockets.add(udp_socket);
+
+		Ok(udp_handle)
+	}
+
+	/// Enables or disables the checksum of the IPv4 datagrams, which are sent by the UDP socket `handle`.
+	///
+	/// A zero checksum is only legal for IPv4. Hence, sockets, which are bound to an IPv6
+	/// address, are rejected and IPv6 datagrams of the socket are always sent with a checksum.
+	/// If the network device offloads the checksum calculation, the device may still fill in
+	/// the checksum.
+	#[cfg(feature = "udp")]
+	pub(crate) fn set_udp_checksum(
+		&mut self,
+		handle: Handle,
+		enabled: bool,
+	) -> Result<(), IoError> {
+		let endpoint = self.sockets.get::<udp::Socket<'_>>(handle).endpoint();
+		if matches!(endpoint.addr, Some(IpAddress::Ipv6(_))) {
+			return Err(IoError::EINVAL);
+		}
+
+		self.udp_zero_checksum
+			.retain(|zero_checksum| *zero_checksum != handle);
+		if !enabled {
+			self.udp_zero_checksum.push(handle);
+		}
+
+		Ok(())
+	}
+
+	/// Returns `true`, if the UDP socket `handle` sends its IPv4 datagrams with a checksum.
+	#[cfg(feature = "udp")]
+	pub(crate) fn udp_checksum(&self, handle: Handle) -> bool {
+		!self.udp_zero_checksum.contains(&handle)
+	}
+
+	/// Enqueues the datagram `payload` on the UDP socket `handle`.
+	///
+	/// Fails with `EMSGSIZE`, if the datagram can't be sent at all, because it exceeds
+	/// the maximal datagram size of the IP version or the capacity of the send buffer.
+	/// Fails with `EAGAIN`, if the send buffer has currently not enough space.
+	#[cfg(feature = "udp")]
+	pub(crate) fn udp_send_slice(
+		&mut self,
+		handle: Handle,
+		payload: &[u8],
+		meta: udp::UdpMetadata,
+	) -> Result<(), IoError> {
+		if let Some(err) = self.take_socket_error(handle) {
+			return Err(err);
+		}
+
+		let socket = self.sockets.get_mut::<udp::Socket<'_>>(handle);
+		let max_payload = match meta.endpoint.addr {
+			IpAddress::Ipv4(_) => UDP_MAX_PAYLOAD_IPV4,
+			IpAddress::Ipv6(_) => UDP_MAX_PAYLOAD_IPV6,
+		};
+		if payload.len() > max_payload.min(socket.payload_send_capacity()) {
+			return Err(IoError::EMSGSIZE);
+		}
+
+		socket.send_slice(payload, meta).map_err(|err| match err {
+			udp::SendError::BufferFull => IoError::EAGAIN,
+			udp::SendError::Unaddressable => IoError::EINVAL,
+		})?;
+		let queued = socket.send_queue();
+		self.udp_queued
+			.push(handle, meta.endpoint.addr, payload.len());
+		self.udp_queued.sync(handle, queued);
+		Ok(())
+	}
+
+	#[cfg(feature = "tcp")]
+	pub(crate) fn create_tcp_handle(&mut self) -> Result<Handle, IoError> {
+		self.check_socket_limit()?;
+
+		let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
+		let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
+		let mut tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
+		tcp_socket.set_nagle_enabled(true);
+		let tcp_handle = self.sockets.add(tcp_socket);
+
+		if let Some(segments) = self.tcp_initcwnd {
+			let window = InitialWindow::new(segments, self.tcp_mss());
+			self.tcp_initial_windows.push((tcp_handle, window));
+		}
+
+		Ok(tcp_handle)
+	}
+
+	/// Maximal segment size of the device. The options of the TCP header
+	/// and the IPv6 header, which is larger than the IPv4 header, are ignored.
+	#[cfg(feature = "tcp")]
+	fn tcp_mss(&self) -> usize {
+		let capabilities = self.device.capabilities();
+		let link_header = match capabilities.medium {
+			smoltcp::phy::Medium::Ethernet => smoltcp::wire::EthernetFrame::<&[u8]>::header_len(),
+			_ => 0,
+		};
+		capabilities
+			.max_transmission_unit
+			.saturating_sub(link_header + 40)
+			.max(1)
+	}
+
+	/// Returns the initial window of the TCP socket `handle`, as long as the peer
+	/// hasn't acknowledged any data.
+	#[cfg(feature = "tcp")]
+	fn tcp_initial_window(&mut self, handle: Handle) -> Option<&mut InitialWindow> {
+		let index = self
+			.tcp_initial_windows
+			.iter()
+			.position(|(initial_window, _)| *initial_window == handle)?;
+		let send_queue = self.sockets.get::<tcp::Socket<'_>>(handle).send_queue();
+		if self.tcp_initial_windows[index].1.is_acked(send_queue) {
+			self.tcp_initial_windows.swap_remove(index);
+			return None;
+		}
+
+		Some(&mut self.tcp_initial_windows[index].1)
+	}
+
+	/// Returns the low-water marks of the TCP socket `handle`.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_low_water(&self, handle: Handle) -> LowWater {
+		self.tcp_low_water
+			.iter()
+			.find(|(low_water, _)| *low_water == handle)
+			.map_or_else(LowWater::default, |(_, low_water)| *low_water)
+	}
+
+	/// Sets the low-water marks of the TCP socket `handle`. Like on Linux, a mark
+	/// of zero is treated as one byte. Fails with `EINVAL`, if a mark exceeds
+	/// the size of the respective buffer.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn set_tcp_low_water(
+		&mut self,
+		handle: Handle,
+		low_water: LowWater,
+	) -> Result<(), IoError> {
+		let low_water = LowWater {
+			recv: low_water.recv.max(1),
+			send: low_water.send.max(1),
+		};
+		let socket = self.sockets.get::<tcp::Socket<'_>>(handle);
+		if low_water.recv > socket.recv_capacity() || low_water.send > socket.send_capacity() {
+			return Err(IoError::EINVAL);
+		}
+
+		self.tcp_low_water.retain(|(other, _)| *other != handle);
+		if low_water != LowWater::default() {
+			self.tcp_low_water.push((handle, low_water));
+		}
+		Ok(())
+	}
+
+	/// Returns `true`, if the receive buffer of the TCP socket `handle` holds
+	/// at least the receive low-water mark.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_can_recv(&self, handle: Handle) -> bool {
+		let low_water = self.tcp_low_water(handle);
+		self.sockets.get::<tcp::Socket<'_>>(handle).recv_queue() >= low_water.recv
+	}
+
+	/// Returns `true`, if a read of `len` bytes from the TCP socket `handle`
+	/// doesn't have to wait, i.e. the receive buffer holds at least the receive
+	/// low-water mark or `len` bytes, whichever is smaller.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_can_read(&self, handle: Handle, len: usize) -> bool {
+		let low_water = self.tcp_low_water(handle);
+		self.sockets.get::<tcp::Socket<'_>>(handle).recv_queue() >= low_water.recv.min(len)
+	}
+
+	/// Returns `true`, if the TCP socket `handle` may enqueue further data, i.e.
+	/// the transmit buffer has room for at least the send low-water mark.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_can_send(&mut self, handle: Handle) -> bool {
+		let exhausted = self
+			.tcp_initial_window(handle)
+			.is_some_and(|window| window.is_exhausted());
+		let low_water = self.tcp_low_water(handle);
+		let socket = self.sockets.get::<tcp::Socket<'_>>(handle);
+		!exhausted
+			&& socket.can_send()
+			&& socket.send_capacity() - socket.send_queue() >= low_water.send
+	}
+
+	/// Enqueues `data` into the transmit buffer of the TCP socket `handle` and
+	/// returns the number of enqueued bytes. Before the first acknowledgment, the
+	/// data is limited by the initial congestion window.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_send_slice(&mut self, handle: Handle, data: &[u8]) -> Result<usize, IoError> {
+		if let Some(err) = self.take_socket_error(handle) {
+			return Err(err);
+		}
+
+		let Some(window) = self.tcp_initial_window(handle) else {
+			return self
+				.sockets
+				.get_mut::<tcp::Socket<'_>>(handle)
+				.send_slice(data)
+				.map_err(|_| IoError::EIO);
+		};
+
+		let len = window.admit(data.len());
+		let len = self
+			.sockets
+			.get_mut::<tcp::Socket<'_>>(handle)
+			.send_slice(&data[..len])
+			.map_err(|_| IoError::EIO)?;
+		if let Some(window) = self.tcp_initial_window(handle) {
+			window.consume(len);
+		}
+
+		Ok(len)
+	}
+
+	/// Connects the TCP socket `handle` to `remote` and sends `initial_data`.
+	///
+	/// With a cached fast open cookie of the server, the data should be part of
+	/// the SYN. smoltcp can't send data in the SYN. Hence, the connection is always
+	/// established by a regular handshake and `initial_data` is sent by
+	/// [`poll_device`](Self::poll_device), as soon as the connection is established.
+	/// This is also the behavior for the first connection to a server, for which
+	/// no cookie is known.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_connect_with_data(
+		&mut self,
+		handle: Handle,
+		remote: IpEndpoint,
+		local_port: u16,
+		initial_data: &[u8],
+	) -> Result<(), IoError> {
+		if self.tfo_cookies.get(remote.addr).is_some() {
+			trace!("Fast open isn't supported, connect to {remote} without data in the SYN");
+		}
+
+		let socket = self.sockets.get_mut::<tcp::Socket<'_>>(handle);
+		if initial_data.len() > socket.send_capacity() {
+			return Err(IoError::EMSGSIZE);
+		}
+		socket
+			.connect(self.iface.context(), remote, local_port)
+			.map_err(|_| IoError::EIO)?;
+
+		if !initial_data.is_empty() {
+			self.tcp_initial_data.push((handle, initial_data.to_vec()));
+		}
+
+		Ok(())
+	}
+
+	/// Moves the initial data of established connections into the transmit buffers.
+	#[cfg(feature = "tcp")]
+	fn send_initial_data(&mut self) {
+		let sockets = &mut self.sockets;
+		self.tcp_initial_data.retain(|(handle, data)| {
+			let socket = sockets.get_mut::<tcp::Socket<'_>>(*handle);
+			match socket.state() {
+				tcp::State::SynSent | tcp::State::SynReceived => true,
+				tcp::State::Established | tcp::State::CloseWait => {
+					// The capacity of the transmit buffer is checked on connect
+					// and the buffer is still empty.
+					if socket.send_slice(data) != Ok(data.len()) {
+						warn!("Unable to send the initial data of a TCP connection");
+					}
+					false
+				}
+				_ => false,
+			}
+		});
+	}
+
+	/// Polls the interface on top of the underlying device.
+	pub(crate) fn poll_device(&mut self, timestamp: Instant) {
+		let resolutions = RefCell::new(core::mem::take(&mut self.neighbors));
+		let mut device = NeighborProbe::new(&mut self.device, &resolutions);
+		#[cfg(feature = "tcp")]
+		let peers = RefCell::new(self.tcp_keepalive_peers());
+		#[cfg(feature = "tcp")]
+		let mut device = KeepAliveProbe::new(&mut device, &peers);
+		#[cfg(feature = "udp")]
+		if !self.udp_zero_checksum.is_empty() {
+			let ports = self
+				.udp_zero_checksum
+				.iter()
+				.map(|handle| self.sockets.get::<udp::Socket<'_>>(*handle).endpoint().port)
+				.filter(|port| *port != 0)
+				.collect::<Vec<_>>();
+			let mut device = ZeroUdpChecksum {
+				device: &mut device,
+				ports: &ports,
+			};
+			let _ = self.iface.poll(timestamp, &mut device, &mut self.sockets);
+		} else {
+			let _ = self.iface.poll(timestamp, &mut device, &mut self.sockets);
+		}
+		#[cfg(not(feature = "udp"))]
+		let _ = self.iface.poll(timestamp, &mut device, &mut self.sockets);
+		self.neighbors = resolutions.into_inner();
+		self.fail_unresolved_neighbors(timestamp);
+		#[cfg(feature = "tcp")]
+		{
+			let peers = peers.into_inner();
+			for ((_, keep_alive), peer) in self.tcp_keep_alives.iter_mut().zip(peers) {
+				keep_alive.polled(timestamp, peer.heard);
+			}
+			self.fail_idle_connections(timestamp);
+
+			// The forwarded data is transmitted by the next poll, which is due immediately.
+			let sockets = &mut self.sockets;
+			self.tcp_splices
+				.retain_mut(|splice| splice.forward(sockets));
+		}
+
+		#[cfg(feature = "dhcpv4")]
+		match self
+			.sockets
+			.get_mut::<dhcpv4::Socket<'_>>(self.dhcp_handle)
+			.poll()
+		{
+			None => {}
+			Some(dhcpv4::Event::Configured(config)) => {
+				info!("DHCP config acquired!");
+				info!("IP address:      {}", config.address);
+				self.iface.update_ip_addrs(|addrs| {
+					if let Some(dest) = addrs
+						.iter_mut()
+						.find(|cidr| matches!(cidr, IpCidr::Ipv4(_)))
+					{
+						*dest = IpCidr::Ipv4(config.address);
+					} else if addrs.push(IpCidr::Ipv4(config.address)).is_err() {
+						info!("Unable to update IP address");
+					}
+				});
+				self.wake_config_waiters();
+				if let Some(router) = config.router {
+					info!("Default gateway: {}", router);
+					self.iface
+						.routes_mut()
+						.add_default_ipv4_route(router)
+						.unwrap();
+				} else {
+					info!("Default gateway: None");
+					self.iface.routes_mut().remove_default_ipv4_route();
+				}
+
+				#[cfg(feature = "dns")]
+				let mut dns_servers: Vec<IpAddress> = Vec::new();
+				for (i, s) in config.dns_servers.iter().enumerate() {
+					info!("DNS server {}:    {}", i, s);
+					#[cfg(feature = "dns")]
+					dns_servers.push(IpAddress::Ipv4(*s));
+				}
+
+				#[cfg(feature = "dns")]
+				if dns_servers.len() > 0 {
+					// DNS servers of DHCP replace the recursive DNS servers of SLAAC
+					if let Some(dns_handle) = self.dns_handle.take() {
+						self.sockets.remove(dns_handle);
+					}
+					#[cfg(feature = "ipv6")]
+					{
+						self.slaac.dns_socket = false;
+					}
+
+					let dns_socket = dns::Socket::new(dns_servers.as_slice(), vec![]);
+					self.dns_handle = Some(self.sockets.add(dns_socket));
+				}
+			}
+			Some(dhcpv4::Event::Deconfigured) => {
+				info!("DHCP lost config!");
+				let cidr = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
+				self.iface.update_ip_addrs(|addrs| {
+					if let Some(dest) = addrs
+						.iter_mut()
+						.find(|cidr| matches!(cidr, IpCidr::Ipv4(_)))
+					{
+						*dest = IpCidr::Ipv4(cidr);
+					}
+				});
+				self.iface.routes_mut().remove_default_ipv4_route();
+
+				#[cfg(feature = "dns")]
+				{
+					if let Some(dns_handle) = self.dns_handle {
+						self.sockets.remove(dns_handle);
+					}
+
+					self.dns_handle = None;
+				}
+			}
+		};
+
+		#[cfg(feature = "ipv6")]
+		self.poll_slaac(timestamp);
+
+		#[cfg(feature = "tcp")]
+		self.send_initial_data();
+	}
+
+	/// Evaluates received router advertisements and removes the parts of the
+	/// configuration, whose lifetime is expired.
+	#[cfg(feature = "ipv6")]
+	fn poll_slaac(&mut self, timestamp: Instant) {
+		let mut advert = None;
+		let socket = self.sockets.get_mut::<raw::Socket<'_>>(self.slaac.handle);
+		while let Ok(packet) = socket.recv() {
+			if let Some(ra) = ndisc::parse(packet) {
+				advert = Some(ra);
+			}
+		}
+
+		if let Some(advert) = advert {
+			self.apply_router_advert(advert, timestamp);
+		}
+
+		if let Some((cidr, expiry)) = self.slaac.address {
+			if expiry <= timestamp {
+				info!("SLAAC address {} expired", cidr);
+				self.remove_slaac_address();
+			}
+		}
+
+		if let Some((router, expiry)) = self.slaac.router {
+			if expiry <= timestamp {
+				info!("IPv6 gateway {} expired", router);
+				self.remove_slaac_router();
+			}
+		}
+
+		if let Some((_, expiry)) = self.slaac.dns_servers {
+			if expiry <= timestamp {
+				info!("IPv6 DNS servers expired");
+				self.update_slaac_dns_servers(None);
+			}
+		}
+	}
+
+	/// Applies the configuration of a router advertisement. A lifetime of zero
+	/// withdraws the corresponding part of the configuration immediately.
+	#[cfg(feature = "ipv6")]
+	fn apply_router_advert(&mut self, advert: ndisc::RouterAdvert, timestamp: Instant) {
+		if let Some(prefix) = advert.prefix {
+			let cidr = ndisc::slaac_address(prefix.prefix, self.slaac.mac);
+			let current = self.slaac.address.map(|(cidr, _)| cidr);
+			if prefix.valid_lifetime == Duration::ZERO {
+				if current == Some(cidr) {
+					info!("SLAAC address {} withdrawn", cidr);
+					self.remove_slaac_address();
+				}
+			} else {
+				if current != Some(cidr) {
+					// the router announces a new prefix, which replaces the old address
+					self.remove_slaac_address();
+					info!("SLAAC address:   {}", cidr);
+					self.iface.update_ip_addrs(|addrs| {
+						if addrs.push(IpCidr::Ipv6(cidr)).is_err() {
+							info!("Unable to add IPv6 address");
+						}
+					});
+				}
+				self.slaac.address = Some((cidr, timestamp + prefix.valid_lifetime));
+				self.wake_config_waiters();
+			}
+		}
+
+		if advert.router_lifetime == Duration::ZERO {
+			if self
+				.slaac
+				.router
+				.is_some_and(|(router, _)| router == advert.router)
+			{
+				info!("IPv6 gateway {} withdrawn", advert.router);
+				self.remove_slaac_router();
+			}
+		} else {
+			if self.slaac.router.map(|(router, _)| router) != Some(advert.router) {
+				info!("IPv6 gateway:    {}", advert.router);
+				if self
+					.iface
+					.routes_mut()
+					.add_default_ipv6_route(advert.router)
+					.is_err()
+				{
+					info!("Unable to add IPv6 default route");
+				}
+			}
+			self.slaac.router = Some((advert.router, timestamp + advert.router_lifetime));
+		}
+
+		if !advert.dns_servers.is_empty() {
+			if advert.dns_lifetime == Duration::ZERO {
+				info!("IPv6 DNS servers withdrawn");
+				self.update_slaac_dns_servers(None);
+			} else {
+				let expiry = timestamp + advert.dns_lifetime;
+				self.update_slaac_dns_servers(Some((advert.dns_servers, expiry)));
+			}
+		}
+	}
+
+	/// Removes the address, which is derived from the announced prefix.
+	#[cfg(feature = "ipv6")]
+	fn remove_slaac_address(&mut self) {
+		if let Some((cidr, _)) = self.slaac.address.take() {
+			self.iface
+				.update_ip_addrs(|addrs| addrs.retain(|addr| *addr != IpCidr::Ipv6(cidr)));
+		}
+	}
+
+	/// Removes the default route over the announced router.
+	#[cfg(feature = "ipv6")]
+	fn remove_slaac_router(&mut self) {
+		if self.slaac.router.take().is_some() {
+			self.iface.routes_mut().remove_default_ipv6_route();
+		}
+	}
+
+	/// Replaces the recursive DNS servers and passes them to the DNS socket.
+	///
+	/// The DNS socket is only created and updated for the recursive DNS servers,
+	/// if DHCP doesn't provide DNS servers.
+	#[cfg(feature = "ipv6")]
+	fn update_slaac_dns_servers(&mut self, dns_servers: Option<(Vec<Ipv6Address>, Instant)>) {
+		let servers = dns_servers
+			.as_ref()
+			.map(|(servers, _)| servers.clone())
+			.unwrap_or_default();
+		let changed = self
+			.slaac
+			.dns_servers
+			.as_ref()
+			.map_or(true, |(old, _)| *old != servers);
+		self.slaac.dns_servers = dns_servers;
+		if !changed {
+			return;
+		}
+
+		for (i, s) in servers.iter().enumerate() {
+			info!("DNS server {}:    {}", i, s);
+		}
+
+		#[cfg(feature = "dns")]
+		{
+			let servers: Vec<IpAddress> = servers.into_iter().map(IpAddress::Ipv6).collect();
+			match self.dns_handle {
+				Some(dns_handle) if self.slaac.dns_socket => {
+					if servers.is_empty() {
+						self.sockets.remove(dns_handle);
+						self.dns_handle = None;
+						self.slaac.dns_socket = false;
+					} else {
+						self.sockets
+							.get_mut::<dns::Socket<'a>>(dns_handle)
+							.update_servers(servers.as_slice());
+					}
+				}
+				None if !servers.is_empty() => {
+					let dns_socket = dns::Socket::new(servers.as_slice(), vec![]);
+					self.dns_handle = Some(self.sockets.add(dns_socket));
+					self.slaac.dns_socket = true;
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Returns the time until the interface has to be polled again.
+	///
+	/// smoltcp takes the minimum over the interface and the `poll_at` deadlines of
+	/// all sockets. Returns `None`, if no timer is pending.
+	pub(crate) fn poll_delay(&mut self, timestamp: Instant) -> Option<Duration> {
+		let delay = self.iface.poll_delay(timestamp, &self.sockets);
+
+		// smoltcp doesn't know the deadlines of the keepalive probes
+		#[cfg(feature = "tcp")]
+		let delay = self
+			.tcp_keep_alives
+			.iter()
+			.filter_map(|(handle, keep_alive)| self.tcp_keepalive_deadline(*handle, keep_alive))
+			.map(|deadline| {
+				if deadline > timestamp {
+					deadline - timestamp
+				} else {
+					Duration::ZERO
+				}
+			})
+			.chain(delay)
+			.min();
+
+		delay
+	}
+
+	/// Returns the peers of the TCP sockets with keepalive in the order of
+	/// [`tcp_keep_alives`](Self::tcp_keep_alives).
+	#[cfg(feature = "tcp")]
+	fn tcp_keepalive_peers(&self) -> Vec<Peer> {
+		self.tcp_keep_alives
+			.iter()
+			.map(|(handle, _)| {
+				let socket = self.sockets.get::<tcp::Socket<'_>>(*handle);
+				let local_port = socket.local_endpoint().map_or(0, |local| local.port);
+				Peer::new(socket.remote_endpoint(), local_port)
+			})
+			.collect()
+	}
+
+	#[allow(dead_code)]
+	pub(crate) fn get_socket<T: AnySocket<'a>>(&self, handle: SocketHandle) -> &T {
+		self.sockets.get(handle)
+	}
+
+	pub(crate) fn get_mut_socket<T: AnySocket<'a>>(&mut self, handle: SocketHandle) -> &mut T {
+		self.sockets.get_mut(handle)
+	}
+
+	pub(crate) fn get_socket_and_context<T: AnySocket<'a>>(
+		&mut self,
+		handle: SocketHandle,
+	) -> (&mut T, &mut smoltcp::iface::Context) {
+		(self.sockets.get_mut(handle), self.iface.context())
+	}
+
+	pub(crate) fn destroy_socket(&mut self, handle: Handle) {
+		#[cfg(feature = "udp")]
+		self.udp_zero_checksum
+			.retain(|zero_checksum| *zero_checksum != handle);
+		#[cfg(feature = "udp")]
+		self.udp_queued.remove(handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_bound.retain(|(bound, _)| *bound != handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_initial_data
+			.retain(|(initial_data, _)| *initial_data != handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_initial_windows
+			.retain(|(initial_window, _)| *initial_window != handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_low_water
+			.retain(|(low_water, _)| *low_water != handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_keep_alives
+			.retain(|(keep_alive, _)| *keep_alive != handle);
+		#[cfg(feature = "tcp")]
+		self.tcp_splices.retain(|splice| !splice.contains(handle));
+		self.socket_errors.retain(|(socket, _)| *socket != handle);
+
+		// This deallocates the socket's buffers
+		self.sockets.remove(handle);
+	}
+
+	/// Closes the TCP socket `handle` and returns the number of bytes,
+	/// which are enqueued in the transmit buffer and not yet sent.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn close(&mut self, handle: Handle) -> usize {
+		let socket = self.sockets.get_mut::<tcp::Socket<'_>>(handle);
+		if socket.state() == tcp::State::Closed {
+			return 0;
+		}
+
+		let unsent = socket.send_queue();
+		socket.close();
+
+		unsent
+	}
+
+	/// Checks if the transmit buffer of the TCP socket `handle` has room for further data.
+	///
+	/// Registers `cx` to be woken up by [`poll_common`](Self::poll_common) while the socket
+	/// connects, the buffer is full, e.g. because the peer announced a zero window, or
+	/// the initial congestion window is used up. Fails if the socket isn't connected
+	/// or is already closed for sending.
+	#[cfg(feature = "tcp")]
+	pub(crate) fn poll_send_ready(
+		&mut self,
+		handle: Handle,
+		cx: &mut Context<'_>,
+	) -> Poll<Result<(), IoError>> {
+		if let Some(err) = self.take_socket_error(handle) {
+			return Poll::Ready(Err(err));
+		}
+
+		let can_send = self.tcp_can_send(handle);
+		let socket = self.sockets.get_mut::<tcp::Socket<'_>>(handle);
+		match socket.state() {
+			tcp::State::Established | tcp::State::CloseWait if can_send => Poll::Ready(Ok(())),
+			tcp::State::Established
+			| tcp::State::CloseWait
+			| tcp::State::SynSent
+			| tcp::State::SynReceived => {
+				socket.register_send_waker(cx.waker());
+				Poll::Pending
+			}
+			tcp::State::Listen => Poll::Ready(Err(IoError::ENOTCONN)),
+			tcp::State::Closed
+			| tcp::State::FinWait1
+			| tcp::State::FinWait2
+			| tcp::State::Closing
+			| tcp::State::LastAck
+			| tcp::State::TimeWait => Poll::Ready(Err(IoError::EPIPE)),
+		}
+	}
+
+	/// Checks if another socket than `handle` already uses the local `endpoint`.
+	///
+	/// If `reuse_addr` is set (`SO_REUSEADDR`), only listening TCP sockets
+	/// and bound UDP sockets are considered as conflicts.
+	pub(crate) fn check_bind(
+		&self,
+		handle: Handle,
+		endpoint: IpListenEndpoint,
+		reuse_addr: bool,
+	) -> Result<(), IoError> {
+		for (h, socket) in self.sockets.iter() {
+			if h == handle {
+				continue;
+			}
+
+			#[cfg(feature = "tcp")]
+			if let Some(socket) = tcp::Socket::downcast(socket) {
+				let in_use = match socket.state() {
+					tcp::State::Closed => self
+						.tcp_bound
+						.iter()
+						.find(|(bound, _)| *bound == h)
+						.map(|(_, endpoint)| *endpoint),
+					tcp::State::Listen => Some(socket.listen_endpoint()),
+					_ if reuse_addr => None,
+					_ => socket.local_endpoint().map(IpListenEndpoint::from),
+				};
+
+				if in_use.is_some_and(|in_use| endpoints_conflict(in_use, endpoint)) {
+					return Err(IoError::EADDRINUSE);
+				}
+			}
+
+			#[cfg(feature = "udp")]
+			if let Some(socket) = udp::Socket::downcast(socket) {
+				if socket.is_open() && endpoints_conflict(socket.endpoint(), endpoint) {
+					return Err(IoError::EADDRINUSE);
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Binds the TCP socket `handle` to the local `endpoint`, if no other socket
+	/// uses it (see [`check_bind`](Self::check_bind)).
+	#[cfg(feature = "tcp")]
+	pub(crate) fn tcp_bind(
+		&mut self,
+		handle: Handle,
+		endpoint: IpListenEndpoint,
+		reuse_addr: bool,
+	) -> Result<(), IoError> {
+		self.check_bind(handle, endpoint, reuse_addr)?;
+		self.tcp_bound.retain(|(bound, _)| *bound != handle);
+		self.tcp_bound.push((handle, endpoint));
+		Ok(())
+	}
+
+	/// Prints the addresses, the routing table and all sockets of the interface.
+	pub(crate) fn print_sockets(&mut self) {
+		println!("Addresses:");
+		for cidr in self.iface.ip_addrs() {
+			println!("  {cidr}");
+		}
+
+		println!("Routes:");
+		self.iface.routes_mut().update(|routes| {
+			for route in routes.iter() {
+				println!("  {} via {}", route.cidr, route.via_router);
+			}
+		});
+
+		println!("Sockets:");
+		println!(
+			"  {:<6} {:<6} {:<12} {:<24} {:<24} {:>12} {:>12}",
+			"Handle", "Type", "State", "Local", "Remote", "Recv-Q", "Send-Q"
+		);
+		for (handle, socket) in self.sockets.iter() {
+			#[cfg(feature = "tcp")]
+			if let Some(socket) = tcp::Socket::downcast(socket) {
+				let local = socket.local_endpoint().map_or_else(
+					|| format!("{}", socket.listen_endpoint()),
+					|endpoint| format!("{endpoint}"),
+				);
+				let remote = socket
+					.remote_endpoint()
+					.map_or_else(|| "*".into(), |endpoint| format!("{endpoint}"));
+				println!(
+					"  {:<6} {:<6} {:<12} {:<24} {:<24} {:>12} {:>12}",
+					format!("{handle}"),
+					"TCP",
+					format!("{}", socket.state()),
+					local,
+					remote,
+					format!("{}/{}", socket.recv_queue(), socket.recv_capacity()),
+					format!("{}/{}", socket.send_queue(), socket.send_capacity()),
+				);
+				continue;
+			}
+
+			#[cfg(feature = "udp")]
+			if let Some(socket) = udp::Socket::downcast(socket) {
+				println!(
+					"  {:<6} {:<6} {:<12} {:<24} {:<24} {:>12} {:>12}",
+					format!("{handle}"),
+					"UDP",
+					if socket.is_open() { "Open" } else { "Closed" },
+					format!("{}", socket.endpoint()),
+					"*",
+					format!("{}", socket.recv_queue()),
+					format!("{}", socket.send_queue()),
+				);
+				continue;
+			}
+
+			#[cfg(feature = "dhcpv4")]
+			if dhcpv4::Socket::downcast(socket).is_some() {
+				println!("  {:<6} {:<6}", format!("{handle}"), "DHCP");
+				continue;
+			}
+
+			#[cfg(feature = "dns")]
+			if dns::Socket::downcast(socket).is_some() {
+				println!("  {:<6} {:<6}", format!("{handle}"), "DNS");
+				continue;
+			}
+
+			println!("  {:<6} {:<6}", format!("{handle}"), "Other");
+		}
+	}
+
+	#[cfg(feature = "dns")]
+	pub(crate) fn start_query(
+		&mut self,
+		name: &str,
+		query_type: DnsQueryType,
+	) -> Result<QueryHandle, IoError> {
+		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
+		let socket: &mut dns::Socket<'a> = self.sockets.get_mut(dns_handle);
+		socket
+			.start_query(self.iface.context(), name, query_type)
+			.map_err(|_| IoError::EIO)
+	}
+
+	#[allow(dead_code)]
+	#[cfg(feature = "dns")]
+	pub(crate) fn get_dns_socket(&self) -> Result<&dns::Socket<'a>, IoError> {
+		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
+		Ok(self.sockets.get(dns_handle))
+	}
+
+	#[cfg(feature = "dns")]
+	pub(crate) fn get_mut_dns_socket(&mut self) -> Result<&mut dns::Socket<'a>, IoError> {
+		let dns_handle = self.dns_handle.ok_or(IoError::EINVAL)?;
+		Ok(self.sockets.get_mut(dns_handle))
+	}
+}
+
+/// Returns `true` if both local endpoints use the same port and their addresses overlap.
+/// A missing or unspecified address is a wildcard and overlaps with any address.
+fn endpoints_conflict(a: IpListenEndpoint, b: IpListenEndpoint) -> bool {
+	if a.port == 0 || a.port != b.port {
+		return false;
+	}
+
+	match (a.addr, b.addr) {
+		(Some(x), Some(y)) => x.is_unspecified() || y.is_unspecified() || x == y,
+		_ => true,
+	}
+}
+
+/// Returns the time until the interface has to be polled again.
+///
+/// Returns `None`, if the interface is missing or its initialization failed.
+#[inline]
+pub(crate) fn network_delay(timestamp: Instant) -> Option<Duration> {
+	match NIC.lock().deref_mut() {
+		NetworkState::Initialized(nic) => nic.poll_delay(timestamp),
+		NetworkState::Missing | NetworkState::InitializationFailed => None,
+	}
+}
+
+/// Polls the interface. Like [`network_delay`], the call has no effect, if the
+/// interface isn't available.
+#[inline]
+fn network_poll(timestamp: Instant) {
+	if let NetworkState::Initialized(nic) = NIC.lock().deref_mut() {
+		nic.poll_common(timestamp);
+	}
+}
+
+/// Device wrapper, which transmits the IPv4 datagrams of the UDP sockets
+/// bound to `ports` with a zero checksum.
+#[cfg(feature = "udp")]
+struct ZeroUdpChecksum<'d, D> {
+	device: &'d mut D,
+	ports: &'d [u16],
+}
+
+#[cfg(feature = "udp")]
+struct ZeroUdpChecksumTxToken<'d, T> {
+	token: T,
+	ports: &'d [u16],
+}
+
+/// Clears the checksum of an IPv4 UDP datagram, whose source port is part of `ports`.
+#[cfg(feature = "udp")]
+fn clear_udp_checksum(frame: &mut [u8], ports: &[u16]) {
+	use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, UdpPacket};
+
+	let Ok(mut frame) = EthernetFrame::new_checked(frame) else {
+		return;
+	};
+	if frame.ethertype() != EthernetProtocol::Ipv4 {
+		return;
+	}
+	let Ok(mut packet) = Ipv4Packet::new_checked(frame.payload_mut()) else {
+		return;
+	};
+	if packet.version() != 4 || packet.next_header() != IpProtocol::Udp {
+		return;
+	}
+	let Ok(mut datagram) = UdpPacket::new_checked(packet.payload_mut()) else {
+		return;
+	};
+	if ports.contains(&datagram.src_port()) {
+		datagram.set_checksum(0);
+	}
+}
+
+#[cfg(feature = "udp")]
+impl<T: smoltcp::phy::TxToken> smoltcp::phy::TxToken for ZeroUdpChecksumTxToken<'_, T> {
+	fn consume<R, F>(self, len: usize, f: F) -> R
+	where
+		F: FnOnce(&mut [u8]) -> R,
+	{
+		let ports = self.ports;
+		self.token.consume(len, |buffer| {
+			let result = f(buffer);
+			clear_udp_checksum(buffer, ports);
+			result
+		})
+	}
+}
+
+#[cfg(feature = "udp")]
+impl<'d, D: Device> Device for ZeroUdpChecksum<'d, D> {
+	type RxToken<'a>
+		= D::RxToken<'a>
+	where
+		Self: 'a;
+	type TxToken<'a>
+		= ZeroUdpChecksumTxToken<'a, D::TxToken<'a>>
+	where
+		Self: 'a;
+
+	fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+		let ports = self.ports;
+		self.device
+			.receive(timestamp)
+			.map(|(rx, token)| (rx, ZeroUdpChecksumTxToken { token, ports }))
+	}
+
+	fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+		let ports = self.ports;
+		self.device
+			.transmit(timestamp)
+			.map(|token| ZeroUdpChecksumTxToken { token, ports })
+	}
+
+	fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+		self.device.capabilities()
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use smoltcp::wire::Ipv4Address;
+
+	use super::*;
+	#[cfg(feature = "dhcpv4")]
+	use crate::executor::device::test_nic;
+
+	#[test]
+	fn poll_without_interface() {
+		assert!(matches!(*NIC.lock(), NetworkState::Missing));
+		assert_eq!(network_delay(Instant::ZERO), None);
+		network_poll(Instant::ZERO);
+	}
+
+	#[test]
+	fn endpoints_conflict_on_same_port() {
+		let any = IpListenEndpoint::from(80);
+		let unspecified = IpListenEndpoint::from((Ipv4Address::UNSPECIFIED, 80));
+		let a = IpListenEndpoint::from((Ipv4Address::new(1, 2, 3, 4), 80));
+		let b = IpListenEndpoint::from((Ipv4Address::new(1, 2, 3, 5), 80));
+
+		assert!(endpoints_conflict(any, any));
+		assert!(endpoints_conflict(any, a));
+		assert!(endpoints_conflict(unspecified, a));
+		assert!(endpoints_conflict(a, a));
+		assert!(!endpoints_conflict(a, b));
+		assert!(!endpoints_conflict(a, IpListenEndpoint::from(81)));
+		assert!(!endpoints_conflict(
+			IpListenEndpoint::from(0),
+			IpListenEndpoint::from(0)
+		));
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn bind_same_port_twice() {
+		use smoltcp::phy::{Loopback, Medium};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		let first = nic.create_tcp_handle().unwrap();
+		let second = nic.create_tcp_handle().unwrap();
+		let endpoint = IpListenEndpoint::from(80);
+
+		assert_eq!(nic.check_bind(first, endpoint, false), Ok(()));
+		nic.get_mut_socket::<tcp::Socket<'_>>(first)
+			.listen(endpoint)
+			.unwrap();
+
+		assert_eq!(
+			nic.check_bind(second, endpoint, false),
+			Err(IoError::EADDRINUSE)
+		);
+		assert_eq!(
+			nic.check_bind(
+				second,
+				IpListenEndpoint::from((Ipv4Address::new(1, 2, 3, 4), 80)),
+				true
+			),
+			Err(IoError::EADDRINUSE)
+		);
+		assert_eq!(
+			nic.check_bind(second, IpListenEndpoint::from(81), false),
+			Ok(())
+		);
+
+		// a bound socket, which doesn't listen yet, also occupies its endpoint
+		let third = nic.create_tcp_handle().unwrap();
+		let endpoint = IpListenEndpoint::from((Ipv4Address::new(1, 2, 3, 4), 82));
+		assert_eq!(nic.tcp_bind(third, endpoint, false), Ok(()));
+		assert_eq!(
+			nic.tcp_bind(second, IpListenEndpoint::from(82), false),
+			Err(IoError::EADDRINUSE)
+		);
+		assert_eq!(
+			nic.tcp_bind(
+				second,
+				IpListenEndpoint::from((Ipv4Address::new(1, 2, 3, 5), 82)),
+				false
+			),
+			Ok(())
+		);
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn close_closed_socket() {
+		use smoltcp::phy::{Loopback, Medium};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		let handle = nic.create_tcp_handle().unwrap();
+
+		assert_eq!(nic.close(handle), 0);
+
+		nic.get_mut_socket::<tcp::Socket<'_>>(handle)
+			.listen(80)
+			.unwrap();
+		assert_eq!(nic.close(handle), 0);
+		assert_eq!(nic.close(handle), 0);
+	}
+
+	/// Closing an established connection reports the bytes, which weren't sent yet.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn close_with_unsent_data() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let mut nic = test_nic(Loopback::new(Medium::Ethernet));
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let listener = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(listener)
+			.listen(80)
+			.unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(client)
+			.connect(
+				nic.iface.context(),
+				(IpAddress::v4(127, 0, 0, 1), 80),
+				49152,
+			)
+			.unwrap();
+		let mut timestamp = Instant::from_secs(1);
+		for _ in 0..10 {
+			nic.poll_device(timestamp);
+			timestamp += Duration::from_millis(10);
+		}
+		assert!(nic.get_mut_socket::<tcp::Socket<'_>>(client).may_send());
+
+		// The data is only enqueued, because the device isn't polled again.
+		let sent = nic
+			.get_mut_socket::<tcp::Socket<'_>>(client)
+			.send_slice(b"unsent data")
+			.unwrap();
+		assert_eq!(sent, 11);
+		assert_eq!(nic.close(client), 11);
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(client).state(),
+			tcp::State::FinWait1
+		);
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn socket_limit() {
+		use smoltcp::phy::{Loopback, Medium};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		)
+		.with_max_sockets(4);
+
+		let handles = (0..4)
+			.map(|_| nic.create_tcp_handle().unwrap())
+			.collect::<alloc::vec::Vec<_>>();
+		assert_eq!(nic.create_tcp_handle(), Err(IoError::EMFILE));
+
+		nic.destroy_socket(handles[0]);
+		assert!(nic.create_tcp_handle().is_ok());
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn send_ready_on_unconnected_socket() {
+		use core::task::Waker;
+
+		use smoltcp::phy::{Loopback, Medium};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		let handle = nic.create_tcp_handle().unwrap();
+		let mut cx = Context::from_waker(Waker::noop());
+
+		assert_eq!(
+			nic.poll_send_ready(handle, &mut cx),
+			Poll::Ready(Err(IoError::EPIPE))
+		);
+
+		nic.get_mut_socket::<tcp::Socket<'_>>(handle)
+			.listen(80)
+			.unwrap();
+		assert_eq!(
+			nic.poll_send_ready(handle, &mut cx),
+			Poll::Ready(Err(IoError::ENOTCONN))
+		);
+	}
+
+	/// Loopback device, which marks all transmitted TCP segments with payload as urgent
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	struct UrgentLoopback(smoltcp::phy::Loopback);
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	struct UrgentTxToken<T>(T);
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	impl<T: smoltcp::phy::TxToken> smoltcp::phy::TxToken for UrgentTxToken<T> {
+		fn consume<R, F>(self, len: usize, f: F) -> R
+		where
+			F: FnOnce(&mut [u8]) -> R,
+		{
+			use smoltcp::wire::{
+				EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket,
+			};
+
+			self.0.consume(len, |buffer| {
+				let result = f(buffer);
+
+				let mut frame = EthernetFrame::new_unchecked(&mut *buffer);
+				if frame.ethertype() == EthernetProtocol::Ipv4 {
+					let mut packet = Ipv4Packet::new_unchecked(frame.payload_mut());
+					let (src_addr, dst_addr) = (packet.src_addr(), packet.dst_addr());
+					if packet.next_header() == IpProtocol::Tcp {
+						let mut segment = TcpPacket::new_unchecked(packet.payload_mut());
+						if !segment.payload().is_empty() {
+							segment.set_urg(true);
+							segment.set_urgent_at(1);
+							segment.fill_checksum(&src_addr.into(), &dst_addr.into());
+						}
+					}
+				}
+
+				result
+			})
+		}
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	impl Device for UrgentLoopback {
+		type RxToken<'a> = <smoltcp::phy::Loopback as Device>::RxToken<'a>;
+		type TxToken<'a> = UrgentTxToken<<smoltcp::phy::Loopback as Device>::TxToken<'a>>;
+
+		fn receive(
+			&mut self,
+			timestamp: Instant,
+		) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+			self.0
+				.receive(timestamp)
+				.map(|(rx, tx)| (rx, UrgentTxToken(tx)))
+		}
+
+		fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+			self.0.transmit(timestamp).map(UrgentTxToken)
+		}
+
+		fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+			self.0.capabilities()
+		}
+	}
+
+	/// Urgent data of inbound segments has to be received inline without disrupting the stream.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn receive_urgent_data_inline() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let mut nic = NetworkInterface::create_with_device(
+			UrgentLoopback(Loopback::new(Medium::Ethernet)),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let server = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.listen(80)
+			.unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(client)
+			.connect(
+				nic.iface.context(),
+				(IpAddress::v4(127, 0, 0, 1), 80),
+				49152,
+			)
+			.unwrap();
+
+		let mut timestamp = Instant::from_secs(1);
+		let mut poll = |nic: &mut NetworkInterface<'_, UrgentLoopback>| {
+			for _ in 0..10 {
+				nic.poll_device(timestamp);
+				timestamp += Duration::from_millis(10);
+			}
+		};
+
+		poll(&mut nic);
+		assert!(nic.get_mut_socket::<tcp::Socket<'_>>(client).may_send());
+
+		let data = b"urgent data is part of the stream";
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(client)
+				.send_slice(data),
+			Ok(data.len())
+		);
+		poll(&mut nic);
+
+		let mut buffer = [0; 64];
+		let len = nic
+			.get_mut_socket::<tcp::Socket<'_>>(server)
+			.recv_slice(&mut buffer)
+			.unwrap();
+		assert_eq!(&buffer[..len], data);
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn send_initial_data_after_handshake() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let server = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.listen(80)
+			.unwrap();
+
+		// No cookie is known for the first connection to the server.
+		let data = b"GET / HTTP/1.0\r\n\r\n";
+		nic.tcp_connect_with_data(
+			client,
+			IpEndpoint::new(IpAddress::v4(127, 0, 0, 1), 80),
+			49152,
+			data,
+		)
+		.unwrap();
+		assert_eq!(nic.tcp_initial_data.len(), 1);
+
+		let mut timestamp = Instant::from_secs(1);
+		for _ in 0..10 {
+			nic.poll_device(timestamp);
+			timestamp += Duration::from_millis(10);
+		}
+		assert!(nic.tcp_initial_data.is_empty());
+
+		let mut buffer = [0; 64];
+		let len = nic
+			.get_mut_socket::<tcp::Socket<'_>>(server)
+			.recv_slice(&mut buffer)
+			.unwrap();
+		assert_eq!(&buffer[..len], data);
+	}
+
+	/// Device, which records all transmitted frames and only receives injected frames
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[derive(Default)]
+	struct Capture {
+		frames: Vec<Vec<u8>>,
+		injected: alloc::collections::VecDeque<Vec<u8>>,
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	struct CaptureRxToken(Vec<u8>);
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	impl smoltcp::phy::RxToken for CaptureRxToken {
+		fn consume<R, F>(mut self, f: F) -> R
+		where
+			F: FnOnce(&mut [u8]) -> R,
+		{
+			f(&mut self.0)
+		}
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	struct CaptureTxToken<'a>(&'a mut Vec<Vec<u8>>);
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	impl smoltcp::phy::TxToken for CaptureTxToken<'_> {
+		fn consume<R, F>(self, len: usize, f: F) -> R
+		where
+			F: FnOnce(&mut [u8]) -> R,
+		{
+			let mut buffer = vec![0; len];
+			let result = f(&mut buffer);
+			self.0.push(buffer);
+			result
+		}
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	impl Device for Capture {
+		type RxToken<'a> = CaptureRxToken;
+		type TxToken<'a> = CaptureTxToken<'a>;
+
+		fn receive(
+			&mut self,
+			_timestamp: Instant,
+		) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+			let frame = self.injected.pop_front()?;
+			Some((CaptureRxToken(frame), CaptureTxToken(&mut self.frames)))
+		}
+
+		fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+			Some(CaptureTxToken(&mut self.frames))
+		}
+
+		fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+			let mut caps = smoltcp::phy::DeviceCapabilities::default();
+			caps.max_transmission_unit = 1514;
+			caps
+		}
+	}
+
+	/// Sends a datagram from port 5000 and returns the checksum of the transmitted datagram.
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	fn send_datagram(nic: &mut NetworkInterface<'_, Capture>, handle: Handle) -> (u16, bool) {
+		use smoltcp::wire::{EthernetFrame, Ipv4Packet, UdpPacket};
+
+		nic.get_mut_socket::<udp::Socket<'_>>(handle)
+			.send_slice(b"telemetry", (Ipv4Address::BROADCAST, 6000))
+			.unwrap();
+		nic.device.frames.clear();
+		nic.poll_device(Instant::from_secs(2));
+
+		nic.device
+			.frames
+			.iter()
+			.find_map(|frame| {
+				let frame = EthernetFrame::new_checked(&frame[..]).ok()?;
+				let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+				let datagram = UdpPacket::new_checked(packet.payload()).ok()?;
+				(datagram.src_port() == 5000).then(|| {
+					(
+						datagram.checksum(),
+						datagram
+							.verify_checksum(&packet.src_addr().into(), &packet.dst_addr().into()),
+					)
+				})
+			})
+			.unwrap()
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn dhcp_hostname_option() {
+		use smoltcp::wire::{DhcpPacket, EthernetFrame, Ipv4Packet, UdpPacket};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		)
+		.with_dhcp_hostname("hermit-1");
+		nic.poll_device(Instant::from_secs(1));
+
+		let hostname = nic
+			.device
+			.frames
+			.iter()
+			.find_map(|frame| {
+				let frame = EthernetFrame::new_checked(&frame[..]).ok()?;
+				let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+				let datagram = UdpPacket::new_checked(packet.payload()).ok()?;
+				let dhcp = DhcpPacket::new_checked(datagram.payload()).ok()?;
+				let option = dhcp.options().find(|option| option.kind == 12)?;
+				Some(option.data.to_vec())
+			})
+			.unwrap();
+		assert_eq!(hostname, b"hermit-1");
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn udp_zero_checksum() {
+		let mut nic = test_nic(Capture::default());
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(10, 0, 5, 3), 24))
+				.unwrap();
+		});
+		let handle = nic.create_udp_handle().unwrap();
+		nic.get_mut_socket::<udp::Socket<'_>>(handle)
+			.bind(5000)
+			.unwrap();
+
+		let (checksum, valid) = send_datagram(&mut nic, handle);
+		assert_ne!(checksum, 0);
+		assert!(valid);
+
+		nic.set_udp_checksum(handle, false).unwrap();
+		assert!(!nic.udp_checksum(handle));
+		let (checksum, _) = send_datagram(&mut nic, handle);
+		assert_eq!(checksum, 0);
+
+		nic.set_udp_checksum(handle, true).unwrap();
+		assert!(nic.udp_checksum(handle));
+		let (checksum, valid) = send_datagram(&mut nic, handle);
+		assert_ne!(checksum, 0);
+		assert!(valid);
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn udp_zero_checksum_rejects_ipv6() {
+		let mut nic = test_nic(Capture::default());
+		let handle = nic.create_udp_handle().unwrap();
+		nic.get_mut_socket::<udp::Socket<'_>>(handle)
+			.bind((IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 1), 5000))
+			.unwrap();
+
+		assert_eq!(nic.set_udp_checksum(handle, false), Err(IoError::EINVAL));
+	}
+
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn reject_oversized_udp_datagram() {
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		let handle = nic.create_udp_handle().unwrap();
+		nic.get_mut_socket::<udp::Socket<'_>>(handle)
+			.bind(5000)
+			.unwrap();
+		let meta = udp::UdpMetadata::from((IpAddress::v4(10, 0, 5, 2), 5001));
+
+		let payload = vec![0xaa; 70000];
+		assert_eq!(
+			nic.udp_send_slice(handle, &payload, meta),
+			Err(IoError::EMSGSIZE)
+		);
+		assert_eq!(
+			nic.udp_send_slice(handle, &payload[..UDP_MAX_PAYLOAD_IPV4 + 1], meta),
+			Err(IoError::EMSGSIZE)
+		);
+		assert_eq!(
+			nic.get_mut_socket::<udp::Socket<'_>>(handle).send_queue(),
+			0
+		);
+
+		assert_eq!(
+			nic.udp_send_slice(handle, &payload[..UDP_MAX_PAYLOAD_IPV4], meta),
+			Ok(())
+		);
+	}
+
+	/// Counts the transmitted TCP segments with the SYN flag.
+	#[cfg(all(feature = "tcp", feature = "udp", feature = "dhcpv4"))]
+	fn count_syns(frames: &[Vec<u8>]) -> usize {
+		use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket};
+
+		frames
+			.iter()
+			.filter(|frame| {
+				let frame = EthernetFrame::new_unchecked(&frame[..]);
+				if frame.ethertype() != EthernetProtocol::Ipv4 {
+					return false;
+				}
+				let packet = Ipv4Packet::new_unchecked(frame.payload());
+				packet.next_header() == IpProtocol::Tcp
+					&& TcpPacket::new_unchecked(packet.payload()).syn()
+			})
+			.count()
+	}
+
+	/// The interface is configured by an IPv4 address and wakes up the waiting tasks.
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn configured_by_ipv4_address() {
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		assert!(!nic.is_configured());
+		nic.config_wakers.push(Waker::noop().clone());
+
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(10, 0, 5, 3), 24))
+				.unwrap();
+		});
+		nic.wake_config_waiters();
+		assert!(nic.is_configured());
+		assert!(nic.config_wakers.is_empty());
+	}
+
+	/// A request of the device to announce the interface queues a gratuitous ARP.
+	#[cfg(all(feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn announce_after_migration() {
+		use smoltcp::wire::{ArpOperation, ArpPacket, ArpRepr, EthernetFrame, EthernetProtocol};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		let addr = Ipv4Address::new(10, 0, 5, 3);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs.push(IpCidr::new(IpAddress::Ipv4(addr), 24)).unwrap();
+		});
+
+		net::request_announce();
+		assert!(net::take_announce_request());
+		assert!(!net::take_announce_request());
+		nic.announce(Instant::from_secs(1));
+
+		assert_eq!(nic.device.frames.len(), 1);
+		let frame = EthernetFrame::new_checked(&nic.device.frames[0][..]).unwrap();
+		assert_eq!(frame.ethertype(), EthernetProtocol::Arp);
+		let arp = ArpRepr::parse(&ArpPacket::new_checked(frame.payload()).unwrap()).unwrap();
+		assert!(matches!(
+			arp,
+			ArpRepr::EthernetIpv4 {
+				operation: ArpOperation::Request,
+				source_protocol_addr,
+				target_protocol_addr,
+				..
+			} if source_protocol_addr == addr && target_protocol_addr == addr
+		));
+	}
+
+	/// The SYN has to be retransmitted at the deadline, which is reported by `poll_delay`,
+	/// although the peer never answers.
+	#[cfg(all(feature = "tcp", feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn retransmit_without_inbound_traffic() {
+		let mut nic = silent_peer();
+		let mut timestamp = Instant::from_secs(1);
+
+		let handle = nic.create_tcp_handle().unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(handle)
+			.connect(nic.iface.context(), (IpAddress::v4(10, 0, 5, 2), 80), 49152)
+			.unwrap();
+		nic.poll_device(timestamp);
+		assert_eq!(count_syns(&nic.device.frames), 1);
+
+		let delay = nic.poll_delay(timestamp).unwrap();
+		assert!(delay > Duration::ZERO);
+
+		nic.poll_device(timestamp + delay / 2);
+		assert_eq!(count_syns(&nic.device.frames), 1);
+
+		timestamp += delay;
+		nic.poll_device(timestamp);
+		assert_eq!(count_syns(&nic.device.frames), 2);
+	}
+
+	/// Sockets, whose next hop never answers the ARP requests, fail after the timeout.
+	#[cfg(all(feature = "tcp", feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn fail_on_unresolved_neighbor() {
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		)
+		.with_neighbor_timeout(Duration::from_secs(2));
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(10, 0, 5, 3), 24))
+				.unwrap();
+		});
+
+		let tcp_handle = nic.create_tcp_handle().unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(tcp_handle)
+			.connect(nic.iface.context(), (IpAddress::v4(10, 0, 5, 2), 80), 49152)
+			.unwrap();
+		let udp_handle = nic.create_udp_handle().unwrap();
+		nic.get_mut_socket::<udp::Socket<'_>>(udp_handle)
+			.bind(5000)
+			.unwrap();
+		let meta = udp::UdpMetadata::from((IpAddress::v4(10, 0, 5, 2), 5001));
+		nic.udp_send_slice(udp_handle, b"ping", meta).unwrap();
+
+		nic.poll_device(Instant::from_secs(1));
+		assert_eq!(count_syns(&nic.device.frames), 0);
+
+		// a socket, which waits for another neighbor, isn't affected
+		let other_handle = nic.create_udp_handle().unwrap();
+		nic.get_mut_socket::<udp::Socket<'_>>(other_handle)
+			.bind(5002)
+			.unwrap();
+		let other_meta = udp::UdpMetadata::from((IpAddress::v4(10, 0, 5, 4), 5001));
+		nic.udp_send_slice(other_handle, b"ping", other_meta)
+			.unwrap();
+
+		nic.poll_device(Instant::from_millis(2500));
+		assert_eq!(nic.take_socket_error(tcp_handle), None);
+
+		nic.poll_device(Instant::from_secs(3));
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(tcp_handle).state(),
+			tcp::State::Closed
+		);
+		assert_eq!(
+			nic.take_socket_error(tcp_handle),
+			Some(IoError::EHOSTUNREACH)
+		);
+		assert_eq!(nic.take_socket_error(tcp_handle), None);
+		assert_eq!(
+			nic.udp_send_slice(udp_handle, b"ping", meta),
+			Err(IoError::EHOSTUNREACH)
+		);
+		assert_eq!(
+			nic.get_mut_socket::<udp::Socket<'_>>(udp_handle)
+				.send_queue(),
+			0
+		);
+		assert_eq!(nic.take_socket_error(other_handle), None);
+		assert_eq!(
+			nic.get_mut_socket::<udp::Socket<'_>>(other_handle)
+				.send_queue(),
+			4
+		);
+	}
+
+	/// Creates an interface with the address 10.0.5.3, whose neighbor cache holds
+	/// the silent peer 10.0.5.2.
+	#[cfg(all(feature = "tcp", feature = "udp", feature = "dhcpv4"))]
+	fn silent_peer() -> NetworkInterface<'static, Capture> {
+		use smoltcp::wire::{
+			ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+			EthernetRepr,
+		};
+
+		let mut nic = NetworkInterface::create_with_device(
+			Capture::default(),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(10, 0, 5, 3), 24))
+				.unwrap();
+		});
+
+		// The ARP request of the peer fills the neighbor cache. Afterwards,
+		// the peer stays silent.
+		let peer = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x57]);
+		let arp = ArpRepr::EthernetIpv4 {
+			operation: ArpOperation::Request,
+			source_hardware_addr: peer,
+			source_protocol_addr: Ipv4Address::new(10, 0, 5, 2),
+			target_hardware_addr: EthernetAddress([0; 6]),
+			target_protocol_addr: Ipv4Address::new(10, 0, 5, 3),
+		};
+		let ethernet = EthernetRepr {
+			src_addr: peer,
+			dst_addr: EthernetAddress::BROADCAST,
+			ethertype: EthernetProtocol::Arp,
+		};
+		let mut request = vec![0; ethernet.buffer_len() + arp.buffer_len()];
+		let mut frame = EthernetFrame::new_unchecked(&mut request[..]);
+		ethernet.emit(&mut frame);
+		arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+		nic.device.injected.push_back(request);
+		nic.poll_device(Instant::from_secs(1));
+
+		nic
+	}
+
+	/// A connection, whose peer doesn't answer the keepalive probes, is dropped
+	/// after the configured number of probes.
+	#[cfg(all(feature = "tcp", feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn drop_connection_of_silent_peer() {
+		use smoltcp::phy::ChecksumCapabilities;
+		use smoltcp::wire::{
+			EthernetFrame, EthernetProtocol, EthernetRepr, IpProtocol, Ipv4Packet, Ipv4Repr,
+			TcpControl, TcpPacket, TcpRepr, TcpSeqNumber,
+		};
+
+		let mut nic = silent_peer().with_tcp_keepalive_probes(2);
+		let handle = nic.create_tcp_handle().unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(handle)
+			.connect(nic.iface.context(), (IpAddress::v4(10, 0, 5, 2), 80), 49152)
+			.unwrap();
+		nic.poll_device(Instant::from_secs(1));
+
+		// The peer answers the SYN once and stays silent afterwards.
+		let syn = nic
+			.device
+			.frames
+			.iter()
+			.find(|frame| count_syns(core::slice::from_ref(*frame)) == 1)
+			.unwrap()
+			.clone();
+		let syn_frame = EthernetFrame::new_checked(&syn[..]).unwrap();
+		let syn_packet = Ipv4Packet::new_checked(syn_frame.payload()).unwrap();
+		let syn_segment = TcpPacket::new_checked(syn_packet.payload()).unwrap();
+		let tcp_repr = TcpRepr {
+			src_port: syn_segment.dst_port(),
+			dst_port: syn_segment.src_port(),
+			control: TcpControl::Syn,
+			seq_number: TcpSeqNumber(1000),
+			ack_number: Some(syn_segment.seq_number() + 1),
+			window_len: 64240,
+			window_scale: None,
+			max_seg_size: Some(1460),
+			sack_permitted: false,
+			sack_ranges: [None, None, None],
+			payload: &[],
+		};
+		let ip_repr = Ipv4Repr {
+			src_addr: syn_packet.dst_addr(),
+			dst_addr: syn_packet.src_addr(),
+			next_header: IpProtocol::Tcp,
+			payload_len: tcp_repr.buffer_len(),
+			hop_limit: 64,
+		};
+		let ethernet = EthernetRepr {
+			src_addr: syn_frame.dst_addr(),
+			dst_addr: syn_frame.src_addr(),
+			ethertype: EthernetProtocol::Ipv4,
+		};
+		let mut syn_ack =
+			vec![0; ethernet.buffer_len() + ip_repr.buffer_len() + tcp_repr.buffer_len()];
+		let mut frame = EthernetFrame::new_unchecked(&mut syn_ack[..]);
+		ethernet.emit(&mut frame);
+		let mut packet = Ipv4Packet::new_unchecked(frame.payload_mut());
+		ip_repr.emit(&mut packet, &ChecksumCapabilities::default());
+		tcp_repr.emit(
+			&mut TcpPacket::new_unchecked(packet.payload_mut()),
+			&ip_repr.src_addr.into(),
+			&ip_repr.dst_addr.into(),
+			&ChecksumCapabilities::default(),
+		);
+		nic.device.injected.push_back(syn_ack);
+
+		nic.set_tcp_keep_alive(handle, Some(Duration::from_secs(10)));
+		nic.poll_device(Instant::from_secs(2));
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(handle).state(),
+			tcp::State::Established
+		);
+
+		// The probes are sent at 12 s and 22 s, the budget is used up at 32 s.
+		for secs in [12, 22, 31] {
+			nic.poll_device(Instant::from_secs(secs));
+			assert_eq!(nic.take_socket_error(handle), None);
+		}
+		assert!(nic
+			.poll_delay(Instant::from_secs(31))
+			.is_some_and(|delay| delay <= Duration::from_secs(1)));
+
+		nic.poll_device(Instant::from_secs(32));
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(handle).state(),
+			tcp::State::Closed
+		);
+		assert_eq!(nic.take_socket_error(handle), Some(IoError::ETIMEDOUT));
+	}
+
+	/// Loopback device with the frame length `mtu`, which counts the transmitted
+	/// TCP segments with payload
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	struct CountingLoopback {
+		loopback: smoltcp::phy::Loopback,
+		mtu: usize,
+		segments: usize,
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	struct CountingTxToken<'a, T> {
+		token: T,
+		segments: &'a mut usize,
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	impl<T: smoltcp::phy::TxToken> smoltcp::phy::TxToken for CountingTxToken<'_, T> {
+		fn consume<R, F>(self, len: usize, f: F) -> R
+		where
+			F: FnOnce(&mut [u8]) -> R,
+		{
+			use smoltcp::wire::{
+				EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket,
+			};
+
+			let segments = self.segments;
+			self.token.consume(len, |buffer| {
+				let result = f(buffer);
+
+				let frame = EthernetFrame::new_unchecked(&*buffer);
+				if frame.ethertype() == EthernetProtocol::Ipv4 {
+					let packet = Ipv4Packet::new_unchecked(frame.payload());
+					if packet.next_header() == IpProtocol::Tcp
+						&& !TcpPacket::new_unchecked(packet.payload())
+							.payload()
+							.is_empty()
+					{
+						*segments += 1;
+					}
+				}
+
+				result
+			})
+		}
+	}
+
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	impl Device for CountingLoopback {
+		type RxToken<'a> = <smoltcp::phy::Loopback as Device>::RxToken<'a>;
+		type TxToken<'a> = CountingTxToken<'a, <smoltcp::phy::Loopback as Device>::TxToken<'a>>;
+
+		fn receive(
+			&mut self,
+			timestamp: Instant,
+		) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+			let segments = &mut self.segments;
+			self.loopback
+				.receive(timestamp)
+				.map(|(rx, token)| (rx, CountingTxToken { token, segments }))
+		}
+
+		fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+			let segments = &mut self.segments;
+			self.loopback
+				.transmit(timestamp)
+				.map(|token| CountingTxToken { token, segments })
+		}
+
+		fn capabilities(&self) -> smoltcp::phy::DeviceCapabilities {
+			let mut capabilities = self.loopback.capabilities();
+			capabilities.max_transmission_unit = self.mtu;
+			capabilities
+		}
+	}
+
+	/// Before the first acknowledgment, a socket sends at most the initial congestion window.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn send_initial_window_before_first_ack() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let device = CountingLoopback {
+			loopback: Loopback::new(Medium::Ethernet),
+			mtu: 1514,
+			segments: 0,
+		};
+		let mut nic = NetworkInterface::create_with_device(
+			device,
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		)
+		.with_tcp_initcwnd(2);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let server = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.listen(80)
+			.unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(client)
+			.connect(
+				nic.iface.context(),
+				(IpAddress::v4(127, 0, 0, 1), 80),
+				49152,
+			)
+			.unwrap();
+
+		let mut timestamp = Instant::from_secs(1);
+		let mut poll = |nic: &mut NetworkInterface<'_, CountingLoopback>| {
+			for _ in 0..10 {
+				nic.poll_device(timestamp);
+				timestamp += Duration::from_millis(10);
+			}
+		};
+		poll(&mut nic);
+		assert!(nic.get_mut_socket::<tcp::Socket<'_>>(client).may_send());
+
+		let data = [0xa5; 8192];
+		assert_eq!(nic.tcp_send_slice(client, &data), Ok(2 * 1460));
+		assert!(!nic.tcp_can_send(client));
+		assert_eq!(nic.tcp_send_slice(client, &data), Ok(0));
+		poll(&mut nic);
+		assert_eq!(nic.device.segments, 2);
+
+		// The acknowledgment ends the initial window.
+		assert!(nic.tcp_can_send(client));
+		assert_eq!(nic.tcp_send_slice(client, &data), Ok(data.len()));
+		assert!(nic.tcp_initial_windows.is_empty());
+	}
+
+	/// With jumbo frames, 8000 bytes are transmitted and looped back in a single segment.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn loop_back_jumbo_frame() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let device = CountingLoopback {
+			loopback: Loopback::new(Medium::Ethernet),
+			mtu: 9014,
+			segments: 0,
+		};
+		let mut nic = NetworkInterface::create_with_device(
+			device,
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let server = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.listen(80)
+			.unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(client)
+			.connect(
+				nic.iface.context(),
+				(IpAddress::v4(127, 0, 0, 1), 80),
+				49152,
+			)
+			.unwrap();
+
+		let mut timestamp = Instant::from_secs(1);
+		let mut poll = |nic: &mut NetworkInterface<'_, CountingLoopback>| {
+			for _ in 0..10 {
+				nic.poll_device(timestamp);
+				timestamp += Duration::from_millis(10);
+			}
+		};
+		poll(&mut nic);
+		assert!(nic.get_mut_socket::<tcp::Socket<'_>>(client).may_send());
+
+		let data = [0x5a; 8000];
+		assert_eq!(nic.tcp_send_slice(client, &data), Ok(data.len()));
+		poll(&mut nic);
+		assert_eq!(nic.device.segments, 1);
+
+		let mut received = [0; 8000];
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(server)
+				.recv_slice(&mut received),
+			Ok(data.len())
+		);
+		assert_eq!(received, data);
+	}
+
+	/// A splice forwards the data of two loopback connections byte-exact in both
+	/// directions and passes the end of each direction on.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn splice_loopback_connections() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		// client -> proxy_in <=> proxy_out -> server
+		let connect = |nic: &mut NetworkInterface<'_, Loopback>, port: u16| {
+			let listener = nic.create_tcp_handle().unwrap();
+			let client = nic.create_tcp_handle().unwrap();
+			nic.get_mut_socket::<tcp::Socket<'_>>(listener)
+				.listen(port)
+				.unwrap();
+			nic.sockets
+				.get_mut::<tcp::Socket<'_>>(client)
+				.connect(
+					nic.iface.context(),
+					(IpAddress::v4(127, 0, 0, 1), port),
+					port + 49152,
+				)
+				.unwrap();
+			(client, listener)
+		};
+		let (client, proxy_in) = connect(&mut nic, 80);
+		let (proxy_out, server) = connect(&mut nic, 81);
+		assert_eq!(nic.splice(proxy_in, proxy_in), Err(IoError::EINVAL));
+		nic.splice(proxy_in, proxy_out).unwrap();
+		assert_eq!(nic.splice(proxy_out, client), Err(IoError::EINVAL));
+
+		let mut timestamp = Instant::from_secs(1);
+		let mut poll = |nic: &mut NetworkInterface<'_, Loopback>| {
+			for _ in 0..10 {
+				nic.poll_device(timestamp);
+				timestamp += Duration::from_millis(10);
+			}
+		};
+
+		// More data than the buffers hold, such that the splice has to wait
+		// for free space in the transmit buffer
+		let request = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+		let mut sent = 0;
+		let mut received = Vec::new();
+		while received.len() < request.len() {
+			sent += nic
+				.get_mut_socket::<tcp::Socket<'_>>(client)
+				.send_slice(&request[sent..])
+				.unwrap();
+			poll(&mut nic);
+			nic.get_mut_socket::<tcp::Socket<'_>>(server)
+				.recv(|data| {
+					received.extend_from_slice(data);
+					(data.len(), ())
+				})
+				.unwrap();
+		}
+		assert_eq!(received, request);
+
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.send_slice(b"response")
+			.unwrap();
+		nic.get_mut_socket::<tcp::Socket<'_>>(client).close();
+		poll(&mut nic);
+		let mut response = [0; 16];
+		let len = nic
+			.get_mut_socket::<tcp::Socket<'_>>(client)
+			.recv_slice(&mut response)
+			.unwrap();
+		assert_eq!(&response[..len], b"response");
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(server).state(),
+			tcp::State::CloseWait
+		);
+
+		nic.get_mut_socket::<tcp::Socket<'_>>(server).close();
+		poll(&mut nic);
+		assert!(!nic.get_mut_socket::<tcp::Socket<'_>>(client).may_recv());
+		assert!(nic.tcp_splices.is_empty());
+	}
+
+	/// A socket with a receive low-water mark isn't readable before the mark is reached.
+	#[cfg(all(feature = "tcp", feature = "dhcpv4"))]
+	#[test]
+	fn readable_at_receive_low_water() {
+		use smoltcp::phy::{Loopback, Medium};
+		use smoltcp::wire::IpAddress;
+
+		let mut nic = NetworkInterface::create_with_device(
+			Loopback::new(Medium::Ethernet),
+			[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+			Instant::from_secs(1),
+		);
+		nic.iface.update_ip_addrs(|addrs| {
+			addrs
+				.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+				.unwrap();
+		});
+
+		let server = nic.create_tcp_handle().unwrap();
+		let client = nic.create_tcp_handle().unwrap();
+		let capacity = nic
+			.get_mut_socket::<tcp::Socket<'_>>(server)
+			.recv_capacity();
+		let too_large = LowWater {
+			recv: capacity + 1,
+			send: 1,
+		};
+		assert_eq!(
+			nic.set_tcp_low_water(server, too_large),
+			Err(IoError::EINVAL)
+		);
+		let low_water = LowWater { recv: 100, send: 1 };
+		nic.set_tcp_low_water(server, low_water).unwrap();
+		assert_eq!(nic.tcp_low_water(server), low_water);
+
+		nic.get_mut_socket::<tcp::Socket<'_>>(server)
+			.listen(80)
+			.unwrap();
+		nic.sockets
+			.get_mut::<tcp::Socket<'_>>(client)
+			.connect(
+				nic.iface.context(),
+				(IpAddress::v4(127, 0, 0, 1), 80),
+				49152,
+			)
+			.unwrap();
+
+		let mut timestamp = Instant::from_secs(1);
+		let mut poll = |nic: &mut NetworkInterface<'_, Loopback>| {
+			for _ in 0..10 {
+				nic.poll_device(timestamp);
+				timestamp += Duration::from_millis(10);
+			}
+		};
+		poll(&mut nic);
+
+		let data = [0x5a; 60];
+		assert_eq!(nic.tcp_send_slice(client, &data), Ok(data.len()));
+		poll(&mut nic);
+		assert_eq!(
+			nic.get_mut_socket::<tcp::Socket<'_>>(server).recv_queue(),
+			60
+		);
+		assert!(!nic.tcp_can_recv(server));
+		assert!(!nic.tcp_can_read(server, 100));
+		// a smaller read doesn't wait for the low-water mark
+		assert!(nic.tcp_can_read(server, 60));
+
+		assert_eq!(nic.tcp_send_slice(client, &data[..40]), Ok(40));
+		poll(&mut nic);
+		assert!(nic.tcp_can_recv(server));
+	}
+
+	/// A router advertisement with a new prefix replaces the address, which is
+	/// derived from the old prefix.
+	#[cfg(all(feature = "ipv6", feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn slaac_prefix_change() {
+		use crate::executor::ndisc::{PrefixInfo, RouterAdvert};
+
+		let mut nic = test_nic(Capture::default());
+		let advert = |prefix: Ipv6Address| RouterAdvert {
+			router: Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+			router_lifetime: Duration::from_secs(1800),
+			prefix: Some(PrefixInfo {
+				prefix,
+				valid_lifetime: Duration::from_secs(3600),
+			}),
+			dns_servers: Vec::new(),
+			dns_lifetime: Duration::ZERO,
+		};
+		let mac = nic.slaac.mac;
+		let old = Ipv6Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0);
+		let new = Ipv6Address::new(0x2001, 0xdb8, 2, 0, 0, 0, 0, 0);
+		let old_cidr = IpCidr::Ipv6(ndisc::slaac_address(old, mac));
+		let new_cidr = IpCidr::Ipv6(ndisc::slaac_address(new, mac));
+
+		nic.apply_router_advert(advert(old), Instant::from_secs(1));
+		assert!(nic.iface.ip_addrs().contains(&old_cidr));
+
+		nic.apply_router_advert(advert(new), Instant::from_secs(2));
+		assert!(!nic.iface.ip_addrs().contains(&old_cidr));
+		assert!(nic.iface.ip_addrs().contains(&new_cidr));
+		assert_eq!(
+			nic.slaac.address,
+			Some((ndisc::slaac_address(new, mac), Instant::from_secs(3602)))
+		);
+
+		// a zero lifetime withdraws the address immediately
+		let mut withdraw = advert(new);
+		withdraw.prefix.as_mut().unwrap().valid_lifetime = Duration::ZERO;
+		nic.apply_router_advert(withdraw, Instant::from_secs(3));
+		assert!(!nic.iface.ip_addrs().contains(&new_cidr));
+		assert_eq!(nic.slaac.address, None);
+	}
+
+	/// The interface is configured by a SLAAC address and wakes up the waiting tasks.
+	#[cfg(all(feature = "ipv6", feature = "udp", feature = "dhcpv4"))]
+	#[test]
+	fn configured_by_slaac() {
+		use crate::executor::ndisc::{PrefixInfo, RouterAdvert};
+
+		let mut nic = test_nic(Capture::default());
+		assert!(!nic.is_configured());
+		nic.config_wakers.push(Waker::noop().clone());
+
+		let advert = RouterAdvert {
+			router: Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+			router_lifetime: Duration::from_secs(1800),
+			prefix: Some(PrefixInfo {
+				prefix: Ipv6Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0),
+				valid_lifetime: Duration::from_secs(3600),
+			}),
+			dns_servers: Vec::new(),
+			dns_lifetime: Duration::ZERO,
+		};
+		nic.apply_router_advert(advert, Instant::from_secs(1));
+		assert!(nic.is_configured());
+		assert!(nic.config_wakers.is_empty());
+	}
 }
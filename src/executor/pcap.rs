@@ -0,0 +1,272 @@
+//! Capture ring, which records the last frames of the network interface.
+//!
+//! All received and transmitted frames are recorded with a timestamp. The
+//! ring keeps the last `HERMIT_PCAP_FRAMES` frames and truncates each frame
+//! to `HERMIT_PCAP_SNAPLEN` bytes. A TCP connection to `HERMIT_PCAP_PORT`
+//! receives a snapshot of the ring as pcap file and is closed afterwards:
+//!
+//! ```sh
+//! nc <guest> 9877 > hermit.pcap
+//! ```
+//!
+//! With the feature `shell`, the command `pcap` prints the snapshot as
+//! hex dump to the serial console, which can be converted by `xxd -r -p`.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::future;
+use core::task::Poll;
+
+use hermit_sync::InterruptTicketMutex;
+use smoltcp::socket::tcp;
+
+use crate::arch;
+use crate::executor::network::{Handle, NetworkInterface, NIC};
+use crate::executor::spawn;
+
+/// Default number of frames in the ring
+const DEFAULT_FRAMES: usize = 1024;
+/// Default number of bytes, which are stored of each frame
+const DEFAULT_SNAPLEN: u32 = 65535;
+/// Default port of the capture server
+const DEFAULT_PORT: u16 = 9877;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+struct Record {
+	/// Timestamp in microseconds since the epoch
+	timestamp: u64,
+	/// Length of the frame on the wire
+	orig_len: u32,
+	/// First `snaplen` bytes of the frame
+	data: Vec<u8>,
+}
+
+struct CaptureRing {
+	frames: usize,
+	snaplen: u32,
+	records: VecDeque<Record>,
+}
+
+impl CaptureRing {
+	fn new(frames: usize, snaplen: u32) -> Self {
+		Self {
+			frames,
+			snaplen,
+			records: VecDeque::with_capacity(frames),
+		}
+	}
+
+	fn push(&mut self, timestamp: u64, frame: &[u8]) {
+		if self.frames == 0 {
+			return;
+		}
+		if self.records.len() == self.frames {
+			self.records.pop_front();
+		}
+
+		let len = frame.len().min(self.snaplen as usize);
+		self.records.push_back(Record {
+			timestamp,
+			orig_len: frame.len().try_into().unwrap_or(u32::MAX),
+			data: frame[..len].to_vec(),
+		});
+	}
+
+	/// Serializes the ring as pcap file in little endian byte order.
+	fn export(&self) -> Vec<u8> {
+		let len = self
+			.records
+			.iter()
+			.map(|record| 16 + record.data.len())
+			.sum::<usize>();
+		let mut file = Vec::with_capacity(24 + len);
+
+		file.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+		file.extend_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+		file.extend_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+		// thiszone and sigfigs
+		file.extend_from_slice(&0i32.to_le_bytes());
+		file.extend_from_slice(&0u32.to_le_bytes());
+		file.extend_from_slice(&self.snaplen.to_le_bytes());
+		file.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+
+		for record in self.records.iter() {
+			let ts_sec = (record.timestamp / 1_000_000) as u32;
+			let ts_usec = (record.timestamp % 1_000_000) as u32;
+			let incl_len = record.data.len() as u32;
+
+			file.extend_from_slice(&ts_sec.to_le_bytes());
+			file.extend_from_slice(&ts_usec.to_le_bytes());
+			file.extend_from_slice(&incl_len.to_le_bytes());
+			file.extend_from_slice(&record.orig_len.to_le_bytes());
+			file.extend_from_slice(&record.data);
+		}
+
+		file
+	}
+}
+
+static CAPTURE: InterruptTicketMutex<Option<CaptureRing>> = InterruptTicketMutex::new(None);
+
+/// Records a frame, which is received or transmitted by the network interface.
+pub(crate) fn record(frame: &[u8]) {
+	if let Some(ring) = CAPTURE.lock().as_mut() {
+		ring.push(arch::kernel::systemtime::now_micros(), frame);
+	}
+}
+
+/// Returns a snapshot of the capture ring as pcap file.
+pub(crate) fn export() -> Vec<u8> {
+	CAPTURE.lock().as_ref().map_or_else(
+		|| CaptureRing::new(0, DEFAULT_SNAPLEN).export(),
+		CaptureRing::export,
+	)
+}
+
+/// Prints a snapshot of the capture ring as hex dump.
+#[cfg(feature = "shell")]
+pub(crate) fn print() {
+	use alloc::string::String;
+	use core::fmt::Write;
+
+	for chunk in export().chunks(32) {
+		let mut line = String::with_capacity(2 * chunk.len());
+		for byte in chunk {
+			write!(line, "{byte:02x}").unwrap();
+		}
+		println!("{line}");
+	}
+}
+
+/// Allocates the capture ring and spawns the capture server on the executor.
+pub(crate) fn init(nic: &mut NetworkInterface<'_>) {
+	let frames = hermit_var!("HERMIT_PCAP_FRAMES").map_or(DEFAULT_FRAMES, |frames| {
+		frames.parse().unwrap_or_else(|_| {
+			warn!("Invalid number of frames {frames} for the capture ring");
+			DEFAULT_FRAMES
+		})
+	});
+	let snaplen = hermit_var!("HERMIT_PCAP_SNAPLEN").map_or(DEFAULT_SNAPLEN, |snaplen| {
+		snaplen
+			.parse()
+			.ok()
+			.filter(|snaplen| *snaplen > 0)
+			.unwrap_or_else(|| {
+				warn!("Invalid snapshot length {snaplen} for the capture ring");
+				DEFAULT_SNAPLEN
+			})
+	});
+	*CAPTURE.lock() = Some(CaptureRing::new(frames, snaplen));
+	info!("Capture the last {frames} frames with a snapshot length of {snaplen} bytes");
+
+	let port = match hermit_var!("HERMIT_PCAP_PORT") {
+		Some(port) => match port.parse::<u16>() {
+			Ok(port) => port,
+			Err(_) => {
+				warn!("Invalid port {port} for the capture server");
+				return;
+			}
+		},
+		None => DEFAULT_PORT,
+	};
+
+	let handle = match nic.create_tcp_handle() {
+		Ok(handle) => handle,
+		Err(err) => {
+			warn!("Unable to create the socket of the capture server: {err:?}");
+			return;
+		}
+	};
+	if let Err(err) = nic.get_mut_socket::<tcp::Socket<'_>>(handle).listen(port) {
+		warn!("Unable to bind the capture server to port {port}: {err}");
+		nic.destroy_socket(handle);
+		return;
+	}
+
+	info!("Capture server listens on port {port}");
+	spawn(serve(handle, port));
+}
+
+async fn serve(handle: Handle, port: u16) {
+	let mut snapshot: Option<Vec<u8>> = None;
+	let mut offset = 0;
+
+	future::poll_fn(|cx| {
+		let mut guard = NIC.lock();
+		let Ok(nic) = guard.as_nic_mut() else {
+			return Poll::Ready(());
+		};
+		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(handle);
+
+		if !socket.is_open() {
+			snapshot = None;
+			offset = 0;
+			if let Err(err) = socket.listen(port) {
+				warn!("Unable to bind the capture server to port {port}: {err}");
+				return Poll::Ready(());
+			}
+		}
+
+		if socket.may_send() {
+			let file = snapshot.get_or_insert_with(export);
+			match socket.send_slice(&file[offset..]) {
+				Ok(len) => offset += len,
+				Err(err) => {
+					debug!("Unable to send the capture: {err}");
+					socket.abort();
+				}
+			}
+			if offset == file.len() {
+				socket.close();
+			}
+		}
+
+		socket.register_recv_waker(cx.waker());
+		socket.register_send_waker(cx.waker());
+		Poll::Pending
+	})
+	.await
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn export_header_and_records() {
+		let mut ring = CaptureRing::new(4, 4);
+		ring.push(1_500_000, &[1, 2, 3, 4, 5, 6]);
+		let file = ring.export();
+
+		assert_eq!(file.len(), 24 + 16 + 4);
+		assert_eq!(file[0..4], [0xd4, 0xc3, 0xb2, 0xa1]);
+		assert_eq!(file[4..8], [2, 0, 4, 0]);
+		assert_eq!(file[16..20], 4u32.to_le_bytes());
+		assert_eq!(file[20..24], LINKTYPE_ETHERNET.to_le_bytes());
+		// ts_sec, ts_usec, incl_len and orig_len
+		assert_eq!(file[24..28], 1u32.to_le_bytes());
+		assert_eq!(file[28..32], 500_000u32.to_le_bytes());
+		assert_eq!(file[32..36], 4u32.to_le_bytes());
+		assert_eq!(file[36..40], 6u32.to_le_bytes());
+		assert_eq!(file[40..], [1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn keep_last_frames() {
+		let mut ring = CaptureRing::new(2, 64);
+		for i in 0..5u8 {
+			ring.push(u64::from(i), &[i]);
+		}
+
+		let records = ring
+			.records
+			.iter()
+			.map(|record| record.data[0])
+			.collect::<Vec<_>>();
+		assert_eq!(records, [3, 4]);
+	}
+}
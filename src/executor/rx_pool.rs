@@ -0,0 +1,126 @@
+//! Recycling of the buffers of received packets.
+//!
+//! The driver copies each received frame out of its virtqueue buffer, which is
+//! reposted to the device immediately. Without a pool, each frame requires a fresh
+//! allocation. Instead, a frame is copied into a buffer of the pool, which returns
+//! there, as soon as the [`RxToken`](super::device::RxToken) is consumed.
+//!
+//! A frame, which spans multiple virtqueue buffers (`VIRTIO_NET_F_MRG_RXBUF`), grows
+//! its pool buffer. The grown capacity is kept for subsequent frames.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+
+use hermit_sync::InterruptTicketMutex;
+
+use super::device::NET_STATS;
+
+/// Maximal number of buffers, which are kept for reuse
+const CAPACITY: usize = 64;
+
+/// Buffers of received packets, which are free for reuse
+#[derive(Debug)]
+pub(crate) struct RxPool {
+	buffers: Vec<Vec<u8>>,
+}
+
+impl RxPool {
+	pub const fn new() -> Self {
+		Self {
+			buffers: Vec::new(),
+		}
+	}
+
+	/// Preallocates `count` buffers with a capacity of `len` bytes.
+	pub fn fill(&mut self, count: usize, len: usize) {
+		let count = count.min(CAPACITY).saturating_sub(self.buffers.len());
+		self.buffers.reserve_exact(count);
+		for _ in 0..count {
+			self.buffers.push(Vec::with_capacity(len));
+		}
+	}
+
+	/// Returns an empty buffer with a capacity of at least `len` bytes. Returns
+	/// `true` as second value, if the buffer is reused.
+	pub fn take(&mut self, len: usize) -> (Vec<u8>, bool) {
+		match self.buffers.pop() {
+			Some(mut buffer) => {
+				buffer.reserve(len);
+				(buffer, true)
+			}
+			None => (Vec::with_capacity(len), false),
+		}
+	}
+
+	/// Returns `buffer` to the pool. It is freed, if the pool is already full.
+	pub fn recycle(&mut self, mut buffer: Vec<u8>) {
+		if self.buffers.len() < CAPACITY && buffer.capacity() > 0 {
+			buffer.clear();
+			self.buffers.push(buffer);
+		}
+	}
+}
+
+static RX_POOL: InterruptTicketMutex<RxPool> = InterruptTicketMutex::new(RxPool::new());
+
+/// Preallocates `count` buffers with a capacity of `len` bytes.
+pub(crate) fn fill(count: usize, len: usize) {
+	RX_POOL.lock().fill(count, len);
+}
+
+/// Returns an empty buffer with a capacity of at least `len` bytes.
+pub(crate) fn take(len: usize) -> Vec<u8> {
+	let (buffer, reused) = RX_POOL.lock().take(len);
+	if reused {
+		NET_STATS.rx_buffer_reuses.fetch_add(1, Ordering::Relaxed);
+	} else {
+		NET_STATS.rx_buffer_allocs.fetch_add(1, Ordering::Relaxed);
+	}
+
+	buffer
+}
+
+/// Returns `buffer` for reuse.
+pub(crate) fn recycle(buffer: Vec<u8>) {
+	RX_POOL.lock().recycle(buffer);
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reuse_recycled_buffers() {
+		let mut pool = RxPool::new();
+		pool.fill(2, 1514);
+
+		let (mut first, reused) = pool.take(1514);
+		assert!(reused);
+		// a merged frame grows its buffer
+		first.extend_from_slice(&[0xab; 3000]);
+		let (second, _) = pool.take(1514);
+		let (third, reused) = pool.take(1514);
+		assert!(!reused);
+		assert!(third.capacity() >= 1514);
+
+		pool.recycle(first);
+		let (buffer, reused) = pool.take(1514);
+		assert!(reused);
+		assert!(buffer.is_empty());
+		assert!(buffer.capacity() >= 3000);
+
+		pool.recycle(second);
+		pool.recycle(third);
+		assert_eq!(pool.buffers.len(), 2);
+	}
+
+	#[test]
+	fn bounded_pool() {
+		let mut pool = RxPool::new();
+		pool.fill(2 * CAPACITY, 64);
+		assert_eq!(pool.buffers.len(), CAPACITY);
+
+		pool.recycle(Vec::with_capacity(64));
+		assert_eq!(pool.buffers.len(), CAPACITY);
+	}
+}
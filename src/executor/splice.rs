@@ -0,0 +1,108 @@
+//! Forwarding between two TCP sockets inside the kernel.
+//!
+//! A splice connects two TCP sockets, e.g. for loopback tests or simple proxies.
+//! After each poll of the interface, the received data of a socket is moved into
+//! the transmit buffer of the other socket, as far as this buffer has room. Hence,
+//! a full transmit buffer holds the data back and the receive window of the source
+//! closes. If the peer of a socket finishes sending, the other socket is closed
+//! for sending as soon as the remaining data is forwarded. A reset of one
+//! connection aborts the other one.
+
+use smoltcp::iface::SocketSet;
+use smoltcp::socket::tcp;
+
+use crate::executor::network::Handle;
+
+/// Size of the chunks, in which data is moved between the sockets
+const CHUNK_SIZE: usize = 2048;
+
+/// Pair of TCP sockets, which forward their data to each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Splice {
+	a: Handle,
+	b: Handle,
+	/// Set, if the direction from `a` to `b` is finished
+	a_to_b_finished: bool,
+	/// Set, if the direction from `b` to `a` is finished
+	b_to_a_finished: bool,
+}
+
+impl Splice {
+	pub fn new(a: Handle, b: Handle) -> Self {
+		Self {
+			a,
+			b,
+			a_to_b_finished: false,
+			b_to_a_finished: false,
+		}
+	}
+
+	/// Returns `true`, if `handle` is part of the splice.
+	pub fn contains(&self, handle: Handle) -> bool {
+		self.a == handle || self.b == handle
+	}
+
+	/// Forwards the data in both directions. Returns `false`, if the splice is
+	/// torn down.
+	pub fn forward(&mut self, sockets: &mut SocketSet<'_>) -> bool {
+		if !self.a_to_b_finished {
+			self.a_to_b_finished = forward(sockets, self.a, self.b);
+		}
+		if !self.b_to_a_finished {
+			self.b_to_a_finished = forward(sockets, self.b, self.a);
+		}
+		if self.a_to_b_finished && self.b_to_a_finished {
+			return false;
+		}
+
+		let a_closed = sockets.get::<tcp::Socket<'_>>(self.a).state() == tcp::State::Closed;
+		let b_closed = sockets.get::<tcp::Socket<'_>>(self.b).state() == tcp::State::Closed;
+		if a_closed || b_closed {
+			sockets.get_mut::<tcp::Socket<'_>>(self.a).abort();
+			sockets.get_mut::<tcp::Socket<'_>>(self.b).abort();
+			return false;
+		}
+
+		true
+	}
+}
+
+/// Moves the received data of `src` into the transmit buffer of `dst`.
+///
+/// Returns `true`, if the peer of `src` finished sending and all of its data is
+/// forwarded. In this case, `dst` is closed for sending.
+fn forward(sockets: &mut SocketSet<'_>, src: Handle, dst: Handle) -> bool {
+	let mut chunk = [0u8; CHUNK_SIZE];
+	loop {
+		let dst_socket = sockets.get::<tcp::Socket<'_>>(dst);
+		let room = if dst_socket.may_send() {
+			dst_socket.send_capacity() - dst_socket.send_queue()
+		} else {
+			0
+		};
+		let src_socket = sockets.get_mut::<tcp::Socket<'_>>(src);
+		if room == 0 || !src_socket.can_recv() {
+			break;
+		}
+
+		let len = room.min(CHUNK_SIZE);
+		let Ok(len) = src_socket.recv_slice(&mut chunk[..len]) else {
+			break;
+		};
+		let sent = sockets
+			.get_mut::<tcp::Socket<'_>>(dst)
+			.send_slice(&chunk[..len]);
+		debug_assert_eq!(sent, Ok(len));
+	}
+
+	let src_socket = sockets.get::<tcp::Socket<'_>>(src);
+	let finished = matches!(
+		src_socket.state(),
+		tcp::State::CloseWait | tcp::State::LastAck | tcp::State::Closing | tcp::State::TimeWait
+	) && src_socket.recv_queue() == 0;
+	if finished {
+		sockets.get_mut::<tcp::Socket<'_>>(dst).close();
+	}
+
+	finished
+}
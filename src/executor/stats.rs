@@ -0,0 +1,166 @@
+//! Server, which provides kernel statistics as JSON over UDP.
+//!
+//! Each datagram with the payload `stats` (optionally terminated by a line
+//! break) is answered by a JSON object, which contains the uptime, the memory
+//! usage, the counters of the network interface and the number of interrupts.
+//! All other datagrams are ignored. The port is defined by `HERMIT_STATS_PORT`.
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::future;
+use core::sync::atomic::Ordering;
+use core::task::Poll;
+
+use smoltcp::socket::udp;
+
+use crate::arch;
+use crate::arch::mm::physicalmem;
+use crate::executor::device::NET_STATS;
+use crate::executor::network::{Handle, NetworkInterface, NIC};
+use crate::executor::spawn;
+
+/// Default port of the statistics server
+const DEFAULT_PORT: u16 = 9876;
+
+/// Returns `true` if the datagram is a valid request.
+fn is_request(payload: &[u8]) -> bool {
+	let payload = payload.strip_suffix(b"\n").map_or(payload, |payload| {
+		payload.strip_suffix(b"\r").unwrap_or(payload)
+	});
+	payload == b"stats"
+}
+
+/// Serializes the kernel statistics as JSON object.
+fn stats() -> String {
+	let mut json = String::new();
+
+	write!(
+		json,
+		"{{\"uptime_us\":{},\"memory\":{{\"total\":{},\"free\":{}}}",
+		arch::processor::get_timer_ticks(),
+		physicalmem::total_memory_size(),
+		physicalmem::free_memory_size()
+	)
+	.unwrap();
+
+	write!(
+		json,
+		",\"net\":{{\"eth0\":{{\"rx_packets\":{},\"rx_bytes\":{},\"tx_packets\":{},\"tx_bytes\":{}}}}}",
+		NET_STATS.rx_packets.load(Ordering::Relaxed),
+		NET_STATS.rx_bytes.load(Ordering::Relaxed),
+		NET_STATS.tx_packets.load(Ordering::Relaxed),
+		NET_STATS.tx_bytes.load(Ordering::Relaxed)
+	)
+	.unwrap();
+
+	write!(
+		json,
+		",\"irq_latency_us\":{{\"signals\":{},\"avg\":{},\"max\":{},\"overflows\":{}}}",
+		NET_STATS.irq_signals.load(Ordering::Relaxed),
+		NET_STATS.irq_latency_avg(),
+		NET_STATS.irq_latency_max.load(Ordering::Relaxed),
+		NET_STATS.irq_signal_overflows.load(Ordering::Relaxed)
+	)
+	.unwrap();
+
+	write!(
+		json,
+		",\"rx_buffers\":{{\"allocs\":{},\"reuses\":{}}}",
+		NET_STATS.rx_buffer_allocs.load(Ordering::Relaxed),
+		NET_STATS.rx_buffer_reuses.load(Ordering::Relaxed)
+	)
+	.unwrap();
+
+	json.push_str(",\"irqs\":[");
+	#[cfg(not(target_arch = "riscv64"))]
+	{
+		let mut first = true;
+		arch::irq_statistics::for_each_irq_counter(|core_id, irq, name, counter| {
+			if !core::mem::take(&mut first) {
+				json.push(',');
+			}
+			write!(json, "{{\"core\":{core_id},\"irq\":{irq},").unwrap();
+			if let Some(name) = name {
+				write!(json, "\"name\":\"{name}\",").unwrap();
+			}
+			write!(json, "\"count\":{counter}}}").unwrap();
+		});
+	}
+	json.push_str("]}");
+
+	json
+}
+
+/// Binds the statistics server to its port and spawns it on the executor.
+pub(crate) fn init(nic: &mut NetworkInterface<'_>) {
+	let port = match hermit_var!("HERMIT_STATS_PORT") {
+		Some(port) => match port.parse::<u16>() {
+			Ok(port) => port,
+			Err(_) => {
+				warn!("Invalid port {port} for the statistics server");
+				return;
+			}
+		},
+		None => DEFAULT_PORT,
+	};
+
+	let handle = match nic.create_udp_handle() {
+		Ok(handle) => handle,
+		Err(err) => {
+			warn!("Unable to create the socket of the statistics server: {err:?}");
+			return;
+		}
+	};
+	if let Err(err) = nic.get_mut_socket::<udp::Socket<'_>>(handle).bind(port) {
+		warn!("Unable to bind the statistics server to port {port}: {err}");
+		nic.destroy_socket(handle);
+		return;
+	}
+
+	info!("Statistics server listens on port {port}");
+	spawn(serve(handle));
+}
+
+async fn serve(handle: Handle) {
+	future::poll_fn(|cx| {
+		let mut guard = NIC.lock();
+		let Ok(nic) = guard.as_nic_mut() else {
+			return Poll::Ready(());
+		};
+		let socket = nic.get_mut_socket::<udp::Socket<'_>>(handle);
+
+		while let Ok((payload, meta)) = socket.recv() {
+			if !is_request(payload) {
+				continue;
+			}
+
+			if let Err(err) = socket.send_slice(stats().as_bytes(), meta) {
+				debug!("Unable to send statistics to {}: {err}", meta.endpoint);
+			}
+		}
+
+		socket.register_recv_waker(cx.waker());
+		Poll::Pending
+	})
+	.await
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn accept_requests() {
+		assert!(is_request(b"stats"));
+		assert!(is_request(b"stats\n"));
+		assert!(is_request(b"stats\r\n"));
+	}
+
+	#[test]
+	fn ignore_malformed_requests() {
+		assert!(!is_request(b""));
+		assert!(!is_request(b"stat"));
+		assert!(!is_request(b"stats please"));
+		assert!(!is_request(&[0xff; 16]));
+	}
+}
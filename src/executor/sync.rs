@@ -0,0 +1,47 @@
+//! Synchronization primitives for async tasks on the executor.
+//!
+//! In contrast to [`InterruptTicketMutex`](hermit_sync::InterruptTicketMutex),
+//! a task, which waits for an [`AsyncMutex`], doesn't spin. It yields to the
+//! executor and is woken up, when the lock is released.
+
+/// Mutex, whose lock can be awaited by async tasks.
+///
+/// The waiting tasks are queued and are woken up by their wakers.
+pub(crate) type AsyncMutex<T> = async_lock::Mutex<T>;
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::sync::Arc;
+	use core::future::Future;
+	use core::pin::pin;
+	use core::task::{Context, Waker};
+
+	use super::*;
+	use crate::executor::WakeCounter;
+
+	#[test]
+	fn two_tasks_contend_on_mutex() {
+		let mutex = AsyncMutex::new(0);
+		let counter = Arc::new(WakeCounter::default());
+		let waker = Waker::from(counter.clone());
+		let mut cx = Context::from_waker(&waker);
+
+		async fn increment(mutex: &AsyncMutex<i32>) {
+			let mut guard = mutex.lock().await;
+			*guard += 1;
+		}
+		let mut first = pin!(increment(&mutex));
+		let mut second = pin!(increment(&mutex));
+
+		let guard = mutex.try_lock().unwrap();
+		assert!(first.as_mut().poll(&mut cx).is_pending());
+		assert!(second.as_mut().poll(&mut cx).is_pending());
+		assert_eq!(counter.count(), 0);
+
+		drop(guard);
+		assert!(counter.count() > 0);
+		assert!(first.as_mut().poll(&mut cx).is_ready());
+		assert!(second.as_mut().poll(&mut cx).is_ready());
+		assert_eq!(*mutex.try_lock().unwrap(), 2);
+	}
+}
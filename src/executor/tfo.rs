@@ -0,0 +1,177 @@
+//! Client side of TCP Fast Open (RFC 7413).
+//!
+//! A client requests a cookie by an empty fast open option in its SYN. The
+//! server returns the cookie in its SYN-ACK. Later connections to the same
+//! server can carry data in the SYN together with the cookie, which saves
+//! a round-trip.
+//!
+//! smoltcp builds the SYN on its own. It can neither attach data to the SYN
+//! nor append further options. Hence, the cookie request isn't sent yet and
+//! [`NetworkInterface::tcp_connect_with_data`](super::network::NetworkInterface::tcp_connect_with_data)
+//! always falls back to a regular three-way handshake. The option codec and
+//! the cookie cache are already usable by a full implementation.
+
+#![allow(dead_code)]
+
+use alloc::collections::VecDeque;
+
+use smoltcp::wire::IpAddress;
+
+/// Option kind of TCP Fast Open
+pub(crate) const TCPOPT_FASTOPEN: u8 = 34;
+/// Minimal length of a cookie
+const COOKIE_MIN_LEN: usize = 4;
+/// Maximal length of a cookie
+const COOKIE_MAX_LEN: usize = 16;
+/// Number of servers, whose cookies are cached
+const CACHE_SIZE: usize = 16;
+
+/// Cookie, which is issued by a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cookie {
+	len: u8,
+	bytes: [u8; COOKIE_MAX_LEN],
+}
+
+impl Cookie {
+	/// Returns `None`, if the length of `cookie` is invalid.
+	pub fn new(cookie: &[u8]) -> Option<Self> {
+		if !(COOKIE_MIN_LEN..=COOKIE_MAX_LEN).contains(&cookie.len()) || cookie.len() % 2 != 0 {
+			return None;
+		}
+
+		let mut bytes = [0; COOKIE_MAX_LEN];
+		bytes[..cookie.len()].copy_from_slice(cookie);
+		Some(Self {
+			len: cookie.len() as u8,
+			bytes,
+		})
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes[..usize::from(self.len)]
+	}
+
+	/// Writes the fast open option, which carries the cookie, into `buffer`
+	/// and returns the length of the option.
+	pub fn emit(&self, buffer: &mut [u8]) -> usize {
+		let len = 2 + self.as_bytes().len();
+		buffer[0] = TCPOPT_FASTOPEN;
+		buffer[1] = len as u8;
+		buffer[2..len].copy_from_slice(self.as_bytes());
+		len
+	}
+}
+
+/// Fast open option without a cookie, which requests a cookie from the server
+pub(crate) const COOKIE_REQUEST: [u8; 2] = [TCPOPT_FASTOPEN, 2];
+
+/// Searches the options of a TCP header for the fast open option.
+///
+/// Returns `Some(None)` for a cookie request and `Some(Some(cookie))` for a
+/// valid cookie. Returns `None`, if the option is missing or malformed.
+pub(crate) fn parse_option(mut options: &[u8]) -> Option<Option<Cookie>> {
+	while let [kind, rest @ ..] = options {
+		match *kind {
+			// end of option list
+			0 => return None,
+			// no operation
+			1 => options = rest,
+			_ => {
+				let len = usize::from(*rest.first()?);
+				if len < 2 || len > options.len() {
+					return None;
+				}
+				if *kind == TCPOPT_FASTOPEN {
+					return if len == 2 {
+						Some(None)
+					} else {
+						Cookie::new(&options[2..len]).map(Some)
+					};
+				}
+				options = &options[len..];
+			}
+		}
+	}
+
+	None
+}
+
+/// Cookies of recently contacted servers
+///
+/// The least recently inserted cookie is evicted first.
+#[derive(Debug, Default)]
+pub(crate) struct CookieCache {
+	entries: VecDeque<(IpAddress, Cookie)>,
+}
+
+impl CookieCache {
+	pub const fn new() -> Self {
+		Self {
+			entries: VecDeque::new(),
+		}
+	}
+
+	pub fn get(&self, server: IpAddress) -> Option<Cookie> {
+		self.entries
+			.iter()
+			.find(|(addr, _)| *addr == server)
+			.map(|(_, cookie)| *cookie)
+	}
+
+	pub fn insert(&mut self, server: IpAddress, cookie: Cookie) {
+		self.remove(server);
+		if self.entries.len() == CACHE_SIZE {
+			self.entries.pop_front();
+		}
+		self.entries.push_back((server, cookie));
+	}
+
+	/// Forgets the cookie of `server`, e.g. because the server didn't accept it.
+	pub fn remove(&mut self, server: IpAddress) {
+		self.entries.retain(|(addr, _)| *addr != server);
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_cookie_after_other_options() {
+		// MSS, NOP, NOP, fast open with an 8-byte cookie
+		let options = [2, 4, 0x05, 0xb4, 1, 1, 34, 10, 1, 2, 3, 4, 5, 6, 7, 8];
+		let cookie = parse_option(&options).unwrap().unwrap();
+		assert_eq!(cookie.as_bytes(), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+		let mut buffer = [0; 18];
+		let len = cookie.emit(&mut buffer);
+		assert_eq!(buffer[..len], options[6..]);
+	}
+
+	#[test]
+	fn parse_cookie_request_and_malformed_options() {
+		assert_eq!(parse_option(&COOKIE_REQUEST), Some(None));
+		assert_eq!(parse_option(&[2, 4, 0x05, 0xb4]), None);
+		// cookie is too short
+		assert_eq!(parse_option(&[34, 4, 1, 2]), None);
+		// length exceeds the options
+		assert_eq!(parse_option(&[34, 10, 1, 2, 3, 4]), None);
+		// end of option list
+		assert_eq!(parse_option(&[0, 34, 2]), None);
+	}
+
+	#[test]
+	fn evict_oldest_cookie() {
+		let mut cache = CookieCache::new();
+		let cookie = Cookie::new(&[1, 2, 3, 4]).unwrap();
+		for i in 0..=CACHE_SIZE as u8 {
+			cache.insert(IpAddress::v4(10, 0, 0, i), cookie);
+		}
+
+		assert_eq!(cache.get(IpAddress::v4(10, 0, 0, 0)), None);
+		assert_eq!(cache.get(IpAddress::v4(10, 0, 0, 1)), Some(cookie));
+		cache.remove(IpAddress::v4(10, 0, 0, 1));
+		assert_eq!(cache.get(IpAddress::v4(10, 0, 0, 1)), None);
+	}
+}
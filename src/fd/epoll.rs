@@ -0,0 +1,395 @@
+//! Readiness notification in the style of Linux's `epoll`.
+//!
+//! An epoll object holds an interest list of file descriptors. Waiting polls the
+//! registered objects, which register the waker of the waiting task. Hence, progress
+//! of an object, e.g. by `poll_common` for sockets, wakes up the waiting task.
+//!
+//! An interest is level-triggered by default and reports its events, as long as they
+//! are ready. An edge-triggered interest (`EPOLLET`) only reports events, which
+//! weren't ready at the previous wait.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::future::{self, Future};
+use core::task::{Context, Poll, Waker};
+
+use async_trait::async_trait;
+use hermit_sync::InterruptTicketMutex;
+
+use crate::arch::kernel::core_local::core_scheduler;
+use crate::fd::{FileDescriptor, IoError, ObjectInterface, PollEvent};
+
+bitflags! {
+	/// Events of an interest and flags, which control their reporting
+	#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+	pub struct EpollEvents: u32 {
+		const EPOLLIN = 0x1;
+		const EPOLLPRI = 0x2;
+		const EPOLLOUT = 0x4;
+		const EPOLLERR = 0x8;
+		const EPOLLHUP = 0x10;
+		const EPOLLRDNORM = 0x040;
+		const EPOLLRDBAND = 0x080;
+		const EPOLLWRNORM = 0x0100;
+		const EPOLLWRBAND = 0x0200;
+		const EPOLLRDHUP = 0x2000;
+		const EPOLLONESHOT = 1 << 30;
+		const EPOLLET = 1 << 31;
+	}
+}
+
+impl EpollEvents {
+	/// Events, which are reported, even if they aren't requested
+	const ALWAYS: Self = Self::EPOLLERR.union(Self::EPOLLHUP);
+
+	fn from_poll(event: PollEvent) -> Self {
+		// The events share their values with the ones of `poll`
+		Self::from_bits_truncate(u32::from(event.bits() as u16))
+	}
+
+	fn to_poll(self) -> PollEvent {
+		PollEvent::from_bits_truncate((self.bits() & 0xffff) as u16 as i16)
+	}
+}
+
+/// Event of the interest list, which is exchanged with the application
+#[repr(C)]
+#[cfg_attr(target_arch = "x86_64", repr(packed))]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EpollEvent {
+	pub events: EpollEvents,
+	pub data: u64,
+}
+
+/// Operation on the interest list
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum EpollOp {
+	Add,
+	Del,
+	Mod,
+}
+
+/// Interest in the events of a file descriptor
+#[derive(Debug)]
+struct Interest {
+	events: EpollEvents,
+	data: u64,
+	/// Readiness at the previous wait, which is required for edge-triggered interests
+	ready: EpollEvents,
+	/// A one-shot interest reported its events and is disabled until it is modified
+	disabled: bool,
+}
+
+impl Interest {
+	fn new(event: EpollEvent) -> Self {
+		Self {
+			events: event.events,
+			data: event.data,
+			ready: EpollEvents::empty(),
+			disabled: false,
+		}
+	}
+
+	/// Returns the events, which are reported for the readiness `ready`.
+	fn report(&mut self, ready: EpollEvents) -> EpollEvents {
+		let ready = ready & (self.events | EpollEvents::ALWAYS);
+		let events = if self.events.contains(EpollEvents::EPOLLET) {
+			ready - self.ready
+		} else {
+			ready
+		};
+		self.ready = ready;
+
+		if self.disabled || events.is_empty() {
+			return EpollEvents::empty();
+		}
+
+		if self.events.contains(EpollEvents::EPOLLONESHOT) {
+			self.disabled = true;
+		}
+
+		events
+	}
+}
+
+#[derive(Debug, Default)]
+struct EpollState {
+	interests: BTreeMap<FileDescriptor, Interest>,
+	/// Wakers of the waiting tasks, which are woken up by changes of the interest list
+	wakers: VecDeque<Waker>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Epoll {
+	/// Interest list, which is shared with duplicates of the file descriptor
+	state: Arc<InterruptTicketMutex<EpollState>>,
+}
+
+impl Epoll {
+	pub fn new() -> Self {
+		Self {
+			state: Arc::new(InterruptTicketMutex::new(EpollState::default())),
+		}
+	}
+
+	/// Polls the objects of the interest list, which are looked up by `get_object`,
+	/// and stores the reported events in `events`.
+	fn poll_wait<F>(
+		&self,
+		cx: &mut Context<'_>,
+		events: &mut [EpollEvent],
+		mut get_object: F,
+	) -> Poll<Result<usize, IoError>>
+	where
+		F: FnMut(
+			FileDescriptor,
+			&mut Context<'_>,
+		) -> Poll<Result<Arc<dyn ObjectInterface>, IoError>>,
+	{
+		// The objects are polled without holding the lock. Hence, the interest
+		// list may change in the meantime.
+		let fds: Vec<_> = {
+			let state = self.state.lock();
+			state
+				.interests
+				.iter()
+				.filter(|(_, interest)| !interest.disabled)
+				.map(|(fd, interest)| (*fd, interest.events))
+				.collect()
+		};
+
+		let mut readiness = Vec::with_capacity(fds.len());
+		for (fd, interest) in fds {
+			let ready = match get_object(fd, cx) {
+				Poll::Ready(Ok(obj)) => {
+					let mut pinned = core::pin::pin!(obj.poll(interest.to_poll()));
+					match pinned.as_mut().poll(cx) {
+						Poll::Ready(Ok(event)) => Some(EpollEvents::from_poll(event)),
+						Poll::Ready(Err(_)) => Some(EpollEvents::EPOLLERR),
+						Poll::Pending => Some(EpollEvents::empty()),
+					}
+				}
+				// The file descriptor is closed
+				Poll::Ready(Err(_)) => None,
+				Poll::Pending => Some(EpollEvents::empty()),
+			};
+			readiness.push((fd, ready));
+		}
+
+		let mut state = self.state.lock();
+		let mut count = 0;
+		for (fd, ready) in readiness {
+			let Some(ready) = ready else {
+				state.interests.remove(&fd);
+				continue;
+			};
+
+			// Closed file descriptors are still removed, if `events` is full
+			if count == events.len() {
+				continue;
+			}
+
+			// Interests, which were removed in the meantime, aren't reported
+			if let Some(interest) = state.interests.get_mut(&fd) {
+				let reported = interest.report(ready);
+				if !reported.is_empty() {
+					events[count] = EpollEvent {
+						events: reported,
+						data: interest.data,
+					};
+					count += 1;
+				}
+			}
+		}
+
+		if count > 0 {
+			Poll::Ready(Ok(count))
+		} else {
+			state.wakers.push_back(cx.waker().clone());
+			Poll::Pending
+		}
+	}
+}
+
+#[async_trait]
+impl ObjectInterface for Epoll {
+	fn epoll_ctl(&self, op: EpollOp, fd: FileDescriptor, event: EpollEvent) -> Result<(), IoError> {
+		let mut state = self.state.lock();
+		match op {
+			EpollOp::Add => {
+				if state.interests.contains_key(&fd) {
+					return Err(IoError::EEXIST);
+				}
+				state.interests.insert(fd, Interest::new(event));
+			}
+			EpollOp::Mod => {
+				let interest = state.interests.get_mut(&fd).ok_or(IoError::ENOENT)?;
+				*interest = Interest::new(event);
+			}
+			EpollOp::Del => {
+				state.interests.remove(&fd).ok_or(IoError::ENOENT)?;
+			}
+		}
+
+		// Waiting tasks have to take the changed interest list into account
+		for waker in state.wakers.drain(..) {
+			waker.wake();
+		}
+
+		Ok(())
+	}
+
+	async fn epoll_wait(&self, events: &mut [EpollEvent]) -> Result<usize, IoError> {
+		future::poll_fn(|cx| {
+			self.poll_wait(cx, events, |fd, cx| {
+				core::pin::pin!(core_scheduler().get_object(fd)).poll(cx)
+			})
+		})
+		.await
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+	use crate::executor::WakeCounter;
+	use crate::fd::eventfd::EventFd;
+	use crate::fd::EventFlags;
+
+	/// Polls `epoll` with the objects of `table`.
+	fn poll_wait(
+		epoll: &Epoll,
+		table: &BTreeMap<FileDescriptor, Arc<dyn ObjectInterface>>,
+		waker: &Waker,
+		events: &mut [EpollEvent],
+	) -> Poll<Result<usize, IoError>> {
+		let mut cx = Context::from_waker(waker);
+		epoll.poll_wait(&mut cx, events, |fd, _cx| {
+			Poll::Ready(table.get(&fd).cloned().ok_or(IoError::EBADF))
+		})
+	}
+
+	fn write(obj: &Arc<dyn ObjectInterface>, value: u64) {
+		let mut cx = Context::from_waker(Waker::noop());
+		let mut pinned = core::pin::pin!(obj.async_write(&value.to_ne_bytes()));
+		assert!(pinned.as_mut().poll(&mut cx).is_ready());
+	}
+
+	fn interest(events: EpollEvents) -> Interest {
+		Interest::new(EpollEvent { events, data: 42 })
+	}
+
+	#[test]
+	fn level_triggered() {
+		let mut interest = interest(EpollEvents::EPOLLIN);
+
+		assert_eq!(
+			interest.report(EpollEvents::EPOLLIN | EpollEvents::EPOLLOUT),
+			EpollEvents::EPOLLIN
+		);
+		assert_eq!(interest.report(EpollEvents::EPOLLIN), EpollEvents::EPOLLIN);
+		assert_eq!(
+			interest.report(EpollEvents::EPOLLHUP),
+			EpollEvents::EPOLLHUP
+		);
+		assert!(interest.report(EpollEvents::empty()).is_empty());
+	}
+
+	#[test]
+	fn edge_triggered() {
+		let mut interest = interest(EpollEvents::EPOLLIN | EpollEvents::EPOLLET);
+
+		assert_eq!(interest.report(EpollEvents::EPOLLIN), EpollEvents::EPOLLIN);
+		assert!(interest.report(EpollEvents::EPOLLIN).is_empty());
+		assert!(interest.report(EpollEvents::empty()).is_empty());
+		assert_eq!(interest.report(EpollEvents::EPOLLIN), EpollEvents::EPOLLIN);
+	}
+
+	#[test]
+	fn one_shot() {
+		let mut interest = interest(EpollEvents::EPOLLIN | EpollEvents::EPOLLONESHOT);
+
+		assert_eq!(interest.report(EpollEvents::EPOLLIN), EpollEvents::EPOLLIN);
+		assert!(interest.disabled);
+		assert!(interest.report(EpollEvents::EPOLLIN).is_empty());
+	}
+
+	#[test]
+	fn wake_up_waiting_task() {
+		let epoll = Epoll::new();
+		let eventfd: Arc<dyn ObjectInterface> = Arc::new(EventFd::new(0, EventFlags::empty()));
+		let table = BTreeMap::from([(3, eventfd.clone())]);
+		let event = EpollEvent {
+			events: EpollEvents::EPOLLIN,
+			data: 42,
+		};
+		epoll.epoll_ctl(EpollOp::Add, 3, event).unwrap();
+
+		let counter = Arc::new(WakeCounter::default());
+		let waker = Waker::from(counter.clone());
+		let mut events = [EpollEvent::default(); 4];
+		assert!(poll_wait(&epoll, &table, &waker, &mut events).is_pending());
+
+		// The event file descriptor wakes up the waiting task.
+		write(&eventfd, 1);
+		assert!(counter.count() > 0);
+		assert_eq!(
+			poll_wait(&epoll, &table, &waker, &mut events),
+			Poll::Ready(Ok(1))
+		);
+		assert_eq!({ events[0].events }, EpollEvents::EPOLLIN);
+		assert_eq!({ events[0].data }, 42);
+	}
+
+	#[test]
+	fn remove_fd_while_waiting() {
+		let epoll = Epoll::new();
+		let eventfd: Arc<dyn ObjectInterface> = Arc::new(EventFd::new(0, EventFlags::empty()));
+		let mut table = BTreeMap::from([(3, eventfd.clone()), (4, eventfd)]);
+		let event = EpollEvent {
+			events: EpollEvents::EPOLLIN,
+			data: 0,
+		};
+		epoll.epoll_ctl(EpollOp::Add, 3, event).unwrap();
+		epoll.epoll_ctl(EpollOp::Add, 4, event).unwrap();
+
+		let counter = Arc::new(WakeCounter::default());
+		let waker = Waker::from(counter.clone());
+		let mut events = [EpollEvent::default(); 4];
+		assert!(poll_wait(&epoll, &table, &waker, &mut events).is_pending());
+
+		// Changes of the interest list wake up the waiting task.
+		epoll.epoll_ctl(EpollOp::Del, 3, event).unwrap();
+		assert_eq!(counter.count(), 1);
+		assert!(poll_wait(&epoll, &table, &waker, &mut events).is_pending());
+
+		// A closed file descriptor is removed from the interest list.
+		table.remove(&4);
+		assert!(poll_wait(&epoll, &table, &waker, &mut events).is_pending());
+		assert!(epoll.state.lock().interests.is_empty());
+	}
+
+	#[test]
+	fn remove_closed_fd_if_events_are_full() {
+		let epoll = Epoll::new();
+		let eventfd: Arc<dyn ObjectInterface> = Arc::new(EventFd::new(1, EventFlags::empty()));
+		let table = BTreeMap::from([(3, eventfd.clone()), (4, eventfd)]);
+		let event = EpollEvent {
+			events: EpollEvents::EPOLLIN,
+			data: 0,
+		};
+		for fd in 3..6 {
+			epoll.epoll_ctl(EpollOp::Add, fd, event).unwrap();
+		}
+
+		let mut events = [EpollEvent::default(); 1];
+		assert_eq!(
+			poll_wait(&epoll, &table, Waker::noop(), &mut events),
+			Poll::Ready(Ok(1))
+		);
+		let state = epoll.state.lock();
+		assert_eq!(state.interests.keys().copied().collect::<Vec<_>>(), [3, 4]);
+	}
+}
@@ -1,5 +1,6 @@
 use alloc::boxed::Box;
 use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
 use core::future::{self, Future};
 use core::mem;
 use core::task::{ready, Poll, Waker};
@@ -7,7 +8,10 @@ use core::task::{ready, Poll, Waker};
 use async_lock::Mutex;
 use async_trait::async_trait;
 
-use crate::fd::{block_on, EventFlags, IoError, ObjectInterface, PollEvent};
+use crate::fd::{EventFlags, IoError, ObjectInterface, PollEvent};
+
+/// Maximal value of the counter. A write, which exceeds it, blocks.
+const MAX_COUNTER: u64 = u64::MAX - 1;
 
 #[derive(Debug)]
 struct EventState {
@@ -24,29 +28,69 @@ impl EventState {
 			write_queue: VecDeque::new(),
 		}
 	}
-}
 
-#[derive(Debug)]
-pub(crate) struct EventFd {
-	state: Mutex<EventState>,
-	flags: EventFlags,
-}
+	/// Reads the counter and resets it. In semaphore mode, the counter is
+	/// decremented by one and one is returned. Returns `None`, if the counter
+	/// is zero and the read would block.
+	fn read(&mut self, semaphore: bool) -> Option<u64> {
+		if self.counter == 0 {
+			return None;
+		}
 
-impl Clone for EventFd {
-	fn clone(&self) -> Self {
-		let counter = block_on(async { Ok(self.state.lock().await.counter) }, None).unwrap();
-		Self {
-			state: Mutex::new(EventState::new(counter)),
-			flags: self.flags,
+		let value = if semaphore { 1 } else { self.counter };
+		self.counter -= value;
+		// The counter has room again.
+		for waker in self.write_queue.drain(..) {
+			waker.wake();
 		}
+
+		Some(value)
 	}
+
+	/// Adds `value` to the counter. Returns `None`, if the counter would exceed
+	/// its maximum and the write would block.
+	fn write(&mut self, value: u64) -> Option<()> {
+		if MAX_COUNTER - self.counter < value {
+			return None;
+		}
+
+		self.counter += value;
+		if value > 0 {
+			for waker in self.read_queue.drain(..) {
+				waker.wake();
+			}
+		}
+
+		Some(())
+	}
+
+	/// Returns the available events.
+	fn available(&self) -> PollEvent {
+		let mut available = PollEvent::empty();
+		if self.counter < MAX_COUNTER {
+			available.insert(PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND);
+		}
+		if self.counter > 0 {
+			available.insert(PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND);
+		}
+		available
+	}
+}
+
+/// Counter, which is used to notify tasks about events.
+///
+/// Clones refer to the same counter.
+#[derive(Debug, Clone)]
+pub(crate) struct EventFd {
+	state: Arc<Mutex<EventState>>,
+	flags: EventFlags,
 }
 
 impl EventFd {
 	pub fn new(initval: u64, flags: EventFlags) -> Self {
 		debug!("Create EventFd {}, {:?}", initval, flags);
 		Self {
-			state: Mutex::new(EventState::new(initval)),
+			state: Arc::new(Mutex::new(EventState::new(initval))),
 			flags,
 		}
 	}
@@ -61,34 +105,16 @@ impl ObjectInterface for EventFd {
 			return Err(IoError::EINVAL);
 		}
 
+		let semaphore = self.flags.contains(EventFlags::EFD_SEMAPHORE);
 		future::poll_fn(|cx| {
-			if self.flags.contains(EventFlags::EFD_SEMAPHORE) {
-				let mut pinned = core::pin::pin!(self.state.lock());
-				let mut guard = ready!(pinned.as_mut().poll(cx));
-				if guard.counter > 0 {
-					guard.counter -= 1;
-					let tmp = u64::to_ne_bytes(1);
-					buf[..len].copy_from_slice(&tmp);
-					if let Some(cx) = guard.write_queue.pop_front() {
-						cx.wake_by_ref();
-					}
+			let mut pinned = core::pin::pin!(self.state.lock());
+			let mut guard = ready!(pinned.as_mut().poll(cx));
+			match guard.read(semaphore) {
+				Some(value) => {
+					buf[..len].copy_from_slice(&u64::to_ne_bytes(value));
 					Poll::Ready(Ok(len))
-				} else {
-					guard.read_queue.push_back(cx.waker().clone());
-					Poll::Pending
 				}
-			} else {
-				let mut pinned = core::pin::pin!(self.state.lock());
-				let mut guard = ready!(pinned.as_mut().poll(cx));
-				let tmp = guard.counter;
-				if tmp > 0 {
-					guard.counter = 0;
-					buf[..len].copy_from_slice(&u64::to_ne_bytes(tmp));
-					if let Some(cx) = guard.read_queue.pop_front() {
-						cx.wake_by_ref();
-					}
-					Poll::Ready(Ok(len))
-				} else {
+				None => {
 					guard.read_queue.push_back(cx.waker().clone());
 					Poll::Pending
 				}
@@ -105,67 +131,47 @@ impl ObjectInterface for EventFd {
 		}
 
 		let c = u64::from_ne_bytes(buf[..len].try_into().unwrap());
+		if c == u64::MAX {
+			return Err(IoError::EINVAL);
+		}
 
 		future::poll_fn(|cx| {
 			let mut pinned = core::pin::pin!(self.state.lock());
 			let mut guard = ready!(pinned.as_mut().poll(cx));
-			if u64::MAX - guard.counter > c {
-				guard.counter += c;
-				if self.flags.contains(EventFlags::EFD_SEMAPHORE) {
-					for _i in 0..c {
-						if let Some(cx) = guard.read_queue.pop_front() {
-							cx.wake_by_ref();
-						} else {
-							break;
-						}
-					}
-				} else if let Some(cx) = guard.read_queue.pop_front() {
-					cx.wake_by_ref();
+			match guard.write(c) {
+				Some(()) => Poll::Ready(Ok(len)),
+				None => {
+					guard.write_queue.push_back(cx.waker().clone());
+					Poll::Pending
 				}
-
-				Poll::Ready(Ok(len))
-			} else {
-				guard.write_queue.push_back(cx.waker().clone());
-				Poll::Pending
 			}
 		})
 		.await
 	}
 
 	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
-		let guard = self.state.lock().await;
-
-		let mut available = PollEvent::empty();
-
-		if guard.counter < u64::MAX - 1 {
-			available.insert(PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND);
-		}
-
-		if guard.counter > 0 {
-			available.insert(PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND);
-		}
+		future::poll_fn(|cx| {
+			let mut pinned = core::pin::pin!(self.state.lock());
+			let mut guard = ready!(pinned.as_mut().poll(cx));
 
-		drop(guard);
+			let ret = event & guard.available();
+			if !ret.is_empty() {
+				return Poll::Ready(Ok(ret));
+			}
 
-		let ret = event & available;
+			let mut waiting = false;
+			if event.intersects(PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND) {
+				guard.read_queue.push_back(cx.waker().clone());
+				waiting = true;
+			}
+			if event.intersects(PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND)
+			{
+				guard.write_queue.push_back(cx.waker().clone());
+				waiting = true;
+			}
 
-		future::poll_fn(|cx| {
-			if ret.is_empty() {
-				let mut pinned = core::pin::pin!(self.state.lock());
-				let mut guard = ready!(pinned.as_mut().poll(cx));
-				if event
-					.intersects(PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDNORM)
-				{
-					guard.read_queue.push_back(cx.waker().clone());
-					Poll::Pending
-				} else if event
-					.intersects(PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND)
-				{
-					guard.write_queue.push_back(cx.waker().clone());
-					Poll::Pending
-				} else {
-					Poll::Ready(Ok(ret))
-				}
+			if waiting {
+				Poll::Pending
 			} else {
 				Poll::Ready(Ok(ret))
 			}
@@ -177,3 +183,27 @@ impl ObjectInterface for EventFd {
 		self.flags.contains(EventFlags::EFD_NONBLOCK)
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn read_and_write_counter() {
+		let mut state = EventState::new(0);
+		assert_eq!(state.read(false), None);
+		assert!(state.available().contains(PollEvent::POLLOUT));
+
+		assert_eq!(state.write(3), Some(()));
+		assert!(state.available().contains(PollEvent::POLLIN));
+		assert_eq!(state.read(true), Some(1));
+		assert_eq!(state.read(false), Some(2));
+		assert_eq!(state.read(true), None);
+
+		assert_eq!(state.write(MAX_COUNTER), Some(()));
+		assert_eq!(state.write(1), None);
+		assert!(!state.available().contains(PollEvent::POLLOUT));
+		assert_eq!(state.read(true), Some(1));
+		assert_eq!(state.write(1), Some(()));
+	}
+}
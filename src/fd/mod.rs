@@ -6,17 +6,23 @@ use core::task::Poll::{Pending, Ready};
 use core::time::Duration;
 
 use async_trait::async_trait;
-use dyn_clone::DynClone;
 #[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
+pub(crate) use self::epoll::EpollOp;
+pub use self::epoll::{EpollEvent, EpollEvents};
 use crate::arch::kernel::core_local::core_scheduler;
 use crate::executor::{block_on, poll_on};
 use crate::fs::{DirectoryEntry, FileAttr, SeekWhence};
 
+mod epoll;
 mod eventfd;
+mod pipe;
+#[cfg(all(feature = "udp", not(feature = "newlib")))]
+pub(crate) mod ring;
 #[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 pub(crate) mod socket;
+mod socketpair;
 pub(crate) mod stdio;
 
 pub(crate) const STDIN_FILENO: FileDescriptor = 0;
@@ -43,12 +49,27 @@ pub enum IoError {
 	EEXIST = crate::errno::EEXIST as isize,
 	EADDRINUSE = crate::errno::EADDRINUSE as isize,
 	EOVERFLOW = crate::errno::EOVERFLOW as isize,
+	EPIPE = crate::errno::EPIPE as isize,
+	EMSGSIZE = crate::errno::EMSGSIZE as isize,
+	EBUSY = crate::errno::EBUSY as isize,
+	EHOSTUNREACH = crate::errno::EHOSTUNREACH as isize,
+	EAFNOSUPPORT = crate::errno::EAFNOSUPPORT as isize,
+	EDESTADDRREQ = crate::errno::EDESTADDRREQ as isize,
+	ETIMEDOUT = crate::errno::ETIMEDOUT as isize,
+	ENOTSOCK = crate::errno::ENOTSOCK as isize,
+	ENOTTY = crate::errno::ENOTTY as isize,
+	EISCONN = crate::errno::EISCONN as isize,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub(crate) enum SocketOption {
 	TcpNoDelay,
+	ReuseAddr,
+	KeepAlive,
+	RcvLowat,
+	SndLowat,
+	NoCheck,
 }
 
 #[allow(dead_code)]
@@ -147,7 +168,7 @@ impl Default for AccessPermission {
 }
 
 #[async_trait]
-pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
+pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug {
 	/// check if an IO event is possible
 	async fn poll(&self, _event: PollEvent) -> Result<PollEvent, IoError> {
 		Ok(PollEvent::empty())
@@ -165,6 +186,66 @@ pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 		Err(IoError::ENOSYS)
 	}
 
+	/// `async_readv` scatters the read data to `bufs`. By default, the buffers
+	/// are filled one after another, until a buffer isn't filled completely.
+	/// Only the first buffer may block, such that the operation is never
+	/// interrupted after data has been read.
+	async fn async_readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize, IoError> {
+		let mut len = 0;
+		for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+			let result = if len == 0 {
+				self.async_read(buf).await
+			} else if let Some(result) = now_or_never(self.async_read(buf)).await {
+				result
+			} else {
+				break;
+			};
+
+			match result {
+				Ok(n) => {
+					len += n;
+					if n < buf.len() {
+						break;
+					}
+				}
+				// report the already read data
+				Err(_) if len > 0 => break,
+				Err(err) => return Err(err),
+			}
+		}
+		Ok(len)
+	}
+
+	/// `async_writev` gathers the written data from `bufs`. By default, the buffers
+	/// are written one after another, until a buffer isn't written completely.
+	/// Only the first buffer may block, such that the operation is never
+	/// interrupted after data has been written.
+	async fn async_writev(&self, bufs: &[&[u8]]) -> Result<usize, IoError> {
+		let mut len = 0;
+		for buf in bufs.iter().filter(|buf| !buf.is_empty()) {
+			let result = if len == 0 {
+				self.async_write(buf).await
+			} else if let Some(result) = now_or_never(self.async_write(buf)).await {
+				result
+			} else {
+				break;
+			};
+
+			match result {
+				Ok(n) => {
+					len += n;
+					if n < buf.len() {
+						break;
+					}
+				}
+				// report the already written data
+				Err(_) if len > 0 => break,
+				Err(err) => return Err(err),
+			}
+		}
+		Ok(len)
+	}
+
 	/// `is_nonblocking` returns `true`, if `read`, `write`, `recv` and send operations
 	/// don't block.
 	fn is_nonblocking(&self) -> bool {
@@ -212,6 +293,13 @@ pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 		Err(IoError::EINVAL)
 	}
 
+	/// `try_clone` creates a new, unconnected socket with the options and the
+	/// local endpoint of the socket
+	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+	fn try_clone(&self) -> Result<Arc<dyn ObjectInterface>, IoError> {
+		Err(IoError::EINVAL)
+	}
+
 	/// initiate a connection on a socket
 	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 	fn connect(&self, _endpoint: IpEndpoint) -> Result<(), IoError> {
@@ -242,6 +330,18 @@ pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 		Err(IoError::EINVAL)
 	}
 
+	/// `setsockopt_int` sets options with an integer value on sockets
+	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+	fn setsockopt_int(&self, _opt: SocketOption, _optval: i32) -> Result<(), IoError> {
+		Err(IoError::EINVAL)
+	}
+
+	/// `getsockopt_int` gets options with an integer value on sockets
+	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+	fn getsockopt_int(&self, _opt: SocketOption) -> Result<i32, IoError> {
+		Err(IoError::EINVAL)
+	}
+
 	/// `getsockname` gets socket name
 	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 	fn getsockname(&self) -> Option<IpEndpoint> {
@@ -276,7 +376,13 @@ pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 	/// shut down part of a full-duplex connection
 	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 	fn shutdown(&self, _how: i32) -> Result<(), IoError> {
-		Err(IoError::ENOSYS)
+		Err(IoError::ENOTSOCK)
+	}
+
+	/// Returns `true`, if the object is a socket of the network stack.
+	#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+	fn is_socket(&self) -> bool {
+		false
 	}
 
 	/// The `ioctl` function manipulates the underlying device parameters of special
@@ -284,6 +390,28 @@ pub(crate) trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 	fn ioctl(&self, _cmd: IoCtl, _value: bool) -> Result<(), IoError> {
 		Err(IoError::ENOSYS)
 	}
+
+	/// Returns the number of bytes, which can be read without blocking (`FIONREAD`).
+	fn nread(&self) -> Result<usize, IoError> {
+		Err(IoError::ENOTTY)
+	}
+
+	/// `epoll_ctl` adds, modifies or removes the interest of an epoll object
+	/// in the file descriptor `fd`
+	fn epoll_ctl(
+		&self,
+		_op: EpollOp,
+		_fd: FileDescriptor,
+		_event: EpollEvent,
+	) -> Result<(), IoError> {
+		Err(IoError::EINVAL)
+	}
+
+	/// `epoll_wait` waits for the events of an epoll object and returns
+	/// the number of reported events
+	async fn epoll_wait(&self, _events: &mut [EpollEvent]) -> Result<usize, IoError> {
+		Err(IoError::EINVAL)
+	}
 }
 
 pub(crate) fn read(fd: FileDescriptor, buf: &mut [u8]) -> Result<usize, IoError> {
@@ -334,6 +462,64 @@ pub(crate) fn write(fd: FileDescriptor, buf: &[u8]) -> Result<usize, IoError> {
 	}
 }
 
+/// Polls `future` once and returns its output, if it is already available.
+async fn now_or_never<F: Future>(future: F) -> Option<F::Output> {
+	let mut future = core::pin::pin!(future);
+	future::poll_fn(|cx| match future.as_mut().poll(cx) {
+		Ready(output) => Ready(Some(output)),
+		Pending => Ready(None),
+	})
+	.await
+}
+
+pub(crate) fn readv(fd: FileDescriptor, bufs: &mut [&mut [u8]]) -> Result<usize, IoError> {
+	let obj = get_object(fd)?;
+
+	if bufs.iter().all(|buf| buf.is_empty()) {
+		return Ok(0);
+	}
+
+	if obj.is_nonblocking() {
+		poll_on(obj.async_readv(bufs), Some(Duration::ZERO)).map_err(|x| {
+			if x == IoError::ETIME {
+				IoError::EAGAIN
+			} else {
+				x
+			}
+		})
+	} else {
+		match poll_on(obj.async_readv(bufs), Some(Duration::from_secs(2))) {
+			Err(IoError::ETIME) => block_on(obj.async_readv(bufs), None),
+			Err(x) => Err(x),
+			Ok(x) => Ok(x),
+		}
+	}
+}
+
+pub(crate) fn writev(fd: FileDescriptor, bufs: &[&[u8]]) -> Result<usize, IoError> {
+	let obj = get_object(fd)?;
+
+	if bufs.iter().all(|buf| buf.is_empty()) {
+		return Ok(0);
+	}
+
+	if obj.is_nonblocking() {
+		poll_on(obj.async_writev(bufs), Some(Duration::ZERO)).map_err(|x| {
+			if x == IoError::ETIME {
+				IoError::EAGAIN
+			} else {
+				x
+			}
+		})
+	} else {
+		match poll_on(obj.async_writev(bufs), Some(Duration::from_secs(2))) {
+			Err(IoError::ETIME) => block_on(obj.async_writev(bufs), None),
+			Err(x) => Err(x),
+			Ok(x) => Ok(x),
+		}
+	}
+}
+
 async fn poll_fds(fds: &mut [PollFd]) -> Result<u64, IoError> {
 	future::poll_fn(|cx| {
 		let mut counter: u64 = 0;
@@ -404,6 +590,74 @@ pub fn eventfd(initval: u64, flags: EventFlags) -> Result<FileDescriptor, IoErro
 	Ok(fd)
 }
 
+/// Creates an epoll object, whose interest list is initially empty.
+pub(crate) fn epoll_create() -> Result<FileDescriptor, IoError> {
+	insert_object(Arc::new(self::epoll::Epoll::new()))
+}
+
+/// Adds, modifies or removes the interest of the epoll object `epfd` in
+/// the file descriptor `fd`.
+pub(crate) fn epoll_ctl(
+	epfd: FileDescriptor,
+	op: EpollOp,
+	fd: FileDescriptor,
+	event: EpollEvent,
+) -> Result<(), IoError> {
+	if epfd == fd {
+		return Err(IoError::EINVAL);
+	}
+
+	let epoll = get_object(epfd).map_err(|_| IoError::EBADF)?;
+	get_object(fd).map_err(|_| IoError::EBADF)?;
+	epoll.epoll_ctl(op, fd, event)
+}
+
+/// Waits for the events of the epoll object `epfd`. Returns the number of
+/// events, which are stored in `events`, or zero, if the timeout elapsed.
+pub(crate) fn epoll_wait(
+	epfd: FileDescriptor,
+	events: &mut [EpollEvent],
+	timeout: Option<Duration>,
+) -> Result<usize, IoError> {
+	let epoll = get_object(epfd).map_err(|_| IoError::EBADF)?;
+	if events.is_empty() {
+		return Err(IoError::EINVAL);
+	}
+
+	match block_on(epoll.epoll_wait(events), timeout) {
+		Err(IoError::ETIME) => Ok(0),
+		result => result,
+	}
+}
+
+/// Creates a pair of connected stream sockets, which transfer
+/// data by a buffer in the kernel.
+pub(crate) fn socketpair(nonblocking: bool) -> Result<(FileDescriptor, FileDescriptor), IoError> {
+	let (first, second) = self::socketpair::Socket::pair(nonblocking);
+
+	let fd0 = insert_object(Arc::new(first))?;
+	let fd1 = insert_object(Arc::new(second)).map_err(|err| {
+		let _ = remove_object(fd0);
+		err
+	})?;
+
+	Ok((fd0, fd1))
+}
+
+/// Creates a pipe, whose data is buffered in the kernel. Returns the file
+/// descriptors of the read end and the write end.
+pub(crate) fn pipe(nonblocking: bool) -> Result<(FileDescriptor, FileDescriptor), IoError> {
+	let (reader, writer) = self::pipe::pipe(nonblocking);
+
+	let fd0 = insert_object(Arc::new(reader))?;
+	let fd1 = insert_object(Arc::new(writer)).map_err(|err| {
+		let _ = remove_object(fd0);
+		err
+	})?;
+
+	Ok((fd0, fd1))
+}
+
 pub(crate) fn get_object(fd: FileDescriptor) -> Result<Arc<dyn ObjectInterface>, IoError> {
 	block_on(core_scheduler().get_object(fd), None)
 }
@@ -428,6 +682,102 @@ pub(crate) fn dup_object(fd: FileDescriptor) -> Result<FileDescriptor, IoError>
 	block_on(core_scheduler().dup_object(fd), None)
 }
 
+/// Makes `fd2` a copy of the file descriptor `fd1`. If `fd2` was open, it is closed
+/// before. In contrast to `dup_object`, `fd2` is returned for `fd1 == fd2`, if `fd1`
+/// is valid.
+pub(crate) fn dup_object2(
+	fd1: FileDescriptor,
+	fd2: FileDescriptor,
+) -> Result<FileDescriptor, IoError> {
+	block_on(core_scheduler().dup_object2(fd1, fd2), None)
+}
+
 pub(crate) fn remove_object(fd: FileDescriptor) -> Result<Arc<dyn ObjectInterface>, IoError> {
 	block_on(core_scheduler().remove_object(fd), None)
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use core::sync::atomic::{AtomicUsize, Ordering};
+	use core::task::{Context, Poll, Waker};
+
+	use super::*;
+
+	/// Object, which transfers `ready` bytes at most and blocks afterwards.
+	#[derive(Debug)]
+	struct Partial {
+		ready: AtomicUsize,
+	}
+
+	impl Clone for Partial {
+		fn clone(&self) -> Self {
+			Self {
+				ready: AtomicUsize::new(self.ready.load(Ordering::Relaxed)),
+			}
+		}
+	}
+
+	impl Partial {
+		fn new(ready: usize) -> Self {
+			Self {
+				ready: AtomicUsize::new(ready),
+			}
+		}
+
+		async fn transfer(&self, len: usize) -> Result<usize, IoError> {
+			future::poll_fn(|_cx| {
+				let ready = self.ready.load(Ordering::Relaxed);
+				if ready == 0 {
+					return Pending;
+				}
+
+				let n = len.min(ready);
+				self.ready.store(ready - n, Ordering::Relaxed);
+				Ready(Ok(n))
+			})
+			.await
+		}
+	}
+
+	#[async_trait]
+	impl ObjectInterface for Partial {
+		async fn async_read(&self, buf: &mut [u8]) -> Result<usize, IoError> {
+			let n = self.transfer(buf.len()).await?;
+			buf[..n].fill(1);
+			Ok(n)
+		}
+
+		async fn async_write(&self, buf: &[u8]) -> Result<usize, IoError> {
+			self.transfer(buf.len()).await
+		}
+	}
+
+	fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+		let mut cx = Context::from_waker(Waker::noop());
+		core::pin::pin!(future).poll(&mut cx)
+	}
+
+	#[test]
+	fn vectored_io_blocks_only_on_first_buffer() {
+		let obj = Partial::new(0);
+		let mut a = [0u8; 4];
+		assert!(poll_once(obj.async_readv(&mut [&mut a[..]])).is_pending());
+		assert!(poll_once(obj.async_writev(&[&a[..]])).is_pending());
+
+		// the data of the first buffer is returned without blocking on the second one
+		let obj = Partial::new(4);
+		let mut b = [0u8; 4];
+		assert_eq!(
+			poll_once(obj.async_readv(&mut [&mut a[..], &mut b[..]])),
+			Ready(Ok(4))
+		);
+		assert_eq!(a, [1; 4]);
+		assert_eq!(b, [0; 4]);
+
+		let obj = Partial::new(6);
+		assert_eq!(
+			poll_once(obj.async_writev(&[&a[..], &b[..], &a[..]])),
+			Ready(Ok(6))
+		);
+	}
+}
@@ -0,0 +1,200 @@
+//! Unidirectional byte pipe with a bounded buffer in the kernel.
+//!
+//! Reading from an empty pipe blocks, until data is written or all write ends are
+//! closed. In the latter case, the reader gets the end of the stream. Writing to
+//! a full pipe blocks, until the reader consumes data. If all read ends are closed,
+//! writing fails with `EPIPE`.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::future;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use async_trait::async_trait;
+use hermit_sync::InterruptTicketMutex;
+
+use super::socketpair::Channel;
+use crate::fd::{IoCtl, IoError, ObjectInterface, PollEvent};
+
+/// Read end of a pipe, which signals the writer its closing
+#[derive(Debug)]
+struct ReadEnd(Arc<InterruptTicketMutex<Channel>>);
+
+impl Drop for ReadEnd {
+	fn drop(&mut self) {
+		let mut channel = self.0.lock();
+		channel.reader_closed = true;
+		channel.wake_all();
+	}
+}
+
+/// Write end of a pipe, which signals the reader the end of the stream
+#[derive(Debug)]
+struct WriteEnd(Arc<InterruptTicketMutex<Channel>>);
+
+impl Drop for WriteEnd {
+	fn drop(&mut self) {
+		let mut channel = self.0.lock();
+		channel.writer_closed = true;
+		channel.wake_all();
+	}
+}
+
+/// Creates a pipe and returns its read and write end.
+pub(crate) fn pipe(nonblocking: bool) -> (PipeReader, PipeWriter) {
+	let channel = Arc::new(InterruptTicketMutex::new(Channel::default()));
+
+	let reader = PipeReader {
+		end: Arc::new(ReadEnd(channel.clone())),
+		nonblocking: Arc::new(AtomicBool::new(nonblocking)),
+	};
+	let writer = PipeWriter {
+		end: Arc::new(WriteEnd(channel)),
+		nonblocking: Arc::new(AtomicBool::new(nonblocking)),
+	};
+
+	(reader, writer)
+}
+
+/// Read end of a pipe
+#[derive(Debug, Clone)]
+pub(crate) struct PipeReader {
+	end: Arc<ReadEnd>,
+	nonblocking: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl ObjectInterface for PipeReader {
+	async fn async_read(&self, buf: &mut [u8]) -> Result<usize, IoError> {
+		future::poll_fn(|cx| {
+			let mut channel = self.end.0.lock();
+			match channel.read(buf) {
+				Some(len) => Poll::Ready(Ok(len)),
+				None => {
+					channel.read_queue.push_back(cx.waker().clone());
+					Poll::Pending
+				}
+			}
+		})
+		.await
+	}
+
+	async fn async_write(&self, _buf: &[u8]) -> Result<usize, IoError> {
+		Err(IoError::EBADF)
+	}
+
+	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
+		future::poll_fn(|cx| {
+			let mut channel = self.end.0.lock();
+			let ret = event & channel.rx_events();
+			if ret.is_empty() {
+				channel.read_queue.push_back(cx.waker().clone());
+				Poll::Pending
+			} else {
+				Poll::Ready(Ok(ret))
+			}
+		})
+		.await
+	}
+
+	fn is_nonblocking(&self) -> bool {
+		self.nonblocking.load(Ordering::Relaxed)
+	}
+
+	fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
+		match cmd {
+			IoCtl::NonBlocking => {
+				self.nonblocking.store(value, Ordering::Relaxed);
+				Ok(())
+			}
+		}
+	}
+
+	fn nread(&self) -> Result<usize, IoError> {
+		Ok(self.end.0.lock().len())
+	}
+}
+
+/// Write end of a pipe
+#[derive(Debug, Clone)]
+pub(crate) struct PipeWriter {
+	end: Arc<WriteEnd>,
+	nonblocking: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl ObjectInterface for PipeWriter {
+	async fn async_read(&self, _buf: &mut [u8]) -> Result<usize, IoError> {
+		Err(IoError::EBADF)
+	}
+
+	async fn async_write(&self, buf: &[u8]) -> Result<usize, IoError> {
+		future::poll_fn(|cx| {
+			let mut channel = self.end.0.lock();
+			match channel.write(buf) {
+				Some(result) => Poll::Ready(result),
+				None => {
+					channel.write_queue.push_back(cx.waker().clone());
+					Poll::Pending
+				}
+			}
+		})
+		.await
+	}
+
+	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
+		future::poll_fn(|cx| {
+			let mut channel = self.end.0.lock();
+			let ret = event & channel.tx_events();
+			if ret.is_empty() {
+				channel.write_queue.push_back(cx.waker().clone());
+				Poll::Pending
+			} else {
+				Poll::Ready(Ok(ret))
+			}
+		})
+		.await
+	}
+
+	fn is_nonblocking(&self) -> bool {
+		self.nonblocking.load(Ordering::Relaxed)
+	}
+
+	fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
+		match cmd {
+			IoCtl::NonBlocking => {
+				self.nonblocking.store(value, Ordering::Relaxed);
+				Ok(())
+			}
+		}
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn close_ends() {
+		let (reader, writer) = pipe(false);
+		let channel = reader.end.0.clone();
+		assert_eq!(channel.lock().write(b"hello"), Some(Ok(5)));
+
+		// A duplicated write end keeps the pipe open.
+		let duplicate = writer.clone();
+		drop(writer);
+		assert!(!channel.lock().writer_closed);
+		drop(duplicate);
+		assert!(channel.lock().writer_closed);
+
+		let mut buf = [0; 16];
+		assert_eq!(channel.lock().read(&mut buf), Some(5));
+		assert_eq!(channel.lock().read(&mut buf), Some(0));
+		assert!(channel.lock().rx_events().contains(PollEvent::POLLHUP));
+
+		drop(reader);
+		assert!(channel.lock().reader_closed);
+		assert_eq!(channel.lock().write(b"hello"), Some(Err(IoError::EPIPE)));
+	}
+}
@@ -0,0 +1,560 @@
+//! Submission and completion queues for receiving into pre-registered buffers.
+//!
+//! An application registers a set of buffers once and submits receive requests,
+//! which reference a buffer by its index. Each request is completed by a
+//! [`RingCqe`], which carries the `user_data` of the request and the number of
+//! received bytes or a negative error number. Hence, the buffers don't have to
+//! be passed and validated by each receive call.
+//!
+//! Requests on the same socket complete in the order of their submission.
+//! Requests on different sockets complete in the order, in which the data arrives.
+//!
+//! A queue pair is referenced by an identifier, which is returned by [`setup`].
+//! Destroying a queue pair cancels its requests in flight. The first version
+//! supports only receiving from UDP sockets.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::future::{self, Future};
+use core::mem;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicI32, Ordering};
+use core::task::{Poll, Waker};
+
+use hermit_sync::InterruptTicketMutex;
+
+use crate::executor::spawn;
+use crate::fd::{get_object, FileDescriptor, IoError, ObjectInterface};
+
+/// Receives a datagram into a registered buffer
+pub const RING_OP_RECV_FIXED: u8 = 1;
+
+/// Submission queue entry
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RingSqe {
+	/// Operation of the request
+	pub opcode: u8,
+	/// Reserved, must be zero
+	pub flags: u8,
+	/// Index of the registered buffer
+	pub buf_index: u16,
+	/// File descriptor of the socket
+	pub fd: i32,
+	/// Value, which is passed to the completion
+	pub user_data: u64,
+}
+
+/// Completion queue entry
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RingCqe {
+	/// Value of the completed request
+	pub user_data: u64,
+	/// Number of received bytes or a negative error number
+	pub res: i32,
+	/// Index of the registered buffer, which is released by the completion
+	pub buf_index: u16,
+	/// Reserved
+	pub flags: u16,
+}
+
+/// Registered buffer of the application
+#[derive(Debug, Copy, Clone)]
+struct FixedBuffer {
+	addr: usize,
+	len: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Request {
+	user_data: u64,
+	buf_index: u16,
+}
+
+#[derive(Debug)]
+struct RingState {
+	/// Maximal number of requests, which are in flight or whose completions
+	/// aren't reaped yet
+	entries: usize,
+	buffers: Vec<FixedBuffer>,
+	/// `true`, if the buffer is used by a request in flight
+	busy: Vec<bool>,
+	in_flight: usize,
+	/// Pending requests of each socket in the order of their submission. The
+	/// first request is the one, which receives. A socket has an entry as long
+	/// as a task receives for it.
+	queues: BTreeMap<FileDescriptor, VecDeque<Request>>,
+	completions: VecDeque<RingCqe>,
+	waiters: VecDeque<Waker>,
+	/// `true`, if the queue pair is destroyed
+	cancelled: bool,
+}
+
+impl RingState {
+	fn new(entries: usize) -> Self {
+		Self {
+			entries,
+			buffers: Vec::new(),
+			busy: Vec::new(),
+			in_flight: 0,
+			queues: BTreeMap::new(),
+			completions: VecDeque::new(),
+			waiters: VecDeque::new(),
+			cancelled: false,
+		}
+	}
+
+	fn is_full(&self) -> bool {
+		self.in_flight + self.completions.len() >= self.entries
+	}
+
+	fn register(&mut self, buffers: Vec<FixedBuffer>) -> Result<(), IoError> {
+		if self.in_flight > 0 {
+			return Err(IoError::EBUSY);
+		}
+		if buffers.len() > usize::from(u16::MAX) {
+			return Err(IoError::EINVAL);
+		}
+
+		self.busy = vec![false; buffers.len()];
+		self.buffers = buffers;
+		Ok(())
+	}
+
+	/// Validates `sqe` and reserves its buffer. Returns the error number of
+	/// an invalid request.
+	fn prepare(&mut self, sqe: &RingSqe) -> Result<(), i32> {
+		if sqe.opcode != RING_OP_RECV_FIXED || sqe.flags != 0 {
+			return Err(crate::errno::EINVAL);
+		}
+
+		let index = usize::from(sqe.buf_index);
+		match self.busy.get_mut(index) {
+			None => Err(crate::errno::EINVAL),
+			Some(true) => Err(crate::errno::EBUSY),
+			Some(busy) => {
+				*busy = true;
+				self.in_flight += 1;
+				Ok(())
+			}
+		}
+	}
+
+	/// Queues the completion of a request and releases its buffer.
+	fn complete(&mut self, request: Request, res: i32) {
+		if let Some(busy) = self.busy.get_mut(usize::from(request.buf_index)) {
+			*busy = false;
+		}
+		self.in_flight -= 1;
+		self.push_completion(RingCqe {
+			user_data: request.user_data,
+			res,
+			buf_index: request.buf_index,
+			flags: 0,
+		});
+	}
+
+	/// Completes the requests in flight with `ECANCELED`. Afterwards, the
+	/// receiving tasks don't write into the registered buffers anymore.
+	fn cancel(&mut self) {
+		self.cancelled = true;
+		for request in mem::take(&mut self.queues).into_values().flatten() {
+			self.complete(request, -crate::errno::ECANCELED);
+		}
+	}
+
+	fn push_completion(&mut self, cqe: RingCqe) {
+		self.completions.push_back(cqe);
+		for waker in self.waiters.drain(..) {
+			waker.wake();
+		}
+	}
+}
+
+/// Queue pair for fixed-buffer receives
+#[derive(Debug, Clone)]
+pub(crate) struct Ring {
+	state: Arc<InterruptTicketMutex<RingState>>,
+}
+
+impl Ring {
+	pub fn new(entries: usize) -> Self {
+		Self {
+			state: Arc::new(InterruptTicketMutex::new(RingState::new(entries))),
+		}
+	}
+
+	/// Registers the buffers `(addr, len)`, which replace the previously
+	/// registered buffers. Returns `EBUSY` while requests are in flight.
+	///
+	/// # Safety
+	///
+	/// The buffers have to stay valid until all requests, which use them,
+	/// are completed.
+	pub unsafe fn register_buffers(&self, buffers: &[(*mut u8, usize)]) -> Result<(), IoError> {
+		let buffers = buffers
+			.iter()
+			.map(|(addr, len)| FixedBuffer {
+				addr: addr.expose_provenance(),
+				len: *len,
+			})
+			.collect();
+		self.state.lock().register(buffers)
+	}
+
+	/// Submits the requests `sqes` and returns the number of accepted requests.
+	///
+	/// An invalid request is accepted and is completed immediately with an
+	/// error. Returns `EBUSY`, if the queues are full.
+	pub fn submit(&self, sqes: &[RingSqe]) -> Result<usize, IoError> {
+		self.submit_with(sqes, get_object, spawn)
+	}
+
+	/// Submits the requests `sqes`, whose sockets are looked up by `get_object`.
+	/// The receiving tasks are started by `spawn`.
+	fn submit_with<G, S>(
+		&self,
+		sqes: &[RingSqe],
+		mut get_object: G,
+		mut spawn: S,
+	) -> Result<usize, IoError>
+	where
+		G: FnMut(FileDescriptor) -> Result<Arc<dyn ObjectInterface>, IoError>,
+		S: FnMut(Pin<Box<dyn Future<Output = ()> + Send>>),
+	{
+		if self.state.lock().cancelled {
+			return Err(IoError::EBADF);
+		}
+
+		let mut accepted = 0;
+
+		for sqe in sqes {
+			let mut state = self.state.lock();
+			if state.is_full() {
+				break;
+			}
+			accepted += 1;
+
+			if let Err(errno) = state.prepare(sqe) {
+				state.push_completion(RingCqe {
+					user_data: sqe.user_data,
+					res: -errno,
+					buf_index: sqe.buf_index,
+					flags: 0,
+				});
+				continue;
+			}
+			drop(state);
+
+			let request = Request {
+				user_data: sqe.user_data,
+				buf_index: sqe.buf_index,
+			};
+			let obj = match get_object(sqe.fd) {
+				Ok(obj) => obj,
+				Err(_) => {
+					self.state.lock().complete(request, -crate::errno::EBADF);
+					continue;
+				}
+			};
+
+			let mut state = self.state.lock();
+			if let Some(queue) = state.queues.get_mut(&sqe.fd) {
+				queue.push_back(request);
+			} else {
+				state.queues.insert(sqe.fd, VecDeque::from([request]));
+				drop(state);
+				spawn(Box::pin(receive(self.state.clone(), sqe.fd, obj)));
+			}
+		}
+
+		if accepted == 0 && !sqes.is_empty() {
+			Err(IoError::EBUSY)
+		} else {
+			Ok(accepted)
+		}
+	}
+
+	/// Waits for at least `min_complete` completions and stores up to `cqes.len()`
+	/// completions in `cqes`. Returns the number of stored completions.
+	pub async fn reap(&self, cqes: &mut [RingCqe], min_complete: usize) -> usize {
+		let min_complete = min_complete.min(cqes.len());
+
+		future::poll_fn(|cx| {
+			let mut state = self.state.lock();
+			if state.completions.len() < min_complete {
+				state.waiters.push_back(cx.waker().clone());
+				return Poll::Pending;
+			}
+
+			let len = state.completions.len().min(cqes.len());
+			for (cqe, completion) in cqes.iter_mut().zip(state.completions.drain(..len)) {
+				*cqe = completion;
+			}
+			Poll::Ready(len)
+		})
+		.await
+	}
+}
+
+/// Receives for the requests of the socket `fd` in the order of their submission.
+async fn receive(
+	state: Arc<InterruptTicketMutex<RingState>>,
+	fd: FileDescriptor,
+	obj: Arc<dyn ObjectInterface>,
+) {
+	loop {
+		let (request, buffer) = {
+			let mut guard = state.lock();
+			let Some(request) = guard
+				.queues
+				.get(&fd)
+				.and_then(|queue| queue.front().copied())
+			else {
+				guard.queues.remove(&fd);
+				return;
+			};
+			(request, guard.buffers[usize::from(request.buf_index)])
+		};
+
+		// SAFETY: The buffer is reserved for this request and the application
+		// guarantees that it stays valid.
+		let buf = unsafe {
+			core::slice::from_raw_parts_mut(
+				core::ptr::with_exposed_provenance_mut::<u8>(buffer.addr),
+				buffer.len,
+			)
+		};
+		let mut read = obj.async_read(buf);
+		let completed = future::poll_fn(|cx| {
+			// The read is polled with the lock held. Hence, it doesn't write
+			// into the buffer after the request is cancelled.
+			let mut guard = state.lock();
+			if guard.cancelled {
+				return Poll::Ready(false);
+			}
+
+			let res = match read.as_mut().poll(cx) {
+				Poll::Ready(Ok(len)) => i32::try_from(len).unwrap_or(i32::MAX),
+				Poll::Ready(Err(err)) => -num::ToPrimitive::to_i32(&err).unwrap(),
+				Poll::Pending => return Poll::Pending,
+			};
+			guard.queues.get_mut(&fd).unwrap().pop_front();
+			guard.complete(request, res);
+			Poll::Ready(true)
+		})
+		.await;
+
+		if !completed {
+			return;
+		}
+	}
+}
+
+/// Identifier of the next queue pair
+static RING_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Queue pairs of the application
+static RINGS: InterruptTicketMutex<BTreeMap<i32, Ring>> =
+	InterruptTicketMutex::new(BTreeMap::new());
+
+/// Creates a queue pair for `entries` requests and returns its identifier.
+pub(crate) fn setup(entries: usize) -> Result<i32, IoError> {
+	if entries == 0 {
+		return Err(IoError::EINVAL);
+	}
+
+	// Identifiers aren't reused. Hence, a stale identifier doesn't refer to
+	// another queue pair.
+	let id = RING_ID.fetch_add(1, Ordering::Relaxed);
+	RINGS.lock().insert(id, Ring::new(entries));
+	Ok(id)
+}
+
+/// Returns the queue pair `id`.
+pub(crate) fn get(id: i32) -> Result<Ring, IoError> {
+	RINGS.lock().get(&id).cloned().ok_or(IoError::EBADF)
+}
+
+/// Destroys the queue pair `id` and cancels its requests in flight. Afterwards,
+/// the registered buffers aren't used anymore.
+pub(crate) fn destroy(id: i32) -> Result<(), IoError> {
+	let ring = RINGS.lock().remove(&id).ok_or(IoError::EBADF)?;
+	ring.state.lock().cancel();
+	Ok(())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use core::task::Context;
+
+	use super::*;
+	use crate::fd::eventfd::EventFd;
+	use crate::fd::EventFlags;
+
+	type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+	fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+		let mut cx = Context::from_waker(Waker::noop());
+		core::pin::pin!(future).poll(&mut cx)
+	}
+
+	/// Submits `sqes`, whose socket 0 is `obj`, and returns the receiving tasks.
+	fn submit(
+		ring: &Ring,
+		sqes: &[RingSqe],
+		obj: &Arc<dyn ObjectInterface>,
+	) -> Result<Vec<Task>, IoError> {
+		let mut tasks = Vec::new();
+		ring.submit_with(
+			sqes,
+			|fd| match fd {
+				0 => Ok(obj.clone()),
+				_ => Err(IoError::EBADF),
+			},
+			|task| tasks.push(task),
+		)?;
+		Ok(tasks)
+	}
+
+	fn write(obj: &Arc<dyn ObjectInterface>, value: u64) {
+		assert!(poll_once(obj.async_write(&value.to_ne_bytes())).is_ready());
+	}
+
+	fn sqe(user_data: u64, buf_index: u16) -> RingSqe {
+		RingSqe {
+			opcode: RING_OP_RECV_FIXED,
+			buf_index,
+			user_data,
+			..Default::default()
+		}
+	}
+
+	fn buffers(count: usize) -> Vec<FixedBuffer> {
+		vec![FixedBuffer { addr: 0, len: 64 }; count]
+	}
+
+	#[test]
+	fn validate_buffer_index() {
+		let mut state = RingState::new(4);
+		state.register(buffers(2)).unwrap();
+
+		assert_eq!(state.prepare(&sqe(1, 2)), Err(crate::errno::EINVAL));
+		assert_eq!(state.prepare(&sqe(2, 1)), Ok(()));
+		assert_eq!(state.prepare(&sqe(3, 1)), Err(crate::errno::EBUSY));
+		assert_eq!(state.register(buffers(2)), Err(IoError::EBUSY));
+
+		let mut invalid = sqe(4, 0);
+		invalid.opcode = 0;
+		assert_eq!(state.prepare(&invalid), Err(crate::errno::EINVAL));
+	}
+
+	#[test]
+	fn complete_and_release_buffers() {
+		let mut state = RingState::new(2);
+		state.register(buffers(2)).unwrap();
+
+		state.prepare(&sqe(1, 0)).unwrap();
+		state.prepare(&sqe(2, 1)).unwrap();
+		assert!(state.is_full());
+
+		let first = Request {
+			user_data: 1,
+			buf_index: 0,
+		};
+		let second = Request {
+			user_data: 2,
+			buf_index: 1,
+		};
+		state.complete(second, 16);
+		state.complete(first, 8);
+		assert_eq!(state.prepare(&sqe(3, 0)), Ok(()));
+		assert!(state.is_full());
+
+		let completions: Vec<_> = state.completions.drain(..).collect();
+		assert_eq!(completions[0].user_data, 2);
+		assert_eq!(completions[0].res, 16);
+		assert_eq!(completions[1].user_data, 1);
+		assert_eq!(completions[1].buf_index, 0);
+		assert!(!state.is_full());
+	}
+
+	#[test]
+	fn receive_into_registered_buffer() {
+		let id = setup(4).unwrap();
+		let ring = get(id).unwrap();
+		let mut buffers = [[0u8; 8]; 2];
+		let registered = buffers.each_mut().map(|buf| (buf.as_mut_ptr(), buf.len()));
+		unsafe { ring.register_buffers(&registered) }.unwrap();
+
+		let eventfd: Arc<dyn ObjectInterface> = Arc::new(EventFd::new(0, EventFlags::empty()));
+		let sqes = [sqe(1, 0), RingSqe { fd: 1, ..sqe(2, 1) }];
+		let mut tasks = submit(&ring, &sqes, &eventfd).unwrap();
+		assert_eq!(tasks.len(), 1);
+		assert!(poll_once(tasks[0].as_mut()).is_pending());
+
+		// The request on the closed socket completes immediately.
+		let mut cqes = [RingCqe::default(); 4];
+		assert_eq!(poll_once(ring.reap(&mut cqes, 1)), Poll::Ready(1));
+		assert_eq!(cqes[0].user_data, 2);
+		assert_eq!(cqes[0].res, -crate::errno::EBADF);
+
+		write(&eventfd, 5);
+		assert!(poll_once(tasks[0].as_mut()).is_ready());
+		assert_eq!(poll_once(ring.reap(&mut cqes, 1)), Poll::Ready(1));
+		assert_eq!(
+			cqes[0],
+			RingCqe {
+				user_data: 1,
+				res: 8,
+				buf_index: 0,
+				flags: 0,
+			}
+		);
+		assert_eq!(buffers[0], 5u64.to_ne_bytes());
+
+		destroy(id).unwrap();
+		assert!(get(id).is_err());
+	}
+
+	#[test]
+	fn cancel_on_destroy() {
+		let id = setup(4).unwrap();
+		let ring = get(id).unwrap();
+		let mut buffer = [0u8; 8];
+		unsafe { ring.register_buffers(&[(buffer.as_mut_ptr(), buffer.len())]) }.unwrap();
+
+		let eventfd: Arc<dyn ObjectInterface> = Arc::new(EventFd::new(0, EventFlags::empty()));
+		let mut tasks = submit(&ring, &[sqe(1, 0)], &eventfd).unwrap();
+		assert!(poll_once(tasks[0].as_mut()).is_pending());
+
+		// The request in flight doesn't prevent destroying the queue pair.
+		destroy(id).unwrap();
+		let mut cqes = [RingCqe::default(); 4];
+		assert_eq!(poll_once(ring.reap(&mut cqes, 1)), Poll::Ready(1));
+		assert_eq!(cqes[0].user_data, 1);
+		assert_eq!(cqes[0].res, -crate::errno::ECANCELED);
+
+		// The cancelled request doesn't receive anymore.
+		write(&eventfd, 5);
+		assert!(poll_once(tasks[0].as_mut()).is_ready());
+		assert_eq!(buffer, [0; 8]);
+		assert_eq!(
+			submit(&ring, &[sqe(2, 0)], &eventfd).err(),
+			Some(IoError::EBADF)
+		);
+	}
+
+	#[test]
+	fn unique_identifiers() {
+		let first = setup(1).unwrap();
+		destroy(first).unwrap();
+		let second = setup(1).unwrap();
+		assert_ne!(first, second);
+		assert_eq!(destroy(first), Err(IoError::EBADF));
+		destroy(second).unwrap();
+	}
+}
@@ -2,3 +2,87 @@
 pub(crate) mod tcp;
 #[cfg(feature = "udp")]
 pub(crate) mod udp;
+
+#[cfg(feature = "udp")]
+use alloc::vec::Vec;
+
+use crate::fd::IoError;
+
+/// further receives will be disallowed
+pub const SHUT_RD: i32 = 0;
+/// further sends will be disallowed
+pub const SHUT_WR: i32 = 1;
+/// further sends and receives will be disallowed
+pub const SHUT_RDWR: i32 = 2;
+
+/// Returns, whether `how` shuts down the receiving and the sending direction.
+pub(crate) fn shutdown_directions(how: i32) -> Result<(bool, bool), IoError> {
+	match how {
+		SHUT_RD => Ok((true, false)),
+		SHUT_WR => Ok((false, true)),
+		SHUT_RDWR => Ok((true, true)),
+		_ => Err(IoError::EINVAL),
+	}
+}
+
+/// Concatenates the buffers of a gathering write, such that a datagram
+/// isn't split at the buffer boundaries.
+#[cfg(feature = "udp")]
+pub(crate) fn gather(bufs: &[&[u8]]) -> Vec<u8> {
+	bufs.concat()
+}
+
+/// Scatters `data` to `bufs` and returns the number of copied bytes.
+pub(crate) fn scatter(mut data: &[u8], bufs: &mut [&mut [u8]]) -> usize {
+	let len = data.len();
+	for buf in bufs.iter_mut() {
+		if data.is_empty() {
+			break;
+		}
+
+		let n = buf.len().min(data.len());
+		buf[..n].copy_from_slice(&data[..n]);
+		data = &data[n..];
+	}
+	len - data.len()
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shutdown_how() {
+		assert_eq!(shutdown_directions(SHUT_RD), Ok((true, false)));
+		assert_eq!(shutdown_directions(SHUT_WR), Ok((false, true)));
+		assert_eq!(shutdown_directions(SHUT_RDWR), Ok((true, true)));
+		assert_eq!(shutdown_directions(3), Err(IoError::EINVAL));
+	}
+
+	#[test]
+	#[cfg(feature = "udp")]
+	fn gather_buffers() {
+		let data = b"hello, world!";
+		assert_eq!(gather(&[&data[..3], &[], &data[3..5], &data[5..]]), data);
+	}
+
+	#[test]
+	fn scatter_buffers() {
+		let data = b"hello, world!";
+
+		let mut a = [0u8; 2];
+		let mut b = [0u8; 0];
+		let mut c = [0u8; 4];
+		let mut d = [0u8; 16];
+		let mut bufs: [&mut [u8]; 4] = [&mut a, &mut b, &mut c, &mut d];
+		assert_eq!(scatter(data, &mut bufs), data.len());
+		assert_eq!(&a, b"he");
+		assert_eq!(&c, b"llo,");
+		assert_eq!(&d[..7], b" world!");
+
+		// the data is truncated, if the buffers are too small
+		let mut e = [0u8; 5];
+		assert_eq!(scatter(data, &mut [&mut e]), 5);
+		assert_eq!(&e, b"hello");
+	}
+}
@@ -1,26 +1,90 @@
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use core::future;
 use core::ops::DerefMut;
 use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use core::task::Poll;
 
 use async_trait::async_trait;
-use smoltcp::iface;
+use crossbeam_utils::atomic::AtomicCell;
 use smoltcp::socket::tcp;
 use smoltcp::time::Duration;
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
+use super::shutdown_directions;
 use crate::executor::block_on;
-use crate::executor::network::{now, Handle, NetworkState, NIC};
+use crate::executor::network::{
+	now, tcp_send_ready, Handle, LowWater, NetworkInterface, NetworkState, NIC,
+};
 use crate::fd::{IoCtl, IoError, ObjectInterface, PollEvent, SocketOption};
 use crate::DEFAULT_KEEP_ALIVE_INTERVAL;
 
-/// further receives will be disallowed
-pub const SHUT_RD: i32 = 0;
-/// further sends will be disallowed
-pub const SHUT_WR: i32 = 1;
-/// further sends and receives will be disallowed
-pub const SHUT_RDWR: i32 = 2;
+/// Discards the data in the receive buffer of `socket`.
+fn discard_received(socket: &mut tcp::Socket<'_>) {
+	while socket.can_recv() {
+		if socket.recv(|data| (data.len(), ())).is_err() {
+			break;
+		}
+	}
+}
+
+/// Directions of a connection, which are shut down
+#[derive(Debug, Default)]
+struct Shutdown {
+	/// Received data is discarded (`SHUT_RD`)
+	read: AtomicBool,
+	/// The sending direction is closed (`SHUT_WR`)
+	write: AtomicBool,
+}
+
+impl Shutdown {
+	/// Shuts down the directions `how` of `socket`. Shutting down a direction
+	/// again has no effect.
+	fn shutdown(&self, socket: &mut tcp::Socket<'_>, how: i32) -> Result<(), IoError> {
+		let (read, write) = shutdown_directions(how)?;
+		let read = read && !self.read.load(Ordering::Acquire);
+		let write = write && !self.write.load(Ordering::Acquire);
+		if !read && !write {
+			return Ok(());
+		}
+
+		if matches!(socket.state(), tcp::State::Closed | tcp::State::Listen) {
+			return Err(IoError::ENOTCONN);
+		}
+
+		if read {
+			self.read.store(true, Ordering::Release);
+			discard_received(socket);
+		}
+
+		if write {
+			// sends a FIN, but still receives data of the peer
+			self.write.store(true, Ordering::Release);
+			socket.close();
+		}
+
+		Ok(())
+	}
+
+	/// Returns `true` and discards the received data of `socket`, if the
+	/// receiving direction is shut down.
+	fn discard_received(&self, socket: &mut tcp::Socket<'_>) -> bool {
+		let read = self.read.load(Ordering::Acquire);
+		if read {
+			discard_received(socket);
+		}
+		read
+	}
+
+	/// Returns `EPIPE`, if the sending direction is shut down.
+	fn check_send(&self) -> Result<(), IoError> {
+		if self.write.load(Ordering::Acquire) {
+			Err(IoError::EPIPE)
+		} else {
+			Ok(())
+		}
+	}
+}
 
 fn get_ephemeral_port() -> u16 {
 	static LOCAL_ENDPOINT: AtomicU16 = AtomicU16::new(49152);
@@ -28,24 +92,38 @@ fn get_ephemeral_port() -> u16 {
 	LOCAL_ENDPOINT.fetch_add(1, Ordering::SeqCst)
 }
 
+/// TCP socket on top of smoltcp
+///
+/// smoltcp ignores the urgent pointer of received segments. Hence, urgent data
+/// is not signaled separately and is received inline as part of the normal
+/// stream, similar to `SO_OOBINLINE`. Sending urgent data is not supported.
 #[derive(Debug)]
 pub struct Socket {
 	handle: Handle,
-	port: AtomicU16,
+	/// Local endpoint, to which the socket is bound
+	endpoint: AtomicCell<IpListenEndpoint>,
 	nonblocking: AtomicBool,
 	listen: AtomicBool,
+	reuse_addr: AtomicBool,
+	shutdown: Shutdown,
 }
 
 impl Socket {
 	pub fn new(handle: Handle) -> Self {
 		Self {
 			handle,
-			port: AtomicU16::new(0),
+			endpoint: AtomicCell::new(IpListenEndpoint::from(0)),
 			nonblocking: AtomicBool::new(false),
 			listen: AtomicBool::new(false),
+			reuse_addr: AtomicBool::new(false),
+			shutdown: Shutdown::default(),
 		}
 	}
 
+	/// Calls `f` with the smoltcp socket and polls the interface afterwards.
+	///
+	/// A socket is only created on an initialized interface, which is never
+	/// torn down. Hence, the interface is always available here.
 	fn with<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>) -> R) -> R {
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().unwrap();
@@ -55,34 +133,66 @@ impl Socket {
 		result
 	}
 
-	fn with_context<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>, &mut iface::Context) -> R) -> R {
+	/// Like [`Self::with`], but calls `f` with the interface.
+	fn with_nic<R>(&self, f: impl FnOnce(&mut NetworkInterface<'_>) -> R) -> R {
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().unwrap();
-		let (s, cx) = nic.get_socket_and_context::<tcp::Socket<'_>>(self.handle);
-		let result = f(s, cx);
+		let result = f(nic);
 		nic.poll_common(now());
 
 		result
 	}
 
-	async fn async_connect(&self, endpoint: IpEndpoint) -> Result<(), IoError> {
-		self.with_context(|socket, cx| socket.connect(cx, endpoint, get_ephemeral_port()))
-			.map_err(|_| IoError::EIO)?;
+	/// Connects to `endpoint` and sends `initial_data`, as soon as the connection
+	/// is established.
+	async fn async_connect(
+		&self,
+		endpoint: IpEndpoint,
+		initial_data: &[u8],
+	) -> Result<(), IoError> {
+		self.with_nic(|nic| {
+			nic.tcp_connect_with_data(self.handle, endpoint, get_ephemeral_port(), initial_data)
+		})?;
 
 		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed | tcp::State::TimeWait => Poll::Ready(Err(IoError::EFAULT)),
-				tcp::State::Listen => Poll::Ready(Err(IoError::EIO)),
-				tcp::State::SynSent | tcp::State::SynReceived => {
-					socket.register_send_waker(cx.waker());
-					Poll::Pending
+			self.with_nic(|nic| {
+				if let Some(err) = nic.take_socket_error(self.handle) {
+					return Poll::Ready(Err(err));
+				}
+
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
+				match socket.state() {
+					tcp::State::Closed | tcp::State::TimeWait => Poll::Ready(Err(IoError::EFAULT)),
+					tcp::State::Listen => Poll::Ready(Err(IoError::EIO)),
+					tcp::State::SynSent | tcp::State::SynReceived => {
+						socket.register_send_waker(cx.waker());
+						Poll::Pending
+					}
+					_ => Poll::Ready(Ok(())),
 				}
-				_ => Poll::Ready(Ok(())),
 			})
 		})
 		.await
 	}
 
+	fn connect_with_data(&self, endpoint: IpEndpoint, initial_data: &[u8]) -> Result<(), IoError> {
+		if self.nonblocking.load(Ordering::Acquire) {
+			block_on(
+				self.async_connect(endpoint, initial_data),
+				Some(Duration::ZERO.into()),
+			)
+			.map_err(|x| {
+				if x == IoError::ETIME {
+					IoError::EAGAIN
+				} else {
+					x
+				}
+			})
+		} else {
+			block_on(self.async_connect(endpoint, initial_data), None)
+		}
+	}
+
 	async fn async_close(&self) -> Result<(), IoError> {
 		future::poll_fn(|cx| {
 			self.with(|socket| match socket.state() {
@@ -124,7 +234,7 @@ impl Socket {
 		future::poll_fn(|cx| {
 			self.with(|socket| match socket.state() {
 				tcp::State::Closed => {
-					let _ = socket.listen(self.port.load(Ordering::Acquire));
+					let _ = socket.listen(self.endpoint.load());
 					Poll::Ready(())
 				}
 				tcp::State::Listen | tcp::State::Established => Poll::Ready(()),
@@ -158,76 +268,136 @@ impl Socket {
 
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+		let interval = Duration::from_millis(DEFAULT_KEEP_ALIVE_INTERVAL);
+		nic.set_tcp_keep_alive(self.handle, Some(interval));
 		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
-		socket.set_keep_alive(Some(Duration::from_millis(DEFAULT_KEEP_ALIVE_INTERVAL)));
 
 		Ok(socket.remote_endpoint().unwrap())
 	}
+
+	/// Receives data by passing the received bytes to `copy`, which returns
+	/// the number of consumed bytes. Waits for the receive low-water mark, but
+	/// at most for `len` bytes, which `copy` is able to consume.
+	async fn async_recv_with<F>(&self, len: usize, mut copy: F) -> Result<usize, IoError>
+	where
+		F: FnMut(&[u8]) -> usize + Send,
+	{
+		future::poll_fn(|cx| {
+			self.with_nic(|nic| {
+				if let Some(err) = nic.take_socket_error(self.handle) {
+					return Poll::Ready(Err(err));
+				}
+
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
+				if self.shutdown.discard_received(socket) {
+					return Poll::Ready(Ok(0));
+				}
+
+				let can_recv = nic.tcp_can_read(self.handle, len);
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
+				match socket.state() {
+					tcp::State::Closed | tcp::State::Closing | tcp::State::CloseWait => {
+						Poll::Ready(Ok(0))
+					}
+					tcp::State::FinWait1
+					| tcp::State::FinWait2
+					| tcp::State::Listen
+					| tcp::State::TimeWait => Poll::Ready(Err(IoError::EIO)),
+					_ => {
+						if can_recv {
+							Poll::Ready(
+								socket
+									.recv(|data| {
+										let len = copy(data);
+										(len, len)
+									})
+									.map_err(|_| IoError::EIO),
+							)
+						} else {
+							socket.register_recv_waker(cx.waker());
+							Poll::Pending
+						}
+					}
+				}
+			})
+		})
+		.await
+	}
 }
 
 #[async_trait]
 impl ObjectInterface for Socket {
 	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
 		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed | tcp::State::Closing | tcp::State::CloseWait => {
-					let available = PollEvent::POLLOUT
-						| PollEvent::POLLWRNORM | PollEvent::POLLWRBAND
-						| PollEvent::POLLIN | PollEvent::POLLRDNORM
-						| PollEvent::POLLRDBAND;
-
-					let ret = event & available;
+			self.with_nic(|nic| {
+				if let Some(err) = nic.take_socket_error(self.handle) {
+					return Poll::Ready(Err(err));
+				}
 
-					if ret.is_empty() {
+				let can_recv = nic.tcp_can_recv(self.handle);
+				let can_send = nic.tcp_can_send(self.handle);
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
+				match socket.state() {
+					tcp::State::Closed | tcp::State::Closing | tcp::State::CloseWait => {
+						let available = PollEvent::POLLOUT
+							| PollEvent::POLLWRNORM
+							| PollEvent::POLLWRBAND
+							| PollEvent::POLLIN | PollEvent::POLLRDNORM
+							| PollEvent::POLLRDBAND;
+
+						let ret = event & available;
+
+						if ret.is_empty() {
+							Poll::Ready(Ok(PollEvent::POLLHUP))
+						} else {
+							Poll::Ready(Ok(ret))
+						}
+					}
+					tcp::State::FinWait1 | tcp::State::FinWait2 | tcp::State::TimeWait => {
 						Poll::Ready(Ok(PollEvent::POLLHUP))
-					} else {
-						Poll::Ready(Ok(ret))
 					}
-				}
-				tcp::State::FinWait1 | tcp::State::FinWait2 | tcp::State::TimeWait => {
-					Poll::Ready(Ok(PollEvent::POLLHUP))
-				}
-				tcp::State::Listen => {
-					socket.register_recv_waker(cx.waker());
-					socket.register_send_waker(cx.waker());
-					Poll::Pending
-				}
-				_ => {
-					let mut available = PollEvent::empty();
-
-					if socket.can_recv()
-						|| socket.may_recv() && self.listen.swap(false, Ordering::Relaxed)
-					{
-						// In case, we just establish a fresh connection in non-blocking mode, we try to read data.
-						available.insert(
-							PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND,
-						);
+					tcp::State::Listen => {
+						socket.register_recv_waker(cx.waker());
+						socket.register_send_waker(cx.waker());
+						Poll::Pending
 					}
+					_ => {
+						let mut available = PollEvent::empty();
+
+						if can_recv
+							|| socket.may_recv() && self.listen.swap(false, Ordering::Relaxed)
+						{
+							// In case, we just establish a fresh connection in non-blocking mode, we try to read data.
+							available.insert(
+								PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND,
+							);
+						}
 
-					if socket.can_send() {
-						available.insert(
-							PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND,
-						);
-					}
+						if can_send {
+							available.insert(
+								PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND,
+							);
+						}
 
-					let ret = event & available;
+						let ret = event & available;
 
-					if ret.is_empty() {
-						if event.intersects(
-							PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND,
-						) {
-							socket.register_recv_waker(cx.waker());
-						}
+						if ret.is_empty() {
+							if event.intersects(
+								PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND,
+							) {
+								socket.register_recv_waker(cx.waker());
+							}
 
-						if event.intersects(
-							PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND,
-						) {
-							socket.register_send_waker(cx.waker());
-						}
+							if event.intersects(
+								PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND,
+							) {
+								socket.register_send_waker(cx.waker());
+							}
 
-						Poll::Pending
-					} else {
-						Poll::Ready(Ok(ret))
+							Poll::Pending
+						} else {
+							Poll::Ready(Ok(ret))
+						}
 					}
 				}
 			})
@@ -239,96 +409,105 @@ impl ObjectInterface for Socket {
 	// https://github.com/rust-lang/rust-clippy/issues/11380
 	#[allow(clippy::needless_pass_by_ref_mut)]
 	async fn async_read(&self, buffer: &mut [u8]) -> Result<usize, IoError> {
-		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed | tcp::State::Closing | tcp::State::CloseWait => {
-					Poll::Ready(Ok(0))
-				}
-				tcp::State::FinWait1
-				| tcp::State::FinWait2
-				| tcp::State::Listen
-				| tcp::State::TimeWait => Poll::Ready(Err(IoError::EIO)),
-				_ => {
-					if socket.can_recv() {
-						Poll::Ready(
-							socket
-								.recv(|data| {
-									let len = core::cmp::min(buffer.len(), data.len());
-									buffer[..len].copy_from_slice(&data[..len]);
-									(len, len)
-								})
-								.map_err(|_| IoError::EIO),
-						)
-					} else {
-						socket.register_recv_waker(cx.waker());
-						Poll::Pending
-					}
-				}
-			})
+		self.async_recv_with(buffer.len(), |data| {
+			let len = core::cmp::min(buffer.len(), data.len());
+			buffer[..len].copy_from_slice(&data[..len]);
+			len
 		})
 		.await
 	}
 
 	async fn async_write(&self, buffer: &[u8]) -> Result<usize, IoError> {
+		if let Some(err) = self.with_nic(|nic| nic.take_socket_error(self.handle)) {
+			return Err(err);
+		}
+
+		self.shutdown.check_send()?;
+
 		let mut pos: usize = 0;
 
 		while pos < buffer.len() {
-			let n = future::poll_fn(|cx| {
-				self.with(|socket| {
-					match socket.state() {
-						tcp::State::Closed | tcp::State::Closing | tcp::State::CloseWait => {
-							Poll::Ready(Ok(0))
-						}
-						tcp::State::FinWait1
-						| tcp::State::FinWait2
-						| tcp::State::Listen
-						| tcp::State::TimeWait => Poll::Ready(Err(IoError::EIO)),
-						_ => {
-							if socket.can_send() {
-								Poll::Ready(
-									socket.send_slice(&buffer[pos..]).map_err(|_| IoError::EIO),
-								)
-							} else if pos > 0 {
-								// we already send some data => return 0 as signal to stop the
-								// async write
-								Poll::Ready(Ok(0))
-							} else {
-								socket.register_send_waker(cx.waker());
-								Poll::Pending
-							}
-						}
-					}
-				})
-			})
-			.await?;
+			// we already sent some data => don't block and return the number of sent bytes
+			if pos > 0
+				&& !NIC
+					.lock()
+					.as_nic_mut()
+					.map_err(|_| IoError::EIO)?
+					.tcp_can_send(self.handle)
+			{
+				break;
+			}
+
+			tcp_send_ready(self.handle).await?;
+
+			pos += {
+				let mut guard = NIC.lock();
+				let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+				let len = nic.tcp_send_slice(self.handle, &buffer[pos..])?;
+				nic.poll_common(now());
+				len
+			};
+		}
+
+		Ok(pos)
+	}
+
+	async fn async_readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize, IoError> {
+		let len = bufs.iter().map(|buf| buf.len()).sum();
+		self.async_recv_with(len, |data| super::scatter(data, bufs))
+			.await
+	}
 
-			if n == 0 {
+	async fn async_writev(&self, bufs: &[&[u8]]) -> Result<usize, IoError> {
+		let mut pos: usize = 0;
+
+		for buf in bufs.iter().filter(|buf| !buf.is_empty()) {
+			// we already sent some data => don't block and return the number of sent bytes
+			if pos > 0
+				&& !NIC
+					.lock()
+					.as_nic_mut()
+					.map_err(|_| IoError::EIO)?
+					.tcp_can_send(self.handle)
+			{
 				break;
 			}
 
-			pos += n;
+			let len = self.async_write(buf).await?;
+			pos += len;
+			if len < buf.len() {
+				break;
+			}
 		}
 
 		Ok(pos)
 	}
 
 	fn bind(&self, endpoint: IpListenEndpoint) -> Result<(), IoError> {
-		self.port.store(endpoint.port, Ordering::Release);
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+		nic.tcp_bind(
+			self.handle,
+			endpoint,
+			self.reuse_addr.load(Ordering::Acquire),
+		)?;
+		self.endpoint.store(endpoint);
 		Ok(())
 	}
 
 	fn connect(&self, endpoint: IpEndpoint) -> Result<(), IoError> {
-		if self.nonblocking.load(Ordering::Acquire) {
-			block_on(self.async_connect(endpoint), Some(Duration::ZERO.into())).map_err(|x| {
-				if x == IoError::ETIME {
-					IoError::EAGAIN
-				} else {
-					x
-				}
-			})
-		} else {
-			block_on(self.async_connect(endpoint), None)
+		self.connect_with_data(endpoint, &[])
+	}
+
+	/// Like with TCP fast open, `sendto` connects an unconnected socket and
+	/// sends `buffer`, as soon as the connection is established.
+	fn sendto(&self, buffer: &[u8], endpoint: IpEndpoint) -> Result<usize, IoError> {
+		if self.with(|socket| socket.state()) != tcp::State::Closed {
+			return Err(IoError::EISCONN);
 		}
+
+		self.connect_with_data(endpoint, buffer)?;
+		Ok(buffer.len())
 	}
 
 	fn accept(&self) -> Result<IpEndpoint, IoError> {
@@ -345,6 +524,22 @@ impl ObjectInterface for Socket {
 		}
 	}
 
+	fn try_clone(&self) -> Result<Arc<dyn ObjectInterface>, IoError> {
+		let handle = match NIC.lock().deref_mut() {
+			NetworkState::Initialized(nic) => nic.create_tcp_handle()?,
+			_ => return Err(IoError::EIO),
+		};
+
+		Ok(Arc::new(Self {
+			handle,
+			endpoint: AtomicCell::new(self.endpoint.load()),
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+			listen: AtomicBool::new(false),
+			reuse_addr: AtomicBool::new(self.reuse_addr.load(Ordering::Acquire)),
+			shutdown: Shutdown::default(),
+		}))
+	}
+
 	fn getpeername(&self) -> Option<IpEndpoint> {
 		self.with(|socket| socket.remote_endpoint())
 	}
@@ -362,7 +557,7 @@ impl ObjectInterface for Socket {
 			if !socket.is_open() {
 				self.listen.store(true, Ordering::Relaxed);
 				socket
-					.listen(self.port.load(Ordering::Acquire))
+					.listen(self.endpoint.load())
 					.map(|_| ())
 					.map_err(|_| IoError::EIO)
 			} else {
@@ -382,6 +577,13 @@ impl ObjectInterface for Socket {
 				}
 			});
 			Ok(())
+		} else if opt == SocketOption::ReuseAddr {
+			self.reuse_addr.store(optval, Ordering::Release);
+			Ok(())
+		} else if opt == SocketOption::KeepAlive {
+			let interval = optval.then(|| Duration::from_millis(DEFAULT_KEEP_ALIVE_INTERVAL));
+			self.with_nic(|nic| nic.set_tcp_keep_alive(self.handle, interval));
+			Ok(())
 		} else {
 			Err(IoError::EINVAL)
 		}
@@ -390,18 +592,58 @@ impl ObjectInterface for Socket {
 	fn getsockopt(&self, opt: SocketOption) -> Result<bool, IoError> {
 		if opt == SocketOption::TcpNoDelay {
 			self.with(|socket| Ok(socket.nagle_enabled()))
+		} else if opt == SocketOption::ReuseAddr {
+			Ok(self.reuse_addr.load(Ordering::Acquire))
+		} else if opt == SocketOption::KeepAlive {
+			self.with_nic(|nic| Ok(nic.tcp_keep_alive(self.handle).is_some()))
 		} else {
 			Err(IoError::EINVAL)
 		}
 	}
 
+	fn setsockopt_int(&self, opt: SocketOption, optval: i32) -> Result<(), IoError> {
+		let optval = usize::try_from(optval).map_err(|_| IoError::EINVAL)?;
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+		let low_water = nic.tcp_low_water(self.handle);
+		let low_water = match opt {
+			SocketOption::RcvLowat => LowWater {
+				recv: optval,
+				..low_water
+			},
+			SocketOption::SndLowat => LowWater {
+				send: optval,
+				..low_water
+			},
+			_ => return Err(IoError::EINVAL),
+		};
+		nic.set_tcp_low_water(self.handle, low_water)
+	}
+
+	fn getsockopt_int(&self, opt: SocketOption) -> Result<i32, IoError> {
+		let mut guard = NIC.lock();
+		let low_water = guard
+			.as_nic_mut()
+			.map_err(|_| IoError::EIO)?
+			.tcp_low_water(self.handle);
+		let value = match opt {
+			SocketOption::RcvLowat => low_water.recv,
+			SocketOption::SndLowat => low_water.send,
+			_ => return Err(IoError::EINVAL),
+		};
+		Ok(i32::try_from(value).unwrap_or(i32::MAX))
+	}
+
 	fn shutdown(&self, how: i32) -> Result<(), IoError> {
-		match how {
-			SHUT_RD /* Read  */ |
-			SHUT_WR /* Write */ |
-			SHUT_RDWR /* Both */ => Ok(()),
-			_ => Err(IoError::EINVAL),
-		}
+		self.with(|socket| self.shutdown.shutdown(socket, how))
+	}
+
+	fn is_socket(&self) -> bool {
+		true
+	}
+
+	fn nread(&self) -> Result<usize, IoError> {
+		Ok(self.with(|socket| socket.recv_queue()))
 	}
 
 	fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
@@ -421,36 +663,174 @@ impl ObjectInterface for Socket {
 	}
 }
 
-impl Clone for Socket {
-	fn clone(&self) -> Self {
+impl Drop for Socket {
+	fn drop(&mut self) {
+		// `async_close` waits until the transmit buffer is drained. Hence, the
+		// unsent bytes have to be determined before.
+		let unsent = self.with(|socket| socket.send_queue());
+		if unsent > 0 {
+			warn!("TCP socket is closed with {unsent} unsent bytes");
+		}
+		let _ = block_on(self.async_close(), None);
+
 		let mut guard = NIC.lock();
+		if let Ok(nic) = guard.as_nic_mut() {
+			nic.close(self.handle);
+			nic.destroy_socket(self.handle);
+		}
+	}
+}
 
-		let handle = if let NetworkState::Initialized(nic) = guard.deref_mut() {
-			nic.create_tcp_handle().unwrap()
-		} else {
-			panic!("Unable to create handle");
-		};
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec;
+
+	use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+	use smoltcp::phy::{Loopback, Medium};
+	use smoltcp::time::Instant;
+	use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr};
+
+	use super::*;
+	use crate::fd::socket::{SHUT_RD, SHUT_RDWR, SHUT_WR};
+
+	/// TCP connection over a loopback device
+	struct Connection {
+		iface: Interface,
+		device: Loopback,
+		sockets: SocketSet<'static>,
+		client: SocketHandle,
+		server: SocketHandle,
+		timestamp: Instant,
+	}
 
-		drop(guard);
-		let port = self.port.load(Ordering::Acquire);
-		let obj = Self {
-			handle,
-			port: AtomicU16::new(port),
-			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
-			listen: AtomicBool::new(false),
-		};
+	impl Connection {
+		fn new() -> Self {
+			let mut device = Loopback::new(Medium::Ethernet);
+			let config = Config::new(EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]).into());
+			let timestamp = Instant::from_secs(1);
+			let mut iface = Interface::new(config, &mut device, timestamp);
+			iface.update_ip_addrs(|addrs| {
+				addrs
+					.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+					.unwrap();
+			});
+
+			let mut sockets = SocketSet::new(vec![]);
+			let mut socket = || {
+				sockets.add(tcp::Socket::new(
+					tcp::SocketBuffer::new(vec![0; 1024]),
+					tcp::SocketBuffer::new(vec![0; 1024]),
+				))
+			};
+			let server = socket();
+			let client = socket();
+			sockets
+				.get_mut::<tcp::Socket<'_>>(server)
+				.listen(80)
+				.unwrap();
+			sockets
+				.get_mut::<tcp::Socket<'_>>(client)
+				.connect(iface.context(), (IpAddress::v4(127, 0, 0, 1), 80), 49152)
+				.unwrap();
+
+			let mut connection = Self {
+				iface,
+				device,
+				sockets,
+				client,
+				server,
+				timestamp,
+			};
+			connection.poll();
+			assert_eq!(connection.client().state(), tcp::State::Established);
+			connection
+		}
+
+		/// Exchanges the pending segments.
+		fn poll(&mut self) {
+			for _ in 0..10 {
+				let _ = self
+					.iface
+					.poll(self.timestamp, &mut self.device, &mut self.sockets);
+				self.timestamp += Duration::from_millis(10);
+			}
+		}
 
-		if port > 0 {
-			let _ = obj.listen(1024);
+		fn client(&mut self) -> &mut tcp::Socket<'static> {
+			self.sockets.get_mut(self.client)
 		}
 
-		obj
+		fn server(&mut self) -> &mut tcp::Socket<'static> {
+			self.sockets.get_mut(self.server)
+		}
 	}
-}
 
-impl Drop for Socket {
-	fn drop(&mut self) {
-		let _ = block_on(self.async_close(), None);
-		NIC.lock().as_nic_mut().unwrap().destroy_socket(self.handle);
+	#[test]
+	fn shutdown_write() {
+		let mut connection = Connection::new();
+		let shutdown = Shutdown::default();
+
+		shutdown.shutdown(connection.client(), SHUT_WR).unwrap();
+		assert_eq!(connection.client().state(), tcp::State::FinWait1);
+		assert_eq!(shutdown.check_send(), Err(IoError::EPIPE));
+
+		// The peer still sends data to the half-closed connection.
+		connection.poll();
+		assert_eq!(connection.client().state(), tcp::State::FinWait2);
+		connection.server().send_slice(b"hello").unwrap();
+		connection.poll();
+		assert!(!shutdown.discard_received(connection.client()));
+		assert_eq!(connection.client().recv_queue(), 5);
+	}
+
+	#[test]
+	fn shutdown_read() {
+		let mut connection = Connection::new();
+		let shutdown = Shutdown::default();
+		connection.server().send_slice(b"hello").unwrap();
+		connection.poll();
+
+		shutdown.shutdown(connection.client(), SHUT_RD).unwrap();
+		assert_eq!(connection.client().recv_queue(), 0);
+		assert_eq!(connection.client().state(), tcp::State::Established);
+		assert_eq!(shutdown.check_send(), Ok(()));
+
+		// Later data is discarded by the reads, which return zero bytes.
+		connection.server().send_slice(b"world").unwrap();
+		connection.poll();
+		assert!(shutdown.discard_received(connection.client()));
+		assert_eq!(connection.client().recv_queue(), 0);
+	}
+
+	#[test]
+	fn shutdown_twice() {
+		let mut connection = Connection::new();
+		let shutdown = Shutdown::default();
+
+		shutdown.shutdown(connection.client(), SHUT_WR).unwrap();
+		connection.poll();
+		shutdown.shutdown(connection.client(), SHUT_WR).unwrap();
+		assert_eq!(connection.client().state(), tcp::State::FinWait2);
+
+		shutdown.shutdown(connection.client(), SHUT_RDWR).unwrap();
+		shutdown.shutdown(connection.client(), SHUT_RDWR).unwrap();
+		assert_eq!(connection.client().state(), tcp::State::FinWait2);
+		assert_eq!(
+			shutdown.shutdown(connection.client(), 3),
+			Err(IoError::EINVAL)
+		);
+	}
+
+	#[test]
+	fn shutdown_unconnected_socket() {
+		let mut connection = Connection::new();
+		let shutdown = Shutdown::default();
+
+		connection.client().abort();
+		connection.poll();
+		assert_eq!(
+			shutdown.shutdown(connection.client(), SHUT_WR),
+			Err(IoError::ENOTCONN)
+		);
 	}
 }
@@ -1,6 +1,5 @@
 use alloc::boxed::Box;
 use core::future;
-use core::ops::DerefMut;
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::Poll;
 
@@ -9,28 +8,44 @@ use crossbeam_utils::atomic::AtomicCell;
 use smoltcp::socket::udp;
 use smoltcp::socket::udp::UdpMetadata;
 use smoltcp::time::Duration;
-use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
+use smoltcp::wire::{IpEndpoint, IpListenEndpoint, IpVersion};
 
-use crate::executor::network::{now, Handle, NetworkState, NIC};
+use super::shutdown_directions;
+use crate::executor::network::{now, Handle, NIC, UDP_MAX_PAYLOAD_IPV6};
 use crate::executor::{block_on, poll_on};
-use crate::fd::{IoCtl, IoError, ObjectInterface, PollEvent};
+use crate::fd::{IoCtl, IoError, ObjectInterface, PollEvent, SocketOption};
 
 #[derive(Debug)]
 pub struct Socket {
 	handle: Handle,
+	/// Address family of the socket
+	version: IpVersion,
 	nonblocking: AtomicBool,
 	endpoint: AtomicCell<Option<IpEndpoint>>,
+	reuse_addr: AtomicBool,
+	/// Received datagrams are ignored (`SHUT_RD`)
+	read_shutdown: AtomicBool,
+	/// Sending datagrams is disallowed (`SHUT_WR`)
+	write_shutdown: AtomicBool,
 }
 
 impl Socket {
-	pub fn new(handle: Handle) -> Self {
+	pub fn new(handle: Handle, version: IpVersion) -> Self {
 		Self {
 			handle,
+			version,
 			nonblocking: AtomicBool::new(false),
 			endpoint: AtomicCell::new(None),
+			reuse_addr: AtomicBool::new(false),
+			read_shutdown: AtomicBool::new(false),
+			write_shutdown: AtomicBool::new(false),
 		}
 	}
 
+	/// Calls `f` with the smoltcp socket and polls the interface afterwards.
+	///
+	/// A socket is only created on an initialized interface, which is never
+	/// torn down. Hence, the interface is always available here.
 	fn with<R>(&self, f: impl FnOnce(&mut udp::Socket<'_>) -> R) -> R {
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().unwrap();
@@ -40,6 +55,15 @@ impl Socket {
 		result
 	}
 
+	/// Checks that `endpoint` belongs to the address family of the socket.
+	fn check_family(&self, endpoint: IpEndpoint) -> Result<(), IoError> {
+		if endpoint.addr.version() == self.version {
+			Ok(())
+		} else {
+			Err(IoError::EAFNOSUPPORT)
+		}
+	}
+
 	async fn async_close(&self) -> Result<(), IoError> {
 		future::poll_fn(|_cx| {
 			self.with(|socket| {
@@ -50,23 +74,29 @@ impl Socket {
 		.await
 	}
 
-	async fn async_recvfrom(&self, buffer: &mut [u8]) -> Result<(usize, IpEndpoint), IoError> {
+	/// Receives a datagram by passing its payload to `copy`, which returns
+	/// the number of consumed bytes. Datagrams from other peers than the
+	/// connected one are discarded.
+	async fn async_recv_with<F>(&self, mut copy: F) -> Result<(usize, IpEndpoint), IoError>
+	where
+		F: FnMut(&[u8]) -> usize + Send,
+	{
+		// Shutting down requires a connected socket.
+		if self.read_shutdown.load(Ordering::Acquire) {
+			return Ok((0, self.endpoint.load().unwrap()));
+		}
+
 		future::poll_fn(|cx| {
 			self.with(|socket| {
 				if socket.is_open() {
 					if socket.can_recv() {
-						match socket.recv_slice(buffer) {
-							Ok((len, meta)) => match self.endpoint.load() {
-								Some(ep) => {
-									if meta.endpoint == ep {
-										Poll::Ready(Ok((len, meta.endpoint)))
-									} else {
-										buffer[..len].iter_mut().for_each(|x| *x = 0);
-										socket.register_recv_waker(cx.waker());
-										Poll::Pending
-									}
+						match socket.recv() {
+							Ok((data, meta)) => match self.endpoint.load() {
+								Some(ep) if meta.endpoint != ep => {
+									socket.register_recv_waker(cx.waker());
+									Poll::Pending
 								}
-								None => Poll::Ready(Ok((len, meta.endpoint))),
+								_ => Poll::Ready(Ok((copy(data), meta.endpoint))),
 							},
 							_ => Poll::Ready(Err(IoError::EIO)),
 						}
@@ -82,29 +112,45 @@ impl Socket {
 		.await
 	}
 
+	async fn async_recvfrom(&self, buffer: &mut [u8]) -> Result<(usize, IpEndpoint), IoError> {
+		self.async_recv_with(|data| {
+			let len = core::cmp::min(buffer.len(), data.len());
+			buffer[..len].copy_from_slice(&data[..len]);
+			len
+		})
+		.await
+	}
+
 	async fn async_write_with_meta(
 		&self,
 		buffer: &[u8],
 		meta: &UdpMetadata,
 	) -> Result<usize, IoError> {
+		if self.write_shutdown.load(Ordering::Acquire) {
+			return Err(IoError::EPIPE);
+		}
+
 		future::poll_fn(|cx| {
-			self.with(|socket| {
-				if socket.is_open() {
-					if socket.can_send() {
-						Poll::Ready(
-							socket
-								.send_slice(buffer, *meta)
-								.map(|_| buffer.len())
-								.map_err(|_| IoError::EIO),
-						)
-					} else {
-						socket.register_recv_waker(cx.waker());
+			let mut guard = NIC.lock();
+			let Ok(nic) = guard.as_nic_mut() else {
+				return Poll::Ready(Err(IoError::EIO));
+			};
+			let result = if nic.get_mut_socket::<udp::Socket<'_>>(self.handle).is_open() {
+				match nic.udp_send_slice(self.handle, buffer, *meta) {
+					Ok(()) => Poll::Ready(Ok(buffer.len())),
+					Err(IoError::EAGAIN) => {
+						nic.get_mut_socket::<udp::Socket<'_>>(self.handle)
+							.register_send_waker(cx.waker());
 						Poll::Pending
 					}
-				} else {
-					Poll::Ready(Err(IoError::EIO))
+					Err(err) => Poll::Ready(Err(err)),
 				}
-			})
+			} else {
+				Poll::Ready(Err(IoError::EIO))
+			};
+			nic.poll_common(now());
+
+			result
 		})
 		.await
 	}
@@ -118,13 +164,14 @@ impl ObjectInterface for Socket {
 				let ret = if socket.is_open() {
 					let mut avail = PollEvent::empty();
 
-					if socket.can_send() {
+					// A shut down direction doesn't block.
+					if socket.can_send() || self.write_shutdown.load(Ordering::Acquire) {
 						avail.insert(
 							PollEvent::POLLOUT | PollEvent::POLLWRNORM | PollEvent::POLLWRBAND,
 						);
 					}
 
-					if socket.can_recv() {
+					if socket.can_recv() || self.read_shutdown.load(Ordering::Acquire) {
 						avail.insert(
 							PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND,
 						);
@@ -158,15 +205,53 @@ impl ObjectInterface for Socket {
 	}
 
 	fn bind(&self, endpoint: IpListenEndpoint) -> Result<(), IoError> {
-		self.with(|socket| socket.bind(endpoint).map_err(|_| IoError::EADDRINUSE))
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+		nic.check_bind(
+			self.handle,
+			endpoint,
+			self.reuse_addr.load(Ordering::Acquire),
+		)?;
+		nic.get_mut_socket::<udp::Socket<'_>>(self.handle)
+			.bind(endpoint)
+			.map_err(|_| IoError::EADDRINUSE)
+	}
+
+	fn setsockopt(&self, opt: SocketOption, optval: bool) -> Result<(), IoError> {
+		match opt {
+			SocketOption::ReuseAddr => {
+				self.reuse_addr.store(optval, Ordering::Release);
+				Ok(())
+			}
+			SocketOption::NoCheck if self.version == IpVersion::Ipv4 => {
+				let mut guard = NIC.lock();
+				let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+				nic.set_udp_checksum(self.handle, !optval)
+			}
+			_ => Err(IoError::EINVAL),
+		}
+	}
+
+	fn getsockopt(&self, opt: SocketOption) -> Result<bool, IoError> {
+		match opt {
+			SocketOption::ReuseAddr => Ok(self.reuse_addr.load(Ordering::Acquire)),
+			SocketOption::NoCheck if self.version == IpVersion::Ipv4 => {
+				let mut guard = NIC.lock();
+				let nic = guard.as_nic_mut().map_err(|_| IoError::EIO)?;
+				Ok(!nic.udp_checksum(self.handle))
+			}
+			_ => Err(IoError::EINVAL),
+		}
 	}
 
 	fn connect(&self, endpoint: IpEndpoint) -> Result<(), IoError> {
+		self.check_family(endpoint)?;
 		self.endpoint.store(Some(endpoint));
 		Ok(())
 	}
 
 	fn sendto(&self, buf: &[u8], endpoint: IpEndpoint) -> Result<usize, IoError> {
+		self.check_family(endpoint)?;
 		let meta = UdpMetadata::from(endpoint);
 
 		if self.nonblocking.load(Ordering::Acquire) {
@@ -201,35 +286,8 @@ impl ObjectInterface for Socket {
 	}
 
 	async fn async_read(&self, buffer: &mut [u8]) -> Result<usize, IoError> {
-		future::poll_fn(|cx| {
-			self.with(|socket| {
-				if socket.is_open() {
-					if socket.can_recv() {
-						match socket.recv_slice(buffer) {
-							Ok((len, meta)) => match self.endpoint.load() {
-								Some(ep) => {
-									if meta.endpoint == ep {
-										Poll::Ready(Ok(len))
-									} else {
-										buffer[..len].iter_mut().for_each(|x| *x = 0);
-										socket.register_recv_waker(cx.waker());
-										Poll::Pending
-									}
-								}
-								None => Poll::Ready(Ok(len)),
-							},
-							_ => Poll::Ready(Err(IoError::EIO)),
-						}
-					} else {
-						socket.register_recv_waker(cx.waker());
-						Poll::Pending
-					}
-				} else {
-					Poll::Ready(Err(IoError::EIO))
-				}
-			})
-		})
-		.await
+		let (len, _) = self.async_recvfrom(buffer).await?;
+		Ok(len)
 	}
 
 	async fn async_write(&self, buf: &[u8]) -> Result<usize, IoError> {
@@ -237,8 +295,57 @@ impl ObjectInterface for Socket {
 			let meta = UdpMetadata::from(endpoint);
 			self.async_write_with_meta(buf, &meta).await
 		} else {
-			Err(IoError::EINVAL)
+			Err(IoError::EDESTADDRREQ)
+		}
+	}
+
+	async fn async_readv(&self, bufs: &mut [&mut [u8]]) -> Result<usize, IoError> {
+		let (len, _) = self
+			.async_recv_with(|data| super::scatter(data, bufs))
+			.await?;
+		Ok(len)
+	}
+
+	async fn async_writev(&self, bufs: &[&[u8]]) -> Result<usize, IoError> {
+		// A datagram must be contiguous. Hence, the buffers are gathered, but
+		// only if they fit into a single datagram.
+		if bufs.iter().map(|buf| buf.len()).sum::<usize>() > UDP_MAX_PAYLOAD_IPV6 {
+			return Err(IoError::EMSGSIZE);
+		}
+
+		self.async_write(&super::gather(bufs)).await
+	}
+
+	fn shutdown(&self, how: i32) -> Result<(), IoError> {
+		let (read, write) = shutdown_directions(how)?;
+
+		// Like on Linux, only connected datagram sockets can be shut down.
+		if self.endpoint.load().is_none() {
+			return Err(IoError::ENOTCONN);
+		}
+
+		// Shutting down a direction again has no effect.
+		if read {
+			self.read_shutdown.store(true, Ordering::Release);
 		}
+		if write {
+			self.write_shutdown.store(true, Ordering::Release);
+		}
+
+		Ok(())
+	}
+
+	fn is_socket(&self) -> bool {
+		true
+	}
+
+	fn nread(&self) -> Result<usize, IoError> {
+		if self.read_shutdown.load(Ordering::Acquire) {
+			return Ok(0);
+		}
+
+		// Like on Linux, only the size of the next datagram is reported.
+		Ok(self.with(|socket| socket.peek().map_or(0, |(data, _)| data.len())))
 	}
 
 	fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
@@ -258,26 +365,65 @@ impl ObjectInterface for Socket {
 	}
 }
 
-impl Clone for Socket {
-	fn clone(&self) -> Self {
-		let mut guard = NIC.lock();
+impl Drop for Socket {
+	fn drop(&mut self) {
+		let _ = block_on(self.async_close(), None);
+	}
+}
 
-		let handle = if let NetworkState::Initialized(nic) = guard.deref_mut() {
-			nic.create_udp_handle().unwrap()
-		} else {
-			panic!("Unable to create handle");
-		};
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec;
+	use core::future::Future;
+	use core::mem::ManuallyDrop;
+	use core::task::{Context, Waker};
 
-		Self {
-			handle,
-			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
-			endpoint: AtomicCell::new(self.endpoint.load()),
-		}
+	use smoltcp::iface::SocketSet;
+	use smoltcp::wire::Ipv4Address;
+
+	use super::*;
+	use crate::fd::socket::{SHUT_RD, SHUT_WR};
+
+	/// Creates a socket, which is connected to 10.0.5.1:5000. The socket
+	/// isn't closed on drop, because the tests run without a network interface.
+	fn connected_socket() -> ManuallyDrop<Socket> {
+		let mut sockets = SocketSet::new(vec![]);
+		let handle = sockets.add(udp::Socket::new(
+			udp::PacketBuffer::new(vec![], vec![]),
+			udp::PacketBuffer::new(vec![], vec![]),
+		));
+		let socket = ManuallyDrop::new(Socket::new(handle, IpVersion::Ipv4));
+		assert_eq!(socket.shutdown(SHUT_WR), Err(IoError::ENOTCONN));
+		socket
+			.connect(IpEndpoint::new(Ipv4Address::new(10, 0, 5, 1).into(), 5000))
+			.unwrap();
+		socket
 	}
-}
 
-impl Drop for Socket {
-	fn drop(&mut self) {
-		let _ = block_on(self.async_close(), None);
+	#[test]
+	fn write_after_shutdown_wr() {
+		let socket = connected_socket();
+		let mut cx = Context::from_waker(Waker::noop());
+
+		socket.shutdown(SHUT_WR).unwrap();
+		socket.shutdown(SHUT_WR).unwrap();
+		assert_eq!(
+			socket.async_write(b"hello").as_mut().poll(&mut cx),
+			Poll::Ready(Err(IoError::EPIPE))
+		);
+	}
+
+	#[test]
+	fn read_after_shutdown_rd() {
+		let socket = connected_socket();
+		let mut cx = Context::from_waker(Waker::noop());
+		let mut buffer = [0u8; 8];
+
+		socket.shutdown(SHUT_RD).unwrap();
+		assert_eq!(
+			socket.async_read(&mut buffer).as_mut().poll(&mut cx),
+			Poll::Ready(Ok(0))
+		);
+		assert_eq!(socket.nread(), Ok(0));
 	}
 }
@@ -0,0 +1,271 @@
+use alloc::boxed::Box;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use core::future;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Poll, Waker};
+
+use async_trait::async_trait;
+use hermit_sync::InterruptTicketMutex;
+
+use crate::fd::{IoCtl, IoError, ObjectInterface, PollEvent};
+
+/// Capacity of the buffer of each direction in bytes
+pub(super) const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Buffer, which transfers data in one direction between two sockets
+/// or the ends of a pipe
+#[derive(Debug, Default)]
+pub(super) struct Channel {
+	buffer: VecDeque<u8>,
+	/// The receiving socket is closed
+	pub reader_closed: bool,
+	/// The sending socket is closed
+	pub writer_closed: bool,
+	pub read_queue: VecDeque<Waker>,
+	pub write_queue: VecDeque<Waker>,
+}
+
+impl Channel {
+	/// Reads data from the buffer. Returns `Some(0)` at the end of
+	/// the stream and `None`, if the read would block.
+	pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+		if self.buffer.is_empty() {
+			return self.writer_closed.then_some(0);
+		}
+
+		let len = buf.len().min(self.buffer.len());
+		for (dst, src) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+			*dst = src;
+		}
+
+		if let Some(waker) = self.write_queue.pop_front() {
+			waker.wake();
+		}
+
+		Some(len)
+	}
+
+	/// Writes as much data as fits into the buffer. Returns `None`,
+	/// if the buffer is full and the write would block.
+	pub fn write(&mut self, buf: &[u8]) -> Option<Result<usize, IoError>> {
+		if self.reader_closed {
+			return Some(Err(IoError::EPIPE));
+		}
+
+		let len = buf.len().min(BUFFER_SIZE - self.buffer.len());
+		if len == 0 {
+			return None;
+		}
+
+		self.buffer.extend(&buf[..len]);
+		if let Some(waker) = self.read_queue.pop_front() {
+			waker.wake();
+		}
+
+		Some(Ok(len))
+	}
+
+	/// Returns the number of buffered bytes.
+	pub fn len(&self) -> usize {
+		self.buffer.len()
+	}
+
+	/// Returns the available events of the receiving side.
+	pub fn rx_events(&self) -> PollEvent {
+		let mut available = PollEvent::empty();
+		if !self.buffer.is_empty() || self.writer_closed {
+			available.insert(PollEvent::POLLIN | PollEvent::POLLRDNORM);
+		}
+		if self.writer_closed {
+			available.insert(PollEvent::POLLRDHUP | PollEvent::POLLHUP);
+		}
+		available
+	}
+
+	/// Returns the available events of the sending side.
+	pub fn tx_events(&self) -> PollEvent {
+		if self.reader_closed {
+			PollEvent::POLLERR | PollEvent::POLLHUP
+		} else if self.buffer.len() < BUFFER_SIZE {
+			PollEvent::POLLOUT | PollEvent::POLLWRNORM
+		} else {
+			PollEvent::empty()
+		}
+	}
+
+	pub fn wake_all(&mut self) {
+		for waker in self.read_queue.drain(..).chain(self.write_queue.drain(..)) {
+			waker.wake();
+		}
+	}
+}
+
+/// One end of a pair of connected sockets
+#[derive(Debug)]
+struct Endpoint {
+	rx: Arc<InterruptTicketMutex<Channel>>,
+	tx: Arc<InterruptTicketMutex<Channel>>,
+}
+
+impl Drop for Endpoint {
+	fn drop(&mut self) {
+		// signal EOF to the peer
+		let mut rx = self.rx.lock();
+		rx.reader_closed = true;
+		rx.wake_all();
+		drop(rx);
+
+		let mut tx = self.tx.lock();
+		tx.writer_closed = true;
+		tx.wake_all();
+	}
+}
+
+/// Stream socket, which is connected to another socket in the same guest.
+///
+/// In contrast to TCP sockets, the data is transferred by a buffer
+/// in the kernel and not by the network stack.
+#[derive(Debug, Clone)]
+pub(crate) struct Socket {
+	endpoint: Arc<Endpoint>,
+	nonblocking: Arc<AtomicBool>,
+}
+
+impl Socket {
+	/// Creates two connected sockets.
+	pub fn pair(nonblocking: bool) -> (Self, Self) {
+		let a = Arc::new(InterruptTicketMutex::new(Channel::default()));
+		let b = Arc::new(InterruptTicketMutex::new(Channel::default()));
+
+		let first = Self {
+			endpoint: Arc::new(Endpoint {
+				rx: a.clone(),
+				tx: b.clone(),
+			}),
+			nonblocking: Arc::new(AtomicBool::new(nonblocking)),
+		};
+		let second = Self {
+			endpoint: Arc::new(Endpoint { rx: b, tx: a }),
+			nonblocking: Arc::new(AtomicBool::new(nonblocking)),
+		};
+
+		(first, second)
+	}
+}
+
+#[async_trait]
+impl ObjectInterface for Socket {
+	async fn async_read(&self, buf: &mut [u8]) -> Result<usize, IoError> {
+		future::poll_fn(|cx| {
+			let mut rx = self.endpoint.rx.lock();
+			match rx.read(buf) {
+				Some(len) => Poll::Ready(Ok(len)),
+				None => {
+					rx.read_queue.push_back(cx.waker().clone());
+					Poll::Pending
+				}
+			}
+		})
+		.await
+	}
+
+	async fn async_write(&self, buf: &[u8]) -> Result<usize, IoError> {
+		future::poll_fn(|cx| {
+			let mut tx = self.endpoint.tx.lock();
+			match tx.write(buf) {
+				Some(result) => Poll::Ready(result),
+				None => {
+					tx.write_queue.push_back(cx.waker().clone());
+					Poll::Pending
+				}
+			}
+		})
+		.await
+	}
+
+	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
+		future::poll_fn(|cx| {
+			let mut rx = self.endpoint.rx.lock();
+			let mut tx = self.endpoint.tx.lock();
+
+			let ret = event & (rx.rx_events() | tx.tx_events());
+			if ret.is_empty() {
+				if event.intersects(PollEvent::POLLIN | PollEvent::POLLRDNORM) {
+					rx.read_queue.push_back(cx.waker().clone());
+				}
+				if event.intersects(PollEvent::POLLOUT | PollEvent::POLLWRNORM) {
+					tx.write_queue.push_back(cx.waker().clone());
+				}
+				Poll::Pending
+			} else {
+				Poll::Ready(Ok(ret))
+			}
+		})
+		.await
+	}
+
+	fn is_nonblocking(&self) -> bool {
+		self.nonblocking.load(Ordering::Relaxed)
+	}
+
+	fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
+		match cmd {
+			IoCtl::NonBlocking => {
+				self.nonblocking.store(value, Ordering::Relaxed);
+				Ok(())
+			}
+		}
+	}
+
+	fn nread(&self) -> Result<usize, IoError> {
+		Ok(self.endpoint.rx.lock().len())
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec;
+
+	use super::*;
+
+	#[test]
+	fn backpressure() {
+		let mut channel = Channel::default();
+		let data = vec![0xab; BUFFER_SIZE + 10];
+
+		assert_eq!(channel.write(&data), Some(Ok(BUFFER_SIZE)));
+		assert_eq!(channel.write(&data), None);
+
+		let mut buf = [0; 16];
+		assert_eq!(channel.read(&mut buf), Some(16));
+		assert_eq!(channel.write(&data), Some(Ok(16)));
+	}
+
+	#[test]
+	fn nread_and_nonblocking() {
+		let (first, second) = Socket::pair(false);
+		assert_eq!(first.endpoint.tx.lock().write(b"hello"), Some(Ok(5)));
+		assert_eq!(second.nread(), Ok(5));
+		assert_eq!(first.nread(), Ok(0));
+
+		second.ioctl(IoCtl::NonBlocking, true).unwrap();
+		assert!(second.is_nonblocking());
+		assert!(!first.is_nonblocking());
+	}
+
+	#[test]
+	fn eof_on_close() {
+		let mut channel = Channel::default();
+		let mut buf = [0; 16];
+
+		assert_eq!(channel.write(b"hello"), Some(Ok(5)));
+		channel.writer_closed = true;
+		assert_eq!(channel.read(&mut buf), Some(5));
+		assert_eq!(&buf[..5], b"hello");
+		assert_eq!(channel.read(&mut buf), Some(0));
+
+		channel.reader_closed = true;
+		assert_eq!(channel.write(b"hello"), Some(Err(IoError::EPIPE)));
+	}
+}
@@ -1,6 +1,10 @@
 use alloc::boxed::Box;
+#[cfg(target_arch = "x86_64")]
+use core::future;
 #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
 use core::ptr;
+#[cfg(target_arch = "x86_64")]
+use core::task::Poll;
 
 use async_lock::Mutex;
 use async_trait::async_trait;
@@ -8,6 +12,8 @@ use async_trait::async_trait;
 use x86::io::*;
 
 use crate::arch;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::{serial, COM1};
 #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
 use crate::arch::mm::{paging, VirtAddr};
 use crate::fd::{IoError, ObjectInterface, PollEvent, STDERR_FILENO, STDOUT_FILENO};
@@ -70,8 +76,66 @@ fn uhyve_send<T>(_port: u16, _data: &mut T) {
 #[derive(Debug, Clone)]
 pub struct GenericStdin;
 
+#[cfg(not(target_arch = "x86_64"))]
 impl ObjectInterface for GenericStdin {}
 
+/// Stdin reads the input of the serial console, if it isn't consumed by the kernel shell.
+#[cfg(target_arch = "x86_64")]
+#[async_trait]
+impl ObjectInterface for GenericStdin {
+	async fn poll(&self, event: PollEvent) -> Result<PollEvent, IoError> {
+		let available = PollEvent::POLLIN | PollEvent::POLLRDNORM | PollEvent::POLLRDBAND;
+		if !event.intersects(available) || console_owned_by_shell() {
+			return Ok(PollEvent::empty());
+		}
+
+		future::poll_fn(|cx| {
+			// register before checking, so that no input is missed
+			serial::register_input_waker(cx.waker());
+			if COM1
+				.lock()
+				.as_ref()
+				.is_some_and(|serial| serial.has_input())
+			{
+				Poll::Ready(Ok(event & available))
+			} else {
+				Poll::Pending
+			}
+		})
+		.await
+	}
+
+	async fn async_read(&self, buf: &mut [u8]) -> Result<usize, IoError> {
+		if console_owned_by_shell() {
+			return Err(IoError::EBUSY);
+		}
+
+		future::poll_fn(|cx| {
+			serial::register_input_waker(cx.waker());
+			let len = COM1
+				.lock()
+				.as_mut()
+				.map_or(0, |serial| serial.read_buf(buf));
+			if len > 0 || buf.is_empty() {
+				Poll::Ready(Ok(len))
+			} else {
+				Poll::Pending
+			}
+		})
+		.await
+	}
+}
+
+/// Returns `true`, if the kernel shell consumes the input of the serial console.
+#[cfg(target_arch = "x86_64")]
+fn console_owned_by_shell() -> bool {
+	#[cfg(feature = "shell")]
+	return crate::shell::owns_input();
+
+	#[cfg(not(feature = "shell"))]
+	false
+}
+
 impl GenericStdin {
 	pub const fn new() -> Self {
 		Self {}
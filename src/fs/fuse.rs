@@ -41,6 +41,10 @@ const U64_SIZE: usize = ::core::mem::size_of::<u64>();
 const S_IFLNK: u32 = 40960;
 const S_IFMT: u32 = 61440;
 
+/// Start of the year 2000 in seconds since the epoch. An earlier realtime clock is
+/// considered as unset.
+const MIN_REALTIME_SECS: u64 = 946_684_800;
+
 pub(crate) trait FuseInterface {
 	fn send_command<O: ops::Op>(
 		&mut self,
@@ -132,6 +136,29 @@ pub(crate) mod ops {
 		}
 	}
 
+	#[derive(Debug)]
+	pub(crate) struct Setattr;
+
+	impl Op for Setattr {
+		const OP_CODE: fuse_abi::Opcode = fuse_abi::Opcode::Setattr;
+		type InStruct = fuse_abi::SetattrIn;
+		type InPayload = ();
+		type OutStruct = fuse_abi::AttrOut;
+		type OutPayload = ();
+	}
+
+	impl Setattr {
+		pub(crate) fn create(
+			nid: u64,
+			attr: fuse_abi::SetattrIn,
+		) -> ((Box<CmdHeader<Self>>, Option<Box<[u8]>>), Box<Rsp<Self>>) {
+			let cmd = CmdHeader::<Self>::new(nid, attr);
+			let rsp = unsafe { Box::new_uninit().assume_init() };
+
+			((cmd, None), rsp)
+		}
+	}
+
 	#[derive(Debug)]
 	pub(crate) struct Open;
 
@@ -610,11 +637,68 @@ fn readlink(nid: u64) -> Result<String, IoError> {
 	.unwrap())
 }
 
+/// Returns the attributes, which set the timestamps of an open file `fh` to the
+/// current wall-clock time. The access time is only set, if `atime` is set.
+///
+/// If the realtime clock isn't set yet, the host sets the access and modification
+/// time by its own clock and the change time remains untouched.
+fn timestamps(fh: u64, now_micros: u64, atime: bool) -> fuse_abi::SetattrIn {
+	let secs = now_micros / 1_000_000;
+	if secs < MIN_REALTIME_SECS {
+		let valid = if atime {
+			fuse_abi::FATTR_ATIME | fuse_abi::FATTR_ATIME_NOW
+		} else {
+			0
+		};
+		return fuse_abi::SetattrIn {
+			valid: valid | fuse_abi::FATTR_FH | fuse_abi::FATTR_MTIME | fuse_abi::FATTR_MTIME_NOW,
+			fh,
+			..Default::default()
+		};
+	}
+
+	let nsecs = u32::try_from(now_micros % 1_000_000 * 1000).unwrap();
+	let mut attr = fuse_abi::SetattrIn {
+		valid: fuse_abi::FATTR_FH | fuse_abi::FATTR_MTIME | fuse_abi::FATTR_CTIME,
+		fh,
+		mtime: secs,
+		mtimensec: nsecs,
+		ctime: secs,
+		ctimensec: nsecs,
+		..Default::default()
+	};
+	if atime {
+		attr.valid |= fuse_abi::FATTR_ATIME;
+		attr.atime = secs;
+		attr.atimensec = nsecs;
+	}
+	attr
+}
+
+/// Sets the timestamps of the open file `fh` of the node `nid` to the current
+/// wall-clock time.
+fn set_timestamps(nid: u64, fh: u64, atime: bool) -> Result<(), IoError> {
+	let now_micros = arch::kernel::systemtime::now_micros();
+	let (cmd, mut rsp) = ops::Setattr::create(nid, timestamps(fh, now_micros, atime));
+	get_filesystem_driver()
+		.ok_or(IoError::ENOSYS)?
+		.lock()
+		.send_command(cmd, rsp.as_mut())?;
+
+	if unsafe { rsp.out_header.assume_init_ref().error } < 0 {
+		return Err(IoError::EIO);
+	}
+
+	Ok(())
+}
+
 #[derive(Debug)]
 struct FuseFileHandleInner {
 	fuse_nid: Option<u64>,
 	fuse_fh: Option<u64>,
 	offset: usize,
+	/// The file was written and its timestamps have to be set on release.
+	modified: bool,
 }
 
 impl FuseFileHandleInner {
@@ -623,6 +707,7 @@ impl FuseFileHandleInner {
 			fuse_nid: None,
 			fuse_fh: None,
 			offset: 0,
+			modified: false,
 		}
 	}
 
@@ -731,6 +816,8 @@ impl FuseFileHandleInner {
 				rsp_size.try_into().unwrap()
 			};
 			self.offset += rsp_len;
+			self.modified = true;
+
 			Ok(rsp_len)
 		} else {
 			warn!("File not open, cannot read!");
@@ -764,6 +851,17 @@ impl FuseFileHandleInner {
 impl Drop for FuseFileHandleInner {
 	fn drop(&mut self) {
 		if self.fuse_nid.is_some() && self.fuse_fh.is_some() {
+			// The host stamps each write by its own clock, which may differ from
+			// the guest. Hence, the timestamps are set once for all writes. The data
+			// is written anyway and a failure isn't reported.
+			if self.modified {
+				if let Err(err) =
+					set_timestamps(self.fuse_nid.unwrap(), self.fuse_fh.unwrap(), false)
+				{
+					debug!("Unable to set the timestamps of a written file: {err:?}");
+				}
+			}
+
 			let (cmd, mut rsp) =
 				ops::Release::create(self.fuse_nid.unwrap(), self.fuse_fh.unwrap());
 			get_filesystem_driver()
@@ -1147,6 +1245,10 @@ impl VfsNode for FuseDirectory {
 				let inner = unsafe { rsp.op_header.assume_init() };
 				file_guard.fuse_nid = Some(inner.entry.nodeid);
 				file_guard.fuse_fh = Some(inner.open.fh);
+
+				if let Err(err) = set_timestamps(inner.entry.nodeid, inner.open.fh, true) {
+					debug!("Unable to set the timestamps of a created file: {err:?}");
+				}
 			}
 
 			drop(file_guard);
@@ -1331,3 +1433,47 @@ pub(crate) fn init() {
 		}
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn timestamps_from_realtime_clock() {
+		let now_micros = 1_700_000_000_250_000;
+		let attr = timestamps(3, now_micros, false);
+		assert_eq!(
+			attr.valid,
+			fuse_abi::FATTR_FH | fuse_abi::FATTR_MTIME | fuse_abi::FATTR_CTIME
+		);
+		assert_eq!(attr.fh, 3);
+		assert_eq!((attr.mtime, attr.mtimensec), (1_700_000_000, 250_000_000));
+		assert_eq!((attr.ctime, attr.ctimensec), (1_700_000_000, 250_000_000));
+		assert_eq!((attr.atime, attr.atimensec), (0, 0));
+
+		let attr = timestamps(3, now_micros, true);
+		assert_ne!(attr.valid & fuse_abi::FATTR_ATIME, 0);
+		assert_eq!((attr.atime, attr.atimensec), (1_700_000_000, 250_000_000));
+	}
+
+	#[test]
+	fn timestamps_without_realtime_clock() {
+		// 10 seconds after boot
+		let attr = timestamps(3, 10_000_000, true);
+		assert_eq!(
+			attr.valid,
+			fuse_abi::FATTR_FH
+				| fuse_abi::FATTR_ATIME
+				| fuse_abi::FATTR_ATIME_NOW
+				| fuse_abi::FATTR_MTIME
+				| fuse_abi::FATTR_MTIME_NOW
+		);
+		assert_eq!((attr.mtime, attr.ctime, attr.atime), (0, 0, 0));
+
+		let attr = timestamps(3, 10_000_000, false);
+		assert_eq!(
+			attr.valid & (fuse_abi::FATTR_ATIME | fuse_abi::FATTR_ATIME_NOW),
+			0
+		);
+	}
+}
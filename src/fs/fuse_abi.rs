@@ -6,6 +6,20 @@ pub(crate) const ROOT_ID: u64 = 1;
 #[allow(dead_code)]
 pub(crate) const GETATTR_FH: u32 = 1 << 0;
 
+/// Bits of `SetattrIn::valid`, which select the attributes to set
+pub(crate) const FATTR_MODE: u32 = 1 << 0;
+pub(crate) const FATTR_UID: u32 = 1 << 1;
+pub(crate) const FATTR_GID: u32 = 1 << 2;
+pub(crate) const FATTR_SIZE: u32 = 1 << 3;
+pub(crate) const FATTR_ATIME: u32 = 1 << 4;
+pub(crate) const FATTR_MTIME: u32 = 1 << 5;
+pub(crate) const FATTR_FH: u32 = 1 << 6;
+/// The host sets the access time by its own clock
+pub(crate) const FATTR_ATIME_NOW: u32 = 1 << 7;
+/// The host sets the modification time by its own clock
+pub(crate) const FATTR_MTIME_NOW: u32 = 1 << 8;
+pub(crate) const FATTR_CTIME: u32 = 1 << 10;
+
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct Dirent {
@@ -161,6 +175,27 @@ pub(crate) struct Attr {
 	pub padding: u32,
 }
 
+#[repr(C)]
+#[derive(Default, Debug)]
+pub(crate) struct SetattrIn {
+	pub valid: u32,
+	pub padding: u32,
+	pub fh: u64,
+	pub size: u64,
+	pub lock_owner: u64,
+	pub atime: u64,
+	pub mtime: u64,
+	pub ctime: u64,
+	pub atimensec: u32,
+	pub mtimensec: u32,
+	pub ctimensec: u32,
+	pub mode: u32,
+	pub unused4: u32,
+	pub uid: u32,
+	pub gid: u32,
+	pub unused5: u32,
+}
+
 #[repr(C)]
 #[derive(Default, Debug)]
 pub(crate) struct CreateIn {
@@ -16,11 +16,12 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::slice;
 
-use async_lock::{Mutex, RwLock};
+use async_lock::RwLock;
 use async_trait::async_trait;
 
 use crate::arch;
 use crate::executor::block_on;
+use crate::executor::sync::AsyncMutex;
 use crate::fd::{AccessPermission, IoError, ObjectInterface, OpenOption, PollEvent};
 use crate::fs::{DirectoryEntry, FileAttr, NodeKind, VfsNode};
 use crate::time::timespec;
@@ -43,7 +44,7 @@ impl RomFileInner {
 #[derive(Debug, Clone)]
 struct RomFileInterface {
 	/// Position within the file
-	pos: Arc<Mutex<usize>>,
+	pos: Arc<AsyncMutex<usize>>,
 	/// File content
 	inner: Arc<RwLock<RomFileInner>>,
 }
@@ -95,7 +96,7 @@ impl ObjectInterface for RomFileInterface {
 impl RomFileInterface {
 	pub fn new(inner: Arc<RwLock<RomFileInner>>) -> Self {
 		Self {
-			pos: Arc::new(Mutex::new(0)),
+			pos: Arc::new(AsyncMutex::new(0)),
 			inner,
 		}
 	}
@@ -123,7 +124,7 @@ impl RamFileInner {
 #[derive(Debug, Clone)]
 pub struct RamFileInterface {
 	/// Position within the file
-	pos: Arc<Mutex<usize>>,
+	pos: Arc<AsyncMutex<usize>>,
 	/// File content
 	inner: Arc<RwLock<RamFileInner>>,
 }
@@ -197,7 +198,7 @@ impl ObjectInterface for RamFileInterface {
 impl RamFileInterface {
 	pub fn new(inner: Arc<RwLock<RamFileInner>>) -> Self {
 		Self {
-			pos: Arc::new(Mutex::new(0)),
+			pos: Arc::new(AsyncMutex::new(0)),
 			inner,
 		}
 	}
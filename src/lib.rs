@@ -212,9 +212,14 @@ fn boot_processor_main() -> ! {
 	});
 	info!("tls_info = {:#x?}", kernel::boot_info().load_info.tls_info);
 	arch::boot_processor_init();
+	scheduler::tick::init();
+	#[cfg(feature = "watchdog")]
+	scheduler::watchdog::init();
 
 	#[cfg(not(target_arch = "riscv64"))]
 	scheduler::add_current_core();
+	#[cfg(not(target_arch = "riscv64"))]
+	scheduler::fallback_timer::detect();
 
 	if !env::is_uhyve() {
 		arch::boot_application_processors();
@@ -238,7 +243,7 @@ fn boot_processor_main() -> ! {
 	}
 
 	// Start the initd task.
-	unsafe {
+	let tid = unsafe {
 		scheduler::PerCoreScheduler::spawn(
 			initd,
 			0,
@@ -247,6 +252,7 @@ fn boot_processor_main() -> ! {
 			USER_STACK_SIZE,
 		)
 	};
+	scheduler::set_main_task(tid);
 
 	// Run the scheduler loop.
 	PerCoreScheduler::run();
@@ -1,6 +1,9 @@
+use alloc::vec::Vec;
 use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use anstyle::AnsiColor;
+use hermit_sync::InterruptTicketMutex;
 use log::{Level, LevelFilter, Metadata, Record};
 
 /// Data structure to filter kernel messages
@@ -54,6 +57,130 @@ fn no_color() -> bool {
 	option_env!("NO_COLOR").is_some_and(|val| !val.is_empty())
 }
 
+/// Number of messages, which may be logged in a burst for the same key
+const RATE_LIMIT_BURST: u32 = 10;
+/// Time in microseconds, after which a further message may be logged
+const RATE_LIMIT_INTERVAL: u64 = 1_000_000;
+/// Maximal number of keys, whose state is tracked
+const RATE_LIMIT_KEYS: usize = 64;
+
+/// Token bucket of a single key
+struct Bucket {
+	key: &'static str,
+	tokens: u32,
+	last_refill: u64,
+	suppressed: u64,
+	/// Level of the last suppressed message
+	level: Level,
+}
+
+impl Bucket {
+	fn new(key: &'static str, now: u64) -> Self {
+		Self {
+			key,
+			tokens: RATE_LIMIT_BURST,
+			last_refill: now,
+			suppressed: 0,
+			level: Level::Trace,
+		}
+	}
+
+	/// Returns the number of suppressed messages, if a message may be logged.
+	fn acquire(&mut self, level: Level, now: u64) -> Option<u64> {
+		let refill = now.saturating_sub(self.last_refill) / RATE_LIMIT_INTERVAL;
+		if refill > 0 {
+			self.tokens = u64::from(self.tokens)
+				.saturating_add(refill)
+				.min(RATE_LIMIT_BURST.into()) as u32;
+			self.last_refill += refill * RATE_LIMIT_INTERVAL;
+		}
+
+		if self.tokens > 0 {
+			self.tokens -= 1;
+			Some(core::mem::take(&mut self.suppressed))
+		} else {
+			self.suppressed += 1;
+			self.level = level;
+			None
+		}
+	}
+}
+
+/// Rate limiter of log messages, see [`rate_limited_warn`].
+pub(crate) struct RateLimiter {
+	buckets: InterruptTicketMutex<Vec<Bucket>>,
+	/// Time, at which the window of the next key with suppressed messages expires
+	next_flush: AtomicU64,
+}
+
+impl RateLimiter {
+	pub const fn new() -> Self {
+		Self {
+			buckets: InterruptTicketMutex::new(Vec::new()),
+			next_flush: AtomicU64::new(u64::MAX),
+		}
+	}
+
+	/// Returns the number of messages with the same `key`, which were suppressed
+	/// since the last logged message, or `None`, if this message has to be suppressed.
+	///
+	/// If too many keys are in use, the messages of further keys aren't limited.
+	pub fn acquire(&self, key: &'static str, level: Level, now: u64) -> Option<u64> {
+		let mut buckets = self.buckets.lock();
+		if let Some(bucket) = buckets.iter_mut().find(|bucket| bucket.key == key) {
+			let suppressed = bucket.acquire(level, now);
+			if suppressed.is_none() {
+				self.next_flush
+					.fetch_min(bucket.last_refill + RATE_LIMIT_INTERVAL, Ordering::Relaxed);
+			}
+			return suppressed;
+		}
+		if buckets.len() < RATE_LIMIT_KEYS {
+			let mut bucket = Bucket::new(key, now);
+			let suppressed = bucket.acquire(level, now);
+			buckets.push(bucket);
+			return suppressed;
+		}
+
+		Some(0)
+	}
+
+	/// Calls `f` with the key, the level and the number of suppressed messages
+	/// of each key, whose window expired without logging a further message.
+	pub fn flush(&self, now: u64, mut f: impl FnMut(&'static str, Level, u64)) {
+		if now < self.next_flush.load(Ordering::Relaxed) {
+			return;
+		}
+
+		let mut buckets = self.buckets.lock();
+		let mut next_flush = u64::MAX;
+		for bucket in buckets.iter_mut().filter(|bucket| bucket.suppressed > 0) {
+			let expiry = bucket.last_refill + RATE_LIMIT_INTERVAL;
+			if now >= expiry {
+				f(
+					bucket.key,
+					bucket.level,
+					core::mem::take(&mut bucket.suppressed),
+				);
+			} else {
+				next_flush = next_flush.min(expiry);
+			}
+		}
+		self.next_flush.store(next_flush, Ordering::Relaxed);
+	}
+}
+
+pub(crate) static LOG_RATE_LIMITER: RateLimiter = RateLimiter::new();
+
+/// Logs the number of suppressed messages of each key, whose window expired
+/// without logging a further message, see [`rate_limited_log`].
+pub(crate) fn flush_rate_limited() {
+	LOG_RATE_LIMITER.flush(
+		crate::arch::processor::get_timer_ticks(),
+		|key, level, suppressed| log::log!(level, "{key}: {suppressed} messages suppressed"),
+	);
+}
+
 pub unsafe fn init() {
 	log::set_logger(&KernelLogger).expect("Can't initialize logger");
 	// Determines LevelFilter at compile time
@@ -97,3 +224,124 @@ macro_rules! infofooter {
 		::log::info!("");
 	}};
 }
+
+/// Logs a message with `level`, unless too many messages with the same `key`
+/// were logged recently.
+///
+/// Each key may log a burst of messages. Afterwards, one message per second is
+/// logged. The next logged message reports the number of suppressed messages.
+/// If no further message is logged, the executor reports them, as soon as the
+/// window expires.
+macro_rules! rate_limited_log {
+	($level:expr, $key:expr, $($arg:tt)+) => {{
+		let level = $level;
+		if ::log::log_enabled!(level) {
+			if let Some(suppressed) = $crate::logging::LOG_RATE_LIMITER
+				.acquire($key, level, $crate::arch::processor::get_timer_ticks())
+			{
+				if suppressed > 0 {
+					::log::log!(
+						level,
+						"{} ({suppressed} suppressed)",
+						format_args!($($arg)+)
+					);
+				} else {
+					::log::log!(level, $($arg)+);
+				}
+			}
+		}
+	}};
+}
+
+/// Logs an error, which is rate-limited by `key`, see [`rate_limited_log`].
+#[allow(unused_macros)]
+macro_rules! rate_limited_error {
+	($key:expr, $($arg:tt)+) => {
+		rate_limited_log!(::log::Level::Error, $key, $($arg)+)
+	};
+}
+
+/// Logs a warning, which is rate-limited by `key`, see [`rate_limited_log`].
+#[allow(unused_macros)]
+macro_rules! rate_limited_warn {
+	($key:expr, $($arg:tt)+) => {
+		rate_limited_log!(::log::Level::Warn, $key, $($arg)+)
+	};
+}
+
+/// Logs an information, which is rate-limited by `key`, see [`rate_limited_log`].
+#[allow(unused_macros)]
+macro_rules! rate_limited_info {
+	($key:expr, $($arg:tt)+) => {
+		rate_limited_log!(::log::Level::Info, $key, $($arg)+)
+	};
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn suppress_after_burst() {
+		let limiter = RateLimiter::new();
+		for _ in 0..RATE_LIMIT_BURST {
+			assert_eq!(limiter.acquire("rx", Level::Warn, 0), Some(0));
+		}
+		assert_eq!(limiter.acquire("rx", Level::Warn, 1), None);
+		assert_eq!(limiter.acquire("rx", Level::Warn, 2), None);
+		// other keys are independent
+		assert_eq!(limiter.acquire("link", Level::Warn, 2), Some(0));
+
+		assert_eq!(
+			limiter.acquire("rx", Level::Warn, RATE_LIMIT_INTERVAL),
+			Some(2)
+		);
+		assert_eq!(
+			limiter.acquire("rx", Level::Warn, RATE_LIMIT_INTERVAL),
+			None
+		);
+	}
+
+	#[test]
+	fn refill_up_to_burst() {
+		let limiter = RateLimiter::new();
+		for _ in 0..RATE_LIMIT_BURST {
+			limiter.acquire("rx", Level::Warn, 0);
+		}
+
+		let later = 100 * RATE_LIMIT_INTERVAL;
+		for _ in 0..RATE_LIMIT_BURST {
+			assert_eq!(limiter.acquire("rx", Level::Warn, later), Some(0));
+		}
+		assert_eq!(limiter.acquire("rx", Level::Warn, later), None);
+	}
+
+	#[test]
+	fn flush_after_window() {
+		let limiter = RateLimiter::new();
+		for _ in 0..RATE_LIMIT_BURST + 3 {
+			limiter.acquire("rx", Level::Warn, 0);
+		}
+
+		let mut flushed = Vec::new();
+		limiter.flush(RATE_LIMIT_INTERVAL - 1, |key, level, suppressed| {
+			flushed.push((key, level, suppressed))
+		});
+		assert!(flushed.is_empty());
+
+		limiter.flush(RATE_LIMIT_INTERVAL, |key, level, suppressed| {
+			flushed.push((key, level, suppressed))
+		});
+		assert_eq!(flushed, [("rx", Level::Warn, 3)]);
+
+		// the suppressed messages are reported only once
+		limiter.flush(2 * RATE_LIMIT_INTERVAL, |key, level, suppressed| {
+			flushed.push((key, level, suppressed))
+		});
+		assert_eq!(flushed.len(), 1);
+		assert_eq!(
+			limiter.acquire("rx", Level::Warn, RATE_LIMIT_INTERVAL),
+			Some(0)
+		);
+	}
+}
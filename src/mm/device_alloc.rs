@@ -1,5 +1,5 @@
 use core::alloc::{AllocError, Allocator, Layout};
-use core::ptr::{self, NonNull};
+use core::ptr::NonNull;
 
 use align_address::Align;
 
@@ -10,12 +10,13 @@ use crate::arch::mm::paging::{BasePageSize, PageSize};
 /// Allocations from this allocator always correspond to contiguous physical memory.
 pub struct DeviceAlloc;
 
+#[cfg(not(all(test, not(target_os = "none"))))]
 unsafe impl Allocator for DeviceAlloc {
 	fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
 		assert!(layout.align() <= BasePageSize::SIZE as usize);
 		let size = layout.size().align_up(BasePageSize::SIZE as usize);
 		let ptr = super::allocate(size, true).as_mut_ptr::<u8>();
-		let slice = ptr::slice_from_raw_parts_mut(ptr, size);
+		let slice = core::ptr::slice_from_raw_parts_mut(ptr, size);
 		Ok(NonNull::new(slice).unwrap())
 	}
 
@@ -26,3 +27,23 @@ unsafe impl Allocator for DeviceAlloc {
 		super::deallocate(addr, size);
 	}
 }
+
+/// Host tests run without the memory management of the kernel. Hence, the
+/// pages are taken from the global allocator.
+#[cfg(all(test, not(target_os = "none")))]
+unsafe impl Allocator for DeviceAlloc {
+	fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+		alloc::alloc::Global.allocate(page_layout(layout))
+	}
+
+	unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+		unsafe { alloc::alloc::Global.deallocate(ptr, page_layout(layout)) }
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+fn page_layout(layout: Layout) -> Layout {
+	assert!(layout.align() <= BasePageSize::SIZE as usize);
+	let size = layout.size().align_up(BasePageSize::SIZE as usize);
+	Layout::from_size_align(size.max(1), BasePageSize::SIZE as usize).unwrap()
+}
@@ -0,0 +1,265 @@
+//! Anonymous memory mappings, which are created by `sys_mmap`.
+//!
+//! The physical memory of a mapping is allocated page by page. `MADV_DONTNEED`
+//! releases the pages of a range. On x86_64, a released page is mapped again
+//! by the page fault handler, when it is touched the next time. Other
+//! architectures don't support demand paging yet. Here, the pages are zeroed
+//! instead of released.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::ptr;
+
+use align_address::Align;
+use hermit_sync::InterruptTicketMutex;
+
+use crate::arch;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::mm::paging::PageTableEntryFlagsExt;
+use crate::arch::mm::paging::{BasePageSize, PageSize, PageTableEntryFlags};
+use crate::arch::mm::{physicalmem, virtualmem, PhysAddr, VirtAddr};
+use crate::errno::{EINVAL, ENOMEM};
+
+/// Advice of `sys_madvise`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Advice {
+	/// The pages are accessed soon and are populated in advance.
+	WillNeed,
+	/// The pages aren't needed any more. The next access reads zeros.
+	DontNeed,
+}
+
+struct Mapping {
+	start: VirtAddr,
+	flags: PageTableEntryFlags,
+	/// Physical frame of each page, if the page is resident
+	frames: Vec<Option<PhysAddr>>,
+}
+
+impl Mapping {
+	fn page_address(&self, page: usize) -> VirtAddr {
+		self.start + page * BasePageSize::SIZE as usize
+	}
+
+	/// Maps a zeroed frame to `page`, if the page isn't resident.
+	fn populate(&mut self, page: usize) -> Result<(), i32> {
+		if self.frames[page].is_some() {
+			return Ok(());
+		}
+
+		let frame = physicalmem::allocate(BasePageSize::SIZE as usize).map_err(|_| ENOMEM)?;
+		let addr = self.page_address(page);
+		arch::mm::paging::map::<BasePageSize>(addr, frame, 1, self.flags);
+		unsafe {
+			ptr::write_bytes(addr.as_mut_ptr::<u8>(), 0, BasePageSize::SIZE as usize);
+		}
+		self.frames[page] = Some(frame);
+
+		Ok(())
+	}
+
+	/// Releases the frame of `page`.
+	fn release(&mut self, page: usize) {
+		if let Some(frame) = self.frames[page].take() {
+			arch::mm::paging::unmap::<BasePageSize>(self.page_address(page), 1);
+			physicalmem::deallocate(frame, BasePageSize::SIZE as usize);
+		}
+	}
+
+	/// Makes sure that the next access of `page` reads zeros.
+	fn discard(&mut self, page: usize) {
+		if cfg!(target_arch = "x86_64") {
+			self.release(page);
+		} else if self.frames[page].is_some() {
+			unsafe {
+				ptr::write_bytes(
+					self.page_address(page).as_mut_ptr::<u8>(),
+					0,
+					BasePageSize::SIZE as usize,
+				);
+			}
+		}
+	}
+}
+
+static MAPPINGS: InterruptTicketMutex<Vec<Mapping>> = InterruptTicketMutex::new(Vec::new());
+
+/// Returns the indices of the pages of `[start, start + size)` within a mapping, which
+/// starts at `mapping_start` and consists of `pages` pages. Returns `None`, if
+/// the range isn't completely part of the mapping.
+fn page_range(mapping_start: u64, pages: usize, start: u64, size: usize) -> Option<Range<usize>> {
+	let offset = start.checked_sub(mapping_start)?;
+	let first = usize::try_from(offset / BasePageSize::SIZE).ok()?;
+	let size = size.checked_add(BasePageSize::SIZE as usize - 1)?;
+	let last = first.checked_add(size / BasePageSize::SIZE as usize)?;
+
+	(last <= pages).then_some(first..last)
+}
+
+fn flush_tlb() {
+	#[cfg(all(target_arch = "x86_64", feature = "smp"))]
+	crate::arch::x86_64::kernel::apic::ipi_tlb_flush();
+}
+
+/// Creates an anonymous mapping of `size` bytes, whose pages are zeroed.
+pub(crate) fn map_anonymous(
+	size: usize,
+	writable: bool,
+	executable: bool,
+) -> Result<VirtAddr, i32> {
+	if size == 0 {
+		return Err(EINVAL);
+	}
+
+	let size = size.align_up(BasePageSize::SIZE as usize);
+	let start = virtualmem::allocate(size).map_err(|_| ENOMEM)?;
+
+	let mut flags = PageTableEntryFlags::empty();
+	flags.normal();
+	if writable {
+		flags.writable();
+	}
+	if !executable {
+		flags.execute_disable();
+	}
+
+	let mut mapping = Mapping {
+		start,
+		flags,
+		frames: (0..size / BasePageSize::SIZE as usize)
+			.map(|_| None)
+			.collect(),
+	};
+
+	for page in 0..mapping.frames.len() {
+		if let Err(err) = mapping.populate(page) {
+			for page in 0..mapping.frames.len() {
+				mapping.release(page);
+			}
+			virtualmem::deallocate(start, size);
+			return Err(err);
+		}
+	}
+
+	MAPPINGS.lock().push(mapping);
+
+	Ok(start)
+}
+
+/// Removes the anonymous mapping, which starts at `addr`. Only complete
+/// mappings can be removed.
+pub(crate) fn unmap_anonymous(addr: VirtAddr, size: usize) -> Result<(), i32> {
+	let mut mappings = MAPPINGS.lock();
+	let index = mappings
+		.iter()
+		.position(|mapping| {
+			mapping.start == addr
+				&& size.align_up(BasePageSize::SIZE as usize)
+					== mapping.frames.len() * BasePageSize::SIZE as usize
+		})
+		.ok_or(EINVAL)?;
+
+	let mut mapping = mappings.swap_remove(index);
+	drop(mappings);
+
+	for page in 0..mapping.frames.len() {
+		mapping.release(page);
+	}
+	flush_tlb();
+	virtualmem::deallocate(
+		mapping.start,
+		mapping.frames.len() * BasePageSize::SIZE as usize,
+	);
+
+	Ok(())
+}
+
+/// Applies `advice` to the pages of `[addr, addr + size)`.
+///
+/// Fails with `EINVAL`, if `addr` isn't page aligned, and with `ENOMEM`,
+/// if the range isn't part of a single anonymous mapping.
+pub(crate) fn advise(addr: VirtAddr, size: usize, advice: Advice) -> Result<(), i32> {
+	if !addr.is_aligned(BasePageSize::SIZE) {
+		return Err(EINVAL);
+	}
+	if size == 0 {
+		return Ok(());
+	}
+
+	let mut mappings = MAPPINGS.lock();
+	let (mapping, pages) = mappings
+		.iter_mut()
+		.find_map(|mapping| {
+			page_range(
+				mapping.start.as_u64(),
+				mapping.frames.len(),
+				addr.as_u64(),
+				size,
+			)
+			.map(|pages| (mapping, pages))
+		})
+		.ok_or(ENOMEM)?;
+
+	match advice {
+		Advice::WillNeed => {
+			for page in pages {
+				mapping.populate(page)?;
+			}
+		}
+		Advice::DontNeed => {
+			for page in pages {
+				mapping.discard(page);
+			}
+			flush_tlb();
+		}
+	}
+
+	Ok(())
+}
+
+/// Maps a zeroed page, if `addr` belongs to a released page of an anonymous
+/// mapping. Returns `true`, if the access can be repeated.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn handle_page_fault(addr: VirtAddr) -> bool {
+	// The lock isn't held, while a released page is accessed. Hence, this
+	// core doesn't hold the lock already, and a contended lock is released soon.
+	MAPPINGS.lock().iter_mut().any(|mapping| {
+		page_range(
+			mapping.start.as_u64(),
+			mapping.frames.len(),
+			addr.as_u64(),
+			1,
+		)
+		.is_some_and(|pages| mapping.populate(pages.start).is_ok())
+	})
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn range_within_mapping() {
+		const PAGE: u64 = BasePageSize::SIZE;
+
+		assert_eq!(page_range(0x10000, 4, 0x10000, 1), Some(0..1));
+		assert_eq!(
+			page_range(0x10000, 4, 0x10000 + PAGE, 2 * PAGE as usize),
+			Some(1..3)
+		);
+		assert_eq!(
+			page_range(0x10000, 4, 0x10000, 4 * PAGE as usize),
+			Some(0..4)
+		);
+	}
+
+	#[test]
+	fn range_outside_of_mapping() {
+		const PAGE: u64 = BasePageSize::SIZE;
+
+		assert_eq!(page_range(0x10000, 4, 0x10000 - PAGE, 1), None);
+		assert_eq!(page_range(0x10000, 4, 0x10000, 4 * PAGE as usize + 1), None);
+		assert_eq!(page_range(0x10000, 4, 0x10000 + 4 * PAGE, 1), None);
+		assert_eq!(page_range(0x10000, 4, 0x10000, usize::MAX), None);
+	}
+}
@@ -1,5 +1,6 @@
 pub mod allocator;
 pub mod device_alloc;
+pub(crate) mod mmap;
 
 use core::mem;
 use core::ops::Range;
@@ -313,6 +314,7 @@ pub(crate) fn print_information() {
 }
 
 /// Soft-deprecated in favor of `DeviceAlloc`
+#[cfg(not(all(test, not(target_os = "none"))))]
 pub(crate) fn allocate(size: usize, no_execution: bool) -> VirtAddr {
 	let size = size.align_up(BasePageSize::SIZE as usize);
 	let physical_address = arch::mm::physicalmem::allocate(size).unwrap();
@@ -330,6 +332,7 @@ pub(crate) fn allocate(size: usize, no_execution: bool) -> VirtAddr {
 }
 
 /// Soft-deprecated in favor of `DeviceAlloc`
+#[cfg(not(all(test, not(target_os = "none"))))]
 pub(crate) fn deallocate(virtual_address: VirtAddr, size: usize) {
 	let size = size.align_up(BasePageSize::SIZE as usize);
 
@@ -348,6 +351,27 @@ pub(crate) fn deallocate(virtual_address: VirtAddr, size: usize) {
 	}
 }
 
+/// Host tests run without the memory management of the kernel. Hence, the
+/// pages are taken from the global allocator.
+#[cfg(all(test, not(target_os = "none")))]
+pub(crate) fn allocate(size: usize, _no_execution: bool) -> VirtAddr {
+	let ptr = unsafe { alloc::alloc::alloc_zeroed(host_page_layout(size)) };
+	assert!(!ptr.is_null());
+	VirtAddr::from(ptr.expose_provenance())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+pub(crate) fn deallocate(virtual_address: VirtAddr, size: usize) {
+	let ptr = core::ptr::with_exposed_provenance_mut(virtual_address.as_usize());
+	unsafe { alloc::alloc::dealloc(ptr, host_page_layout(size)) }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+fn host_page_layout(size: usize) -> core::alloc::Layout {
+	let size = size.max(1).align_up(BasePageSize::SIZE as usize);
+	core::alloc::Layout::from_size_align(size, BasePageSize::SIZE as usize).unwrap()
+}
+
 /// Maps a given physical address and size in virtual space and returns address.
 #[cfg(feature = "pci")]
 pub(crate) fn map(
@@ -0,0 +1,58 @@
+//! Fallback for a timer interrupt, which isn't delivered.
+//!
+//! On minimal hypervisor configurations, the timer interrupt might not be wired.
+//! The timer ticks still advance, but blocked tasks and the network are never woken up.
+//! At startup, the boot processor arms the one-shot timer and waits for its interrupt.
+//! If it doesn't arrive within [`PROBE_WINDOW`], the hardware timer is disabled on all
+//! cores and the idle loop polls the deadlines of the timer wheel instead of halting.
+//! Busy cores check the deadlines in the scheduler, i.e. whenever a task blocks or yields.
+//!
+//! A disabled hardware timer cannot fire late. An interrupt, which was already pending
+//! at the switch, only expires timers, whose deadline has passed. Hence, a deadline is
+//! handled once, regardless of whether the interrupt or the idle loop reaches it first.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::arch;
+use crate::arch::processor::get_timer_ticks;
+
+/// Delay of the probing timer interrupt in microseconds
+const PROBE_DELAY: u64 = 1000;
+/// Time in microseconds, in which the probing timer interrupt has to arrive
+const PROBE_WINDOW: u64 = 100_000;
+
+/// Set, if a timer interrupt was delivered
+static FIRED: AtomicBool = AtomicBool::new(false);
+/// Set, if the idle loop and the scheduler poll the timer instead of the timer interrupt
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Records the delivery of a timer interrupt.
+#[inline]
+pub(crate) fn record_interrupt() {
+	FIRED.store(true, Ordering::Relaxed);
+}
+
+/// Returns `true`, if the hardware timer is replaced by polling.
+#[inline]
+pub(crate) fn is_active() -> bool {
+	ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Checks that the timer interrupt is delivered and activates the fallback otherwise.
+///
+/// Has to be called by the boot processor with enabled interrupts, after its
+/// scheduler is initialized.
+pub(crate) fn detect() {
+	let start = get_timer_ticks();
+	arch::set_oneshot_timer(Some(start + PROBE_DELAY));
+
+	while !FIRED.load(Ordering::Relaxed) && get_timer_ticks() - start < PROBE_WINDOW {
+		core::hint::spin_loop();
+	}
+
+	if !FIRED.load(Ordering::Relaxed) {
+		warn!("Timer interrupt doesn't arrive within {PROBE_WINDOW} us, poll the timer instead");
+		arch::set_oneshot_timer(None);
+		ACTIVE.store(true, Ordering::Relaxed);
+	}
+}
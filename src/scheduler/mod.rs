@@ -4,7 +4,6 @@ use alloc::boxed::Box;
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::rc::Rc;
 use alloc::sync::Arc;
-#[cfg(feature = "smp")]
 use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::future::{self, Future};
@@ -31,7 +30,13 @@ use crate::fd::{FileDescriptor, IoError, ObjectInterface};
 use crate::kernel::scheduler::TaskStacks;
 use crate::scheduler::task::*;
 
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub(crate) mod fallback_timer;
 pub mod task;
+pub(crate) mod tick;
+mod timer_wheel;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
 
 static NO_TASKS: AtomicU32 = AtomicU32::new(0);
 /// Map between Core ID and per-core scheduler
@@ -44,16 +49,41 @@ static WAITING_TASKS: InterruptTicketMutex<BTreeMap<TaskId, VecDeque<TaskHandle>
 /// Map between Task ID and TaskHandle
 static TASKS: InterruptTicketMutex<BTreeMap<TaskId, TaskHandle>> =
 	InterruptTicketMutex::new(BTreeMap::new());
+/// ID of the main task of the application
+static MAIN_TASK: OnceCell<TaskId> = OnceCell::new();
+/// Map between Task ID and the CPU time (in microseconds), which the task consumed
+/// in its previous time slices
+static CPU_TIMES: InterruptTicketMutex<BTreeMap<TaskId, u64>> =
+	InterruptTicketMutex::new(BTreeMap::new());
+/// Map between Task ID and the set of cores, on which the task may run.
+/// Tasks without an entry may run on all cores.
+static AFFINITIES: InterruptTicketMutex<BTreeMap<TaskId, CpuSet>> =
+	InterruptTicketMutex::new(BTreeMap::new());
 
 /// Unique identifier for a core.
 pub type CoreId = u32;
 
+/// Set of cores, where bit `n` represents core `n`.
+pub(crate) type CpuSet = u64;
+
+/// Task, which is moved to another core.
+#[cfg(feature = "smp")]
+struct MigratedTask(Rc<RefCell<Task>>);
+
+// SAFETY: The source core releases all references to the task before the task
+// is passed to the target core. Hence, the reference count isn't accessed
+// concurrently.
+#[cfg(feature = "smp")]
+unsafe impl Send for MigratedTask {}
+
 #[cfg(feature = "smp")]
 pub(crate) struct SchedulerInput {
 	/// Queue of new tasks
 	new_tasks: VecDeque<NewTask>,
 	/// Queue of task, which are wakeup by another core
 	wakeup_tasks: VecDeque<TaskHandle>,
+	/// Queue of ready tasks, which are migrated from another core
+	migrated_tasks: VecDeque<MigratedTask>,
 }
 
 #[cfg(feature = "smp")]
@@ -62,6 +92,7 @@ impl SchedulerInput {
 		Self {
 			new_tasks: VecDeque::new(),
 			wakeup_tasks: VecDeque::new(),
+			migrated_tasks: VecDeque::new(),
 		}
 	}
 }
@@ -86,8 +117,16 @@ pub(crate) struct PerCoreScheduler {
 	ready_queue: PriorityTaskQueue,
 	/// Queue of tasks, which are finished and can be released
 	finished_tasks: VecDeque<Rc<RefCell<Task>>>,
+	/// Queue of tasks, which have to be migrated to another core
+	#[cfg(feature = "smp")]
+	migrating_tasks: VecDeque<Rc<RefCell<Task>>>,
+	/// Indicates that the current task gives up the core voluntarily
+	#[cfg(feature = "smp")]
+	yielding: bool,
 	/// Queue of blocked tasks, sorted by wakeup time.
 	blocked_tasks: BlockedTaskQueue,
+	/// Time stamp of the last task switch
+	last_task_switch: u64,
 }
 
 pub(crate) trait PerCoreSchedulerExt {
@@ -95,6 +134,11 @@ pub(crate) trait PerCoreSchedulerExt {
 	/// Interrupt flag will be cleared during the reschedule
 	fn reschedule(self);
 
+	/// Gives up the core voluntarily. In contrast to [`reschedule`](Self::reschedule),
+	/// the current task is migrated, if it may not run on this core anymore.
+	/// Hence, the caller must not use its reference to the scheduler afterwards.
+	fn yield_now(self);
+
 	#[cfg(any(feature = "tcp", feature = "udp"))]
 	fn add_network_timer(self, wakeup_time: Option<u64>);
 
@@ -160,6 +204,16 @@ impl PerCoreSchedulerExt for &mut PerCoreScheduler {
 		without_interrupts(|| self.scheduler());
 	}
 
+	fn yield_now(self) {
+		without_interrupts(move || {
+			#[cfg(feature = "smp")]
+			{
+				self.yielding = true;
+			}
+			self.reschedule();
+		})
+	}
+
 	#[cfg(any(feature = "tcp", feature = "udp"))]
 	fn add_network_timer(self, wakeup_time: Option<u64>) {
 		without_interrupts(|| {
@@ -194,6 +248,8 @@ impl PerCoreSchedulerExt for &mut PerCoreScheduler {
 					self.custom_wakeup(task);
 				}
 			}
+			CPU_TIMES.lock().remove(&current_id);
+			AFFINITIES.lock().remove(&current_id);
 		});
 
 		self.reschedule();
@@ -385,12 +441,27 @@ impl PerCoreScheduler {
 		self.current_task.borrow().prio < self.ready_queue.get_highest_priority()
 	}
 
+	/// Wakes up the tasks, whose timer is expired. In contrast to
+	/// [`handle_waiting_tasks`](Self::handle_waiting_tasks), nothing is done before
+	/// the next deadline.
+	fn handle_expired_timers(&mut self) {
+		if self
+			.blocked_tasks
+			.next_deadline()
+			.is_some_and(|deadline| deadline <= arch::processor::get_timer_ticks())
+		{
+			self.handle_waiting_tasks();
+		}
+	}
+
 	#[inline]
 	pub fn handle_waiting_tasks(&mut self) {
 		without_interrupts(|| {
+			#[cfg(feature = "watchdog")]
+			watchdog::check();
 			crate::executor::run();
 			for task in self.blocked_tasks.handle_waiting_tasks() {
-				self.ready_queue.push(task);
+				self.wakeup(task);
 			}
 		});
 	}
@@ -399,7 +470,7 @@ impl PerCoreScheduler {
 	pub fn custom_wakeup(&mut self, task: TaskHandle) {
 		without_interrupts(|| {
 			let task = self.blocked_tasks.custom_wakeup(task);
-			self.ready_queue.push(task);
+			self.wakeup(task);
 		});
 	}
 
@@ -408,7 +479,7 @@ impl PerCoreScheduler {
 		if task.get_core_id() == self.core_id {
 			without_interrupts(|| {
 				let task = self.blocked_tasks.custom_wakeup(task);
-				self.ready_queue.push(task);
+				self.wakeup(task);
 			});
 		} else {
 			get_scheduler_input(task.get_core_id())
@@ -459,6 +530,13 @@ impl PerCoreScheduler {
 		without_interrupts(|| self.current_task.borrow().id)
 	}
 
+	/// Returns the id of the current task, unless the task is borrowed right now.
+	/// A non-maskable interrupt may interrupt the scheduler, while it holds the borrow.
+	#[cfg(all(target_arch = "x86_64", feature = "watchdog"))]
+	pub fn try_get_current_task_id(&self) -> Option<TaskId> {
+		self.current_task.try_borrow().ok().map(|task| task.id)
+	}
+
 	#[inline]
 	pub fn get_current_task_object_map(
 		&self,
@@ -580,7 +658,7 @@ impl PerCoreScheduler {
 				let mut pinned_obj = core::pin::pin!(borrowed.object_map.write());
 
 				let mut guard = ready!(pinned_obj.as_mut().poll(cx));
-				let obj = (*(guard.get(&fd).ok_or(IoError::EINVAL)?)).clone();
+				let obj = (*(guard.get(&fd).ok_or(IoError::EBADF)?)).clone();
 
 				let new_fd = || -> Result<FileDescriptor, IoError> {
 					let mut fd: FileDescriptor = 0;
@@ -606,6 +684,32 @@ impl PerCoreScheduler {
 		.await
 	}
 
+	/// Duplicate the IO interface `fd1` as file descriptor `fd2`. Both file
+	/// descriptors share the same object. An object, which was named by `fd2`
+	/// before, is released. Returns `fd2`.
+	pub async fn dup_object2(
+		&self,
+		fd1: FileDescriptor,
+		fd2: FileDescriptor,
+	) -> Result<FileDescriptor, IoError> {
+		let replaced = future::poll_fn(|cx| {
+			without_interrupts(|| {
+				let borrowed = self.current_task.borrow();
+				let mut pinned_obj = core::pin::pin!(borrowed.object_map.write());
+
+				let mut guard = ready!(pinned_obj.as_mut().poll(cx));
+				Ready(dup_in_map(&mut guard, fd1, fd2))
+			})
+		})
+		.await?;
+
+		// The replaced object is released after the object map is unlocked,
+		// because closing a socket may have to lock the network interface.
+		drop(replaced);
+
+		Ok(fd2)
+	}
+
 	/// Remove a IO interface, which is named by the file descriptor
 	pub async fn remove_object(
 		&self,
@@ -720,13 +824,71 @@ impl PerCoreScheduler {
 
 		while let Some(task) = input_locked.wakeup_tasks.pop_front() {
 			let task = self.blocked_tasks.custom_wakeup(task);
-			self.ready_queue.push(task);
+			self.wakeup(task);
 		}
 
 		while let Some(new_task) = input_locked.new_tasks.pop_front() {
 			let task = Rc::new(RefCell::new(Task::from(new_task)));
 			self.ready_queue.push(task.clone());
 		}
+
+		while let Some(MigratedTask(task)) = input_locked.migrated_tasks.pop_front() {
+			task.borrow_mut().core_id = self.core_id;
+			self.ready_queue.push(task);
+		}
+	}
+
+	/// Makes the woken up `task` ready. A task, which may not run on this core
+	/// anymore, is migrated instead.
+	fn wakeup(&mut self, task: Rc<RefCell<Task>>) {
+		#[cfg(feature = "smp")]
+		if self.migration_target(task.borrow().id).is_some() {
+			self.migrating_tasks.push_back(task);
+			return;
+		}
+
+		self.ready_queue.push(task);
+	}
+
+	/// Returns the core, to which the task `id` has to be migrated, or `None`
+	/// if the task may run on this core.
+	#[cfg(feature = "smp")]
+	fn migration_target(&self, id: TaskId) -> Option<CoreId> {
+		let cpuset = AFFINITIES.lock().get(&id).copied()?;
+		target_core(cpuset & online_cores(), self.core_id)
+	}
+
+	/// Passes the tasks, which may not run on this core, to their target cores.
+	///
+	/// A task is passed after the switch to another task. At this point,
+	/// its context is completely saved.
+	#[cfg(feature = "smp")]
+	fn migrate_tasks(&mut self) {
+		while let Some(task) = self.migrating_tasks.pop_front() {
+			let id = task.borrow().id;
+			let Some(core_id) = self.migration_target(id) else {
+				// the affinity has been changed in the meantime
+				self.ready_queue.push(task);
+				continue;
+			};
+
+			// The FPU state is saved now, because the task leaves the core.
+			#[cfg(target_arch = "x86_64")]
+			if Rc::ptr_eq(&task, &self.fpu_owner) {
+				self.fpu_owner.borrow_mut().last_fpu_state.save();
+				self.fpu_owner = self.idle_task.clone();
+			}
+
+			debug!("Migrating task {} to core {}", id, core_id);
+			if let Some(handle) = TASKS.lock().get_mut(&id) {
+				*handle = TaskHandle::new(id, handle.get_priority(), core_id);
+			}
+			get_scheduler_input(core_id)
+				.lock()
+				.migrated_tasks
+				.push_back(MigratedTask(task));
+			arch::wakeup_core(core_id);
+		}
 	}
 
 	/// Only the idle task should call this function.
@@ -735,18 +897,40 @@ impl PerCoreScheduler {
 	pub fn run() -> ! {
 		let backoff = Backoff::new();
 
+		// start the periodic check of the other cores
+		#[cfg(feature = "watchdog")]
+		crate::arch::set_oneshot_timer(None);
+
 		loop {
 			let core_scheduler = core_scheduler();
 			interrupts::disable();
+			#[cfg(feature = "watchdog")]
+			watchdog::heartbeat();
 
 			// run async tasks
 			crate::executor::run();
 
 			// do housekeeping
 			core_scheduler.cleanup_tasks();
+			#[cfg(feature = "smp")]
+			core_scheduler.migrate_tasks();
+
+			// without a timer interrupt, the idle loop has to handle the expired timers
+			#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+			let polls_timer = fallback_timer::is_active();
+			#[cfg(target_arch = "riscv64")]
+			let polls_timer = false;
+			if polls_timer {
+				core_scheduler.handle_expired_timers();
+			}
 
 			if core_scheduler.ready_queue.is_empty() {
-				if backoff.is_completed() {
+				// a busy polled network device doesn't wake up a halted core
+				if backoff.is_completed() && !crate::executor::is_busy_polling() && !polls_timer {
+					// a halted core doesn't make progress
+					#[cfg(feature = "watchdog")]
+					watchdog::exempt(interrupts::enable_and_wait);
+					#[cfg(not(feature = "watchdog"))]
 					interrupts::enable_and_wait();
 				} else {
 					interrupts::enable();
@@ -769,12 +953,24 @@ impl PerCoreScheduler {
 	/// Triggers the scheduler to reschedule the tasks.
 	/// Interrupt flag must be cleared before calling this function.
 	pub fn scheduler(&mut self) -> Option<*mut usize> {
+		#[cfg(feature = "watchdog")]
+		watchdog::heartbeat();
+
 		// run background tasks
 		crate::executor::run();
 
 		// Someone wants to give up the CPU
 		// => we have time to cleanup the system
 		self.cleanup_tasks();
+		#[cfg(feature = "smp")]
+		self.migrate_tasks();
+
+		// without a timer interrupt, a busy core handles the expired timers, whenever
+		// it switches tasks
+		#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+		if fallback_timer::is_active() {
+			self.handle_expired_timers();
+		}
 
 		// Get information about the current task.
 		let (id, last_stack_pointer, prio, status) = {
@@ -787,9 +983,19 @@ impl PerCoreScheduler {
 			)
 		};
 
+		// A running task, which may not run on this core anymore, gives up the core.
+		// The task is only migrated, if it yields voluntarily. An interrupted task
+		// may still use the scheduler of this core, once it is resumed.
+		#[cfg(feature = "smp")]
+		let migrate = core::mem::take(&mut self.yielding)
+			&& status == TaskStatus::Running
+			&& self.migration_target(id).is_some();
+		#[cfg(not(feature = "smp"))]
+		let migrate = false;
+
 		let mut new_task = None;
 
-		if status == TaskStatus::Running {
+		if status == TaskStatus::Running && !migrate {
 			// A task is currently running.
 			// Check if a task with a equal or higher priority is available.
 			if let Some(task) = self.ready_queue.pop_with_prio(prio) {
@@ -822,6 +1028,13 @@ impl PerCoreScheduler {
 			if status == TaskStatus::Running {
 				// Mark the running task as ready again and add it back to the queue.
 				self.current_task.borrow_mut().status = TaskStatus::Ready;
+				#[cfg(feature = "smp")]
+				if migrate {
+					self.migrating_tasks.push_back(self.current_task.clone());
+				} else {
+					self.ready_queue.push(self.current_task.clone());
+				}
+				#[cfg(not(feature = "smp"))]
 				self.ready_queue.push(self.current_task.clone());
 			}
 
@@ -837,6 +1050,13 @@ impl PerCoreScheduler {
 			};
 
 			if id != new_id {
+				// Account the time slice of the previous task.
+				let now = arch::processor::get_timer_ticks();
+				if status != TaskStatus::Finished {
+					*CPU_TIMES.lock().entry(id).or_default() += now - self.last_task_switch;
+				}
+				self.last_task_switch = now;
+
 				// Tell the scheduler about the new task.
 				debug!(
 					"Switching task from {} to {} (stack {:#X} => {:p})",
@@ -921,7 +1141,12 @@ pub(crate) fn add_current_core() {
 		idle_task,
 		ready_queue: PriorityTaskQueue::new(),
 		finished_tasks: VecDeque::new(),
+		#[cfg(feature = "smp")]
+		migrating_tasks: VecDeque::new(),
+		#[cfg(feature = "smp")]
+		yielding: false,
 		blocked_tasks: BlockedTaskQueue::new(),
+		last_task_switch: arch::processor::get_timer_ticks(),
 	});
 
 	let scheduler = Box::into_raw(boxed_scheduler);
@@ -962,15 +1187,16 @@ pub unsafe fn spawn(
 
 #[allow(clippy::result_unit_err)]
 pub fn join(id: TaskId) -> Result<(), ()> {
-	let core_scheduler = core_scheduler();
-
 	debug!(
 		"Task {} is waiting for task {}",
-		core_scheduler.get_current_task_id(),
+		core_scheduler().get_current_task_id(),
 		id
 	);
 
 	loop {
+		// The task may be migrated, while it is blocked. Hence, the scheduler
+		// is looked up in each iteration.
+		let core_scheduler = core_scheduler();
 		let mut waiting_tasks_guard = WAITING_TASKS.lock();
 
 		if let Some(queue) = waiting_tasks_guard.get_mut(&id) {
@@ -990,10 +1216,119 @@ pub fn shutdown(arg: i32) -> ! {
 	crate::syscalls::shutdown(arg)
 }
 
+/// Registers the main task of the application, whose ID is used as process ID.
+pub(crate) fn set_main_task(id: TaskId) {
+	MAIN_TASK.set(id).unwrap();
+}
+
+/// Returns the process ID. As Hermit runs a single application, this is the
+/// ID of its main task, such that the process ID of the main task matches its
+/// task ID. Returns `None`, if the application isn't started yet.
+pub(crate) fn get_pid() -> Option<TaskId> {
+	MAIN_TASK.get().copied()
+}
+
+/// Returns the IDs of all tasks of the application, which aren't finished yet.
+/// The idle tasks of the cores are not part of the application.
+pub(crate) fn get_tids() -> Vec<TaskId> {
+	let alive: Vec<TaskId> = WAITING_TASKS.lock().keys().copied().collect();
+	let tasks = TASKS.lock();
+	alive
+		.into_iter()
+		.filter(|id| {
+			tasks
+				.get(id)
+				.is_some_and(|task| task.get_priority() != IDLE_PRIO)
+		})
+		.collect()
+}
+
+/// Returns the number of tasks, which aren't finished yet.
+pub(crate) fn number_of_tasks() -> u32 {
+	NO_TASKS.load(Ordering::SeqCst)
+}
+
 fn get_task_handle(id: TaskId) -> Option<TaskHandle> {
 	TASKS.lock().get(&id).copied()
 }
 
+/// Returns the CPU time (in microseconds), which the task `id` consumed, or
+/// `None` if the task doesn't exist (anymore).
+///
+/// The current time slice is only included if the task runs on the current core.
+pub(crate) fn get_cpu_time(id: TaskId) -> Option<u64> {
+	without_interrupts(|| {
+		if !WAITING_TASKS.lock().contains_key(&id) {
+			return None;
+		}
+
+		let mut cpu_time = CPU_TIMES.lock().get(&id).copied().unwrap_or_default();
+		let core_scheduler = core_scheduler();
+		if core_scheduler.current_task.borrow().id == id {
+			cpu_time += arch::processor::get_timer_ticks() - core_scheduler.last_task_switch;
+		}
+
+		Some(cpu_time)
+	})
+}
+
+/// Returns the set of online cores.
+fn online_cores() -> CpuSet {
+	let count = get_processor_count().min(CpuSet::BITS);
+	CpuSet::MAX.checked_shr(CpuSet::BITS - count).unwrap_or(0)
+}
+
+/// Returns the lowest core of `cpuset`, if `core_id` isn't part of `cpuset`.
+/// Returns `None`, if the task may stay on `core_id` or if `cpuset` is empty.
+#[cfg_attr(not(feature = "smp"), allow(dead_code))]
+fn target_core(cpuset: CpuSet, core_id: CoreId) -> Option<CoreId> {
+	let allowed = 1u64
+		.checked_shl(core_id)
+		.is_some_and(|bit| cpuset & bit != 0);
+	if cpuset == 0 || allowed {
+		None
+	} else {
+		Some(cpuset.trailing_zeros())
+	}
+}
+
+/// Returns the set of cores, on which the task `id` may run, or `None` if the
+/// task doesn't exist.
+pub(crate) fn get_affinity(id: TaskId) -> Option<CpuSet> {
+	without_interrupts(|| {
+		get_task_handle(id)?;
+		Some(AFFINITIES.lock().get(&id).copied().unwrap_or(CpuSet::MAX) & online_cores())
+	})
+}
+
+/// Restricts the task `id` to the cores in `cpuset`. Cores, which aren't online,
+/// are ignored.
+///
+/// A task is migrated at the next voluntary point, i.e. if it yields the core
+/// or if it is woken up. Returns `EINVAL` if `cpuset` contains no online core
+/// or if `id` is an idle task and `ESRCH` if the task doesn't exist.
+pub(crate) fn set_affinity(id: TaskId, cpuset: CpuSet) -> Result<(), i32> {
+	let cpuset = cpuset & online_cores();
+	if cpuset == 0 {
+		return Err(crate::errno::EINVAL);
+	}
+
+	without_interrupts(|| {
+		let task = get_task_handle(id).ok_or(crate::errno::ESRCH)?;
+		if task.get_priority() == IDLE_PRIO {
+			return Err(crate::errno::EINVAL);
+		}
+
+		let mut affinities = AFFINITIES.lock();
+		if cpuset == online_cores() {
+			affinities.remove(&id);
+		} else {
+			affinities.insert(id, cpuset);
+		}
+		Ok(())
+	})
+}
+
 #[cfg(all(target_arch = "x86_64", feature = "common-os"))]
 pub(crate) static BOOT_ROOT_PAGE_TABLE: OnceCell<usize> = OnceCell::new();
 
@@ -1002,3 +1337,83 @@ pub(crate) fn get_root_page_table() -> usize {
 	let current_task_borrowed = core_scheduler().current_task.borrow_mut();
 	current_task_borrowed.root_page_table
 }
+
+/// Names the object of `fd1` also by `fd2` and returns the object, which was
+/// named by `fd2` before.
+fn dup_in_map(
+	map: &mut HashMap<FileDescriptor, Arc<dyn ObjectInterface>, RandomState>,
+	fd1: FileDescriptor,
+	fd2: FileDescriptor,
+) -> Result<Option<Arc<dyn ObjectInterface>>, IoError> {
+	let obj = map.get(&fd1).ok_or(IoError::EBADF)?.clone();
+	if fd2 < 0 {
+		return Err(IoError::EBADF);
+	}
+
+	Ok(map.insert(fd2, obj))
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[derive(Debug)]
+	struct Object;
+
+	impl ObjectInterface for Object {}
+
+	fn object_map() -> HashMap<FileDescriptor, Arc<dyn ObjectInterface>, RandomState> {
+		let mut map = HashMap::with_hasher(RandomState::with_seeds(0, 0, 0, 0));
+		map.insert(3, Arc::new(Object) as Arc<dyn ObjectInterface>);
+		map.insert(4, Arc::new(Object) as Arc<dyn ObjectInterface>);
+		map
+	}
+
+	#[test]
+	fn dup_to_same_fd() {
+		let mut map = object_map();
+		let obj = map[&3].clone();
+
+		// The object stays open, although it is replaced by itself.
+		let replaced = dup_in_map(&mut map, 3, 3).unwrap().unwrap();
+		assert!(Arc::ptr_eq(&replaced, &obj));
+		drop(replaced);
+		assert!(Arc::ptr_eq(&map[&3], &obj));
+		assert_eq!(Arc::strong_count(&obj), 2);
+	}
+
+	#[test]
+	fn dup_to_open_fd() {
+		let mut map = object_map();
+		let obj = map[&3].clone();
+		let target = map[&4].clone();
+
+		let replaced = dup_in_map(&mut map, 3, 4).unwrap().unwrap();
+		assert!(Arc::ptr_eq(&replaced, &target));
+		assert!(Arc::ptr_eq(&map[&4], &obj));
+		assert_eq!(Arc::strong_count(&obj), 3);
+
+		// The previous object of the target is released.
+		drop(replaced);
+		assert_eq!(Arc::strong_count(&target), 1);
+	}
+
+	#[test]
+	fn dup_invalid_fd() {
+		let mut map = object_map();
+
+		assert_eq!(dup_in_map(&mut map, 5, 6).err(), Some(IoError::EBADF));
+		assert_eq!(dup_in_map(&mut map, 3, -1).err(), Some(IoError::EBADF));
+		assert!(dup_in_map(&mut map, 3, 7).unwrap().is_none());
+		assert_eq!(map.len(), 3);
+	}
+
+	#[test]
+	fn select_target_core() {
+		assert_eq!(target_core(0b0110, 1), None);
+		assert_eq!(target_core(0b0110, 0), Some(1));
+		assert_eq!(target_core(0b0110, 3), Some(1));
+		assert_eq!(target_core(0b1000, 64), Some(3));
+		assert_eq!(target_core(0, 0), None);
+	}
+}
@@ -2,7 +2,7 @@
 
 #[cfg(not(feature = "common-os"))]
 use alloc::boxed::Box;
-use alloc::collections::{LinkedList, VecDeque};
+use alloc::collections::{BTreeMap, LinkedList, VecDeque};
 use alloc::rc::Rc;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -26,6 +26,7 @@ use crate::fd::stdio::*;
 use crate::fd::{
 	FileDescriptor, IoError, ObjectInterface, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
 };
+use crate::scheduler::timer_wheel::TimerWheel;
 use crate::scheduler::CoreId;
 use crate::{arch, env};
 
@@ -531,15 +532,24 @@ impl BlockedTask {
 }
 
 pub(crate) struct BlockedTaskQueue {
-	list: LinkedList<BlockedTask>,
+	tasks: BTreeMap<TaskId, BlockedTask>,
+	/// Timers of the blocked tasks.
+	///
+	/// Timers aren't removed, if a task is woken up before its wakeup time.
+	/// Hence, each expired timer is checked against the current wakeup time
+	/// of its task.
+	timers: TimerWheel<TaskId>,
+	/// Time of the next poll of the network interface. The network task
+	/// rearms it after each poll, so it is kept apart from the timer wheel.
 	#[cfg(any(feature = "tcp", feature = "udp"))]
 	network_wakeup_time: Option<u64>,
 }
 
 impl BlockedTaskQueue {
-	pub const fn new() -> Self {
+	pub fn new() -> Self {
 		Self {
-			list: LinkedList::new(),
+			tasks: BTreeMap::new(),
+			timers: TimerWheel::new(),
 			#[cfg(any(feature = "tcp", feature = "udp"))]
 			network_wakeup_time: None,
 		}
@@ -568,23 +578,36 @@ impl BlockedTaskQueue {
 		borrowed.status = TaskStatus::Ready;
 	}
 
-	#[cfg(any(feature = "tcp", feature = "udp"))]
-	pub fn add_network_timer(&mut self, wakeup_time: Option<u64>) {
-		self.network_wakeup_time = wakeup_time;
+	/// Returns the next deadline of the blocked tasks and the network interface.
+	pub fn next_deadline(&self) -> Option<u64> {
+		#[cfg(any(feature = "tcp", feature = "udp"))]
+		if let Some(wt) = self.network_wakeup_time {
+			let deadline = self.timers.next_deadline();
+			return Some(deadline.map_or(wt, |deadline| deadline.min(wt)));
+		}
 
-		let next = self.list.front().and_then(|t| t.wakeup_time);
+		self.timers.next_deadline()
+	}
 
-		let time = match (wakeup_time, next) {
-			(Some(a), Some(b)) => Some(a.min(b)),
-			(a, b) => a.or(b),
-		};
+	/// Programs the One-Shot Timer to fire at the next deadline.
+	fn set_oneshot_timer(&self) {
+		arch::set_oneshot_timer(self.next_deadline());
+	}
 
-		arch::set_oneshot_timer(time);
+	/// Replaces the time of the next poll of the network interface.
+	#[cfg(any(feature = "tcp", feature = "udp"))]
+	pub fn add_network_timer(&mut self, wakeup_time: Option<u64>) {
+		if self.network_wakeup_time == wakeup_time {
+			return;
+		}
+
+		self.network_wakeup_time = wakeup_time;
+		self.set_oneshot_timer();
 	}
 
 	/// Blocks the given task for `wakeup_time` ticks, or indefinitely if None is given.
 	pub fn add(&mut self, task: Rc<RefCell<Task>>, wakeup_time: Option<u64>) {
-		{
+		let id = {
 			// Set the task status to Blocked.
 			let mut borrowed = task.borrow_mut();
 			debug!("Blocking task {}", borrowed.id);
@@ -596,108 +619,26 @@ impl BlockedTaskQueue {
 				borrowed.id
 			);
 			borrowed.status = TaskStatus::Blocked;
-		}
+			borrowed.id
+		};
 
-		let new_node = BlockedTask::new(task, wakeup_time);
+		self.tasks.insert(id, BlockedTask::new(task, wakeup_time));
 
 		// Shall the task automatically be woken up after a certain time?
 		if let Some(wt) = wakeup_time {
-			let mut cursor = self.list.cursor_front_mut();
-			let set_oneshot_timer = || {
-				#[cfg(not(any(feature = "tcp", feature = "udp")))]
-				arch::set_oneshot_timer(wakeup_time);
-				#[cfg(any(feature = "tcp", feature = "udp"))]
-				match self.network_wakeup_time {
-					Some(time) => {
-						if time > wt {
-							arch::set_oneshot_timer(wakeup_time);
-						} else {
-							arch::set_oneshot_timer(self.network_wakeup_time);
-						}
-					}
-					_ => arch::set_oneshot_timer(wakeup_time),
-				}
-			};
-
-			while let Some(node) = cursor.current() {
-				let node_wakeup_time = node.wakeup_time;
-				if node_wakeup_time.is_none() || wt < node_wakeup_time.unwrap() {
-					cursor.insert_before(new_node);
-
-					set_oneshot_timer();
-					return;
-				}
-
-				cursor.move_next();
-			}
-
-			set_oneshot_timer();
+			self.timers.insert(wt, id);
+			self.set_oneshot_timer();
 		}
-
-		self.list.push_back(new_node);
 	}
 
 	/// Manually wake up a blocked task.
 	pub fn custom_wakeup(&mut self, task: TaskHandle) -> Rc<RefCell<Task>> {
-		let mut first_task = true;
-		let mut cursor = self.list.cursor_front_mut();
-
-		#[cfg(any(feature = "tcp", feature = "udp"))]
-		if let Some(wakeup_time) = self.network_wakeup_time {
-			if wakeup_time <= arch::processor::get_timer_ticks() {
-				self.network_wakeup_time = None;
-			}
-		}
-
-		// Loop through all blocked tasks to find it.
-		while let Some(node) = cursor.current() {
-			if node.task.borrow().id == task.get_id() {
-				// Remove it from the list of blocked tasks.
-				let task_ref = node.task.clone();
-				cursor.remove_current();
-
-				// If this is the first task, adjust the One-Shot Timer to fire at the
-				// next task's wakeup time (if any).
-				#[cfg(any(feature = "tcp", feature = "udp"))]
-				if first_task {
-					arch::set_oneshot_timer(cursor.current().map_or_else(
-						|| self.network_wakeup_time,
-						|node| match node.wakeup_time {
-							Some(wt) => {
-								if let Some(timer) = self.network_wakeup_time {
-									if wt < timer {
-										Some(wt)
-									} else {
-										Some(timer)
-									}
-								} else {
-									Some(wt)
-								}
-							}
-							None => self.network_wakeup_time,
-						},
-					));
-				}
-				#[cfg(not(any(feature = "tcp", feature = "udp")))]
-				if first_task {
-					arch::set_oneshot_timer(
-						cursor
-							.current()
-							.map_or_else(|| None, |node| node.wakeup_time),
-					);
-				}
-
-				// Wake it up.
-				Self::wakeup_task(task_ref.clone());
+		let node = self.tasks.remove(&task.get_id()).unwrap();
 
-				return task_ref;
-			}
-
-			first_task = false;
-			cursor.move_next();
-		}
+		// Wake it up.
+		Self::wakeup_task(node.task.clone());
 
-		unreachable!();
+		node.task
 	}
 
 	/// Wakes up all tasks whose wakeup time has elapsed.
@@ -717,47 +658,25 @@ impl BlockedTaskQueue {
 			}
 		}
 
+		#[cfg(any(feature = "tcp", feature = "udp"))]
+		if self.network_wakeup_time.is_some_and(|wt| wt <= time) {
+			self.network_wakeup_time = None;
+		}
+
 		let mut tasks = vec![];
 
-		// Loop through all blocked tasks.
-		let mut cursor = self.list.cursor_front_mut();
-		while let Some(node) = cursor.current() {
-			// Get the wakeup time of this task and check if we have reached the first task
-			// that hasn't elapsed yet or waits indefinitely.
-			let node_wakeup_time = node.wakeup_time;
-			if node_wakeup_time.is_none() || time < node_wakeup_time.unwrap() {
-				break;
+		for (deadline, id) in self.timers.expire(time) {
+			// ignore timers of tasks, which are already woken up or blocked again
+			if self
+				.tasks
+				.get(&id)
+				.is_some_and(|node| node.wakeup_time == Some(deadline))
+			{
+				tasks.push(self.tasks.remove(&id).unwrap().task);
 			}
-
-			// Otherwise, this task has elapsed, so remove it from the list and wake it up.
-			tasks.push(node.task.clone());
-			cursor.remove_current();
 		}
 
-		#[cfg(any(feature = "tcp", feature = "udp"))]
-		arch::set_oneshot_timer(cursor.current().map_or_else(
-			|| self.network_wakeup_time,
-			|node| match node.wakeup_time {
-				Some(wt) => {
-					if let Some(timer) = self.network_wakeup_time {
-						if wt < timer {
-							Some(wt)
-						} else {
-							Some(timer)
-						}
-					} else {
-						Some(wt)
-					}
-				}
-				None => self.network_wakeup_time,
-			},
-		));
-		#[cfg(not(any(feature = "tcp", feature = "udp")))]
-		arch::set_oneshot_timer(
-			cursor
-				.current()
-				.map_or_else(|| None, |node| node.wakeup_time),
-		);
+		self.set_oneshot_timer();
 
 		for task in tasks.iter().cloned() {
 			Self::wakeup_task(task);
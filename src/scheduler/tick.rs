@@ -0,0 +1,78 @@
+//! Granularity of the timer.
+//!
+//! Hermit is tickless and programs the one-shot timer to the exact deadline. If
+//! `HERMIT_TICK_HZ` is set, deadlines are rounded up to the next tick of the given
+//! frequency. Thereby, timers are coalesced and idle guests wake up less often.
+//! The tick period is also reported as resolution by `sys_clock_getres`.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Lowest supported tick frequency in Hz
+const MIN_HZ: u64 = 100;
+/// Highest supported tick frequency in Hz
+const MAX_HZ: u64 = 10_000;
+
+/// Tick period in microseconds. Zero keeps the timer tickless.
+static PERIOD: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the tick period in microseconds of `hz`, which is clamped to the supported range.
+fn period(hz: u64) -> u64 {
+	1_000_000 / hz.clamp(MIN_HZ, MAX_HZ)
+}
+
+/// Rounds `wakeup_time` up to the next multiple of `period`.
+fn round_up(wakeup_time: u64, period: u64) -> u64 {
+	wakeup_time.div_ceil(period).saturating_mul(period)
+}
+
+pub(crate) fn init() {
+	let Some(hz) = hermit_var!("HERMIT_TICK_HZ") else {
+		return;
+	};
+
+	let Ok(hz) = hz.parse::<u64>() else {
+		warn!("Invalid HERMIT_TICK_HZ {hz:?}, keep the timer tickless");
+		return;
+	};
+
+	if !(MIN_HZ..=MAX_HZ).contains(&hz) {
+		warn!("HERMIT_TICK_HZ {hz} is out of range, clamp it to {MIN_HZ}..={MAX_HZ} Hz");
+	}
+
+	let period = period(hz);
+	PERIOD.store(period, Ordering::Relaxed);
+	info!("Timer ticks with {} Hz", 1_000_000 / period);
+}
+
+/// Returns the resolution of the timer in microseconds.
+pub(crate) fn resolution() -> u64 {
+	PERIOD.load(Ordering::Relaxed).max(1)
+}
+
+/// Returns the wakeup time of the one-shot timer, which is aligned to the tick.
+#[inline]
+pub(crate) fn align(wakeup_time: Option<u64>) -> Option<u64> {
+	let period = PERIOD.load(Ordering::Relaxed);
+	if period == 0 {
+		return wakeup_time;
+	}
+
+	wakeup_time.map(|wakeup_time| round_up(wakeup_time, period))
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tick_period() {
+		assert_eq!(period(1000), 1000);
+		assert_eq!(period(10), 10_000);
+		assert_eq!(period(1_000_000), 100);
+
+		assert_eq!(round_up(0, 1000), 0);
+		assert_eq!(round_up(1, 1000), 1000);
+		assert_eq!(round_up(2000, 1000), 2000);
+		assert_eq!(round_up(2001, 1000), 3000);
+	}
+}
@@ -0,0 +1,231 @@
+//! Hierarchical timer wheel, which stores timers by their deadline in microseconds.
+//!
+//! The wheel consists of [`LEVELS`] levels with 64 slots each. A slot of level `n`
+//! covers `64^n` microseconds. A timer is stored in the lowest level, whose slot
+//! range still contains the deadline. Hence, inserting a timer is O(1). While the
+//! time elapses, the timers of a slot are either expired or moved to a lower
+//! level. Each timer moves at most once per level.
+//!
+//! The wheel doesn't support the removal of timers. Users have to check on
+//! expiration, whether a timer is still valid.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Number of bits of the deadline, which are covered by a single level
+const LEVEL_BITS: u32 = 6;
+/// Number of slots per level
+const SLOTS: usize = 1 << LEVEL_BITS;
+/// Number of levels, which are required to cover the whole range of `u64`
+const LEVELS: usize = u64::BITS.div_ceil(LEVEL_BITS) as usize;
+
+struct Level<T> {
+	/// Bitmap of the slots, which contain at least one timer
+	occupied: u64,
+	slots: [Vec<(u64, T)>; SLOTS],
+}
+
+impl<T> Level<T> {
+	fn new() -> Self {
+		Self {
+			occupied: 0,
+			slots: core::array::from_fn(|_| Vec::new()),
+		}
+	}
+}
+
+/// Returns the number of microseconds, which are covered by a slot of `level`.
+fn slot_range(level: usize) -> u64 {
+	1u64 << (level as u32 * LEVEL_BITS)
+}
+
+/// Returns the number of microseconds, which are covered by all slots of `level`.
+/// The highest level covers the whole range and returns `None`.
+fn level_range(level: usize) -> Option<u64> {
+	1u64.checked_shl((level as u32 + 1) * LEVEL_BITS)
+}
+
+/// Returns the level of a timer with the deadline `deadline`, if the timers
+/// of the wheel are processed until `elapsed`.
+fn level_for(elapsed: u64, deadline: u64) -> usize {
+	// the lowest level is used, if both times share the same slot of level 0
+	let masked = (elapsed ^ deadline) | (SLOTS as u64 - 1);
+	let significant = u64::BITS - 1 - masked.leading_zeros();
+	(significant / LEVEL_BITS) as usize
+}
+
+/// Returns the slot of `deadline` within `level`.
+fn slot_for(level: usize, deadline: u64) -> usize {
+	((deadline >> (level as u32 * LEVEL_BITS)) & (SLOTS as u64 - 1)) as usize
+}
+
+pub(crate) struct TimerWheel<T> {
+	/// Point in time, until which all timers have been processed
+	elapsed: u64,
+	levels: Box<[Level<T>]>,
+}
+
+impl<T> TimerWheel<T> {
+	pub fn new() -> Self {
+		Self {
+			elapsed: 0,
+			levels: (0..LEVELS).map(|_| Level::new()).collect(),
+		}
+	}
+
+	/// Inserts a timer, which expires at `deadline`. A deadline in the past expires
+	/// with the next call of [`expire`](Self::expire).
+	pub fn insert(&mut self, deadline: u64, value: T) {
+		let position = deadline.max(self.elapsed);
+		let level = level_for(self.elapsed, position);
+		let slot = slot_for(level, position);
+
+		let level = &mut self.levels[level];
+		level.slots[slot].push((deadline, value));
+		level.occupied |= 1 << slot;
+	}
+
+	/// Returns the level, the slot and the start time of the next occupied slot.
+	fn next_slot(&self) -> Option<(usize, usize, u64)> {
+		self.levels.iter().enumerate().find_map(|(level, slots)| {
+			// all timers of a level are located at or behind the current slot
+			let current = slot_for(level, self.elapsed);
+			let occupied = slots.occupied & (u64::MAX << current);
+			if occupied == 0 {
+				return None;
+			}
+
+			let slot = occupied.trailing_zeros() as usize;
+			let level_start = level_range(level).map_or(0, |range| self.elapsed & !(range - 1));
+			Some((level, slot, level_start + slot as u64 * slot_range(level)))
+		})
+	}
+
+	/// Returns a point in time, at which [`expire`](Self::expire) has to be called next.
+	///
+	/// The returned time may be earlier than the earliest deadline. In this case,
+	/// the call of [`expire`](Self::expire) moves timers to lower levels.
+	pub fn next_deadline(&self) -> Option<u64> {
+		self.next_slot().map(|(_, _, start)| start)
+	}
+
+	/// Removes all timers, whose deadline is at or before `now`, and returns
+	/// them together with their deadlines.
+	pub fn expire(&mut self, now: u64) -> Vec<(u64, T)> {
+		let mut expired = Vec::new();
+
+		while let Some((level, slot, start)) = self.next_slot() {
+			if start > now {
+				break;
+			}
+
+			self.elapsed = self.elapsed.max(start);
+			let level = &mut self.levels[level];
+			level.occupied &= !(1 << slot);
+			let timers = mem::take(&mut level.slots[slot]);
+
+			for (deadline, value) in timers {
+				if deadline <= now {
+					expired.push((deadline, value));
+				} else {
+					self.insert(deadline, value);
+				}
+			}
+		}
+
+		self.elapsed = self.elapsed.max(now);
+
+		expired
+	}
+
+	/// Returns `true`, if the wheel doesn't contain any timer.
+	pub fn is_empty(&self) -> bool {
+		self.levels.iter().all(|level| level.occupied == 0)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn expire_in_order() {
+		let mut wheel = TimerWheel::new();
+		wheel.insert(100, 'b');
+		wheel.insert(5, 'a');
+		wheel.insert(1_000_000, 'c');
+		wheel.insert(u64::MAX, 'd');
+
+		assert_eq!(wheel.next_deadline(), Some(5));
+		assert_eq!(wheel.expire(4), []);
+		assert_eq!(wheel.expire(100), [(5, 'a'), (100, 'b')]);
+		assert!(wheel.next_deadline().unwrap() <= 1_000_000);
+		assert_eq!(wheel.expire(999_999), []);
+		assert_eq!(wheel.expire(1_000_000), [(1_000_000, 'c')]);
+		assert_eq!(wheel.expire(u64::MAX), [(u64::MAX, 'd')]);
+		assert!(wheel.is_empty());
+		assert_eq!(wheel.next_deadline(), None);
+	}
+
+	#[test]
+	fn deadline_in_the_past() {
+		let mut wheel = TimerWheel::new();
+		assert_eq!(wheel.expire(1000), []);
+
+		wheel.insert(10, ());
+		assert_eq!(wheel.next_deadline(), Some(1000));
+		assert_eq!(wheel.expire(1000), [(10, ())]);
+	}
+
+	/// Inserts and expires 10k timers, which are spread over several levels.
+	#[test]
+	fn expire_many_timers() {
+		const TIMERS: u64 = 10_000;
+
+		let mut wheel = TimerWheel::new();
+		for i in 0..TIMERS {
+			// pseudo random order of the deadlines
+			wheel.insert((i * 7919) % TIMERS * 97, i);
+		}
+
+		let mut expired = 0;
+		let mut last = 0;
+		while let Some(deadline) = wheel.next_deadline() {
+			for (deadline, _) in wheel.expire(deadline) {
+				assert!(deadline >= last);
+				last = deadline;
+				expired += 1;
+			}
+		}
+
+		assert_eq!(expired, TIMERS);
+		assert!(wheel.is_empty());
+	}
+
+	/// Benchmark of inserting and expiring 10k timers. Run it by
+	/// `cargo test bench_many_timers -- --ignored --nocapture`.
+	#[test]
+	#[ignore]
+	fn bench_many_timers() {
+		const TIMERS: u64 = 10_000;
+
+		let start = std::time::Instant::now();
+		let mut wheel = TimerWheel::new();
+		for i in 0..TIMERS {
+			wheel.insert((i * 7919) % TIMERS * 97, i);
+		}
+		let inserted = start.elapsed();
+
+		let mut expired = 0;
+		while let Some(deadline) = wheel.next_deadline() {
+			expired += wheel.expire(deadline).len();
+		}
+		println!(
+			"Inserted {TIMERS} timers in {inserted:?} and expired them in {:?}",
+			start.elapsed() - inserted
+		);
+
+		assert_eq!(expired, TIMERS as usize);
+	}
+}
@@ -0,0 +1,210 @@
+//! Watchdog, which detects cores that stopped making progress.
+//!
+//! Each core records a heartbeat whenever its scheduler runs. If the watchdog is
+//! enabled via `HERMIT_WATCHDOG_MS`, the one-shot timer of each core fires at least
+//! twice per window and the timer interrupt checks the heartbeats of all other cores.
+//! A stalled core is reported and, if `HERMIT_WATCHDOG_PANIC` is set, the kernel panics.
+//! On x86_64, the stalled core is interrupted by an NMI and logs the backtrace of its task.
+//!
+//! A core cannot detect its own stall. Hence, at least two cores are required.
+//!
+//! Sections, which legitimately run for a long time without reaching the
+//! scheduler (e.g. with disabled interrupts), have to be wrapped by [`exempt`].
+
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use hermit_sync::OnceCell;
+
+use crate::arch;
+use crate::arch::core_local::core_id;
+
+/// Heartbeat of a core, which is idle or exempted from the watchdog
+const PARKED: u64 = u64::MAX;
+/// Time in microseconds, which a stalled core gets to log its backtrace
+#[cfg(all(target_arch = "x86_64", feature = "smp"))]
+const BACKTRACE_TIMEOUT: u64 = 100_000;
+
+/// Window in microseconds, in which each core has to make progress. Zero disables the watchdog.
+static WINDOW: AtomicU64 = AtomicU64::new(0);
+/// Panic, if a stall is detected
+static PANIC: AtomicBool = AtomicBool::new(false);
+/// Time stamp of the last heartbeat of each core
+static HEARTBEATS: OnceCell<Box<[AtomicU64]>> = OnceCell::new();
+/// Cores, which are requested to log the backtrace of their current task
+static BACKTRACE_REQUESTS: OnceCell<Box<[AtomicBool]>> = OnceCell::new();
+
+pub(crate) fn init() {
+	let window_ms = hermit_var!("HERMIT_WATCHDOG_MS")
+		.and_then(|ms| ms.parse::<u64>().ok())
+		.unwrap_or(0);
+	if window_ms == 0 {
+		return;
+	}
+
+	let heartbeats = (0..arch::get_processor_count())
+		.map(|_| AtomicU64::new(PARKED))
+		.collect();
+	HEARTBEATS.set(heartbeats).unwrap();
+	let requests = (0..arch::get_processor_count())
+		.map(|_| AtomicBool::new(false))
+		.collect();
+	BACKTRACE_REQUESTS.set(requests).unwrap();
+	PANIC.store(
+		hermit_var!("HERMIT_WATCHDOG_PANIC").is_some(),
+		Ordering::Relaxed,
+	);
+	WINDOW.store(window_ms * 1000, Ordering::Release);
+
+	info!("Watchdog enabled with a window of {window_ms} ms");
+}
+
+fn heartbeats() -> Option<&'static [AtomicU64]> {
+	if WINDOW.load(Ordering::Acquire) == 0 {
+		None
+	} else {
+		HEARTBEATS.get().map(|heartbeats| &heartbeats[..])
+	}
+}
+
+/// Records that the current core made progress.
+#[inline]
+pub(crate) fn heartbeat() {
+	if let Some(heartbeats) = heartbeats() {
+		heartbeats[core_id() as usize].store(arch::processor::get_timer_ticks(), Ordering::Relaxed);
+	}
+}
+
+/// Excludes the current core from the watchdog until the next heartbeat.
+#[inline]
+fn park() {
+	if let Some(heartbeats) = heartbeats() {
+		heartbeats[core_id() as usize].store(PARKED, Ordering::Relaxed);
+	}
+}
+
+/// Runs `f` without monitoring of the current core.
+///
+/// Required for sections, which legitimately block the scheduler of
+/// the core longer than the watchdog window, e.g. the idle loop, which
+/// halts the core.
+pub fn exempt<R>(f: impl FnOnce() -> R) -> R {
+	park();
+	let result = f();
+	heartbeat();
+	result
+}
+
+/// Returns the wakeup time of the one-shot timer, which ensures that the
+/// heartbeats are checked at least twice per window.
+#[inline]
+pub(crate) fn arm(wakeup_time: Option<u64>) -> Option<u64> {
+	let window = WINDOW.load(Ordering::Acquire);
+	if window == 0 {
+		return wakeup_time;
+	}
+
+	let check = arch::processor::get_timer_ticks() + window / 2;
+	Some(wakeup_time.map_or(check, |wakeup_time| wakeup_time.min(check)))
+}
+
+/// Returns how long a core didn't make progress, if it exceeds `window`.
+fn stall_duration(last_heartbeat: u64, now: u64, window: u64) -> Option<u64> {
+	if last_heartbeat == PARKED {
+		return None;
+	}
+
+	let duration = now.checked_sub(last_heartbeat)?;
+	(duration > window).then_some(duration)
+}
+
+/// Checks the heartbeats of all other cores. Called by the timer interrupt.
+pub(crate) fn check() {
+	let Some(heartbeats) = heartbeats() else {
+		return;
+	};
+
+	let window = WINDOW.load(Ordering::Acquire);
+	let now = arch::processor::get_timer_ticks();
+	let current = core_id() as usize;
+
+	for (core, heartbeat) in heartbeats.iter().enumerate() {
+		if core == current {
+			continue;
+		}
+
+		if let Some(duration) = stall_duration(heartbeat.load(Ordering::Relaxed), now, window) {
+			error!(
+				"Watchdog: core {core} made no progress for {} ms",
+				duration / 1000
+			);
+			request_backtrace(core);
+			if PANIC.load(Ordering::Relaxed) {
+				panic!("Watchdog detected a stall of core {core}");
+			}
+
+			// report the stall only once per window
+			heartbeat.store(now, Ordering::Relaxed);
+		}
+	}
+}
+
+/// Interrupts a stalled core by a non-maskable interrupt, whose handler logs
+/// the backtrace of the stalled task (see [`handle_backtrace_request`]).
+///
+/// Other architectures don't provide such an interrupt. Hence, only the stall
+/// itself is reported.
+fn request_backtrace(core: usize) {
+	#[cfg(all(target_arch = "x86_64", feature = "smp"))]
+	if let Some(requests) = BACKTRACE_REQUESTS.get() {
+		requests[core].store(true, Ordering::Release);
+		arch::kernel::apic::ipi_nmi(core.try_into().unwrap());
+		// give the stalled core the chance to log its backtrace before a panic
+		let start = arch::processor::get_timer_ticks();
+		while requests[core].load(Ordering::Acquire)
+			&& arch::processor::get_timer_ticks() - start < BACKTRACE_TIMEOUT
+		{
+			core::hint::spin_loop();
+		}
+	}
+
+	#[cfg(not(all(target_arch = "x86_64", feature = "smp")))]
+	let _ = core;
+}
+
+/// Calls `log`, if the watchdog requested the current core to log its backtrace,
+/// and returns `true` in this case. Called by the handler of the non-maskable interrupt.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn handle_backtrace_request(log: impl FnOnce()) -> bool {
+	let Some(requests) = BACKTRACE_REQUESTS.get() else {
+		return false;
+	};
+
+	let request = &requests[core_id() as usize];
+	if !request.load(Ordering::Acquire) {
+		return false;
+	}
+
+	log();
+	request.store(false, Ordering::Release);
+	true
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn detect_stall() {
+		assert_eq!(stall_duration(1000, 1500, 1000), None);
+		assert_eq!(stall_duration(1000, 2000, 1000), None);
+		assert_eq!(stall_duration(1000, 2001, 1000), Some(1001));
+	}
+
+	#[test]
+	fn ignore_parked_core() {
+		assert_eq!(stall_duration(PARKED, 5000, 1000), None);
+		// heartbeat, which was recorded after the time stamp of the check
+		assert_eq!(stall_duration(3000, 2000, 1000), None);
+	}
+}
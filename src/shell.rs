@@ -1,15 +1,283 @@
-use hermit_sync::Lazy;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::future::{self, Future};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::Poll;
+
+use hermit_sync::{InterruptTicketMutex, Lazy};
 use simple_shell::*;
 
 use crate::arch::kernel::COM1;
 use crate::interrupts::print_statistics;
 
+/// Processing of the console input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputMode {
+	/// Received bytes are passed unmodified to the shell
+	Raw,
+	/// Lines are edited and buffered before they are passed to the shell
+	Cooked,
+}
+
+/// Reaction of the console on a received byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Echo {
+	None,
+	Byte(u8),
+	/// Erases the last character on the terminal
+	Erase,
+}
+
+/// Line discipline of the serial console
+struct LineDiscipline {
+	mode: InputMode,
+	echo: bool,
+	/// Line, which is currently edited
+	line: Vec<u8>,
+	/// Completed line, which isn't consumed by the shell yet
+	pending: VecDeque<u8>,
+	/// Previous byte was a carriage return
+	after_cr: bool,
+}
+
+impl LineDiscipline {
+	const fn new() -> Self {
+		Self {
+			mode: InputMode::Cooked,
+			echo: true,
+			line: Vec::new(),
+			pending: VecDeque::new(),
+			after_cr: false,
+		}
+	}
+
+	/// Processes a byte in cooked mode. CR, LF and CRLF complete the
+	/// current line, backspace and delete erase the last character and
+	/// all other control bytes are ignored.
+	fn input(&mut self, byte: u8) -> Echo {
+		let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+
+		match byte {
+			b'\n' if after_cr => Echo::None,
+			b'\r' | b'\n' => {
+				self.pending.extend(self.line.drain(..));
+				self.pending.push_back(b'\n');
+				// the shell prints the line break
+				Echo::None
+			}
+			0x08 | 0x7f => {
+				if self.line.pop().is_some() && self.echo {
+					Echo::Erase
+				} else {
+					Echo::None
+				}
+			}
+			byte if byte.is_ascii_control() => Echo::None,
+			byte => {
+				self.line.push(byte);
+				if self.echo {
+					Echo::Byte(byte)
+				} else {
+					Echo::None
+				}
+			}
+		}
+	}
+}
+
+static LINE_DISCIPLINE: InterruptTicketMutex<LineDiscipline> =
+	InterruptTicketMutex::new(LineDiscipline::new());
+
+/// Interval (in microseconds), in which an idle console is polled
+const IDLE_POLL_INTERVAL: u64 = 10_000;
+
+/// Time stamp, before which the shell isn't polled again, because
+/// no input is available
+static NEXT_POLL: AtomicU64 = AtomicU64::new(0);
+
+/// Set, if the shell consumes the console input. Otherwise, the
+/// input is passed to the application via stdin.
+static OWNS_INPUT: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true`, if the shell consumes the console input.
+pub(crate) fn owns_input() -> bool {
+	OWNS_INPUT.load(Ordering::Relaxed)
+}
+
+/// Signals that new input is available. Called by the interrupt handler
+/// of the serial port.
+pub(crate) fn wakeup() {
+	NEXT_POLL.store(0, Ordering::Relaxed);
+}
+
 fn read() -> Option<u8> {
-	COM1.lock().as_mut().map(|s| s.read())?
+	let mut discipline = LINE_DISCIPLINE.lock();
+	if let Some(byte) = discipline.pending.pop_front() {
+		return Some(byte);
+	}
+
+	let Some(byte) = COM1.lock().as_mut().and_then(|s| s.read()) else {
+		NEXT_POLL.store(
+			crate::arch::processor::get_timer_ticks() + IDLE_POLL_INTERVAL,
+			Ordering::Relaxed,
+		);
+		return None;
+	};
+	match discipline.mode {
+		InputMode::Raw => Some(byte),
+		InputMode::Cooked => {
+			match discipline.input(byte) {
+				Echo::None => {}
+				Echo::Byte(byte) => print!("{}", char::from(byte)),
+				Echo::Erase => print!("\x08 \x08"),
+			}
+			discipline.pending.pop_front()
+		}
+	}
+}
+
+fn print(s: &str) {
+	// In cooked mode, the line is already echoed while it is edited.
+	// Suppress the echo of the shell while it consumes the line.
+	if LINE_DISCIPLINE.lock().pending.is_empty() {
+		print!("{}", s);
+	}
+}
+
+fn set_input_mode(mode: InputMode) {
+	let mut discipline = LINE_DISCIPLINE.lock();
+	discipline.mode = mode;
+	discipline.line.clear();
+	discipline.after_cr = false;
+}
+
+/// Parses a hexadecimal (prefixed by `0x`) or decimal number.
+#[cfg(feature = "debug-shell")]
+fn parse_number(s: &str) -> Option<u64> {
+	match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+		Some(hex) => u64::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+/// Checks that all pages of the memory area `[addr, addr + len)` are mapped.
+#[cfg(feature = "debug-shell")]
+fn is_mapped(addr: u64, len: u64) -> bool {
+	use crate::arch::mm::paging::{virtual_to_physical, BasePageSize, PageSize};
+	use crate::arch::mm::VirtAddr;
+
+	let Some(end) = addr.checked_add(len) else {
+		return false;
+	};
+	let mut page = addr & !(BasePageSize::SIZE - 1);
+	while page < end {
+		if virtual_to_physical(VirtAddr(page)).is_none() {
+			return false;
+		}
+		page += BasePageSize::SIZE;
+	}
+
+	true
+}
+
+/// Maximal number of bytes, which are dumped by `peek`
+#[cfg(feature = "debug-shell")]
+const PEEK_MAX_LEN: u64 = 4096;
+
+/// Prints a hexdump of the virtual memory area `[addr, addr + len)`.
+#[cfg(feature = "debug-shell")]
+fn peek(args: &[&str]) -> Result<(), &'static str> {
+	let addr = args
+		.first()
+		.and_then(|arg| parse_number(arg))
+		.ok_or("usage: peek <addr> [len]")?;
+	let len = match args.get(1) {
+		Some(arg) => parse_number(arg).ok_or("usage: peek <addr> [len]")?,
+		None => 64,
+	}
+	.min(PEEK_MAX_LEN);
+
+	if !is_mapped(addr, len) {
+		return Err("address range is not mapped");
+	}
+
+	for line in (addr..addr + len).step_by(16) {
+		let bytes: Vec<u8> = (line..(line + 16).min(addr + len))
+			.map(|addr| unsafe { core::ptr::read_volatile(addr as *const u8) })
+			.collect();
+
+		print!("{line:#018x}:");
+		for byte in &bytes {
+			print!(" {byte:02x}");
+		}
+		for _ in bytes.len()..16 {
+			print!("   ");
+		}
+		print!("  |");
+		for byte in &bytes {
+			let c = if byte.is_ascii_graphic() {
+				char::from(*byte)
+			} else {
+				'.'
+			};
+			print!("{c}");
+		}
+		println!("|");
+	}
+
+	Ok(())
+}
+
+/// Writes `value` with a size of 1, 2, 4 or 8 bytes to the virtual address `addr`.
+#[cfg(feature = "debug-shell")]
+fn poke(args: &[&str]) -> Result<(), &'static str> {
+	const USAGE: &str = "usage: poke <addr> <value> [1|2|4|8]";
+
+	let addr = args
+		.first()
+		.and_then(|arg| parse_number(arg))
+		.ok_or(USAGE)?;
+	let value = args.get(1).and_then(|arg| parse_number(arg)).ok_or(USAGE)?;
+	let size = match args.get(2) {
+		Some(arg) => parse_number(arg).ok_or(USAGE)?,
+		None => 1,
+	};
+
+	if !matches!(size, 1 | 2 | 4 | 8) {
+		return Err(USAGE);
+	}
+	if addr % size != 0 {
+		return Err("address is not aligned to the size of the value");
+	}
+	if size < 8 && value >> (size * 8) != 0 {
+		return Err("value is too large");
+	}
+	if !is_mapped(addr, size) {
+		return Err("address is not mapped");
+	}
+
+	unsafe {
+		match size {
+			1 => core::ptr::write_volatile(addr as *mut u8, value as u8),
+			2 => core::ptr::write_volatile(addr as *mut u16, value as u16),
+			4 => core::ptr::write_volatile(addr as *mut u32, value as u32),
+			_ => core::ptr::write_volatile(addr as *mut u64, value),
+		}
+	}
+
+	Ok(())
 }
 
 static mut SHELL: Lazy<Shell<'_>> = Lazy::new(|| {
-	let (print, read) = (|s: &str| print!("{}", s), read);
+	{
+		let mut discipline = LINE_DISCIPLINE.lock();
+		if hermit_var_or!("HERMIT_SHELL_MODE", "cooked") == "raw" {
+			discipline.mode = InputMode::Raw;
+		}
+		discipline.echo = hermit_var_or!("HERMIT_SHELL_ECHO", "1") != "0";
+	}
+
 	let mut shell = Shell::new(print, read);
 
 	shell.commands.insert(
@@ -34,6 +302,109 @@ static mut SHELL: Lazy<Shell<'_>> = Lazy::new(|| {
 			aliases: &["i"],
 		},
 	);
+	shell.commands.insert(
+		"raw",
+		ShellCommand {
+			help: "Pass the console input unmodified to the shell",
+			func: |_, shell| {
+				set_input_mode(InputMode::Raw);
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
+	shell.commands.insert(
+		"cooked",
+		ShellCommand {
+			help: "Edit lines of the console input before passing them to the shell",
+			func: |_, shell| {
+				set_input_mode(InputMode::Cooked);
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
+	shell.commands.insert(
+		"echo",
+		ShellCommand {
+			help: "Toggle the echo of typed characters in cooked mode",
+			func: |_, shell| {
+				let mut discipline = LINE_DISCIPLINE.lock();
+				discipline.echo = !discipline.echo;
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
+	#[cfg(feature = "debug-shell")]
+	shell.commands.insert(
+		"peek",
+		ShellCommand {
+			help: "Print a hexdump of virtual memory: peek <addr> [len]",
+			func: |args, shell| peek(args),
+			aliases: &[],
+		},
+	);
+	#[cfg(feature = "debug-shell")]
+	shell.commands.insert(
+		"poke",
+		ShellCommand {
+			help: "Write to virtual memory: poke <addr> <value> [1|2|4|8]",
+			func: |args, shell| poke(args),
+			aliases: &[],
+		},
+	);
+	#[cfg(any(feature = "tcp", feature = "udp"))]
+	shell.commands.insert(
+		"sockets",
+		ShellCommand {
+			help: "Shows the routing table and all sockets",
+			func: |_, shell| {
+				match crate::executor::network::NIC.lock().as_nic_mut() {
+					Ok(nic) => nic.print_sockets(),
+					Err(err) => println!("{err}"),
+				}
+				Ok(())
+			},
+			aliases: &["ss"],
+		},
+	);
+	#[cfg(any(feature = "tcp", feature = "udp"))]
+	shell.commands.insert(
+		"netstat",
+		ShellCommand {
+			help: "Shows the packet and error counters of the network interface",
+			func: |_, shell| {
+				crate::executor::device::print_statistics();
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
+	#[cfg(feature = "virtio-latency")]
+	shell.commands.insert(
+		"vqlatency",
+		ShellCommand {
+			help: "Shows the latency histograms of the virtqueues",
+			func: |_, shell| {
+				print_latency_histograms();
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
+	#[cfg(feature = "pcap")]
+	shell.commands.insert(
+		"pcap",
+		ShellCommand {
+			help: "Prints the captured frames as hex dump of a pcap file",
+			func: |_, shell| {
+				crate::executor::pcap::print();
+				Ok(())
+			},
+			aliases: &[],
+		},
+	);
 	shell.commands.insert(
 		"shutdown",
 		ShellCommand {
@@ -49,7 +420,87 @@ static mut SHELL: Lazy<Shell<'_>> = Lazy::new(|| {
 	shell
 });
 
+/// Prints the latency histograms of the virtqueues of the network and the
+/// filesystem device.
+#[cfg(feature = "virtio-latency")]
+fn print_latency_histograms() {
+	#[cfg(all(
+		any(feature = "tcp", feature = "udp"),
+		not(any(feature = "rtl8139", feature = "gem-net"))
+	))]
+	{
+		#[cfg(not(feature = "pci"))]
+		use crate::drivers::mmio::get_network_driver;
+		#[cfg(feature = "pci")]
+		use crate::drivers::pci::get_network_driver;
+
+		if let Some(driver) = get_network_driver() {
+			for (index, histogram) in driver.lock().latency_histograms() {
+				println!("virtio-net vq {}:\n{histogram}", u16::from(index));
+			}
+		}
+	}
+
+	#[cfg(feature = "fuse")]
+	if let Some(driver) = crate::drivers::pci::get_filesystem_driver() {
+		for (index, histogram) in driver.lock().latency_histograms() {
+			println!("virtio-fs vq {}:\n{histogram}", u16::from(index));
+		}
+	}
+}
+
 pub(crate) fn init() {
+	// The console input is owned either by the shell or by the application.
+	if hermit_var_or!("HERMIT_CONSOLE_INPUT", "shell") == "app" {
+		info!("Pass the console input to the application, the shell is disabled");
+		return;
+	}
+	OWNS_INPUT.store(true, Ordering::Relaxed);
+
 	// Also supports async
-	crate::executor::spawn(unsafe { SHELL.run_async() });
+	let mut shell = Box::pin(unsafe { SHELL.run_async() });
+
+	// Don't poll an idle console on every run of the executor.
+	crate::executor::spawn(future::poll_fn(move |cx| {
+		if crate::arch::processor::get_timer_ticks() < NEXT_POLL.load(Ordering::Relaxed) {
+			return Poll::Pending;
+		}
+
+		shell.as_mut().poll(cx)
+	}));
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	fn feed(discipline: &mut LineDiscipline, input: &[u8]) -> Vec<u8> {
+		for byte in input {
+			discipline.input(*byte);
+		}
+		discipline.pending.drain(..).collect()
+	}
+
+	#[cfg(feature = "debug-shell")]
+	#[test]
+	fn parse_addresses() {
+		assert_eq!(parse_number("0x1000"), Some(0x1000));
+		assert_eq!(parse_number("4096"), Some(4096));
+		assert_eq!(parse_number("0xzz"), None);
+	}
+
+	#[test]
+	fn line_endings() {
+		let mut discipline = LineDiscipline::new();
+		assert_eq!(feed(&mut discipline, b"ab\r\ncd\ref\n"), b"ab\ncd\nef\n");
+	}
+
+	#[test]
+	fn erase_and_ignore_control_bytes() {
+		let mut discipline = LineDiscipline::new();
+		assert_eq!(discipline.input(0x7f), Echo::None);
+		assert_eq!(discipline.input(b'x'), Echo::Byte(b'x'));
+		assert_eq!(discipline.input(0x08), Echo::Erase);
+		assert_eq!(feed(&mut discipline, b"h\x1bi\x7f\x7fok\r"), b"ok\n");
+	}
 }
@@ -8,6 +8,8 @@ use hermit_sync::InterruptTicketMutex;
 
 use crate::arch::kernel::core_local::core_scheduler;
 use crate::arch::kernel::processor::get_timer_ticks;
+use crate::arch::mm::paging::virtual_to_physical;
+use crate::arch::mm::VirtAddr;
 use crate::errno::{EAGAIN, EINVAL, ETIMEDOUT};
 use crate::scheduler::task::TaskHandlePriorityQueue;
 use crate::scheduler::PerCoreSchedulerExt;
@@ -23,9 +25,14 @@ bitflags! {
 	}
 }
 
+/// Returns the key of the futex at `addr` in the parking lot.
+///
+/// Futexes are identified by their physical address. Hence, all mappings of
+/// a futex share the same wait queue.
 fn addr(addr: &AtomicU32) -> usize {
 	let ptr: *const _ = addr;
-	ptr.addr()
+	virtual_to_physical(VirtAddr::from(ptr.addr()))
+		.map_or(ptr.addr(), |phys_addr| phys_addr.as_u64() as usize)
 }
 
 /// If the value at address matches the expected value, park the current thread until it is either
@@ -40,6 +47,7 @@ pub(crate) fn futex_wait(
 	timeout: Option<u64>,
 	flags: Flags,
 ) -> i32 {
+	let key = addr(address);
 	let mut parking_lot = PARKING_LOT.lock();
 	// Check the futex value after locking the parking lot so that all changes are observed.
 	if address.load(SeqCst) != expected {
@@ -54,18 +62,20 @@ pub(crate) fn futex_wait(
 
 	let scheduler = core_scheduler();
 	scheduler.block_current_task(wakeup_time);
-	let handle = scheduler.get_current_task_handle();
-	parking_lot.entry(addr(address)).or_default().push(handle);
+	let mut handle = scheduler.get_current_task_handle();
+	parking_lot.entry(key).or_default().push(handle);
 	drop(parking_lot);
 
 	loop {
-		scheduler.reschedule();
+		// The task may be migrated to another core, while it is blocked.
+		// Hence, the scheduler is looked up again afterwards.
+		core_scheduler().reschedule();
 
 		let mut parking_lot = PARKING_LOT.lock();
 		if matches!(wakeup_time, Some(t) if t <= get_timer_ticks()) {
 			let mut wakeup = true;
 			// Timeout occurred, try to remove ourselves from the waiting queue.
-			if let Entry::Occupied(mut queue) = parking_lot.entry(addr(address)) {
+			if let Entry::Occupied(mut queue) = parking_lot.entry(key) {
 				// If we are not in the waking queue, this must have been a wakeup.
 				wakeup = !queue.get_mut().remove(handle);
 				if queue.get().is_empty() {
@@ -81,14 +91,20 @@ pub(crate) fn futex_wait(
 		} else {
 			// If we are not in the waking queue, this must have been a wakeup.
 			let wakeup = !matches!(parking_lot
-				.get(&addr(address)), Some(queue) if queue.contains(handle));
+				.get(&key), Some(queue) if queue.contains(handle));
 
 			if wakeup {
 				return 0;
 			} else {
 				// A spurious wakeup occurred, sleep again.
-				// Tasks do not change core, so the handle in the parking lot is still current.
+				// The handle in the parking lot is renewed, because the task may have
+				// been migrated to another core.
+				let scheduler = core_scheduler();
 				scheduler.block_current_task(wakeup_time);
+				let queue = parking_lot.get_mut(&key).unwrap();
+				queue.remove(handle);
+				handle = scheduler.get_current_task_handle();
+				queue.push(handle);
 			}
 		}
 		drop(parking_lot);
@@ -109,6 +125,7 @@ pub(crate) fn futex_wait_and_set(
 	flags: Flags,
 	new_value: u32,
 ) -> i32 {
+	let key = addr(address);
 	let mut parking_lot = PARKING_LOT.lock();
 	// Check the futex value after locking the parking lot so that all changes are observed.
 	if address.swap(new_value, SeqCst) != expected {
@@ -123,18 +140,20 @@ pub(crate) fn futex_wait_and_set(
 
 	let scheduler = core_scheduler();
 	scheduler.block_current_task(wakeup_time);
-	let handle = scheduler.get_current_task_handle();
-	parking_lot.entry(addr(address)).or_default().push(handle);
+	let mut handle = scheduler.get_current_task_handle();
+	parking_lot.entry(key).or_default().push(handle);
 	drop(parking_lot);
 
 	loop {
-		scheduler.reschedule();
+		// The task may be migrated to another core, while it is blocked.
+		// Hence, the scheduler is looked up again afterwards.
+		core_scheduler().reschedule();
 
 		let mut parking_lot = PARKING_LOT.lock();
 		if matches!(wakeup_time, Some(t) if t <= get_timer_ticks()) {
 			let mut wakeup = true;
 			// Timeout occurred, try to remove ourselves from the waiting queue.
-			if let Entry::Occupied(mut queue) = parking_lot.entry(addr(address)) {
+			if let Entry::Occupied(mut queue) = parking_lot.entry(key) {
 				// If we are not in the waking queue, this must have been a wakeup.
 				wakeup = !queue.get_mut().remove(handle);
 				if queue.get().is_empty() {
@@ -150,14 +169,20 @@ pub(crate) fn futex_wait_and_set(
 		} else {
 			// If we are not in the waking queue, this must have been a wakeup.
 			let wakeup = !matches!(parking_lot
-				.get(&addr(address)), Some(queue) if queue.contains(handle));
+				.get(&key), Some(queue) if queue.contains(handle));
 
 			if wakeup {
 				return 0;
 			} else {
 				// A spurious wakeup occurred, sleep again.
-				// Tasks do not change core, so the handle in the parking lot is still current.
+				// The handle in the parking lot is renewed, because the task may have
+				// been migrated to another core.
+				let scheduler = core_scheduler();
 				scheduler.block_current_task(wakeup_time);
+				let queue = parking_lot.get_mut(&key).unwrap();
+				queue.remove(handle);
+				handle = scheduler.get_current_task_handle();
+				queue.push(handle);
 			}
 		}
 		drop(parking_lot);
@@ -172,8 +197,9 @@ pub(crate) fn futex_wake(address: &AtomicU32, count: i32) -> i32 {
 		return -EINVAL;
 	}
 
+	let key = addr(address);
 	let mut parking_lot = PARKING_LOT.lock();
-	let mut queue = match parking_lot.entry(addr(address)) {
+	let mut queue = match parking_lot.entry(key) {
 		Entry::Occupied(entry) => entry,
 		Entry::Vacant(_) => return 0,
 	};
@@ -204,8 +230,9 @@ pub(crate) fn futex_wake_or_set(address: &AtomicU32, count: i32, new_value: u32)
 		return -EINVAL;
 	}
 
+	let key = addr(address);
 	let mut parking_lot = PARKING_LOT.lock();
-	let mut queue = match parking_lot.entry(addr(address)) {
+	let mut queue = match parking_lot.entry(key) {
 		Entry::Occupied(entry) => entry,
 		Entry::Vacant(_) => {
 			address.store(new_value, SeqCst);
@@ -4,3 +4,4 @@ pub mod futex;
 #[cfg(feature = "newlib")]
 pub mod recmutex;
 pub mod semaphore;
+pub(crate) mod spsc;
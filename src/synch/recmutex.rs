@@ -27,8 +27,7 @@ impl RecursiveMutex {
 
 	pub fn acquire(&self) {
 		// Get information about the current task.
-		let core_scheduler = core_scheduler();
-		let tid = core_scheduler.get_current_task_id();
+		let tid = core_scheduler().get_current_task_id();
 
 		loop {
 			{
@@ -51,6 +50,9 @@ impl RecursiveMutex {
 
 				// The mutex is currently acquired by another task.
 				// Block the current task and add it to the wakeup queue.
+				// The task may be migrated, while it is blocked. Hence, the
+				// scheduler is looked up in each iteration.
+				let core_scheduler = core_scheduler();
 				core_scheduler.block_current_task(None);
 				locked_state
 					.queue
@@ -58,7 +60,7 @@ impl RecursiveMutex {
 			}
 
 			// Switch to the next task.
-			core_scheduler.reschedule();
+			core_scheduler().reschedule();
 		}
 	}
 
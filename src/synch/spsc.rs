@@ -0,0 +1,138 @@
+//! Bounded single-producer single-consumer ring without locks.
+//!
+//! The producer and the consumer only synchronize by the indices of the ring.
+//! Hence, the producer may run in an interrupt handler, while the consumer
+//! is interrupted in the middle of an operation.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Ring with `N` slots for values of type `T`
+///
+/// The indices grow monotonically and wrap around at `usize::MAX`. The slot of
+/// an index is the index modulo `N`. Hence, `N` has to be a power of two, which
+/// keeps the slots consecutive at the wrap around. A full ring rejects further
+/// values and counts them as overflows.
+pub(crate) struct SpscRing<T: Copy, const N: usize> {
+	/// Index of the next value, which is read by the consumer
+	head: AtomicUsize,
+	/// Index of the next value, which is written by the producer
+	tail: AtomicUsize,
+	slots: UnsafeCell<MaybeUninit<[T; N]>>,
+	overflows: AtomicU64,
+}
+
+// SAFETY: The slots are shared by the producer and the consumer. A slot is
+// only written by the producer before the tail is released and only read by
+// the consumer after the tail is acquired.
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T: Copy, const N: usize> SpscRing<T, N> {
+	pub const fn new() -> Self {
+		assert!(N.is_power_of_two());
+
+		Self {
+			head: AtomicUsize::new(0),
+			tail: AtomicUsize::new(0),
+			slots: UnsafeCell::new(MaybeUninit::uninit()),
+			overflows: AtomicU64::new(0),
+		}
+	}
+
+	fn slot(&self, index: usize) -> *mut T {
+		self.slots.get().cast::<T>().wrapping_add(index % N)
+	}
+
+	/// Appends `value` to the ring. Returns `false`, if the ring is full.
+	///
+	/// # Safety
+	///
+	/// `push` must not be called concurrently, e.g. the callers have to
+	/// be serialized by a lock.
+	pub unsafe fn push(&self, value: T) -> bool {
+		let tail = self.tail.load(Ordering::Relaxed);
+		let head = self.head.load(Ordering::Acquire);
+		if tail.wrapping_sub(head) == N {
+			self.overflows.fetch_add(1, Ordering::Relaxed);
+			return false;
+		}
+
+		unsafe {
+			self.slot(tail).write(value);
+		}
+		self.tail.store(tail.wrapping_add(1), Ordering::Release);
+		true
+	}
+
+	/// Removes the oldest value from the ring.
+	///
+	/// # Safety
+	///
+	/// `pop` must not be called concurrently, e.g. the callers have to
+	/// be serialized by a lock.
+	pub unsafe fn pop(&self) -> Option<T> {
+		let head = self.head.load(Ordering::Relaxed);
+		let tail = self.tail.load(Ordering::Acquire);
+		if head == tail {
+			return None;
+		}
+
+		let value = unsafe { self.slot(head).read() };
+		self.head.store(head.wrapping_add(1), Ordering::Release);
+		Some(value)
+	}
+
+	/// Returns `true`, if the ring contains no value. May be called by any core.
+	pub fn is_empty(&self) -> bool {
+		self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+	}
+
+	/// Returns the number of values, which were rejected because of a full ring.
+	pub fn overflows(&self) -> u64 {
+		self.overflows.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reject_values_of_full_ring() {
+		let ring = SpscRing::<u64, 2>::new();
+		assert!(ring.is_empty());
+
+		unsafe {
+			assert!(ring.push(1));
+			assert!(ring.push(2));
+			assert!(!ring.push(3));
+			assert_eq!(ring.overflows(), 1);
+
+			assert_eq!(ring.pop(), Some(1));
+			assert!(ring.push(4));
+			assert_eq!(ring.pop(), Some(2));
+			assert_eq!(ring.pop(), Some(4));
+			assert_eq!(ring.pop(), None);
+		}
+		assert!(ring.is_empty());
+	}
+
+	#[test]
+	fn wrap_around_indices() {
+		let ring = SpscRing::<u32, 4>::new();
+		ring.head.store(usize::MAX - 1, Ordering::Relaxed);
+		ring.tail.store(usize::MAX - 1, Ordering::Relaxed);
+
+		unsafe {
+			for i in 0..4 {
+				assert!(ring.push(i));
+			}
+			assert!(!ring.push(4));
+			for i in 0..4 {
+				assert_eq!(ring.pop(), Some(i));
+			}
+		}
+		assert!(ring.is_empty());
+	}
+}
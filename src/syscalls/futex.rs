@@ -1,9 +1,17 @@
 use core::sync::atomic::AtomicU32;
 
-use crate::errno::EINVAL;
+use crate::errno::{EINVAL, ENOSYS};
 use crate::synch::futex::{self as synch, Flags};
 use crate::time::timespec;
 
+/// Operation of [`sys_futex`], which waits on a futex
+const FUTEX_WAIT: i32 = 0;
+/// Operation of [`sys_futex`], which wakes the waiters of a futex
+const FUTEX_WAKE: i32 = 1;
+/// The futex is only used by a single process. As all tasks share the same
+/// address space, the flag is accepted and ignored.
+const FUTEX_PRIVATE_FLAG: i32 = 128;
+
 /// Like `synch::futex_wait`, but does extra sanity checks and takes a `timespec`.
 ///
 /// Returns -EINVAL if
@@ -52,3 +60,29 @@ pub unsafe extern "C" fn sys_futex_wake(address: *mut u32, count: i32) -> i32 {
 	let address = unsafe { &*(address as *const AtomicU32) };
 	synch::futex_wake(address, count)
 }
+
+/// Multiplexed futex interface, which is compatible to Linux.
+///
+/// Supports `FUTEX_WAIT` with a relative timeout and `FUTEX_WAKE`. Both operations
+/// may be combined with `FUTEX_PRIVATE_FLAG`. Waiters are woken up by `FUTEX_WAKE`,
+/// which wakes up at most `val` waiters and returns their number. `FUTEX_WAIT`
+/// returns -EAGAIN if the futex doesn't contain `val`. `uaddr2` and `val3` are
+/// not used by the supported operations.
+///
+/// Returns -ENOSYS for all other operations.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_futex(
+	address: *mut u32,
+	op: i32,
+	val: u32,
+	timeout: *const timespec,
+	_uaddr2: *mut u32,
+	_val3: u32,
+) -> i32 {
+	match op & !FUTEX_PRIVATE_FLAG {
+		FUTEX_WAIT => unsafe { sys_futex_wait(address, val, timeout, Flags::RELATIVE.bits()) },
+		FUTEX_WAKE => unsafe { sys_futex_wake(address, i32::try_from(val).unwrap_or(i32::MAX)) },
+		_ => -ENOSYS,
+	}
+}
@@ -0,0 +1,68 @@
+use crate::arch::mm::VirtAddr;
+use crate::mm::mmap::{self, Advice};
+
+bitflags! {
+	#[repr(transparent)]
+	#[derive(Debug, Copy, Clone, Default)]
+	pub struct MemoryProtection: u32 {
+		/// Pages may not be accessed.
+		const None = 0;
+		/// Pages may be read.
+		const Read = 1 << 0;
+		/// Pages may be written.
+		const Write = 1 << 1;
+		/// Pages may be executed.
+		const Exec = 1 << 2;
+	}
+}
+
+/// The pages will be accessed soon and are populated in advance.
+pub const MADV_WILLNEED: i32 = 3;
+/// The pages aren't needed any more and are zeroed on the next access.
+pub const MADV_DONTNEED: i32 = 4;
+
+/// Creates an anonymous mapping of `size` bytes and stores its address in `ret`.
+/// The memory of the mapping is zeroed.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_mmap(size: usize, prot_flags: MemoryProtection, ret: &mut *mut u8) -> i32 {
+	match mmap::map_anonymous(
+		size,
+		prot_flags.contains(MemoryProtection::Write),
+		prot_flags.contains(MemoryProtection::Exec),
+	) {
+		Ok(addr) => {
+			*ret = addr.as_mut_ptr();
+			0
+		}
+		Err(errno) => -errno,
+	}
+}
+
+/// Removes the anonymous mapping at `ptr`, which was created by [`sys_mmap`].
+/// Partial unmapping isn't supported.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_munmap(ptr: *mut u8, size: usize) -> i32 {
+	match mmap::unmap_anonymous(VirtAddr::from(ptr as u64), size) {
+		Ok(()) => 0,
+		Err(errno) => -errno,
+	}
+}
+
+/// Gives advice about the use of the memory `[ptr, ptr + size)`, which has to be part of
+/// an anonymous mapping. Supports `MADV_WILLNEED` and `MADV_DONTNEED`.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_madvise(ptr: *mut u8, size: usize, advice: i32) -> i32 {
+	let advice = match advice {
+		MADV_WILLNEED => Advice::WillNeed,
+		MADV_DONTNEED => Advice::DontNeed,
+		_ => return -crate::errno::EINVAL,
+	};
+
+	match mmap::advise(VirtAddr::from(ptr as u64), size, advice) {
+		Ok(()) => 0,
+		Err(errno) => -errno,
+	}
+}
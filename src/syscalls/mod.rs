@@ -1,5 +1,6 @@
 #![allow(clippy::result_unit_err)]
 
+use alloc::vec::Vec;
 #[cfg(all(target_os = "none", not(feature = "common-os")))]
 use core::alloc::{GlobalAlloc, Layout};
 use core::ffi::{c_char, CStr};
@@ -12,9 +13,12 @@ use hermit_sync::Lazy;
 pub use self::condvar::*;
 pub use self::entropy::*;
 pub use self::futex::*;
+pub use self::mmap::*;
 pub use self::processor::*;
 #[cfg(feature = "newlib")]
 pub use self::recmutex::*;
+#[cfg(all(feature = "udp", not(feature = "newlib")))]
+pub use self::ring::*;
 pub use self::semaphore::*;
 pub use self::spinlock::*;
 pub use self::system::*;
@@ -22,8 +26,8 @@ pub use self::tasks::*;
 pub use self::timer::*;
 use crate::env;
 use crate::fd::{
-	dup_object, get_object, remove_object, AccessPermission, EventFlags, FileDescriptor, IoCtl,
-	IoError, OpenOption, PollFd,
+	dup_object, dup_object2, get_object, remove_object, AccessPermission, EpollEvent, EpollOp,
+	EventFlags, FileDescriptor, IoCtl, IoError, ObjectInterface, OpenOption, PollFd,
 };
 use crate::fs::{self, FileAttr};
 #[cfg(all(target_os = "none", not(feature = "common-os")))]
@@ -36,9 +40,12 @@ mod futex;
 mod interfaces;
 #[cfg(feature = "newlib")]
 mod lwip;
+mod mmap;
 mod processor;
 #[cfg(feature = "newlib")]
 mod recmutex;
+#[cfg(all(feature = "udp", not(feature = "newlib")))]
+mod ring;
 mod semaphore;
 #[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 pub mod socket;
@@ -73,8 +80,66 @@ struct iovec {
 	pub iov_len: usize,
 }
 
+impl iovec {
+	/// Returns the described memory area.
+	///
+	/// # Safety
+	///
+	/// The area has to be valid, see [`iovecs`].
+	unsafe fn as_slice<'a>(&self) -> &'a [u8] {
+		if self.iov_len == 0 {
+			&[]
+		} else {
+			unsafe { core::slice::from_raw_parts(self.iov_base, self.iov_len) }
+		}
+	}
+
+	/// Returns the described memory area.
+	///
+	/// # Safety
+	///
+	/// The area has to be valid, see [`iovecs`].
+	unsafe fn as_mut_slice<'a>(&self) -> &'a mut [u8] {
+		if self.iov_len == 0 {
+			&mut []
+		} else {
+			unsafe { core::slice::from_raw_parts_mut(self.iov_base, self.iov_len) }
+		}
+	}
+}
+
 const IOV_MAX: usize = 1024;
 
+/// Returns the `iovcnt` entries at `iov`.
+///
+/// Fails with `EINVAL`, if `iovcnt` exceeds [`IOV_MAX`] or the total length
+/// overflows `isize`, and with `EFAULT`, if a pointer is null.
+unsafe fn iovecs<'a>(iov: *const iovec, iovcnt: usize) -> Result<&'a [iovec], i32> {
+	if iovcnt > IOV_MAX {
+		return Err(crate::errno::EINVAL);
+	}
+	if iovcnt == 0 {
+		return Ok(&[]);
+	}
+	if iov.is_null() {
+		return Err(crate::errno::EFAULT);
+	}
+
+	let iovec_buffers = unsafe { core::slice::from_raw_parts(iov, iovcnt) };
+	let mut total: usize = 0;
+	for iovec_buf in iovec_buffers {
+		if iovec_buf.iov_base.is_null() && iovec_buf.iov_len > 0 {
+			return Err(crate::errno::EFAULT);
+		}
+		total = total
+			.checked_add(iovec_buf.iov_len)
+			.filter(|total| isize::try_from(*total).is_ok())
+			.ok_or(crate::errno::EINVAL)?;
+	}
+
+	Ok(iovec_buffers)
+}
+
 pub(crate) fn init() {
 	Lazy::force(&SYS);
 
@@ -429,37 +494,24 @@ pub unsafe extern "C" fn sys_read(fd: FileDescriptor, buf: *mut u8, len: usize)
 ///
 /// Each `iovec` entry specifies the base address and length of an area in memory from
 /// which data should be written.  `readv()` will always fill an completely
-/// before proceeding to the next.
+/// before proceeding to the next. On sockets, a single segment or datagram is
+/// received and scattered to the areas.
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_readv(fd: i32, iov: *const iovec, iovcnt: usize) -> isize {
-	if !(0..=IOV_MAX).contains(&iovcnt) {
-		return (-crate::errno::EINVAL).try_into().unwrap();
-	}
-
-	let mut read_bytes: isize = 0;
-	let iovec_buffers = unsafe { core::slice::from_raw_parts(iov, iovcnt) };
-
-	for iovec_buf in iovec_buffers {
-		let buf = unsafe { core::slice::from_raw_parts_mut(iovec_buf.iov_base, iovec_buf.iov_len) };
-
-		let len = crate::fd::read(fd, buf).map_or_else(
-			|e| -num::ToPrimitive::to_isize(&e).unwrap(),
-			|v| v.try_into().unwrap(),
-		);
-
-		if len < 0 {
-			return len;
-		}
-
-		read_bytes += len;
-
-		if len < iovec_buf.iov_len.try_into().unwrap() {
-			return read_bytes;
-		}
-	}
+	let iovec_buffers = match unsafe { iovecs(iov, iovcnt) } {
+		Ok(iovec_buffers) => iovec_buffers,
+		Err(err) => return (-err).try_into().unwrap(),
+	};
+	let mut bufs: Vec<&mut [u8]> = iovec_buffers
+		.iter()
+		.map(|iovec_buf| unsafe { iovec_buf.as_mut_slice() })
+		.collect();
 
-	read_bytes
+	crate::fd::readv(fd, &mut bufs).map_or_else(
+		|e| -num::ToPrimitive::to_isize(&e).unwrap(),
+		|v| v.try_into().unwrap(),
+	)
 }
 
 unsafe fn write(fd: FileDescriptor, buf: *const u8, len: usize) -> isize {
@@ -490,39 +542,34 @@ pub unsafe extern "C" fn sys_write(fd: FileDescriptor, buf: *const u8, len: usiz
 ///
 /// Each `iovec` entry specifies the base address and length of an area in memory from
 /// which data should be written.  `writev()` will always write a
-/// complete area before proceeding to the next.
+/// complete area before proceeding to the next. On sockets, the areas are
+/// concatenated and sent at once, e.g. as a single datagram.
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_writev(fd: FileDescriptor, iov: *const iovec, iovcnt: usize) -> isize {
-	if !(0..=IOV_MAX).contains(&iovcnt) {
-		return (-crate::errno::EINVAL).try_into().unwrap();
-	}
-
-	let mut written_bytes: isize = 0;
-	let iovec_buffers = unsafe { core::slice::from_raw_parts(iov, iovcnt) };
-
-	for iovec_buf in iovec_buffers {
-		let buf = unsafe { core::slice::from_raw_parts(iovec_buf.iov_base, iovec_buf.iov_len) };
-
-		let len = crate::fd::write(fd, buf).map_or_else(
-			|e| -num::ToPrimitive::to_isize(&e).unwrap(),
-			|v| v.try_into().unwrap(),
-		);
-
-		if len < 0 {
-			return len;
-		}
-
-		written_bytes += len;
-
-		if len < iovec_buf.iov_len.try_into().unwrap() {
-			return written_bytes;
-		}
-	}
+	let iovec_buffers = match unsafe { iovecs(iov, iovcnt) } {
+		Ok(iovec_buffers) => iovec_buffers,
+		Err(err) => return (-err).try_into().unwrap(),
+	};
+	let bufs: Vec<&[u8]> = iovec_buffers
+		.iter()
+		.map(|iovec_buf| unsafe { iovec_buf.as_slice() })
+		.collect();
 
-	written_bytes
+	crate::fd::writev(fd, &bufs).map_or_else(
+		|e| -num::ToPrimitive::to_isize(&e).unwrap(),
+		|v| v.try_into().unwrap(),
+	)
 }
 
+const FIONBIO: i32 = 0x8008667eu32 as i32;
+const FIONREAD: i32 = 0x4008667fu32 as i32;
+
+/// Manipulates the parameters of the file descriptor `fd`.
+///
+/// Supported requests are `FIONBIO`, `FIONREAD`, `SIOCGIFADDR` and `SIOCGIFHWADDR`.
+/// Other requests or requests, which aren't supported by the type of `fd`, fail
+/// with `ENOTTY`.
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_ioctl(
@@ -530,21 +577,38 @@ pub unsafe extern "C" fn sys_ioctl(
 	cmd: i32,
 	argp: *mut core::ffi::c_void,
 ) -> i32 {
-	const FIONBIO: i32 = 0x8008667eu32 as i32;
+	if argp.is_null() {
+		return -crate::errno::EFAULT;
+	}
 
-	if cmd == FIONBIO {
-		let value = unsafe { *(argp as *const i32) };
+	match get_object(fd) {
+		Ok(obj) => unsafe { ioctl(&*obj, cmd, argp) },
+		Err(e) => -num::ToPrimitive::to_i32(&e).unwrap(),
+	}
+}
 
-		let obj = get_object(fd);
-		obj.map_or_else(
-			|e| -num::ToPrimitive::to_i32(&e).unwrap(),
-			|v| {
-				(*v).ioctl(IoCtl::NonBlocking, value != 0)
-					.map_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap(), |_| 0)
-			},
-		)
-	} else {
-		-crate::errno::EINVAL
+/// Handles the request `cmd` of `sys_ioctl` for the object `obj`.
+unsafe fn ioctl(obj: &dyn ObjectInterface, cmd: i32, argp: *mut core::ffi::c_void) -> i32 {
+	let result = match cmd {
+		FIONBIO => {
+			let value = unsafe { *(argp as *const i32) };
+			obj.ioctl(IoCtl::NonBlocking, value != 0)
+		}
+		FIONREAD => obj.nread().map(|len| unsafe {
+			*(argp as *mut i32) = len.try_into().unwrap_or(i32::MAX);
+		}),
+		#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+		socket::SIOCGIFADDR | socket::SIOCGIFHWADDR if obj.is_socket() => {
+			return unsafe { socket::interface_ioctl(cmd, argp.cast()) };
+		}
+		_ => Err(IoError::ENOTTY),
+	};
+
+	match result {
+		Ok(()) => 0,
+		// the file descriptor doesn't support the request
+		Err(IoError::ENOSYS) => -crate::errno::ENOTTY,
+		Err(e) => -num::ToPrimitive::to_i32(&e).unwrap(),
 	}
 }
 
@@ -661,6 +725,15 @@ pub extern "C" fn sys_dup(fd: i32) -> i32 {
 	dup_object(fd).unwrap_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap())
 }
 
+/// Makes `fd2` a copy of the file descriptor `fd1`, which shares the same object,
+/// e.g. socket or file. If `fd2` is open, it is closed before. Returns `fd2` or
+/// `-EBADF`, if `fd1` isn't open or `fd2` is negative.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_dup2(fd1: i32, fd2: i32) -> i32 {
+	dup_object2(fd1, fd2).unwrap_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap())
+}
+
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> i32 {
@@ -685,6 +758,80 @@ pub unsafe extern "C" fn sys_poll(fds: *mut PollFd, nfds: usize, timeout: i32) -
 	)
 }
 
+/// Adds the file descriptor to the interest list of an epoll object
+pub const EPOLL_CTL_ADD: i32 = 1;
+/// Removes the file descriptor from the interest list of an epoll object
+pub const EPOLL_CTL_DEL: i32 = 2;
+/// Changes the interest of an epoll object in the file descriptor
+pub const EPOLL_CTL_MOD: i32 = 3;
+
+/// Creates an epoll object and returns its file descriptor. `size` is
+/// ignored, but has to be positive.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_epoll_create(size: i32) -> i32 {
+	if size <= 0 {
+		return -crate::errno::EINVAL;
+	}
+
+	crate::fd::epoll_create().unwrap_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap())
+}
+
+/// Adds, modifies or removes the interest of the epoll object `epfd` in the file
+/// descriptor `fd`. `event` is ignored by `EPOLL_CTL_DEL`.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_epoll_ctl(epfd: i32, op: i32, fd: i32, event: *mut EpollEvent) -> i32 {
+	let op = match op {
+		EPOLL_CTL_ADD => EpollOp::Add,
+		EPOLL_CTL_DEL => EpollOp::Del,
+		EPOLL_CTL_MOD => EpollOp::Mod,
+		_ => return -crate::errno::EINVAL,
+	};
+
+	let event = if event.is_null() {
+		if op != EpollOp::Del {
+			return -crate::errno::EFAULT;
+		}
+		EpollEvent::default()
+	} else {
+		unsafe { event.read_unaligned() }
+	};
+
+	crate::fd::epoll_ctl(epfd, op, fd, event)
+		.map_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap(), |_| 0)
+}
+
+/// Waits up to `timeout` milliseconds for events of the epoll object `epfd`. A
+/// negative timeout waits indefinitely. Returns the number of events, which are
+/// stored in `events`.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_epoll_wait(
+	epfd: i32,
+	events: *mut EpollEvent,
+	maxevents: i32,
+	timeout: i32,
+) -> i32 {
+	if events.is_null() || maxevents <= 0 {
+		return -crate::errno::EINVAL;
+	}
+
+	let slice = unsafe { core::slice::from_raw_parts_mut(events, maxevents.try_into().unwrap()) };
+	let timeout = if timeout >= 0 {
+		Some(core::time::Duration::from_millis(
+			timeout.try_into().unwrap(),
+		))
+	} else {
+		None
+	};
+
+	crate::fd::epoll_wait(epfd, slice, timeout).map_or_else(
+		|e| -num::ToPrimitive::to_i32(&e).unwrap(),
+		|v| v.try_into().unwrap(),
+	)
+}
+
 #[hermit_macro::system]
 #[no_mangle]
 pub extern "C" fn sys_eventfd(initval: u64, flags: i16) -> i32 {
@@ -696,8 +843,143 @@ pub extern "C" fn sys_eventfd(initval: u64, flags: i16) -> i32 {
 	}
 }
 
+/// Domain of sockets, which are local to the guest. In contrast to POSIX,
+/// `AF_INET6` is 1 in the Hermit ABI. Hence, a distinct value is used.
+pub const AF_UNIX: i32 = 4;
+
+bitflags! {
+	#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+	#[repr(C)]
+	pub struct SockType: i32 {
+		const SOCK_DGRAM = 2;
+		const SOCK_STREAM = 1;
+		const SOCK_NONBLOCK = 0o4000;
+		const SOCK_CLOEXEC = 0o40000;
+	}
+}
+
+/// Creates a pair of connected stream sockets and stores the file
+/// descriptors in `sv`. In contrast to TCP sockets, the data is
+/// transferred by buffers in the kernel. Only `AF_UNIX` and
+/// `SOCK_STREAM` (optionally with `SOCK_NONBLOCK`) are supported.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_socketpair(
+	domain: i32,
+	type_: SockType,
+	protocol: i32,
+	sv: *mut i32,
+) -> i32 {
+	if sv.is_null() || domain != AF_UNIX || protocol != 0 {
+		return -crate::errno::EINVAL;
+	}
+	if type_.difference(SockType::SOCK_NONBLOCK | SockType::SOCK_CLOEXEC) != SockType::SOCK_STREAM {
+		return -crate::errno::EINVAL;
+	}
+
+	match crate::fd::socketpair(type_.contains(SockType::SOCK_NONBLOCK)) {
+		Ok((fd0, fd1)) => {
+			let sv = unsafe { core::slice::from_raw_parts_mut(sv, 2) };
+			sv[0] = fd0;
+			sv[1] = fd1;
+			0
+		}
+		Err(e) => -num::ToPrimitive::to_i32(&e).unwrap(),
+	}
+}
+
+/// Creates a unidirectional pipe and stores the file descriptors of the
+/// read end in `fds[0]` and of the write end in `fds[1]`. The data is
+/// buffered in the kernel. Writing to a pipe, whose read ends are closed,
+/// fails with `EPIPE`.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_pipe(fds: *mut [i32; 2]) -> i32 {
+	let Some(fds) = (unsafe { fds.as_mut() }) else {
+		return -crate::errno::EINVAL;
+	};
+
+	match crate::fd::pipe(false) {
+		Ok((fd0, fd1)) => {
+			*fds = [fd0, fd1];
+			0
+		}
+		Err(e) => -num::ToPrimitive::to_i32(&e).unwrap(),
+	}
+}
+
 #[hermit_macro::system]
 #[no_mangle]
 pub extern "C" fn sys_image_start_addr() -> usize {
 	crate::mm::kernel_start_address().0.try_into().unwrap()
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use core::ptr;
+	use core::sync::atomic::{AtomicBool, Ordering};
+
+	use async_trait::async_trait;
+
+	use super::*;
+
+	/// File with 5 readable bytes
+	#[derive(Debug, Default)]
+	struct File {
+		nonblocking: AtomicBool,
+	}
+
+	#[async_trait]
+	impl ObjectInterface for File {
+		fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
+			assert_eq!(cmd, IoCtl::NonBlocking);
+			self.nonblocking.store(value, Ordering::Relaxed);
+			Ok(())
+		}
+
+		fn nread(&self) -> Result<usize, IoError> {
+			Ok(5)
+		}
+	}
+
+	#[test]
+	fn ioctl_fionbio() {
+		let file = File::default();
+		let mut value: i32 = 1;
+		let argp = ptr::from_mut(&mut value).cast();
+
+		assert_eq!(unsafe { ioctl(&file, FIONBIO, argp) }, 0);
+		assert!(file.nonblocking.load(Ordering::Relaxed));
+
+		value = 0;
+		assert_eq!(unsafe { ioctl(&file, FIONBIO, argp) }, 0);
+		assert!(!file.nonblocking.load(Ordering::Relaxed));
+	}
+
+	#[test]
+	fn ioctl_fionread() {
+		let file = File::default();
+		let mut value: i32 = -1;
+
+		let argp = ptr::from_mut(&mut value).cast();
+		assert_eq!(unsafe { ioctl(&file, FIONREAD, argp) }, 0);
+		assert_eq!(value, 5);
+	}
+
+	#[test]
+	fn ioctl_unsupported_request() {
+		const TCGETS: i32 = 0x5401;
+
+		let file = File::default();
+		let mut ifr = [0u8; 64];
+		let argp = ifr.as_mut_ptr().cast();
+
+		assert_eq!(unsafe { ioctl(&file, TCGETS, argp) }, -crate::errno::ENOTTY);
+		// The interface requests are restricted to sockets.
+		#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+		assert_eq!(
+			unsafe { ioctl(&file, socket::SIOCGIFADDR, argp) },
+			-crate::errno::ENOTTY
+		);
+	}
+}
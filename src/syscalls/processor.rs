@@ -1,3 +1,4 @@
+use crate::arch::core_local::core_id;
 use crate::arch::get_processor_count;
 
 /// Returns the number of processors currently online.
@@ -13,6 +14,21 @@ pub extern "C" fn sys_available_parallelism() -> usize {
 	get_processor_count().try_into().unwrap()
 }
 
+/// Stores the core, on which the calling thread is running, in `cpu`. Hermit
+/// has no NUMA support. Hence, the node is always 0. Both pointers may be null.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_getcpu(cpu: *mut u32, node: *mut u32) -> i32 {
+	if let Some(cpu) = unsafe { cpu.as_mut() } {
+		*cpu = core_id();
+	}
+	if let Some(node) = unsafe { node.as_mut() } {
+		*node = 0;
+	}
+
+	0
+}
+
 /// Returns the processor frequency in MHz.
 #[hermit_macro::system]
 #[no_mangle]
@@ -0,0 +1,88 @@
+//! Receiving into pre-registered buffers, see [`crate::fd::ring`].
+
+use alloc::vec::Vec;
+
+use super::iovec;
+use crate::executor::block_on;
+use crate::fd::ring::{self, RingCqe, RingSqe};
+use crate::fd::IoError;
+
+fn into_errno(err: IoError) -> i32 {
+	-num::ToPrimitive::to_i32(&err).unwrap()
+}
+
+/// Creates a queue pair for up to `entries` requests, whose completions
+/// aren't reaped yet. Returns the identifier of the queue pair.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_ring_setup(entries: u32) -> i32 {
+	ring::setup(entries.try_into().unwrap()).unwrap_or_else(into_errno)
+}
+
+/// Destroys the queue pair `id`. Requests in flight are cancelled.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_ring_destroy(id: i32) -> i32 {
+	ring::destroy(id).map_or_else(into_errno, |_| 0)
+}
+
+/// Registers `nr` buffers, which are described by `iov`. A request references
+/// a buffer by its index in `iov`. The buffers have to stay valid until the
+/// queue pair is destroyed or other buffers are registered.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_ring_register_buffers(id: i32, iov: *const iovec, nr: usize) -> i32 {
+	if nr > super::IOV_MAX || (iov.is_null() && nr > 0) {
+		return -crate::errno::EINVAL;
+	}
+
+	let ring = match ring::get(id) {
+		Ok(ring) => ring,
+		Err(err) => return into_errno(err),
+	};
+	let buffers: Vec<_> = if nr > 0 {
+		let iov = unsafe { core::slice::from_raw_parts(iov, nr) };
+		iov.iter().map(|iov| (iov.iov_base, iov.iov_len)).collect()
+	} else {
+		Vec::new()
+	};
+
+	unsafe { ring.register_buffers(&buffers) }.map_or_else(into_errno, |_| 0)
+}
+
+/// Submits `nr` requests and returns the number of accepted requests.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_ring_submit(id: i32, sqes: *const RingSqe, nr: usize) -> i32 {
+	if sqes.is_null() || nr == 0 {
+		return -crate::errno::EINVAL;
+	}
+
+	let sqes = unsafe { core::slice::from_raw_parts(sqes, nr) };
+	ring::get(id)
+		.and_then(|ring| ring.submit(sqes))
+		.map_or_else(into_errno, |accepted| accepted.try_into().unwrap())
+}
+
+/// Waits for at least `min_complete` completions and stores up to `nr`
+/// completions in `cqes`. Returns the number of stored completions.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_ring_reap(
+	id: i32,
+	cqes: *mut RingCqe,
+	nr: usize,
+	min_complete: usize,
+) -> i32 {
+	if cqes.is_null() || nr == 0 {
+		return -crate::errno::EINVAL;
+	}
+
+	let ring = match ring::get(id) {
+		Ok(ring) => ring,
+		Err(err) => return into_errno(err),
+	};
+	let cqes = unsafe { core::slice::from_raw_parts_mut(cqes, nr) };
+	block_on(async { Ok(ring.reap(cqes, min_complete).await) }, None)
+		.map_or_else(into_errno, |len| len.try_into().unwrap())
+}
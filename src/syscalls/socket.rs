@@ -4,9 +4,12 @@ use alloc::sync::Arc;
 use core::ffi::{c_char, c_void};
 use core::mem::size_of;
 use core::ops::DerefMut;
+use core::ptr;
 
 #[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
-use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
+use smoltcp::wire::{
+	EthernetAddress, IpAddress, IpEndpoint, IpListenEndpoint, IpVersion, Ipv4Address,
+};
 
 use crate::errno::*;
 use crate::executor::network::{NetworkState, NIC};
@@ -14,8 +17,10 @@ use crate::executor::network::{NetworkState, NIC};
 use crate::fd::socket::tcp;
 #[cfg(feature = "udp")]
 use crate::fd::socket::udp;
-use crate::fd::{get_object, insert_object, replace_object, ObjectInterface, SocketOption};
-use crate::syscalls::IoCtl;
+use crate::fd::{
+	get_object, insert_object, replace_object, IoError, ObjectInterface, SocketOption,
+};
+use crate::syscalls::{IoCtl, SockType};
 
 pub const AF_INET: i32 = 0;
 pub const AF_INET6: i32 = 1;
@@ -40,11 +45,16 @@ pub const SO_BROADCAST: i32 = 0x0020;
 pub const SO_LINGER: i32 = 0x0080;
 pub const SO_SNDBUF: i32 = 0x1001;
 pub const SO_RCVBUF: i32 = 0x1002;
+pub const SO_SNDLOWAT: i32 = 0x1003;
+pub const SO_RCVLOWAT: i32 = 0x1004;
 pub const SO_SNDTIMEO: i32 = 0x1005;
 pub const SO_RCVTIMEO: i32 = 0x1006;
 pub const SO_ERROR: i32 = 0x1007;
+pub const SO_NO_CHECK: i32 = 0x100a;
 pub const TCP_NODELAY: i32 = 1;
 pub const MSG_PEEK: i32 = 1;
+// The POSIX value of `MSG_OOB` is already used by `MSG_PEEK`.
+pub const MSG_OOB: i32 = 2;
 pub const EAI_AGAIN: i32 = 2;
 pub const EAI_BADFLAGS: i32 = 3;
 pub const EAI_FAIL: i32 = 4;
@@ -56,22 +66,18 @@ pub const EAI_SERVICE: i32 = 9;
 pub const EAI_SOCKTYPE: i32 = 10;
 pub const EAI_SYSTEM: i32 = 11;
 pub const EAI_OVERFLOW: i32 = 14;
+pub const SIOCGIFADDR: i32 = 0x8915;
+pub const SIOCGIFHWADDR: i32 = 0x8927;
+pub const IFNAMSIZ: usize = 16;
+pub const ARPHRD_ETHER: sa_family_t = 1;
+/// Maximal time, which `sys_getaddrbyname` waits for the configuration of the interface
+#[cfg(feature = "dns")]
+const DNS_CONFIG_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(10);
 pub type sa_family_t = u8;
 pub type socklen_t = u32;
 pub type in_addr_t = u32;
 pub type in_port_t = u16;
 
-bitflags! {
-	#[derive(Debug, Copy, Clone)]
-	#[repr(C)]
-	pub struct SockType: i32 {
-		const SOCK_DGRAM = 2;
-		const SOCK_STREAM = 1;
-		const SOCK_NONBLOCK = 0o4000;
-		const SOCK_CLOEXEC = 0o40000;
-	}
-}
-
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone)]
 pub struct in_addr {
@@ -102,6 +108,16 @@ pub struct sockaddr_in {
 	pub sin_zero: [c_char; 8],
 }
 
+/// Request of `ioctl`, which refers to a network interface
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ifreq {
+	pub ifr_name: [c_char; IFNAMSIZ],
+	pub ifr_addr: sockaddr,
+	/// remaining space of the union in the C definition
+	pub ifr_pad: [u8; 8],
+}
+
 #[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
 impl From<sockaddr_in> for IpListenEndpoint {
 	fn from(addr: sockaddr_in) -> IpListenEndpoint {
@@ -223,6 +239,57 @@ impl From<IpEndpoint> for sockaddr_in6 {
 	}
 }
 
+/// Reads the endpoint of a `sockaddr_in` or `sockaddr_in6`, which is selected by its
+/// address family.
+///
+/// The buffer may be larger than the address, e.g. a `sockaddr_storage`.
+#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+unsafe fn endpoint_from_sockaddr(
+	addr: *const sockaddr,
+	addrlen: socklen_t,
+) -> Result<IpEndpoint, IoError> {
+	let addrlen = usize::try_from(addrlen).unwrap();
+	if addr.is_null() || addrlen < size_of::<sockaddr>() {
+		return Err(IoError::EINVAL);
+	}
+
+	match i32::from(unsafe { (*addr).sa_family }) {
+		AF_INET if addrlen >= size_of::<sockaddr_in>() => {
+			Ok(IpEndpoint::from(unsafe { *(addr as *const sockaddr_in) }))
+		}
+		AF_INET6 if addrlen >= size_of::<sockaddr_in6>() => {
+			Ok(IpEndpoint::from(unsafe { *(addr as *const sockaddr_in6) }))
+		}
+		AF_INET | AF_INET6 => Err(IoError::EINVAL),
+		_ => Err(IoError::EAFNOSUPPORT),
+	}
+}
+
+/// Stores `endpoint` as `sockaddr_in` or `sockaddr_in6`.
+///
+/// If the buffer is too small, the address is truncated. In any case, `addrlen`
+/// returns the size of the complete address.
+#[cfg(all(any(feature = "tcp", feature = "udp"), not(feature = "newlib")))]
+unsafe fn endpoint_to_sockaddr(endpoint: IpEndpoint, addr: *mut sockaddr, addrlen: *mut socklen_t) {
+	fn copy_truncated<T>(src: &T, addr: *mut sockaddr, addrlen: &mut socklen_t) {
+		let len = size_of::<T>().min(usize::try_from(*addrlen).unwrap());
+		unsafe {
+			ptr::copy_nonoverlapping(ptr::from_ref(src).cast::<u8>(), addr.cast::<u8>(), len);
+		}
+		*addrlen = size_of::<T>().try_into().unwrap();
+	}
+
+	if addr.is_null() || addrlen.is_null() {
+		return;
+	}
+
+	let addrlen = unsafe { &mut *addrlen };
+	match endpoint.addr {
+		IpAddress::Ipv4(_) => copy_truncated(&sockaddr_in::from(endpoint), addr, addrlen),
+		IpAddress::Ipv6(_) => copy_truncated(&sockaddr_in6::from(endpoint), addr, addrlen),
+	}
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct ip_mreq {
@@ -304,7 +371,7 @@ pub unsafe extern "C" fn sys_getaddrbyname(
 	use smoltcp::wire::DnsQueryType;
 
 	use crate::executor::block_on;
-	use crate::executor::network::get_query_result;
+	use crate::executor::network::{get_query_result, wait_configured_timeout};
 
 	if len != size_of::<in_addr>().try_into().unwrap()
 		&& len != size_of::<in6_addr>().try_into().unwrap()
@@ -329,10 +396,20 @@ pub unsafe extern "C" fn sys_getaddrbyname(
 		return -EINVAL;
 	};
 
+	// The DNS servers are known, as soon as the interface is configured.
+	if let Err(e) = wait_configured_timeout(DNS_CONFIG_TIMEOUT) {
+		return -num::ToPrimitive::to_i32(&e).unwrap();
+	}
+
 	let query = {
 		let mut guard = NIC.lock();
-		let nic = guard.as_nic_mut().unwrap();
-		let query = nic.start_query(&name, query_type).unwrap();
+		let Ok(nic) = guard.as_nic_mut() else {
+			return -EIO;
+		};
+		let query = match nic.start_query(&name, query_type) {
+			Ok(query) => query,
+			Err(e) => return -num::ToPrimitive::to_i32(&e).unwrap(),
+		};
 		nic.poll_common(crate::executor::network::now());
 
 		query
@@ -368,9 +445,17 @@ pub extern "C" fn sys_socket(domain: i32, type_: SockType, protocol: i32) -> i32
 		if let NetworkState::Initialized(nic) = guard.deref_mut() {
 			#[cfg(feature = "udp")]
 			if type_.contains(SockType::SOCK_DGRAM) {
-				let handle = nic.create_udp_handle().unwrap();
+				let handle = match nic.create_udp_handle() {
+					Ok(handle) => handle,
+					Err(err) => return -num::ToPrimitive::to_i32(&err).unwrap(),
+				};
 				drop(guard);
-				let socket = udp::Socket::new(handle);
+				let version = if domain == AF_INET6 {
+					IpVersion::Ipv6
+				} else {
+					IpVersion::Ipv4
+				};
+				let socket = udp::Socket::new(handle, version);
 
 				if type_.contains(SockType::SOCK_NONBLOCK) {
 					socket.ioctl(IoCtl::NonBlocking, true).unwrap();
@@ -383,7 +468,10 @@ pub extern "C" fn sys_socket(domain: i32, type_: SockType, protocol: i32) -> i32
 
 			#[cfg(feature = "tcp")]
 			if type_.contains(SockType::SOCK_STREAM) {
-				let handle = nic.create_tcp_handle().unwrap();
+				let handle = match nic.create_tcp_handle() {
+					Ok(handle) => handle,
+					Err(err) => return -num::ToPrimitive::to_i32(&err).unwrap(),
+				};
 				drop(guard);
 				let socket = tcp::Socket::new(handle);
 
@@ -406,41 +494,80 @@ pub extern "C" fn sys_socket(domain: i32, type_: SockType, protocol: i32) -> i32
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_accept(fd: i32, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32 {
-	let obj = get_object(fd);
-	obj.map_or_else(
+	unsafe { sys_accept4(fd, addr, addrlen, 0) }
+}
+
+/// Returns the flags of `accept4`. Only `SOCK_NONBLOCK` and `SOCK_CLOEXEC`
+/// are valid.
+fn accept_flags(flags: i32) -> Result<SockType, IoError> {
+	match SockType::from_bits(flags) {
+		Some(flags) if (SockType::SOCK_NONBLOCK | SockType::SOCK_CLOEXEC).contains(flags) => {
+			Ok(flags)
+		}
+		_ => Err(IoError::EINVAL),
+	}
+}
+
+/// Connection, which is accepted by [`accept_connection`]
+struct Accepted {
+	/// Socket, which continues to listen
+	listener: Arc<dyn ObjectInterface>,
+	/// Connected socket
+	connection: Arc<dyn ObjectInterface>,
+	/// Endpoint of the peer
+	peer: IpEndpoint,
+}
+
+/// Accepts a connection on the socket `listener` and applies `flags` to
+/// the connected socket.
+fn accept_connection(
+	listener: Arc<dyn ObjectInterface>,
+	flags: SockType,
+) -> Result<Accepted, IoError> {
+	// The socket, which continues to listen, is created in advance. Hence,
+	// an exhausted socket set doesn't break an accepted connection.
+	let new_listener = listener.try_clone()?;
+	let peer = listener.accept()?;
+	let _ = new_listener.listen(1024);
+	// The flags are applied, before the socket is visible to other tasks.
+	listener.ioctl(IoCtl::NonBlocking, flags.contains(SockType::SOCK_NONBLOCK))?;
+
+	Ok(Accepted {
+		listener: new_listener,
+		connection: listener,
+		peer,
+	})
+}
+
+/// Accepts a connection on the listening socket `fd` and returns the file
+/// descriptor of the connected socket.
+///
+/// The address of the peer is stored in `addr` and truncated, if `addrlen` is
+/// too small. If `flags` contains `SOCK_NONBLOCK`, the new socket is non-blocking.
+/// `SOCK_CLOEXEC` is accepted, but has no effect, because HermitOS doesn't
+/// support `exec`.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_accept4(
+	fd: i32,
+	addr: *mut sockaddr,
+	addrlen: *mut socklen_t,
+	flags: i32,
+) -> i32 {
+	let accepted = accept_flags(flags)
+		.and_then(|flags| get_object(fd).map(|obj| (obj, flags)))
+		.and_then(|(obj, flags)| accept_connection(obj, flags));
+	accepted.map_or_else(
 		|e| -num::ToPrimitive::to_i32(&e).unwrap(),
-		|v| {
-			(*v).accept().map_or_else(
-				|e| -num::ToPrimitive::to_i32(&e).unwrap(),
-				|endpoint| {
-					let new_obj = dyn_clone::clone_box(&*v);
-					replace_object(fd, Arc::from(new_obj)).unwrap();
-					let new_fd = insert_object(v).unwrap();
-
-					if !addr.is_null() && !addrlen.is_null() {
-						let addrlen = unsafe { &mut *addrlen };
-
-						match endpoint.addr {
-							IpAddress::Ipv4(_) => {
-								if *addrlen >= size_of::<sockaddr_in>().try_into().unwrap() {
-									let addr = unsafe { &mut *(addr as *mut sockaddr_in) };
-									*addr = sockaddr_in::from(endpoint);
-									*addrlen = size_of::<sockaddr_in>().try_into().unwrap();
-								}
-							}
-							IpAddress::Ipv6(_) => {
-								if *addrlen >= size_of::<sockaddr_in6>().try_into().unwrap() {
-									let addr = unsafe { &mut *(addr as *mut sockaddr_in6) };
-									*addr = sockaddr_in6::from(endpoint);
-									*addrlen = size_of::<sockaddr_in6>().try_into().unwrap();
-								}
-							}
-						}
-					}
+		|accepted| {
+			replace_object(fd, accepted.listener).unwrap();
+			let new_fd = insert_object(accepted.connection).unwrap();
 
-					new_fd
-				},
-			)
+			unsafe {
+				endpoint_to_sockaddr(accepted.peer, addr, addrlen);
+			}
+
+			new_fd
 		},
 	)
 }
@@ -576,8 +703,50 @@ pub unsafe extern "C" fn sys_setsockopt(
 					.map_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap(), |_| 0)
 			},
 		)
-	} else if level == SOL_SOCKET && optname == SO_REUSEADDR {
-		0
+	} else if level == SOL_SOCKET
+		&& (optname == SO_REUSEADDR || optname == SO_KEEPALIVE || optname == SO_NO_CHECK)
+		&& optlen == size_of::<i32>().try_into().unwrap()
+	{
+		if optval.is_null() {
+			return -crate::errno::EINVAL;
+		}
+
+		let opt = match optname {
+			SO_REUSEADDR => SocketOption::ReuseAddr,
+			SO_KEEPALIVE => SocketOption::KeepAlive,
+			_ => SocketOption::NoCheck,
+		};
+		let value = unsafe { *(optval as *const i32) };
+		let obj = get_object(fd);
+		obj.map_or_else(
+			|e| -num::ToPrimitive::to_i32(&e).unwrap(),
+			|v| {
+				(*v).setsockopt(opt, value != 0)
+					.map_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap(), |_| 0)
+			},
+		)
+	} else if level == SOL_SOCKET
+		&& (optname == SO_RCVLOWAT || optname == SO_SNDLOWAT)
+		&& optlen == size_of::<i32>().try_into().unwrap()
+	{
+		if optval.is_null() {
+			return -crate::errno::EINVAL;
+		}
+
+		let opt = if optname == SO_RCVLOWAT {
+			SocketOption::RcvLowat
+		} else {
+			SocketOption::SndLowat
+		};
+		let value = unsafe { *(optval as *const i32) };
+		let obj = get_object(fd);
+		obj.map_or_else(
+			|e| -num::ToPrimitive::to_i32(&e).unwrap(),
+			|v| {
+				(*v).setsockopt_int(opt, value)
+					.map_or_else(|e| -num::ToPrimitive::to_i32(&e).unwrap(), |_| 0)
+			},
+		)
 	} else {
 		-crate::errno::EINVAL
 	}
@@ -597,18 +766,25 @@ pub unsafe extern "C" fn sys_getsockopt(
 		fd, level, optname
 	);
 
-	if level == IPPROTO_TCP && optname == TCP_NODELAY {
+	if (level == IPPROTO_TCP && optname == TCP_NODELAY)
+		|| (level == SOL_SOCKET && (optname == SO_KEEPALIVE || optname == SO_NO_CHECK))
+	{
 		if optval.is_null() || optlen.is_null() {
 			return -crate::errno::EINVAL;
 		}
 
+		let opt = match (level, optname) {
+			(SOL_SOCKET, SO_KEEPALIVE) => SocketOption::KeepAlive,
+			(SOL_SOCKET, _) => SocketOption::NoCheck,
+			_ => SocketOption::TcpNoDelay,
+		};
 		let optval = unsafe { &mut *(optval as *mut i32) };
 		let optlen = unsafe { &mut *(optlen as *mut socklen_t) };
 		let obj = get_object(fd);
 		obj.map_or_else(
 			|e| -num::ToPrimitive::to_i32(&e).unwrap(),
 			|v| {
-				(*v).getsockopt(SocketOption::TcpNoDelay).map_or_else(
+				(*v).getsockopt(opt).map_or_else(
 					|e| -num::ToPrimitive::to_i32(&e).unwrap(),
 					|value| {
 						if value {
@@ -618,6 +794,33 @@ pub unsafe extern "C" fn sys_getsockopt(
 						}
 						*optlen = core::mem::size_of::<i32>().try_into().unwrap();
 
+						0
+					},
+				)
+			},
+		)
+	} else if level == SOL_SOCKET && (optname == SO_RCVLOWAT || optname == SO_SNDLOWAT) {
+		if optval.is_null() || optlen.is_null() {
+			return -crate::errno::EINVAL;
+		}
+
+		let opt = if optname == SO_RCVLOWAT {
+			SocketOption::RcvLowat
+		} else {
+			SocketOption::SndLowat
+		};
+		let optval = unsafe { &mut *(optval as *mut i32) };
+		let optlen = unsafe { &mut *(optlen as *mut socklen_t) };
+		let obj = get_object(fd);
+		obj.map_or_else(
+			|e| -num::ToPrimitive::to_i32(&e).unwrap(),
+			|v| {
+				(*v).getsockopt_int(opt).map_or_else(
+					|e| -num::ToPrimitive::to_i32(&e).unwrap(),
+					|value| {
+						*optval = value;
+						*optlen = core::mem::size_of::<i32>().try_into().unwrap();
+
 						0
 					},
 				)
@@ -690,10 +893,20 @@ pub unsafe extern "C" fn sys_getaddrinfo(
 
 #[hermit_macro::system]
 #[no_mangle]
-pub unsafe extern "C" fn sys_send(s: i32, mem: *const c_void, len: usize, _flags: i32) -> isize {
+pub unsafe extern "C" fn sys_send(s: i32, mem: *const c_void, len: usize, flags: i32) -> isize {
+	// smoltcp is not able to send urgent data
+	if flags & MSG_OOB != 0 {
+		return (-crate::errno::EOPNOTSUPP).try_into().unwrap();
+	}
+
 	unsafe { super::write(s, mem.cast(), len) }
 }
 
+/// Shuts down the receiving (`SHUT_RD`), the sending (`SHUT_WR`) or both
+/// directions (`SHUT_RDWR`) of the socket `sockfd`.
+///
+/// Not to be confused with the power-off of the kernel by `syscalls::shutdown`,
+/// which is reached via `sys_exit`. Fails with `ENOTSOCK`, if `sockfd` isn't a socket.
 fn shutdown(sockfd: i32, how: i32) -> i32 {
 	let obj = get_object(sockfd);
 	obj.map_or_else(
@@ -737,16 +950,23 @@ pub unsafe extern "C" fn sys_sendto(
 	fd: i32,
 	buf: *const u8,
 	len: usize,
-	_flags: i32,
+	flags: i32,
 	addr: *const sockaddr,
 	addr_len: socklen_t,
 ) -> isize {
-	let endpoint = if addr_len == size_of::<sockaddr_in>().try_into().unwrap() {
-		IpEndpoint::from(unsafe { *(addr as *const sockaddr_in) })
-	} else if addr_len == size_of::<sockaddr_in6>().try_into().unwrap() {
-		IpEndpoint::from(unsafe { *(addr as *const sockaddr_in6) })
-	} else {
-		return (-crate::errno::EINVAL).try_into().unwrap();
+	// smoltcp is not able to send urgent data
+	if flags & MSG_OOB != 0 {
+		return (-crate::errno::EOPNOTSUPP).try_into().unwrap();
+	}
+
+	// Without an address, the datagram is sent to the connected peer
+	if addr.is_null() {
+		return unsafe { super::write(fd, buf, len) };
+	}
+
+	let endpoint = match unsafe { endpoint_from_sockaddr(addr, addr_len) } {
+		Ok(endpoint) => endpoint,
+		Err(e) => return -num::ToPrimitive::to_isize(&e).unwrap(),
 	};
 	let slice = unsafe { core::slice::from_raw_parts(buf, len) };
 	let obj = get_object(fd);
@@ -780,29 +1000,8 @@ pub unsafe extern "C" fn sys_recvfrom(
 			(*v).recvfrom(slice).map_or_else(
 				|e| -num::ToPrimitive::to_isize(&e).unwrap(),
 				|(len, endpoint)| {
-					if !addr.is_null() && !addrlen.is_null() {
-						let addrlen = unsafe { &mut *addrlen };
-
-						match endpoint.addr {
-							IpAddress::Ipv4(_) => {
-								if *addrlen >= size_of::<sockaddr_in>().try_into().unwrap() {
-									let addr = unsafe { &mut *(addr as *mut sockaddr_in) };
-									*addr = sockaddr_in::from(endpoint);
-									*addrlen = size_of::<sockaddr_in>().try_into().unwrap();
-								} else {
-									return (-crate::errno::EINVAL).try_into().unwrap();
-								}
-							}
-							IpAddress::Ipv6(_) => {
-								if *addrlen >= size_of::<sockaddr_in6>().try_into().unwrap() {
-									let addr = unsafe { &mut *(addr as *mut sockaddr_in6) };
-									*addr = sockaddr_in6::from(endpoint);
-									*addrlen = size_of::<sockaddr_in6>().try_into().unwrap();
-								} else {
-									return (-crate::errno::EINVAL).try_into().unwrap();
-								}
-							}
-						}
+					unsafe {
+						endpoint_to_sockaddr(endpoint, addr, addrlen);
 					}
 
 					len.try_into().unwrap()
@@ -811,3 +1010,196 @@ pub unsafe extern "C" fn sys_recvfrom(
 		},
 	)
 }
+
+/// Returns the address of an interface, whose IPv4 address is `addr`.
+fn ifr_addr(addr: Ipv4Address) -> sockaddr {
+	let addr = sockaddr_in::from(IpEndpoint::new(IpAddress::Ipv4(addr), 0));
+	// SAFETY: both types are plain old data of the same size
+	unsafe { core::mem::transmute::<sockaddr_in, sockaddr>(addr) }
+}
+
+/// Returns the hardware address of an interface, whose Ethernet address is `mac`.
+fn ifr_hwaddr(mac: EthernetAddress) -> sockaddr {
+	let mut addr = sockaddr {
+		sa_len: size_of::<sockaddr>().try_into().unwrap(),
+		sa_family: ARPHRD_ETHER,
+		..Default::default()
+	};
+	for (dst, src) in addr.sa_data.iter_mut().zip(mac.as_bytes()) {
+		*dst = *src as c_char;
+	}
+	addr
+}
+
+/// Handles the requests `SIOCGIFADDR` and `SIOCGIFHWADDR` of `ioctl`.
+///
+/// HermitOS has only one network interface. Hence, the name in `req` is ignored.
+pub(crate) unsafe fn interface_ioctl(cmd: i32, req: *mut ifreq) -> i32 {
+	let Some(req) = (unsafe { req.as_mut() }) else {
+		return -EFAULT;
+	};
+	let mut guard = NIC.lock();
+	let Ok(nic) = guard.as_nic_mut() else {
+		return -ENODEV;
+	};
+
+	let addr = match cmd {
+		SIOCGIFADDR => nic.ipv4_addr().map(ifr_addr),
+		SIOCGIFHWADDR => nic.ethernet_addr().map(ifr_hwaddr),
+		_ => return -ENOTTY,
+	};
+
+	match addr {
+		Some(addr) => {
+			req.ifr_addr = addr;
+			0
+		}
+		None => -EADDRNOTAVAIL,
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use core::sync::atomic::{AtomicBool, Ordering};
+
+	use super::*;
+
+	/// Listening socket, which accepts a connection of 10.0.5.2:4711
+	#[derive(Debug, Default)]
+	struct Listener {
+		nonblocking: AtomicBool,
+	}
+
+	impl ObjectInterface for Listener {
+		fn accept(&self) -> Result<IpEndpoint, IoError> {
+			Ok(IpEndpoint::from((IpAddress::v4(10, 0, 5, 2), 4711)))
+		}
+
+		fn try_clone(&self) -> Result<Arc<dyn ObjectInterface>, IoError> {
+			Ok(Arc::new(Listener::default()))
+		}
+
+		fn ioctl(&self, cmd: IoCtl, value: bool) -> Result<(), IoError> {
+			assert_eq!(cmd, IoCtl::NonBlocking);
+			self.nonblocking.store(value, Ordering::Relaxed);
+			Ok(())
+		}
+
+		fn is_nonblocking(&self) -> bool {
+			self.nonblocking.load(Ordering::Relaxed)
+		}
+	}
+
+	#[test]
+	fn accept_with_invalid_flags() {
+		let stream = SockType::SOCK_STREAM.bits();
+		let nonblock = SockType::SOCK_NONBLOCK.bits();
+		assert_eq!(accept_flags(stream).err(), Some(IoError::EINVAL));
+		assert_eq!(accept_flags(nonblock | stream).err(), Some(IoError::EINVAL));
+		assert_eq!(accept_flags(1 << 20).err(), Some(IoError::EINVAL));
+		assert_eq!(
+			accept_flags(nonblock | 1 << 20).err(),
+			Some(IoError::EINVAL)
+		);
+		assert_eq!(
+			accept_flags(nonblock | SockType::SOCK_CLOEXEC.bits()),
+			Ok(SockType::SOCK_NONBLOCK | SockType::SOCK_CLOEXEC)
+		);
+		assert_eq!(accept_flags(0), Ok(SockType::empty()));
+	}
+
+	#[test]
+	fn accept_nonblocking_connection() {
+		let accepted = accept_connection(
+			Arc::new(Listener::default()),
+			accept_flags(SockType::SOCK_NONBLOCK.bits()).unwrap(),
+		)
+		.unwrap();
+		assert!(accepted.connection.is_nonblocking());
+		assert!(!accepted.listener.is_nonblocking());
+		assert_eq!(
+			accepted.peer,
+			IpEndpoint::from((IpAddress::v4(10, 0, 5, 2), 4711))
+		);
+
+		// Without SOCK_NONBLOCK, the connected socket blocks.
+		let accepted = accept_connection(Arc::new(Listener::default()), SockType::empty()).unwrap();
+		assert!(!accepted.connection.is_nonblocking());
+	}
+
+	#[test]
+	fn endpoint_from_sockaddr_by_family() {
+		let endpoint = IpEndpoint::from((IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 1), 53));
+		// A sockaddr_storage is larger than the actual address
+		let mut storage = [0u64; 16];
+		unsafe {
+			*storage.as_mut_ptr().cast::<sockaddr_in6>() = sockaddr_in6::from(endpoint);
+		}
+		let addr = storage.as_ptr().cast::<sockaddr>();
+
+		let len = core::mem::size_of_val(&storage).try_into().unwrap();
+		assert_eq!(unsafe { endpoint_from_sockaddr(addr, len) }, Ok(endpoint));
+		let len = size_of::<sockaddr_in>().try_into().unwrap();
+		assert_eq!(
+			unsafe { endpoint_from_sockaddr(addr, len) },
+			Err(IoError::EINVAL)
+		);
+
+		unsafe {
+			(*storage.as_mut_ptr().cast::<sockaddr>()).sa_family = 42;
+		}
+		let len = core::mem::size_of_val(&storage).try_into().unwrap();
+		assert_eq!(
+			unsafe { endpoint_from_sockaddr(addr, len) },
+			Err(IoError::EAFNOSUPPORT)
+		);
+	}
+
+	#[test]
+	fn endpoint_to_truncated_sockaddr() {
+		let endpoint = IpEndpoint::from((IpAddress::v4(10, 0, 5, 2), 67));
+		let mut storage = [0xffu8; 8];
+		let mut addrlen: socklen_t = 4;
+
+		unsafe {
+			endpoint_to_sockaddr(endpoint, storage.as_mut_ptr().cast(), &mut addrlen);
+		}
+
+		assert_eq!(addrlen, size_of::<sockaddr_in>().try_into().unwrap());
+		assert_eq!(storage[1], AF_INET.try_into().unwrap());
+		assert_eq!(storage[2..4], 67u16.to_be_bytes());
+		assert_eq!(storage[4..], [0xff; 4]);
+	}
+
+	#[test]
+	fn interface_addresses() {
+		let addr = ifr_addr(Ipv4Address::new(10, 0, 5, 3));
+		let addr = unsafe { core::mem::transmute::<sockaddr, sockaddr_in>(addr) };
+		assert_eq!(addr.sin_family, AF_INET.try_into().unwrap());
+		assert_eq!(addr.sin_addr.s_addr.to_ne_bytes(), [10, 0, 5, 3]);
+		assert_eq!(addr.sin_port, 0);
+
+		let mac = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+		let addr = ifr_hwaddr(mac);
+		assert_eq!(addr.sa_family, ARPHRD_ETHER);
+		assert!(addr
+			.sa_data
+			.iter()
+			.zip(mac.as_bytes())
+			.all(|(x, y)| *x as u8 == *y));
+		assert_eq!(size_of::<ifreq>(), 40);
+	}
+
+	#[test]
+	fn distinct_address_families() {
+		let families = [AF_INET, AF_INET6, crate::syscalls::AF_UNIX];
+		for (i, x) in families.iter().enumerate() {
+			assert!(families[i + 1..].iter().all(|y| x != y));
+		}
+	}
+
+	#[test]
+	fn disjoint_message_flags() {
+		assert_eq!(MSG_PEEK & MSG_OOB, 0);
+	}
+}
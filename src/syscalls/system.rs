@@ -1,5 +1,170 @@
+use core::ffi::{c_char, c_long, c_ulong, c_ushort};
+
+use crate::arch::mm::physicalmem;
+use crate::errno::EINVAL;
+use crate::{arch, scheduler};
+
+/// Length of the fields in [`utsname`], including the terminating null byte.
+const UTSNAME_LENGTH: usize = 65;
+
+/// System identification, which is returned by [`sys_uname`].
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct utsname {
+	/// Name of the operating system
+	pub sysname: [c_char; UTSNAME_LENGTH],
+	/// Name of the node within the network
+	pub nodename: [c_char; UTSNAME_LENGTH],
+	/// Release of the operating system
+	pub release: [c_char; UTSNAME_LENGTH],
+	/// Version of the operating system
+	pub version: [c_char; UTSNAME_LENGTH],
+	/// Hardware type
+	pub machine: [c_char; UTSNAME_LENGTH],
+	/// NIS or YP domain name
+	pub domainname: [c_char; UTSNAME_LENGTH],
+}
+
+/// Copies `src` into `dst`. The string is truncated, if necessary, and always
+/// terminated by a null byte.
+fn copy_field(dst: &mut [c_char; UTSNAME_LENGTH], src: &str) {
+	let len = src.len().min(UTSNAME_LENGTH - 1);
+	for (d, s) in dst.iter_mut().zip(&src.as_bytes()[..len]) {
+		*d = *s as c_char;
+	}
+	dst[len..].fill(0);
+}
+
+/// Statistics about the system, which are returned by [`sys_sysinfo`].
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct sysinfo {
+	/// Seconds since boot
+	pub uptime: c_long,
+	/// 1, 5, and 15 minute load averages
+	pub loads: [c_ulong; 3],
+	/// Total usable main memory size
+	pub totalram: c_ulong,
+	/// Available memory size
+	pub freeram: c_ulong,
+	/// Amount of shared memory
+	pub sharedram: c_ulong,
+	/// Memory used by buffers
+	pub bufferram: c_ulong,
+	/// Total swap space size
+	pub totalswap: c_ulong,
+	/// Swap space still available
+	pub freeswap: c_ulong,
+	/// Number of current processes
+	pub procs: c_ushort,
+	pub pad: c_ushort,
+	/// Total high memory size
+	pub totalhigh: c_ulong,
+	/// Available high memory size
+	pub freehigh: c_ulong,
+	/// Memory unit size in bytes
+	pub mem_unit: u32,
+}
+
+fn machine() -> &'static str {
+	if cfg!(target_arch = "x86_64") {
+		"x86_64"
+	} else if cfg!(target_arch = "aarch64") {
+		"aarch64"
+	} else if cfg!(target_arch = "riscv64") {
+		"riscv64"
+	} else {
+		"unknown"
+	}
+}
+
 #[hermit_macro::system]
 #[no_mangle]
 pub extern "C" fn sys_getpagesize() -> i32 {
 	crate::arch::mm::paging::get_application_page_size() as i32
 }
+
+/// Stores the name and information about the kernel in `buf`.
+///
+/// The node name can be defined by the environment variable `HERMIT_HOSTNAME`.
+/// Returns `0` on success, `-EINVAL` if `buf` is a null pointer.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_uname(buf: *mut utsname) -> i32 {
+	if buf.is_null() {
+		return -EINVAL;
+	}
+
+	let buf = unsafe { &mut *buf };
+	copy_field(&mut buf.sysname, "Hermit");
+	copy_field(
+		&mut buf.nodename,
+		hermit_var_or!("HERMIT_HOSTNAME", "hermit"),
+	);
+	copy_field(&mut buf.release, env!("CARGO_PKG_VERSION"));
+	copy_field(
+		&mut buf.version,
+		concat!("Hermit ", env!("CARGO_PKG_VERSION")),
+	);
+	copy_field(&mut buf.machine, machine());
+	copy_field(&mut buf.domainname, "(none)");
+
+	0
+}
+
+/// Stores statistics about memory usage and the number of tasks in `info`.
+///
+/// Hermit doesn't track the load, shared memory, buffers and swap space. The
+/// corresponding fields are set to zero. `procs` contains the number of tasks.
+/// Returns `0` on success, `-EINVAL` if `info` is a null pointer.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_sysinfo(info: *mut sysinfo) -> i32 {
+	if info.is_null() {
+		return -EINVAL;
+	}
+
+	let info = unsafe { &mut *info };
+	*info = sysinfo {
+		uptime: (arch::processor::get_timer_ticks() / 1_000_000) as c_long,
+		totalram: physicalmem::total_memory_size() as c_ulong,
+		freeram: physicalmem::free_memory_size() as c_ulong,
+		procs: scheduler::number_of_tasks()
+			.try_into()
+			.unwrap_or(c_ushort::MAX),
+		mem_unit: 1,
+		..Default::default()
+	};
+
+	0
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn copy_field_terminates() {
+		let mut field = [1 as c_char; UTSNAME_LENGTH];
+		copy_field(&mut field, "Hermit");
+
+		assert!(field[..6]
+			.iter()
+			.zip(b"Hermit")
+			.all(|(&c, &b)| c == b as c_char));
+		assert!(field[6..].iter().all(|&c| c == 0));
+	}
+
+	#[test]
+	fn copy_field_truncates() {
+		let mut field = [1 as c_char; UTSNAME_LENGTH];
+		copy_field(&mut field, &"x".repeat(100));
+
+		assert!(field[..UTSNAME_LENGTH - 1]
+			.iter()
+			.all(|&c| c == b'x' as c_char));
+		assert_eq!(field[UTSNAME_LENGTH - 1], 0);
+	}
+}
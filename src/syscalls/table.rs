@@ -28,6 +28,10 @@ const SYSNO_FUTEX_WAIT: usize = 10;
 const SYSNO_FUTEX_WAKE: usize = 11;
 /// number of the system call `open`
 const SYSNO_OPEN: usize = 12;
+/// number of the system call `gettid`
+const SYSNO_GETTID: usize = 13;
+/// number of the system call `futex`
+const SYSNO_FUTEX: usize = 14;
 
 /// total number of system calls
 const NO_SYSCALLS: usize = 32;
@@ -76,6 +80,8 @@ impl SyscallTable {
 		table.handle[SYSNO_FUTEX_WAIT] = sys_futex_wait as *const _;
 		table.handle[SYSNO_FUTEX_WAKE] = sys_futex_wake as *const _;
 		table.handle[SYSNO_OPEN] = sys_open as *const _;
+		table.handle[SYSNO_GETTID] = sys_gettid as *const _;
+		table.handle[SYSNO_FUTEX] = sys_futex as *const _;
 
 		table
 	}
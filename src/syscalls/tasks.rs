@@ -22,7 +22,37 @@ pub type Tid = i32;
 #[hermit_macro::system]
 #[no_mangle]
 pub extern "C" fn sys_getpid() -> Tid {
-	0
+	scheduler::get_pid().map_or(0, TaskId::into)
+}
+
+/// Returns the ID of the calling thread. For the main thread, the thread ID
+/// equals the process ID.
+#[hermit_macro::system]
+#[no_mangle]
+pub extern "C" fn sys_gettid() -> Tid {
+	core_scheduler().get_current_task_id().into()
+}
+
+/// Stores the IDs of all threads of the process in `tids`.
+///
+/// At most `len` IDs are stored. Returns the total number of threads, which may
+/// be larger than `len`. Hence, `len == 0` can be used to determine the number
+/// of threads. Returns `-EINVAL` if `tids` is a null pointer and `len` isn't zero.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_gettids(tids: *mut Tid, len: usize) -> isize {
+	if tids.is_null() && len > 0 {
+		return (-EINVAL).try_into().unwrap();
+	}
+
+	let ids = scheduler::get_tids();
+	for (i, id) in ids.iter().copied().take(len).enumerate() {
+		unsafe {
+			tids.add(i).write(id.into());
+		}
+	}
+
+	ids.len().try_into().unwrap()
 }
 
 #[cfg(feature = "newlib")]
@@ -167,7 +197,7 @@ pub extern "C" fn sys_clone(id: *mut Tid, func: extern "C" fn(usize), arg: usize
 #[hermit_macro::system]
 #[no_mangle]
 pub extern "C" fn sys_yield() {
-	core_scheduler().reschedule();
+	core_scheduler().yield_now();
 }
 
 #[cfg(feature = "newlib")]
@@ -301,3 +331,71 @@ pub extern "C" fn sys_set_current_task_priority(prio: u8) {
 		panic!("Invalid priority {}", prio);
 	}
 }
+
+/// Restricts the thread `id` to the cores in the bit mask `mask` of `cpusetsize`
+/// bytes, where bit `n` represents core `n`. The ID 0 selects the calling thread.
+///
+/// Cores, which aren't online, are ignored. A thread is migrated, when it yields
+/// or when it is woken up. Hence, the calling thread yields if it isn't allowed
+/// to stay on its core. Returns `-EINVAL` if `mask` is a null pointer or contains no online
+/// core and `-ESRCH` if the thread doesn't exist.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_sched_setaffinity(id: Tid, cpusetsize: usize, mask: *const u8) -> i32 {
+	if mask.is_null() {
+		return -EINVAL;
+	}
+
+	let mask = unsafe { core::slice::from_raw_parts(mask, cpusetsize) };
+	let mut cpuset: scheduler::CpuSet = 0;
+	for (i, byte) in mask.iter().take(8).enumerate() {
+		cpuset |= scheduler::CpuSet::from(*byte) << (8 * i);
+	}
+
+	let current_id = core_scheduler().get_current_task_id();
+	let id = if id == 0 {
+		current_id
+	} else {
+		TaskId::from(id)
+	};
+	match scheduler::set_affinity(id, cpuset) {
+		Ok(()) => {
+			if id == current_id {
+				core_scheduler().yield_now();
+			}
+			0
+		}
+		Err(errno) => -errno,
+	}
+}
+
+/// Stores the set of cores, on which the thread `id` may run, as bit mask of
+/// `cpusetsize` bytes in `mask`. The ID 0 selects the calling thread.
+///
+/// Returns `-EINVAL` if `mask` is a null pointer or too small to represent all
+/// online cores and `-ESRCH` if the thread doesn't exist.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_sched_getaffinity(id: Tid, cpusetsize: usize, mask: *mut u8) -> i32 {
+	let cores = usize::try_from(arch::get_processor_count()).unwrap();
+	if mask.is_null() || cpusetsize.saturating_mul(8) < cores {
+		return -EINVAL;
+	}
+
+	let id = if id == 0 {
+		core_scheduler().get_current_task_id()
+	} else {
+		TaskId::from(id)
+	};
+	let Some(cpuset) = scheduler::get_affinity(id) else {
+		return -ESRCH;
+	};
+
+	let mask = unsafe { core::slice::from_raw_parts_mut(mask, cpusetsize) };
+	mask.fill(0);
+	for (byte, value) in mask.iter_mut().zip(cpuset.to_le_bytes()) {
+		*byte = value;
+	}
+
+	0
+}
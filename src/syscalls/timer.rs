@@ -1,7 +1,9 @@
-use crate::arch;
+use crate::arch::core_local::core_scheduler;
 use crate::errno::*;
-use crate::syscalls::usleep;
+use crate::scheduler::task::TaskId;
+use crate::syscalls::{usleep, Tid};
 use crate::time::{itimerval, timespec, timeval};
+use crate::{arch, scheduler};
 
 #[allow(non_camel_case_types)]
 pub type clockid_t = i32;
@@ -10,8 +12,50 @@ pub(crate) const CLOCK_REALTIME: clockid_t = 1;
 pub(crate) const CLOCK_PROCESS_CPUTIME_ID: clockid_t = 2;
 pub(crate) const CLOCK_THREAD_CPUTIME_ID: clockid_t = 3;
 pub(crate) const CLOCK_MONOTONIC: clockid_t = 4;
+/// International Atomic Time, which doesn't include leap seconds
+pub(crate) const CLOCK_TAI: clockid_t = 11;
 pub(crate) const TIMER_ABSTIME: i32 = 4;
 
+/// Returns the clock id of the CPU-time clock of thread `tid`.
+///
+/// Like on Linux, CPU-time clocks of specific threads are encoded as negative
+/// clock ids and don't collide with the static clocks.
+fn thread_cpu_clock(tid: Tid) -> clockid_t {
+	-tid - 1
+}
+
+/// Returns the thread of a CPU-time clock, which is created by [`thread_cpu_clock`].
+fn cpu_clock_thread(clock_id: clockid_t) -> Option<Tid> {
+	(clock_id < 0).then(|| -(clock_id + 1))
+}
+
+/// Converts the realtime `micros` to TAI by adding `offset` seconds.
+fn tai_micros(micros: i64, offset: i64) -> i64 {
+	micros.saturating_add(offset.saturating_mul(1_000_000))
+}
+
+/// Returns the clock id of the CPU-time clock of thread `tid`.
+///
+/// The clock id can be passed to `sys_clock_gettime` and `sys_clock_getres`.
+/// Returns `0` on success, `-ESRCH` if the thread doesn't exist and
+/// `-EINVAL` if `clock_id` is a null pointer.
+#[hermit_macro::system]
+#[no_mangle]
+pub unsafe extern "C" fn sys_clock_getcpuclockid(tid: Tid, clock_id: *mut clockid_t) -> i32 {
+	if clock_id.is_null() {
+		return -EINVAL;
+	}
+
+	if tid < 0 || scheduler::get_cpu_time(TaskId::from(tid)).is_none() {
+		return -ESRCH;
+	}
+
+	unsafe {
+		*clock_id = thread_cpu_clock(tid);
+	}
+	0
+}
+
 /// Finds the resolution (or precision) of a clock.
 ///
 /// This function gets the clock resolution of the clock with `clock_id` and stores it in parameter `res`.
@@ -22,6 +66,7 @@ pub(crate) const TIMER_ABSTIME: i32 = 4;
 /// - `CLOCK_PROCESS_CPUTIME_ID`
 /// - `CLOCK_THREAD_CPUTIME_ID`
 /// - `CLOCK_MONOTONIC`
+/// - `CLOCK_TAI`
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_clock_getres(clock_id: clockid_t, res: *mut timespec) -> i32 {
@@ -32,8 +77,17 @@ pub unsafe extern "C" fn sys_clock_getres(clock_id: clockid_t, res: *mut timespe
 	let result = unsafe { &mut *res };
 
 	match clock_id {
-		CLOCK_REALTIME | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID | CLOCK_MONOTONIC => {
-			// All clocks in Hermit have 1 microsecond resolution.
+		CLOCK_REALTIME
+		| CLOCK_PROCESS_CPUTIME_ID
+		| CLOCK_THREAD_CPUTIME_ID
+		| CLOCK_MONOTONIC
+		| CLOCK_TAI => {
+			// All clocks in Hermit have the resolution of the timer tick,
+			// which is 1 microsecond by default.
+			*result = timespec::from_usec(scheduler::tick::resolution() as i64);
+			0
+		}
+		_ if cpu_clock_thread(clock_id).is_some() => {
 			*result = timespec::from_usec(1);
 			0
 		}
@@ -52,6 +106,11 @@ pub unsafe extern "C" fn sys_clock_getres(clock_id: clockid_t, res: *mut timespe
 /// Supported clocks:
 /// - `CLOCK_REALTIME`
 /// - `CLOCK_MONOTONIC`
+/// - `CLOCK_THREAD_CPUTIME_ID`
+/// - `CLOCK_TAI`, which is the realtime clock plus the offset of `HERMIT_TAI_OFFSET`
+///   (37 seconds by default)
+/// - CPU-time clocks of other threads, see [`sys_clock_getcpuclockid`].
+///   Returns `-ESRCH` if the thread has exited in the meantime.
 #[hermit_macro::system]
 #[no_mangle]
 pub unsafe extern "C" fn sys_clock_gettime(clock_id: clockid_t, tp: *mut timespec) -> i32 {
@@ -70,6 +129,26 @@ pub unsafe extern "C" fn sys_clock_gettime(clock_id: clockid_t, tp: *mut timespe
 			*result = timespec::from_usec(arch::processor::get_timer_ticks() as i64);
 			0
 		}
+		CLOCK_TAI => {
+			let micros = arch::kernel::systemtime::now_micros() as i64;
+			*result = timespec::from_usec(tai_micros(micros, crate::env::tai_offset()));
+			0
+		}
+		CLOCK_THREAD_CPUTIME_ID => {
+			let id = core_scheduler().get_current_task_id();
+			let cpu_time = scheduler::get_cpu_time(id).unwrap();
+			*result = timespec::from_usec(cpu_time as i64);
+			0
+		}
+		_ if cpu_clock_thread(clock_id).is_some() => {
+			let tid = cpu_clock_thread(clock_id).unwrap();
+			if let Some(cpu_time) = scheduler::get_cpu_time(TaskId::from(tid)) {
+				*result = timespec::from_usec(cpu_time as i64);
+				0
+			} else {
+				-ESRCH
+			}
+		}
 		_ => {
 			debug!(
 				"Called sys_clock_gettime for unsupported clock {}",
@@ -169,3 +248,24 @@ pub unsafe extern "C" fn sys_setitimer(
 	debug!("Called sys_setitimer, which is unimplemented and always returns 0");
 	0
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cpu_clock_roundtrip() {
+		for tid in [0, 1, 42, Tid::MAX - 1] {
+			let clock_id = thread_cpu_clock(tid);
+			assert!(clock_id < 0);
+			assert_eq!(cpu_clock_thread(clock_id), Some(tid));
+		}
+		assert_eq!(cpu_clock_thread(CLOCK_THREAD_CPUTIME_ID), None);
+	}
+
+	#[test]
+	fn tai_is_ahead_of_realtime() {
+		assert_eq!(tai_micros(1_500_000, 37), 38_500_000);
+		assert_eq!(tai_micros(i64::MAX - 1, 37), i64::MAX);
+	}
+}
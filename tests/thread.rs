@@ -18,7 +18,10 @@ mod common;
 use alloc::vec;
 
 use hermit::errno::{EAGAIN, ETIMEDOUT};
-use hermit::syscalls::{sys_futex_wait, sys_futex_wake, sys_join, sys_spawn2, sys_usleep};
+use hermit::syscalls::{
+	sys_futex_wait, sys_futex_wake, sys_get_processor_count, sys_getcpu, sys_join,
+	sys_sched_setaffinity, sys_spawn2, sys_usleep,
+};
 use hermit::time::timespec;
 
 const USER_STACK_SIZE: usize = 1_048_576;
@@ -91,6 +94,69 @@ pub fn test_futex() {
 	assert_eq!(ret, 0);
 }
 
+fn current_cpu() -> u32 {
+	let mut cpu = u32::MAX;
+	let ret = unsafe { sys_getcpu(&mut cpu, ptr::null_mut()) };
+	assert_eq!(ret, 0);
+	cpu
+}
+
+fn set_affinity(id: i32, cpu: u32) -> i32 {
+	let mask = 1u64 << cpu;
+	unsafe { sys_sched_setaffinity(id, 8, ptr::from_ref(&mask).cast()) }
+}
+
+#[test_case]
+pub fn test_migrate_running_thread() {
+	let cores = u32::try_from(sys_get_processor_count()).unwrap();
+	if cores < 2 {
+		println!("Skipping the migration test on a single core");
+		return;
+	}
+
+	// The calling thread yields and continues on the target core.
+	let target = (current_cpu() + 1) % cores;
+	assert_eq!(set_affinity(0, target), 0);
+	assert_eq!(current_cpu(), target);
+
+	let all = u64::MAX;
+	let ret = unsafe { sys_sched_setaffinity(0, 8, ptr::from_ref(&all).cast()) };
+	assert_eq!(ret, 0);
+}
+
+static SLEEPER_CPU: AtomicU32 = AtomicU32::new(u32::MAX);
+
+extern "C" fn sleeper_func(_: usize) {
+	SLEEPER_CPU.store(current_cpu(), Relaxed);
+	// The thread is migrated, when it is woken up.
+	sys_usleep(100_000);
+	SLEEPER_CPU.store(current_cpu(), Relaxed);
+}
+
+#[test_case]
+pub fn test_migrate_blocked_thread() {
+	let cores = u32::try_from(sys_get_processor_count()).unwrap();
+	if cores < 2 {
+		println!("Skipping the migration test on a single core");
+		return;
+	}
+
+	let sleeper = unsafe { sys_spawn2(sleeper_func, 0, NORMAL_PRIO, USER_STACK_SIZE, -1) };
+	assert!(sleeper >= 0);
+	let cpu = loop {
+		let cpu = SLEEPER_CPU.load(Relaxed);
+		if cpu != u32::MAX {
+			break cpu;
+		}
+		sys_usleep(1000);
+	};
+
+	let target = (cpu + 1) % cores;
+	assert_eq!(set_affinity(sleeper, target), 0);
+	assert_eq!(sys_join(sleeper), 0);
+	assert_eq!(SLEEPER_CPU.load(Relaxed), target);
+}
+
 #[test_case]
 pub fn test_thread_local() {
 	#[repr(C, align(0x10))]